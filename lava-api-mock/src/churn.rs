@@ -0,0 +1,269 @@
+use std::collections::HashSet;
+use std::sync::Mutex;
+
+use boulder::{BuildableWithPersianRug, BuilderWithPersianRug};
+use chrono::Utc;
+use persian_rug::{Accessor, Proxy};
+use wiremock::{Request, Respond, ResponseTemplate};
+
+use crate::permissions::{add_id_filter, remove_id_filters};
+use crate::{Job, JobState, SharedState, State};
+
+/// A change made to the jobs of a [`LavaMock`](crate::LavaMock)
+/// after each page of jobs is served
+///
+/// Clients reading jobs a page at a time see the changes made
+/// between pages, as they would on a busy server, so these reproduce
+/// the jobs which are duplicated or missed by clients using offset
+/// pagination.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Churn {
+    /// Submit a new public job, which takes the next free id
+    ///
+    /// When jobs are listed by descending id, the new job comes
+    /// first, pushing every job back by one place, so that the next
+    /// page repeats the last job of the previous one. Use
+    /// [`Churn::Insert`] to add a job elsewhere in the listing.
+    Submit,
+    /// Submit a new public job with this id, unless a job already has
+    /// it
+    ///
+    /// Given a state whose ids leave gaps, this places the new job
+    /// anywhere among the existing ones, pushing every later job back
+    /// by one place. Since the id is fixed, only the first request
+    /// after which it is free adds a job.
+    Insert(i64),
+    /// Remove the job at this position, counting from 0 in order of
+    /// ascending id, among the jobs not already removed
+    ///
+    /// Removing a job on a page already read pulls every later job
+    /// forward by one place, so that the next page skips a job.
+    /// Objects cannot be removed from a [`State`], so removed jobs
+    /// are only hidden from the jobs endpoint.
+    Remove(usize),
+}
+
+/// A [`wiremock::Respond`] implementation changing the jobs after
+/// every request.
+///
+/// This wraps another endpoint serving [`Job`] instances, usually
+/// one created by [`SharedState::endpoint`], and after each request
+/// applies every one of a list of [`Churn`]s, in order. Jobs removed
+/// by a [`Churn::Remove`] are hidden from later requests.
+///
+/// Jobs are hidden with an `id__in` filter added to the request,
+/// which the inner endpoint copies into its links to other pages.
+/// Those filters are dropped from incoming requests, so following a
+/// link still shows the jobs added since it was made.
+pub struct ChurnEndpoint<R> {
+    data: SharedState,
+    inner: R,
+    churn: Vec<Churn>,
+    removed: Mutex<HashSet<i64>>,
+}
+
+impl<R> ChurnEndpoint<R> {
+    fn submit(data: &mut SharedState, id: i64) {
+        let _ = Proxy::<Job<State>>::builder()
+            .id(id)
+            .is_public(true)
+            .state(JobState::Submitted)
+            .submit_time(Some(Utc::now()))
+            .build(data.mutate());
+    }
+
+    fn apply(&self, churn: Churn, removed: &mut HashSet<i64>) {
+        let mut data = self.data.clone();
+        match churn {
+            Churn::Submit => {
+                let id = data
+                    .access()
+                    .get_iter::<Job<State>>()
+                    .map(|j| j.id + 1)
+                    .max()
+                    .unwrap_or(0);
+                Self::submit(&mut data, id);
+            }
+            Churn::Insert(id) => {
+                if data.access().get_iter::<Job<State>>().all(|j| j.id != id) {
+                    Self::submit(&mut data, id);
+                }
+            }
+            Churn::Remove(position) => {
+                let state = data.access();
+                let mut ids = state
+                    .get_iter::<Job<State>>()
+                    .map(|j| j.id)
+                    .filter(|id| !removed.contains(id))
+                    .collect::<Vec<_>>();
+                ids.sort_unstable();
+                if let Some(id) = ids.get(position) {
+                    removed.insert(*id);
+                }
+            }
+        }
+    }
+}
+
+impl<R: Respond> Respond for ChurnEndpoint<R> {
+    fn respond(&self, request: &Request) -> ResponseTemplate {
+        let mut removed = self.removed.lock().unwrap();
+        // The jobs shown are worked out afresh for every request,
+        // rather than taken from the links to other pages.
+        let request = remove_id_filters(request);
+        let response = if removed.is_empty() {
+            self.inner.respond(&request)
+        } else {
+            let state = self.data.access();
+            let ids = state
+                .get_iter::<Job<State>>()
+                .map(|job| job.id)
+                .filter(|id| !removed.contains(id))
+                .collect::<Vec<_>>();
+            self.inner.respond(&add_id_filter(&request, ids))
+        };
+
+        for churn in self.churn.iter() {
+            self.apply(*churn, &mut removed);
+        }
+        response
+    }
+}
+
+/// Create a new [`ChurnEndpoint`] wrapping `inner`, changing the
+/// jobs in the given [`SharedState`] after each request.
+///
+/// Example:
+/// ```rust
+/// use lava_api_mock::{churn_endpoint, Churn, Job, SharedState, State};
+///
+/// let p = SharedState::new();
+///
+/// # tokio_test::block_on( async {
+/// let server = wiremock::MockServer::start().await;
+///
+/// wiremock::Mock::given(wiremock::matchers::method("GET"))
+///     .and(wiremock::matchers::path("/api/v0.2/jobs/"))
+///     .respond_with(churn_endpoint(
+///         p.clone(),
+///         p.endpoint::<Job<State>>(Some(&server.uri()), Some(10)),
+///         vec![Churn::Submit],
+///     ))
+///     .mount(&server)
+///     .await;
+/// # });
+/// ```
+pub fn churn_endpoint<R: Respond>(
+    data: SharedState,
+    inner: R,
+    churn: Vec<Churn>,
+) -> ChurnEndpoint<R> {
+    ChurnEndpoint {
+        data,
+        inner,
+        churn,
+        removed: Mutex::new(HashSet::new()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::{LavaMock, PaginationLimits, PopulationParams};
+
+    use boulder::{Buildable, Builder};
+    use test_log::test;
+
+    async fn page(url: &str) -> (Vec<i64>, Option<String>) {
+        let page: serde_json::Value = reqwest::get(url)
+            .await
+            .expect("failed to query jobs")
+            .json()
+            .await
+            .expect("failed to parse jobs");
+        let ids = page["results"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|job| job["id"].as_i64().unwrap())
+            .collect();
+        (ids, page["next"].as_str().map(String::from))
+    }
+
+    async fn page_ids(mock: &LavaMock, query: &str) -> Vec<i64> {
+        page(&format!("{}/api/v0.2/jobs/?{}", mock.uri(), query))
+            .await
+            .0
+    }
+
+    fn jobs(count: usize) -> SharedState {
+        let mut p = SharedState::new_populated(PopulationParams::builder().jobs(0usize).build());
+        p.add_jobs(count, |_, _| {});
+        p
+    }
+
+    #[test(tokio::test)]
+    async fn test_churn() {
+        let limits = PaginationLimits::builder().jobs(Some(3)).build();
+
+        let mock = LavaMock::builder(jobs(8))
+            .limits(limits.clone())
+            .churn(Churn::Remove(0))
+            .build()
+            .await;
+        assert_eq!(page_ids(&mock, "").await, vec![0, 1, 2]);
+        // Job 0 is gone, so job 3 is now on the first page
+        assert_eq!(page_ids(&mock, "offset=3").await, vec![4, 5, 6]);
+        assert_eq!(page_ids(&mock, "offset=6").await, vec![]);
+
+        let mock = LavaMock::builder(jobs(8))
+            .limits(limits.clone())
+            .churn(Churn::Submit)
+            .build()
+            .await;
+        assert_eq!(page_ids(&mock, "ordering=-id").await, vec![7, 6, 5]);
+        // Job 8 is new, so job 5 is now on the second page
+        assert_eq!(
+            page_ids(&mock, "ordering=-id&offset=3").await,
+            vec![5, 4, 3]
+        );
+        assert_eq!(mock.state().get_iter::<Job<State>>().count(), 10);
+
+        let mut p = SharedState::new_populated(PopulationParams::builder().jobs(0usize).build());
+        p.add_jobs(8, |i, job| job.id = 2 * i as i64);
+        let mock = LavaMock::builder(p)
+            .limits(limits)
+            .churn(Churn::Insert(3))
+            .build()
+            .await;
+        assert_eq!(page_ids(&mock, "").await, vec![0, 2, 4]);
+        // Job 3 is new, so job 4 is now on the second page
+        assert_eq!(page_ids(&mock, "offset=3").await, vec![4, 6, 8]);
+        assert_eq!(page_ids(&mock, "offset=6").await, vec![10, 12, 14]);
+        assert_eq!(mock.state().get_iter::<Job<State>>().count(), 9);
+    }
+
+    #[test(tokio::test)]
+    async fn test_churn_next() {
+        let limits = PaginationLimits::builder().jobs(Some(3)).build();
+
+        let mock = LavaMock::builder(jobs(8))
+            .limits(limits)
+            .churn(Churn::Remove(0))
+            .churn(Churn::Submit)
+            .build()
+            .await;
+
+        let (ids, next) = page(&format!("{}/api/v0.2/jobs/", mock.uri())).await;
+        assert_eq!(ids, vec![0, 1, 2]);
+        // Job 0 is gone, and job 8 is new
+        let (ids, next) = page(&next.expect("no second page")).await;
+        assert_eq!(ids, vec![4, 5, 6]);
+        // Job 1 is gone, and job 9 is new, even though the link to
+        // this page was made before it was submitted
+        let (ids, next) = page(&next.expect("no third page")).await;
+        assert_eq!(ids, vec![8, 9]);
+        assert_eq!(next, None);
+    }
+}
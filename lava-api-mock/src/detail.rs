@@ -0,0 +1,103 @@
+use persian_rug::Accessor;
+use regex::Regex;
+use serde_json::{json, Value};
+use wiremock::{Request, Respond, ResponseTemplate};
+
+use crate::permissions::{authenticate, can_view};
+use crate::{Job, SharedState, State};
+
+// Render `job` as the REST API does, with related objects given by
+// the same keys that the jobs endpoint uses for them.
+fn job_json(state: &State, job: &Job<State>) -> Value {
+    json!({
+        "id": job.id,
+        "submitter": state.get(&job.submitter).username,
+        "viewing_groups": job
+            .viewing_groups
+            .iter()
+            .map(|g| state.get(g).id)
+            .collect::<Vec<_>>(),
+        "is_public": job.is_public,
+        "description": job.description,
+        "health_check": job.health_check,
+        "requested_device_type": job
+            .requested_device_type
+            .as_ref()
+            .map(|d| state.get(d).name.clone()),
+        "tags": job.tags.iter().map(|t| state.get(t).id).collect::<Vec<_>>(),
+        "actual_device": job
+            .actual_device
+            .as_ref()
+            .map(|d| state.get(d).hostname.clone()),
+        "submit_time": job.submit_time,
+        "start_time": job.start_time,
+        "end_time": job.end_time,
+        "state": job.state.to_string(),
+        "health": job.health.to_string(),
+        "priority": job.priority,
+        "definition": job.definition,
+        "original_definition": job.original_definition,
+        "multinode_definition": job.multinode_definition,
+        "failure_tags": job
+            .failure_tags
+            .iter()
+            .map(|t| state.get(t).id)
+            .collect::<Vec<_>>(),
+        "failure_comment": job.failure_comment,
+    })
+}
+
+/// A [`wiremock::Respond`] implementation serving single jobs.
+///
+/// This serves `GET` requests of the form `/api/v0.2/jobs/<id>/`,
+/// replying with the [`Job`] with that id in the same form as the
+/// jobs endpoint lists it. Requests for jobs which are unknown, or
+/// not visible to the user making the request, receive a 404
+/// response, as from a real server.
+pub struct JobDetailEndpoint {
+    data: SharedState,
+}
+
+impl Respond for JobDetailEndpoint {
+    fn respond(&self, request: &Request) -> ResponseTemplate {
+        let state = self.data.access();
+        let user = match authenticate(&state, request) {
+            Ok(user) => user,
+            Err(response) => return response,
+        };
+
+        let rr = Regex::new(r"/api/v0.2/jobs/(?P<id>[0-9]+)/$").unwrap();
+        let job = rr
+            .captures(request.url.path())
+            .and_then(|captures| captures.get(1).unwrap().as_str().parse::<i64>().ok())
+            .and_then(|id| state.get_iter::<Job<State>>().find(|j| j.id == id))
+            .filter(|job| can_view(&state, user.as_ref(), job));
+
+        match job {
+            Some(job) => ResponseTemplate::new(200).set_body_json(job_json(&state, job)),
+            None => ResponseTemplate::new(404).set_body_json(json!({ "detail": "Not found." })),
+        }
+    }
+}
+
+/// Create a new [`JobDetailEndpoint`] for the given [`SharedState`].
+///
+/// Example:
+/// ```rust
+/// use lava_api_mock::{job_detail_endpoint, SharedState};
+///
+/// let p = SharedState::new();
+///
+/// # tokio_test::block_on( async {
+/// let server = wiremock::MockServer::start().await;
+///
+/// wiremock::Mock::given(wiremock::matchers::method("GET"))
+///     .and(wiremock::matchers::path_regex(r"^/api/v0.2/jobs/[0-9]+/$"))
+///     .respond_with(job_detail_endpoint(p))
+///     .mount(&server)
+///     .await;
+/// # });
+/// ```
+pub fn job_detail_endpoint(data: SharedState) -> JobDetailEndpoint {
+    JobDetailEndpoint { data }
+}
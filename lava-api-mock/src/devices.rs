@@ -4,11 +4,16 @@ use django_query::{
     filtering::FilterableWithPersianRug, row::IntoRowWithPersianRug,
     sorting::SortableWithPersianRug,
 };
-use persian_rug::{contextual, Context, Proxy};
+use persian_rug::{contextual, Accessor, Context, Mutator, Proxy};
+use serde::Deserialize;
+use serde_json::json;
 use strum::{Display, EnumString};
+use wiremock::{Request, Respond, ResponseTemplate};
 
+use crate::permissions::check_superuser;
 use crate::{
-    Alias, Architecture, BitWidth, Core, DeviceType, Group, Job, ProcessorFamily, Tag, User, Worker,
+    Alias, Architecture, BitWidth, Core, DeviceType, Group, Job, ProcessorFamily, SharedState, Tag,
+    User, Worker,
 };
 
 /// A device from the LAVA API.
@@ -90,10 +95,10 @@ pub struct Device<C: Context + 'static> {
     #[django(traverse, foreign_key = "id")]
     pub tags: Vec<Proxy<Tag<C>>>,
 
-    #[django(sort)]
+    #[django(sort, op(iexact, in))]
     #[boulder(default=State::Idle)]
     pub state: State,
-    #[django(sort)]
+    #[django(sort, op(iexact, in))]
     #[boulder(default=Health::Good)]
     pub health: Health,
     #[boulder(buildable_with_persian_rug, generatable_with_persian_rug)]
@@ -131,6 +136,141 @@ pub enum State {
 impl django_query::filtering::ops::Scalar for State {}
 impl django_query::row::StringCellValue for State {}
 
+#[derive(Deserialize)]
+struct DeviceUpdate {
+    health: Option<String>,
+    tags: Option<Vec<u32>>,
+}
+
+/// A [`wiremock::Respond`] implementation changing the health and
+/// tags of devices.
+///
+/// This serves `PATCH` requests to `/api/v0.2/devices/<hostname>/`,
+/// with a JSON body giving the new `health` of the [`Device`] by
+/// name, for example `{"health": "Maintenance"}`, or its new `tags`
+/// by id, for example `{"tags": [1, 4]}`, or both, and updates the
+/// [`SharedState`] to match. Any other fields of the body, such as a
+/// `reason` for the change, are ignored. Only superusers may change
+/// devices; other users receive a 403 response, and anonymous
+/// requests a 401 response. Requests for unknown devices receive a
+/// 404 response, and those with neither field, an unknown health or
+/// an unknown tag a 400 response.
+pub struct DeviceUpdateEndpoint {
+    data: SharedState,
+}
+
+impl Respond for DeviceUpdateEndpoint {
+    fn respond(&self, request: &Request) -> ResponseTemplate {
+        let mut data = self.data.clone();
+        if let Err(response) = check_superuser(&data.access(), request) {
+            return response;
+        }
+
+        let hostname = match request
+            .url
+            .path()
+            .strip_prefix("/api/v0.2/devices/")
+            .and_then(|rest| rest.strip_suffix('/'))
+        {
+            Some(hostname) if !hostname.is_empty() => hostname.to_string(),
+            _ => return ResponseTemplate::new(404),
+        };
+
+        let update = match serde_json::from_slice::<DeviceUpdate>(&request.body) {
+            Ok(update) if update.health.is_some() || update.tags.is_some() => update,
+            Ok(_) => {
+                return ResponseTemplate::new(400).set_body_json(json!({
+                    "non_field_errors": ["One of health or tags is required."]
+                }))
+            }
+            Err(_) => {
+                return ResponseTemplate::new(400)
+                    .set_body_json(json!({ "detail": "JSON parse error." }))
+            }
+        };
+        let health = match update.health.map(|h| h.parse::<Health>().map_err(|_| h)) {
+            Some(Ok(health)) => Some(health),
+            Some(Err(h)) => {
+                return ResponseTemplate::new(400).set_body_json(json!({
+                    "health": [format!("\"{}\" is not a valid choice.", h)]
+                }))
+            }
+            None => None,
+        };
+        let tags =
+            match update.tags {
+                Some(ids) => {
+                    let access = data.access();
+                    let mut tags = Vec::new();
+                    for id in ids {
+                        match access
+                            .get_proxy_iter::<Tag<crate::State>>()
+                            .find(|t| access.get(t).id == id && !access.get(t).deleted)
+                            .cloned()
+                        {
+                            Some(tag) => tags.push(tag),
+                            None => return ResponseTemplate::new(400).set_body_json(json!({
+                                "tags": [format!("Invalid pk \"{}\" - object does not exist.", id)]
+                            })),
+                        }
+                    }
+                    Some(tags)
+                }
+                None => None,
+            };
+
+        let mut m = data.mutate();
+        let device = match m
+            .get_iter_mut::<Device<crate::State>>()
+            .find(|d| d.hostname == hostname)
+        {
+            Some(device) => device,
+            None => {
+                return ResponseTemplate::new(404).set_body_json(json!({ "detail": "Not found." }))
+            }
+        };
+        if let Some(health) = health {
+            device.health = health;
+        }
+        if let Some(tags) = tags {
+            device.tags = tags;
+        }
+        let (hostname, health, tags) = (
+            device.hostname.clone(),
+            device.health.to_string(),
+            device.tags.clone(),
+        );
+        ResponseTemplate::new(200).set_body_json(json!({
+            "hostname": hostname,
+            "health": health,
+            "tags": tags.iter().map(|t| m.get(t).id).collect::<Vec<_>>(),
+        }))
+    }
+}
+
+/// Create a new [`DeviceUpdateEndpoint`] for the given
+/// [`SharedState`].
+///
+/// Example:
+/// ```rust
+/// use lava_api_mock::{device_update_endpoint, SharedState};
+///
+/// let p = SharedState::new();
+///
+/// # tokio_test::block_on( async {
+/// let server = wiremock::MockServer::start().await;
+///
+/// wiremock::Mock::given(wiremock::matchers::method("PATCH"))
+///     .and(wiremock::matchers::path_regex(r"^/api/v0.2/devices/[^/]+/$"))
+///     .respond_with(device_update_endpoint(p))
+///     .mount(&server)
+///     .await;
+/// # });
+/// ```
+pub fn device_update_endpoint(data: SharedState) -> DeviceUpdateEndpoint {
+    DeviceUpdateEndpoint { data }
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
@@ -347,4 +487,70 @@ mod test {
             }
         );
     }
+
+    #[tokio::test]
+    async fn test_update() {
+        let mut p = SharedState::new();
+        {
+            let m = p.mutate();
+            let (_, m) = Proxy::<User<_>>::builder()
+                .username("admin")
+                .is_superuser(true)
+                .token(Some("admin-token".to_string()))
+                .build(m);
+            let (worker, m) = Proxy::<Worker<_>>::builder().hostname("worker1").build(m);
+            let (device_type, m) = Proxy::<DeviceType<_>>::builder().name("type1").build(m);
+            let _ = Proxy::<Device<_>>::builder()
+                .hostname("test1")
+                .worker_host(worker)
+                .device_type(device_type)
+                .health(Health::Good)
+                .build(m);
+        }
+
+        let server = wiremock::MockServer::start().await;
+        wiremock::Mock::given(wiremock::matchers::method("PATCH"))
+            .and(wiremock::matchers::path_regex(
+                r"^/api/v0.2/devices/[^/]+/$",
+            ))
+            .respond_with(device_update_endpoint(p.clone()))
+            .mount(&server)
+            .await;
+
+        let update = |body: &'static str| {
+            let url = format!("{}/api/v0.2/devices/test1/", server.uri());
+            async move {
+                let response = reqwest::Client::new()
+                    .patch(&url)
+                    .header("Authorization", "Token admin-token")
+                    .header("Content-Type", "application/json")
+                    .body(body)
+                    .send()
+                    .await
+                    .expect("failed to update device");
+                let status = response.status().as_u16();
+                let body: Value = response.json().await.expect("failed to parse reply");
+                (status, body)
+            }
+        };
+
+        let (status, body) = update(r#"{"health": "Maintenance"}"#).await;
+        assert_eq!(status, 200);
+        assert_eq!(body["health"], json!("Maintenance"));
+
+        let (status, body) = update("{}").await;
+        assert_eq!(status, 400);
+        assert_eq!(
+            body,
+            json!({ "non_field_errors": ["One of health or tags is required."] })
+        );
+
+        let (status, body) = update("not json").await;
+        assert_eq!(status, 400);
+        assert_eq!(body, json!({ "detail": "JSON parse error." }));
+
+        let access = p.access();
+        let device = access.get_iter::<Device<state::State>>().next().unwrap();
+        assert_eq!(device.health, Health::Maintenance);
+    }
 }
@@ -0,0 +1,56 @@
+//! Fixtures for the examples in the documentation of `lava-api`
+//!
+//! These are not part of the public interface of this crate, and can
+//! change at any time.
+
+use crate::{LavaMock, PaginationLimits, PopulationParams, SharedState};
+
+use boulder::{Buildable, Builder};
+
+// Start a mock for `population`, once `f` has added what the
+// example needs.
+async fn start<F>(population: PopulationParams, f: F) -> LavaMock
+where
+    F: FnOnce(&mut SharedState),
+{
+    let mut state = SharedState::new_populated(population);
+    f(&mut state);
+    LavaMock::new(state, PaginationLimits::new()).await
+}
+
+/// Start a mock with the default population but for devices and
+/// jobs, serving just the devices `f` adds.
+pub async fn devices<F>(f: F) -> LavaMock
+where
+    F: FnOnce(&mut SharedState),
+{
+    let population = PopulationParams::builder()
+        .devices(0usize)
+        .jobs(0usize)
+        .build();
+    start(population, f).await
+}
+
+/// Start a mock with the default population but for jobs, serving
+/// just the jobs `f` adds.
+pub async fn jobs<F>(f: F) -> LavaMock
+where
+    F: FnOnce(&mut SharedState),
+{
+    let population = PopulationParams::builder().jobs(0usize).build();
+    start(population, f).await
+}
+
+/// Start a mock with the default population but for workers, devices
+/// and jobs, serving just the workers `f` adds.
+pub async fn workers<F>(f: F) -> LavaMock
+where
+    F: FnOnce(&mut SharedState),
+{
+    let population = PopulationParams::builder()
+        .workers(0usize)
+        .devices(0usize)
+        .jobs(0usize)
+        .build();
+    start(population, f).await
+}
@@ -0,0 +1,59 @@
+use wiremock::{Request, Respond, ResponseTemplate};
+
+/// A [`wiremock::Respond`] implementation ignoring requests to select
+/// fields.
+///
+/// This wraps another endpoint, usually one created by
+/// [`SharedState::endpoint`](crate::SharedState::endpoint), and
+/// removes any `fields` parameters from requests before passing them
+/// on, so that complete objects are always returned. This is how a
+/// server without support for sparse fieldsets behaves, and clients
+/// asking for fewer fields must cope with it.
+pub struct IgnoredFieldsEndpoint<R> {
+    inner: R,
+}
+
+impl<R: Respond> Respond for IgnoredFieldsEndpoint<R> {
+    fn respond(&self, request: &Request) -> ResponseTemplate {
+        if !request.url.query_pairs().any(|(k, _)| k == "fields") {
+            return self.inner.respond(request);
+        }
+
+        let pairs = request
+            .url
+            .query_pairs()
+            .filter(|(k, _)| k != "fields")
+            .map(|(k, v)| (k.into_owned(), v.into_owned()))
+            .collect::<Vec<_>>();
+        let mut request = request.clone();
+        request.url.set_query(None);
+        if !pairs.is_empty() {
+            request.url.query_pairs_mut().extend_pairs(pairs);
+        }
+        self.inner.respond(&request)
+    }
+}
+
+/// Create a new [`IgnoredFieldsEndpoint`] wrapping `inner`.
+///
+/// Example:
+/// ```rust
+/// use lava_api_mock::{ignored_fields_endpoint, Job, SharedState, State};
+///
+/// let p = SharedState::new();
+///
+/// # tokio_test::block_on( async {
+/// let server = wiremock::MockServer::start().await;
+///
+/// wiremock::Mock::given(wiremock::matchers::method("GET"))
+///     .and(wiremock::matchers::path("/api/v0.2/jobs/"))
+///     .respond_with(ignored_fields_endpoint(
+///         p.endpoint::<Job<State>>(Some(&server.uri()), None),
+///     ))
+///     .mount(&server)
+///     .await;
+/// # });
+/// ```
+pub fn ignored_fields_endpoint<R: Respond>(inner: R) -> IgnoredFieldsEndpoint<R> {
+    IgnoredFieldsEndpoint { inner }
+}
@@ -17,7 +17,6 @@ use crate::workers::Worker;
 /// A job from the LAVA API
 // Filters from lava/lava_rest_app/filters.py
 // FIXME: the model contains
-// - is_public
 // - target_group
 // - sub_id
 // That don't seem to appear in query output
@@ -79,9 +78,11 @@ pub struct Job<C: Context + 'static> {
     #[boulder(generatable_with_persian_rug, sequence = 3usize)]
     #[django(traverse, foreign_key = "id")]
     pub viewing_groups: Vec<Proxy<Group<C>>>,
+    #[boulder(default = true)]
+    pub is_public: bool,
     // FIXME: verify: is this really mandatory?
     #[boulder(default = "Example job description")]
-    #[django(op(in, contains, icontains, startswith, endswith))]
+    #[django(sort, op(in, contains, icontains, startswith, endswith))]
     pub description: String,
     #[boulder(default = true)]
     pub health_check: bool,
@@ -104,12 +105,12 @@ pub struct Job<C: Context + 'static> {
     #[django(op(gt, lt, isnull), sort)]
     pub end_time: Option<DateTime<Utc>>,
     #[boulder(default=State::Submitted)]
-    #[django(op(iexact, in))]
+    #[django(sort, op(iexact, in))]
     pub state: State,
     #[boulder(default=Health::Unknown)]
-    #[django(op(iexact, in))]
+    #[django(sort, op(iexact, in))]
     pub health: Health,
-    #[django(op(in, lt, gt, lte, gte))]
+    #[django(sort, op(in, lt, gt, lte, gte))]
     pub priority: i64,
     #[boulder(default = "Example job definition")]
     #[django(op(in, contains, icontains, startswith, endswith))]
@@ -127,7 +128,9 @@ pub struct Job<C: Context + 'static> {
 }
 
 /// The health (i.e. completion type) of a [`Job`] in the LAVA API
-#[derive(Copy, Clone, Debug, PartialEq, Eq, EnumString, Display)]
+///
+/// These are ordered as LAVA orders them when sorting jobs.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, EnumString, PartialOrd, Ord, Display)]
 pub enum Health {
     Unknown,
     Complete,
@@ -139,7 +142,9 @@ impl django_query::filtering::ops::Scalar for Health {}
 impl django_query::row::StringCellValue for Health {}
 
 /// The state (i.e. progress) of a [`Job`] in the LAVA API
-#[derive(Copy, Clone, Debug, PartialEq, Eq, EnumString, Display)]
+///
+/// These are ordered as LAVA orders them when sorting jobs.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, EnumString, PartialOrd, Ord, Display)]
 pub enum State {
     Submitted,
     Scheduling,
@@ -185,6 +190,7 @@ mod tests {
                 id: 1,
                 submitter,
                 viewing_groups: Vec::new(),
+                is_public: true,
                 description: "A job submitted by Fred".to_string(),
                 health_check: false,
                 requested_device_type: Some(device_type),
@@ -209,6 +215,7 @@ mod tests {
                 id: 2,
                 submitter,
                 viewing_groups: Vec::new(),
+                is_public: true,
                 description: "A job submitted by Jane".to_string(),
                 health_check: false,
                 requested_device_type: Some(device_type),
@@ -389,6 +396,7 @@ mod tests {
                             "viewing_groups": [
 
                             ],
+                            "is_public": true,
                             "description": "Example job description",
                             "health_check": false,
                             "requested_device_type": "test-device-type-0",
@@ -416,6 +424,7 @@ mod tests {
                             "viewing_groups": [
 
                             ],
+                            "is_public": true,
                             "description": "Example job description",
                             "health_check": true,
                             "requested_device_type": "test-device-type-1",
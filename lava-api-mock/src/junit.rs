@@ -72,6 +72,13 @@ fn create_junit(job_id: i64, data: &SharedState) -> Report {
     rb.build()
 }
 
+/// A [`wiremock::Respond`] implementation serving JUnit results.
+///
+/// This serves requests of the form `/api/v0.2/jobs/<id>/junit/`,
+/// by converting the [`TestCase`](crate::TestCase) instances for the
+/// job into a JUnit XML report, with one test suite per
+/// [`TestSuite`](crate::TestSuite). Requests for other paths receive
+/// a 404 response.
 pub struct JunitEndpoint {
     data: SharedState,
 }
@@ -91,6 +98,25 @@ impl Respond for JunitEndpoint {
     }
 }
 
+/// Create a new [`JunitEndpoint`] for the given [`SharedState`].
+///
+/// Example:
+/// ```rust
+/// use django_query::mock::nested_endpoint_matches;
+/// use lava_api_mock::{junit_endpoint, SharedState};
+///
+/// let p = SharedState::new();
+///
+/// # tokio_test::block_on( async {
+/// let server = wiremock::MockServer::start().await;
+///
+/// wiremock::Mock::given(wiremock::matchers::method("GET"))
+///     .and(nested_endpoint_matches("/api/v0.2", "jobs", "junit"))
+///     .respond_with(junit_endpoint(p))
+///     .mount(&server)
+///     .await;
+/// # });
+/// ```
 pub fn junit_endpoint(data: SharedState) -> JunitEndpoint {
     JunitEndpoint { data }
 }
@@ -1,11 +1,26 @@
-use crate::junit_endpoint;
+use crate::permissions::{authenticate, rejected_token_endpoint};
 use crate::state::{SharedState, State};
-use crate::{Alias, Device, DeviceType, Job, Tag, TestCase, TestSuite, Worker};
+use crate::{
+    cancel_endpoint, churn_endpoint, create_tag_endpoint, delete_tag_endpoint,
+    device_update_endpoint, ignored_fields_endpoint, job_detail_endpoint, job_log_endpoint,
+    junit_endpoint, live_tags_endpoint, restricted_users_endpoint, resubmit_endpoint,
+    submission_endpoint, visible_jobs_endpoint, whoami_endpoint, worker_update_endpoint,
+};
+use crate::{
+    Alias, Churn, Device, DeviceType, Group, Job, JobLogParams, Tag, TestCase, TestSuite, User,
+    Worker,
+};
 
 use boulder::Buildable;
 use clone_replace::MutateGuard;
 use django_query::mock::{nested_endpoint_matches, NestedEndpointParams};
+use serde_json::json;
+use std::collections::HashSet;
 use std::sync::Arc;
+use std::time::Duration;
+use strum::{EnumIter, IntoEnumIterator};
+use wiremock::http::Method;
+use wiremock::{matchers, Mock, MockBuilder, Request, ResponseTemplate};
 
 /// Pagination limits for constructing a [`LavaMock`] instance.
 ///
@@ -28,6 +43,8 @@ pub struct PaginationLimits {
     devices: Option<usize>,
     tags: Option<usize>,
     workers: Option<usize>,
+    users: Option<usize>,
+    groups: Option<usize>,
 }
 
 impl PaginationLimits {
@@ -46,20 +63,64 @@ impl PaginationLimits {
 /// - `/api/v0.2/aliases/`
 /// - `/api/v0.2/devices/`
 /// - `/api/v0.2/devicetypes/`
+/// - `/api/v0.2/groups/`
 /// - `/api/v0.2/jobs/`
+/// - `/api/v0.2/system/version/`
+/// - `/api/v0.2/system/whoami/`
 /// - `/api/v0.2/tags/`
+/// - `/api/v0.2/users/`
 /// - `/api/v0.2/workers/`
 ///
+/// Requests are made on behalf of the [`User`] whose
+/// [`token`](User::token) they carry, or anonymously if they carry
+/// none. Anonymous users can only see public jobs; see
+/// [`VisibleJobsEndpoint`](crate::VisibleJobsEndpoint) for the rules
+/// for other users. They are not shown the email addresses of users
+/// either; see [`RestrictedUsersEndpoint`](crate::RestrictedUsersEndpoint).
+/// Like many servers, the jobs endpoint does not
+/// support selecting fields, and ignores any `fields` parameter; see
+/// [`IgnoredFieldsEndpoint`](crate::IgnoredFieldsEndpoint).
+///
+/// Single jobs can be retrieved from `/api/v0.2/jobs/<id>/`; see
+/// [`JobDetailEndpoint`](crate::JobDetailEndpoint). The logs of jobs
+/// which have started are generated on demand, and served from
+/// `/api/v0.2/jobs/<id>/logs/`; see
+/// [`JobLogEndpoint`](crate::JobLogEndpoint).
+///
 /// It also provides the following nested endpoints for jobs:
 /// - `/api/v0.2/jobs/<id>/tests/`
 /// - `/api/v0.2/jobs/<id>/suites/`
+/// - `/api/v0.2/jobs/<id>/junit/`
+///
+/// Jobs can also be submitted by `POST` to `/api/v0.2/jobs/`, which
+/// adds them to the [`SharedState`]; see
+/// [`SubmissionEndpoint`](crate::SubmissionEndpoint). Existing jobs
+/// can be cancelled and resubmitted by `GET` or `POST` to
+/// - `/api/v0.2/jobs/<id>/cancel/`
+/// - `/api/v0.2/jobs/<id>/resubmit/`
+///
+/// which require the token of the job's submitter or a superuser; see
+/// [`CancelEndpoint`](crate::CancelEndpoint) and
+/// [`ResubmitEndpoint`](crate::ResubmitEndpoint).
+///
+/// Superusers can create tags by `POST` to `/api/v0.2/tags/`, and
+/// delete them by `DELETE` to `/api/v0.2/tags/<id>/`; see
+/// [`CreateTagEndpoint`](crate::CreateTagEndpoint) and
+/// [`DeleteTagEndpoint`](crate::DeleteTagEndpoint). They can also
+/// change the health and tags of devices by `PATCH` to
+/// `/api/v0.2/devices/<hostname>/`, and the health and job limit of
+/// workers by `PATCH` to `/api/v0.2/workers/<hostname>/`; see
+/// [`DeviceUpdateEndpoint`](crate::DeviceUpdateEndpoint) and
+/// [`WorkerUpdateEndpoint`](crate::WorkerUpdateEndpoint).
 ///
 /// You can use [`uri`](LavaMock::uri) to find the initial portion
-/// of the URL for your test instance.
+/// of the URL for your test instance. Each of these routes is an
+/// [`Endpoint`], and can be left out or made to fail by creating the
+/// mock with a [`LavaMockBuilder`].
 ///
-/// The mock object does not support the Lava mutation endpoints, but
-/// you can mutate the provided [`SharedState`] directly for testing.
-/// There are two ways to do this:
+/// The mock object does not support the other Lava mutation
+/// endpoints, but you can mutate the provided [`SharedState`]
+/// directly for testing. There are two ways to do this:
 /// - You can keep a clone of the [`SharedState`] you pass in and obtain
 ///   a [`MutateGuard`] with [`mutate`](SharedState::mutate).
 /// - You can call [`state_mut`](LavaMock::state_mut) to get a [`MutateGuard`]
@@ -69,93 +130,422 @@ pub struct LavaMock {
     state: SharedState,
 }
 
-impl LavaMock {
-    /// Create and start a new [`LavaMock`]
+/// The version reported by a [`LavaMock`], unless another is given
+/// to [`LavaMockBuilder::version`]
+pub const DEFAULT_VERSION: &str = "2024.01";
+
+/// An endpoint served by a [`LavaMock`]
+///
+/// These are used with [`LavaMockBuilder`] to leave endpoints out of
+/// a mock, or to make them fail. Each names a single route, given by
+/// its method and path; for example the jobs list and job submission
+/// are both served from `/api/v0.2/jobs/`, but by `GET` and `POST`
+/// respectively.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, EnumIter)]
+pub enum Endpoint {
+    /// `GET /api/v0.2/aliases/`
+    Aliases,
+    /// `GET /api/v0.2/jobs/<id>/tests/`
+    TestCases,
+    /// `GET /api/v0.2/jobs/<id>/suites/`
+    TestSuites,
+    /// `GET /api/v0.2/jobs/<id>/junit/`
+    Junit,
+    /// `GET` or `POST /api/v0.2/jobs/<id>/cancel/`
+    Cancel,
+    /// `GET` or `POST /api/v0.2/jobs/<id>/resubmit/`
+    Resubmit,
+    /// `GET /api/v0.2/jobs/<id>/logs/`
+    JobLog,
+    /// `GET /api/v0.2/jobs/`
+    Jobs,
+    /// `GET /api/v0.2/jobs/<id>/`
+    JobDetail,
+    /// `POST /api/v0.2/jobs/`
+    Submission,
+    /// `GET /api/v0.2/devicetypes/`
+    DeviceTypes,
+    /// `GET /api/v0.2/devices/`
+    Devices,
+    /// `PATCH /api/v0.2/devices/<hostname>/`
+    DeviceUpdate,
+    /// `GET /api/v0.2/tags/`
+    Tags,
+    /// `POST /api/v0.2/tags/`
+    CreateTag,
+    /// `DELETE /api/v0.2/tags/<id>/`
+    DeleteTag,
+    /// `GET /api/v0.2/workers/`
+    Workers,
+    /// `PATCH /api/v0.2/workers/<hostname>/`
+    WorkerUpdate,
+    /// `GET /api/v0.2/users/`
+    Users,
+    /// `GET /api/v0.2/system/version/`
+    Version,
+    /// `GET /api/v0.2/system/whoami/`
+    Whoami,
+    /// `GET /api/v0.2/groups/`
+    Groups,
+}
+
+impl Endpoint {
+    // Begin a mock matching the requests for this endpoint.
+    fn given(self) -> MockBuilder {
+        let method = match self {
+            Endpoint::Submission | Endpoint::CreateTag => "POST",
+            Endpoint::DeviceUpdate | Endpoint::WorkerUpdate => "PATCH",
+            Endpoint::DeleteTag => "DELETE",
+            _ => "GET",
+        };
+        let mock = match self {
+            // LAVA serves these by GET, but clients may reasonably
+            // POST to them instead, since they change the job.
+            Endpoint::Cancel | Endpoint::Resubmit => Mock::given(|request: &Request| {
+                request.method == Method::Get || request.method == Method::Post
+            }),
+            _ => Mock::given(matchers::method(method)),
+        };
+        match self {
+            Endpoint::Aliases => mock.and(matchers::path("/api/v0.2/aliases/")),
+            Endpoint::TestCases => mock.and(nested_endpoint_matches("/api/v0.2", "jobs", "tests")),
+            Endpoint::TestSuites => {
+                mock.and(nested_endpoint_matches("/api/v0.2", "jobs", "suites"))
+            }
+            Endpoint::Junit => mock.and(nested_endpoint_matches("/api/v0.2", "jobs", "junit")),
+            Endpoint::Cancel => mock.and(nested_endpoint_matches("/api/v0.2", "jobs", "cancel")),
+            Endpoint::Resubmit => {
+                mock.and(nested_endpoint_matches("/api/v0.2", "jobs", "resubmit"))
+            }
+            Endpoint::JobLog => mock.and(nested_endpoint_matches("/api/v0.2", "jobs", "logs")),
+            Endpoint::Jobs | Endpoint::Submission => mock.and(matchers::path("/api/v0.2/jobs/")),
+            Endpoint::JobDetail => mock.and(matchers::path_regex(r"^/api/v0.2/jobs/[0-9]+/$")),
+            Endpoint::DeviceTypes => mock.and(matchers::path("/api/v0.2/devicetypes/")),
+            Endpoint::Devices => mock.and(matchers::path("/api/v0.2/devices/")),
+            Endpoint::DeviceUpdate => mock.and(matchers::path_regex(r"^/api/v0.2/devices/[^/]+/$")),
+            Endpoint::Tags | Endpoint::CreateTag => mock.and(matchers::path("/api/v0.2/tags/")),
+            Endpoint::DeleteTag => mock.and(matchers::path_regex(r"^/api/v0.2/tags/[0-9]+/$")),
+            Endpoint::Workers => mock.and(matchers::path("/api/v0.2/workers/")),
+            Endpoint::WorkerUpdate => mock.and(matchers::path_regex(r"^/api/v0.2/workers/[^/]+/$")),
+            Endpoint::Users => mock.and(matchers::path("/api/v0.2/users/")),
+            Endpoint::Version => mock.and(matchers::path("/api/v0.2/system/version/")),
+            Endpoint::Whoami => mock.and(matchers::path("/api/v0.2/system/whoami/")),
+            Endpoint::Groups => mock.and(matchers::path("/api/v0.2/groups/")),
+        }
+    }
+}
+
+/// A failure to inject into an [`Endpoint`] of a [`LavaMock`]
+///
+/// Requests to the endpoint receive a response with the given status
+/// and an empty body in place of the usual one, either indefinitely
+/// or for a limited number of requests, after which the endpoint
+/// behaves normally. This can be used to check how clients handle
+/// errors and retries.
+///
+/// Example:
+/// ```rust
+/// use lava_api_mock::Fault;
+/// use std::time::Duration;
+///
+/// // Two slow gateway errors, then normal service
+/// let fault = Fault::new(502).times(2).delay(Duration::from_millis(100));
+/// ```
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Fault {
+    status: u16,
+    times: Option<u64>,
+    delay: Option<Duration>,
+}
+
+impl Fault {
+    /// Create a new [`Fault`] replying with `status` to every
+    /// request.
+    pub fn new(status: u16) -> Self {
+        Self {
+            status,
+            times: None,
+            delay: None,
+        }
+    }
+
+    /// Reply with the fault only to the next `n` requests, which
+    /// must be greater than zero.
+    pub fn times(mut self, n: u64) -> Self {
+        self.times = Some(n);
+        self
+    }
+
+    /// Wait for `delay` before sending each faulty reply.
+    pub fn delay(mut self, delay: Duration) -> Self {
+        self.delay = Some(delay);
+        self
+    }
+
+    fn response(&self) -> ResponseTemplate {
+        let response = ResponseTemplate::new(self.status);
+        match self.delay {
+            Some(delay) => response.set_delay(delay),
+            None => response,
+        }
+    }
+}
+
+/// Construct a [`LavaMock`] with non-default settings.
+///
+/// This is obtained from [`LavaMock::builder`], and allows endpoints
+/// to be left out of the mock, for example to simulate an older
+/// server without them, or to be given [`Fault`]s, alongside the
+/// [`PaginationLimits`] that [`LavaMock::new`] accepts. Requests to
+/// disabled endpoints receive a 404 response, as from a server that
+/// does not provide them.
+///
+/// Example:
+/// ```rust
+/// use boulder::{Buildable, Builder};
+/// use lava_api_mock::{Endpoint, Fault, LavaMock, PaginationLimits, SharedState};
+///
+/// # tokio_test::block_on( async {
+/// let mock = LavaMock::builder(SharedState::new())
+///     .limits(PaginationLimits::builder().jobs(Some(5)).build())
+///     .disable(Endpoint::Tags)
+///     .fault(Endpoint::Jobs, Fault::new(503).times(1))
+///     .build()
+///     .await;
+///
+/// let url = format!("{}/api/v0.2/tags/", mock.uri());
+/// let response = reqwest::get(&url).await.expect("failed to query tags");
+/// assert_eq!(response.status(), 404);
+///
+/// let url = format!("{}/api/v0.2/jobs/", mock.uri());
+/// let response = reqwest::get(&url).await.expect("failed to query jobs");
+/// assert_eq!(response.status(), 503);
+/// let response = reqwest::get(&url).await.expect("failed to query jobs");
+/// assert_eq!(response.status(), 200);
+/// # });
+/// ```
+pub struct LavaMockBuilder {
+    state: SharedState,
+    limits: PaginationLimits,
+    version: String,
+    disabled: HashSet<Endpoint>,
+    faults: Vec<(Endpoint, Fault)>,
+    logs: JobLogParams,
+    require_token: bool,
+    churn: Vec<Churn>,
+}
+
+impl LavaMockBuilder {
+    /// Create a new [`LavaMockBuilder`] serving `state`.
     ///
-    /// Here `p` is the [`SharedState`] becomes the underlying data
-    /// source for the mock, and `limits` are the default pagination
-    /// limits as a [`PaginationLimits`] object, which are applied
-    /// when the client does not give any.
-    pub async fn new(p: SharedState, limits: PaginationLimits) -> LavaMock {
-        let s = wiremock::MockServer::start().await;
+    /// By default every endpoint is served, without pagination or
+    /// faults, and the server reports its version as
+    /// [`DEFAULT_VERSION`].
+    pub fn new(state: SharedState) -> Self {
+        Self {
+            state,
+            limits: PaginationLimits::new(),
+            version: DEFAULT_VERSION.to_string(),
+            disabled: HashSet::new(),
+            faults: Vec::new(),
+            logs: JobLogParams::new(),
+            require_token: false,
+            churn: Vec::new(),
+        }
+    }
 
-        wiremock::Mock::given(wiremock::matchers::method("GET"))
-            .and(wiremock::matchers::path("/api/v0.2/aliases/"))
-            .respond_with(p.endpoint::<Alias<State>>(Some(&s.uri()), limits.aliases))
-            .mount(&s)
-            .await;
+    /// Set the default pagination limits, which are applied when the
+    /// client does not give any.
+    pub fn limits(mut self, limits: PaginationLimits) -> Self {
+        self.limits = limits;
+        self
+    }
 
-        wiremock::Mock::given(wiremock::matchers::method("GET"))
-            .and(nested_endpoint_matches("/api/v0.2", "jobs", "tests"))
-            .respond_with(p.nested_endpoint::<TestCase<State>>(
-                NestedEndpointParams {
-                    root: "/api/v0.2",
-                    parent: "jobs",
-                    child: "tests",
-                    parent_query: "suite__job__id",
-                    base_uri: Some(&s.uri()),
-                },
-                limits.test_cases,
-            ))
-            .mount(&s)
-            .await;
+    /// Set the version the server reports from
+    /// `/api/v0.2/system/version/`.
+    pub fn version<T: Into<String>>(mut self, version: T) -> Self {
+        self.version = version.into();
+        self
+    }
 
-        wiremock::Mock::given(wiremock::matchers::method("GET"))
-            .and(nested_endpoint_matches("/api/v0.2", "jobs", "suites"))
-            .respond_with(p.nested_endpoint::<TestSuite<State>>(
-                NestedEndpointParams {
-                    root: "/api/v0.2",
-                    parent: "jobs",
-                    child: "suites",
-                    parent_query: "suite__job__id",
-                    base_uri: Some(&s.uri()),
-                },
-                limits.test_suites,
-            ))
-            .mount(&s)
-            .await;
+    /// Set how the logs of jobs are generated.
+    pub fn job_logs(mut self, params: JobLogParams) -> Self {
+        self.logs = params;
+        self
+    }
 
-        wiremock::Mock::given(wiremock::matchers::method("GET"))
-            .and(nested_endpoint_matches("/api/v0.2", "jobs", "junit"))
-            .respond_with(junit_endpoint(p.clone()))
-            .mount(&s)
-            .await;
+    /// Set whether requests must carry a token.
+    ///
+    /// By default anonymous requests are served, seeing only what an
+    /// anonymous user of a real server can, such as public jobs and
+    /// users without their email addresses. When
+    /// a token is required, requests without an `Authorization:
+    /// Token` header receive a 401 response from every endpoint but
+    /// `/api/v0.2/system/version/`, as from a server which requires
+    /// users to log in. Requests with a token no [`User`] holds are
+    /// rejected either way.
+    pub fn require_token(mut self, require: bool) -> Self {
+        self.require_token = require;
+        self
+    }
 
-        wiremock::Mock::given(wiremock::matchers::method("GET"))
-            .and(wiremock::matchers::path("/api/v0.2/jobs/"))
-            .respond_with(p.endpoint::<Job<State>>(Some(&s.uri()), limits.jobs))
-            .mount(&s)
-            .await;
+    /// Change the jobs with `churn` after every request to
+    /// `/api/v0.2/jobs/`.
+    ///
+    /// Several changes can be given, in which case they are made in
+    /// the order given. See [`ChurnEndpoint`](crate::ChurnEndpoint).
+    pub fn churn(mut self, churn: Churn) -> Self {
+        self.churn.push(churn);
+        self
+    }
 
-        wiremock::Mock::given(wiremock::matchers::method("GET"))
-            .and(wiremock::matchers::path("/api/v0.2/devicetypes/"))
-            .respond_with(p.endpoint::<DeviceType<State>>(Some(&s.uri()), limits.device_types))
-            .mount(&s)
-            .await;
+    /// Leave `endpoint` out of the mock.
+    pub fn disable(mut self, endpoint: Endpoint) -> Self {
+        self.disabled.insert(endpoint);
+        self
+    }
 
-        wiremock::Mock::given(wiremock::matchers::method("GET"))
-            .and(wiremock::matchers::path("/api/v0.2/devices/"))
-            .respond_with(p.endpoint::<Device<State>>(Some(&s.uri()), limits.devices))
-            .mount(&s)
-            .await;
+    /// Reply to requests for `endpoint` with `fault`.
+    ///
+    /// Several faults can be given for the same endpoint, in which
+    /// case they are used in the order given, each until it has been
+    /// used up. Faults are given even for disabled endpoints.
+    pub fn fault(mut self, endpoint: Endpoint, fault: Fault) -> Self {
+        self.faults.push((endpoint, fault));
+        self
+    }
 
-        wiremock::Mock::given(wiremock::matchers::method("GET"))
-            .and(wiremock::matchers::path("/api/v0.2/tags/"))
-            .respond_with(p.endpoint::<Tag<State>>(Some(&s.uri()), limits.tags))
-            .mount(&s)
-            .await;
+    /// Start the [`LavaMock`].
+    pub async fn build(self) -> LavaMock {
+        let s = wiremock::MockServer::start().await;
+        let p = self.state;
+        let limits = self.limits;
+
+        let state = p.clone();
+        let require_token = self.require_token;
+        Mock::given(move |request: &Request| {
+            request.url.path() != "/api/v0.2/system/version/"
+                && match authenticate(&state.access(), request) {
+                    Ok(Some(_)) => false,
+                    Ok(None) => require_token,
+                    Err(_) => true,
+                }
+        })
+        .respond_with(rejected_token_endpoint(p.clone()))
+        .with_priority(1)
+        .mount(&s)
+        .await;
+
+        for (endpoint, fault) in self.faults.iter() {
+            let mock = endpoint
+                .given()
+                .respond_with(fault.response())
+                .with_priority(1);
+            let mock = match fault.times {
+                Some(n) => mock.up_to_n_times(n),
+                None => mock,
+            };
+            mock.mount(&s).await;
+        }
 
-        wiremock::Mock::given(wiremock::matchers::method("GET"))
-            .and(wiremock::matchers::path("/api/v0.2/workers/"))
-            .respond_with(p.endpoint::<Worker<State>>(Some(&s.uri()), limits.workers))
-            .mount(&s)
-            .await;
+        for endpoint in Endpoint::iter().filter(|e| !self.disabled.contains(e)) {
+            let mock = endpoint.given();
+            let mock = match endpoint {
+                Endpoint::Aliases => {
+                    mock.respond_with(p.endpoint::<Alias<State>>(Some(&s.uri()), limits.aliases))
+                }
+                Endpoint::TestCases => mock.respond_with(p.nested_endpoint::<TestCase<State>>(
+                    NestedEndpointParams {
+                        root: "/api/v0.2",
+                        parent: "jobs",
+                        child: "tests",
+                        parent_query: "suite__job__id",
+                        base_uri: Some(&s.uri()),
+                    },
+                    limits.test_cases,
+                )),
+                Endpoint::TestSuites => mock.respond_with(p.nested_endpoint::<TestSuite<State>>(
+                    NestedEndpointParams {
+                        root: "/api/v0.2",
+                        parent: "jobs",
+                        child: "suites",
+                        parent_query: "suite__job__id",
+                        base_uri: Some(&s.uri()),
+                    },
+                    limits.test_suites,
+                )),
+                Endpoint::Junit => mock.respond_with(junit_endpoint(p.clone())),
+                Endpoint::Cancel => mock.respond_with(cancel_endpoint(p.clone())),
+                Endpoint::Resubmit => mock.respond_with(resubmit_endpoint(p.clone())),
+                Endpoint::JobLog => {
+                    mock.respond_with(job_log_endpoint(p.clone(), self.logs.clone()))
+                }
+                Endpoint::Jobs => mock.respond_with(churn_endpoint(
+                    p.clone(),
+                    ignored_fields_endpoint(visible_jobs_endpoint(
+                        p.clone(),
+                        p.endpoint::<Job<State>>(Some(&s.uri()), limits.jobs),
+                    )),
+                    self.churn.clone(),
+                )),
+                Endpoint::JobDetail => mock.respond_with(job_detail_endpoint(p.clone())),
+                Endpoint::Submission => mock.respond_with(submission_endpoint(p.clone())),
+                Endpoint::DeviceTypes => mock.respond_with(
+                    p.endpoint::<DeviceType<State>>(Some(&s.uri()), limits.device_types),
+                ),
+                Endpoint::Devices => {
+                    mock.respond_with(p.endpoint::<Device<State>>(Some(&s.uri()), limits.devices))
+                }
+                Endpoint::DeviceUpdate => mock.respond_with(device_update_endpoint(p.clone())),
+                Endpoint::Tags => mock.respond_with(live_tags_endpoint(
+                    p.clone(),
+                    p.endpoint::<Tag<State>>(Some(&s.uri()), limits.tags),
+                )),
+                Endpoint::CreateTag => mock.respond_with(create_tag_endpoint(p.clone())),
+                Endpoint::DeleteTag => mock.respond_with(delete_tag_endpoint(p.clone())),
+                Endpoint::Workers => {
+                    mock.respond_with(p.endpoint::<Worker<State>>(Some(&s.uri()), limits.workers))
+                }
+                Endpoint::WorkerUpdate => mock.respond_with(worker_update_endpoint(p.clone())),
+                Endpoint::Users => mock.respond_with(restricted_users_endpoint(
+                    p.clone(),
+                    Some(&s.uri()),
+                    limits.users,
+                )),
+                Endpoint::Version => mock.respond_with(
+                    ResponseTemplate::new(200).set_body_json(json!({ "version": &self.version })),
+                ),
+                Endpoint::Whoami => mock.respond_with(whoami_endpoint(p.clone())),
+                Endpoint::Groups => {
+                    mock.respond_with(p.endpoint::<Group<State>>(Some(&s.uri()), limits.groups))
+                }
+            };
+            mock.mount(&s).await;
+        }
 
         LavaMock {
             server: s,
             state: p,
         }
     }
+}
+
+impl LavaMock {
+    /// Create and start a new [`LavaMock`]
+    ///
+    /// Here `p` is the [`SharedState`] becomes the underlying data
+    /// source for the mock, and `limits` are the default pagination
+    /// limits as a [`PaginationLimits`] object, which are applied
+    /// when the client does not give any. For other settings, use
+    /// [`builder`](LavaMock::builder).
+    pub async fn new(p: SharedState, limits: PaginationLimits) -> LavaMock {
+        LavaMockBuilder::new(p).limits(limits).build().await
+    }
+
+    /// Create a [`LavaMockBuilder`] for a mock serving `p`.
+    pub fn builder(p: SharedState) -> LavaMockBuilder {
+        LavaMockBuilder::new(p)
+    }
 
     /// Create and start a default new [`LavaMock`].
     ///
@@ -204,11 +594,11 @@ impl LavaMock {
 mod test {
     use super::*;
 
-    use crate::{devicetypes::DeviceType, Device, Job, JobState};
+    use crate::{devicetypes::DeviceType, Device, Job, JobState, PopulationParams};
 
     use anyhow::Result;
     use boulder::{
-        BuildableWithPersianRug, BuilderWithPersianRug, GeneratableWithPersianRug,
+        BuildableWithPersianRug, Builder, BuilderWithPersianRug, GeneratableWithPersianRug,
         TryRepeatFromPersianRug,
     };
     use boulder::{GeneratorToGeneratorWithPersianRugWrapper, GeneratorWithPersianRugMutIterator};
@@ -285,4 +675,127 @@ mod test {
 
         assert_eq!(jobs["results"].as_array().unwrap().len(), 500);
     }
+
+    #[tokio::test]
+    async fn test_users() {
+        let pop = PopulationParams::builder()
+            .groups(2usize)
+            .users(4usize)
+            .jobs(0usize)
+            .build();
+        let mock = LavaMock::new(SharedState::new_populated(pop), Default::default()).await;
+
+        let users = make_request(mock.uri(), "users/")
+            .await
+            .expect("failed to query users");
+        let users = users["results"].as_array().unwrap();
+        assert_eq!(users.len(), 4);
+        for user in users {
+            assert!(user["username"].is_string());
+            assert!(user["email"].is_null());
+            assert!(user.get("token").is_none());
+        }
+
+        let groups = make_request(mock.uri(), "groups/")
+            .await
+            .expect("failed to query groups");
+        assert_eq!(groups["results"].as_array().unwrap().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_builder() {
+        let pop = PopulationParams::builder()
+            .workers(3usize)
+            .devices(5usize)
+            .jobs(0usize)
+            .build();
+        let mock = LavaMock::builder(SharedState::new_populated(pop))
+            .limits(PaginationLimits::builder().devices(Some(2)).build())
+            .disable(Endpoint::Tags)
+            .disable(Endpoint::Whoami)
+            .fault(Endpoint::Workers, Fault::new(503).times(1))
+            .fault(
+                Endpoint::Workers,
+                Fault::new(502).times(1).delay(Duration::from_millis(10)),
+            )
+            .fault(Endpoint::Groups, Fault::new(500))
+            .build()
+            .await;
+
+        let status = |endpoint: &'static str| {
+            let url = format!("{}/api/v0.2/{}", mock.uri(), endpoint);
+            async move { reqwest::get(&url).await.unwrap().status().as_u16() }
+        };
+
+        assert_eq!(status("tags/").await, 404);
+        assert_eq!(status("system/whoami/").await, 404);
+        assert_eq!(status("workers/").await, 503);
+        assert_eq!(status("workers/").await, 502);
+        assert_eq!(status("workers/").await, 200);
+        for _ in 0..3 {
+            assert_eq!(status("groups/").await, 500);
+        }
+
+        let devices = make_request(mock.uri(), "devices/")
+            .await
+            .expect("failed to query devices");
+        assert_eq!(devices["count"], 5);
+        assert_eq!(devices["results"].as_array().unwrap().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_require_token() {
+        let mut s = SharedState::new();
+        let _ = Proxy::<User<State>>::builder()
+            .username("fred")
+            .token(Some("secret".to_string()))
+            .build(s.mutate());
+        let mock = LavaMock::builder(s).require_token(true).build().await;
+
+        let get = |endpoint: &'static str, token: Option<&'static str>| {
+            let mut request =
+                reqwest::Client::new().get(format!("{}/api/v0.2/{}", mock.uri(), endpoint));
+            if let Some(token) = token {
+                request = request.header("Authorization", format!("Token {}", token));
+            }
+            async move { request.send().await.unwrap() }
+        };
+        let status = |endpoint: &'static str, token: Option<&'static str>| async move {
+            get(endpoint, token).await.status().as_u16()
+        };
+
+        assert_eq!(status("jobs/", None).await, 401);
+        assert_eq!(status("jobs/", Some("secret")).await, 200);
+        assert_eq!(status("jobs/", Some("wrong")).await, 401);
+        assert_eq!(status("system/whoami/", Some("secret")).await, 200);
+        assert_eq!(status("system/version/", None).await, 200);
+
+        for endpoint in ["devices/", "workers/", "devicetypes/", "aliases/", "tags/"] {
+            assert_eq!(status(endpoint, None).await, 401);
+            assert_eq!(status(endpoint, Some("secret")).await, 200);
+            let response = get(endpoint, Some("wrong")).await;
+            assert_eq!(response.status().as_u16(), 401);
+            let body: serde_json::Value = response.json().await.unwrap();
+            assert_eq!(body["detail"], "Invalid token.");
+        }
+
+        let users: serde_json::Value = get("users/", Some("secret")).await.json().await.unwrap();
+        assert_eq!(users["results"][0]["email"], "test@test.com");
+    }
+
+    #[tokio::test]
+    async fn test_unknown_token() {
+        let mock = LavaMock::new(SharedState::new(), Default::default()).await;
+
+        let status = |endpoint: &'static str, token: &'static str| {
+            let request = reqwest::Client::new()
+                .get(format!("{}/api/v0.2/{}", mock.uri(), endpoint))
+                .header("Authorization", format!("Token {}", token));
+            async move { request.send().await.unwrap().status().as_u16() }
+        };
+
+        assert_eq!(status("devices/", "wrong").await, 401);
+        assert_eq!(status("tags/", "wrong").await, 401);
+        assert_eq!(status("system/version/", "wrong").await, 200);
+    }
 }
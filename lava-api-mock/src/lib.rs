@@ -66,25 +66,56 @@
 //! # });
 //! ```
 
+mod churn;
+mod detail;
 mod devices;
 mod devicetypes;
+#[doc(hidden)]
+pub mod doctest;
+mod fields;
 mod jobs;
 mod junit;
 mod lava_mock;
+mod logs;
+mod permissions;
 mod state;
+mod submission;
 mod tags;
 mod testcases;
 mod users;
 mod workers;
 
-pub use devices::{Device, Health as DeviceHealth, State as DeviceState};
+pub use churn::{churn_endpoint, Churn, ChurnEndpoint};
+pub use detail::{job_detail_endpoint, JobDetailEndpoint};
+pub use devices::{
+    device_update_endpoint, Device, DeviceUpdateEndpoint, Health as DeviceHealth,
+    State as DeviceState,
+};
 pub use devicetypes::{Alias, Architecture, BitWidth, Core, DeviceType, ProcessorFamily};
+pub use fields::{ignored_fields_endpoint, IgnoredFieldsEndpoint};
 pub use jobs::Job;
 pub use jobs::{Health as JobHealth, State as JobState};
 pub use junit::{junit_endpoint, JunitEndpoint};
-pub use lava_mock::{LavaMock, PaginationLimits};
+pub use lava_mock::{
+    Endpoint, Fault, LavaMock, LavaMockBuilder, PaginationLimits, DEFAULT_VERSION,
+};
+pub use logs::{job_log_endpoint, JobLogEndpoint, JobLogParams};
+pub use permissions::{
+    restricted_users_endpoint, visible_jobs_endpoint, whoami_endpoint, RestrictedUsersEndpoint,
+    VisibleJobsEndpoint, WhoamiEndpoint,
+};
 pub use state::{PopulationParams, SharedState, State};
-pub use tags::Tag;
+pub use submission::{
+    cancel_endpoint, resubmit_endpoint, submission_endpoint, CancelEndpoint, ResubmitEndpoint,
+    SubmissionEndpoint,
+};
+pub use tags::{
+    create_tag_endpoint, delete_tag_endpoint, live_tags_endpoint, CreateTagEndpoint,
+    DeleteTagEndpoint, LiveTagsEndpoint, Tag,
+};
 pub use testcases::{Metadata, PassFail, TestCase, TestSet, TestSuite};
 pub use users::{Group, User};
-pub use workers::Worker;
+pub use workers::{
+    worker_update_endpoint, Health as WorkerHealth, State as WorkerState, Worker,
+    WorkerUpdateEndpoint,
+};
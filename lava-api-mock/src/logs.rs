@@ -0,0 +1,285 @@
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+use persian_rug::Accessor;
+use regex::Regex;
+use serde_json::json;
+use wiremock::{Request, Respond, ResponseTemplate};
+
+use crate::permissions::{authenticate, can_view};
+use crate::{Job, JobState, SharedState, State};
+
+/// The levels given to generated log entries, in turn
+const LEVELS: &[&str] = &["info", "debug", "target"];
+
+/// Settings for the logs generated by a [`JobLogEndpoint`].
+///
+/// Every job which has started has a log of
+/// [`entries`](JobLogParams::entries) entries, the first dated at
+/// the job's [`start_time`](Job::start_time) and each following one
+/// [`interval`](JobLogParams::interval) later. The default is 20
+/// entries, one second apart.
+///
+/// Example:
+/// ```rust
+/// use lava_api_mock::JobLogParams;
+/// use std::time::Duration;
+///
+/// let params = JobLogParams::new()
+///     .entries(100)
+///     .interval(Duration::from_millis(10));
+/// ```
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct JobLogParams {
+    entries: usize,
+    interval: Duration,
+}
+
+impl JobLogParams {
+    /// Create a new [`JobLogParams`] with the default settings.
+    pub fn new() -> Self {
+        Self {
+            entries: 20,
+            interval: Duration::from_secs(1),
+        }
+    }
+
+    /// Set the number of entries in the log of each job.
+    pub fn entries(mut self, entries: usize) -> Self {
+        self.entries = entries;
+        self
+    }
+
+    /// Set the time between successive entries in each log.
+    pub fn interval(mut self, interval: Duration) -> Self {
+        self.interval = interval;
+        self
+    }
+
+    // The number of entries of the log of `job` written by `now`, or
+    // `None` if the job has no log yet.
+    fn written(&self, job: &Job<State>, now: DateTime<Utc>) -> Option<usize> {
+        let start = job.start_time?;
+        match job.state {
+            JobState::Submitted | JobState::Scheduling | JobState::Scheduled => None,
+            JobState::Finished => Some(self.entries),
+            JobState::Running | JobState::Canceling => match (now - start).to_std() {
+                Ok(elapsed) => {
+                    let interval = self.interval.as_nanos().max(1);
+                    let written = usize::try_from(elapsed.as_nanos() / interval)
+                        .unwrap_or(usize::MAX)
+                        .saturating_add(1);
+                    Some(self.entries.min(written))
+                }
+                Err(_) => Some(0),
+            },
+        }
+    }
+
+    // Render entry `index` of the log of `job` as a line of YAML, or
+    // `None` if the entry would be dated too far in the future to
+    // represent.
+    fn entry(&self, job: &Job<State>, index: usize) -> Option<String> {
+        let offset = u32::try_from(index)
+            .ok()
+            .and_then(|index| self.interval.checked_mul(index))
+            .and_then(|offset| chrono::Duration::from_std(offset).ok())?;
+        let dt = job.start_time?.checked_add_signed(offset)?;
+        let entry = json!({
+            "dt": dt.format("%Y-%m-%dT%H:%M:%S%.6f").to_string(),
+            "lvl": LEVELS[index % LEVELS.len()],
+            "msg": format!("Log entry {} of job {}", index, job.id),
+        });
+        Some(format!("- {}\n", entry))
+    }
+}
+
+impl Default for JobLogParams {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// A [`wiremock::Respond`] implementation serving generated job logs.
+///
+/// This serves `GET` requests of the form `/api/v0.2/jobs/<id>/logs/`,
+/// replying with the entries of the job's log as YAML, one per line,
+/// as a real server does. The log is generated according to a
+/// [`JobLogParams`]. For finished jobs the whole log is served, while
+/// for running jobs it grows over time, serving only the entries
+/// dated no later than the time of the request, so that clients
+/// following a log can be tested.
+///
+/// The `start` and `end` query parameters select the entries from
+/// index `start` up to, but not including, index `end`, with an `end`
+/// of 0 meaning the end of the log. Requests for jobs which have not
+/// started, which are unknown, or which are not visible to the user
+/// making the request, receive a 404 response.
+pub struct JobLogEndpoint {
+    data: SharedState,
+    params: JobLogParams,
+}
+
+impl Respond for JobLogEndpoint {
+    fn respond(&self, request: &Request) -> ResponseTemplate {
+        let state = self.data.access();
+        let user = match authenticate(&state, request) {
+            Ok(user) => user,
+            Err(response) => return response,
+        };
+
+        let rr = Regex::new(r"/api/v0.2/jobs/(?P<id>[0-9]+)/logs/$").unwrap();
+        let job = rr
+            .captures(request.url.path())
+            .and_then(|captures| captures.get(1).unwrap().as_str().parse::<i64>().ok())
+            .and_then(|id| state.get_iter::<Job<State>>().find(|j| j.id == id))
+            .filter(|job| can_view(&state, user.as_ref(), job));
+        let (job, written) = match job
+            .and_then(|job| Some((job, self.params.written(job, Utc::now())?)))
+        {
+            Some(found) => found,
+            None => {
+                return ResponseTemplate::new(404).set_body_json(json!({ "detail": "Not found." }))
+            }
+        };
+
+        let mut start = 0;
+        let mut end = 0;
+        for (key, value) in request.url.query_pairs() {
+            let value = match value.parse::<usize>() {
+                Ok(value) => value,
+                Err(_) => return ResponseTemplate::new(400),
+            };
+            match key.as_ref() {
+                "start" => start = value,
+                "end" => end = value,
+                _ => {}
+            }
+        }
+        let end = if end == 0 { written } else { end.min(written) };
+
+        let body = (start..end)
+            .map_while(|index| self.params.entry(job, index))
+            .collect::<String>();
+        ResponseTemplate::new(200).set_body_raw(body, "application/yaml")
+    }
+}
+
+/// Create a new [`JobLogEndpoint`] for the given [`SharedState`],
+/// generating logs according to `params`.
+///
+/// Example:
+/// ```rust
+/// use django_query::mock::nested_endpoint_matches;
+/// use lava_api_mock::{job_log_endpoint, JobLogParams, SharedState};
+///
+/// let p = SharedState::new();
+///
+/// # tokio_test::block_on( async {
+/// let server = wiremock::MockServer::start().await;
+///
+/// wiremock::Mock::given(wiremock::matchers::method("GET"))
+///     .and(nested_endpoint_matches("/api/v0.2", "jobs", "logs"))
+///     .respond_with(job_log_endpoint(p, JobLogParams::new()))
+///     .mount(&server)
+///     .await;
+/// # });
+/// ```
+pub fn job_log_endpoint(data: SharedState, params: JobLogParams) -> JobLogEndpoint {
+    JobLogEndpoint { data, params }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use boulder::{BuildableWithPersianRug, BuilderWithPersianRug};
+    use persian_rug::{Mutator, Proxy};
+    use test_log::test;
+
+    #[test(tokio::test)]
+    async fn test_logs() {
+        // Entries are an hour apart, so that the log does not grow
+        // while the test runs
+        let params = JobLogParams::new()
+            .entries(5)
+            .interval(Duration::from_secs(60 * 60));
+        let mut p = SharedState::new();
+        let job = Proxy::<Job<State>>::builder()
+            .id(1)
+            .state(JobState::Running)
+            .start_time(Some(Utc::now() - chrono::Duration::minutes(150)))
+            .build(p.mutate())
+            .0;
+        let _ = Proxy::<Job<State>>::builder()
+            .id(2)
+            .state(JobState::Submitted)
+            .build(p.mutate());
+
+        let server = wiremock::MockServer::start().await;
+        wiremock::Mock::given(wiremock::matchers::method("GET"))
+            .and(django_query::mock::nested_endpoint_matches(
+                "/api/v0.2",
+                "jobs",
+                "logs",
+            ))
+            .respond_with(job_log_endpoint(p.clone(), params))
+            .mount(&server)
+            .await;
+
+        let get = |id: i64, query: &str| {
+            let url = format!("{}/api/v0.2/jobs/{}/logs/{}", server.uri(), id, query);
+            async move {
+                let response = reqwest::get(&url).await.expect("failed to read log");
+                let status = response.status().as_u16();
+                let body = response.text().await.expect("failed to read log");
+                (status, body)
+            }
+        };
+
+        // Only the first three entries have been written so far
+        let (status, body) = get(1, "").await;
+        assert_eq!(status, 200);
+        let entries: Vec<serde_yaml::Value> =
+            serde_yaml::from_str(&body).expect("failed to parse log");
+        assert_eq!(entries.len(), 3);
+        assert_eq!(entries[0]["lvl"], "info");
+        assert_eq!(entries[1]["msg"], "Log entry 1 of job 1");
+
+        let (_, body) = get(1, "?start=1&end=2").await;
+        assert_eq!(body.lines().count(), 1);
+        assert!(body.contains("Log entry 1 of job 1"));
+        let (_, body) = get(1, "?start=10").await;
+        assert_eq!(body, "");
+
+        assert_eq!(get(2, "").await.0, 404);
+        assert_eq!(get(3, "").await.0, 404);
+
+        {
+            let mut m = p.mutate();
+            m.get_mut(&job).state = JobState::Finished;
+        }
+        let (_, body) = get(1, "?start=2").await;
+        assert_eq!(body.lines().count(), 3);
+    }
+
+    #[test]
+    fn test_entry_overflow() {
+        let mut p = SharedState::new();
+        let job = Proxy::<Job<State>>::builder()
+            .id(1)
+            .state(JobState::Finished)
+            .start_time(Some(Utc::now()))
+            .build(p.mutate())
+            .0;
+        let params = JobLogParams::new()
+            .entries(usize::MAX)
+            .interval(Duration::from_secs(u64::MAX / 4));
+
+        let state = p.access();
+        let job = state.get(&job);
+        assert!(params.entry(job, 0).is_some());
+        assert_eq!(params.entry(job, 8), None);
+        assert_eq!(params.entry(job, usize::MAX), None);
+    }
+}
@@ -0,0 +1,467 @@
+use persian_rug::{Accessor, Mutator, Proxy};
+use serde_json::json;
+use wiremock::{Request, Respond, ResponseTemplate};
+
+use crate::{Job, SharedState, State, User};
+
+// Find the user making a request, from its `Authorization: Token`
+// header. Requests without the header are anonymous, and those with
+// a token no user holds are rejected as by a real server.
+pub(crate) fn authenticate(
+    state: &State,
+    request: &Request,
+) -> Result<Option<Proxy<User<State>>>, ResponseTemplate> {
+    let header = match request
+        .headers
+        .iter()
+        .find(|(name, _)| name.as_str().eq_ignore_ascii_case("authorization"))
+    {
+        Some((_, values)) => values.last().as_str().to_string(),
+        None => return Ok(None),
+    };
+
+    header
+        .strip_prefix("Token ")
+        .and_then(|token| {
+            state
+                .get_proxy_iter::<User<State>>()
+                .find(|u| state.get(u).token.as_deref() == Some(token))
+                .cloned()
+        })
+        .map(Some)
+        .ok_or_else(|| {
+            ResponseTemplate::new(401).set_body_json(json!({ "detail": "Invalid token." }))
+        })
+}
+
+// Check that a request to change tags or devices comes from a
+// superuser, returning the response to give otherwise.
+pub(crate) fn check_superuser(state: &State, request: &Request) -> Result<(), ResponseTemplate> {
+    match authenticate(state, request)? {
+        Some(user) if state.get(&user).is_superuser => Ok(()),
+        Some(_) => Err(ResponseTemplate::new(403).set_body_json(
+            json!({ "detail": "You do not have permission to perform this action." }),
+        )),
+        None => Err(not_provided()),
+    }
+}
+
+// The response a real server gives to anonymous requests for what
+// needs a user.
+fn not_provided() -> ResponseTemplate {
+    ResponseTemplate::new(401)
+        .set_body_json(json!({ "detail": "Authentication credentials were not provided." }))
+}
+
+// Reject requests with an unknown token, or without one, as a real
+// server does before reaching any view. The `LavaMock` only mounts
+// this for requests it has already found to be rejected.
+pub(crate) struct RejectedTokenEndpoint {
+    data: SharedState,
+}
+
+impl Respond for RejectedTokenEndpoint {
+    fn respond(&self, request: &Request) -> ResponseTemplate {
+        match authenticate(&self.data.access(), request) {
+            Err(response) => response,
+            Ok(_) => not_provided(),
+        }
+    }
+}
+
+pub(crate) fn rejected_token_endpoint(data: SharedState) -> RejectedTokenEndpoint {
+    RejectedTokenEndpoint { data }
+}
+
+/// Whether `user` (or an anonymous user, for `None`) may see `job`.
+///
+/// Public jobs are visible to everyone. Other jobs are visible to
+/// superusers, to their submitter, and to members of any of their
+/// viewing groups.
+pub(crate) fn can_view(state: &State, user: Option<&Proxy<User<State>>>, job: &Job<State>) -> bool {
+    if job.is_public {
+        return true;
+    }
+    let user = match user {
+        Some(user) => user,
+        None => return false,
+    };
+    if &job.submitter == user {
+        return true;
+    }
+    let user = state.get(user);
+    user.is_superuser
+        || user
+            .group
+            .as_ref()
+            .map(|g| job.viewing_groups.contains(g))
+            .unwrap_or(false)
+}
+
+/// Whether `user` (or an anonymous user, for `None`) may cancel or
+/// resubmit `job`.
+///
+/// Only superusers and the submitter of a job may change it.
+pub(crate) fn can_change(
+    state: &State,
+    user: Option<&Proxy<User<State>>>,
+    job: &Job<State>,
+) -> bool {
+    match user {
+        Some(user) => &job.submitter == user || state.get(user).is_superuser,
+        None => false,
+    }
+}
+
+/// Restrict the jobs served for `request` to those with the given
+/// ids, by adding an `id__in` filter to a copy of it.
+///
+/// The filter always starts with -1, which is never a job id, so that
+/// it matches nothing when `ids` is empty, and so that
+/// [`remove_id_filters`] can recognise it when it is carried into the
+/// links to other pages.
+pub(crate) fn add_id_filter<I>(request: &Request, ids: I) -> Request
+where
+    I: IntoIterator<Item = i64>,
+{
+    let ids = std::iter::once(-1)
+        .chain(ids)
+        .map(|id| id.to_string())
+        .collect::<Vec<_>>()
+        .join(",");
+    let mut request = request.clone();
+    request.url.query_pairs_mut().append_pair("id__in", &ids);
+    request
+}
+
+/// Remove the filters added by [`add_id_filter`] from a copy of
+/// `request`.
+///
+/// The jobs endpoint builds its links to other pages from the
+/// request it was given, so a client following them sends back the
+/// filters added for an earlier request, which would otherwise hide
+/// jobs added since.
+pub(crate) fn remove_id_filters(request: &Request) -> Request {
+    let mut request = request.clone();
+    let pairs = request
+        .url
+        .query_pairs()
+        .filter(|(k, v)| k != "id__in" || (v != "-1" && !v.starts_with("-1,")))
+        .map(|(k, v)| (k.into_owned(), v.into_owned()))
+        .collect::<Vec<_>>();
+    if pairs.is_empty() {
+        request.url.set_query(None);
+    } else {
+        request.url.query_pairs_mut().clear().extend_pairs(pairs);
+    }
+    request
+}
+
+/// A [`wiremock::Respond`] implementation restricting the jobs a
+/// request can see.
+///
+/// This wraps another endpoint serving [`Job`] instances, usually
+/// one created by [`SharedState::endpoint`], and hides from it the
+/// jobs that the user making the request cannot see. Public jobs are
+/// visible to everyone; other jobs only to superusers, to their
+/// submitter, and to members of any of their viewing groups. The
+/// user is identified by the token in the request, as for a real
+/// server, and requests with an unknown token receive a 401 response.
+pub struct VisibleJobsEndpoint<R> {
+    data: SharedState,
+    inner: R,
+}
+
+impl<R: Respond> Respond for VisibleJobsEndpoint<R> {
+    fn respond(&self, request: &Request) -> ResponseTemplate {
+        let state = self.data.access();
+        let user = match authenticate(&state, request) {
+            Ok(user) => user,
+            Err(response) => return response,
+        };
+
+        let mut hidden = false;
+        let visible = state
+            .get_iter::<Job<State>>()
+            .filter(|job| {
+                let visible = can_view(&state, user.as_ref(), job);
+                hidden |= !visible;
+                visible
+            })
+            .map(|job| job.id)
+            .collect::<Vec<_>>();
+
+        if !hidden {
+            return self.inner.respond(request);
+        }
+
+        let request = add_id_filter(request, visible);
+        self.inner.respond(&request)
+    }
+}
+
+/// Create a new [`VisibleJobsEndpoint`] wrapping `inner`, with
+/// visibility determined from the given [`SharedState`].
+///
+/// Example:
+/// ```rust
+/// use lava_api_mock::{visible_jobs_endpoint, Job, SharedState, State};
+///
+/// let p = SharedState::new();
+///
+/// # tokio_test::block_on( async {
+/// let server = wiremock::MockServer::start().await;
+///
+/// wiremock::Mock::given(wiremock::matchers::method("GET"))
+///     .and(wiremock::matchers::path("/api/v0.2/jobs/"))
+///     .respond_with(visible_jobs_endpoint(
+///         p.clone(),
+///         p.endpoint::<Job<State>>(Some(&server.uri()), None),
+///     ))
+///     .mount(&server)
+///     .await;
+/// # });
+/// ```
+pub fn visible_jobs_endpoint<R: Respond>(data: SharedState, inner: R) -> VisibleJobsEndpoint<R> {
+    VisibleJobsEndpoint { data, inner }
+}
+
+/// A [`wiremock::Respond`] implementation serving [`User`] instances
+/// with restricted fields for anonymous requests.
+///
+/// Requests with a token are served every user in full, as by an
+/// endpoint created by [`SharedState::endpoint`]. Anonymous requests
+/// are served the same users, but without their
+/// [`email`](User::email) addresses, as by a real server which keeps
+/// those from visitors who have not logged in. Filtering on email
+/// addresses then matches nothing. Requests with an unknown token
+/// receive a 401 response.
+pub struct RestrictedUsersEndpoint {
+    data: SharedState,
+    uri: Option<String>,
+    default_limit: Option<usize>,
+}
+
+impl Respond for RestrictedUsersEndpoint {
+    fn respond(&self, request: &Request) -> ResponseTemplate {
+        let state = self.data.access();
+        match authenticate(&state, request) {
+            Ok(Some(_)) => self
+                .data
+                .endpoint::<User<State>>(self.uri.as_deref(), self.default_limit)
+                .respond(request),
+            Ok(None) => {
+                let mut restricted = (*state).clone();
+                for user in (&mut restricted).get_iter_mut::<User<State>>() {
+                    user.email = None;
+                }
+                SharedState::from_state(restricted)
+                    .endpoint::<User<State>>(self.uri.as_deref(), self.default_limit)
+                    .respond(request)
+            }
+            Err(response) => response,
+        }
+    }
+}
+
+/// Create a new [`RestrictedUsersEndpoint`] for the given
+/// [`SharedState`].
+///
+/// The `uri` and `default_limit` are used as for
+/// [`SharedState::endpoint`].
+///
+/// Example:
+/// ```rust
+/// use lava_api_mock::{restricted_users_endpoint, SharedState};
+///
+/// let p = SharedState::new();
+///
+/// # tokio_test::block_on( async {
+/// let server = wiremock::MockServer::start().await;
+///
+/// wiremock::Mock::given(wiremock::matchers::method("GET"))
+///     .and(wiremock::matchers::path("/api/v0.2/users/"))
+///     .respond_with(restricted_users_endpoint(p, Some(&server.uri()), None))
+///     .mount(&server)
+///     .await;
+/// # });
+/// ```
+pub fn restricted_users_endpoint(
+    data: SharedState,
+    uri: Option<&str>,
+    default_limit: Option<usize>,
+) -> RestrictedUsersEndpoint {
+    RestrictedUsersEndpoint {
+        data,
+        uri: uri.map(str::to_string),
+        default_limit,
+    }
+}
+
+/// A [`wiremock::Respond`] implementation reporting the user making
+/// a request.
+///
+/// This serves `/api/v0.2/system/whoami/`, replying with the
+/// username, email, superuser status and group names of the [`User`]
+/// whose token the request carries, or `null` for anonymous
+/// requests. Requests with an unknown token receive a 401 response.
+pub struct WhoamiEndpoint {
+    data: SharedState,
+}
+
+impl Respond for WhoamiEndpoint {
+    fn respond(&self, request: &Request) -> ResponseTemplate {
+        let state = self.data.access();
+        let user = match authenticate(&state, request) {
+            Ok(Some(user)) => state.get(&user),
+            Ok(None) => return ResponseTemplate::new(200).set_body_json(json!(null)),
+            Err(response) => return response,
+        };
+
+        let groups = user
+            .group
+            .iter()
+            .map(|g| state.get(g).name.clone())
+            .collect::<Vec<_>>();
+        ResponseTemplate::new(200).set_body_json(json!({
+            "username": user.username,
+            "email": user.email,
+            "is_superuser": user.is_superuser,
+            "groups": groups,
+            "permissions": [],
+        }))
+    }
+}
+
+/// Create a new [`WhoamiEndpoint`] for the given [`SharedState`].
+///
+/// Example:
+/// ```rust
+/// use lava_api_mock::{whoami_endpoint, SharedState};
+///
+/// let p = SharedState::new();
+///
+/// # tokio_test::block_on( async {
+/// let server = wiremock::MockServer::start().await;
+///
+/// wiremock::Mock::given(wiremock::matchers::method("GET"))
+///     .and(wiremock::matchers::path("/api/v0.2/system/whoami/"))
+///     .respond_with(whoami_endpoint(p))
+///     .mount(&server)
+///     .await;
+/// # });
+/// ```
+pub fn whoami_endpoint(data: SharedState) -> WhoamiEndpoint {
+    WhoamiEndpoint { data }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::Group;
+
+    use boulder::{BuildableWithPersianRug, BuilderWithPersianRug};
+    use serde_json::Value;
+    use test_log::test;
+
+    async fn visible_ids(server: &wiremock::MockServer, token: Option<&str>) -> (u16, Vec<i64>) {
+        let mut request = reqwest::Client::new().get(&format!("{}/api/v0.2/jobs/", server.uri()));
+        if let Some(token) = token {
+            request = request.header("Authorization", format!("Token {}", token));
+        }
+        let response = request.send().await.expect("failed to query jobs");
+        let status = response.status().as_u16();
+        if status != 200 {
+            return (status, Vec::new());
+        }
+        let body: Value = response.json().await.expect("failed to parse jobs");
+        let mut ids = body["results"]
+            .as_array()
+            .expect("no results in reply")
+            .iter()
+            .map(|job| job["id"].as_i64().expect("job has no id"))
+            .collect::<Vec<_>>();
+        ids.sort();
+        (status, ids)
+    }
+
+    #[test(tokio::test)]
+    async fn test_visibility() {
+        let mut p = SharedState::new();
+        {
+            let m = p.mutate();
+            let (group, m) = Proxy::<Group<State>>::builder().name("lab").build(m);
+            let (fred, m) = Proxy::<User<State>>::builder()
+                .id(1)
+                .username("fred")
+                .token(Some("fred-token".to_string()))
+                .build(m);
+            let (_, m) = Proxy::<User<State>>::builder()
+                .id(2)
+                .username("jane")
+                .group(Some(group))
+                .token(Some("jane-token".to_string()))
+                .build(m);
+            let (_, m) = Proxy::<User<State>>::builder()
+                .id(3)
+                .username("admin")
+                .is_superuser(true)
+                .token(Some("admin-token".to_string()))
+                .build(m);
+            let (_, m) = Proxy::<Job<State>>::builder()
+                .id(0)
+                .submitter(fred)
+                .is_public(true)
+                .build(m);
+            let (_, m) = Proxy::<Job<State>>::builder()
+                .id(1)
+                .submitter(fred)
+                .is_public(false)
+                .viewing_groups(Vec::new())
+                .build(m);
+            let _ = Proxy::<Job<State>>::builder()
+                .id(2)
+                .submitter(fred)
+                .is_public(false)
+                .viewing_groups(vec![group])
+                .build(m);
+        }
+
+        let server = wiremock::MockServer::start().await;
+
+        wiremock::Mock::given(wiremock::matchers::method("GET"))
+            .and(wiremock::matchers::path("/api/v0.2/jobs/"))
+            .respond_with(visible_jobs_endpoint(
+                p.clone(),
+                p.endpoint::<Job<State>>(Some(&server.uri()), None),
+            ))
+            .mount(&server)
+            .await;
+
+        assert_eq!(visible_ids(&server, None).await, (200, vec![0]));
+        assert_eq!(
+            visible_ids(&server, Some("fred-token")).await,
+            (200, vec![0, 1, 2])
+        );
+        assert_eq!(
+            visible_ids(&server, Some("jane-token")).await,
+            (200, vec![0, 2])
+        );
+        assert_eq!(
+            visible_ids(&server, Some("admin-token")).await,
+            (200, vec![0, 1, 2])
+        );
+        assert_eq!(visible_ids(&server, Some("bad-token")).await.0, 401);
+
+        let state = p.access();
+        let job = state.get_iter::<Job<State>>().nth(1).unwrap();
+        let jane = state
+            .get_proxy_iter::<User<State>>()
+            .find(|u| state.get(u).username == "jane")
+            .unwrap();
+        assert!(!can_change(&state, None, job));
+        assert!(!can_change(&state, Some(jane), job));
+        assert!(can_change(&state, Some(&job.submitter), job));
+    }
+}
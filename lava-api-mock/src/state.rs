@@ -11,7 +11,7 @@ use boulder::{
 use clone_replace::{CloneReplace, MutateGuard};
 use django_query::mock::clone_replace::persian_rug::CloneReplacePersianRugTableSource;
 use django_query::mock::{EndpointWithContext, NestedEndpointParams, NestedEndpointWithContext};
-use persian_rug::{Context, Mutator, Proxy};
+use persian_rug::{Accessor, Context, Contextual, Mutator, Owner, Proxy};
 use std::sync::Arc;
 
 /// The data backing a mock Lava instance
@@ -83,6 +83,11 @@ impl SharedState {
         Self(CloneReplace::new(State::new()))
     }
 
+    // Wrap an existing `state`.
+    pub(crate) fn from_state(state: State) -> Self {
+        Self(CloneReplace::new(state))
+    }
+
     /// Create, populate and wrap a [`State`].
     ///
     /// `pop` is a [`PopulationParams`] instance giving a count for
@@ -248,6 +253,116 @@ impl SharedState {
     pub fn mutate(&mut self) -> MutateGuard<State> {
         self.0.mutate()
     }
+
+    // Generate `count` new objects with `generator`, passing each in
+    // turn, with its index, to `f` for adjustment.
+    fn add<T, G, F>(&mut self, generator: G, count: usize, mut f: F) -> Vec<Proxy<T>>
+    where
+        T: Contextual<Context = State> + 'static,
+        State: Owner<T>,
+        G: GeneratorWithPersianRug<State, Output = Proxy<T>>,
+        F: FnMut(usize, &mut T),
+    {
+        let mut m = self.mutate();
+        let objects = GeneratorWithPersianRugIterator::new(generator, &mut *m)
+            .take(count)
+            .collect::<Vec<_>>();
+        for (i, object) in objects.iter().enumerate() {
+            f(i, m.get_mut(object));
+        }
+        objects
+    }
+
+    /// Add `count` new [`Job`] instances to the enclosed [`State`].
+    ///
+    /// The jobs are made by the generator from
+    /// [`make_job_generator`](State::make_job_generator), with ids
+    /// following on from those of the jobs already present. Each is
+    /// then passed, along with its index, to `f`, which can adjust it
+    /// to give the targeted data a test needs.
+    ///
+    /// Example:
+    /// ```rust
+    /// use lava_api_mock::{JobState, SharedState};
+    ///
+    /// let mut p = SharedState::new_populated(Default::default());
+    ///
+    /// let running = p.add_jobs(3, |_, job| job.state = JobState::Running);
+    /// assert_eq!(running.len(), 3);
+    /// ```
+    pub fn add_jobs<F>(&mut self, count: usize, mut f: F) -> Vec<Proxy<Job<State>>>
+    where
+        F: FnMut(usize, &mut Job<State>),
+    {
+        let next = self
+            .access()
+            .get_iter::<Job<State>>()
+            .map(|job| job.id + 1)
+            .max()
+            .unwrap_or_default();
+        self.add(State::make_job_generator(), count, |i, job| {
+            job.id = next + i as i64;
+            f(i, job)
+        })
+    }
+
+    /// Add `count` new [`Device`] instances to the enclosed
+    /// [`State`].
+    ///
+    /// The devices are made by the generator from
+    /// [`make_device_generator`](State::make_device_generator), with
+    /// hostnames numbered after those of the devices already
+    /// present. Each is then passed, along with its index, to `f`,
+    /// which can adjust it to give the targeted data a test needs.
+    ///
+    /// Example:
+    /// ```rust
+    /// use lava_api_mock::{DeviceHealth, SharedState};
+    ///
+    /// let mut p = SharedState::new_populated(Default::default());
+    ///
+    /// let _ = p.add_devices(2, |i, device| {
+    ///     device.hostname = format!("bad-device-{}", i);
+    ///     device.health = DeviceHealth::Bad;
+    /// });
+    /// ```
+    pub fn add_devices<F>(&mut self, count: usize, mut f: F) -> Vec<Proxy<Device<State>>>
+    where
+        F: FnMut(usize, &mut Device<State>),
+    {
+        let first = self.access().get_iter::<Device<State>>().count();
+        self.add(State::make_device_generator(), count, |i, device| {
+            device.hostname = format!("test-device-{}", first + i);
+            f(i, device)
+        })
+    }
+
+    /// Add `count` new [`Worker`] instances to the enclosed
+    /// [`State`].
+    ///
+    /// The workers are made by the default generator, with hostnames
+    /// numbered after those of the workers already present. Each is
+    /// then passed, along with its index, to `f`, which can adjust it
+    /// to give the targeted data a test needs.
+    ///
+    /// Example:
+    /// ```rust
+    /// use lava_api_mock::{SharedState, WorkerState};
+    ///
+    /// let mut p = SharedState::new_populated(Default::default());
+    ///
+    /// let _ = p.add_workers(1, |_, worker| worker.state = WorkerState::Offline);
+    /// ```
+    pub fn add_workers<F>(&mut self, count: usize, mut f: F) -> Vec<Proxy<Worker<State>>>
+    where
+        F: FnMut(usize, &mut Worker<State>),
+    {
+        let first = self.access().get_iter::<Worker<State>>().count();
+        self.add(Proxy::<Worker<State>>::generator(), count, |i, worker| {
+            worker.hostname = format!("a-test-worker-{}", first + i + 1);
+            f(i, worker)
+        })
+    }
 }
 
 impl Clone for SharedState {
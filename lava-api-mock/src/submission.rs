@@ -0,0 +1,618 @@
+use boulder::{BuildableWithPersianRug, BuilderWithPersianRug};
+use chrono::Utc;
+use persian_rug::{Accessor, Mutator, Proxy};
+use regex::Regex;
+use serde::Deserialize;
+use serde_json::json;
+use serde_yaml::Value;
+use wiremock::{Request, Respond, ResponseTemplate};
+
+use crate::permissions::{authenticate, can_change, can_view};
+use crate::{Device, DeviceType, Group, Job, JobHealth, JobState, SharedState, State, User};
+
+#[derive(Deserialize)]
+struct Submission {
+    definition: String,
+}
+
+fn invalid<T: AsRef<str>>(message: T) -> ResponseTemplate {
+    ResponseTemplate::new(400).set_body_json(json!({ "message": message.as_ref() }))
+}
+
+/// The top level keys accepted in a job definition
+const JOB_KEYS: &[&str] = &[
+    "job_name",
+    "device_type",
+    "visibility",
+    "priority",
+    "timeouts",
+    "context",
+    "metadata",
+    "secrets",
+    "environment",
+    "protocols",
+    "notify",
+    "tags",
+    "reboot_to_fastboot",
+    "compatibility",
+    "actions",
+];
+
+// Check that every top level key of a definition is one LAVA knows.
+fn check_keys(definition: &Value) -> Result<(), String> {
+    let mapping = definition
+        .as_mapping()
+        .ok_or("job definition is not a mapping")?;
+    for key in mapping.keys() {
+        match key.as_str() {
+            Some(key) if JOB_KEYS.contains(&key) => {}
+            Some(key) => return Err(format!("unknown job definition key {}", key)),
+            None => return Err("invalid job definition key".to_string()),
+        }
+    }
+    Ok(())
+}
+
+// The device type requested by each job a definition creates: one
+// per node for a multinode job, otherwise just one.
+fn node_device_types(definition: &Value) -> Result<Vec<Option<&str>>, &'static str> {
+    let multinode = match definition
+        .get("protocols")
+        .and_then(|p| p.get("lava-multinode"))
+    {
+        Some(multinode) => multinode,
+        None => return Ok(vec![definition.get("device_type").and_then(Value::as_str)]),
+    };
+
+    let roles = multinode
+        .get("roles")
+        .and_then(Value::as_mapping)
+        .ok_or("multinode job has no roles")?;
+    let mut nodes = Vec::new();
+    for role in roles.values() {
+        let count = role
+            .get("count")
+            .and_then(Value::as_u64)
+            .ok_or("multinode role has no count")? as usize;
+        let device_type = role.get("device_type").and_then(Value::as_str);
+        nodes.extend(std::iter::repeat(device_type).take(count));
+    }
+    if nodes.is_empty() {
+        return Err("multinode job has no nodes");
+    }
+    Ok(nodes)
+}
+
+// Whether the jobs a definition creates are public, and the groups
+// which can view them otherwise. Definitions without a visibility
+// are public.
+fn visibility(
+    state: &State,
+    definition: &Value,
+) -> Result<(bool, Vec<Proxy<Group<State>>>), String> {
+    let visibility = match definition.get("visibility") {
+        Some(visibility) => visibility,
+        None => return Ok((true, Vec::new())),
+    };
+    match visibility.as_str() {
+        Some("public") => return Ok((true, Vec::new())),
+        Some("personal") => return Ok((false, Vec::new())),
+        _ => {}
+    }
+
+    let names = visibility
+        .get("group")
+        .and_then(Value::as_sequence)
+        .ok_or("invalid job visibility")?;
+    let mut groups = Vec::new();
+    for name in names {
+        let name = name.as_str().ok_or("invalid group name")?;
+        let group = state
+            .get_proxy_iter::<Group<State>>()
+            .find(|g| state.get(g).name == name)
+            .ok_or_else(|| format!("unknown group {}", name))?;
+        groups.push(*group);
+    }
+    Ok((false, groups))
+}
+
+// Add the jobs described by a definition to the state, returning
+// their ids, or a message describing why the definition is invalid.
+fn create_jobs(
+    data: &SharedState,
+    submitter: Proxy<User<State>>,
+    source: &str,
+) -> Result<Vec<i64>, String> {
+    let definition: Value = serde_yaml::from_str(source).map_err(|e| e.to_string())?;
+    check_keys(&definition)?;
+    let job_name = definition
+        .get("job_name")
+        .and_then(Value::as_str)
+        .ok_or("job definition has no job_name")?;
+    let nodes = node_device_types(&definition)?;
+
+    let mut data = data.clone();
+    let (mut next_id, device_types, (is_public, viewing_groups)) = {
+        let state = data.access();
+        let visibility = visibility(&state, &definition)?;
+        let mut device_types = Vec::new();
+        for name in nodes {
+            let device_type = match name {
+                Some(name) => Some(
+                    *state
+                        .get_proxy_iter::<DeviceType<State>>()
+                        .find(|d| state.get(d).name == name)
+                        .ok_or_else(|| format!("unknown device type {}", name))?,
+                ),
+                None => None,
+            };
+            device_types.push(device_type);
+        }
+        let next_id = state
+            .get_iter::<Job<State>>()
+            .map(|j| j.id + 1)
+            .max()
+            .unwrap_or(0);
+        (next_id, device_types, visibility)
+    };
+
+    let multinode_definition = if device_types.len() > 1 { source } else { "" };
+
+    let mut m = data.mutate();
+    let mut ids = Vec::new();
+    for device_type in device_types {
+        let (_, m2) = Proxy::<Job<State>>::builder()
+            .id(next_id)
+            .submitter(submitter)
+            .is_public(is_public)
+            .viewing_groups(viewing_groups.clone())
+            .description(job_name)
+            .health_check(false)
+            .requested_device_type(device_type)
+            .actual_device(None::<Proxy<Device<State>>>)
+            .submit_time(Some(Utc::now()))
+            .definition(source)
+            .original_definition(source)
+            .multinode_definition(multinode_definition)
+            .build(m);
+        m = m2;
+        ids.push(next_id);
+        next_id += 1;
+    }
+
+    Ok(ids)
+}
+
+fn submitted(ids: Vec<i64>) -> ResponseTemplate {
+    ResponseTemplate::new(201).set_body_json(json!({
+        "message": "job(s) successfully submitted",
+        "job_ids": ids,
+    }))
+}
+
+// Find the job addressed by a request of the form
+// `/api/v0.2/jobs/<id>/<action>/`, and the user making the request,
+// or the response to give if the user may not change the job.
+fn find_job(
+    data: &SharedState,
+    request: &Request,
+    action: &str,
+) -> Result<(Proxy<Job<State>>, Proxy<User<State>>), ResponseTemplate> {
+    let state = data.access();
+    let user = authenticate(&state, request)?;
+
+    let rr = Regex::new(&format!(r"/api/v0.2/jobs/(?P<parent>[0-9]+)/{}/", action)).unwrap();
+    let job = rr
+        .captures(request.url.as_str())
+        .and_then(|captures| captures.get(1).unwrap().as_str().parse::<i64>().ok())
+        .and_then(|job_id| {
+            state
+                .get_proxy_iter::<Job<State>>()
+                .find(|j| state.get(j).id == job_id)
+                .cloned()
+        })
+        .filter(|job| can_view(&state, user.as_ref(), state.get(job)))
+        .ok_or_else(|| ResponseTemplate::new(404))?;
+
+    match user {
+        Some(user) if can_change(&state, Some(&user), state.get(&job)) => Ok((job, user)),
+        _ => Err(ResponseTemplate::new(403)),
+    }
+}
+
+/// A [`wiremock::Respond`] implementation accepting job submissions.
+///
+/// This serves `POST` requests to `/api/v0.2/jobs/`, by parsing the
+/// submitted definition and adding new [`Job`] instances to the
+/// [`SharedState`] in the [`Submitted`](crate::JobState::Submitted)
+/// state. Multinode definitions create one job per node, each
+/// requesting the device type of its role. The validation performed
+/// is minimal: the definition must be a YAML mapping containing a
+/// `job_name`, with only the top level keys LAVA accepts, otherwise a
+/// 400 response is returned, as from a real server. Any device types
+/// requested must name existing [`DeviceType`] instances, and the
+/// `visibility` of the definition, if given, must be `public`,
+/// `personal` or name existing [`Group`] instances.
+///
+/// Requests must carry the token of a [`User`], who becomes the
+/// submitter of the new jobs; anonymous requests receive a 401
+/// response.
+pub struct SubmissionEndpoint {
+    data: SharedState,
+}
+
+impl Respond for SubmissionEndpoint {
+    fn respond(&self, request: &Request) -> ResponseTemplate {
+        let submitter = match authenticate(&self.data.access(), request) {
+            Ok(Some(user)) => user,
+            Ok(None) => {
+                return ResponseTemplate::new(401).set_body_json(
+                    json!({ "detail": "Authentication credentials were not provided." }),
+                )
+            }
+            Err(response) => return response,
+        };
+        let submission: Submission = match serde_json::from_slice(&request.body) {
+            Ok(submission) => submission,
+            Err(e) => return invalid(e.to_string()),
+        };
+        match create_jobs(&self.data, submitter, &submission.definition) {
+            Ok(ids) => submitted(ids),
+            Err(e) => invalid(e),
+        }
+    }
+}
+
+/// Create a new [`SubmissionEndpoint`] for the given [`SharedState`].
+///
+/// Example:
+/// ```rust
+/// use lava_api_mock::{submission_endpoint, SharedState};
+///
+/// let p = SharedState::new();
+///
+/// # tokio_test::block_on( async {
+/// let server = wiremock::MockServer::start().await;
+///
+/// wiremock::Mock::given(wiremock::matchers::method("POST"))
+///     .and(wiremock::matchers::path("/api/v0.2/jobs/"))
+///     .respond_with(submission_endpoint(p))
+///     .mount(&server)
+///     .await;
+/// # });
+/// ```
+pub fn submission_endpoint(data: SharedState) -> SubmissionEndpoint {
+    SubmissionEndpoint { data }
+}
+
+/// A [`wiremock::Respond`] implementation for cancelling jobs.
+///
+/// This serves requests of the form `/api/v0.2/jobs/<id>/cancel/`,
+/// whatever their method.
+/// Jobs which have not yet started are finished immediately with
+/// [`Canceled`](crate::JobHealth::Canceled) health, and running jobs
+/// move to [`Canceling`](crate::JobState::Canceling). Only the
+/// submitter of a job, or a superuser, may cancel it; other requests
+/// receive a 403 response. Requests for jobs which are unknown, or
+/// not visible to the user making the request, receive a 404
+/// response.
+pub struct CancelEndpoint {
+    data: SharedState,
+}
+
+impl Respond for CancelEndpoint {
+    fn respond(&self, request: &Request) -> ResponseTemplate {
+        let (job, _) = match find_job(&self.data, request, "cancel") {
+            Ok(found) => found,
+            Err(response) => return response,
+        };
+
+        let mut data = self.data.clone();
+        let mut m = data.mutate();
+        let job = m.get_mut(&job);
+        match job.state {
+            JobState::Submitted | JobState::Scheduling | JobState::Scheduled => {
+                job.state = JobState::Finished;
+                job.health = JobHealth::Canceled;
+                job.end_time = Some(Utc::now());
+            }
+            JobState::Running => job.state = JobState::Canceling,
+            JobState::Canceling | JobState::Finished => {}
+        }
+
+        ResponseTemplate::new(200).set_body_json(json!({ "message": "Job cancel signal sent." }))
+    }
+}
+
+/// Create a new [`CancelEndpoint`] for the given [`SharedState`].
+///
+/// Example:
+/// ```rust
+/// use django_query::mock::nested_endpoint_matches;
+/// use lava_api_mock::{cancel_endpoint, SharedState};
+///
+/// let p = SharedState::new();
+///
+/// # tokio_test::block_on( async {
+/// let server = wiremock::MockServer::start().await;
+///
+/// wiremock::Mock::given(wiremock::matchers::method("GET"))
+///     .and(nested_endpoint_matches("/api/v0.2", "jobs", "cancel"))
+///     .respond_with(cancel_endpoint(p))
+///     .mount(&server)
+///     .await;
+/// # });
+/// ```
+pub fn cancel_endpoint(data: SharedState) -> CancelEndpoint {
+    CancelEndpoint { data }
+}
+
+/// A [`wiremock::Respond`] implementation for resubmitting jobs.
+///
+/// This serves requests of the form `/api/v0.2/jobs/<id>/resubmit/`,
+/// by submitting the job's definition again as for a
+/// [`SubmissionEndpoint`]; for a job from a multinode group, the
+/// whole group is resubmitted, with the user making the request as
+/// the submitter. Permissions and unknown jobs are handled as for a
+/// [`CancelEndpoint`].
+pub struct ResubmitEndpoint {
+    data: SharedState,
+}
+
+impl Respond for ResubmitEndpoint {
+    fn respond(&self, request: &Request) -> ResponseTemplate {
+        let (job, user) = match find_job(&self.data, request, "resubmit") {
+            Ok(found) => found,
+            Err(response) => return response,
+        };
+
+        let definition = {
+            let state = self.data.access();
+            let job = state.get(&job);
+            if job.multinode_definition.is_empty() {
+                job.definition.clone()
+            } else {
+                job.multinode_definition.clone()
+            }
+        };
+        match create_jobs(&self.data, user, &definition) {
+            Ok(ids) => submitted(ids),
+            Err(e) => invalid(e),
+        }
+    }
+}
+
+/// Create a new [`ResubmitEndpoint`] for the given [`SharedState`].
+///
+/// Example:
+/// ```rust
+/// use django_query::mock::nested_endpoint_matches;
+/// use lava_api_mock::{resubmit_endpoint, SharedState};
+///
+/// let p = SharedState::new();
+///
+/// # tokio_test::block_on( async {
+/// let server = wiremock::MockServer::start().await;
+///
+/// wiremock::Mock::given(wiremock::matchers::method("GET"))
+///     .and(nested_endpoint_matches("/api/v0.2", "jobs", "resubmit"))
+///     .respond_with(resubmit_endpoint(p))
+///     .mount(&server)
+///     .await;
+/// # });
+/// ```
+pub fn resubmit_endpoint(data: SharedState) -> ResubmitEndpoint {
+    ResubmitEndpoint { data }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use serde_json::Value as JsonValue;
+    use test_log::test;
+
+    fn add_user(p: &mut SharedState, username: &str, token: &str) -> Proxy<User<State>> {
+        Proxy::<User<State>>::builder()
+            .username(username)
+            .token(Some(token.to_string()))
+            .build(p.mutate())
+            .0
+    }
+
+    async fn submit(server: &wiremock::MockServer, definition: &str) -> (u16, JsonValue) {
+        let response = reqwest::Client::new()
+            .post(&format!("{}/api/v0.2/jobs/", server.uri()))
+            .header("Content-Type", "application/json")
+            .header("Authorization", "Token test")
+            .body(json!({ "definition": definition }).to_string())
+            .send()
+            .await
+            .expect("failed to submit job");
+        let status = response.status().as_u16();
+        let body = response.text().await.expect("failed to read reply");
+        (
+            status,
+            serde_json::from_str(&body).expect("failed to parse reply"),
+        )
+    }
+
+    #[test(tokio::test)]
+    async fn test_submit() {
+        let mut p = SharedState::new();
+        let user = add_user(&mut p, "fred", "test");
+        let group = Proxy::<Group<State>>::builder()
+            .name("lab")
+            .build(p.mutate())
+            .0;
+        let qemu = Proxy::<DeviceType<State>>::builder()
+            .name("qemu")
+            .build(p.mutate())
+            .0;
+
+        let server = wiremock::MockServer::start().await;
+
+        wiremock::Mock::given(wiremock::matchers::method("POST"))
+            .and(wiremock::matchers::path("/api/v0.2/jobs/"))
+            .respond_with(submission_endpoint(p.clone()))
+            .mount(&server)
+            .await;
+
+        let (status, reply) = submit(&server, "job_name: test\ndevice_type: qemu\n").await;
+        assert_eq!(status, 201);
+        assert_eq!(reply["job_ids"], json!([0]));
+
+        let (status, reply) = submit(
+            &server,
+            "job_name: test\nprotocols:\n  lava-multinode:\n    roles:\n      a:\n        device_type: qemu\n        count: 2\n      b:\n        count: 1\n",
+        )
+        .await;
+        assert_eq!(status, 201);
+        assert_eq!(reply["job_ids"], json!([1, 2, 3]));
+
+        let (status, reply) = submit(
+            &server,
+            "job_name: test\nvisibility:\n  group:\n    - lab\n",
+        )
+        .await;
+        assert_eq!(status, 201);
+        assert_eq!(reply["job_ids"], json!([4]));
+
+        let state = p.access();
+        let jobs = state.get_iter::<Job<State>>().collect::<Vec<_>>();
+        assert_eq!(jobs.len(), 5);
+        assert_eq!(jobs[0].description, "test");
+        assert_eq!(jobs[0].submitter, user);
+        assert!(jobs[0].is_public);
+        assert_eq!(jobs[0].requested_device_type, Some(qemu));
+        assert_eq!(jobs[0].multinode_definition, "");
+        assert_ne!(jobs[1].multinode_definition, "");
+        assert_eq!(jobs[2].requested_device_type, Some(qemu));
+        assert_eq!(jobs[3].requested_device_type, None);
+        assert!(!jobs[4].is_public);
+        assert_eq!(jobs[4].viewing_groups, vec![group]);
+
+        let (status, reply) = submit(&server, "- not a job").await;
+        assert_eq!(status, 400);
+        assert!(reply["message"].is_string());
+        let (status, _) = submit(&server, "job_name: test\nvisibility:\n  group: [none]\n").await;
+        assert_eq!(status, 400);
+        let (status, reply) = submit(&server, "job_name: test\ndevice_type: none\n").await;
+        assert_eq!(status, 400);
+        assert_eq!(reply["message"], "unknown device type none");
+        let (status, reply) = submit(&server, "job_name: test\nwomble: true\n").await;
+        assert_eq!(status, 400);
+        assert_eq!(reply["message"], "unknown job definition key womble");
+        assert_eq!(p.access().get_iter::<Job<State>>().count(), 5);
+
+        let response = reqwest::Client::new()
+            .post(&format!("{}/api/v0.2/jobs/", server.uri()))
+            .header("Content-Type", "application/json")
+            .body(json!({ "definition": "job_name: test\n" }).to_string())
+            .send()
+            .await
+            .expect("failed to submit job");
+        assert_eq!(response.status().as_u16(), 401);
+    }
+
+    #[test(tokio::test)]
+    async fn test_cancel() {
+        let mut p = SharedState::new();
+        add_user(&mut p, "fred", "test");
+        add_user(&mut p, "jane", "other");
+
+        let server = wiremock::MockServer::start().await;
+
+        wiremock::Mock::given(wiremock::matchers::method("POST"))
+            .and(wiremock::matchers::path("/api/v0.2/jobs/"))
+            .respond_with(submission_endpoint(p.clone()))
+            .mount(&server)
+            .await;
+
+        wiremock::Mock::given(wiremock::matchers::method("GET"))
+            .and(django_query::mock::nested_endpoint_matches(
+                "/api/v0.2",
+                "jobs",
+                "cancel",
+            ))
+            .respond_with(cancel_endpoint(p.clone()))
+            .mount(&server)
+            .await;
+
+        let (status, _) = submit(&server, "job_name: test\n").await;
+        assert_eq!(status, 201);
+
+        let cancel = |id: i64, token: Option<&str>| {
+            let mut request = reqwest::Client::new().get(&format!(
+                "{}/api/v0.2/jobs/{}/cancel/",
+                server.uri(),
+                id
+            ));
+            if let Some(token) = token {
+                request = request.header("Authorization", format!("Token {}", token));
+            }
+            async move {
+                request
+                    .send()
+                    .await
+                    .expect("failed to cancel job")
+                    .status()
+                    .as_u16()
+            }
+        };
+
+        assert_eq!(cancel(0, None).await, 403);
+        assert_eq!(cancel(0, Some("other")).await, 403);
+        assert_eq!(cancel(0, Some("unknown")).await, 401);
+        assert_eq!(cancel(1, Some("test")).await, 404);
+        assert_eq!(cancel(0, Some("test")).await, 200);
+
+        let state = p.access();
+        let job = state.get_iter::<Job<State>>().next().unwrap();
+        assert_eq!(job.state, JobState::Finished);
+        assert_eq!(job.health, JobHealth::Canceled);
+    }
+
+    #[test(tokio::test)]
+    async fn test_post_actions() {
+        let mut p = SharedState::new();
+        add_user(&mut p, "fred", "test");
+        let mock = crate::LavaMock::new(p.clone(), Default::default()).await;
+
+        let post = |path: &str| {
+            reqwest::Client::new()
+                .post(&format!("{}/api/v0.2/jobs/{}", mock.uri(), path))
+                .header("Content-Type", "application/json")
+                .header("Authorization", "Token test")
+        };
+
+        let response = post("")
+            .body(json!({ "definition": "job_name: test\n" }).to_string())
+            .send()
+            .await
+            .expect("failed to submit job");
+        assert_eq!(response.status().as_u16(), 201);
+
+        let response = post("0/resubmit/")
+            .send()
+            .await
+            .expect("failed to resubmit job");
+        assert_eq!(response.status().as_u16(), 201);
+        let reply: JsonValue = response.json().await.expect("failed to parse reply");
+        assert_eq!(reply["job_ids"], json!([1]));
+
+        let response = post("0/cancel/")
+            .send()
+            .await
+            .expect("failed to cancel job");
+        assert_eq!(response.status().as_u16(), 200);
+
+        let state = p.access();
+        let jobs = state.get_iter::<Job<State>>().collect::<Vec<_>>();
+        assert_eq!(jobs.len(), 2);
+        assert_eq!(jobs[0].state, JobState::Finished);
+        assert_eq!(jobs[0].health, JobHealth::Canceled);
+        assert_eq!(jobs[1].state, JobState::Submitted);
+        assert_eq!(jobs[1].description, "test");
+    }
+}
@@ -1,11 +1,18 @@
-use boulder::{BuildableWithPersianRug, GeneratableWithPersianRug};
+use boulder::{BuildableWithPersianRug, BuilderWithPersianRug, GeneratableWithPersianRug};
 use boulder::{Inc, Pattern};
 use django_query::{
     filtering::FilterableWithPersianRug, row::IntoRowWithPersianRug,
     sorting::SortableWithPersianRug,
 };
 
-use persian_rug::{contextual, Context};
+use persian_rug::{contextual, Accessor, Context, Mutator, Proxy};
+use regex::Regex;
+use serde::Deserialize;
+use serde_json::json;
+use wiremock::{Request, Respond, ResponseTemplate};
+
+use crate::permissions::check_superuser;
+use crate::{SharedState, State};
 
 /// A tag in the LAVA API
 #[derive(
@@ -24,6 +31,7 @@ pub struct Tag<C: Context + 'static> {
     #[django(exclude)]
     _marker: core::marker::PhantomData<C>,
     #[boulder(generator=Inc(0u32))]
+    #[django(op(in))]
     pub id: u32,
     #[boulder(default="test-tag", generator=Pattern!("test-tag-{}", Inc(0)))]
     #[django(sort, op(in, contains, icontains, startswith, endswith))]
@@ -31,15 +39,237 @@ pub struct Tag<C: Context + 'static> {
     #[boulder(default=Some("An example tag description".to_string()))]
     #[django(sort, op(in, contains, icontains, startswith, endswith))]
     pub description: Option<String>,
+    /// Whether the tag has been deleted.
+    ///
+    /// Objects cannot be removed from a [`State`], so deleted tags
+    /// are marked instead, and hidden from the tags endpoint of a
+    /// [`LavaMock`](crate::LavaMock). They remain attached to any
+    /// devices and jobs that refer to them.
+    #[boulder(default = false)]
+    #[django(exclude)]
+    pub deleted: bool,
+}
+
+/// A [`wiremock::Respond`] implementation hiding deleted tags.
+///
+/// This wraps another endpoint serving [`Tag`] instances, usually
+/// one created by [`SharedState::endpoint`], and hides from it the
+/// tags marked as [`deleted`](Tag::deleted).
+pub struct LiveTagsEndpoint<R> {
+    data: SharedState,
+    inner: R,
+}
+
+impl<R: Respond> Respond for LiveTagsEndpoint<R> {
+    fn respond(&self, request: &Request) -> ResponseTemplate {
+        let state = self.data.access();
+        if !state.get_iter::<Tag<State>>().any(|t| t.deleted) {
+            return self.inner.respond(request);
+        }
+
+        let ids = state
+            .get_iter::<Tag<State>>()
+            .filter(|t| !t.deleted)
+            .map(|t| t.id.to_string())
+            .collect::<Vec<_>>();
+        // Tag ids are unsigned, so there is no id to filter on which
+        // matches nothing when every tag has been deleted.
+        if ids.is_empty() {
+            return ResponseTemplate::new(200).set_body_json(json!({
+                "count": 0,
+                "next": null,
+                "previous": null,
+                "results": [],
+            }));
+        }
+        let mut request = request.clone();
+        request
+            .url
+            .query_pairs_mut()
+            .append_pair("id__in", &ids.join(","));
+        self.inner.respond(&request)
+    }
+}
+
+/// Create a new [`LiveTagsEndpoint`] wrapping `inner`, with deleted
+/// tags determined from the given [`SharedState`].
+///
+/// Example:
+/// ```rust
+/// use lava_api_mock::{live_tags_endpoint, SharedState, State, Tag};
+///
+/// let p = SharedState::new();
+///
+/// # tokio_test::block_on( async {
+/// let server = wiremock::MockServer::start().await;
+///
+/// wiremock::Mock::given(wiremock::matchers::method("GET"))
+///     .and(wiremock::matchers::path("/api/v0.2/tags/"))
+///     .respond_with(live_tags_endpoint(
+///         p.clone(),
+///         p.endpoint::<Tag<State>>(Some(&server.uri()), None),
+///     ))
+///     .mount(&server)
+///     .await;
+/// # });
+/// ```
+pub fn live_tags_endpoint<R: Respond>(data: SharedState, inner: R) -> LiveTagsEndpoint<R> {
+    LiveTagsEndpoint { data, inner }
+}
+
+#[derive(Deserialize)]
+struct NewTag {
+    name: String,
+    #[serde(default)]
+    description: Option<String>,
+}
+
+/// A [`wiremock::Respond`] implementation creating tags.
+///
+/// This serves `POST` requests to `/api/v0.2/tags/`, with a JSON
+/// body giving the `name` and optionally the `description` of the
+/// new [`Tag`], which is added to the [`SharedState`] with the next
+/// unused id. Only superusers may create tags; other users receive a
+/// 403 response, and anonymous requests a 401 response. Requests
+/// with a missing or existing name receive a 400 response.
+pub struct CreateTagEndpoint {
+    data: SharedState,
+}
+
+impl Respond for CreateTagEndpoint {
+    fn respond(&self, request: &Request) -> ResponseTemplate {
+        let mut data = self.data.clone();
+        if let Err(response) = check_superuser(&data.access(), request) {
+            return response;
+        }
+
+        let tag: NewTag = match serde_json::from_slice(&request.body) {
+            Ok(tag) => tag,
+            Err(_) => {
+                return ResponseTemplate::new(400)
+                    .set_body_json(json!({ "name": ["This field is required."] }))
+            }
+        };
+
+        // The name is checked, and the id picked, under the same lock
+        // as the tag is added, so that concurrent requests cannot
+        // both be given the same id or name.
+        let mut m = data.mutate();
+        if m.get_iter_mut::<Tag<State>>()
+            .any(|t| !t.deleted && t.name == tag.name)
+        {
+            return ResponseTemplate::new(400)
+                .set_body_json(json!({ "name": ["tag with this name already exists."] }));
+        }
+        let id = m
+            .get_iter_mut::<Tag<State>>()
+            .map(|t| t.id + 1)
+            .max()
+            .unwrap_or(0);
+        let _ = Proxy::<Tag<State>>::builder()
+            .id(id)
+            .name(tag.name.clone())
+            .description(tag.description.clone())
+            .build(m);
+
+        ResponseTemplate::new(201).set_body_json(json!({
+            "id": id,
+            "name": tag.name,
+            "description": tag.description,
+        }))
+    }
+}
+
+/// Create a new [`CreateTagEndpoint`] for the given [`SharedState`].
+///
+/// Example:
+/// ```rust
+/// use lava_api_mock::{create_tag_endpoint, SharedState};
+///
+/// let p = SharedState::new();
+///
+/// # tokio_test::block_on( async {
+/// let server = wiremock::MockServer::start().await;
+///
+/// wiremock::Mock::given(wiremock::matchers::method("POST"))
+///     .and(wiremock::matchers::path("/api/v0.2/tags/"))
+///     .respond_with(create_tag_endpoint(p))
+///     .mount(&server)
+///     .await;
+/// # });
+/// ```
+pub fn create_tag_endpoint(data: SharedState) -> CreateTagEndpoint {
+    CreateTagEndpoint { data }
+}
+
+/// A [`wiremock::Respond`] implementation deleting tags.
+///
+/// This serves `DELETE` requests to `/api/v0.2/tags/<id>/`, marking
+/// the [`Tag`] with that id as [`deleted`](Tag::deleted). As for
+/// [`CreateTagEndpoint`], only superusers may delete tags. Requests
+/// for unknown or already deleted tags receive a 404 response.
+pub struct DeleteTagEndpoint {
+    data: SharedState,
+}
+
+impl Respond for DeleteTagEndpoint {
+    fn respond(&self, request: &Request) -> ResponseTemplate {
+        let mut data = self.data.clone();
+        if let Err(response) = check_superuser(&data.access(), request) {
+            return response;
+        }
+
+        let rr = Regex::new(r"/api/v0.2/tags/(?P<id>[0-9]+)/").unwrap();
+        let id = match rr
+            .captures(request.url.as_str())
+            .and_then(|captures| captures.get(1).unwrap().as_str().parse::<u32>().ok())
+        {
+            Some(id) => id,
+            None => return ResponseTemplate::new(404),
+        };
+
+        let mut m = data.mutate();
+        match m
+            .get_iter_mut::<Tag<State>>()
+            .find(|t| t.id == id && !t.deleted)
+        {
+            Some(tag) => {
+                tag.deleted = true;
+                ResponseTemplate::new(204)
+            }
+            None => ResponseTemplate::new(404).set_body_json(json!({ "detail": "Not found." })),
+        }
+    }
+}
+
+/// Create a new [`DeleteTagEndpoint`] for the given [`SharedState`].
+///
+/// Example:
+/// ```rust
+/// use lava_api_mock::{delete_tag_endpoint, SharedState};
+///
+/// let p = SharedState::new();
+///
+/// # tokio_test::block_on( async {
+/// let server = wiremock::MockServer::start().await;
+///
+/// wiremock::Mock::given(wiremock::matchers::method("DELETE"))
+///     .and(wiremock::matchers::path_regex(r"^/api/v0.2/tags/[0-9]+/$"))
+///     .respond_with(delete_tag_endpoint(p))
+///     .mount(&server)
+///     .await;
+/// # });
+/// ```
+pub fn delete_tag_endpoint(data: SharedState) -> DeleteTagEndpoint {
+    DeleteTagEndpoint { data }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::{SharedState, State};
+    use crate::User;
 
     use boulder::GeneratorWithPersianRugIterator;
-    use persian_rug::Proxy;
     use test_log::test;
 
     #[test(tokio::test)]
@@ -100,4 +330,106 @@ mod tests {
             }
         );
     }
+
+    #[test(tokio::test)]
+    async fn test_create_delete() {
+        let mut p = SharedState::new();
+        {
+            let m = p.mutate();
+            let (_, m) = Proxy::<User<State>>::builder()
+                .username("admin")
+                .is_superuser(true)
+                .token(Some("admin-token".to_string()))
+                .build(m);
+            let (_, m) = Proxy::<User<State>>::builder()
+                .username("fred")
+                .token(Some("fred-token".to_string()))
+                .build(m);
+            let _ = Proxy::<Tag<State>>::builder()
+                .id(1u32)
+                .name("existing")
+                .build(m);
+        }
+
+        let server = wiremock::MockServer::start().await;
+
+        wiremock::Mock::given(wiremock::matchers::method("GET"))
+            .and(wiremock::matchers::path("/api/v0.2/tags/"))
+            .respond_with(live_tags_endpoint(
+                p.clone(),
+                p.endpoint::<Tag<State>>(Some(&server.uri()), None),
+            ))
+            .mount(&server)
+            .await;
+        wiremock::Mock::given(wiremock::matchers::method("POST"))
+            .and(wiremock::matchers::path("/api/v0.2/tags/"))
+            .respond_with(create_tag_endpoint(p.clone()))
+            .mount(&server)
+            .await;
+        wiremock::Mock::given(wiremock::matchers::method("DELETE"))
+            .and(wiremock::matchers::path_regex(r"^/api/v0.2/tags/[0-9]+/$"))
+            .respond_with(delete_tag_endpoint(p.clone()))
+            .mount(&server)
+            .await;
+
+        let client = reqwest::Client::new();
+        let tags_url = format!("{}/api/v0.2/tags/", server.uri());
+        let create = |token: Option<&str>, body: serde_json::Value| {
+            let mut request = client.post(&tags_url).json(&body);
+            if let Some(token) = token {
+                request = request.header("Authorization", format!("Token {}", token));
+            }
+            request.send()
+        };
+
+        let body = json!({ "name": "new", "description": "A new tag" });
+        let status = |r: reqwest::Result<reqwest::Response>| r.expect("request failed").status();
+        assert_eq!(status(create(None, body.clone()).await), 401);
+        assert_eq!(status(create(Some("fred-token"), body.clone()).await), 403);
+        assert_eq!(
+            status(create(Some("admin-token"), json!({ "name": "existing" })).await),
+            400
+        );
+        let created: serde_json::Value = create(Some("admin-token"), body)
+            .await
+            .expect("request failed")
+            .json()
+            .await
+            .expect("failed to parse tag");
+        assert_eq!(
+            created,
+            json!({ "id": 2, "name": "new", "description": "A new tag" })
+        );
+
+        let delete = |id: u32, token: &str| {
+            client
+                .delete(&format!("{}{}/", tags_url, id))
+                .header("Authorization", format!("Token {}", token))
+                .send()
+        };
+        assert_eq!(status(delete(1, "fred-token").await), 403);
+        assert_eq!(status(delete(1, "admin-token").await), 204);
+        assert_eq!(status(delete(1, "admin-token").await), 404);
+        assert_eq!(status(delete(7, "admin-token").await), 404);
+
+        let body: serde_json::Value = reqwest::get(&tags_url)
+            .await
+            .expect("error getting tags")
+            .json()
+            .await
+            .expect("error parsing tags/");
+        assert_eq!(body["count"], json!(1));
+        assert_eq!(body["results"][0]["name"], json!("new"));
+
+        // With every tag deleted, none are returned
+        assert_eq!(status(delete(2, "admin-token").await), 204);
+        let body: serde_json::Value = reqwest::get(&tags_url)
+            .await
+            .expect("error getting tags")
+            .json()
+            .await
+            .expect("error parsing tags/");
+        assert_eq!(body["count"], json!(0));
+        assert_eq!(body["results"], json!([]));
+    }
 }
@@ -252,7 +252,7 @@ pub struct TestCase<C: Context + 'static> {
     #[django(sort, op(in, contains, icontains, startswith, endswith))]
     pub unit: String,
     #[boulder(default=PassFail::Pass)]
-    #[django(sort)]
+    #[django(sort, op(in))]
     pub result: PassFail,
     // FIXME: better default
     #[django(sort, op(lt, lte, gt, gte))]
@@ -7,7 +7,11 @@ use django_query::{
 use persian_rug::{contextual, Context, Proxy};
 
 /// A user in the LAVA API
-// FIXME: Deriving sort and IntoRowWithPersianRug needlessly
+///
+/// Users are identified by their [`token`](User::token), which the
+/// [`LavaMock`](crate::LavaMock) uses to decide which jobs a request
+/// may see and act upon. A user is a member of its
+/// [`group`](User::group), if it has one.
 #[derive(
     Clone,
     Debug,
@@ -35,10 +39,17 @@ pub struct User<C: Context + 'static> {
               generator=GSome(Pattern!("test-user-{}@example.com", Inc(1))))]
     #[django(op(in, contains, icontains, startswith, endswith))]
     pub email: Option<String>,
+    #[boulder(default = false)]
+    pub is_superuser: bool,
+    /// The API token which authenticates this user, if any.
+    ///
+    /// This is not visible through the API.
+    #[boulder(generator=GSome(Pattern!("test-token-{}", Inc(1))))]
+    #[django(exclude)]
+    pub token: Option<String>,
 }
 
 /// A group in the LAVA API
-// FIXME: Deriving sort and IntoRowWithPersianRug needlessly
 #[derive(
     Clone,
     Debug,
@@ -6,7 +6,13 @@ use boulder::{Inc, Pattern};
 use django_query::filtering::{ops::Scalar, FilterableWithPersianRug};
 use django_query::{row::IntoRowWithPersianRug, sorting::SortableWithPersianRug};
 
-use persian_rug::{contextual, Context};
+use persian_rug::{contextual, Context, Mutator};
+use serde::Deserialize;
+use serde_json::json;
+use wiremock::{Request, Respond, ResponseTemplate};
+
+use crate::permissions::check_superuser;
+use crate::SharedState;
 
 /// A worker in the LAVA API
 #[derive(
@@ -71,6 +77,113 @@ pub enum State {
 impl Scalar for State {}
 impl django_query::row::StringCellValue for State {}
 
+#[derive(Deserialize)]
+struct WorkerUpdate {
+    #[serde(default)]
+    health: Option<String>,
+    #[serde(default)]
+    job_limit: Option<i64>,
+}
+
+/// A [`wiremock::Respond`] implementation changing workers.
+///
+/// This serves `PATCH` requests to `/api/v0.2/workers/<hostname>/`,
+/// with a JSON body giving a new `health` for the [`Worker`] by
+/// name, a new `job_limit`, or both, for example
+/// `{"health": "Maintenance"}`, and updates the [`SharedState`] to
+/// match. Only superusers may change workers; other users receive a
+/// 403 response, and anonymous requests a 401 response. Requests for
+/// unknown workers receive a 404 response, and those with an unknown
+/// health or a negative job limit a 400 response.
+pub struct WorkerUpdateEndpoint {
+    data: SharedState,
+}
+
+impl Respond for WorkerUpdateEndpoint {
+    fn respond(&self, request: &Request) -> ResponseTemplate {
+        let mut data = self.data.clone();
+        if let Err(response) = check_superuser(&data.access(), request) {
+            return response;
+        }
+
+        let hostname = match request
+            .url
+            .path()
+            .strip_prefix("/api/v0.2/workers/")
+            .and_then(|rest| rest.strip_suffix('/'))
+        {
+            Some(hostname) if !hostname.is_empty() => hostname.to_string(),
+            _ => return ResponseTemplate::new(404),
+        };
+
+        let update: WorkerUpdate = match serde_json::from_slice(&request.body) {
+            Ok(update) => update,
+            Err(_) => {
+                return ResponseTemplate::new(400)
+                    .set_body_json(json!({ "detail": "JSON parse error." }))
+            }
+        };
+        let health = match update.health.as_deref().map(str::parse::<Health>) {
+            Some(Ok(health)) => Some(health),
+            Some(Err(_)) => {
+                return ResponseTemplate::new(400).set_body_json(json!({
+                    "health": [format!("\"{}\" is not a valid choice.", update.health.unwrap())]
+                }))
+            }
+            None => None,
+        };
+        if matches!(update.job_limit, Some(limit) if limit < 0) {
+            return ResponseTemplate::new(400).set_body_json(json!({
+                "job_limit": ["Ensure this value is greater than or equal to 0."]
+            }));
+        }
+
+        let mut m = data.mutate();
+        match m
+            .get_iter_mut::<Worker<crate::State>>()
+            .find(|w| w.hostname == hostname)
+        {
+            Some(worker) => {
+                if let Some(health) = health {
+                    worker.health = health;
+                }
+                if let Some(job_limit) = update.job_limit {
+                    worker.job_limit = job_limit;
+                }
+                ResponseTemplate::new(200).set_body_json(json!({
+                    "hostname": worker.hostname,
+                    "health": worker.health.to_string(),
+                    "job_limit": worker.job_limit,
+                }))
+            }
+            None => ResponseTemplate::new(404).set_body_json(json!({ "detail": "Not found." })),
+        }
+    }
+}
+
+/// Create a new [`WorkerUpdateEndpoint`] for the given
+/// [`SharedState`].
+///
+/// Example:
+/// ```rust
+/// use lava_api_mock::{worker_update_endpoint, SharedState};
+///
+/// let p = SharedState::new();
+///
+/// # tokio_test::block_on( async {
+/// let server = wiremock::MockServer::start().await;
+///
+/// wiremock::Mock::given(wiremock::matchers::method("PATCH"))
+///     .and(wiremock::matchers::path_regex(r"^/api/v0.2/workers/[^/]+/$"))
+///     .respond_with(worker_update_endpoint(p))
+///     .mount(&server)
+///     .await;
+/// # });
+/// ```
+pub fn worker_update_endpoint(data: SharedState) -> WorkerUpdateEndpoint {
+    WorkerUpdateEndpoint { data }
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
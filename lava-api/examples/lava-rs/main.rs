@@ -1,10 +1,9 @@
+use std::collections::BTreeMap;
 use std::fs::File;
 use std::io::Read;
 use std::path::PathBuf;
-use std::time::Duration;
 
 use anyhow::anyhow;
-use anyhow::bail;
 use anyhow::Context;
 use anyhow::Result;
 use futures::stream::TryStreamExt;
@@ -15,7 +14,7 @@ use lava_api::joblog::JobLogError;
 use lava_api::worker::{self, Worker};
 use lava_api::Lava;
 use structopt::StructOpt;
-use tokio::time::sleep;
+use strum::IntoEnumIterator;
 
 fn device_health_to_emoji(health: device::Health) -> &'static str {
     use device::Health::*;
@@ -61,9 +60,70 @@ async fn devices(lava: &Lava) -> Result<()> {
     Ok(())
 }
 
+async fn report_devices(lava: &Lava, opts: DeviceReportCmd) -> Result<()> {
+    let mut builder = lava.devices_builder();
+    if let Some(device_type) = opts.device_type {
+        builder = builder.device_type(device_type);
+    }
+    if let Some(worker) = opts.worker {
+        builder = builder.worker_host(worker);
+    }
+    if let Some(tag) = opts.tag {
+        builder = builder.tag(tag);
+    }
+    let devices: Vec<device::Device> = builder.query().try_collect().await?;
+
+    let mut by_worker: BTreeMap<&str, Vec<&device::Device>> = BTreeMap::new();
+    for d in devices.iter() {
+        by_worker.entry(&d.worker_host).or_default().push(d);
+    }
+
+    println!("Devices by worker:");
+    for (worker, devices) in by_worker {
+        println!(" {} ({} devices)", worker, devices.len());
+        for health in device::Health::iter() {
+            let mut hostnames = devices
+                .iter()
+                .filter(|d| d.health == health)
+                .map(|d| d.hostname.as_str())
+                .collect::<Vec<&str>>();
+            if hostnames.is_empty() {
+                continue;
+            }
+            hostnames.sort_unstable();
+            println!(
+                "  {}  {}: {}",
+                device_health_to_emoji(health),
+                health,
+                hostnames.join(", ")
+            );
+        }
+    }
+
+    let mut tags = devices
+        .iter()
+        .flat_map(|d| d.tags.iter().map(|t| t.name.as_str()))
+        .collect::<Vec<&str>>();
+    tags.sort_unstable();
+    tags.dedup();
+    let combinations = tags.iter().map(std::slice::from_ref).collect::<Vec<_>>();
+    let counts = lava.device_counts_by_tags(&combinations).await?;
+
+    println!("\nTags (across all devices):");
+    for count in counts {
+        println!(
+            " {}: {} devices, {} schedulable",
+            count.tags.join(" + "),
+            count.devices,
+            count.schedulable
+        );
+    }
+    Ok(())
+}
+
 async fn log(lava: &Lava, opts: LogCmd) -> Result<()> {
     println!("Job log:");
-    let mut log = lava.log(opts.job).log();
+    let mut log = lava.log(opts.job).follow(opts.follow).log();
 
     while let Some(entry) = log.try_next().await? {
         println!("{:?}", entry);
@@ -99,45 +159,20 @@ async fn submit(lava: &Lava, opts: SubmitCmd) -> Result<()> {
     job.read_to_string(&mut definition)
         .context("Failed to read job")?;
 
-    let mut ids = lava.submit_job(&definition).await?;
-    println!("Submitted job(s): {:?}", ids);
-    let id = ids.pop().ok_or_else(|| anyhow!("No job id"))?;
+    let submitted = lava.submit_job(&definition).await?;
+    println!("Submitted job(s): {:?}", submitted.ids());
+    let id = *submitted.ids().last().ok_or_else(|| anyhow!("No job id"))?;
     if opts.follow {
         // TODO support following more then 1 job
-        let builder = lava.jobs().id(id);
-        let mut offset = 0;
-        loop {
-            let mut jobs = builder.clone().query();
-            match jobs.try_next().await {
-                Ok(Some(job)) => {
-                    //if job.state == job::State::Running {
-                    let mut log = lava.log(job.id).start(offset).log();
-                    while let Some(entry) = log.next().await {
-                        match entry {
-                            Ok(entry) => {
-                                println!("{:?}: {:?}", entry.dt, entry.msg);
-                                offset += 1;
-                            }
-                            Err(JobLogError::NoData) => (),
-                            Err(JobLogError::ParseError(s, e)) => {
-                                println!("Couldn't parse {} - {}", s.trim_end(), e);
-                                offset += 1;
-                            }
-                            Err(e) => return Err(e.into()),
-                        }
-                    }
-                    //}
-                    if job.state == job::State::Finished {
-                        break;
-                    }
-                }
-                Ok(None) => bail!("Job not found"),
-                Err(e) => {
-                    println!("Failed to check status: {:?}", e);
+        let mut log = lava.log(id).follow(true).log();
+        while let Some(entry) = log.next().await {
+            match entry {
+                Ok(entry) => println!("{:?}: {:?}", entry.dt, entry.msg),
+                Err(JobLogError::ParseError(s, e)) => {
+                    println!("Couldn't parse {} - {}", s.trim_end(), e)
                 }
+                Err(e) => return Err(e.into()),
             }
-
-            sleep(Duration::from_secs(10)).await;
         }
     }
     Ok(())
@@ -152,6 +187,25 @@ async fn workers(lava: &Lava) -> Result<()> {
     Ok(())
 }
 
+#[derive(StructOpt, Debug)]
+struct DeviceReportCmd {
+    /// Only report devices of this type
+    #[structopt(long)]
+    device_type: Option<String>,
+    /// Only report devices on this worker
+    #[structopt(long)]
+    worker: Option<String>,
+    /// Only report devices with this tag
+    #[structopt(long)]
+    tag: Option<String>,
+}
+
+#[derive(StructOpt, Debug)]
+enum ReportCmd {
+    /// Summarise devices by worker, health and tag
+    Devices(DeviceReportCmd),
+}
+
 #[derive(StructOpt, Debug)]
 struct SubmitCmd {
     #[structopt(short, long)]
@@ -162,7 +216,7 @@ struct SubmitCmd {
 #[derive(StructOpt, Debug)]
 struct LogCmd {
     #[structopt(short, long)]
-    _follow: bool,
+    follow: bool,
     job: i64,
 }
 
@@ -184,6 +238,8 @@ enum Command {
     Jobs(JobsCmd),
     /// List workers
     Workers,
+    /// Summarise the state of the lab
+    Report(ReportCmd),
 }
 
 #[derive(StructOpt, Debug)]
@@ -212,6 +268,7 @@ async fn main() -> Result<()> {
         Command::Log(opts) => log(&l, opts).await?,
         Command::Jobs(j) => jobs(&l, j).await?,
         Command::Workers => workers(&l).await?,
+        Command::Report(ReportCmd::Devices(r)) => report_devices(&l, r).await?,
     }
 
     Ok(())
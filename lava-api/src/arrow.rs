@@ -0,0 +1,480 @@
+//! Export jobs and test cases as Arrow record batches and Parquet
+//! files
+//!
+//! This module is only available with the `arrow` feature enabled.
+//!
+//! Each exported type implements [`ToRecordBatch`], which flattens a
+//! slice of objects into a single [`RecordBatch`] with a fixed
+//! [`Schema`](arrow_schema::Schema). Tags are exported as lists of tag
+//! names, times as UTC microsecond timestamps, and enumerations as
+//! their string forms. The large job definition fields are not
+//! exported.
+
+use arrow_array::builder::{ListBuilder, StringBuilder};
+use arrow_array::{
+    ArrayRef, BooleanArray, Float64Array, Int64Array, RecordBatch, StringArray,
+    TimestampMicrosecondArray, UInt32Array,
+};
+use arrow_schema::{ArrowError, DataType, Field, Schema, SchemaRef, TimeUnit};
+use chrono::{DateTime, Utc};
+use futures::stream::{Stream, StreamExt, TryStream, TryStreamExt};
+use parquet::arrow::ArrowWriter;
+use parquet::errors::ParquetError;
+use std::io::Write;
+use std::sync::Arc;
+use thiserror::Error;
+
+use crate::error::{Classify, ErrorClass};
+use crate::job::{Job, Visibility};
+use crate::tag::Tag;
+use crate::test::TestCase;
+
+/// Errors that can occur while exporting a stream of objects
+#[derive(Error, Debug)]
+pub enum ExportError<E>
+where
+    E: std::error::Error + 'static,
+{
+    #[error("Failed to read the data to export")]
+    Source(#[source] E),
+    #[error("Failed to build record batch")]
+    Arrow(#[from] ArrowError),
+    #[error("Failed to write Parquet data")]
+    Parquet(#[from] ParquetError),
+}
+
+impl<E> Classify for ExportError<E>
+where
+    E: std::error::Error + Classify + 'static,
+{
+    fn class(&self) -> ErrorClass {
+        match self {
+            ExportError::Source(e) => e.class(),
+            ExportError::Arrow(_) | ExportError::Parquet(_) => ErrorClass::Other,
+        }
+    }
+}
+
+/// Types which can be flattened into an Arrow [`RecordBatch`]
+pub trait ToRecordBatch: Sized {
+    /// The schema of the batches produced by
+    /// [`to_record_batch`](ToRecordBatch::to_record_batch).
+    fn schema() -> SchemaRef;
+
+    /// Convert `rows` into a single batch, with one row per object.
+    fn to_record_batch(rows: &[Self]) -> Result<RecordBatch, ArrowError>;
+}
+
+fn timestamp_type() -> DataType {
+    DataType::Timestamp(TimeUnit::Microsecond, Some("UTC".into()))
+}
+
+fn timestamps<I>(times: I) -> ArrayRef
+where
+    I: IntoIterator<Item = Option<DateTime<Utc>>>,
+{
+    Arc::new(
+        times
+            .into_iter()
+            .map(|t| t.map(|t| t.timestamp_micros()))
+            .collect::<TimestampMicrosecondArray>()
+            .with_timezone("UTC"),
+    )
+}
+
+fn tag_names<'a, I>(tags: I) -> ArrayRef
+where
+    I: IntoIterator<Item = &'a [Tag]>,
+{
+    let mut builder = ListBuilder::new(StringBuilder::new());
+    for tags in tags {
+        for tag in tags {
+            builder.values().append_value(&tag.name);
+        }
+        builder.append(true);
+    }
+    Arc::new(builder.finish())
+}
+
+fn tag_list_field(name: &str) -> Field {
+    Field::new_list(name, Field::new("item", DataType::Utf8, true), false)
+}
+
+fn visibility_name(visibility: &Visibility) -> &'static str {
+    match visibility {
+        Visibility::Public => "public",
+        Visibility::Personal => "personal",
+        Visibility::Group(_) => "group",
+        Visibility::Unknown => "unknown",
+    }
+}
+
+impl ToRecordBatch for Job {
+    fn schema() -> SchemaRef {
+        Arc::new(Schema::new(vec![
+            Field::new("id", DataType::Int64, false),
+            Field::new("submitter", DataType::Utf8, false),
+            Field::new("visibility", DataType::Utf8, false),
+            Field::new("description", DataType::Utf8, false),
+            Field::new("health_check", DataType::Boolean, false),
+            Field::new("requested_device_type", DataType::Utf8, true),
+            tag_list_field("tags"),
+            Field::new("actual_device", DataType::Utf8, true),
+            Field::new("submit_time", timestamp_type(), false),
+            Field::new("start_time", timestamp_type(), true),
+            Field::new("end_time", timestamp_type(), true),
+            Field::new("state", DataType::Utf8, false),
+            Field::new("health", DataType::Utf8, false),
+            Field::new("priority", DataType::Int64, false),
+            tag_list_field("failure_tags"),
+            Field::new("failure_comment", DataType::Utf8, true),
+        ]))
+    }
+
+    fn to_record_batch(rows: &[Self]) -> Result<RecordBatch, ArrowError> {
+        let columns: Vec<ArrayRef> = vec![
+            Arc::new(rows.iter().map(|j| j.id).collect::<Int64Array>()),
+            Arc::new(
+                rows.iter()
+                    .map(|j| Some(j.submitter.as_str()))
+                    .collect::<StringArray>(),
+            ),
+            Arc::new(
+                rows.iter()
+                    .map(|j| Some(visibility_name(&j.visibility)))
+                    .collect::<StringArray>(),
+            ),
+            Arc::new(
+                rows.iter()
+                    .map(|j| Some(j.description.as_str()))
+                    .collect::<StringArray>(),
+            ),
+            Arc::new(
+                rows.iter()
+                    .map(|j| Some(j.health_check))
+                    .collect::<BooleanArray>(),
+            ),
+            Arc::new(
+                rows.iter()
+                    .map(|j| j.requested_device_type.as_deref())
+                    .collect::<StringArray>(),
+            ),
+            tag_names(rows.iter().map(|j| j.tags.as_slice())),
+            Arc::new(
+                rows.iter()
+                    .map(|j| j.actual_device.as_deref())
+                    .collect::<StringArray>(),
+            ),
+            timestamps(rows.iter().map(|j| Some(j.submit_time))),
+            timestamps(rows.iter().map(|j| j.start_time)),
+            timestamps(rows.iter().map(|j| j.end_time)),
+            Arc::new(
+                rows.iter()
+                    .map(|j| Some(j.state.to_string()))
+                    .collect::<StringArray>(),
+            ),
+            Arc::new(
+                rows.iter()
+                    .map(|j| Some(j.health.to_string()))
+                    .collect::<StringArray>(),
+            ),
+            Arc::new(rows.iter().map(|j| j.priority).collect::<Int64Array>()),
+            tag_names(rows.iter().map(|j| j.failure_tags.as_slice())),
+            Arc::new(
+                rows.iter()
+                    .map(|j| j.failure_comment.as_deref())
+                    .collect::<StringArray>(),
+            ),
+        ];
+        RecordBatch::try_new(Self::schema(), columns)
+    }
+}
+
+impl ToRecordBatch for TestCase {
+    fn schema() -> SchemaRef {
+        Arc::new(Schema::new(vec![
+            Field::new("id", DataType::Int64, false),
+            Field::new("name", DataType::Utf8, false),
+            Field::new("unit", DataType::Utf8, false),
+            Field::new("result", DataType::Utf8, false),
+            Field::new("measurement", DataType::Utf8, true),
+            Field::new("suite", DataType::Int64, false),
+            Field::new("test_set", DataType::Int64, true),
+            Field::new("start_log_line", DataType::UInt32, true),
+            Field::new("end_log_line", DataType::UInt32, true),
+            Field::new("logged", timestamp_type(), false),
+            Field::new("definition", DataType::Utf8, true),
+            Field::new("duration", DataType::Float64, true),
+            Field::new("error_type", DataType::Utf8, true),
+            Field::new("error_msg", DataType::Utf8, true),
+            Field::new("resource_uri", DataType::Utf8, false),
+        ]))
+    }
+
+    fn to_record_batch(rows: &[Self]) -> Result<RecordBatch, ArrowError> {
+        let columns: Vec<ArrayRef> = vec![
+            Arc::new(rows.iter().map(|t| t.id).collect::<Int64Array>()),
+            Arc::new(
+                rows.iter()
+                    .map(|t| Some(t.name.as_str()))
+                    .collect::<StringArray>(),
+            ),
+            Arc::new(
+                rows.iter()
+                    .map(|t| Some(t.unit.as_str()))
+                    .collect::<StringArray>(),
+            ),
+            Arc::new(
+                rows.iter()
+                    .map(|t| Some(t.result.to_string()))
+                    .collect::<StringArray>(),
+            ),
+            Arc::new(
+                rows.iter()
+                    .map(|t| t.measurement.as_deref())
+                    .collect::<StringArray>(),
+            ),
+            Arc::new(rows.iter().map(|t| t.suite).collect::<Int64Array>()),
+            Arc::new(rows.iter().map(|t| t.test_set).collect::<Int64Array>()),
+            Arc::new(
+                rows.iter()
+                    .map(|t| t.start_log_line)
+                    .collect::<UInt32Array>(),
+            ),
+            Arc::new(rows.iter().map(|t| t.end_log_line).collect::<UInt32Array>()),
+            timestamps(rows.iter().map(|t| Some(t.logged))),
+            Arc::new(
+                rows.iter()
+                    .map(|t| t.metadata.as_ref().map(|m| m.definition.as_str()))
+                    .collect::<StringArray>(),
+            ),
+            Arc::new(
+                rows.iter()
+                    .map(|t| {
+                        t.metadata
+                            .as_ref()
+                            .and_then(|m| m.duration.as_ref())
+                            .and_then(|d| d.parse::<f64>().ok())
+                    })
+                    .collect::<Float64Array>(),
+            ),
+            Arc::new(
+                rows.iter()
+                    .map(|t| {
+                        t.metadata
+                            .as_ref()
+                            .and_then(|m| m.error_type)
+                            .map(|e| e.to_string())
+                    })
+                    .collect::<StringArray>(),
+            ),
+            Arc::new(
+                rows.iter()
+                    .map(|t| t.metadata.as_ref().and_then(|m| m.error_msg.as_deref()))
+                    .collect::<StringArray>(),
+            ),
+            Arc::new(
+                rows.iter()
+                    .map(|t| Some(t.resource_uri.as_str()))
+                    .collect::<StringArray>(),
+            ),
+        ];
+        RecordBatch::try_new(Self::schema(), columns)
+    }
+}
+
+/// Convert a stream of objects into a stream of [`RecordBatch`]
+/// instances of at most `batch_size` rows each.
+///
+/// This can be used on the streams returned by
+/// [`JobsBuilder::query`](crate::job::JobsBuilder::query) and
+/// [`Lava::test_cases`](crate::Lava::test_cases).
+pub fn record_batches<S, T>(
+    stream: S,
+    batch_size: usize,
+) -> impl Stream<Item = Result<RecordBatch, ExportError<S::Error>>>
+where
+    S: TryStream<Ok = T>,
+    S::Error: std::error::Error + 'static,
+    T: ToRecordBatch,
+{
+    stream
+        .into_stream()
+        .try_chunks(batch_size)
+        .map(|chunk| match chunk {
+            Ok(rows) => Ok(T::to_record_batch(&rows)?),
+            Err(e) => Err(ExportError::Source(e.1)),
+        })
+}
+
+/// Write a stream of objects to `writer` in Parquet format, returning
+/// the number of rows written.
+///
+/// Rows are converted and written in batches of `batch_size`, so the
+/// whole stream is never held in memory at once.
+///
+/// Example:
+/// ```rust
+/// # use lava_api_mock::{LavaMock, PaginationLimits, PopulationParams, SharedState};
+/// use lava_api::{arrow::write_parquet, job::Job, Lava};
+/// #
+/// # tokio_test::block_on( async {
+/// # let limits = PaginationLimits::new();
+/// # let population = PopulationParams::new();
+/// # let mock = LavaMock::new(SharedState::new_populated(population), limits).await;
+/// # let service_uri = mock.uri();
+/// # let lava_token = None;
+///
+/// let lava = Lava::new(&service_uri, lava_token).expect("failed to make lava");
+///
+/// let mut data = Vec::new();
+/// let rows = write_parquet::<_, Job, _>(lava.jobs().query(), &mut data, 1000)
+///     .await
+///     .expect("failed to export jobs");
+/// println!("Exported {} jobs in {} bytes", rows, data.len());
+/// # });
+/// ```
+pub async fn write_parquet<S, T, W>(
+    stream: S,
+    writer: W,
+    batch_size: usize,
+) -> Result<usize, ExportError<S::Error>>
+where
+    S: TryStream<Ok = T>,
+    S::Error: std::error::Error + 'static,
+    T: ToRecordBatch,
+    W: Write + Send,
+{
+    let mut writer = ArrowWriter::try_new(writer, T::schema(), None)?;
+    let mut rows = 0;
+    let mut batches = Box::pin(record_batches(stream, batch_size));
+    while let Some(batch) = batches.try_next().await? {
+        rows += batch.num_rows();
+        writer.write(&batch)?;
+    }
+    writer.close()?;
+    Ok(rows)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{write_parquet, ToRecordBatch};
+    use crate::job::Job;
+    use crate::test::TestCase;
+    use crate::Lava;
+
+    use arrow_array::{Array, Int64Array, StringArray};
+    use boulder::{Buildable, Builder};
+    use bytes::Bytes;
+    use futures::stream::{self, TryStreamExt};
+    use lava_api_mock::{LavaMock, PaginationLimits, PopulationParams, SharedState};
+    use parquet::arrow::arrow_reader::ParquetRecordBatchReaderBuilder;
+    use test_log::test;
+
+    fn make_test_case(id: i64, metadata: &str) -> TestCase {
+        serde_json::from_value(serde_json::json!({
+            "id": id,
+            "name": format!("case-{}", id),
+            "unit": "",
+            "result": "fail",
+            "measurement": null,
+            "metadata": metadata,
+            "suite": 3,
+            "start_log_line": 10,
+            "end_log_line": null,
+            "test_set": null,
+            "logged": "2022-04-10T16:30:00Z",
+            "resource_uri": format!("/api/v0.2/jobs/1/tests/{}/", id),
+        }))
+        .expect("failed to parse test case")
+    }
+
+    #[test]
+    fn test_test_cases() {
+        let cases = vec![
+            make_test_case(
+                1,
+                "definition: lava\ncase: job\nresult: fail\nduration: '1.50'\nerror_type: Infrastructure\nerror_msg: oops\n",
+            ),
+            make_test_case(2, "definition: 0_smoke\ncase: ls\nresult: pass\n"),
+        ];
+
+        let batch = TestCase::to_record_batch(&cases).expect("failed to make batch");
+        assert_eq!(batch.num_rows(), 2);
+        assert_eq!(batch.schema(), TestCase::schema());
+
+        let definitions = batch
+            .column_by_name("definition")
+            .unwrap()
+            .as_any()
+            .downcast_ref::<StringArray>()
+            .unwrap();
+        assert_eq!(definitions.value(0), "lava");
+        assert_eq!(definitions.value(1), "0_smoke");
+
+        let error_types = batch
+            .column_by_name("error_type")
+            .unwrap()
+            .as_any()
+            .downcast_ref::<StringArray>()
+            .unwrap();
+        assert_eq!(error_types.value(0), "Infrastructure");
+        assert!(error_types.is_null(1));
+
+        let mut data = Vec::new();
+        let rows = tokio_test::block_on(write_parquet::<_, TestCase, _>(
+            stream::iter(cases.into_iter().map(Ok::<_, std::io::Error>)),
+            &mut data,
+            1,
+        ));
+        assert_eq!(rows.expect("failed to write parquet"), 2);
+
+        let reader = ParquetRecordBatchReaderBuilder::try_new(Bytes::from(data))
+            .expect("failed to read parquet")
+            .build()
+            .expect("failed to read parquet");
+        let mut ids = Vec::new();
+        for batch in reader {
+            let batch = batch.expect("failed to read batch");
+            let col = batch
+                .column_by_name("id")
+                .unwrap()
+                .as_any()
+                .downcast_ref::<Int64Array>()
+                .unwrap()
+                .clone();
+            ids.extend(col.values().iter().copied());
+        }
+        assert_eq!(ids, vec![1, 2]);
+    }
+
+    #[test(tokio::test)]
+    async fn test_jobs() {
+        let server = LavaMock::new(
+            SharedState::new_populated(PopulationParams::builder().jobs(20usize).build()),
+            PaginationLimits::builder().jobs(Some(7)).build(),
+        )
+        .await;
+
+        let lava = Lava::new(&server.uri(), None).expect("failed to make lava server");
+
+        let jobs: Vec<Job> = lava
+            .jobs()
+            .query()
+            .try_collect()
+            .await
+            .expect("failed to get jobs");
+        let batch = Job::to_record_batch(&jobs).expect("failed to make batch");
+        assert_eq!(batch.num_rows(), 20);
+
+        let mut data = Vec::new();
+        let rows = write_parquet::<_, Job, _>(lava.jobs().query(), &mut data, 8)
+            .await
+            .expect("failed to write parquet");
+        assert_eq!(rows, 20);
+
+        let reader = ParquetRecordBatchReaderBuilder::try_new(Bytes::from(data))
+            .expect("failed to read parquet");
+        assert_eq!(reader.metadata().file_metadata().num_rows(), 20);
+    }
+}
@@ -0,0 +1,401 @@
+//! Find the token used to authenticate with the server
+//!
+//! A [`TokenSource`] given to
+//! [`LavaBuilder::token_source`](crate::LavaBuilder::token_source)
+//! says where the token for a [`Lava`](crate::Lava) comes from: it
+//! can be given directly, read from an environment variable or a
+//! `.netrc` file, or fetched by a callback before each request, for
+//! tokens which expire and must be refreshed. Several sources can be
+//! tried in turn, so that command line tools can share the same
+//! discovery rules.
+//!
+//! Example:
+//! ```rust,no_run
+//! use lava_api::auth::TokenSource;
+//! use lava_api::Lava;
+//!
+//! let lava = Lava::builder("https://lava.example.com/")
+//!     .token_source(TokenSource::FirstOf(vec![
+//!         TokenSource::Env("LAVA_TOKEN".to_string()),
+//!         TokenSource::Netrc(None),
+//!     ]))
+//!     .build()
+//!     .expect("failed to make lava");
+//! ```
+
+use futures::future::BoxFuture;
+use std::env;
+use std::fmt::{self, Debug, Formatter};
+use std::fs;
+use std::future::Future;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use thiserror::Error;
+use url::Url;
+
+#[derive(Error, Debug)]
+pub enum TokenError {
+    #[error("No token found in {0}")]
+    NotFound(String),
+    #[error("Failed to read {path}")]
+    Netrc {
+        path: PathBuf,
+        #[source]
+        source: io::Error,
+    },
+    #[error("No home directory to find .netrc in")]
+    NoHome,
+}
+
+/// A means of fetching a token when it is needed
+///
+/// The provider is asked for a token before every request, including
+/// retries, so it can refresh a token which has expired; it should
+/// keep the token between calls, rather than fetching a new one each
+/// time. When it returns `None`, the request is sent without a
+/// token.
+///
+/// This is implemented for closures returning a future, so a
+/// provider can be written as:
+/// ```rust
+/// use lava_api::auth::TokenSource;
+///
+/// let source = TokenSource::callback(|| async { Some("secret".to_string()) });
+/// ```
+pub trait TokenProvider: Send + Sync {
+    /// Fetch the current token.
+    fn token(&self) -> BoxFuture<'static, Option<String>>;
+}
+
+impl<F, Fut> TokenProvider for F
+where
+    F: Fn() -> Fut + Send + Sync,
+    Fut: Future<Output = Option<String>> + Send + 'static,
+{
+    fn token(&self) -> BoxFuture<'static, Option<String>> {
+        Box::pin(self())
+    }
+}
+
+/// Where the token used to authenticate with the server comes from
+///
+/// All but [`Callback`](TokenSource::Callback) are read once, when
+/// the [`Lava`](crate::Lava) is built, and building fails with
+/// [`TokenError::NotFound`] if the source has no token.
+#[derive(Clone)]
+pub enum TokenSource {
+    /// The token itself
+    Token(String),
+    /// The environment variable holding the token
+    Env(String),
+    /// A `.netrc` file, where the token is the `password` given for
+    /// the server's host, or for the `default` entry
+    ///
+    /// When no path is given, the file named by the `NETRC`
+    /// environment variable is used, or else `.netrc` in the user's
+    /// home directory.
+    Netrc(Option<PathBuf>),
+    /// A provider asked for the token before each request
+    Callback(Arc<dyn TokenProvider>),
+    /// The first of these sources which has a token
+    ///
+    /// Sources without a token are skipped, but errors reading a
+    /// source, other than a missing `.netrc` file, are not.
+    FirstOf(Vec<TokenSource>),
+}
+
+impl TokenSource {
+    /// A [`Callback`](TokenSource::Callback) source using `provider`.
+    pub fn callback<P: TokenProvider + 'static>(provider: P) -> Self {
+        Self::Callback(Arc::new(provider))
+    }
+
+    // Where to look for the token, for error messages.
+    fn describe(&self) -> String {
+        match self {
+            Self::Token(_) => "the given token".to_string(),
+            Self::Env(var) => format!("environment variable {}", var),
+            Self::Netrc(Some(path)) => path.display().to_string(),
+            Self::Netrc(None) => ".netrc".to_string(),
+            Self::Callback(_) => "the token callback".to_string(),
+            Self::FirstOf(sources) => sources
+                .iter()
+                .map(Self::describe)
+                .collect::<Vec<_>>()
+                .join(", "),
+        }
+    }
+
+    pub(crate) fn resolve(&self, url: &Url) -> Result<Token, TokenError> {
+        self.find(url)?
+            .ok_or_else(|| TokenError::NotFound(self.describe()))
+    }
+
+    fn find(&self, url: &Url) -> Result<Option<Token>, TokenError> {
+        match self {
+            Self::Token(token) => Ok(Some(Token::Fixed(token.clone()))),
+            Self::Env(var) => Ok(env::var(var)
+                .ok()
+                .map(|token| token.trim().to_string())
+                .filter(|token| !token.is_empty())
+                .map(Token::Fixed)),
+            Self::Netrc(path) => {
+                let path = match path {
+                    Some(path) => path.clone(),
+                    None => default_netrc()?,
+                };
+                Ok(netrc_password(&path, url.host_str().unwrap_or_default())?.map(Token::Fixed))
+            }
+            Self::Callback(provider) => Ok(Some(Token::Provider(provider.clone()))),
+            Self::FirstOf(sources) => {
+                for source in sources {
+                    if let Some(token) = source.find(url)? {
+                        return Ok(Some(token));
+                    }
+                }
+                Ok(None)
+            }
+        }
+    }
+}
+
+impl Debug for TokenSource {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        // Keep the token itself out of any logs
+        match self {
+            Self::Token(_) => f.debug_tuple("Token").finish_non_exhaustive(),
+            Self::Env(var) => f.debug_tuple("Env").field(var).finish(),
+            Self::Netrc(path) => f.debug_tuple("Netrc").field(path).finish(),
+            Self::Callback(_) => f.debug_tuple("Callback").finish_non_exhaustive(),
+            Self::FirstOf(sources) => f.debug_tuple("FirstOf").field(sources).finish(),
+        }
+    }
+}
+
+/// A token found by a [`TokenSource`]
+pub(crate) enum Token {
+    Fixed(String),
+    Provider(Arc<dyn TokenProvider>),
+}
+
+fn default_netrc() -> Result<PathBuf, TokenError> {
+    if let Some(path) = env::var_os("NETRC") {
+        return Ok(path.into());
+    }
+    env::var_os("HOME")
+        .map(|home| Path::new(&home).join(".netrc"))
+        .ok_or(TokenError::NoHome)
+}
+
+// The password for `host` in the netrc file at `path`, or for the
+// default entry if there is none for `host`. A missing file has no
+// passwords.
+fn netrc_password(path: &Path, host: &str) -> Result<Option<String>, TokenError> {
+    let contents = match fs::read_to_string(path) {
+        Ok(contents) => contents,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+        Err(source) => {
+            return Err(TokenError::Netrc {
+                path: path.to_path_buf(),
+                source,
+            })
+        }
+    };
+    Ok(parse_netrc(&contents, host))
+}
+
+fn parse_netrc(contents: &str, host: &str) -> Option<String> {
+    // Macro definitions run until the next blank line, and are not
+    // split into tokens.
+    let mut words = Vec::new();
+    let mut in_macro = false;
+    for line in contents.lines() {
+        if in_macro {
+            in_macro = !line.trim().is_empty();
+            continue;
+        }
+        let mut line_words = line.split_whitespace();
+        while let Some(word) = line_words.next() {
+            if word == "macdef" {
+                line_words.next();
+                in_macro = true;
+                break;
+            }
+            words.push(word);
+        }
+    }
+
+    #[derive(PartialEq)]
+    enum Entry {
+        Host,
+        Default,
+        Other,
+    }
+
+    let mut found = None;
+    let mut default = None;
+    let mut entry = Entry::Other;
+    let mut words = words.into_iter();
+    while let Some(word) = words.next() {
+        match word {
+            "machine" => {
+                entry = match words.next() == Some(host) {
+                    true => Entry::Host,
+                    false => Entry::Other,
+                }
+            }
+            "default" => entry = Entry::Default,
+            "password" => {
+                let password = words.next().map(str::to_string);
+                match entry {
+                    Entry::Host if found.is_none() => found = password,
+                    Entry::Default if default.is_none() => default = password,
+                    _ => (),
+                }
+            }
+            "login" | "account" => {
+                words.next();
+            }
+            _ => (),
+        }
+    }
+    found.or(default)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{parse_netrc, TokenError, TokenSource};
+    use crate::{Lava, LavaError};
+
+    use futures::TryStreamExt;
+    use serde_json::json;
+    use std::path::PathBuf;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+    use test_log::test;
+    use url::Url;
+    use wiremock::matchers::{header, method, path};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    #[test]
+    fn test_parse_netrc() {
+        let netrc = "
+machine other.example.com login a password other
+macdef init
+machine lava.example.com password wrong
+
+machine lava.example.com
+    login b
+    password secret
+default login c password fallback
+";
+        assert_eq!(
+            parse_netrc(netrc, "lava.example.com"),
+            Some("secret".to_string())
+        );
+        assert_eq!(
+            parse_netrc(netrc, "other.example.com"),
+            Some("other".to_string())
+        );
+        assert_eq!(
+            parse_netrc(netrc, "unknown.example.com"),
+            Some("fallback".to_string())
+        );
+        assert_eq!(parse_netrc("machine a password x", "b"), None);
+    }
+
+    #[test]
+    fn test_sources() {
+        let url = Url::parse("https://lava.example.com/").unwrap();
+        let found = |source: TokenSource| match source.resolve(&url) {
+            Ok(super::Token::Fixed(token)) => Ok(token),
+            Ok(super::Token::Provider(_)) => panic!("unexpected provider"),
+            Err(e) => Err(e),
+        };
+
+        let var = format!("LAVA_API_TEST_TOKEN_{}", std::process::id());
+        std::env::set_var(&var, "from-env\n");
+        assert_eq!(found(TokenSource::Env(var.clone())).unwrap(), "from-env");
+
+        let path = std::env::temp_dir().join(format!("lava-api-netrc-{}", std::process::id()));
+        std::fs::write(&path, "machine lava.example.com password from-netrc\n").unwrap();
+        assert_eq!(
+            found(TokenSource::Netrc(Some(path.clone()))).unwrap(),
+            "from-netrc"
+        );
+
+        let missing = TokenSource::Env(format!("{}_MISSING", var));
+        let no_file = TokenSource::Netrc(Some(PathBuf::from("/nonexistent/.netrc")));
+        assert!(matches!(
+            found(missing.clone()),
+            Err(TokenError::NotFound(_))
+        ));
+        assert!(matches!(
+            found(no_file.clone()),
+            Err(TokenError::NotFound(_))
+        ));
+        assert_eq!(
+            found(TokenSource::FirstOf(vec![
+                missing.clone(),
+                no_file.clone(),
+                TokenSource::Netrc(Some(path.clone())),
+                TokenSource::Env(var.clone()),
+            ]))
+            .unwrap(),
+            "from-netrc"
+        );
+        assert!(matches!(
+            found(TokenSource::FirstOf(vec![missing, no_file])),
+            Err(TokenError::NotFound(_))
+        ));
+
+        std::fs::remove_file(&path).unwrap();
+        std::env::remove_var(&var);
+
+        assert!(matches!(
+            Lava::builder("https://lava.example.com/")
+                .token_source(TokenSource::Env(var))
+                .build(),
+            Err(LavaError::TokenSource(TokenError::NotFound(_)))
+        ));
+    }
+
+    #[test(tokio::test)]
+    async fn test_callback() {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/api/v0.2/workers/"))
+            .and(header("authorization", "Token token-1"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "count": 0,
+                "next": null,
+                "results": [],
+            })))
+            .expect(2)
+            .mount(&server)
+            .await;
+
+        let calls = Arc::new(AtomicUsize::new(0));
+        let source = {
+            let calls = calls.clone();
+            TokenSource::callback(move || {
+                calls.fetch_add(1, Ordering::SeqCst);
+                async { Some("token-1".to_string()) }
+            })
+        };
+        let lava = Lava::builder(&server.uri())
+            .token_source(source)
+            .build()
+            .expect("failed to make lava server");
+
+        for _ in 0..2 {
+            lava.workers()
+                .try_collect::<Vec<_>>()
+                .await
+                .expect("failed to query workers");
+        }
+        // The provider is asked for each request
+        assert_eq!(calls.load(Ordering::SeqCst), 2);
+    }
+}
@@ -0,0 +1,302 @@
+//! Keep an up to date table of the devices on a server
+//!
+//! Schedulers and dashboards built on this crate often need to know
+//! the current state of every device, and to react when it changes.
+//! A [`DeviceCache`] holds the devices read by the most recent
+//! [`snapshot`], and on each [`refresh`](DeviceCache::refresh)
+//! compares a new snapshot with it, broadcasting the differences to
+//! any subscribers.
+//!
+//! LAVA does not provide entity tags for its REST endpoints, so each
+//! refresh reads the complete device list; only the notifications
+//! are incremental.
+//!
+//! Example:
+//! ```rust
+//! # use lava_api_mock::{LavaMock, PaginationLimits, PopulationParams, SharedState};
+//! use lava_api::{Lava, cache::DeviceCache};
+//! #
+//! # tokio_test::block_on( async {
+//! # let limits = PaginationLimits::new();
+//! # let population = PopulationParams::new();
+//! # let mock = LavaMock::new(SharedState::new_populated(population), limits).await;
+//! # let service_uri = mock.uri();
+//! # let lava_token = None;
+//!
+//! let lava = Lava::new(&service_uri, lava_token).expect("failed to make lava");
+//!
+//! let cache = DeviceCache::new();
+//! let mut changes = cache.subscribe();
+//! cache.refresh(&lava).await.expect("failed to refresh devices");
+//!
+//! while let Ok(change) = changes.try_recv() {
+//!     println!("Device {} changed: {:?}", change.hostname(), change);
+//! }
+//! # });
+//! ```
+
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::sync::RwLock;
+use std::time::Duration;
+use tokio::sync::broadcast;
+
+use crate::device::Device;
+use crate::paginator::PaginationError;
+use crate::snapshot::{snapshot, EntityKind};
+use crate::Lava;
+
+/// The number of changes buffered for each subscriber by default
+const DEFAULT_CAPACITY: usize = 256;
+
+/// A difference between two device tables
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub enum DeviceChange {
+    /// A device with a new hostname appeared
+    Added(Device),
+    /// A device's hostname no longer appears
+    Removed(Device),
+    /// Some other field of a device changed
+    Changed { old: Device, new: Device },
+}
+
+impl DeviceChange {
+    /// The hostname of the device which changed.
+    pub fn hostname(&self) -> &str {
+        match self {
+            DeviceChange::Added(device) => &device.hostname,
+            DeviceChange::Removed(device) => &device.hostname,
+            DeviceChange::Changed { new, .. } => &new.hostname,
+        }
+    }
+}
+
+/// Compute the changes needed to turn the devices in `old` into those
+/// in `new`.
+///
+/// Devices are matched by hostname, and the changes are returned in
+/// hostname order.
+pub fn diff_devices<'a, I, J>(old: I, new: J) -> Vec<DeviceChange>
+where
+    I: IntoIterator<Item = &'a Device>,
+    J: IntoIterator<Item = &'a Device>,
+{
+    let mut old = old
+        .into_iter()
+        .map(|d| (d.hostname.as_str(), d))
+        .collect::<BTreeMap<_, _>>();
+    let new = new
+        .into_iter()
+        .map(|d| (d.hostname.as_str(), d))
+        .collect::<BTreeMap<_, _>>();
+
+    let mut changes = Vec::new();
+    for (hostname, device) in new.iter() {
+        match old.remove(hostname) {
+            Some(previous) if previous == *device => {}
+            Some(previous) => changes.push(DeviceChange::Changed {
+                old: previous.clone(),
+                new: (*device).clone(),
+            }),
+            None => changes.push(DeviceChange::Added((*device).clone())),
+        }
+    }
+    changes.extend(
+        old.into_values()
+            .map(|device| DeviceChange::Removed(device.clone())),
+    );
+    changes.sort_by(|a, b| a.hostname().cmp(b.hostname()));
+    changes
+}
+
+/// An in-memory table of the devices on a server
+///
+/// The table starts empty, and is updated by
+/// [`refresh`](DeviceCache::refresh), or periodically by
+/// [`run`](DeviceCache::run). Each [`DeviceChange`] found by a
+/// refresh is sent to every receiver returned by
+/// [`subscribe`](DeviceCache::subscribe); receivers which fall too
+/// far behind miss changes, as for any [`broadcast`] channel, and
+/// should then re-read the table with
+/// [`devices`](DeviceCache::devices).
+///
+/// The cache holds no reference to a [`Lava`], so a single cache can
+/// be shared between tasks, for example in an [`Arc`](std::sync::Arc).
+pub struct DeviceCache {
+    devices: RwLock<BTreeMap<String, Device>>,
+    refreshed: RwLock<Option<DateTime<Utc>>>,
+    changes: broadcast::Sender<DeviceChange>,
+}
+
+impl DeviceCache {
+    /// Create a new, empty [`DeviceCache`].
+    pub fn new() -> Self {
+        Self::with_capacity(DEFAULT_CAPACITY)
+    }
+
+    /// Create a new, empty [`DeviceCache`], buffering up to
+    /// `capacity` changes for each subscriber.
+    pub fn with_capacity(capacity: usize) -> Self {
+        let (changes, _) = broadcast::channel(capacity);
+        Self {
+            devices: RwLock::new(BTreeMap::new()),
+            refreshed: RwLock::new(None),
+            changes,
+        }
+    }
+
+    /// Receive the changes found by future refreshes.
+    pub fn subscribe(&self) -> broadcast::Receiver<DeviceChange> {
+        self.changes.subscribe()
+    }
+
+    /// The device with the given hostname, if it is in the table.
+    pub fn get(&self, hostname: &str) -> Option<Device> {
+        self.devices.read().unwrap().get(hostname).cloned()
+    }
+
+    /// All the devices in the table, in hostname order.
+    pub fn devices(&self) -> Vec<Device> {
+        self.devices.read().unwrap().values().cloned().collect()
+    }
+
+    /// The time at which the snapshot behind the table was taken, or
+    /// `None` if the cache has never been refreshed.
+    pub fn refreshed(&self) -> Option<DateTime<Utc>> {
+        *self.refreshed.read().unwrap()
+    }
+
+    /// Read the devices from the server, update the table, and notify
+    /// subscribers of any changes.
+    ///
+    /// The changes are also returned. If reading the devices fails,
+    /// the table is left as it was.
+    pub async fn refresh(&self, lava: &Lava) -> Result<Vec<DeviceChange>, PaginationError> {
+        let snapshot = snapshot(lava, &[EntityKind::Devices]).await?;
+        let devices = snapshot.devices.unwrap_or_default();
+
+        let changes = {
+            let mut table = self.devices.write().unwrap();
+            let changes = diff_devices(table.values(), devices.iter());
+            *table = devices
+                .into_iter()
+                .map(|d| (d.hostname.clone(), d))
+                .collect();
+            *self.refreshed.write().unwrap() = Some(snapshot.taken);
+            changes
+        };
+
+        for change in changes.iter() {
+            // Sending only fails when there are no subscribers.
+            let _ = self.changes.send(change.clone());
+        }
+
+        Ok(changes)
+    }
+
+    /// Refresh the table every `period`, until the returned future is
+    /// dropped.
+    ///
+    /// The first refresh is made immediately. Failed refreshes are
+    /// logged and retried at the next period. No task is spawned:
+    /// the caller should drive this future, usually by spawning it
+    /// themselves.
+    pub async fn run(&self, lava: &Lava, period: Duration) {
+        let mut interval = tokio::time::interval(period);
+        interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+        loop {
+            interval.tick().await;
+            if let Err(e) = self.refresh(lava).await {
+                log::warn!("Failed to refresh device cache: {}", e);
+            }
+        }
+    }
+}
+
+impl Default for DeviceCache {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{DeviceCache, DeviceChange};
+    use crate::device::Health;
+    use crate::Lava;
+
+    use boulder::{Buildable, Builder};
+    use lava_api_mock::{
+        Device as MockDevice, DeviceHealth as MockDeviceHealth, LavaMock, PaginationLimits,
+        PopulationParams, SharedState, State,
+    };
+    use persian_rug::Context;
+    use test_log::test;
+
+    #[test(tokio::test)]
+    async fn test_refresh() {
+        let mut state =
+            SharedState::new_populated(PopulationParams::builder().devices(6usize).build());
+        let server = LavaMock::new(
+            state.clone(),
+            PaginationLimits::builder().devices(Some(4)).build(),
+        )
+        .await;
+
+        let lava = Lava::new(&server.uri(), None).expect("failed to make lava server");
+
+        let cache = DeviceCache::new();
+        assert!(cache.refreshed().is_none());
+        let mut changes = cache.subscribe();
+
+        let found = cache.refresh(&lava).await.expect("failed to refresh");
+        assert_eq!(found.len(), 6);
+        assert!(found.iter().all(|c| matches!(c, DeviceChange::Added(_))));
+        assert_eq!(cache.devices().len(), 6);
+        assert!(cache.refreshed().is_some());
+        for change in found.iter() {
+            assert_eq!(changes.try_recv().as_ref(), Ok(change));
+        }
+
+        let found = cache.refresh(&lava).await.expect("failed to refresh");
+        assert!(found.is_empty());
+        assert!(changes.try_recv().is_err());
+
+        let (changed, renamed) = {
+            let mut m = state.mutate();
+            let mut devices = m.get_iter_mut::<MockDevice<State>>();
+            let first = devices.next().unwrap();
+            first.health = if first.health == MockDeviceHealth::Bad {
+                MockDeviceHealth::Good
+            } else {
+                MockDeviceHealth::Bad
+            };
+            let changed = first.hostname.clone();
+            let second = devices.next().unwrap();
+            let renamed = second.hostname.clone();
+            second.hostname = "renamed-device".to_string();
+            (changed, renamed)
+        };
+
+        let found = cache.refresh(&lava).await.expect("failed to refresh");
+        assert_eq!(found.len(), 3);
+        for change in found.iter() {
+            assert_eq!(changes.try_recv().as_ref(), Ok(change));
+            match change {
+                DeviceChange::Added(device) => assert_eq!(device.hostname, "renamed-device"),
+                DeviceChange::Removed(device) => assert_eq!(device.hostname, renamed),
+                DeviceChange::Changed { old, new } => {
+                    assert_eq!(new.hostname, changed);
+                    assert_ne!(old.health, new.health);
+                }
+            }
+        }
+        assert!(cache.get(&renamed).is_none());
+        assert!(cache.get("renamed-device").is_some());
+        assert!(matches!(
+            cache.get(&changed).map(|d| d.health),
+            Some(Health::Good) | Some(Health::Bad)
+        ));
+    }
+}
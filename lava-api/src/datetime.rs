@@ -0,0 +1,151 @@
+//! Parse the times reported by the server
+//!
+//! LAVA normally reports times in RFC 3339 format, with microseconds
+//! and an offset, but some deployments and endpoints leave out the
+//! fractional seconds or the offset, or separate the date from the
+//! time with a space. The models in this crate accept all of these
+//! forms, reading times without an offset as UTC, which is how LAVA
+//! stores them. The functions here can be used with
+//! `#[serde(deserialize_with = ...)]` to do the same in other
+//! models; fields which should only accept RFC 3339 can use the
+//! default [`DateTime`] deserializer instead.
+//!
+//! Example:
+//! ```rust
+//! use lava_api::datetime;
+//!
+//! let precise = datetime::parse("2022-03-17T17:00:00.123456+00:00").unwrap();
+//! let naive = datetime::parse("2022-03-17 17:00:00").unwrap();
+//! assert_eq!(precise.timestamp(), naive.timestamp());
+//! ```
+
+use chrono::{DateTime, NaiveDateTime, Utc};
+use serde::{Deserialize, Deserializer};
+
+// Formats with an offset, tried after RFC 3339.
+const OFFSET_FORMATS: &[&str] = &["%Y-%m-%dT%H:%M:%S%.f%z", "%Y-%m-%d %H:%M:%S%.f%z"];
+
+// Formats without an offset, which are read as UTC.
+const NAIVE_FORMATS: &[&str] = &[
+    "%Y-%m-%dT%H:%M:%S%.f",
+    "%Y-%m-%d %H:%M:%S%.f",
+    "%Y-%m-%dT%H:%M",
+    "%Y-%m-%d %H:%M",
+];
+
+/// Parse a time as reported by a LAVA server.
+///
+/// This accepts RFC 3339 times, times with an offset given without a
+/// colon, and times with no offset, which are taken to be in UTC.
+/// Seconds and fractional seconds are optional, and the date and
+/// time can be separated by a space instead of `T`.
+pub fn parse(s: &str) -> Result<DateTime<Utc>, chrono::ParseError> {
+    let s = s.trim();
+    let mut result = DateTime::parse_from_rfc3339(s).map(|dt| dt.with_timezone(&Utc));
+    for format in OFFSET_FORMATS {
+        if result.is_ok() {
+            break;
+        }
+        result = DateTime::parse_from_str(s, format).map(|dt| dt.with_timezone(&Utc));
+    }
+    for format in NAIVE_FORMATS {
+        if result.is_ok() {
+            break;
+        }
+        result = NaiveDateTime::parse_from_str(s, format).map(|dt| dt.and_utc());
+    }
+    result
+}
+
+/// Deserialize a time with [`parse`].
+pub fn deserialize<'de, D>(deserializer: D) -> Result<DateTime<Utc>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let s = String::deserialize(deserializer)?;
+    parse(&s).map_err(serde::de::Error::custom)
+}
+
+/// Deserialize an optional time with [`parse`].
+///
+/// Fields using this should also be marked `#[serde(default)]`, so
+/// that they can be left out as other [`Option`] fields can.
+pub fn deserialize_option<'de, D>(deserializer: D) -> Result<Option<DateTime<Utc>>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    Option::<String>::deserialize(deserializer)?
+        .map(|s| parse(&s))
+        .transpose()
+        .map_err(serde::de::Error::custom)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::parse;
+
+    use chrono::{DateTime, Utc};
+    use serde::Deserialize;
+
+    #[test]
+    fn test_parse() {
+        let expected = |s: &str| DateTime::parse_from_rfc3339(s).unwrap().with_timezone(&Utc);
+
+        let exact = expected("2022-03-17T17:00:00.123456Z");
+        for s in [
+            "2022-03-17T17:00:00.123456Z",
+            "2022-03-17T17:00:00.123456+00:00",
+            "2022-03-17T18:00:00.123456+01:00",
+            "2022-03-17T18:00:00.123456+0100",
+            "2022-03-17T17:00:00.123456",
+            "2022-03-17 17:00:00.123456",
+            " 2022-03-17T17:00:00.123456Z\n",
+        ] {
+            assert_eq!(parse(s).expect(s), exact, "{}", s);
+        }
+
+        let whole = expected("2022-03-17T17:00:00Z");
+        for s in [
+            "2022-03-17T17:00:00Z",
+            "2022-03-17T17:00:00",
+            "2022-03-17 17:00:00",
+            "2022-03-17 17:00:00+00:00",
+            "2022-03-17T17:00",
+            "2022-03-17 17:00",
+        ] {
+            assert_eq!(parse(s).expect(s), whole, "{}", s);
+        }
+
+        for s in ["", "yesterday", "2022-03-17", "2022-13-17T17:00:00Z"] {
+            assert!(parse(s).is_err(), "{}", s);
+        }
+    }
+
+    #[test]
+    fn test_deserialize() {
+        #[derive(Deserialize)]
+        struct Times {
+            #[serde(deserialize_with = "super::deserialize")]
+            at: DateTime<Utc>,
+            #[serde(default, deserialize_with = "super::deserialize_option")]
+            until: Option<DateTime<Utc>>,
+        }
+
+        let times: Times = serde_json::from_str(r#"{"at": "2022-03-17 17:00:00", "until": null}"#)
+            .expect("failed to parse times");
+        assert_eq!(times.at, parse("2022-03-17T17:00:00Z").unwrap());
+        assert_eq!(times.until, None);
+
+        let times: Times = serde_json::from_str(r#"{"at": "2022-03-17T17:00:00Z"}"#)
+            .expect("failed to parse times");
+        assert_eq!(times.until, None);
+
+        let times: Times = serde_json::from_str(
+            r#"{"at": "2022-03-17T17:00:00Z", "until": "2022-03-17T18:00:00.5"}"#,
+        )
+        .expect("failed to parse times");
+        assert_eq!(times.until, Some(parse("2022-03-17T18:00:00.5Z").unwrap()));
+
+        assert!(serde_json::from_str::<Times>(r#"{"at": "soon"}"#).is_err());
+    }
+}
@@ -1,20 +1,40 @@
-//! Retrieve devices
+//! Retrieve and manage devices
 
 use futures::future::BoxFuture;
 use futures::FutureExt;
-use futures::{stream, stream::Stream, stream::StreamExt};
-use serde::Deserialize;
-use serde_with::DeserializeFromStr;
+use futures::{stream::Stream, TryStreamExt};
+use reqwest::StatusCode;
+use serde::{Deserialize, Serialize};
+use serde_with::{DeserializeFromStr, SerializeDisplay};
+use std::collections::hash_map::{Entry, HashMap};
+use std::collections::{BTreeMap, HashSet};
 use std::pin::Pin;
 use std::task::{Context, Poll};
-use strum::{Display, EnumString};
+use strum::{Display, EnumIter, EnumString, IntoEnumIterator};
+use thiserror::Error;
+use url::Url;
 
-use crate::paginator::{PaginationError, Paginator};
-use crate::tag::Tag;
+use crate::error::{Classify, ErrorClass};
+use crate::paginator::{PaginationError, PaginationProgress, Paginator};
+use crate::queryset::{QuerySet, QuerySetMember, ValueSet};
+use crate::tag::{create_tag, Tag, TagError, TagFilter, TagRef};
+use crate::transport;
 use crate::Lava;
 
 /// The current status of a [`Device`]
-#[derive(Clone, Copy, Debug, DeserializeFromStr, Display, EnumString, Eq, PartialEq)]
+#[derive(
+    Clone,
+    Copy,
+    Debug,
+    DeserializeFromStr,
+    Display,
+    EnumIter,
+    EnumString,
+    Eq,
+    Hash,
+    PartialEq,
+    SerializeDisplay,
+)]
 pub enum Health {
     Unknown,
     Maintenance,
@@ -24,45 +44,138 @@ pub enum Health {
     Retired,
 }
 
+impl QuerySetMember for Health {
+    type Iter = HealthIter;
+    fn all() -> Self::Iter {
+        Self::iter()
+    }
+}
+
+/// Whether a [`Device`] is currently in use
+#[derive(
+    Clone,
+    Copy,
+    Debug,
+    DeserializeFromStr,
+    Display,
+    EnumIter,
+    EnumString,
+    Eq,
+    Hash,
+    PartialEq,
+    SerializeDisplay,
+)]
+pub enum State {
+    Idle,
+    Reserved,
+    Running,
+}
+
+impl QuerySetMember for State {
+    type Iter = StateIter;
+    fn all() -> Self::Iter {
+        Self::iter()
+    }
+}
+
+/// The possible orderings in which devices can be returned
+///
+/// These are usually combined with a [`bool`] in use, indicating
+/// whether the order is to be ascending or descending.
+#[derive(
+    Copy, Clone, Debug, Default, Hash, PartialEq, Eq, Display, EnumString, DeserializeFromStr,
+)]
+#[strum(serialize_all = "snake_case")]
+pub enum Ordering {
+    #[default]
+    Hostname,
+    DeviceType,
+    WorkerHost,
+    State,
+    Health,
+}
+
 #[derive(Clone, Deserialize, Debug)]
 struct LavaDevice {
     hostname: String,
     worker_host: String,
     device_type: String,
     description: Option<String>,
+    #[serde(default)]
+    device_version: Option<String>,
+    #[serde(default)]
+    physical_owner: Option<i64>,
+    #[serde(default)]
+    physical_group: Option<i64>,
+    state: State,
     health: Health,
     pub tags: Vec<u32>,
+    #[serde(default)]
+    is_synced: bool,
+    #[serde(default)]
+    last_health_report_job: Option<i64>,
 }
 
 /// A subset of the data available for a device from the LAVA API.
 ///
 /// Note that [`tags`](Device::tags) have been resolved into [`Tag`]
-/// objects, rather than tag ids.
-#[derive(Clone, Debug, PartialEq, Eq)]
+/// objects, rather than tag ids, while the owner, group and health
+/// report job are given by id, as the server reports them.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
 pub struct Device {
     pub hostname: String,
     pub worker_host: String,
     pub device_type: String,
     pub description: Option<String>,
+    /// The version of the device hardware, if recorded
+    pub device_version: Option<String>,
+    /// The id of the user physically responsible for the device
+    ///
+    /// This is left unresolved, as the crate has no query for users
+    /// to look it up in.
+    pub physical_owner: Option<i64>,
+    /// The id of the group physically responsible for the device
+    ///
+    /// This is left unresolved, as the crate has no query for groups
+    /// to look it up in.
+    pub physical_group: Option<i64>,
+    pub state: State,
     pub health: Health,
     pub tags: Vec<Tag>,
+    /// Whether the device's configuration is kept in sync with the
+    /// worker's, rather than edited on the server
+    pub is_synced: bool,
+    /// The id of the job which last reported on the health of the
+    /// device
+    pub last_health_report_job: Option<i64>,
 }
 
-enum State<'a> {
+enum PagingState<'a> {
     Paging,
-    Transforming(BoxFuture<'a, Device>),
+    Resolving(BoxFuture<'a, HashMap<u32, Tag>>),
 }
 
-/// A [`Stream`] that yields all the [`Device`] instances on a LAVA
-/// server.
+/// A [`Stream`] that yields a selected subset of the [`Device`]
+/// instances on a LAVA server.
 pub struct Devices<'a> {
     lava: &'a Lava,
     paginator: Paginator<LavaDevice>,
-    state: State<'a>,
+    state: PagingState<'a>,
+    yielded: u32,
+    // The page whose tags have been resolved into `tags`
+    page: u32,
+    tags: HashMap<u32, Tag>,
+    // The item waiting for the tags of its page to be resolved
+    pending: Option<LavaDevice>,
+    // The tags a device must carry, which the server cannot check
+    required: TagFilter,
 }
 
 impl<'a> Devices<'a> {
-    /// Create a new stream, using the given [`Lava`] proxy.
+    /// Create a new stream of all devices, using the given [`Lava`]
+    /// proxy, ordered by hostname.
+    ///
+    /// Use a [`DevicesBuilder`] to select only some devices.
     ///
     /// Note that due to pagination, the dataset returned is not
     /// guaranteed to be self-consistent, and the odds of
@@ -70,33 +183,663 @@ impl<'a> Devices<'a> {
     /// the stream. It is therefore advisable to extract whatever data
     /// is required immediately after the creation of this object.
     pub fn new(lava: &'a Lava) -> Self {
-        let url = lava
-            .base
-            .join("devices/?ordering=hostname")
-            .expect("Failed to append to base url");
-        let paginator = Paginator::new(lava.client.clone(), url);
+        DevicesBuilder::new(lava).query()
+    }
+
+    /// The server's latest report of how many [`Device`] instances
+    /// are in the result set.
+    ///
+    /// As for [`Jobs::reported_items`](crate::job::Jobs::reported_items),
+    /// this only counts devices matching the query, and is subject
+    /// to change as the stream is read.
+    pub fn reported_items(&self) -> Option<u32> {
+        self.paginator.reported_items()
+    }
+}
+
+impl PaginationProgress for Devices<'_> {
+    fn reported_items(&self) -> Option<u32> {
+        self.paginator.reported_items()
+    }
+
+    fn yielded_items(&self) -> u32 {
+        self.yielded
+    }
+
+    fn fetched_pages(&self) -> u32 {
+        self.paginator.fetched_pages()
+    }
+}
+
+/// Select a set of [`Device`] instances to return from the LAVA
+/// server.
+///
+/// This is the way to construct a filtered [`Devices`] object. It
+/// allows customisation of which devices to return, and in what
+/// order; the filtering is performed by the server.
+///
+/// Example:
+/// ```rust
+/// use futures::stream::TryStreamExt;
+/// # use lava_api_mock::{LavaMock, PaginationLimits, PopulationParams, SharedState};
+/// use lava_api::{Lava, device::Health, device::Ordering};
+/// #
+/// # tokio_test::block_on( async {
+/// # let limits = PaginationLimits::new();
+/// # let population = PopulationParams::new();
+/// # let mock = LavaMock::new(SharedState::new_populated(population), limits).await;
+/// # let service_uri = mock.uri();
+/// # let lava_token = None;
+///
+/// let lava = Lava::new(&service_uri, lava_token).expect("failed to make lava");
+///
+/// let mut ld = lava
+///     .devices_builder()
+///     .health(Health::Bad)
+///     .ordering(Ordering::WorkerHost, true)
+///     .query();
+///
+/// while let Some(device) = ld
+///     .try_next()
+///     .await
+///     .expect("failed to get device")
+/// {
+///     println!("Got device {:?}", device);
+/// }
+/// # });
+/// ```
+#[derive(Debug, Clone)]
+pub struct DevicesBuilder<'a> {
+    lava: &'a Lava,
+    healths: QuerySet<Health>,
+    states: QuerySet<State>,
+    device_types: ValueSet<String>,
+    worker_hosts: ValueSet<String>,
+    hostname_prefix: Option<String>,
+    tags: TagFilter,
+    limit: Option<u32>,
+    ordering: Ordering,
+    ascending: bool,
+    raw_params: Vec<(String, String)>,
+}
+
+impl<'a> DevicesBuilder<'a> {
+    /// Create a new [`DevicesBuilder`]
+    ///
+    /// The default query is:
+    /// - order by [`Ordering::Hostname`]
+    /// - no filtering
+    /// - default result pagination
+    pub fn new(lava: &'a Lava) -> Self {
         Self {
             lava,
+            healths: QuerySet::new("health"),
+            states: QuerySet::new("state"),
+            device_types: ValueSet::new("device_type__name"),
+            worker_hosts: ValueSet::new("worker_host__hostname"),
+            hostname_prefix: None,
+            tags: TagFilter::new(),
+            limit: None,
+            ordering: Ordering::Hostname,
+            ascending: true,
+            raw_params: Vec::new(),
+        }
+    }
+
+    /// Return devices with this health.
+    ///
+    /// Example:
+    /// ```rust
+    /// use futures::stream::TryStreamExt;
+    /// # use lava_api_mock::DeviceHealth;
+    /// use lava_api::{Lava, device::Health};
+    /// #
+    /// # tokio_test::block_on( async {
+    /// # let mock = lava_api_mock::doctest::devices(|state| {
+    /// #     state.add_devices(2, |_, device| device.health = DeviceHealth::Bad);
+    /// #     state.add_devices(3, |_, device| device.health = DeviceHealth::Good);
+    /// # })
+    /// # .await;
+    /// # let service_uri = mock.uri();
+    /// let lava = Lava::new(&service_uri, None).expect("failed to make lava");
+    ///
+    /// let devices: Vec<_> = lava
+    ///     .devices_builder()
+    ///     .health(Health::Bad)
+    ///     .query()
+    ///     .try_collect()
+    ///     .await
+    ///     .expect("failed to query devices");
+    /// assert_eq!(devices.len(), 2);
+    /// assert!(devices.iter().all(|device| device.health == Health::Bad));
+    /// # });
+    /// ```
+    pub fn health(mut self, health: Health) -> Self {
+        self.healths.include(health);
+        self
+    }
+
+    /// Exclude devices with this health.
+    ///
+    /// Example:
+    /// ```rust
+    /// use futures::stream::TryStreamExt;
+    /// # use lava_api_mock::DeviceHealth;
+    /// use lava_api::{Lava, device::Health};
+    /// #
+    /// # tokio_test::block_on( async {
+    /// # let mock = lava_api_mock::doctest::devices(|state| {
+    /// #     state.add_devices(2, |_, device| device.health = DeviceHealth::Bad);
+    /// #     state.add_devices(3, |_, device| device.health = DeviceHealth::Good);
+    /// # })
+    /// # .await;
+    /// # let service_uri = mock.uri();
+    /// let lava = Lava::new(&service_uri, None).expect("failed to make lava");
+    ///
+    /// let devices: Vec<_> = lava
+    ///     .devices_builder()
+    ///     .health_not(Health::Bad)
+    ///     .query()
+    ///     .try_collect()
+    ///     .await
+    ///     .expect("failed to query devices");
+    /// assert_eq!(devices.len(), 3);
+    /// assert!(devices.iter().all(|device| device.health != Health::Bad));
+    /// # });
+    /// ```
+    pub fn health_not(mut self, health: Health) -> Self {
+        self.healths.exclude(&health);
+        self
+    }
+
+    /// Return devices in this state.
+    ///
+    /// Example:
+    /// ```rust
+    /// use futures::stream::TryStreamExt;
+    /// # use lava_api_mock::DeviceState;
+    /// use lava_api::{Lava, device::State};
+    /// #
+    /// # tokio_test::block_on( async {
+    /// # let mock = lava_api_mock::doctest::devices(|state| {
+    /// #     state.add_devices(1, |_, device| device.state = DeviceState::Running);
+    /// #     state.add_devices(2, |_, device| device.state = DeviceState::Idle);
+    /// # })
+    /// # .await;
+    /// # let service_uri = mock.uri();
+    /// let lava = Lava::new(&service_uri, None).expect("failed to make lava");
+    ///
+    /// let devices: Vec<_> = lava
+    ///     .devices_builder()
+    ///     .state(State::Running)
+    ///     .query()
+    ///     .try_collect()
+    ///     .await
+    ///     .expect("failed to query devices");
+    /// assert_eq!(devices.len(), 1);
+    /// assert!(devices.iter().all(|device| device.state == State::Running));
+    /// # });
+    /// ```
+    pub fn state(mut self, state: State) -> Self {
+        self.states.include(state);
+        self
+    }
+
+    /// Exclude devices in this state.
+    ///
+    /// Example:
+    /// ```rust
+    /// use futures::stream::TryStreamExt;
+    /// # use lava_api_mock::DeviceState;
+    /// use lava_api::{Lava, device::State};
+    /// #
+    /// # tokio_test::block_on( async {
+    /// # let mock = lava_api_mock::doctest::devices(|state| {
+    /// #     state.add_devices(1, |_, device| device.state = DeviceState::Running);
+    /// #     state.add_devices(2, |_, device| device.state = DeviceState::Idle);
+    /// # })
+    /// # .await;
+    /// # let service_uri = mock.uri();
+    /// let lava = Lava::new(&service_uri, None).expect("failed to make lava");
+    ///
+    /// let devices: Vec<_> = lava
+    ///     .devices_builder()
+    ///     .state_not(State::Idle)
+    ///     .query()
+    ///     .try_collect()
+    ///     .await
+    ///     .expect("failed to query devices");
+    /// assert_eq!(devices.len(), 1);
+    /// assert!(devices.iter().all(|device| device.state != State::Idle));
+    /// # });
+    /// ```
+    pub fn state_not(mut self, state: State) -> Self {
+        self.states.exclude(&state);
+        self
+    }
+
+    /// Return devices of the named device type.
+    ///
+    /// If called more than once, devices of any of the given types
+    /// are returned.
+    ///
+    /// Example:
+    /// ```rust
+    /// use futures::stream::TryStreamExt;
+    /// use lava_api::Lava;
+    /// #
+    /// # tokio_test::block_on( async {
+    /// # let mock = lava_api_mock::doctest::devices(|state| {
+    /// #     state.add_devices(10, |_, _| {});
+    /// # })
+    /// # .await;
+    /// # let service_uri = mock.uri();
+    /// let lava = Lava::new(&service_uri, None).expect("failed to make lava");
+    ///
+    /// let first = lava
+    ///     .devices()
+    ///     .try_next()
+    ///     .await
+    ///     .expect("failed to query devices")
+    ///     .expect("no devices");
+    ///
+    /// let devices: Vec<_> = lava
+    ///     .devices_builder()
+    ///     .device_type(&first.device_type)
+    ///     .query()
+    ///     .try_collect()
+    ///     .await
+    ///     .expect("failed to query devices");
+    /// assert!(devices.contains(&first));
+    /// assert!(devices.iter().all(|device| device.device_type == first.device_type));
+    /// # });
+    /// ```
+    pub fn device_type<T: Into<String>>(mut self, device_type: T) -> Self {
+        self.device_types.include(device_type.into());
+        self
+    }
+
+    /// Return devices attached to the named worker.
+    ///
+    /// If called more than once, devices attached to any of the given
+    /// workers are returned.
+    ///
+    /// Example:
+    /// ```rust
+    /// use futures::stream::TryStreamExt;
+    /// use lava_api::Lava;
+    /// #
+    /// # tokio_test::block_on( async {
+    /// # let mock = lava_api_mock::doctest::devices(|state| {
+    /// #     state.add_devices(10, |_, _| {});
+    /// # })
+    /// # .await;
+    /// # let service_uri = mock.uri();
+    /// let lava = Lava::new(&service_uri, None).expect("failed to make lava");
+    ///
+    /// let first = lava
+    ///     .devices()
+    ///     .try_next()
+    ///     .await
+    ///     .expect("failed to query devices")
+    ///     .expect("no devices");
+    ///
+    /// let devices: Vec<_> = lava
+    ///     .devices_builder()
+    ///     .worker_host(&first.worker_host)
+    ///     .query()
+    ///     .try_collect()
+    ///     .await
+    ///     .expect("failed to query devices");
+    /// assert!(devices.contains(&first));
+    /// assert!(devices.iter().all(|device| device.worker_host == first.worker_host));
+    /// # });
+    /// ```
+    pub fn worker_host<T: Into<String>>(mut self, worker_host: T) -> Self {
+        self.worker_hosts.include(worker_host.into());
+        self
+    }
+
+    /// Return only devices whose hostname starts with `prefix`.
+    ///
+    /// Example:
+    /// ```rust
+    /// use futures::stream::TryStreamExt;
+    /// use lava_api::Lava;
+    /// #
+    /// # tokio_test::block_on( async {
+    /// # let mock = lava_api_mock::doctest::devices(|state| {
+    /// #     state.add_devices(2, |i, device| device.hostname = format!("rack1-{}", i));
+    /// #     state.add_devices(3, |i, device| device.hostname = format!("rack2-{}", i));
+    /// # })
+    /// # .await;
+    /// # let service_uri = mock.uri();
+    /// let lava = Lava::new(&service_uri, None).expect("failed to make lava");
+    ///
+    /// let devices: Vec<_> = lava
+    ///     .devices_builder()
+    ///     .hostname_prefix("rack1-")
+    ///     .query()
+    ///     .try_collect()
+    ///     .await
+    ///     .expect("failed to query devices");
+    /// let hostnames: Vec<_> = devices.iter().map(|device| device.hostname.as_str()).collect();
+    /// assert_eq!(hostnames, vec!["rack1-0", "rack1-1"]);
+    /// # });
+    /// ```
+    pub fn hostname_prefix<T: Into<String>>(mut self, prefix: T) -> Self {
+        self.hostname_prefix = Some(prefix.into());
+        self
+    }
+
+    /// Return devices carrying the given tag, by name or by id.
+    ///
+    /// If called more than once, devices carrying any of the given
+    /// tags are returned. Tags given by name and tags given by id are
+    /// matched separately, so giving both returns only devices
+    /// carrying one of each. Use [`tags_all`](Self::tags_all) to
+    /// require several tags at once.
+    ///
+    /// Example:
+    /// ```rust
+    /// use futures::stream::TryStreamExt;
+    /// # use boulder::{BuildableWithPersianRug, BuilderWithPersianRug};
+    /// # use lava_api_mock::{State, Tag};
+    /// # use persian_rug::Proxy;
+    /// use lava_api::Lava;
+    /// #
+    /// # tokio_test::block_on( async {
+    /// # let mock = lava_api_mock::doctest::devices(|state| {
+    /// #     let (fast, _) = Proxy::<Tag<State>>::builder().id(100).name("fast").build(state.mutate());
+    /// #     state.add_devices(2, |_, device| device.tags = vec![fast]);
+    /// #     state.add_devices(3, |_, device| device.tags.clear());
+    /// # })
+    /// # .await;
+    /// # let service_uri = mock.uri();
+    /// let lava = Lava::new(&service_uri, None).expect("failed to make lava");
+    ///
+    /// let devices: Vec<_> = lava
+    ///     .devices_builder()
+    ///     .tag("fast")
+    ///     .query()
+    ///     .try_collect()
+    ///     .await
+    ///     .expect("failed to query devices");
+    /// assert_eq!(devices.len(), 2);
+    /// assert!(devices.iter().all(|device| device.tags.iter().any(|tag| tag.name == "fast")));
+    /// # });
+    /// ```
+    pub fn tag<T: Into<TagRef>>(mut self, tag: T) -> Self {
+        self.tags.any(tag.into());
+        self
+    }
+
+    /// Return only devices carrying every one of the given tags, by
+    /// name or by id.
+    ///
+    /// The server can only check one of the tags, so the devices it
+    /// returns are checked for the others as they are read. Devices
+    /// dropped this way still count towards
+    /// [`Devices::reported_items`].
+    ///
+    /// Example:
+    /// ```rust
+    /// use futures::stream::TryStreamExt;
+    /// # use boulder::{BuildableWithPersianRug, BuilderWithPersianRug};
+    /// # use lava_api_mock::{State, Tag};
+    /// # use persian_rug::Proxy;
+    /// use lava_api::Lava;
+    /// #
+    /// # tokio_test::block_on( async {
+    /// # let mock = lava_api_mock::doctest::devices(|state| {
+    /// #     let (fast, _) = Proxy::<Tag<State>>::builder().id(100).name("fast").build(state.mutate());
+    /// #     let (usb, _) = Proxy::<Tag<State>>::builder().id(101).name("usb").build(state.mutate());
+    /// #     state.add_devices(2, |_, device| device.tags = vec![fast, usb]);
+    /// #     state.add_devices(3, |_, device| device.tags = vec![fast]);
+    /// # })
+    /// # .await;
+    /// # let service_uri = mock.uri();
+    /// let lava = Lava::new(&service_uri, None).expect("failed to make lava");
+    ///
+    /// let devices: Vec<_> = lava
+    ///     .devices_builder()
+    ///     .tags_all(["fast", "usb"])
+    ///     .query()
+    ///     .try_collect()
+    ///     .await
+    ///     .expect("failed to query devices");
+    /// assert_eq!(devices.len(), 2);
+    /// # });
+    /// ```
+    pub fn tags_all<I, T>(mut self, tags: I) -> Self
+    where
+        I: IntoIterator<Item = T>,
+        T: Into<TagRef>,
+    {
+        for tag in tags {
+            self.tags.all(tag.into());
+        }
+        self
+    }
+
+    /// Set the number of devices retrieved at a time while the query
+    /// is running.
+    ///
+    /// This is a page size, and has the same caveats as
+    /// [`JobsBuilder::limit`](crate::job::JobsBuilder::limit).
+    pub fn limit(mut self, limit: u32) -> Self {
+        self.limit = Some(limit);
+        self
+    }
+
+    /// Add a query parameter which is passed to the server unchanged.
+    ///
+    /// This gives access to filters the server supports which are
+    /// not otherwise available here, for example
+    /// `description__contains`, with the same caveats as for
+    /// [`JobsBuilder::raw_param`](crate::job::JobsBuilder::raw_param).
+    ///
+    /// Example:
+    /// ```rust
+    /// use futures::stream::TryStreamExt;
+    /// use lava_api::Lava;
+    /// #
+    /// # tokio_test::block_on( async {
+    /// # let mock = lava_api_mock::doctest::devices(|state| {
+    /// #     state.add_devices(2, |_, device| device.description = Some("Bench 1".to_string()));
+    /// #     state.add_devices(1, |_, device| device.description = Some("Bench 2".to_string()));
+    /// # })
+    /// # .await;
+    /// # let service_uri = mock.uri();
+    /// let lava = Lava::new(&service_uri, None).expect("failed to make lava");
+    ///
+    /// let devices: Vec<_> = lava
+    ///     .devices_builder()
+    ///     .raw_param("description__contains", "Bench 1")
+    ///     .query()
+    ///     .try_collect()
+    ///     .await
+    ///     .expect("failed to query devices");
+    /// assert_eq!(devices.len(), 2);
+    /// # });
+    /// ```
+    pub fn raw_param<K: Into<String>, V: Into<String>>(mut self, key: K, value: V) -> Self {
+        self.raw_params.push((key.into(), value.into()));
+        self
+    }
+
+    /// Order returned devices by the given key.
+    pub fn ordering(mut self, ordering: Ordering, ascending: bool) -> Self {
+        self.ordering = ordering;
+        self.ascending = ascending;
+        self
+    }
+
+    /// Apply the settings from a [`DevicesQueryConfig`].
+    ///
+    /// Settings in the configuration are added to those already
+    /// present, in the same way as calling the corresponding methods
+    /// would.
+    pub fn apply(mut self, config: &DevicesQueryConfig) -> Self {
+        for health in config.healths.iter() {
+            self = self.health(*health);
+        }
+        for health in config.health_not.iter() {
+            self = self.health_not(*health);
+        }
+        for state in config.states.iter() {
+            self = self.state(*state);
+        }
+        for state in config.state_not.iter() {
+            self = self.state_not(*state);
+        }
+        for device_type in config.device_types.iter() {
+            self = self.device_type(device_type);
+        }
+        for worker_host in config.worker_hosts.iter() {
+            self = self.worker_host(worker_host);
+        }
+        if let Some(prefix) = &config.hostname_prefix {
+            self = self.hostname_prefix(prefix);
+        }
+        for tag in config.tags.iter() {
+            self = self.tag(tag);
+        }
+        self = self.tags_all(config.tags_all.iter());
+        if let Some(limit) = config.limit {
+            self = self.limit(limit);
+        }
+        if let Some(ordering) = config.ordering {
+            self = self.ordering(ordering, !config.descending);
+        }
+        for (key, value) in config.raw_params.iter() {
+            self = self.raw_param(key, value);
+        }
+        self
+    }
+
+    /// Begin querying for devices, returning a [`Devices`] instance
+    pub fn query(self) -> Devices<'a> {
+        let paginator = self.lava.paginator(self.url());
+        Devices {
+            lava: self.lava,
             paginator,
-            state: State::Paging,
+            state: PagingState::Paging,
+            yielded: 0,
+            page: 0,
+            tags: HashMap::new(),
+            pending: None,
+            required: self.tags,
+        }
+    }
+
+    fn url(&self) -> Url {
+        let mut url = self
+            .lava
+            .base
+            .join("devices/")
+            .expect("Failed to append to base url");
+        url.query_pairs_mut().append_pair(
+            "ordering",
+            &format!(
+                "{}{}",
+                match self.ascending {
+                    true => "",
+                    false => "-",
+                },
+                self.ordering
+            ),
+        );
+        if let Some(pair) = self.healths.query() {
+            url.query_pairs_mut().append_pair(&pair.0, &pair.1);
+        }
+        if let Some(pair) = self.states.query() {
+            url.query_pairs_mut().append_pair(&pair.0, &pair.1);
+        }
+        self.device_types.append_to(&mut url);
+        self.worker_hosts.append_to(&mut url);
+        if let Some(prefix) = &self.hostname_prefix {
+            url.query_pairs_mut()
+                .append_pair("hostname__startswith", prefix);
         }
+        self.tags.append_to(&mut url);
+        if let Some(limit) = self.limit {
+            url.query_pairs_mut()
+                .append_pair("limit", &limit.to_string());
+        };
+        for (key, value) in self.raw_params.iter() {
+            url.query_pairs_mut().append_pair(key, value);
+        }
+        url
     }
 }
 
-async fn transform_device(device: LavaDevice, lava: &Lava) -> Device {
-    let t = stream::iter(device.tags.iter());
-    let tags = t
-        .filter_map(|i| async move { lava.tag(*i).await })
-        .collect()
-        .await;
+/// Settings for a [`DevicesBuilder`] which can be loaded from a
+/// configuration file.
+///
+/// Every field is optional, and the settings are applied with
+/// [`DevicesBuilder::apply`].
+///
+/// Example:
+/// ```rust
+/// use lava_api::device::DevicesQueryConfig;
+///
+/// let config: DevicesQueryConfig = serde_yaml::from_str(
+///     r#"
+/// health_not: [Retired]
+/// device_types: [qemu, rk3399-gru-kevin]
+/// ordering: worker_host
+/// "#,
+/// )
+/// .expect("failed to parse config");
+/// ```
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Eq)]
+#[serde(default, deny_unknown_fields)]
+pub struct DevicesQueryConfig {
+    /// Return devices with these healths
+    pub healths: Vec<Health>,
+    /// Exclude devices with these healths
+    pub health_not: Vec<Health>,
+    /// Return devices in these states
+    pub states: Vec<State>,
+    /// Exclude devices in these states
+    pub state_not: Vec<State>,
+    pub device_types: Vec<String>,
+    pub worker_hosts: Vec<String>,
+    pub hostname_prefix: Option<String>,
+    /// Return devices carrying any of these tags
+    pub tags: Vec<String>,
+    /// Return only devices carrying all of these tags
+    pub tags_all: Vec<String>,
+    pub limit: Option<u32>,
+    pub ordering: Option<Ordering>,
+    /// Reverse the [`ordering`](DevicesQueryConfig::ordering)
+    pub descending: bool,
+    /// Query parameters passed to the server unchanged; see
+    /// [`DevicesBuilder::raw_param`]
+    pub raw_params: BTreeMap<String, String>,
+}
+
+fn transform_device(device: LavaDevice, tags: &HashMap<u32, Tag>) -> Device {
+    let tags = device
+        .tags
+        .iter()
+        .filter_map(|id| tags.get(id).cloned())
+        .collect();
 
     Device {
         hostname: device.hostname,
         worker_host: device.worker_host,
         device_type: device.device_type,
         description: device.description,
+        device_version: device.device_version,
+        physical_owner: device.physical_owner,
+        physical_group: device.physical_group,
+        state: device.state,
         health: device.health,
         tags,
+        is_synced: device.is_synced,
+        last_health_report_job: device.last_health_report_job,
     }
 }
 
@@ -108,22 +851,45 @@ impl<'a> Stream for Devices<'a> {
 
         loop {
             return match &mut me.state {
-                State::Paging => {
+                PagingState::Paging => {
                     let p = Pin::new(&mut me.paginator);
                     match p.poll_next(cx) {
                         Poll::Ready(None) => Poll::Ready(None),
                         Poll::Ready(Some(Err(e))) => Poll::Ready(Some(Err(e))),
-                        Poll::Ready(Some(Ok(d))) => {
-                            me.state = State::Transforming(transform_device(d, me.lava).boxed());
+                        Poll::Ready(Some(Ok(d))) if me.page != me.paginator.fetched_pages() => {
+                            // Resolve the tags of the whole page at
+                            // once, as for jobs.
+                            me.page = me.paginator.fetched_pages();
+                            let ids = std::iter::once(&d)
+                                .chain(me.paginator.buffered_items())
+                                .flat_map(|d| d.tags.iter().copied())
+                                .collect::<HashSet<_>>();
+                            me.state = PagingState::Resolving(me.lava.tags_by_id(ids).boxed());
+                            me.pending = Some(d);
                             continue;
                         }
+                        Poll::Ready(Some(Ok(d))) => {
+                            let device = transform_device(d, &me.tags);
+                            if !me.required.carried_by(&device.tags) {
+                                continue;
+                            }
+                            me.yielded += 1;
+                            Poll::Ready(Some(Ok(device)))
+                        }
                         Poll::Pending => Poll::Pending,
                     }
                 }
-                State::Transforming(fut) => match fut.as_mut().poll(cx) {
-                    Poll::Ready(d) => {
-                        me.state = State::Paging;
-                        Poll::Ready(Some(Ok(d)))
+                PagingState::Resolving(fut) => match fut.as_mut().poll(cx) {
+                    Poll::Ready(tags) => {
+                        let d = me.pending.take().unwrap();
+                        me.tags = tags;
+                        me.state = PagingState::Paging;
+                        let device = transform_device(d, &me.tags);
+                        if !me.required.carried_by(&device.tags) {
+                            continue;
+                        }
+                        me.yielded += 1;
+                        Poll::Ready(Some(Ok(device)))
                     }
                     Poll::Pending => Poll::Pending,
                 },
@@ -132,60 +898,328 @@ impl<'a> Stream for Devices<'a> {
     }
 }
 
+/// The number of devices carrying all of a combination of tags, as
+/// computed by [`device_counts_by_tags`]
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct TagCombinationCount {
+    /// The names of the tags in the combination
+    pub tags: Vec<String>,
+    /// The number of devices carrying every tag in the combination
+    pub devices: u32,
+    /// How many of those devices have [`Health::Good`] or
+    /// [`Health::Unknown`], and so can currently run jobs
+    pub schedulable: u32,
+}
+
+/// Count the devices carrying all of the tags in each of the given
+/// combinations.
+///
+/// The server can only filter devices by one tag at a time, so for
+/// each combination this asks the server for the devices carrying
+/// its first tag, and checks the remaining tags on the client.
+/// Combinations with the same first tag share a query. An empty
+/// combination counts every device.
+///
+/// Example:
+/// ```rust
+/// # use lava_api_mock::{LavaMock, PaginationLimits, PopulationParams, SharedState};
+/// use lava_api::{Lava, device::device_counts_by_tags};
+/// #
+/// # tokio_test::block_on( async {
+/// # let limits = PaginationLimits::new();
+/// # let population = PopulationParams::new();
+/// # let mock = LavaMock::new(SharedState::new_populated(population), limits).await;
+/// # let service_uri = mock.uri();
+/// # let lava_token = None;
+///
+/// let lava = Lava::new(&service_uri, lava_token).expect("failed to make lava");
+///
+/// let counts = device_counts_by_tags(&lava, &[&["hdmi", "usb-otg"], &["hdmi"]])
+///     .await
+///     .expect("failed to count devices");
+/// for count in counts {
+///     println!("{:?}: {} devices", count.tags, count.devices);
+/// }
+/// # });
+/// ```
+pub async fn device_counts_by_tags(
+    lava: &Lava,
+    combinations: &[&[&str]],
+) -> Result<Vec<TagCombinationCount>, PaginationError> {
+    let mut candidates: HashMap<Option<&str>, Vec<Device>> = HashMap::new();
+    let mut counts = Vec::new();
+
+    for combination in combinations {
+        let first = combination.first().copied();
+        if let Entry::Vacant(entry) = candidates.entry(first) {
+            let mut builder = DevicesBuilder::new(lava);
+            if let Some(first) = first {
+                builder = builder.tag(first);
+            }
+            entry.insert(builder.query().try_collect().await?);
+        }
+
+        let mut count = TagCombinationCount {
+            tags: combination.iter().map(|t| t.to_string()).collect(),
+            devices: 0,
+            schedulable: 0,
+        };
+        for device in candidates[&first].iter().filter(|d| {
+            combination
+                .iter()
+                .all(|name| d.tags.iter().any(|t| t.name == *name))
+        }) {
+            count.devices += 1;
+            if matches!(device.health, Health::Good | Health::Unknown) {
+                count.schedulable += 1;
+            }
+        }
+        counts.push(count);
+    }
+
+    Ok(counts)
+}
+
+#[derive(Error, Debug)]
+pub enum DeviceHealthError {
+    #[error("Device health request failed")]
+    Request(#[from] reqwest::Error),
+    #[error("Invalid health change: {0}")]
+    InvalidHealth(String),
+    #[error("Not permitted to change device health")]
+    PermissionDenied,
+    #[error("Device not found")]
+    NotFound,
+    #[error("Unexpected reply to device health request: {0}")]
+    UnexpectedReply(reqwest::StatusCode),
+}
+
+impl Classify for DeviceHealthError {
+    fn class(&self) -> ErrorClass {
+        match self {
+            DeviceHealthError::Request(e) => e.class(),
+            DeviceHealthError::InvalidHealth(_) => ErrorClass::Invalid,
+            DeviceHealthError::PermissionDenied => ErrorClass::Auth,
+            DeviceHealthError::NotFound => ErrorClass::NotFound,
+            DeviceHealthError::UnexpectedReply(s) => s.class(),
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct HealthUpdate<'a> {
+    health: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    reason: Option<&'a str>,
+}
+
+/// Set the health of the device with the given hostname, for example
+/// to take it into or out of [`Health::Maintenance`].
+///
+/// Changing devices requires a token for a user with permission to
+/// change them, usually an administrator. The `reason` is sent along
+/// with the change for the server to record; servers which do not
+/// record one ignore it. Setting a device to [`Health::Unknown`] asks
+/// the server to run a health check on it before scheduling any
+/// other jobs.
+pub async fn set_device_health(
+    lava: &Lava,
+    hostname: &str,
+    health: Health,
+    reason: Option<&str>,
+) -> Result<(), DeviceHealthError> {
+    let mut url = lava.base.clone();
+    url.path_segments_mut()
+        .unwrap()
+        .pop_if_empty()
+        .push("devices")
+        .push(hostname)
+        .push("");
+    let update = HealthUpdate {
+        health: health.to_string(),
+        reason,
+    };
+
+    let res = lava
+        .transport
+        .execute(transport::patch_json(url, &update))
+        .await?;
+
+    match res.status() {
+        StatusCode::OK | StatusCode::NO_CONTENT => Ok(()),
+        StatusCode::BAD_REQUEST => Err(DeviceHealthError::InvalidHealth(res.text().await?)),
+        StatusCode::UNAUTHORIZED | StatusCode::FORBIDDEN => {
+            Err(DeviceHealthError::PermissionDenied)
+        }
+        StatusCode::NOT_FOUND => Err(DeviceHealthError::NotFound),
+        s => Err(DeviceHealthError::UnexpectedReply(s)),
+    }
+}
+
+#[derive(Error, Debug)]
+pub enum DeviceTagError {
+    #[error("Device tag request failed")]
+    Request(#[from] reqwest::Error),
+    #[error("Failed to read tags or device")]
+    Query(#[from] PaginationError),
+    #[error("Failed to create tag")]
+    CreateTag(#[from] TagError),
+    #[error("Invalid tag change: {0}")]
+    InvalidTags(String),
+    #[error("Not permitted to change device tags")]
+    PermissionDenied,
+    #[error("Device not found")]
+    NotFound,
+    #[error("Unexpected reply to device tag request: {0}")]
+    UnexpectedReply(reqwest::StatusCode),
+}
+
+impl Classify for DeviceTagError {
+    fn class(&self) -> ErrorClass {
+        match self {
+            DeviceTagError::Request(e) => e.class(),
+            DeviceTagError::Query(e) => e.class(),
+            DeviceTagError::CreateTag(e) => e.class(),
+            DeviceTagError::InvalidTags(_) => ErrorClass::Invalid,
+            DeviceTagError::PermissionDenied => ErrorClass::Auth,
+            DeviceTagError::NotFound => ErrorClass::NotFound,
+            DeviceTagError::UnexpectedReply(s) => s.class(),
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct TagsUpdate {
+    tags: Vec<u32>,
+}
+
+// Find the id of the tag called `name`, refreshing the tag cache if
+// it is not already there.
+async fn tag_id(lava: &Lava, name: &str) -> Result<Option<u32>, PaginationError> {
+    let find = |tags: &HashMap<u32, Tag>| tags.values().find(|t| t.name == name).map(|t| t.id);
+    if let Some(id) = find(&*lava.tags.read().await) {
+        return Ok(Some(id));
+    }
+    lava.refresh_tags().await?;
+    Ok(find(&*lava.tags.read().await))
+}
+
+// Change the tags of the device with the given hostname with
+// `change`, which returns `false` if there is nothing to do.
+async fn update_device_tags<F>(lava: &Lava, hostname: &str, change: F) -> Result<(), DeviceTagError>
+where
+    F: FnOnce(&mut Vec<u32>) -> bool,
+{
+    let mut url = lava
+        .base
+        .join("devices/")
+        .expect("Failed to append to base url");
+    url.query_pairs_mut().append_pair("hostname", hostname);
+    let mut devices: Paginator<LavaDevice> = lava.paginator(url);
+    let mut tags = match devices.try_next().await? {
+        Some(device) => device.tags,
+        None => return Err(DeviceTagError::NotFound),
+    };
+    if !change(&mut tags) {
+        return Ok(());
+    }
+
+    let mut url = lava.base.clone();
+    url.path_segments_mut()
+        .unwrap()
+        .pop_if_empty()
+        .push("devices")
+        .push(hostname)
+        .push("");
+    let res = lava
+        .transport
+        .execute(transport::patch_json(url, &TagsUpdate { tags }))
+        .await?;
+
+    match res.status() {
+        StatusCode::OK | StatusCode::NO_CONTENT => Ok(()),
+        StatusCode::BAD_REQUEST => Err(DeviceTagError::InvalidTags(res.text().await?)),
+        StatusCode::UNAUTHORIZED | StatusCode::FORBIDDEN => Err(DeviceTagError::PermissionDenied),
+        StatusCode::NOT_FOUND => Err(DeviceTagError::NotFound),
+        s => Err(DeviceTagError::UnexpectedReply(s)),
+    }
+}
+
+/// Add the tag with the given name to the device with the given
+/// hostname, creating the tag if the server does not have it yet.
+///
+/// As for [`set_device_health`], this requires a token for a user
+/// with permission to change devices, and to create the tag if
+/// needed. Adding a tag the device already has does nothing. The
+/// server only allows the whole list of tags on a device to be
+/// replaced, so this reads the device's tags and then writes them
+/// back with the new one; a change made by someone else in between
+/// is lost.
+///
+/// The tag cache of `lava` is refreshed if it does not know the tag,
+/// and a new tag is added to it.
+pub async fn add_device_tag(lava: &Lava, hostname: &str, tag: &str) -> Result<(), DeviceTagError> {
+    let id = match tag_id(lava, tag).await? {
+        Some(id) => id,
+        None => create_tag(lava, tag, None).await?.id,
+    };
+    update_device_tags(lava, hostname, |tags| {
+        if tags.contains(&id) {
+            false
+        } else {
+            tags.push(id);
+            true
+        }
+    })
+    .await
+}
+
+/// Remove the tag with the given name from the device with the given
+/// hostname.
+///
+/// This has the same requirements and caveats as
+/// [`add_device_tag`]. Removing a tag the device does not have, or
+/// one the server does not know, does nothing; the tag itself is not
+/// deleted, even if no device has it any more.
+pub async fn remove_device_tag(
+    lava: &Lava,
+    hostname: &str,
+    tag: &str,
+) -> Result<(), DeviceTagError> {
+    let id = match tag_id(lava, tag).await? {
+        Some(id) => id,
+        None => return Ok(()),
+    };
+    update_device_tags(lava, hostname, |tags| {
+        let count = tags.len();
+        tags.retain(|t| *t != id);
+        tags.len() != count
+    })
+    .await
+}
+
 #[cfg(test)]
 mod tests {
-    use super::{Device, Health, Tag};
+    use super::{
+        device_counts_by_tags, DeviceHealthError, DeviceTagError, DevicesQueryConfig, Health,
+        Ordering, State as DeviceState,
+    };
     use crate::Lava;
 
-    use boulder::{Buildable, Builder};
+    use boulder::{
+        Buildable, BuildableWithPersianRug, Builder, BuilderWithPersianRug,
+        GeneratableWithPersianRug, GeneratorWithPersianRugMutIterator, Repeat,
+    };
     use futures::TryStreamExt;
     use lava_api_mock::{
-        Device as MockDevice, DeviceHealth as MockDeviceHealth, DeviceType as MockDeviceType,
+        Device as MockDevice, DeviceHealth as MockDeviceHealth, DeviceState as MockDeviceState,
         LavaMock, PaginationLimits, PopulationParams, SharedState, State, Tag as MockTag,
-        Worker as MockWorker,
+        User as MockUser,
     };
-    use persian_rug::{Accessor, Context};
+    use persian_rug::{Accessor, Proxy};
     use std::collections::BTreeMap;
-    use std::convert::{Infallible, TryFrom, TryInto};
     use test_log::test;
 
-    impl TryFrom<MockDeviceHealth> for Health {
-        type Error = Infallible;
-        fn try_from(dev: MockDeviceHealth) -> Result<Health, Self::Error> {
-            use Health::*;
-            match dev {
-                MockDeviceHealth::Unknown => Ok(Unknown),
-                MockDeviceHealth::Maintenance => Ok(Maintenance),
-                MockDeviceHealth::Good => Ok(Good),
-                MockDeviceHealth::Bad => Ok(Bad),
-                MockDeviceHealth::Looping => Ok(Looping),
-                MockDeviceHealth::Retired => Ok(Retired),
-            }
-        }
-    }
-
-    impl Device {
-        #[persian_rug::constraints(context = C, access(MockTag<C>, MockDeviceType<C>, MockWorker<C>))]
-        pub fn from_mock<'b, B, C>(dev: &MockDevice<C>, context: B) -> Device
-        where
-            B: 'b + Accessor<Context = C>,
-            C: Context + 'static,
-        {
-            Self {
-                hostname: dev.hostname.clone(),
-                worker_host: context.get(&dev.worker_host).hostname.clone(),
-                device_type: context.get(&dev.device_type).name.clone(),
-                description: dev.description.clone(),
-                health: dev.health.clone().try_into().unwrap(),
-                tags: dev
-                    .tags
-                    .iter()
-                    .map(|t| Tag::from_mock(context.get(t), context.clone()))
-                    .collect::<Vec<_>>(),
-            }
-        }
-    }
-
     /// Stream 50 devices with a page limit of 5 from the server
     /// checking that we correctly reconstruct their tags and that
     /// they are all accounted for (that pagination is handled
@@ -219,6 +1253,21 @@ mod tests {
             assert_eq!(device.worker_host, start.get(&dev.worker_host).hostname);
             assert_eq!(device.device_type, start.get(&dev.device_type).name);
             assert_eq!(device.description, dev.description);
+            assert_eq!(device.device_version, dev.device_version);
+            assert_eq!(
+                device.physical_owner,
+                dev.physical_owner.map(|u| start.get(&u).id)
+            );
+            assert_eq!(
+                device.physical_group,
+                dev.physical_group.map(|g| start.get(&g).id)
+            );
+            assert_eq!(device.is_synced, dev.is_synced);
+            assert_eq!(
+                device.last_health_report_job,
+                dev.last_health_report_job.map(|j| start.get(&j).id)
+            );
+            assert_eq!(device.state.to_string(), dev.state.to_string());
             assert_eq!(device.health.to_string(), dev.health.to_string());
 
             assert_eq!(device.tags.len(), dev.tags.len());
@@ -235,4 +1284,446 @@ mod tests {
         }
         assert_eq!(seen.len(), 50);
     }
+
+    /// Generate 30 devices with a spread of states and healths, and
+    /// check that each of the filters restricts the returned devices
+    /// appropriately.
+    #[test(tokio::test)]
+    async fn test_devices_builder() {
+        let mut server = LavaMock::new(
+            SharedState::new_populated(PopulationParams::builder().devices(0usize).build()),
+            PaginationLimits::builder().devices(Some(7)).build(),
+        )
+        .await;
+
+        let mut gen = Proxy::<MockDevice<State>>::generator()
+            .health(Repeat!(
+                MockDeviceHealth::Good,
+                MockDeviceHealth::Bad,
+                MockDeviceHealth::Maintenance
+            ))
+            .state(Repeat!(MockDeviceState::Idle, MockDeviceState::Running));
+
+        let _ = GeneratorWithPersianRugMutIterator::new(&mut gen, server.state_mut())
+            .take(30)
+            .collect::<Vec<_>>();
+
+        let lava = Lava::new(&server.uri(), None).expect("failed to make lava server");
+
+        let mut ld = lava.devices_builder().health(Health::Bad).query();
+        let mut count = 0;
+        while let Some(device) = ld.try_next().await.expect("failed to get device") {
+            assert_eq!(device.health, Health::Bad);
+            count += 1;
+        }
+        assert_eq!(count, 10);
+
+        let mut ld = lava.devices_builder().health_not(Health::Good).query();
+        let mut count = 0;
+        while let Some(device) = ld.try_next().await.expect("failed to get device") {
+            assert_ne!(device.health, Health::Good);
+            count += 1;
+        }
+        assert_eq!(count, 20);
+
+        let mut ld = lava
+            .devices_builder()
+            .state(DeviceState::Running)
+            .health(Health::Good)
+            .query();
+        let mut count = 0;
+        while let Some(device) = ld.try_next().await.expect("failed to get device") {
+            assert_eq!(device.state, DeviceState::Running);
+            assert_eq!(device.health, Health::Good);
+            count += 1;
+        }
+        assert_eq!(count, 5);
+
+        let mut ld = lava
+            .devices_builder()
+            .hostname_prefix("test-device-1")
+            .query();
+        let mut count = 0;
+        while let Some(device) = ld.try_next().await.expect("failed to get device") {
+            assert!(device.hostname.starts_with("test-device-1"));
+            count += 1;
+        }
+        assert_eq!(count, 11);
+
+        let (device_type, worker_host, tag) = {
+            let state = server.state();
+            let first = state
+                .get_iter::<MockDevice<State>>()
+                .next()
+                .expect("no devices");
+            (
+                state.get(&first.device_type).name.clone(),
+                state.get(&first.worker_host).hostname.clone(),
+                first.tags.first().map(|t| state.get(t).name.clone()),
+            )
+        };
+
+        let mut ld = lava.devices_builder().device_type(&device_type).query();
+        let mut count = 0;
+        while let Some(device) = ld.try_next().await.expect("failed to get device") {
+            assert_eq!(device.device_type, device_type);
+            count += 1;
+        }
+        assert!(count > 0);
+
+        let mut ld = lava.devices_builder().worker_host(&worker_host).query();
+        let mut count = 0;
+        while let Some(device) = ld.try_next().await.expect("failed to get device") {
+            assert_eq!(device.worker_host, worker_host);
+            count += 1;
+        }
+        assert!(count > 0);
+
+        if let Some(tag) = tag {
+            let mut ld = lava.devices_builder().tag(&tag).query();
+            let mut count = 0;
+            while let Some(device) = ld.try_next().await.expect("failed to get device") {
+                assert!(device.tags.iter().any(|t| t.name == tag));
+                count += 1;
+            }
+            assert!(count > 0);
+        }
+
+        let mut ld = lava
+            .devices_builder()
+            .ordering(Ordering::Hostname, false)
+            .limit(4)
+            .query();
+        let mut hostnames = Vec::new();
+        while let Some(device) = ld.try_next().await.expect("failed to get device") {
+            hostnames.push(device.hostname);
+        }
+        assert_eq!(hostnames.len(), 30);
+        let mut sorted = hostnames.clone();
+        sorted.sort();
+        sorted.reverse();
+        assert_eq!(hostnames, sorted);
+    }
+
+    /// Filter devices by tag name and id, for any and all of several
+    /// tags
+    #[test(tokio::test)]
+    async fn test_tag_filters() {
+        let mut state = SharedState::new_populated(
+            PopulationParams::builder()
+                .devices(0usize)
+                .jobs(0usize)
+                .build(),
+        );
+        let (fast, _) = Proxy::<MockTag<State>>::builder()
+            .id(100u32)
+            .name("fast")
+            .build(state.mutate());
+        let (usb, _) = Proxy::<MockTag<State>>::builder()
+            .id(101u32)
+            .name("usb")
+            .build(state.mutate());
+        state.add_devices(1, |_, device| device.tags = vec![fast, usb]);
+        state.add_devices(2, |_, device| device.tags = vec![fast]);
+        state.add_devices(3, |_, device| device.tags = vec![usb]);
+        state.add_devices(4, |_, device| device.tags.clear());
+        let server = LavaMock::new(state, PaginationLimits::new()).await;
+        let lava = Lava::new(&server.uri(), None).expect("failed to make lava server");
+
+        let count = |builder: super::DevicesBuilder<'_>| async move {
+            builder
+                .query()
+                .try_collect::<Vec<_>>()
+                .await
+                .expect("failed to query devices")
+                .len()
+        };
+
+        assert_eq!(count(lava.devices_builder().tag("fast")).await, 3);
+        assert_eq!(count(lava.devices_builder().tag(101u32)).await, 4);
+        assert_eq!(
+            count(lava.devices_builder().tag("fast").tag("usb")).await,
+            6
+        );
+        assert_eq!(
+            count(lava.devices_builder().tag(100u32).tag(101u32)).await,
+            6
+        );
+        assert_eq!(
+            count(lava.devices_builder().tags_all(["fast", "usb"])).await,
+            1
+        );
+        assert_eq!(
+            count(lava.devices_builder().tags_all([100u32, 101u32])).await,
+            1
+        );
+        assert_eq!(count(lava.devices_builder().tags_all(["usb"])).await, 4);
+    }
+
+    /// Count devices for several tag combinations, and check the
+    /// counts against the mock's own data
+    #[test(tokio::test)]
+    async fn test_counts_by_tags() {
+        let state = SharedState::new_populated(
+            PopulationParams::builder()
+                .devices(20usize)
+                .tags(5usize)
+                .build(),
+        );
+        let server = LavaMock::new(
+            state.clone(),
+            PaginationLimits::builder().devices(Some(6)).build(),
+        )
+        .await;
+
+        let start = state.access();
+        let names = start
+            .get_iter::<MockTag<State>>()
+            .map(|t| t.name.clone())
+            .collect::<Vec<_>>();
+        let names = names.iter().map(String::as_str).collect::<Vec<_>>();
+        let combinations = vec![
+            vec![names[0], names[1]],
+            vec![names[0]],
+            vec![names[1], names[2], names[3]],
+            vec![],
+            vec!["no-such-tag"],
+        ];
+        let combinations = combinations.iter().map(Vec::as_slice).collect::<Vec<_>>();
+
+        let lava = Lava::new(&server.uri(), None).expect("failed to make lava server");
+        let counts = device_counts_by_tags(&lava, &combinations)
+            .await
+            .expect("failed to count devices");
+        assert_eq!(counts.len(), combinations.len());
+
+        for (count, combination) in counts.iter().zip(combinations.iter()) {
+            assert_eq!(&count.tags, combination);
+            let matching = start
+                .get_iter::<MockDevice<State>>()
+                .filter(|d| {
+                    combination
+                        .iter()
+                        .all(|name| d.tags.iter().any(|t| start.get(t).name == *name))
+                })
+                .collect::<Vec<_>>();
+            assert_eq!(count.devices as usize, matching.len());
+            assert_eq!(
+                count.schedulable as usize,
+                matching
+                    .iter()
+                    .filter(|d| matches!(
+                        d.health,
+                        MockDeviceHealth::Good | MockDeviceHealth::Unknown
+                    ))
+                    .count()
+            );
+        }
+        assert_eq!(counts[3].devices, 20);
+        assert_eq!(counts[4].devices, 0);
+    }
+
+    /// Take a device into and out of maintenance, checking that only
+    /// superusers may do so, and that the mock records each change
+    #[test(tokio::test)]
+    async fn test_set_health() {
+        let mut state =
+            SharedState::new_populated(PopulationParams::builder().devices(0usize).build());
+        state.add_devices(1, |_, device| device.health = MockDeviceHealth::Good);
+        {
+            let m = state.mutate();
+            let (_, m) = Proxy::<MockUser<State>>::builder()
+                .username("admin")
+                .is_superuser(true)
+                .token(Some("admin-token".to_string()))
+                .build(m);
+            let _ = Proxy::<MockUser<State>>::builder()
+                .username("fred")
+                .token(Some("fred-token".to_string()))
+                .build(m);
+        }
+        let server = LavaMock::new(state.clone(), PaginationLimits::new()).await;
+        let health = || {
+            state
+                .access()
+                .get_iter::<MockDevice<State>>()
+                .next()
+                .unwrap()
+                .health
+                .clone()
+        };
+
+        let fred = Lava::new(&server.uri(), Some("fred-token".to_string()))
+            .expect("failed to make lava server");
+        let err = fred
+            .set_device_health("test-device-0", Health::Maintenance, None)
+            .await
+            .expect_err("changed device health without permission");
+        assert!(matches!(err, DeviceHealthError::PermissionDenied));
+        assert_eq!(health(), MockDeviceHealth::Good);
+
+        let admin = Lava::new(&server.uri(), Some("admin-token".to_string()))
+            .expect("failed to make lava server");
+        admin
+            .set_device_health(
+                "test-device-0",
+                Health::Maintenance,
+                Some("replacing power supply"),
+            )
+            .await
+            .expect("failed to set device health");
+        assert_eq!(health(), MockDeviceHealth::Maintenance);
+        let device = admin
+            .devices()
+            .try_next()
+            .await
+            .expect("failed to get device")
+            .expect("no devices");
+        assert_eq!(device.health, Health::Maintenance);
+
+        admin
+            .set_device_health("test-device-0", Health::Unknown, None)
+            .await
+            .expect("failed to set device health");
+        assert_eq!(health(), MockDeviceHealth::Unknown);
+
+        let err = admin
+            .set_device_health("no-such-device", Health::Good, None)
+            .await
+            .expect_err("changed health of a missing device");
+        assert!(matches!(err, DeviceHealthError::NotFound));
+    }
+
+    /// Add and remove device tags, checking that missing tags are
+    /// created and that the mock records each change
+    #[test(tokio::test)]
+    async fn test_device_tags() {
+        let mut state = SharedState::new_populated(
+            PopulationParams::builder()
+                .devices(0usize)
+                .tags(0usize)
+                .build(),
+        );
+        state.add_devices(1, |_, device| device.tags = Vec::new());
+        {
+            let m = state.mutate();
+            let (_, m) = Proxy::<MockUser<State>>::builder()
+                .username("admin")
+                .is_superuser(true)
+                .token(Some("admin-token".to_string()))
+                .build(m);
+            let (_, m) = Proxy::<MockUser<State>>::builder()
+                .username("fred")
+                .token(Some("fred-token".to_string()))
+                .build(m);
+            let _ = Proxy::<MockTag<State>>::builder()
+                .id(1u32)
+                .name("hdmi")
+                .build(m);
+        }
+        let server = LavaMock::new(state.clone(), PaginationLimits::new()).await;
+        let tags = || {
+            let access = state.access();
+            let device = access.get_iter::<MockDevice<State>>().next().unwrap();
+            let mut names = device
+                .tags
+                .iter()
+                .map(|t| access.get(t).name.clone())
+                .collect::<Vec<_>>();
+            names.sort();
+            names
+        };
+
+        let fred = Lava::new(&server.uri(), Some("fred-token".to_string()))
+            .expect("failed to make lava server");
+        let err = fred
+            .add_device_tag("test-device-0", "hdmi")
+            .await
+            .expect_err("changed device tags without permission");
+        assert!(matches!(err, DeviceTagError::PermissionDenied));
+        assert!(tags().is_empty());
+
+        let admin = Lava::new(&server.uri(), Some("admin-token".to_string()))
+            .expect("failed to make lava server");
+        admin
+            .add_device_tag("test-device-0", "hdmi")
+            .await
+            .expect("failed to add device tag");
+        assert_eq!(tags(), vec!["hdmi"]);
+        admin
+            .add_device_tag("test-device-0", "hdmi")
+            .await
+            .expect("failed to add device tag twice");
+        assert_eq!(tags(), vec!["hdmi"]);
+
+        admin
+            .add_device_tag("test-device-0", "usb-otg")
+            .await
+            .expect("failed to add new device tag");
+        assert_eq!(tags(), vec!["hdmi", "usb-otg"]);
+        let device = admin
+            .devices()
+            .try_next()
+            .await
+            .expect("failed to get device")
+            .expect("no devices");
+        let mut names = device.tags.into_iter().map(|t| t.name).collect::<Vec<_>>();
+        names.sort();
+        assert_eq!(names, vec!["hdmi", "usb-otg"]);
+
+        admin
+            .remove_device_tag("test-device-0", "hdmi")
+            .await
+            .expect("failed to remove device tag");
+        assert_eq!(tags(), vec!["usb-otg"]);
+        admin
+            .remove_device_tag("test-device-0", "no-such-tag")
+            .await
+            .expect("failed to remove unknown tag");
+        assert_eq!(tags(), vec!["usb-otg"]);
+
+        let err = admin
+            .add_device_tag("no-such-device", "hdmi")
+            .await
+            .expect_err("changed tags of a missing device");
+        assert!(matches!(err, DeviceTagError::NotFound));
+    }
+
+    #[test]
+    fn test_query_config() {
+        let config: DevicesQueryConfig = serde_yaml::from_str(
+            r#"
+healths: [Good]
+state_not: [Reserved]
+device_types: [qemu]
+hostname_prefix: lab-
+tags_all: [fast, usb]
+ordering: worker_host
+descending: true
+raw_params:
+  device_version: "2"
+"#,
+        )
+        .expect("failed to parse config");
+
+        assert_eq!(config.healths, vec![Health::Good]);
+        assert_eq!(config.state_not, vec![DeviceState::Reserved]);
+        assert_eq!(config.device_types, vec!["qemu".to_string()]);
+        assert_eq!(config.hostname_prefix.as_deref(), Some("lab-"));
+        assert_eq!(config.ordering, Some(Ordering::WorkerHost));
+        assert!(config.descending);
+        assert!(config.tags.is_empty());
+
+        assert!(serde_yaml::from_str::<DevicesQueryConfig>("bogus: 1").is_err());
+
+        let lava = Lava::new("http://localhost/", None).expect("failed to make lava");
+        let url = lava.devices_builder().apply(&config).url().to_string();
+        assert!(url.contains("ordering=-worker_host"));
+        assert!(url.contains("health=Good"));
+        assert!(url.contains("state__in="));
+        assert!(url.contains("device_type__name=qemu"));
+        assert!(url.contains("hostname__startswith=lab-"));
+        assert!(url.contains("tags__name=fast&tags__name=usb"));
+        assert!(url.ends_with("&device_version=2"));
+    }
 }
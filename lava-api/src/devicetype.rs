@@ -0,0 +1,413 @@
+//! Retrieve device types
+
+use serde::{Deserialize, Serialize};
+use serde_with::{DeserializeFromStr, SerializeDisplay};
+use strum::{Display, EnumString};
+use url::Url;
+
+use crate::paginator::Paginator;
+use crate::queryset::ValueSet;
+use crate::Lava;
+
+/// The units of [`health_frequency`](DeviceType::health_frequency)
+#[derive(
+    Copy, Clone, Debug, DeserializeFromStr, Display, EnumString, PartialEq, Eq, SerializeDisplay,
+)]
+#[strum(serialize_all = "snake_case")]
+pub enum HealthDenominator {
+    Hours,
+    Jobs,
+}
+
+/// An alternative name for a device type from the LAVA API
+///
+/// Aliases are listed by name in the
+/// [`aliases`](DeviceType::aliases) of the device types they refer
+/// to. Use [`Lava::resolve_device_type`](crate::Lava::resolve_device_type)
+/// to find the device type for an alias.
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq, Eq)]
+pub struct Alias {
+    pub name: String,
+}
+
+/// The data available for a device type from the LAVA API
+///
+/// Note that the related objects (such as the
+/// [`architecture`](DeviceType::architecture) and the
+/// [`aliases`](DeviceType::aliases)) are given by name.
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq, Eq)]
+pub struct DeviceType {
+    pub name: String,
+    pub architecture: Option<String>,
+    pub processor: Option<String>,
+    pub cpu_model: Option<String>,
+    pub aliases: Vec<String>,
+    pub bits: Option<u64>,
+    pub cores: Vec<String>,
+    pub core_count: Option<u64>,
+    pub description: Option<String>,
+    /// How often devices of this type run a health check, in
+    /// [`health_denominator`](DeviceType::health_denominator) units
+    pub health_frequency: i64,
+    /// Whether health checks are turned off for devices of this type
+    pub disable_health_check: bool,
+    pub health_denominator: HealthDenominator,
+    /// Whether the device type is shown in the LAVA web interface
+    pub display: bool,
+}
+
+/// Select the device types to return from a query.
+///
+/// This is obtained from [`Lava::device_types_builder`], and its
+/// [`query`](DeviceTypesBuilder::query) method returns a stream of
+/// the matching device types.
+///
+/// Example:
+/// ```rust
+/// use futures::stream::TryStreamExt;
+/// # use lava_api_mock::{LavaMock, PaginationLimits, PopulationParams, SharedState};
+/// use lava_api::Lava;
+/// #
+/// # tokio_test::block_on( async {
+/// # let limits = PaginationLimits::new();
+/// # let population = PopulationParams::new();
+/// # let mock = LavaMock::new(SharedState::new_populated(population), limits).await;
+/// # let service_uri = mock.uri();
+/// # let lava_token = None;
+///
+/// let lava = Lava::new(&service_uri, lava_token).expect("failed to make lava");
+///
+/// let mut device_types = lava
+///     .device_types_builder()
+///     .name("qemu")
+///     .alias("qemu-aarch64")
+///     .query();
+///
+/// while let Some(dt) = device_types.try_next().await.expect("failed to get device type") {
+///     if dt.disable_health_check {
+///         println!("Health checks are disabled for {}", dt.name);
+///     } else {
+///         println!(
+///             "{} runs a health check every {} {}",
+///             dt.name, dt.health_frequency, dt.health_denominator
+///         );
+///     }
+/// }
+/// # });
+/// ```
+#[derive(Debug, Clone)]
+pub struct DeviceTypesBuilder<'a> {
+    lava: &'a Lava,
+    names: ValueSet<String>,
+    aliases: ValueSet<String>,
+    limit: Option<u32>,
+    raw_params: Vec<(String, String)>,
+}
+
+impl<'a> DeviceTypesBuilder<'a> {
+    /// Create a new [`DeviceTypesBuilder`]
+    ///
+    /// The default query is:
+    /// - no filtering
+    /// - default result pagination
+    pub fn new(lava: &'a Lava) -> Self {
+        Self {
+            lava,
+            names: ValueSet::new("name"),
+            aliases: ValueSet::new("aliases__name"),
+            limit: None,
+            raw_params: Vec::new(),
+        }
+    }
+
+    /// Return the device type with this name.
+    ///
+    /// If called more than once, device types with any of the given
+    /// names are returned.
+    pub fn name<T: Into<String>>(mut self, name: T) -> Self {
+        self.names.include(name.into());
+        self
+    }
+
+    /// Return device types with this alias.
+    ///
+    /// If called more than once, device types with any of the given
+    /// aliases are returned. Combined with
+    /// [`name`](DeviceTypesBuilder::name), only device types matching
+    /// both are returned; use
+    /// [`Lava::resolve_device_type`] to find the device type for
+    /// something which could be either.
+    pub fn alias<T: Into<String>>(mut self, alias: T) -> Self {
+        self.aliases.include(alias.into());
+        self
+    }
+
+    /// Set the number of device types retrieved at a time while the
+    /// query is running.
+    ///
+    /// This is a page size, and has the same caveats as
+    /// [`JobsBuilder::limit`](crate::job::JobsBuilder::limit).
+    pub fn limit(mut self, limit: u32) -> Self {
+        self.limit = Some(limit);
+        self
+    }
+
+    /// Add a query parameter which is passed to the server unchanged.
+    ///
+    /// This gives access to filters the server supports which are
+    /// not otherwise available here, for example
+    /// `disable_health_check`, with the same caveats as for
+    /// [`JobsBuilder::raw_param`](crate::job::JobsBuilder::raw_param).
+    pub fn raw_param<K: Into<String>, V: Into<String>>(mut self, key: K, value: V) -> Self {
+        self.raw_params.push((key.into(), value.into()));
+        self
+    }
+
+    /// Begin querying for device types, returning a stream of the
+    /// matching [`DeviceType`] instances
+    pub fn query(self) -> Paginator<DeviceType> {
+        self.lava.paginator(self.url())
+    }
+
+    fn url(&self) -> Url {
+        let mut url = self
+            .lava
+            .base
+            .join("devicetypes/")
+            .expect("Failed to append to base url");
+        self.names.append_to(&mut url);
+        self.aliases.append_to(&mut url);
+        if let Some(limit) = self.limit {
+            url.query_pairs_mut()
+                .append_pair("limit", &limit.to_string());
+        };
+        for (key, value) in self.raw_params.iter() {
+            url.query_pairs_mut().append_pair(key, value);
+        }
+        url
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::Lava;
+
+    use boulder::{Buildable, BuildableWithPersianRug, Builder, BuilderWithPersianRug};
+    use futures::TryStreamExt;
+    use lava_api_mock::{
+        Alias, DeviceType, LavaMock, PaginationLimits, PopulationParams, SharedState, State,
+    };
+    use persian_rug::{Accessor, Proxy};
+    use std::collections::BTreeMap;
+    use test_log::test;
+
+    /// Stream 23 device types with a page limit of 4 from the server
+    #[test(tokio::test)]
+    async fn test_basic() {
+        let state =
+            SharedState::new_populated(PopulationParams::builder().device_types(23usize).build());
+        let server = LavaMock::new(
+            state.clone(),
+            PaginationLimits::builder().device_types(Some(4)).build(),
+        )
+        .await;
+
+        let mut map = BTreeMap::new();
+        let start = state.access();
+        for dt in start.get_iter::<DeviceType<State>>() {
+            map.insert(dt.name.clone(), dt.clone());
+        }
+
+        let lava = Lava::new(&server.uri(), None).expect("failed to make lava server");
+
+        let mut ldt = lava.device_types();
+
+        let mut seen = BTreeMap::new();
+        while let Some(dt) = ldt.try_next().await.expect("failed to get device type") {
+            assert!(!seen.contains_key(&dt.name));
+            assert!(map.contains_key(&dt.name));
+            let dd = map.get(&dt.name).unwrap();
+            assert_eq!(
+                dt.architecture.as_ref(),
+                dd.architecture.as_ref().map(|a| &start.get(a).name)
+            );
+            assert_eq!(
+                dt.processor.as_ref(),
+                dd.processor.as_ref().map(|p| &start.get(p).name)
+            );
+            assert_eq!(dt.cpu_model, dd.cpu_model);
+            assert_eq!(dt.aliases.len(), dd.aliases.len());
+            for i in 0..dt.aliases.len() {
+                assert_eq!(dt.aliases[i], start.get(&dd.aliases[i]).name);
+            }
+            assert_eq!(dt.bits, dd.bits.as_ref().map(|b| start.get(b).width));
+            assert_eq!(dt.cores.len(), dd.cores.len());
+            for i in 0..dt.cores.len() {
+                assert_eq!(dt.cores[i], start.get(&dd.cores[i]).name);
+            }
+            assert_eq!(dt.core_count, dd.core_count);
+            assert_eq!(dt.description, dd.description);
+            assert_eq!(dt.health_frequency, dd.health_frequency);
+            assert_eq!(dt.disable_health_check, dd.disable_health_check);
+            assert_eq!(
+                dt.health_denominator.to_string(),
+                dd.health_denominator.to_string()
+            );
+            assert_eq!(dt.display, dd.display);
+
+            seen.insert(dt.name.clone(), dt.clone());
+        }
+        assert_eq!(seen.len(), 23);
+    }
+
+    /// Look up device types by name through the cache, including one
+    /// added after the cache was filled
+    #[test(tokio::test)]
+    async fn test_cache() {
+        let mut state =
+            SharedState::new_populated(PopulationParams::builder().device_types(5usize).build());
+        let server = LavaMock::new(
+            state.clone(),
+            PaginationLimits::builder().device_types(Some(2)).build(),
+        )
+        .await;
+
+        let names = state
+            .access()
+            .get_iter::<DeviceType<State>>()
+            .map(|dt| dt.name.clone())
+            .collect::<Vec<_>>();
+
+        let lava = Lava::new(&server.uri(), None).expect("failed to make lava server");
+
+        for name in names.iter() {
+            let dt = lava.device_type(name).await.expect("missing device type");
+            assert_eq!(&dt.name, name);
+        }
+        assert!(lava.device_type("new-type").await.is_none());
+
+        let _ = Proxy::<DeviceType<State>>::builder()
+            .name("new-type")
+            .build(state.mutate());
+
+        let dt = lava
+            .device_type("new-type")
+            .await
+            .expect("device type not refreshed");
+        assert_eq!(dt.name, "new-type");
+    }
+
+    /// Stream 12 aliases with a page limit of 5 from the server, and
+    /// resolve each of them to a device type
+    #[test(tokio::test)]
+    async fn test_aliases() {
+        let state = SharedState::new_populated(
+            PopulationParams::builder()
+                .aliases(12usize)
+                .device_types(6usize)
+                .build(),
+        );
+        let server = LavaMock::new(
+            state.clone(),
+            PaginationLimits::builder().aliases(Some(5)).build(),
+        )
+        .await;
+
+        let lava = Lava::new(&server.uri(), None).expect("failed to make lava server");
+
+        let aliases = lava
+            .aliases()
+            .try_collect::<Vec<_>>()
+            .await
+            .expect("failed to get aliases");
+        let mut names = aliases.into_iter().map(|a| a.name).collect::<Vec<_>>();
+        names.sort();
+        let mut expected = state
+            .access()
+            .get_iter::<Alias<State>>()
+            .map(|a| a.name.clone())
+            .collect::<Vec<_>>();
+        expected.sort();
+        assert_eq!(names, expected);
+
+        let start = state.access();
+        for dt in start.get_iter::<DeviceType<State>>() {
+            let resolved = lava
+                .resolve_device_type(&dt.name)
+                .await
+                .expect("failed to resolve device type");
+            assert_eq!(resolved.name, dt.name);
+
+            for alias in dt.aliases.iter() {
+                let alias = &start.get(alias).name;
+                let resolved = lava
+                    .resolve_device_type(alias)
+                    .await
+                    .expect("failed to resolve alias");
+                assert!(resolved.aliases.contains(alias));
+            }
+        }
+        assert!(lava.resolve_device_type("no-such-type").await.is_none());
+    }
+
+    /// Filter device types by name and by alias
+    #[test(tokio::test)]
+    async fn test_builder() {
+        let state = SharedState::new_populated(
+            PopulationParams::builder()
+                .aliases(12usize)
+                .device_types(6usize)
+                .build(),
+        );
+        let server = LavaMock::new(
+            state.clone(),
+            PaginationLimits::builder().device_types(Some(2)).build(),
+        )
+        .await;
+
+        let lava = Lava::new(&server.uri(), None).expect("failed to make lava server");
+
+        let start = state.access();
+        let all = start.get_iter::<DeviceType<State>>().collect::<Vec<_>>();
+
+        let found = lava
+            .device_types_builder()
+            .name(&all[1].name)
+            .name(&all[3].name)
+            .query()
+            .try_collect::<Vec<_>>()
+            .await
+            .expect("failed to get device types");
+        let mut names = found.into_iter().map(|dt| dt.name).collect::<Vec<_>>();
+        names.sort();
+        let mut expected = vec![all[1].name.clone(), all[3].name.clone()];
+        expected.sort();
+        assert_eq!(names, expected);
+
+        for dt in all.iter() {
+            for alias in dt.aliases.iter() {
+                let alias = &start.get(alias).name;
+                let found = lava
+                    .device_types_builder()
+                    .alias(alias)
+                    .query()
+                    .try_collect::<Vec<_>>()
+                    .await
+                    .expect("failed to get device types");
+                assert!(!found.is_empty());
+                assert!(found.iter().all(|found| found.aliases.contains(alias)));
+                assert!(found.iter().any(|found| found.name == dt.name));
+            }
+        }
+
+        let found = lava
+            .device_types_builder()
+            .name(&all[0].name)
+            .alias("no-such-alias")
+            .query()
+            .try_collect::<Vec<_>>()
+            .await
+            .expect("failed to get device types");
+        assert!(found.is_empty());
+    }
+}
@@ -0,0 +1,142 @@
+//! Classify the errors returned when talking to a server
+//!
+//! Each kind of request has its own error type, with variants for
+//! the failures particular to it. To tell users what to do about a
+//! failure, whatever request it came from, the error types also
+//! implement [`Classify`], which sorts them into the broad classes
+//! of [`ErrorClass`].
+//!
+//! Example:
+//! ```rust
+//! use lava_api::error::{Classify, ErrorClass};
+//! use lava_api::paginator::PaginationError;
+//!
+//! fn advice(error: &PaginationError) -> &'static str {
+//!     match error.class() {
+//!         ErrorClass::Auth => "check that your token is valid and has not expired",
+//!         ErrorClass::Decode => "the server may be running an unsupported version of LAVA",
+//!         ErrorClass::RateLimited | ErrorClass::Server | ErrorClass::Network => {
+//!             "try again later"
+//!         }
+//!         _ => "see the error for details",
+//!     }
+//! }
+//! ```
+
+use std::fmt;
+
+use reqwest::StatusCode;
+
+/// The broad class of a failed request
+#[derive(Copy, Clone, Debug, Hash, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum ErrorClass {
+    /// The server refused the request, because no token was given,
+    /// the token is invalid or has expired, or its user lacks
+    /// permission (401 or 403)
+    Auth,
+    /// The object requested does not exist, or is not visible to the
+    /// user making the request (404 or 410)
+    NotFound,
+    /// The server is limiting the rate of requests (429)
+    RateLimited,
+    /// The server failed, or is unavailable (5xx)
+    Server,
+    /// The server rejected the content of the request (400 or 422)
+    Invalid,
+    /// The server's reply could not be understood, which usually
+    /// means that it does not match the schema this crate expects
+    Decode,
+    /// The server could not be reached, or did not reply in time
+    Network,
+    /// Any other failure
+    Other,
+}
+
+impl ErrorClass {
+    /// The class of a reply from the server with the given status.
+    pub fn from_status(status: StatusCode) -> Self {
+        match status {
+            StatusCode::UNAUTHORIZED | StatusCode::FORBIDDEN => ErrorClass::Auth,
+            StatusCode::NOT_FOUND | StatusCode::GONE => ErrorClass::NotFound,
+            StatusCode::TOO_MANY_REQUESTS => ErrorClass::RateLimited,
+            StatusCode::BAD_REQUEST | StatusCode::UNPROCESSABLE_ENTITY => ErrorClass::Invalid,
+            s if s.is_server_error() => ErrorClass::Server,
+            _ => ErrorClass::Other,
+        }
+    }
+
+    /// Whether trying the request again later might succeed.
+    ///
+    /// This is what decides whether a
+    /// [`PaginationError`](crate::paginator::PaginationError) is
+    /// [retryable](crate::paginator::PaginationError::is_retryable).
+    pub fn is_transient(&self) -> bool {
+        matches!(
+            self,
+            ErrorClass::RateLimited | ErrorClass::Server | ErrorClass::Network
+        )
+    }
+}
+
+impl fmt::Display for ErrorClass {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            ErrorClass::Auth => "not authorized; the token may be missing, invalid or expired",
+            ErrorClass::NotFound => "not found",
+            ErrorClass::RateLimited => "too many requests to the server",
+            ErrorClass::Server => "the server failed or is unavailable",
+            ErrorClass::Invalid => "the server rejected the request as invalid",
+            ErrorClass::Decode => "the server's reply could not be understood",
+            ErrorClass::Network => "the server could not be reached",
+            ErrorClass::Other => "the request failed",
+        })
+    }
+}
+
+/// An error which can be sorted into an [`ErrorClass`]
+pub trait Classify {
+    /// The broad class of this error.
+    fn class(&self) -> ErrorClass;
+}
+
+impl Classify for reqwest::Error {
+    fn class(&self) -> ErrorClass {
+        if let Some(status) = self.status() {
+            ErrorClass::from_status(status)
+        } else if self.is_decode() {
+            ErrorClass::Decode
+        } else if self.is_timeout() || self.is_connect() || self.is_request() || self.is_body() {
+            ErrorClass::Network
+        } else {
+            ErrorClass::Other
+        }
+    }
+}
+
+impl Classify for StatusCode {
+    fn class(&self) -> ErrorClass {
+        ErrorClass::from_status(*self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_from_status() {
+        assert_eq!(StatusCode::UNAUTHORIZED.class(), ErrorClass::Auth);
+        assert_eq!(StatusCode::FORBIDDEN.class(), ErrorClass::Auth);
+        assert_eq!(StatusCode::NOT_FOUND.class(), ErrorClass::NotFound);
+        assert_eq!(
+            StatusCode::TOO_MANY_REQUESTS.class(),
+            ErrorClass::RateLimited
+        );
+        assert_eq!(StatusCode::BAD_GATEWAY.class(), ErrorClass::Server);
+        assert_eq!(StatusCode::BAD_REQUEST.class(), ErrorClass::Invalid);
+        assert_eq!(StatusCode::IM_A_TEAPOT.class(), ErrorClass::Other);
+        assert!(ErrorClass::Server.is_transient());
+        assert!(!ErrorClass::Decode.is_transient());
+    }
+}
@@ -0,0 +1,333 @@
+//! Record metrics for the requests made to the server
+//!
+//! An [`Instrumentation`] given to
+//! [`LavaBuilder::instrumentation`](crate::LavaBuilder::instrumentation)
+//! is told about every request a [`Lava`](crate::Lava) makes, with
+//! its endpoint, status, duration and size, and about each retry, so
+//! that they can be fed into a monitoring system. [`RequestCounters`]
+//! is a simple implementation which totals them for each endpoint.
+//!
+//! Example:
+//! ```rust
+//! use lava_api::instrument::RequestCounters;
+//! use lava_api::Lava;
+//! use std::sync::Arc;
+//!
+//! let counters = Arc::new(RequestCounters::new());
+//! let lava = Lava::builder("https://lava.example.com/")
+//!     .instrumentation(counters.clone())
+//!     .build()
+//!     .expect("failed to make lava");
+//!
+//! // ... make some requests, then
+//! for (endpoint, stats) in counters.snapshot() {
+//!     println!("{}: {} requests in {:?}", endpoint, stats.requests, stats.duration);
+//! }
+//! ```
+
+use futures::future::BoxFuture;
+use reqwest::{Method, Request, Response, StatusCode};
+use std::collections::{BTreeMap, HashMap};
+use std::fmt::Debug;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use url::Url;
+
+use crate::transport::Transport;
+
+// The path segments naming a collection; the segment after one of
+// these identifies a single object.
+const COLLECTIONS: &[&str] = &[
+    "aliases",
+    "devices",
+    "devicetypes",
+    "groups",
+    "jobs",
+    "suites",
+    "tags",
+    "tests",
+    "users",
+    "workers",
+];
+
+/// The endpoint a request was made to, for grouping metrics.
+///
+/// This is the path of the url, with the segments identifying single
+/// objects, such as job ids and device hostnames, replaced by
+/// `{id}`, so that all requests for the same kind of object are
+/// grouped together.
+///
+/// Example:
+/// ```rust
+/// use lava_api::instrument::endpoint;
+/// use url::Url;
+///
+/// let url = Url::parse("https://lava.example.com/api/v0.2/jobs/1234/suites/?limit=10").unwrap();
+/// assert_eq!(endpoint(&url), "/api/v0.2/jobs/{id}/suites/");
+/// ```
+pub fn endpoint(url: &Url) -> String {
+    let mut endpoint = String::new();
+    let mut object = false;
+    for segment in url.path().split('/').skip(1) {
+        endpoint.push('/');
+        if object && !segment.is_empty() {
+            endpoint.push_str("{id}");
+            object = false;
+        } else {
+            endpoint.push_str(segment);
+            object = COLLECTIONS.contains(&segment);
+        }
+    }
+    endpoint
+}
+
+/// The metrics for a single request
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RequestMetrics {
+    pub method: Method,
+    /// The [`endpoint`] the request was made to
+    pub endpoint: String,
+    /// The status of the reply, or `None` if the request failed
+    /// without one
+    pub status: Option<StatusCode>,
+    /// The time until the response headers arrived, not including
+    /// reading the body
+    pub duration: Duration,
+    /// The size of the request body
+    pub request_bytes: u64,
+    /// The size of the response body, when the server reports it
+    pub response_bytes: Option<u64>,
+}
+
+/// A recipient of metrics for the requests made to the server
+///
+/// Each attempt at a request is reported separately to
+/// [`request`](Instrumentation::request), and each attempt after the
+/// first is also reported to [`retry`](Instrumentation::retry)
+/// before it is made. Both are called from the task making the
+/// request, so they should return quickly.
+pub trait Instrumentation: Debug + Send + Sync {
+    /// Record a request which has been answered, or has failed.
+    fn request(&self, metrics: &RequestMetrics);
+
+    /// Record that a request to `endpoint` is being retried, as the
+    /// given (1-based) attempt.
+    ///
+    /// By default this does nothing.
+    fn retry(&self, _method: &Method, _endpoint: &str, _attempt: u32) {}
+}
+
+/// The totals for one endpoint in a [`RequestCounters`]
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct EndpointStats {
+    /// The number of requests made, including retries
+    pub requests: u64,
+    /// The number of requests which failed without a reply
+    pub failures: u64,
+    /// The number of retries
+    pub retries: u64,
+    /// The number of replies with each status code
+    pub statuses: BTreeMap<u16, u64>,
+    /// The total time spent waiting for replies
+    pub duration: Duration,
+    /// The total size of the request bodies sent
+    pub request_bytes: u64,
+    /// The total size of the response bodies whose size was
+    /// reported
+    pub response_bytes: u64,
+}
+
+/// An [`Instrumentation`] totalling the metrics for each endpoint
+#[derive(Debug, Default)]
+pub struct RequestCounters {
+    endpoints: Mutex<HashMap<String, EndpointStats>>,
+}
+
+impl RequestCounters {
+    /// Create a new [`RequestCounters`], with no requests recorded.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The totals so far, by [`endpoint`].
+    pub fn snapshot(&self) -> BTreeMap<String, EndpointStats> {
+        self.endpoints
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|(endpoint, stats)| (endpoint.clone(), stats.clone()))
+            .collect()
+    }
+}
+
+impl Instrumentation for RequestCounters {
+    fn request(&self, metrics: &RequestMetrics) {
+        let mut endpoints = self.endpoints.lock().unwrap();
+        let stats = endpoints.entry(metrics.endpoint.clone()).or_default();
+        stats.requests += 1;
+        match metrics.status {
+            Some(status) => *stats.statuses.entry(status.as_u16()).or_default() += 1,
+            None => stats.failures += 1,
+        }
+        stats.duration += metrics.duration;
+        stats.request_bytes += metrics.request_bytes;
+        stats.response_bytes += metrics.response_bytes.unwrap_or_default();
+    }
+
+    fn retry(&self, _method: &Method, endpoint: &str, _attempt: u32) {
+        let mut endpoints = self.endpoints.lock().unwrap();
+        endpoints.entry(endpoint.to_string()).or_default().retries += 1;
+    }
+}
+
+/// A [`Transport`] reporting each request to an [`Instrumentation`]
+/// as it passes it on.
+#[derive(Debug)]
+pub(crate) struct Instrumented {
+    inner: Arc<dyn Transport>,
+    instrumentation: Arc<dyn Instrumentation>,
+}
+
+impl Instrumented {
+    pub(crate) fn new(
+        inner: Arc<dyn Transport>,
+        instrumentation: Arc<dyn Instrumentation>,
+    ) -> Self {
+        Self {
+            inner,
+            instrumentation,
+        }
+    }
+}
+
+impl Transport for Instrumented {
+    fn execute(&self, request: Request) -> BoxFuture<'static, reqwest::Result<Response>> {
+        let method = request.method().clone();
+        let endpoint = endpoint(request.url());
+        let request_bytes = request
+            .body()
+            .and_then(|b| b.as_bytes())
+            .map(|b| b.len() as u64)
+            .unwrap_or_default();
+        let instrumentation = self.instrumentation.clone();
+        let start = Instant::now();
+        let response = self.inner.execute(request);
+        Box::pin(async move {
+            let response = response.await;
+            instrumentation.request(&RequestMetrics {
+                method,
+                endpoint,
+                status: response.as_ref().ok().map(|r| r.status()),
+                duration: start.elapsed(),
+                request_bytes,
+                response_bytes: response.as_ref().ok().and_then(|r| r.content_length()),
+            });
+            response
+        })
+    }
+
+    fn retrying(&self, request: &Request, attempt: u32) {
+        self.instrumentation
+            .retry(request.method(), &endpoint(request.url()), attempt);
+        self.inner.retrying(request, attempt);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{endpoint, RequestCounters};
+    use crate::retry::RetryPolicy;
+    use crate::Lava;
+
+    use futures::TryStreamExt;
+    use serde_json::json;
+    use std::sync::Arc;
+    use std::time::Duration;
+    use test_log::test;
+    use url::Url;
+    use wiremock::matchers::{method, path};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    #[test]
+    fn test_endpoint() {
+        let url = |p: &str| {
+            Url::parse("https://lava.example.com/")
+                .unwrap()
+                .join(p)
+                .unwrap()
+        };
+        for (p, e) in [
+            ("api/v0.2/jobs/", "/api/v0.2/jobs/"),
+            ("api/v0.2/jobs/?limit=5", "/api/v0.2/jobs/"),
+            ("api/v0.2/jobs/12/", "/api/v0.2/jobs/{id}/"),
+            ("api/v0.2/jobs/12/logs/", "/api/v0.2/jobs/{id}/logs/"),
+            (
+                "api/v0.2/jobs/12/suites/3/tests/",
+                "/api/v0.2/jobs/{id}/suites/{id}/tests/",
+            ),
+            ("api/v0.2/devices/rpi4-01/", "/api/v0.2/devices/{id}/"),
+            ("api/v0.2/system/whoami/", "/api/v0.2/system/whoami/"),
+        ] {
+            assert_eq!(endpoint(&url(p)), e, "{}", p);
+        }
+    }
+
+    #[test(tokio::test)]
+    async fn test_counters() {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/api/v0.2/workers/"))
+            .respond_with(ResponseTemplate::new(503))
+            .up_to_n_times(1)
+            .with_priority(1)
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/api/v0.2/workers/"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "count": 0,
+                "next": null,
+                "results": [],
+            })))
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/api/v0.2/jobs/7/cancel/"))
+            .respond_with(ResponseTemplate::new(404))
+            .mount(&server)
+            .await;
+
+        let counters = Arc::new(RequestCounters::new());
+        let lava = Lava::builder(&server.uri())
+            .retry_policy(
+                RetryPolicy::new()
+                    .max_attempts(2)
+                    .backoff(Duration::from_millis(1), Duration::from_millis(1)),
+            )
+            .instrumentation(counters.clone())
+            .build()
+            .expect("failed to make lava server");
+
+        lava.workers()
+            .try_collect::<Vec<_>>()
+            .await
+            .expect("failed to get workers");
+        lava.cancel_job(7).await.expect_err("cancelled missing job");
+
+        let stats = counters.snapshot();
+        assert_eq!(stats.len(), 2);
+
+        let workers = &stats["/api/v0.2/workers/"];
+        assert_eq!(workers.requests, 2);
+        assert_eq!(workers.retries, 1);
+        assert_eq!(workers.failures, 0);
+        assert_eq!(workers.statuses.get(&503), Some(&1));
+        assert_eq!(workers.statuses.get(&200), Some(&1));
+        assert!(workers.response_bytes > 0);
+
+        let cancel = &stats["/api/v0.2/jobs/{id}/cancel/"];
+        assert_eq!(cancel.requests, 1);
+        assert_eq!(cancel.retries, 0);
+        assert_eq!(cancel.statuses.get(&404), Some(&1));
+    }
+}
@@ -3,25 +3,42 @@
 use bytes::Bytes;
 use chrono::{DateTime, Utc};
 use futures::future::BoxFuture;
-use futures::stream::{self, Stream, StreamExt};
-use futures::{FutureExt, TryStreamExt};
+use futures::stream::Stream;
+use futures::{ready, FutureExt, StreamExt, TryStreamExt};
 use reqwest::StatusCode;
+use serde::de::{DeserializeOwned, IgnoredAny};
 use serde::{Deserialize, Serialize};
-use serde_with::DeserializeFromStr;
+use serde_with::{DeserializeFromStr, SerializeDisplay};
+use std::collections::{BTreeMap, HashMap, HashSet, VecDeque};
 use std::fmt;
 use std::pin::Pin;
 use std::task::{Context, Poll};
 use strum::{Display, EnumIter, EnumString, IntoEnumIterator};
 use thiserror::Error;
+use url::Url;
 
-use crate::paginator::{PaginationError, Paginator};
-use crate::queryset::{QuerySet, QuerySetMember};
-use crate::tag::Tag;
+use crate::datetime;
+use crate::error::{Classify, ErrorClass};
+use crate::jobdef::JobDefinition;
+use crate::paginator::{PaginationError, PaginationProgress, Paginator};
+use crate::queryset::{QuerySet, QuerySetMember, ValueSet};
+use crate::tag::{Tag, TagFilter, TagRef};
+use crate::transport;
 use crate::Lava;
 
 /// The progress of a job through the system.
 #[derive(
-    Copy, Clone, Debug, Hash, PartialEq, Eq, EnumIter, Display, EnumString, DeserializeFromStr,
+    Copy,
+    Clone,
+    Debug,
+    Hash,
+    PartialEq,
+    Eq,
+    EnumIter,
+    Display,
+    EnumString,
+    DeserializeFromStr,
+    SerializeDisplay,
 )]
 pub enum State {
     Submitted,
@@ -41,7 +58,17 @@ impl QuerySetMember for State {
 
 /// The completion state of a job.
 #[derive(
-    Copy, Clone, Debug, PartialEq, Eq, Hash, EnumIter, EnumString, Display, DeserializeFromStr,
+    Copy,
+    Clone,
+    Debug,
+    PartialEq,
+    Eq,
+    Hash,
+    EnumIter,
+    EnumString,
+    Display,
+    DeserializeFromStr,
+    SerializeDisplay,
 )]
 pub enum Health {
     /// Unknown is the usual state before the job has finished.
@@ -64,12 +91,21 @@ impl QuerySetMember for Health {
 ///
 /// These are usually combined with a [`bool`] in use, indicating
 /// whether the order is to be ascending or descending.
-#[derive(Debug, Clone)]
+#[derive(Copy, Clone, Debug, Default, Hash, PartialEq, Eq, EnumString, DeserializeFromStr)]
+#[strum(serialize_all = "snake_case")]
 pub enum Ordering {
+    #[default]
     Id,
     StartTime,
     EndTime,
     SubmitTime,
+    Priority,
+    /// The [`State`] of the job, in the order jobs pass through them
+    State,
+    /// The [`Health`] of the job, in the order its variants are
+    /// declared
+    Health,
+    Description,
 }
 
 impl fmt::Display for Ordering {
@@ -79,22 +115,67 @@ impl fmt::Display for Ordering {
             Ordering::StartTime => write!(f, "start_time"),
             Ordering::EndTime => write!(f, "end_time"),
             Ordering::SubmitTime => write!(f, "submit_time"),
+            Ordering::Priority => write!(f, "priority"),
+            Ordering::State => write!(f, "state"),
+            Ordering::Health => write!(f, "health"),
+            Ordering::Description => write!(f, "description"),
+        }
+    }
+}
+
+/// Who is permitted to view a job.
+///
+/// LAVA determines this from the job's `is_public` flag and its
+/// viewing groups: when viewing groups are set, only members of those
+/// groups may see the job, regardless of the flag.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub enum Visibility {
+    /// Anyone may view the job.
+    Public,
+    /// Only the submitter (and administrators) may view the job.
+    Personal,
+    /// Only members of the given groups may view the job.
+    Group(Vec<i64>),
+    /// The server did not report whether the job is public.
+    Unknown,
+}
+
+impl Visibility {
+    pub(crate) fn new(is_public: Option<bool>, viewing_groups: &[i64]) -> Self {
+        if !viewing_groups.is_empty() {
+            return Visibility::Group(viewing_groups.to_vec());
+        }
+        match is_public {
+            Some(true) => Visibility::Public,
+            Some(false) => Visibility::Personal,
+            None => Visibility::Unknown,
         }
     }
+
+    /// Whether the job can be viewed by anyone.
+    pub fn is_public(&self) -> bool {
+        matches!(self, Visibility::Public)
+    }
 }
 
-#[derive(Clone, Deserialize, Debug)]
+#[derive(Clone, Deserialize, Debug, Serialize)]
 struct LavaJob {
     id: i64,
     submitter: String,
     viewing_groups: Vec<i64>,
+    // Not reported by all server versions
+    #[serde(default)]
+    is_public: Option<bool>,
     description: String,
     health_check: bool,
     requested_device_type: Option<String>,
     tags: Vec<u32>,
     actual_device: Option<String>,
+    #[serde(deserialize_with = "datetime::deserialize")]
     submit_time: DateTime<Utc>,
+    #[serde(default, deserialize_with = "datetime::deserialize_option")]
     start_time: Option<DateTime<Utc>>,
+    #[serde(default, deserialize_with = "datetime::deserialize_option")]
     end_time: Option<DateTime<Utc>>,
     state: State,
     health: Health,
@@ -112,11 +193,12 @@ struct LavaJob {
 /// objects, rather than tag ids, but that
 /// [`viewing_groups`](Job::viewing_groups) and
 /// [`failure_tags`](Job::failure_tags) have not.
-#[derive(Clone, Debug, PartialEq, Eq)]
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
 pub struct Job {
     pub id: i64,
     pub submitter: String,
     pub viewing_groups: Vec<i64>,
+    pub visibility: Visibility,
     pub description: String,
     pub health_check: bool,
     pub requested_device_type: Option<String>,
@@ -135,9 +217,196 @@ pub struct Job {
     pub failure_comment: Option<String>,
 }
 
+impl Job {
+    /// Parse the [`definition`](Job::definition) of this job.
+    pub fn parsed_definition(&self) -> Result<JobDefinition, serde_yaml::Error> {
+        self.definition.parse()
+    }
+
+    /// Parse the [`original_definition`](Job::original_definition)
+    /// of this job, as it was submitted.
+    pub fn parsed_original_definition(&self) -> Result<JobDefinition, serde_yaml::Error> {
+        self.original_definition.parse()
+    }
+}
+
+/// The data available for a job from the LAVA API, with its tags
+/// left as ids
+///
+/// This is returned by [`JobsBuilder::query_raw`], for consumers
+/// which do not need the tags of a job resolved into [`Tag`] objects.
+/// Its fields are otherwise those of [`Job`].
+///
+/// Unlike a [`Job`], this is serialized in the form the server sends
+/// it, with an `is_public` flag in place of its
+/// [`visibility`](RawJob::visibility), so that it can be read back.
+#[derive(Clone, Debug, Deserialize, PartialEq, Eq, Serialize)]
+#[serde(from = "LavaJob", into = "LavaJob")]
+pub struct RawJob {
+    pub id: i64,
+    pub submitter: String,
+    pub viewing_groups: Vec<i64>,
+    pub visibility: Visibility,
+    pub description: String,
+    pub health_check: bool,
+    pub requested_device_type: Option<String>,
+    /// The ids of the tags the job requires
+    pub tags: Vec<u32>,
+    pub actual_device: Option<String>,
+    pub submit_time: DateTime<Utc>,
+    pub start_time: Option<DateTime<Utc>>,
+    pub end_time: Option<DateTime<Utc>>,
+    pub state: State,
+    pub health: Health,
+    pub priority: i64,
+    pub definition: String,
+    pub original_definition: String,
+    pub multinode_definition: String,
+    /// The ids of the tags describing the job's failure
+    pub failure_tags: Vec<u32>,
+    pub failure_comment: Option<String>,
+}
+
+impl RawJob {
+    /// Convert this into a [`Job`], looking up its tags in `tags`,
+    /// such as a map built from [`Lava::tags`].
+    ///
+    /// Tags missing from `tags` are left out, as they are by
+    /// [`Jobs`] for tags which no longer exist.
+    pub fn resolve(self, tags: &HashMap<u32, Tag>) -> Job {
+        let resolve = |ids: &[u32]| {
+            ids.iter()
+                .filter_map(|id| tags.get(id).cloned())
+                .collect::<Vec<_>>()
+        };
+        Job {
+            tags: resolve(&self.tags),
+            failure_tags: resolve(&self.failure_tags),
+            id: self.id,
+            submitter: self.submitter,
+            viewing_groups: self.viewing_groups,
+            visibility: self.visibility,
+            description: self.description,
+            health_check: self.health_check,
+            requested_device_type: self.requested_device_type,
+            actual_device: self.actual_device,
+            submit_time: self.submit_time,
+            start_time: self.start_time,
+            end_time: self.end_time,
+            state: self.state,
+            health: self.health,
+            priority: self.priority,
+            definition: self.definition,
+            original_definition: self.original_definition,
+            multinode_definition: self.multinode_definition,
+            failure_comment: self.failure_comment,
+        }
+    }
+}
+
+impl From<LavaJob> for RawJob {
+    fn from(job: LavaJob) -> Self {
+        RawJob {
+            id: job.id,
+            submitter: job.submitter,
+            visibility: Visibility::new(job.is_public, &job.viewing_groups),
+            viewing_groups: job.viewing_groups,
+            description: job.description,
+            health_check: job.health_check,
+            requested_device_type: job.requested_device_type,
+            tags: job.tags,
+            actual_device: job.actual_device,
+            submit_time: job.submit_time,
+            start_time: job.start_time,
+            end_time: job.end_time,
+            state: job.state,
+            health: job.health,
+            priority: job.priority,
+            definition: job.definition,
+            original_definition: job.original_definition,
+            multinode_definition: job.multinode_definition,
+            failure_tags: job.failure_tags,
+            failure_comment: job.failure_comment,
+        }
+    }
+}
+
+impl From<RawJob> for LavaJob {
+    fn from(job: RawJob) -> Self {
+        LavaJob {
+            id: job.id,
+            submitter: job.submitter,
+            viewing_groups: job.viewing_groups,
+            is_public: match job.visibility {
+                Visibility::Public => Some(true),
+                Visibility::Personal | Visibility::Group(_) => Some(false),
+                Visibility::Unknown => None,
+            },
+            description: job.description,
+            health_check: job.health_check,
+            requested_device_type: job.requested_device_type,
+            tags: job.tags,
+            actual_device: job.actual_device,
+            submit_time: job.submit_time,
+            start_time: job.start_time,
+            end_time: job.end_time,
+            state: job.state,
+            health: job.health,
+            priority: job.priority,
+            definition: job.definition,
+            original_definition: job.original_definition,
+            multinode_definition: job.multinode_definition,
+            failure_tags: job.failure_tags,
+            failure_comment: job.failure_comment,
+        }
+    }
+}
+
+/// The fields requested from the server for a [`ReducedJob`]
+const REDUCED_FIELDS: &[&str] = &[
+    "id",
+    "submitter",
+    "description",
+    "health_check",
+    "requested_device_type",
+    "actual_device",
+    "submit_time",
+    "start_time",
+    "end_time",
+    "state",
+    "health",
+    "priority",
+];
+
+/// A summary of a job from the LAVA API
+///
+/// This holds the fields of [`Job`] which are small and need no
+/// further lookups, leaving out the job definitions, tags and failure
+/// details. It is returned by [`JobsBuilder::query_reduced`], which
+/// asks the server to send only these fields, and so is much cheaper
+/// to fetch than a [`Job`] when exporting many jobs.
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq, Eq)]
+pub struct ReducedJob {
+    pub id: i64,
+    pub submitter: String,
+    pub description: String,
+    pub health_check: bool,
+    pub requested_device_type: Option<String>,
+    pub actual_device: Option<String>,
+    #[serde(deserialize_with = "datetime::deserialize")]
+    pub submit_time: DateTime<Utc>,
+    #[serde(default, deserialize_with = "datetime::deserialize_option")]
+    pub start_time: Option<DateTime<Utc>>,
+    #[serde(default, deserialize_with = "datetime::deserialize_option")]
+    pub end_time: Option<DateTime<Utc>>,
+    pub state: State,
+    pub health: Health,
+    pub priority: i64,
+}
+
 enum PagingState<'a> {
     Paging,
-    Transforming(BoxFuture<'a, Job>),
+    Resolving(BoxFuture<'a, HashMap<u32, Tag>>),
 }
 
 /// A [`Stream`] that yields a selected subset of the [`Job`]
@@ -149,6 +418,18 @@ pub struct Jobs<'a> {
     lava: &'a Lava,
     paginator: Paginator<LavaJob>,
     state: PagingState<'a>,
+    yielded: u32,
+    // The jobs read from the server, including those dropped for not
+    // carrying the required tags, from which a resumed stream carries
+    // on
+    consumed: u32,
+    // The page whose tags have been resolved into `tags`
+    page: u32,
+    tags: HashMap<u32, Tag>,
+    // The item waiting for the tags of its page to be resolved
+    pending: Option<LavaJob>,
+    // The tags a job must require, which the server cannot check
+    required: TagFilter,
 }
 
 impl<'a> Jobs<'a> {
@@ -164,6 +445,179 @@ impl<'a> Jobs<'a> {
     pub fn reported_items(&self) -> Option<u32> {
         self.paginator.reported_items()
     }
+
+    /// A new stream for the same query, starting from the first job
+    /// this stream has not yet returned.
+    ///
+    /// This is intended for restarting a stream which has returned an
+    /// error, for example after waiting for the server to recover:
+    /// when a page could not be read, the new stream starts by reading
+    /// that page again. The progress reported by [`PaginationProgress`] carries
+    /// over to the new stream.
+    ///
+    /// Note that unless the query was made with
+    /// [`JobsBuilder::stable_pagination`], jobs added or removed while
+    /// the stream was stopped can shift the later pages, as for any
+    /// other query.
+    pub fn resume_from(&self) -> Jobs<'a> {
+        Jobs {
+            lava: self.lava,
+            paginator: self.paginator.resume(self.consumed),
+            state: PagingState::Paging,
+            yielded: self.yielded,
+            consumed: self.consumed,
+            page: 0,
+            tags: HashMap::new(),
+            pending: None,
+            required: self.required.clone(),
+        }
+    }
+
+    /// Drop the jobs this stream returns more than once.
+    ///
+    /// This is for queries using offset pagination, which can return
+    /// a job twice when jobs are added while the query runs, as
+    /// described for [`JobsBuilder::limit`]. See [`DeduplicatedJobs`]
+    /// for how duplicates are found, and for an estimate of the jobs
+    /// missed when jobs are removed instead.
+    pub fn deduplicated(self) -> DeduplicatedJobs<'a> {
+        DeduplicatedJobs::new(self)
+    }
+}
+
+impl PaginationProgress for Jobs<'_> {
+    fn reported_items(&self) -> Option<u32> {
+        self.paginator.reported_items()
+    }
+
+    fn yielded_items(&self) -> u32 {
+        self.yielded
+    }
+
+    fn fetched_pages(&self) -> u32 {
+        self.paginator.fetched_pages()
+    }
+}
+
+/// Read the shards made by [`JobsBuilder::sharded`] concurrently,
+/// returning their jobs as they arrive.
+///
+/// The jobs of each shard are returned in order, but those of
+/// different shards are interleaved.
+pub fn merge_shards<'a>(
+    shards: Vec<Jobs<'a>>,
+) -> impl Stream<Item = Result<Job, PaginationError>> + 'a {
+    futures::stream::select_all(shards)
+}
+
+/// The number of job ids a [`DeduplicatedJobs`] remembers by default
+pub const DEFAULT_DEDUPLICATION_WINDOW: usize = 1000;
+
+/// A [`Stream`] of the jobs from a [`Jobs`] stream, without the
+/// duplicates caused by offset pagination.
+///
+/// These are constructed using [`Jobs::deduplicated`]. Duplicates are
+/// found by remembering the ids of the most recent jobs returned, up
+/// to [`window`](DeduplicatedJobs::window) of them, so that memory
+/// use stays bounded however long the query. Since duplicates only
+/// occur where a page repeats the end of the one before it, a window
+/// of a few pages is enough.
+///
+/// Jobs removed while the query runs can instead cause later jobs to
+/// be skipped, which cannot be detected from the jobs themselves. The
+/// server's count of matching jobs is checked with each page, and
+/// every drop in it is counted towards
+/// [`suspected_omissions`](DeduplicatedJobs::suspected_omissions).
+/// This is only an estimate: a removal from a page not yet read
+/// causes no omission, and a removal and an addition between the same
+/// pages cancel out. Use [`JobsBuilder::stable_pagination`] when
+/// accurate results are required.
+pub struct DeduplicatedJobs<'a> {
+    jobs: Jobs<'a>,
+    window: usize,
+    seen: HashSet<i64>,
+    order: VecDeque<i64>,
+    duplicates: u32,
+    suspected_omissions: u32,
+    reported: Option<u32>,
+}
+
+impl<'a> DeduplicatedJobs<'a> {
+    fn new(jobs: Jobs<'a>) -> Self {
+        Self {
+            jobs,
+            window: DEFAULT_DEDUPLICATION_WINDOW,
+            seen: HashSet::new(),
+            order: VecDeque::new(),
+            duplicates: 0,
+            suspected_omissions: 0,
+            reported: None,
+        }
+    }
+
+    /// Set the number of job ids to remember.
+    ///
+    /// The default is [`DEFAULT_DEDUPLICATION_WINDOW`].
+    pub fn window(mut self, window: usize) -> Self {
+        self.window = window;
+        self.forget();
+        self
+    }
+
+    /// The number of duplicate jobs dropped so far.
+    pub fn duplicates(&self) -> u32 {
+        self.duplicates
+    }
+
+    /// An estimate of the number of jobs missed so far.
+    pub fn suspected_omissions(&self) -> u32 {
+        self.suspected_omissions
+    }
+
+    /// The underlying [`Jobs`] stream.
+    pub fn get_ref(&self) -> &Jobs<'a> {
+        &self.jobs
+    }
+
+    // Record any drop in the number of matching jobs since the last
+    // page
+    fn check_count(&mut self) {
+        if let Some(reported) = self.jobs.reported_items() {
+            if let Some(previous) = self.reported {
+                self.suspected_omissions += previous.saturating_sub(reported);
+            }
+            self.reported = Some(reported);
+        }
+    }
+
+    fn forget(&mut self) {
+        while self.order.len() > self.window {
+            if let Some(id) = self.order.pop_front() {
+                self.seen.remove(&id);
+            }
+        }
+    }
+}
+
+impl Stream for DeduplicatedJobs<'_> {
+    type Item = Result<Job, PaginationError>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Option<Self::Item>> {
+        let me = self.get_mut();
+        loop {
+            let item = ready!(me.jobs.poll_next_unpin(cx));
+            me.check_count();
+            match item {
+                Some(Ok(job)) if !me.seen.insert(job.id) => me.duplicates += 1,
+                Some(Ok(job)) => {
+                    me.order.push_back(job.id);
+                    me.forget();
+                    return Poll::Ready(Some(Ok(job)));
+                }
+                item => return Poll::Ready(item),
+            }
+        }
+    }
 }
 
 /// Select a set of [`Job`] instances to return from the LAVA server.
@@ -205,16 +659,7 @@ impl<'a> Jobs<'a> {
 #[derive(Debug, Clone)]
 pub struct JobsBuilder<'a> {
     lava: &'a Lava,
-    states: QuerySet<State>,
-    healths: QuerySet<Health>,
-    limit: Option<u32>,
-    ordering: Ordering,
-    ids: Vec<i64>,
-    id_after: Option<i64>,
-    started_after: Option<DateTime<Utc>>,
-    submitted_after: Option<DateTime<Utc>>,
-    ended_after: Option<DateTime<Utc>>,
-    ascending: bool,
+    query: JobsQuery,
 }
 
 impl<'a> JobsBuilder<'a> {
@@ -225,30 +670,78 @@ impl<'a> JobsBuilder<'a> {
     /// - no filtering
     /// - default result pagination
     pub fn new(lava: &'a Lava) -> Self {
-        Self {
-            lava,
-            states: QuerySet::new(String::from("state")),
-            healths: QuerySet::new(String::from("health")),
-            limit: None,
-            ordering: Ordering::Id,
-            ids: Vec::new(),
-            id_after: None,
-            started_after: None,
-            submitted_after: None,
-            ended_after: None,
-            ascending: true,
-        }
+        Self::with_query(lava, JobsQuery::new())
+    }
+
+    /// Create a new [`JobsBuilder`] starting from a query which was
+    /// constructed in advance.
+    pub fn with_query(lava: &'a Lava, query: JobsQuery) -> Self {
+        Self { lava, query }
     }
 
     /// Return jobs in this state.
+    ///
+    /// Example:
+    /// ```rust
+    /// use futures::stream::TryStreamExt;
+    /// # use lava_api_mock::JobState;
+    /// use lava_api::{Lava, job::State};
+    /// #
+    /// # tokio_test::block_on( async {
+    /// # let mock = lava_api_mock::doctest::jobs(|state| {
+    /// #     state.add_jobs(2, |_, job| job.state = JobState::Running);
+    /// #     state.add_jobs(3, |_, job| job.state = JobState::Finished);
+    /// # })
+    /// # .await;
+    /// # let service_uri = mock.uri();
+    /// let lava = Lava::new(&service_uri, None).expect("failed to make lava");
+    ///
+    /// let jobs: Vec<_> = lava
+    ///     .jobs()
+    ///     .state(State::Running)
+    ///     .query()
+    ///     .try_collect()
+    ///     .await
+    ///     .expect("failed to query jobs");
+    /// assert_eq!(jobs.len(), 2);
+    /// assert!(jobs.iter().all(|job| job.state == State::Running));
+    /// # });
+    /// ```
     pub fn state(mut self, state: State) -> Self {
-        self.states.include(state);
+        self.query = self.query.state(state);
         self
     }
 
     /// Exclude jobs in this state.
+    ///
+    /// Example:
+    /// ```rust
+    /// use futures::stream::TryStreamExt;
+    /// # use lava_api_mock::JobState;
+    /// use lava_api::{Lava, job::State};
+    /// #
+    /// # tokio_test::block_on( async {
+    /// # let mock = lava_api_mock::doctest::jobs(|state| {
+    /// #     state.add_jobs(2, |_, job| job.state = JobState::Running);
+    /// #     state.add_jobs(3, |_, job| job.state = JobState::Finished);
+    /// # })
+    /// # .await;
+    /// # let service_uri = mock.uri();
+    /// let lava = Lava::new(&service_uri, None).expect("failed to make lava");
+    ///
+    /// let jobs: Vec<_> = lava
+    ///     .jobs()
+    ///     .state_not(State::Finished)
+    ///     .query()
+    ///     .try_collect()
+    ///     .await
+    ///     .expect("failed to query jobs");
+    /// assert_eq!(jobs.len(), 2);
+    /// assert!(jobs.iter().all(|job| job.state != State::Finished));
+    /// # });
+    /// ```
     pub fn state_not(mut self, state: State) -> Self {
-        self.states.exclude(&state);
+        self.query = self.query.state_not(state);
         self
     }
 
@@ -272,171 +765,1741 @@ impl<'a> JobsBuilder<'a> {
     /// client side. Each page contains a section of the query
     /// begining with the job at some multiple of the limit count into
     /// the result set.  However the result set is evolving while the
-    /// paging is occurring, and this is not currently compensated
-    /// for, which leads to jobs being returned multiple times at the
-    /// boundaries between pages - or even omitted depending on the
-    /// query. In general, query sets that can shrink are not safe to
-    /// use with paging, because results can be lost rather than
+    /// paging is occurring, and this is not compensated for unless
+    /// [`stable_pagination`](Self::stable_pagination) is used, which
+    /// leads to jobs being returned multiple times at the boundaries
+    /// between pages - or even omitted depending on the query. In
+    /// general, query sets that can shrink are not safe to use with
+    /// offset paging, because results can be lost rather than
     /// duplicated.
     pub fn limit(mut self, limit: u32) -> Self {
-        self.limit = Some(limit);
+        self.query = self.query.limit(limit);
         self
     }
 
     /// Return jobs with this health.
+    ///
+    /// Example:
+    /// ```rust
+    /// use futures::stream::TryStreamExt;
+    /// # use lava_api_mock::JobHealth;
+    /// use lava_api::{Lava, job::Health};
+    /// #
+    /// # tokio_test::block_on( async {
+    /// # let mock = lava_api_mock::doctest::jobs(|state| {
+    /// #     state.add_jobs(2, |_, job| job.health = JobHealth::Complete);
+    /// #     state.add_jobs(1, |_, job| job.health = JobHealth::Incomplete);
+    /// # })
+    /// # .await;
+    /// # let service_uri = mock.uri();
+    /// let lava = Lava::new(&service_uri, None).expect("failed to make lava");
+    ///
+    /// let jobs: Vec<_> = lava
+    ///     .jobs()
+    ///     .health(Health::Complete)
+    ///     .query()
+    ///     .try_collect()
+    ///     .await
+    ///     .expect("failed to query jobs");
+    /// assert_eq!(jobs.len(), 2);
+    /// assert!(jobs.iter().all(|job| job.health == Health::Complete));
+    /// # });
+    /// ```
     pub fn health(mut self, health: Health) -> Self {
-        self.healths.include(health);
+        self.query = self.query.health(health);
         self
     }
 
     /// Exclude jobs with this health.
+    ///
+    /// Example:
+    /// ```rust
+    /// use futures::stream::TryStreamExt;
+    /// # use lava_api_mock::JobHealth;
+    /// use lava_api::{Lava, job::Health};
+    /// #
+    /// # tokio_test::block_on( async {
+    /// # let mock = lava_api_mock::doctest::jobs(|state| {
+    /// #     state.add_jobs(2, |_, job| job.health = JobHealth::Complete);
+    /// #     state.add_jobs(1, |_, job| job.health = JobHealth::Incomplete);
+    /// # })
+    /// # .await;
+    /// # let service_uri = mock.uri();
+    /// let lava = Lava::new(&service_uri, None).expect("failed to make lava");
+    ///
+    /// let jobs: Vec<_> = lava
+    ///     .jobs()
+    ///     .health_not(Health::Incomplete)
+    ///     .query()
+    ///     .try_collect()
+    ///     .await
+    ///     .expect("failed to query jobs");
+    /// assert_eq!(jobs.len(), 2);
+    /// assert!(jobs.iter().all(|job| job.health != Health::Incomplete));
+    /// # });
+    /// ```
     pub fn health_not(mut self, health: Health) -> Self {
-        self.healths.exclude(&health);
+        self.query = self.query.health_not(health);
         self
     }
 
     /// Return only jobs whose id is `id`.
+    ///
+    /// Example:
+    /// ```rust
+    /// use futures::stream::TryStreamExt;
+    /// use lava_api::Lava;
+    /// #
+    /// # tokio_test::block_on( async {
+    /// # let mock = lava_api_mock::doctest::jobs(|state| {
+    /// #     state.add_jobs(5, |_, _| {});
+    /// # })
+    /// # .await;
+    /// # let service_uri = mock.uri();
+    /// let lava = Lava::new(&service_uri, None).expect("failed to make lava");
+    ///
+    /// let jobs: Vec<_> = lava
+    ///     .jobs()
+    ///     .id(1)
+    ///     .id(3)
+    ///     .query()
+    ///     .try_collect()
+    ///     .await
+    ///     .expect("failed to query jobs");
+    /// let ids: Vec<_> = jobs.iter().map(|job| job.id).collect();
+    /// assert_eq!(ids, vec![1, 3]);
+    /// # });
+    /// ```
     pub fn id(mut self, id: i64) -> Self {
-        self.ids.push(id);
+        self.query = self.query.id(id);
+        self
+    }
+
+    /// Return only jobs whose id is one of `ids`.
+    ///
+    /// This can be combined with [`id`](JobsBuilder::id), to look up
+    /// many jobs with a single query.
+    ///
+    /// Example:
+    /// ```rust
+    /// use futures::stream::TryStreamExt;
+    /// use lava_api::Lava;
+    /// #
+    /// # tokio_test::block_on( async {
+    /// # let mock = lava_api_mock::doctest::jobs(|state| {
+    /// #     state.add_jobs(5, |_, _| {});
+    /// # })
+    /// # .await;
+    /// # let service_uri = mock.uri();
+    /// let lava = Lava::new(&service_uri, None).expect("failed to make lava");
+    ///
+    /// let jobs: Vec<_> = lava
+    ///     .jobs()
+    ///     .ids(&[0, 2, 4])
+    ///     .query()
+    ///     .try_collect()
+    ///     .await
+    ///     .expect("failed to query jobs");
+    /// let ids: Vec<_> = jobs.iter().map(|job| job.id).collect();
+    /// assert_eq!(ids, vec![0, 2, 4]);
+    /// # });
+    /// ```
+    pub fn ids(mut self, ids: &[i64]) -> Self {
+        self.query = self.query.ids(ids);
         self
     }
 
     /// Return only jobs whose id is strictly greater than `id`.
+    ///
+    /// Example:
+    /// ```rust
+    /// use futures::stream::TryStreamExt;
+    /// use lava_api::Lava;
+    /// #
+    /// # tokio_test::block_on( async {
+    /// # let mock = lava_api_mock::doctest::jobs(|state| {
+    /// #     state.add_jobs(5, |_, _| {});
+    /// # })
+    /// # .await;
+    /// # let service_uri = mock.uri();
+    /// let lava = Lava::new(&service_uri, None).expect("failed to make lava");
+    ///
+    /// let jobs: Vec<_> = lava
+    ///     .jobs()
+    ///     .id_after(2)
+    ///     .query()
+    ///     .try_collect()
+    ///     .await
+    ///     .expect("failed to query jobs");
+    /// let ids: Vec<_> = jobs.iter().map(|job| job.id).collect();
+    /// assert_eq!(ids, vec![3, 4]);
+    /// # });
+    /// ```
     pub fn id_after(mut self, id: i64) -> Self {
-        self.id_after = Some(id);
+        self.query = self.query.id_after(id);
         self
     }
 
     /// Return only jobs whose start time is strictly after the given
     /// instant.
+    ///
+    /// Example:
+    /// ```rust
+    /// use futures::stream::TryStreamExt;
+    /// use chrono::{DateTime, Duration, Utc};
+    /// use lava_api::Lava;
+    /// #
+    /// # tokio_test::block_on( async {
+    /// # let mock = lava_api_mock::doctest::jobs(|state| {
+    /// #     let start: DateTime<Utc> = "2022-03-17T17:00:00Z".parse().unwrap();
+    /// #     state.add_jobs(4, |i, job| {
+    /// #         job.start_time = Some(start + Duration::hours(i as i64));
+    /// #     });
+    /// # })
+    /// # .await;
+    /// # let service_uri = mock.uri();
+    /// let lava = Lava::new(&service_uri, None).expect("failed to make lava");
+    ///
+    /// let cutoff: DateTime<Utc> = "2022-03-17T18:30:00Z".parse().unwrap();
+    /// let jobs: Vec<_> = lava
+    ///     .jobs()
+    ///     .started_after(cutoff)
+    ///     .query()
+    ///     .try_collect()
+    ///     .await
+    ///     .expect("failed to query jobs");
+    /// assert_eq!(jobs.len(), 2);
+    /// assert!(jobs.iter().all(|job| job.start_time.unwrap() > cutoff));
+    /// # });
+    /// ```
     pub fn started_after(mut self, when: chrono::DateTime<Utc>) -> Self {
-        self.started_after = Some(when);
+        self.query = self.query.started_after(when);
         self
     }
 
     /// Return only jobs whose submission time is strictly after the
     /// given instant.
+    ///
+    /// Example:
+    /// ```rust
+    /// use futures::stream::TryStreamExt;
+    /// use chrono::{DateTime, Duration, Utc};
+    /// use lava_api::Lava;
+    /// #
+    /// # tokio_test::block_on( async {
+    /// # let mock = lava_api_mock::doctest::jobs(|state| {
+    /// #     let start: DateTime<Utc> = "2022-03-17T17:00:00Z".parse().unwrap();
+    /// #     state.add_jobs(4, |i, job| {
+    /// #         job.submit_time = Some(start + Duration::hours(i as i64));
+    /// #     });
+    /// # })
+    /// # .await;
+    /// # let service_uri = mock.uri();
+    /// let lava = Lava::new(&service_uri, None).expect("failed to make lava");
+    ///
+    /// let cutoff: DateTime<Utc> = "2022-03-17T18:30:00Z".parse().unwrap();
+    /// let jobs: Vec<_> = lava
+    ///     .jobs()
+    ///     .submitted_after(cutoff)
+    ///     .query()
+    ///     .try_collect()
+    ///     .await
+    ///     .expect("failed to query jobs");
+    /// assert_eq!(jobs.len(), 2);
+    /// assert!(jobs.iter().all(|job| job.submit_time > cutoff));
+    /// # });
+    /// ```
     pub fn submitted_after(mut self, when: chrono::DateTime<Utc>) -> Self {
-        self.submitted_after = Some(when);
+        self.query = self.query.submitted_after(when);
         self
     }
 
     /// Return only jobs which ended strictly after the given instant.
+    ///
+    /// Example:
+    /// ```rust
+    /// use futures::stream::TryStreamExt;
+    /// use chrono::{DateTime, Duration, Utc};
+    /// use lava_api::Lava;
+    /// #
+    /// # tokio_test::block_on( async {
+    /// # let mock = lava_api_mock::doctest::jobs(|state| {
+    /// #     let start: DateTime<Utc> = "2022-03-17T17:00:00Z".parse().unwrap();
+    /// #     state.add_jobs(4, |i, job| {
+    /// #         job.end_time = Some(start + Duration::hours(i as i64));
+    /// #     });
+    /// # })
+    /// # .await;
+    /// # let service_uri = mock.uri();
+    /// let lava = Lava::new(&service_uri, None).expect("failed to make lava");
+    ///
+    /// let cutoff: DateTime<Utc> = "2022-03-17T18:30:00Z".parse().unwrap();
+    /// let jobs: Vec<_> = lava
+    ///     .jobs()
+    ///     .ended_after(cutoff)
+    ///     .query()
+    ///     .try_collect()
+    ///     .await
+    ///     .expect("failed to query jobs");
+    /// assert_eq!(jobs.len(), 2);
+    /// assert!(jobs.iter().all(|job| job.end_time.unwrap() > cutoff));
+    /// # });
+    /// ```
     pub fn ended_after(mut self, when: chrono::DateTime<Utc>) -> Self {
-        self.ended_after = Some(when);
+        self.query = self.query.ended_after(when);
         self
     }
 
-    /// Order returned jobs by the given key.
-    pub fn ordering(mut self, ordering: Ordering, ascending: bool) -> Self {
-        self.ordering = ordering;
-        self.ascending = ascending;
+    /// Return only jobs whose start time is strictly before the given
+    /// instant.
+    ///
+    /// Example:
+    /// ```rust
+    /// use futures::stream::TryStreamExt;
+    /// use chrono::{DateTime, Duration, Utc};
+    /// use lava_api::Lava;
+    /// #
+    /// # tokio_test::block_on( async {
+    /// # let mock = lava_api_mock::doctest::jobs(|state| {
+    /// #     let start: DateTime<Utc> = "2022-03-17T17:00:00Z".parse().unwrap();
+    /// #     state.add_jobs(4, |i, job| {
+    /// #         job.start_time = Some(start + Duration::hours(i as i64));
+    /// #     });
+    /// # })
+    /// # .await;
+    /// # let service_uri = mock.uri();
+    /// let lava = Lava::new(&service_uri, None).expect("failed to make lava");
+    ///
+    /// let cutoff: DateTime<Utc> = "2022-03-17T18:30:00Z".parse().unwrap();
+    /// let jobs: Vec<_> = lava
+    ///     .jobs()
+    ///     .started_before(cutoff)
+    ///     .query()
+    ///     .try_collect()
+    ///     .await
+    ///     .expect("failed to query jobs");
+    /// assert_eq!(jobs.len(), 2);
+    /// assert!(jobs.iter().all(|job| job.start_time.unwrap() < cutoff));
+    /// # });
+    /// ```
+    pub fn started_before(mut self, when: chrono::DateTime<Utc>) -> Self {
+        self.query = self.query.started_before(when);
         self
     }
 
-    /// Begin querying for jobs, returning a [`Jobs`] instance
-    pub fn query(self) -> Jobs<'a> {
-        let mut url = self
-            .lava
-            .base
-            .join("jobs/")
-            .expect("Failed to append to base url");
-        url.query_pairs_mut().append_pair(
-            "ordering",
-            &format!(
-                "{}{}",
-                match self.ascending {
-                    true => "",
-                    false => "-",
-                },
-                self.ordering
-            ),
-        );
-        if let Some(pair) = self.states.query() {
-            url.query_pairs_mut().append_pair(&pair.0, &pair.1);
-        }
-        if let Some(limit) = self.limit {
-            url.query_pairs_mut()
-                .append_pair("limit", &limit.to_string());
-        };
-        if let Some(pair) = self.healths.query() {
-            url.query_pairs_mut().append_pair(&pair.0, &pair.1);
-        }
+    /// Return only jobs which ended strictly before the given
+    /// instant.
+    ///
+    /// Example:
+    /// ```rust
+    /// use futures::stream::TryStreamExt;
+    /// use chrono::{DateTime, Duration, Utc};
+    /// use lava_api::Lava;
+    /// #
+    /// # tokio_test::block_on( async {
+    /// # let mock = lava_api_mock::doctest::jobs(|state| {
+    /// #     let start: DateTime<Utc> = "2022-03-17T17:00:00Z".parse().unwrap();
+    /// #     state.add_jobs(4, |i, job| {
+    /// #         job.end_time = Some(start + Duration::hours(i as i64));
+    /// #     });
+    /// # })
+    /// # .await;
+    /// # let service_uri = mock.uri();
+    /// let lava = Lava::new(&service_uri, None).expect("failed to make lava");
+    ///
+    /// let cutoff: DateTime<Utc> = "2022-03-17T18:30:00Z".parse().unwrap();
+    /// let jobs: Vec<_> = lava
+    ///     .jobs()
+    ///     .ended_before(cutoff)
+    ///     .query()
+    ///     .try_collect()
+    ///     .await
+    ///     .expect("failed to query jobs");
+    /// assert_eq!(jobs.len(), 2);
+    /// assert!(jobs.iter().all(|job| job.end_time.unwrap() < cutoff));
+    /// # });
+    /// ```
+    pub fn ended_before(mut self, when: chrono::DateTime<Utc>) -> Self {
+        self.query = self.query.ended_before(when);
+        self
+    }
 
-        match self.ids.len() {
-            0 => (),
-            1 => {
-                url.query_pairs_mut()
-                    .append_pair("id", &self.ids[0].to_string());
-            }
-            _ => {
-                let ids: Vec<_> = self.ids.iter().map(i64::to_string).collect();
-                url.query_pairs_mut().append_pair("id__in", &ids.join(","));
-            }
-        }
+    /// Return only jobs whose submission time is strictly before the
+    /// given instant.
+    ///
+    /// Example:
+    /// ```rust
+    /// use futures::stream::TryStreamExt;
+    /// use chrono::{DateTime, Duration, Utc};
+    /// use lava_api::Lava;
+    /// #
+    /// # tokio_test::block_on( async {
+    /// # let mock = lava_api_mock::doctest::jobs(|state| {
+    /// #     let start: DateTime<Utc> = "2022-03-17T17:00:00Z".parse().unwrap();
+    /// #     state.add_jobs(4, |i, job| {
+    /// #         job.submit_time = Some(start + Duration::hours(i as i64));
+    /// #     });
+    /// # })
+    /// # .await;
+    /// # let service_uri = mock.uri();
+    /// let lava = Lava::new(&service_uri, None).expect("failed to make lava");
+    ///
+    /// let cutoff: DateTime<Utc> = "2022-03-17T18:30:00Z".parse().unwrap();
+    /// let jobs: Vec<_> = lava
+    ///     .jobs()
+    ///     .submitted_before(cutoff)
+    ///     .query()
+    ///     .try_collect()
+    ///     .await
+    ///     .expect("failed to query jobs");
+    /// assert_eq!(jobs.len(), 2);
+    /// assert!(jobs.iter().all(|job| job.submit_time < cutoff));
+    /// # });
+    /// ```
+    pub fn submitted_before(mut self, when: chrono::DateTime<Utc>) -> Self {
+        self.query = self.query.submitted_before(when);
+        self
+    }
 
-        if let Some(id_after) = self.id_after {
-            url.query_pairs_mut()
-                .append_pair("id__gt", &id_after.to_string());
-        };
-        if let Some(started_after) = self.started_after {
-            url.query_pairs_mut()
-                .append_pair("start_time__gt", &started_after.to_rfc3339());
-        };
-        if let Some(submitted_after) = self.submitted_after {
-            url.query_pairs_mut()
-                .append_pair("submit_time__gt", &submitted_after.to_rfc3339());
-        };
-        if let Some(ended_after) = self.ended_after {
-            url.query_pairs_mut()
-                .append_pair("end_time__gt", &ended_after.to_rfc3339());
-        };
+    /// Return only jobs which started from `start` up to, but not
+    /// including, `end`.
+    ///
+    /// The server only compares times strictly, so this sets
+    /// [`started_after`](Self::started_after) one microsecond before
+    /// `start`, the resolution at which LAVA stores times, and
+    /// [`started_before`](Self::started_before) `end`, replacing any
+    /// start time filters set earlier. Consecutive windows sharing an
+    /// endpoint therefore neither overlap nor leave a gap between
+    /// them, which suits fetching jobs periodically. If `end` is not
+    /// after `start`, no jobs are returned; see
+    /// [`window`](Self::window) for a version which checks this.
+    ///
+    /// Example:
+    /// ```rust
+    /// use futures::stream::TryStreamExt;
+    /// use chrono::{DateTime, Duration, Utc};
+    /// use lava_api::Lava;
+    /// #
+    /// # tokio_test::block_on( async {
+    /// # let mock = lava_api_mock::doctest::jobs(|state| {
+    /// #     let start: DateTime<Utc> = "2022-03-17T17:00:00Z".parse().unwrap();
+    /// #     state.add_jobs(4, |i, job| {
+    /// #         job.start_time = Some(start + Duration::hours(i as i64));
+    /// #     });
+    /// # })
+    /// # .await;
+    /// # let service_uri = mock.uri();
+    /// let lava = Lava::new(&service_uri, None).expect("failed to make lava");
+    ///
+    /// let from: DateTime<Utc> = "2022-03-17T18:00:00Z".parse().unwrap();
+    /// let jobs: Vec<_> = lava
+    ///     .jobs()
+    ///     .started_between(from, from + Duration::hours(2))
+    ///     .query()
+    ///     .try_collect()
+    ///     .await
+    ///     .expect("failed to query jobs");
+    /// let starts: Vec<_> = jobs.iter().map(|job| job.start_time.unwrap()).collect();
+    /// assert_eq!(starts, vec![from, from + Duration::hours(1)]);
+    /// # });
+    /// ```
+    pub fn started_between(mut self, start: DateTime<Utc>, end: DateTime<Utc>) -> Self {
+        self.query = self.query.started_between(start, end);
+        self
+    }
 
-        let paginator = Paginator::new(self.lava.client.clone(), url);
-        Jobs {
-            lava: self.lava,
-            paginator,
-            state: PagingState::Paging,
-        }
+    /// Return only jobs submitted from `start` up to, but not
+    /// including, `end`.
+    ///
+    /// This replaces any submission time filters set earlier; see
+    /// [`started_between`](Self::started_between) for details.
+    pub fn submitted_between(mut self, start: DateTime<Utc>, end: DateTime<Utc>) -> Self {
+        self.query = self.query.submitted_between(start, end);
+        self
     }
-}
 
-async fn transform_job(job: LavaJob, lava: &Lava) -> Job {
-    let t = stream::iter(job.tags.iter());
-    let tags = t
-        .filter_map(|i| async move { lava.tag(*i).await })
-        .collect()
-        .await;
+    /// Return only jobs which ended from `start` up to, but not
+    /// including, `end`.
+    ///
+    /// This replaces any end time filters set earlier; see
+    /// [`started_between`](Self::started_between) for details.
+    pub fn ended_between(mut self, start: DateTime<Utc>, end: DateTime<Utc>) -> Self {
+        self.query = self.query.ended_between(start, end);
+        self
+    }
 
-    let t = stream::iter(job.failure_tags.iter());
-    let failure_tags = t
-        .filter_map(|i| async move { lava.tag(*i).await })
-        .collect()
-        .await;
+    /// Return only jobs whose priority is at least `priority`.
+    ///
+    /// Combined with [`priority_at_most`](Self::priority_at_most),
+    /// this selects a range of priorities.
+    ///
+    /// Example:
+    /// ```rust
+    /// use futures::stream::TryStreamExt;
+    /// use lava_api::Lava;
+    /// #
+    /// # tokio_test::block_on( async {
+    /// # let mock = lava_api_mock::doctest::jobs(|state| {
+    /// #     state.add_jobs(5, |i, job| job.priority = i as i64 * 25);
+    /// # })
+    /// # .await;
+    /// # let service_uri = mock.uri();
+    /// let lava = Lava::new(&service_uri, None).expect("failed to make lava");
+    ///
+    /// let jobs: Vec<_> = lava
+    ///     .jobs()
+    ///     .priority_at_least(25)
+    ///     .priority_at_most(75)
+    ///     .query()
+    ///     .try_collect()
+    ///     .await
+    ///     .expect("failed to query jobs");
+    /// let priorities: Vec<_> = jobs.iter().map(|job| job.priority).collect();
+    /// assert_eq!(priorities, vec![25, 50, 75]);
+    /// # });
+    /// ```
+    pub fn priority_at_least(mut self, priority: i64) -> Self {
+        self.query = self.query.priority_at_least(priority);
+        self
+    }
+
+    /// Return only jobs whose priority is at most `priority`.
+    ///
+    /// See [`priority_at_least`](Self::priority_at_least) for an
+    /// example.
+    pub fn priority_at_most(mut self, priority: i64) -> Self {
+        self.query = self.query.priority_at_most(priority);
+        self
+    }
+
+    /// Return only health checks if `health_check` is true, or only
+    /// other jobs if it is false.
+    ///
+    /// Example:
+    /// ```rust
+    /// use futures::stream::TryStreamExt;
+    /// use lava_api::Lava;
+    /// #
+    /// # tokio_test::block_on( async {
+    /// # let mock = lava_api_mock::doctest::jobs(|state| {
+    /// #     state.add_jobs(2, |_, job| job.health_check = true);
+    /// #     state.add_jobs(3, |_, job| job.health_check = false);
+    /// # })
+    /// # .await;
+    /// # let service_uri = mock.uri();
+    /// let lava = Lava::new(&service_uri, None).expect("failed to make lava");
+    ///
+    /// let jobs: Vec<_> = lava
+    ///     .jobs()
+    ///     .health_check(false)
+    ///     .query()
+    ///     .try_collect()
+    ///     .await
+    ///     .expect("failed to query jobs");
+    /// assert_eq!(jobs.len(), 3);
+    /// assert!(jobs.iter().all(|job| !job.health_check));
+    /// # });
+    /// ```
+    pub fn health_check(mut self, health_check: bool) -> Self {
+        self.query = self.query.health_check(health_check);
+        self
+    }
+
+    /// Return only jobs submitted by the named user.
+    ///
+    /// If called more than once, jobs submitted by any of the given
+    /// users are returned.
+    ///
+    /// Example:
+    /// ```rust
+    /// use futures::stream::TryStreamExt;
+    /// use lava_api::Lava;
+    /// #
+    /// # tokio_test::block_on( async {
+    /// # let mock = lava_api_mock::doctest::jobs(|state| {
+    /// #     state.add_jobs(5, |_, _| {});
+    /// # })
+    /// # .await;
+    /// # let service_uri = mock.uri();
+    /// let lava = Lava::new(&service_uri, None).expect("failed to make lava");
+    ///
+    /// let first = lava
+    ///     .jobs()
+    ///     .query()
+    ///     .try_next()
+    ///     .await
+    ///     .expect("failed to query jobs")
+    ///     .expect("no jobs");
+    ///
+    /// let jobs: Vec<_> = lava
+    ///     .jobs()
+    ///     .submitter(&first.submitter)
+    ///     .query()
+    ///     .try_collect()
+    ///     .await
+    ///     .expect("failed to query jobs");
+    /// assert!(jobs.contains(&first));
+    /// assert!(jobs.iter().all(|job| job.submitter == first.submitter));
+    /// # });
+    /// ```
+    pub fn submitter<T: Into<String>>(mut self, username: T) -> Self {
+        self.query = self.query.submitter(username);
+        self
+    }
+
+    /// Return only jobs which ran on the device with the given
+    /// hostname.
+    ///
+    /// If called more than once, jobs which ran on any of the given
+    /// devices are returned. Jobs which have not been scheduled on a
+    /// device are never returned.
+    ///
+    /// Example:
+    /// ```rust
+    /// use futures::stream::TryStreamExt;
+    /// use lava_api::Lava;
+    /// #
+    /// # tokio_test::block_on( async {
+    /// # let mock = lava_api_mock::doctest::jobs(|state| {
+    /// #     state.add_jobs(5, |_, _| {});
+    /// # })
+    /// # .await;
+    /// # let service_uri = mock.uri();
+    /// let lava = Lava::new(&service_uri, None).expect("failed to make lava");
+    ///
+    /// let first = lava
+    ///     .jobs()
+    ///     .query()
+    ///     .try_next()
+    ///     .await
+    ///     .expect("failed to query jobs")
+    ///     .expect("no jobs");
+    /// let hostname = first.actual_device.clone().expect("job has no device");
+    ///
+    /// let jobs: Vec<_> = lava
+    ///     .jobs()
+    ///     .actual_device(&hostname)
+    ///     .query()
+    ///     .try_collect()
+    ///     .await
+    ///     .expect("failed to query jobs");
+    /// assert!(jobs.contains(&first));
+    /// assert!(jobs.iter().all(|job| job.actual_device.as_ref() == Some(&hostname)));
+    /// # });
+    /// ```
+    pub fn actual_device<T: Into<String>>(mut self, hostname: T) -> Self {
+        self.query = self.query.actual_device(hostname);
+        self
+    }
+
+    /// Return only jobs requesting the named device type.
+    ///
+    /// If called more than once, jobs requesting any of the given
+    /// device types are returned.
+    ///
+    /// Example:
+    /// ```rust
+    /// use futures::stream::TryStreamExt;
+    /// use lava_api::Lava;
+    /// #
+    /// # tokio_test::block_on( async {
+    /// # let mock = lava_api_mock::doctest::jobs(|state| {
+    /// #     state.add_jobs(5, |_, _| {});
+    /// # })
+    /// # .await;
+    /// # let service_uri = mock.uri();
+    /// let lava = Lava::new(&service_uri, None).expect("failed to make lava");
+    ///
+    /// let first = lava
+    ///     .jobs()
+    ///     .query()
+    ///     .try_next()
+    ///     .await
+    ///     .expect("failed to query jobs")
+    ///     .expect("no jobs");
+    /// let device_type = first.requested_device_type.clone().expect("no device type");
+    ///
+    /// let jobs: Vec<_> = lava
+    ///     .jobs()
+    ///     .requested_device_type(&device_type)
+    ///     .query()
+    ///     .try_collect()
+    ///     .await
+    ///     .expect("failed to query jobs");
+    /// assert!(jobs.contains(&first));
+    /// assert!(jobs
+    ///     .iter()
+    ///     .all(|job| job.requested_device_type.as_ref() == Some(&device_type)));
+    /// # });
+    /// ```
+    pub fn requested_device_type<T: Into<String>>(mut self, device_type: T) -> Self {
+        self.query = self.query.requested_device_type(device_type);
+        self
+    }
+
+    /// Return only jobs requiring the given tag, by name or by id.
+    ///
+    /// The tags of a job are those a device must carry to run it. If
+    /// called more than once, jobs requiring any of the given tags
+    /// are returned. Tags given by name and tags given by id are
+    /// matched separately, so giving both returns only jobs requiring
+    /// one of each. Use [`tags_all`](Self::tags_all) to require
+    /// several tags at once.
+    ///
+    /// Example:
+    /// ```rust
+    /// use futures::stream::TryStreamExt;
+    /// # use boulder::{BuildableWithPersianRug, BuilderWithPersianRug};
+    /// # use lava_api_mock::{State, Tag};
+    /// # use persian_rug::Proxy;
+    /// use lava_api::Lava;
+    /// #
+    /// # tokio_test::block_on( async {
+    /// # let mock = lava_api_mock::doctest::jobs(|state| {
+    /// #     let (usb, _) = Proxy::<Tag<State>>::builder().id(100).name("usb").build(state.mutate());
+    /// #     state.add_jobs(2, |_, job| job.tags = vec![usb]);
+    /// #     state.add_jobs(3, |_, job| job.tags.clear());
+    /// # })
+    /// # .await;
+    /// # let service_uri = mock.uri();
+    /// let lava = Lava::new(&service_uri, None).expect("failed to make lava");
+    ///
+    /// let jobs: Vec<_> = lava
+    ///     .jobs()
+    ///     .tag("usb")
+    ///     .query()
+    ///     .try_collect()
+    ///     .await
+    ///     .expect("failed to query jobs");
+    /// assert_eq!(jobs.len(), 2);
+    /// assert!(jobs.iter().all(|job| job.tags.iter().any(|tag| tag.name == "usb")));
+    /// # });
+    /// ```
+    pub fn tag<T: Into<TagRef>>(mut self, tag: T) -> Self {
+        self.query = self.query.tag(tag);
+        self
+    }
+
+    /// Return only jobs requiring every one of the given tags, by
+    /// name or by id.
+    ///
+    /// Tags are given as for [`tag`](Self::tag). The server can only
+    /// check one of them, so the jobs it returns are checked for the
+    /// others as [`query`](Self::query) reads them; jobs dropped this
+    /// way still count towards [`Jobs::reported_items`]. The streams
+    /// of [`query_raw`](Self::query_raw) and
+    /// [`query_reduced`](Self::query_reduced) are not checked, and
+    /// can return jobs lacking all but one of the tags.
+    pub fn tags_all<I, T>(mut self, tags: I) -> Self
+    where
+        I: IntoIterator<Item = T>,
+        T: Into<TagRef>,
+    {
+        self.query = self.query.tags_all(tags);
+        self
+    }
+
+    /// Return only jobs which are marked as public on the server.
+    ///
+    /// Note that a public job can still be restricted to its viewing
+    /// groups; check [`Job::visibility`] to be certain that a job is
+    /// visible to everyone.
+    ///
+    /// Example:
+    /// ```rust
+    /// use futures::stream::TryStreamExt;
+    /// use lava_api::Lava;
+    /// #
+    /// # tokio_test::block_on( async {
+    /// # let mock = lava_api_mock::doctest::jobs(|state| {
+    /// #     state.add_jobs(3, |i, job| {
+    /// #         job.is_public = i != 0;
+    /// #         job.viewing_groups.clear();
+    /// #     });
+    /// # })
+    /// # .await;
+    /// # let service_uri = mock.uri();
+    /// let lava = Lava::new(&service_uri, None).expect("failed to make lava");
+    ///
+    /// let jobs: Vec<_> = lava
+    ///     .jobs()
+    ///     .viewing_public_only()
+    ///     .query()
+    ///     .try_collect()
+    ///     .await
+    ///     .expect("failed to query jobs");
+    /// assert_eq!(jobs.len(), 2);
+    /// assert!(jobs.iter().all(|job| job.visibility.is_public()));
+    /// # });
+    /// ```
+    pub fn viewing_public_only(mut self) -> Self {
+        self.query = self.query.viewing_public_only();
+        self
+    }
+
+    /// Return only jobs requesting a device type which is displayed
+    /// on the server.
+    ///
+    /// Device types which have been retired, or which only exist for
+    /// testing, are usually hidden from the LAVA web interface, and
+    /// this excludes their jobs from the results. Jobs which do not
+    /// request a device type are also excluded.
+    ///
+    /// Example:
+    /// ```rust
+    /// use futures::stream::TryStreamExt;
+    /// use lava_api::Lava;
+    /// #
+    /// # tokio_test::block_on( async {
+    /// # let mock = lava_api_mock::doctest::jobs(|state| {
+    /// #     state.add_jobs(2, |_, job| job.requested_device_type = None);
+    /// #     state.add_jobs(3, |_, _| {});
+    /// # })
+    /// # .await;
+    /// # let service_uri = mock.uri();
+    /// let lava = Lava::new(&service_uri, None).expect("failed to make lava");
+    ///
+    /// let jobs: Vec<_> = lava
+    ///     .jobs()
+    ///     .displayed_device_types_only()
+    ///     .query()
+    ///     .try_collect()
+    ///     .await
+    ///     .expect("failed to query jobs");
+    /// assert!(jobs.iter().all(|job| job.requested_device_type.is_some()));
+    /// # });
+    /// ```
+    pub fn displayed_device_types_only(mut self) -> Self {
+        self.query = self.query.displayed_device_types_only();
+        self
+    }
+
+    /// Page through the results by job id, rather than by offset.
+    ///
+    /// By default, each page of results is requested by its offset
+    /// into the result set, which produces the artifacts described
+    /// for [`limit`](Self::limit) when the result set changes during
+    /// the query. With stable pagination, each page is instead
+    /// requested as the jobs following the last job received, so
+    /// that no job is duplicated or missed at a page boundary. Jobs
+    /// are then always ordered by id, ascending unless a descending
+    /// order is given to [`ordering`](Self::ordering), and the order
+    /// key given there is ignored.
+    pub fn stable_pagination(mut self) -> Self {
+        self.query = self.query.stable_pagination();
+        self
+    }
+
+    /// Add a query parameter which is passed to the server unchanged.
+    ///
+    /// This gives access to filters the server supports which are
+    /// not otherwise available here, for example
+    /// `description__icontains`. Parameters are added after
+    /// those set by the other methods, and the server's handling of
+    /// conflicting parameters varies, so they are best used for
+    /// fields no other method filters on.
+    ///
+    /// Example:
+    /// ```rust
+    /// use futures::stream::TryStreamExt;
+    /// use lava_api::Lava;
+    /// #
+    /// # tokio_test::block_on( async {
+    /// # let mock = lava_api_mock::doctest::jobs(|state| {
+    /// #     state.add_jobs(2, |i, job| job.description = format!("Boot test {}", i));
+    /// #     state.add_jobs(1, |_, job| job.description = "Smoke test".to_string());
+    /// # })
+    /// # .await;
+    /// # let service_uri = mock.uri();
+    /// let lava = Lava::new(&service_uri, None).expect("failed to make lava");
+    ///
+    /// let jobs: Vec<_> = lava
+    ///     .jobs()
+    ///     .raw_param("description__icontains", "boot")
+    ///     .query()
+    ///     .try_collect()
+    ///     .await
+    ///     .expect("failed to query jobs");
+    /// let ids: Vec<_> = jobs.iter().map(|job| job.id).collect();
+    /// assert_eq!(ids, vec![0, 1]);
+    /// # });
+    /// ```
+    pub fn raw_param<K: Into<String>, V: Into<String>>(mut self, key: K, value: V) -> Self {
+        self.query = self.query.raw_param(key, value);
+        self
+    }
+
+    /// Order returned jobs by the given key.
+    ///
+    /// This replaces any keys given before, including those given to
+    /// [`then_by`](Self::then_by).
+    pub fn ordering(mut self, ordering: Ordering, ascending: bool) -> Self {
+        self.query = self.query.ordering(ordering, ascending);
+        self
+    }
+
+    /// Order jobs which are equal in every key given so far by
+    /// another key.
+    ///
+    /// Example:
+    /// ```rust
+    /// use futures::stream::TryStreamExt;
+    /// # use lava_api_mock::{LavaMock, PaginationLimits, PopulationParams, SharedState};
+    /// use lava_api::job::Ordering;
+    /// use lava_api::Lava;
+    /// #
+    /// # tokio_test::block_on( async {
+    /// # let limits = PaginationLimits::new();
+    /// # let population = PopulationParams::new();
+    /// # let mock = LavaMock::new(SharedState::new_populated(population), limits).await;
+    /// # let service_uri = mock.uri();
+    /// # let lava_token = None;
+    ///
+    /// let lava = Lava::new(&service_uri, lava_token).expect("failed to make lava");
+    ///
+    /// // The most urgent jobs first, oldest first among equals
+    /// let jobs: Vec<_> = lava
+    ///     .jobs()
+    ///     .ordering(Ordering::Priority, false)
+    ///     .then_by(Ordering::SubmitTime, true)
+    ///     .query()
+    ///     .try_collect()
+    ///     .await
+    ///     .expect("failed to query jobs");
+    /// for pair in jobs.windows(2) {
+    ///     assert!(pair[0].priority >= pair[1].priority);
+    ///     if pair[0].priority == pair[1].priority {
+    ///         assert!(pair[0].submit_time <= pair[1].submit_time);
+    ///     }
+    /// }
+    /// # });
+    /// ```
+    ///
+    /// As with [`ordering`](Self::ordering), the keys are ignored
+    /// with [`stable_pagination`](Self::stable_pagination).
+    pub fn then_by(mut self, ordering: Ordering, ascending: bool) -> Self {
+        self.query = self.query.then_by(ordering, ascending);
+        self
+    }
+
+    /// Apply the settings from a [`JobsQueryConfig`].
+    ///
+    /// See [`JobsQuery::apply`] for details.
+    pub fn apply(mut self, config: &JobsQueryConfig) -> Self {
+        self.query = self.query.apply(config);
+        self
+    }
+
+    /// Begin querying for jobs, returning a [`Jobs`] instance
+    pub fn query(self) -> Jobs<'a> {
+        let paginator = self.paginator(self.url(), |job: &LavaJob| job.id.to_string());
+        Jobs {
+            lava: self.lava,
+            paginator,
+            state: PagingState::Paging,
+            yielded: 0,
+            consumed: 0,
+            page: 0,
+            tags: HashMap::new(),
+            pending: None,
+            required: self.query.tags,
+        }
+    }
+
+    /// Begin querying for jobs, returning a [`RawJob`] for each,
+    /// whose tags are left as ids.
+    ///
+    /// This skips looking up the tags of each page of jobs, which
+    /// [`query`](Self::query) does using the tag cache of the
+    /// [`Lava`] instance. The returned stream does not borrow the
+    /// [`Lava`] instance, so it can be moved into a spawned task.
+    pub fn query_raw(self) -> Paginator<RawJob> {
+        self.paginator(self.url(), |job: &RawJob| job.id.to_string())
+    }
+
+    /// Begin querying for jobs, returning only a [`ReducedJob`] for
+    /// each.
+    ///
+    /// The server is asked for just the fields of [`ReducedJob`],
+    /// using the `fields` query parameter. Servers which do not
+    /// support selecting fields ignore it and send complete jobs,
+    /// from which the same fields are read, so this works with any
+    /// server, though without saving any bandwidth.
+    pub fn query_reduced(self) -> Paginator<ReducedJob> {
+        let mut url = self.url();
+        url.query_pairs_mut()
+            .append_pair("fields", &REDUCED_FIELDS.join(","));
+
+        self.paginator(url, |job: &ReducedJob| job.id.to_string())
+    }
+
+    /// Split the query into up to `shards` queries for disjoint
+    /// ranges of job ids, which can be read concurrently.
+    ///
+    /// This is for reading very many jobs, such as when first
+    /// exporting the history of a server, more quickly than a single
+    /// stream reading one page at a time. The server is first asked
+    /// for the lowest and highest ids of the matching jobs, and that
+    /// range is split evenly, so shards can differ in size when ids
+    /// are unevenly spread among the matching jobs. Each shard is read
+    /// in ascending order of id, using
+    /// [`stable_pagination`](Self::stable_pagination), whatever the
+    /// ordering given here.
+    ///
+    /// Jobs submitted after the range is found are not returned. If
+    /// no jobs match, no shards are returned.
+    ///
+    /// The shards can be read together with [`merge_shards`]. Each
+    /// makes its own requests, so reading many shards at once puts a
+    /// corresponding load on the server, which can be bounded with
+    /// [`LavaBuilder::rate_limit`](crate::LavaBuilder::rate_limit).
+    pub async fn sharded(self, shards: usize) -> Result<Vec<Jobs<'a>>, PaginationError> {
+        let bound = |ascending| {
+            let mut query = self
+                .query
+                .clone()
+                .ordering(Ordering::Id, ascending)
+                .limit(1);
+            query.stable = false;
+            JobsBuilder {
+                lava: self.lava,
+                query,
+            }
+            .query_reduced()
+        };
+        let first = bound(true).try_next().await?;
+        let last = bound(false).try_next().await?;
+        let (first, last) = match (first, last) {
+            (Some(first), Some(last)) => (first.id, last.id),
+            _ => return Ok(Vec::new()),
+        };
+
+        let shards = i64::try_from(shards.max(1)).unwrap_or(i64::MAX);
+        let span = last - first + 1;
+        let width = span / shards + i64::from(span % shards != 0);
+        let mut lower = first - 1;
+        let mut queries = Vec::new();
+        while lower < last {
+            let upper = last.min(lower + width);
+            let mut query = self
+                .query
+                .clone()
+                .id_after(lower)
+                .ordering(Ordering::Id, true)
+                .stable_pagination();
+            query.id_before = Some(upper + 1);
+            queries.push(
+                JobsBuilder {
+                    lava: self.lava,
+                    query,
+                }
+                .query(),
+            );
+            lower = upper;
+        }
+        Ok(queries)
+    }
+
+    /// Count the jobs matching the query, without reading them.
+    ///
+    /// The server is asked for a single job, and the number of
+    /// matching jobs it reports with it is returned, so any
+    /// [`limit`](Self::limit) is ignored. The server is only given one
+    /// of the tags passed to [`tags_all`](Self::tags_all), as they are
+    /// otherwise checked as the jobs are read, so the count then
+    /// includes jobs carrying just some of them.
+    pub async fn count(self) -> Result<u32, PaginationError> {
+        let mut query = self.query.limit(1);
+        query.stable = false;
+        let url = JobsBuilder {
+            lava: self.lava,
+            query,
+        }
+        .url();
+        let mut jobs: Paginator<IgnoredAny> = self.lava.paginator(url);
+        jobs.try_next().await?;
+        Ok(jobs.reported_items().unwrap_or_default())
+    }
+
+    /// Begin querying for the jobs which started in the window from
+    /// `start` up to, but not including, `end`.
+    ///
+    /// This is [`started_between`](Self::started_between), but fails
+    /// for an empty window rather than returning no jobs, and also
+    /// returns the url of the query with the jobs, so that reports
+    /// can record exactly how their figures were obtained.
+    ///
+    /// Example:
+    /// ```rust
+    /// use chrono::{DateTime, Utc};
+    /// use futures::stream::TryStreamExt;
+    /// use lava_api::Lava;
+    /// #
+    /// # tokio_test::block_on( async {
+    /// # let mock = lava_api_mock::doctest::jobs(|state| {
+    /// #     for hour in 16..20 {
+    /// #         let start: DateTime<Utc> = format!("2022-03-17T{}:00:00Z", hour).parse().unwrap();
+    /// #         state.add_jobs(1, |_, job| job.start_time = Some(start));
+    /// #     }
+    /// # })
+    /// # .await;
+    /// # let service_uri = mock.uri();
+    /// let lava = Lava::new(&service_uri, None).expect("failed to make lava");
+    ///
+    /// let start: DateTime<Utc> = "2022-03-17T17:00:00Z".parse().unwrap();
+    /// let end: DateTime<Utc> = "2022-03-17T19:00:00Z".parse().unwrap();
+    /// let window = lava.jobs().window(start, end).expect("empty window");
+    /// println!("Reading jobs from {}", window.url);
+    ///
+    /// let jobs: Vec<_> = window.jobs.try_collect().await.expect("failed to query jobs");
+    /// assert_eq!(jobs.len(), 2);
+    /// assert!(lava.jobs().window(end, start).is_err());
+    /// # });
+    /// ```
+    pub fn window(
+        mut self,
+        start: DateTime<Utc>,
+        end: DateTime<Utc>,
+    ) -> Result<JobsWindow<'a>, WindowError> {
+        if start >= end {
+            return Err(WindowError::Empty { start, end });
+        }
+        self.query = self.query.started_between(start, end);
+        Ok(JobsWindow {
+            url: self.url(),
+            jobs: self.query(),
+        })
+    }
+
+    fn url(&self) -> Url {
+        let mut url = self
+            .lava
+            .base
+            .join("jobs/")
+            .expect("Failed to append to base url");
+        self.query.append_to(&mut url);
+        url
+    }
+
+    // Make a paginator for `url`, continuing by the job id given by
+    // `id` if stable pagination was requested.
+    fn paginator<T>(&self, url: Url, id: fn(&T) -> String) -> Paginator<T>
+    where
+        T: DeserializeOwned + 'static,
+    {
+        let paginator = self.lava.paginator(url);
+        match (self.query.stable, self.query.ascending) {
+            (false, _) => paginator,
+            (true, true) => paginator.keyset("id__gt", id),
+            (true, false) => paginator.keyset("id__lt", id),
+        }
+    }
+}
+
+/// The jobs which started in a window of time, returned by
+/// [`JobsBuilder::window`]
+pub struct JobsWindow<'a> {
+    /// The url of the query made for the jobs
+    pub url: Url,
+    pub jobs: Jobs<'a>,
+}
+
+#[derive(Error, Debug)]
+pub enum WindowError {
+    #[error("Window start {start} is not before its end {end}")]
+    Empty {
+        start: DateTime<Utc>,
+        end: DateTime<Utc>,
+    },
+}
+
+/// A selection of [`Job`] instances, independent of any server.
+///
+/// This holds the same settings as a [`JobsBuilder`], but can be
+/// constructed (even in a `const` context) without a [`Lava`], and
+/// then applied later using [`JobsBuilder::with_query`] or
+/// [`Lava::jobs_with`](crate::Lava::jobs_with). See the
+/// corresponding methods of [`JobsBuilder`] for details of each
+/// setting.
+///
+/// Example:
+/// ```rust
+/// use lava_api::job::{JobsQuery, State};
+///
+/// const QUEUED: JobsQuery = JobsQuery::new();
+///
+/// let query = QUEUED.state(State::Submitted).limit(10);
+/// ```
+#[derive(Debug, Clone)]
+pub struct JobsQuery {
+    states: QuerySet<State>,
+    healths: QuerySet<Health>,
+    limit: Option<u32>,
+    ordering: Ordering,
+    // Further keys for jobs which are equal in the ordering, and
+    // whether each is ascending
+    then_by: Vec<(Ordering, bool)>,
+    ids: ValueSet<i64>,
+    id_after: Option<i64>,
+    // The exclusive upper bound of the ids of a shard made by
+    // `sharded`
+    id_before: Option<i64>,
+    started_after: Option<DateTime<Utc>>,
+    submitted_after: Option<DateTime<Utc>>,
+    ended_after: Option<DateTime<Utc>>,
+    started_before: Option<DateTime<Utc>>,
+    submitted_before: Option<DateTime<Utc>>,
+    ended_before: Option<DateTime<Utc>>,
+    priority_at_least: Option<i64>,
+    priority_at_most: Option<i64>,
+    health_check: Option<bool>,
+    submitters: ValueSet<String>,
+    actual_devices: ValueSet<String>,
+    requested_device_types: ValueSet<String>,
+    tags: TagFilter,
+    public_only: bool,
+    displayed_only: bool,
+    stable: bool,
+    ascending: bool,
+    raw_params: Vec<(String, String)>,
+}
+
+impl JobsQuery {
+    /// Create a new [`JobsQuery`]
+    ///
+    /// The default query is the same as for [`JobsBuilder::new`].
+    pub const fn new() -> Self {
+        Self {
+            states: QuerySet::new("state"),
+            healths: QuerySet::new("health"),
+            limit: None,
+            ordering: Ordering::Id,
+            then_by: Vec::new(),
+            ids: ValueSet::new("id"),
+            id_after: None,
+            id_before: None,
+            started_after: None,
+            submitted_after: None,
+            ended_after: None,
+            started_before: None,
+            submitted_before: None,
+            ended_before: None,
+            priority_at_least: None,
+            priority_at_most: None,
+            health_check: None,
+            submitters: ValueSet::new("submitter__username"),
+            actual_devices: ValueSet::new("actual_device__hostname"),
+            requested_device_types: ValueSet::new("requested_device_type__name"),
+            tags: TagFilter::new(),
+            public_only: false,
+            displayed_only: false,
+            stable: false,
+            ascending: true,
+            raw_params: Vec::new(),
+        }
+    }
+
+    /// Return jobs in this state.
+    pub fn state(mut self, state: State) -> Self {
+        self.states.include(state);
+        self
+    }
+
+    /// Exclude jobs in this state.
+    pub fn state_not(mut self, state: State) -> Self {
+        self.states.exclude(&state);
+        self
+    }
+
+    /// Set the page size used when retrieving jobs.
+    ///
+    /// See [`JobsBuilder::limit`] for the caveats of paging.
+    pub fn limit(mut self, limit: u32) -> Self {
+        self.limit = Some(limit);
+        self
+    }
+
+    /// Return jobs with this health.
+    pub fn health(mut self, health: Health) -> Self {
+        self.healths.include(health);
+        self
+    }
+
+    /// Exclude jobs with this health.
+    pub fn health_not(mut self, health: Health) -> Self {
+        self.healths.exclude(&health);
+        self
+    }
+
+    /// Return only jobs whose id is `id`.
+    pub fn id(mut self, id: i64) -> Self {
+        self.ids.include(id);
+        self
+    }
+
+    /// Return only jobs whose id is one of `ids`.
+    pub fn ids(mut self, ids: &[i64]) -> Self {
+        for id in ids {
+            self.ids.include(*id);
+        }
+        self
+    }
+
+    /// Return only jobs whose id is strictly greater than `id`.
+    pub fn id_after(mut self, id: i64) -> Self {
+        self.id_after = Some(id);
+        self
+    }
+
+    /// Return only jobs whose start time is strictly after the given
+    /// instant.
+    pub fn started_after(mut self, when: chrono::DateTime<Utc>) -> Self {
+        self.started_after = Some(when);
+        self
+    }
+
+    /// Return only jobs whose submission time is strictly after the
+    /// given instant.
+    pub fn submitted_after(mut self, when: chrono::DateTime<Utc>) -> Self {
+        self.submitted_after = Some(when);
+        self
+    }
+
+    /// Return only jobs which ended strictly after the given instant.
+    pub fn ended_after(mut self, when: chrono::DateTime<Utc>) -> Self {
+        self.ended_after = Some(when);
+        self
+    }
+
+    /// Return only jobs whose start time is strictly before the given
+    /// instant.
+    pub fn started_before(mut self, when: chrono::DateTime<Utc>) -> Self {
+        self.started_before = Some(when);
+        self
+    }
 
-    Job {
-        id: job.id,
-        submitter: job.submitter,
-        viewing_groups: job.viewing_groups,
-        description: job.description,
-        health_check: job.health_check,
-        requested_device_type: job.requested_device_type,
-        tags,
-        actual_device: job.actual_device,
-        submit_time: job.submit_time,
-        start_time: job.start_time,
-        end_time: job.end_time,
-        state: job.state,
-        health: job.health,
-        priority: job.priority,
-        definition: job.definition,
-        original_definition: job.original_definition,
-        multinode_definition: job.multinode_definition,
-        failure_tags,
-        failure_comment: job.failure_comment,
+    /// Return only jobs whose submission time is strictly before the
+    /// given instant.
+    pub fn submitted_before(mut self, when: chrono::DateTime<Utc>) -> Self {
+        self.submitted_before = Some(when);
+        self
+    }
+
+    /// Return only jobs which ended strictly before the given
+    /// instant.
+    pub fn ended_before(mut self, when: chrono::DateTime<Utc>) -> Self {
+        self.ended_before = Some(when);
+        self
+    }
+
+    /// Return only jobs which started from `start` up to, but not
+    /// including, `end`.
+    ///
+    /// See [`JobsBuilder::started_between`].
+    pub fn started_between(self, start: DateTime<Utc>, end: DateTime<Utc>) -> Self {
+        self.started_after(just_before(start)).started_before(end)
+    }
+
+    /// Return only jobs submitted from `start` up to, but not
+    /// including, `end`.
+    ///
+    /// See [`JobsBuilder::submitted_between`].
+    pub fn submitted_between(self, start: DateTime<Utc>, end: DateTime<Utc>) -> Self {
+        self.submitted_after(just_before(start))
+            .submitted_before(end)
+    }
+
+    /// Return only jobs which ended from `start` up to, but not
+    /// including, `end`.
+    ///
+    /// See [`JobsBuilder::ended_between`].
+    pub fn ended_between(self, start: DateTime<Utc>, end: DateTime<Utc>) -> Self {
+        self.ended_after(just_before(start)).ended_before(end)
+    }
+
+    /// Return only jobs whose priority is at least `priority`.
+    pub fn priority_at_least(mut self, priority: i64) -> Self {
+        self.priority_at_least = Some(priority);
+        self
+    }
+
+    /// Return only jobs whose priority is at most `priority`.
+    pub fn priority_at_most(mut self, priority: i64) -> Self {
+        self.priority_at_most = Some(priority);
+        self
+    }
+
+    /// Return only health checks if `health_check` is true, or only
+    /// other jobs if it is false.
+    pub fn health_check(mut self, health_check: bool) -> Self {
+        self.health_check = Some(health_check);
+        self
+    }
+
+    /// Return only jobs submitted by the named user.
+    ///
+    /// If called more than once, jobs submitted by any of the given
+    /// users are returned.
+    pub fn submitter<T: Into<String>>(mut self, username: T) -> Self {
+        self.submitters.include(username.into());
+        self
+    }
+
+    /// Return only jobs which ran on the device with the given
+    /// hostname.
+    ///
+    /// If called more than once, jobs which ran on any of the given
+    /// devices are returned.
+    pub fn actual_device<T: Into<String>>(mut self, hostname: T) -> Self {
+        self.actual_devices.include(hostname.into());
+        self
+    }
+
+    /// Return only jobs requesting the named device type.
+    ///
+    /// If called more than once, jobs requesting any of the given
+    /// device types are returned.
+    pub fn requested_device_type<T: Into<String>>(mut self, device_type: T) -> Self {
+        self.requested_device_types.include(device_type.into());
+        self
+    }
+
+    /// Return only jobs requiring the given tag, by name or by id.
+    ///
+    /// If called more than once, jobs requiring any of the given tags
+    /// are returned.
+    pub fn tag<T: Into<TagRef>>(mut self, tag: T) -> Self {
+        self.tags.any(tag.into());
+        self
+    }
+
+    /// Return only jobs requiring every one of the given tags, by
+    /// name or by id.
+    ///
+    /// See [`JobsBuilder::tags_all`] for how far the server can
+    /// check this.
+    pub fn tags_all<I, T>(mut self, tags: I) -> Self
+    where
+        I: IntoIterator<Item = T>,
+        T: Into<TagRef>,
+    {
+        for tag in tags {
+            self.tags.all(tag.into());
+        }
+        self
+    }
+
+    /// Return only jobs which are marked as public on the server.
+    pub fn viewing_public_only(mut self) -> Self {
+        self.public_only = true;
+        self
+    }
+
+    /// Return only jobs requesting a device type which is displayed
+    /// on the server.
+    pub fn displayed_device_types_only(mut self) -> Self {
+        self.displayed_only = true;
+        self
+    }
+
+    /// Page through the results by job id, rather than by offset.
+    pub fn stable_pagination(mut self) -> Self {
+        self.stable = true;
+        self
+    }
+
+    /// Add a query parameter which is passed to the server unchanged.
+    ///
+    /// See [`JobsBuilder::raw_param`].
+    pub fn raw_param<K: Into<String>, V: Into<String>>(mut self, key: K, value: V) -> Self {
+        self.raw_params.push((key.into(), value.into()));
+        self
+    }
+
+    /// Order returned jobs by the given key.
+    ///
+    /// See [`JobsBuilder::ordering`].
+    pub fn ordering(mut self, ordering: Ordering, ascending: bool) -> Self {
+        self.ordering = ordering;
+        self.ascending = ascending;
+        self.then_by.clear();
+        self
+    }
+
+    /// Order jobs which are equal in every key given so far by
+    /// another key.
+    ///
+    /// See [`JobsBuilder::then_by`].
+    pub fn then_by(mut self, ordering: Ordering, ascending: bool) -> Self {
+        self.then_by.push((ordering, ascending));
+        self
+    }
+
+    fn append_to(&self, url: &mut Url) {
+        let key = |ordering: Ordering, ascending: bool| match ascending {
+            true => ordering.to_string(),
+            false => format!("-{}", ordering),
+        };
+        let ordering = match self.stable {
+            true => key(Ordering::Id, self.ascending),
+            false => std::iter::once(key(self.ordering, self.ascending))
+                .chain(self.then_by.iter().map(|(o, a)| key(*o, *a)))
+                .collect::<Vec<_>>()
+                .join(","),
+        };
+        url.query_pairs_mut().append_pair("ordering", &ordering);
+        if let Some(pair) = self.states.query() {
+            url.query_pairs_mut().append_pair(&pair.0, &pair.1);
+        }
+        if let Some(limit) = self.limit {
+            url.query_pairs_mut()
+                .append_pair("limit", &limit.to_string());
+        };
+        if let Some(pair) = self.healths.query() {
+            url.query_pairs_mut().append_pair(&pair.0, &pair.1);
+        }
+
+        self.ids.append_to(url);
+
+        if let Some(id_after) = self.id_after {
+            url.query_pairs_mut()
+                .append_pair("id__gt", &id_after.to_string());
+        };
+        if let Some(id_before) = self.id_before {
+            url.query_pairs_mut()
+                .append_pair("id__lt", &id_before.to_string());
+        };
+        if let Some(started_after) = self.started_after {
+            url.query_pairs_mut()
+                .append_pair("start_time__gt", &started_after.to_rfc3339());
+        };
+        if let Some(submitted_after) = self.submitted_after {
+            url.query_pairs_mut()
+                .append_pair("submit_time__gt", &submitted_after.to_rfc3339());
+        };
+        if let Some(ended_after) = self.ended_after {
+            url.query_pairs_mut()
+                .append_pair("end_time__gt", &ended_after.to_rfc3339());
+        };
+        if let Some(started_before) = self.started_before {
+            url.query_pairs_mut()
+                .append_pair("start_time__lt", &started_before.to_rfc3339());
+        };
+        if let Some(submitted_before) = self.submitted_before {
+            url.query_pairs_mut()
+                .append_pair("submit_time__lt", &submitted_before.to_rfc3339());
+        };
+        if let Some(ended_before) = self.ended_before {
+            url.query_pairs_mut()
+                .append_pair("end_time__lt", &ended_before.to_rfc3339());
+        };
+        if let Some(priority) = self.priority_at_least {
+            url.query_pairs_mut()
+                .append_pair("priority__gte", &priority.to_string());
+        }
+        if let Some(priority) = self.priority_at_most {
+            url.query_pairs_mut()
+                .append_pair("priority__lte", &priority.to_string());
+        }
+        if let Some(health_check) = self.health_check {
+            url.query_pairs_mut()
+                .append_pair("health_check", &health_check.to_string());
+        }
+        self.submitters.append_to(url);
+        self.actual_devices.append_to(url);
+        self.requested_device_types.append_to(url);
+        self.tags.append_to(url);
+        if self.public_only {
+            url.query_pairs_mut().append_pair("is_public", "true");
+        }
+        if self.displayed_only {
+            url.query_pairs_mut()
+                .append_pair("requested_device_type__display", "true");
+        }
+        for (key, value) in self.raw_params.iter() {
+            url.query_pairs_mut().append_pair(key, value);
+        }
     }
 }
 
+/// Settings for a [`JobsQuery`] which can be loaded from a
+/// configuration file.
+///
+/// Every field is optional, and the settings are applied to a query
+/// with [`JobsQuery::apply`] or [`JobsBuilder::apply`].
+///
+/// Example:
+/// ```rust
+/// use lava_api::job::{JobsQuery, JobsQueryConfig};
+///
+/// let config: JobsQueryConfig = serde_yaml::from_str(
+///     r#"
+/// states: [Submitted, Scheduled]
+/// health_not: [Canceled]
+/// ordering: submit_time
+/// descending: true
+/// limit: 50
+/// "#,
+/// )
+/// .expect("failed to parse config");
+///
+/// let query = JobsQuery::new().apply(&config);
+/// ```
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Eq)]
+#[serde(default, deny_unknown_fields)]
+pub struct JobsQueryConfig {
+    /// Return jobs in these states
+    pub states: Vec<State>,
+    /// Exclude jobs in these states
+    pub state_not: Vec<State>,
+    /// Return jobs with these healths
+    pub healths: Vec<Health>,
+    /// Exclude jobs with these healths
+    pub health_not: Vec<Health>,
+    pub limit: Option<u32>,
+    pub ids: Vec<i64>,
+    pub id_after: Option<i64>,
+    pub started_after: Option<DateTime<Utc>>,
+    pub submitted_after: Option<DateTime<Utc>>,
+    pub ended_after: Option<DateTime<Utc>>,
+    pub started_before: Option<DateTime<Utc>>,
+    pub submitted_before: Option<DateTime<Utc>>,
+    pub ended_before: Option<DateTime<Utc>>,
+    pub priority_at_least: Option<i64>,
+    pub priority_at_most: Option<i64>,
+    /// Return only health checks if true, or only other jobs if
+    /// false
+    pub health_check: Option<bool>,
+    /// Return jobs submitted by any of these users
+    pub submitters: Vec<String>,
+    /// Return jobs which ran on any of these devices
+    pub actual_devices: Vec<String>,
+    /// Return jobs requesting any of these device types
+    pub requested_device_types: Vec<String>,
+    /// Return jobs requiring any of these tags
+    pub tags: Vec<String>,
+    /// Return only jobs requiring all of these tags
+    pub tags_all: Vec<String>,
+    pub viewing_public_only: bool,
+    pub displayed_device_types_only: bool,
+    pub stable_pagination: bool,
+    pub ordering: Option<Ordering>,
+    /// Reverse the [`ordering`](JobsQueryConfig::ordering)
+    pub descending: bool,
+    /// Query parameters passed to the server unchanged; see
+    /// [`JobsBuilder::raw_param`]
+    pub raw_params: BTreeMap<String, String>,
+}
+
+impl JobsQuery {
+    /// Apply the settings from a [`JobsQueryConfig`] to this query.
+    ///
+    /// Settings in the configuration are added to those already
+    /// present, in the same way as calling the corresponding methods
+    /// would.
+    pub fn apply(mut self, config: &JobsQueryConfig) -> Self {
+        for state in config.states.iter() {
+            self = self.state(*state);
+        }
+        for state in config.state_not.iter() {
+            self = self.state_not(*state);
+        }
+        for health in config.healths.iter() {
+            self = self.health(*health);
+        }
+        for health in config.health_not.iter() {
+            self = self.health_not(*health);
+        }
+        if let Some(limit) = config.limit {
+            self = self.limit(limit);
+        }
+        for id in config.ids.iter() {
+            self = self.id(*id);
+        }
+        if let Some(id) = config.id_after {
+            self = self.id_after(id);
+        }
+        if let Some(when) = config.started_after {
+            self = self.started_after(when);
+        }
+        if let Some(when) = config.submitted_after {
+            self = self.submitted_after(when);
+        }
+        if let Some(when) = config.ended_after {
+            self = self.ended_after(when);
+        }
+        if let Some(when) = config.started_before {
+            self = self.started_before(when);
+        }
+        if let Some(when) = config.submitted_before {
+            self = self.submitted_before(when);
+        }
+        if let Some(when) = config.ended_before {
+            self = self.ended_before(when);
+        }
+        if let Some(priority) = config.priority_at_least {
+            self = self.priority_at_least(priority);
+        }
+        if let Some(priority) = config.priority_at_most {
+            self = self.priority_at_most(priority);
+        }
+        if let Some(health_check) = config.health_check {
+            self = self.health_check(health_check);
+        }
+        for username in config.submitters.iter() {
+            self = self.submitter(username);
+        }
+        for hostname in config.actual_devices.iter() {
+            self = self.actual_device(hostname);
+        }
+        for device_type in config.requested_device_types.iter() {
+            self = self.requested_device_type(device_type);
+        }
+        for tag in config.tags.iter() {
+            self = self.tag(tag);
+        }
+        self = self.tags_all(config.tags_all.iter());
+        if config.viewing_public_only {
+            self = self.viewing_public_only();
+        }
+        if config.displayed_device_types_only {
+            self = self.displayed_device_types_only();
+        }
+        if config.stable_pagination {
+            self = self.stable_pagination();
+        }
+        if let Some(ordering) = config.ordering {
+            self = self.ordering(ordering, !config.descending);
+        }
+        for (key, value) in config.raw_params.iter() {
+            self = self.raw_param(key, value);
+        }
+        self
+    }
+}
+
+// The server only compares times strictly, so an inclusive lower
+// bound is given as the previous microsecond, the resolution at which
+// LAVA stores times.
+fn just_before(when: DateTime<Utc>) -> DateTime<Utc> {
+    when - chrono::Duration::microseconds(1)
+}
+
+impl Default for JobsQuery {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// The ids of all the tags a job refers to.
+fn tag_ids(job: &LavaJob) -> impl Iterator<Item = u32> + '_ {
+    job.tags.iter().chain(job.failure_tags.iter()).copied()
+}
+
+fn transform_job(job: LavaJob, tags: &HashMap<u32, Tag>) -> Job {
+    RawJob::from(job).resolve(tags)
+}
+
 impl<'a> Stream for Jobs<'a> {
     type Item = Result<Job, PaginationError>;
 
@@ -450,17 +2513,43 @@ impl<'a> Stream for Jobs<'a> {
                     match p.poll_next(cx) {
                         Poll::Ready(None) => Poll::Ready(None),
                         Poll::Ready(Some(Err(e))) => Poll::Ready(Some(Err(e))),
-                        Poll::Ready(Some(Ok(d))) => {
-                            me.state = PagingState::Transforming(transform_job(d, me.lava).boxed());
+                        Poll::Ready(Some(Ok(d))) if me.page != me.paginator.fetched_pages() => {
+                            // Resolve the tags of the whole page at
+                            // once, so that the tag cache is refreshed
+                            // at most once per page.
+                            me.page = me.paginator.fetched_pages();
+                            let ids = std::iter::once(&d)
+                                .chain(me.paginator.buffered_items())
+                                .flat_map(tag_ids)
+                                .collect::<HashSet<_>>();
+                            me.state = PagingState::Resolving(me.lava.tags_by_id(ids).boxed());
+                            me.pending = Some(d);
                             continue;
                         }
+                        Poll::Ready(Some(Ok(d))) => {
+                            me.consumed += 1;
+                            let job = transform_job(d, &me.tags);
+                            if !me.required.carried_by(&job.tags) {
+                                continue;
+                            }
+                            me.yielded += 1;
+                            Poll::Ready(Some(Ok(job)))
+                        }
                         Poll::Pending => Poll::Pending,
                     }
                 }
-                PagingState::Transforming(fut) => match fut.as_mut().poll(cx) {
-                    Poll::Ready(d) => {
+                PagingState::Resolving(fut) => match fut.as_mut().poll(cx) {
+                    Poll::Ready(tags) => {
+                        let d = me.pending.take().unwrap();
+                        me.tags = tags;
                         me.state = PagingState::Paging;
-                        Poll::Ready(Some(Ok(d)))
+                        me.consumed += 1;
+                        let job = transform_job(d, &me.tags);
+                        if !me.required.carried_by(&job.tags) {
+                            continue;
+                        }
+                        me.yielded += 1;
+                        Poll::Ready(Some(Ok(job)))
                     }
                     Poll::Pending => Poll::Pending,
                 },
@@ -470,57 +2559,91 @@ impl<'a> Stream for Jobs<'a> {
 }
 
 #[derive(Error, Debug)]
-pub enum SubmissionError {
-    #[error("Request failed {0}")]
+pub enum JobError {
+    #[error("Job request failed")]
     Request(#[from] reqwest::Error),
-    #[error("Invalid job: {0}")]
-    InvalidJob(String),
-    #[error("Unexpected reply: {0}")]
+    #[error("Unexpected reply to job request: {0}")]
     UnexpectedReply(reqwest::StatusCode),
 }
 
-#[derive(Debug, Serialize)]
-struct Submission<'a> {
-    definition: &'a str,
-}
-
-#[derive(Debug, Deserialize)]
-struct SubmissionReply {
-    message: String,
-    #[serde(default)]
-    job_ids: Vec<i64>,
+impl Classify for JobError {
+    fn class(&self) -> ErrorClass {
+        match self {
+            JobError::Request(e) => e.class(),
+            JobError::UnexpectedReply(s) => s.class(),
+        }
+    }
 }
 
-pub async fn submit_job(lava: &Lava, definition: &str) -> Result<Vec<i64>, SubmissionError> {
-    let url = lava
-        .base
-        .join("jobs/")
-        .expect("Failed to append to base url");
-    let sub = Submission { definition };
-
-    let post = lava.client.post(url).json(&sub).send().await?;
+/// Retrieve the job with the given id, or `None` if there is no such
+/// job, or it is not visible to the user making the request.
+pub async fn job(lava: &Lava, id: i64) -> Result<Option<Job>, JobError> {
+    let mut url = lava.base.clone();
+    url.path_segments_mut()
+        .unwrap()
+        .pop_if_empty()
+        .push("jobs")
+        .push(&id.to_string())
+        .push("");
 
-    match post.status() {
-        StatusCode::CREATED => {
-            let reply: SubmissionReply = post.json().await?;
-            Ok(reply.job_ids)
-        }
-        StatusCode::BAD_REQUEST => {
-            let reply: SubmissionReply = post.json().await?;
-            Err(SubmissionError::InvalidJob(reply.message))
+    let res = lava
+        .retry
+        .send(&*lava.transport, transport::get(url))
+        .await?;
+    match res.status() {
+        StatusCode::OK => {
+            let job: LavaJob = res.json().await?;
+            let tags = lava.tags_by_id(tag_ids(&job)).await;
+            Ok(Some(transform_job(job, &tags)))
         }
-        s => Err(SubmissionError::UnexpectedReply(s)),
+        StatusCode::NOT_FOUND => Ok(None),
+        s => Err(JobError::UnexpectedReply(s)),
     }
 }
 
+/// Retrieve the most recently submitted health check job for the
+/// named device type, or `None` if it has none visible to the user
+/// making the request.
+///
+/// The server selects the job, so this makes a single request for a
+/// single job, however many jobs there are.
+pub async fn latest_health_check(
+    lava: &Lava,
+    device_type: &str,
+) -> Result<Option<Job>, PaginationError> {
+    lava.jobs()
+        .health_check(true)
+        .requested_device_type(device_type)
+        .ordering(Ordering::SubmitTime, false)
+        .limit(1)
+        .query()
+        .try_next()
+        .await
+}
+
 #[derive(Error, Debug)]
 pub enum CancellationError {
-    #[error("Request failed {0}")]
+    #[error("Job cancellation request failed")]
     Request(#[from] reqwest::Error),
-    #[error("Unexpected reply: {0}")]
+    #[error("Not permitted to cancel job")]
+    PermissionDenied,
+    #[error("Job not found")]
+    NotFound,
+    #[error("Unexpected reply to job cancellation: {0}")]
     UnexpectedReply(reqwest::StatusCode),
 }
 
+impl Classify for CancellationError {
+    fn class(&self) -> ErrorClass {
+        match self {
+            CancellationError::Request(e) => e.class(),
+            CancellationError::PermissionDenied => ErrorClass::Auth,
+            CancellationError::NotFound => ErrorClass::NotFound,
+            CancellationError::UnexpectedReply(s) => s.class(),
+        }
+    }
+}
+
 pub async fn cancel_job(lava: &Lava, id: i64) -> Result<(), CancellationError> {
     let mut url = lava.base.clone();
     url.path_segments_mut()
@@ -531,22 +2654,35 @@ pub async fn cancel_job(lava: &Lava, id: i64) -> Result<(), CancellationError> {
         .push("cancel")
         .push("");
 
-    let res = lava.client.get(url).send().await?;
+    let res = lava.transport.execute(transport::get(url)).await?;
 
     match res.status() {
         StatusCode::OK => Ok(()),
+        StatusCode::UNAUTHORIZED | StatusCode::FORBIDDEN => {
+            Err(CancellationError::PermissionDenied)
+        }
+        StatusCode::NOT_FOUND => Err(CancellationError::NotFound),
         s => Err(CancellationError::UnexpectedReply(s)),
     }
 }
 
 #[derive(Error, Debug)]
 pub enum ResultsError {
-    #[error("Request failed {0}")]
+    #[error("Job results request failed")]
     Request(#[from] reqwest::Error),
-    #[error("Unexpected reply: {0}")]
+    #[error("Unexpected reply to job results request: {0}")]
     UnexpectedReply(reqwest::StatusCode),
 }
 
+impl Classify for ResultsError {
+    fn class(&self) -> ErrorClass {
+        match self {
+            ResultsError::Request(e) => e.class(),
+            ResultsError::UnexpectedReply(s) => s.class(),
+        }
+    }
+}
+
 pub async fn job_results_as_junit(
     lava: &Lava,
     id: i64,
@@ -560,121 +2696,89 @@ pub async fn job_results_as_junit(
         .push("junit")
         .push("");
 
-    let res = lava.client.get(url).send().await?;
+    let res = lava.transport.execute(transport::get(url)).await?;
     match res.status() {
         StatusCode::OK => Ok(res.bytes_stream().map_err(ResultsError::from)),
         s => Err(ResultsError::UnexpectedReply(s)),
     }
 }
 
+/// Obtain the whole JUnit XML document for the results of the job
+/// with the given id.
+///
+/// Unlike [`job_results_as_junit`], this reads the complete document
+/// before returning, which is simpler when it is to be archived or
+/// parsed.
+pub async fn job_results_junit(lava: &Lava, id: i64) -> Result<Bytes, ResultsError> {
+    job_results_as_junit(lava, id)
+        .await?
+        .try_fold(Vec::new(), |mut v, b| async move {
+            v.extend_from_slice(&b);
+            Ok(v)
+        })
+        .await
+        .map(Bytes::from)
+}
+
+#[cfg(feature = "junit")]
+#[derive(Error, Debug)]
+pub enum JunitReportError {
+    #[error("Failed to retrieve job results")]
+    Results(#[from] ResultsError),
+    #[error("Failed to parse JUnit results")]
+    Parse(#[from] junit_parser::Error),
+}
+
+#[cfg(feature = "junit")]
+impl Classify for JunitReportError {
+    fn class(&self) -> ErrorClass {
+        match self {
+            JunitReportError::Results(e) => e.class(),
+            JunitReportError::Parse(_) => ErrorClass::Decode,
+        }
+    }
+}
+
+/// Obtain the results of the job with the given id as a parsed JUnit
+/// report.
+#[cfg(feature = "junit")]
+pub async fn job_results_junit_report(
+    lava: &Lava,
+    id: i64,
+) -> Result<junit_parser::TestSuites, JunitReportError> {
+    let junit = job_results_junit(lava, id).await?;
+    Ok(junit_parser::from_reader(std::io::Cursor::new(junit))?)
+}
+
 #[cfg(test)]
 mod tests {
-    use super::{Health, Job, Ordering, State, Tag};
+    use super::{
+        CancellationError, Health, JobsQuery, JobsQueryConfig, Ordering, RawJob, ReducedJob, State,
+        Visibility, WindowError,
+    };
+    use crate::paginator::PaginationProgress;
+    use crate::tag::Tag;
     use crate::Lava;
 
     use boulder::{
-        Buildable, Builder, GeneratableWithPersianRug, GeneratorWithPersianRugMutIterator, Repeat,
-        Some as GSome, SubsetsFromPersianRug, Time,
+        Buildable, BuildableWithPersianRug, Builder, BuilderWithPersianRug,
+        GeneratableWithPersianRug, GeneratorWithPersianRugMutIterator, Repeat, Some as GSome,
+        SubsetsFromPersianRug, Time,
     };
     use chrono::{DateTime, Duration, Utc};
     use futures::{AsyncReadExt, TryStreamExt};
-    use lava_api_mock::{
-        Device as MockDevice, DeviceType as MockDeviceType, Group as MockGroup, Job as MockJob,
-        JobHealth as MockJobHealth, JobState as MockJobState, LavaMock, PaginationLimits, PassFail,
-        PopulationParams, SharedState, Tag as MockTag, User as MockUser,
-    };
-    use persian_rug::{Accessor, Context, Proxy};
-    use std::collections::{BTreeMap, BTreeSet};
-    use std::convert::{Infallible, TryFrom, TryInto};
-    use std::str::FromStr;
-    use test_log::test;
-
-    impl Job {
-        #[persian_rug::constraints(
-            context = C,
-            access(
-                MockUser<C>,
-                MockGroup<C>,
-                MockTag<C>,
-                MockDevice<C>,
-                MockDeviceType<C>
-            )
-        )]
-        pub fn from_mock<'b, B, C>(job: &MockJob<C>, context: B) -> Job
-        where
-            B: 'b + Accessor<Context = C>,
-            C: Context + 'static,
-        {
-            Self {
-                id: job.id,
-                submitter: context.get(&job.submitter).username.clone(),
-                viewing_groups: job
-                    .viewing_groups
-                    .iter()
-                    .map(|g| context.get(g).id)
-                    .collect::<Vec<_>>(),
-                description: job.description.clone(),
-                health_check: job.health_check,
-                requested_device_type: job
-                    .requested_device_type
-                    .map(|d| context.get(&d).name.to_string()),
-                tags: job
-                    .tags
-                    .iter()
-                    .map(|t| Tag::from_mock(context.get(t), context.clone()))
-                    .collect::<Vec<_>>(),
-                actual_device: job
-                    .actual_device
-                    .as_ref()
-                    .map(|d| context.get(d).hostname.to_string()),
-                submit_time: job.submit_time.unwrap(),
-                start_time: job.start_time,
-                end_time: job.end_time,
-                state: job.state.try_into().unwrap(),
-                health: job.health.try_into().unwrap(),
-                priority: job.priority,
-                definition: job.definition.clone(),
-                original_definition: job.original_definition.clone(),
-                multinode_definition: job.multinode_definition.clone(),
-                failure_tags: job
-                    .failure_tags
-                    .iter()
-                    .map(|t| Tag::from_mock(context.get(t), context.clone()))
-                    .collect::<Vec<_>>(),
-                failure_comment: job.failure_comment.clone(),
-            }
-        }
-    }
-
-    impl TryFrom<MockJobState> for State {
-        type Error = Infallible;
-        fn try_from(state: MockJobState) -> Result<State, Self::Error> {
-            use State::*;
-
-            match state {
-                MockJobState::Submitted => Ok(Submitted),
-                MockJobState::Scheduling => Ok(Scheduling),
-                MockJobState::Scheduled => Ok(Scheduled),
-                MockJobState::Running => Ok(Running),
-                MockJobState::Canceling => Ok(Canceling),
-                MockJobState::Finished => Ok(Finished),
-            }
-        }
-    }
-
-    impl TryFrom<MockJobHealth> for Health {
-        type Error = Infallible;
-        fn try_from(health: MockJobHealth) -> Result<Health, Self::Error> {
-            use Health::*;
-
-            match health {
-                MockJobHealth::Unknown => Ok(Unknown),
-                MockJobHealth::Complete => Ok(Complete),
-                MockJobHealth::Incomplete => Ok(Incomplete),
-                MockJobHealth::Canceled => Ok(Canceled),
-            }
-        }
-    }
+    use lava_api_mock::{
+        Churn, DeviceType as MockDeviceType, Job as MockJob, JobHealth as MockJobHealth,
+        JobState as MockJobState, LavaMock, PaginationLimits, PassFail, PopulationParams,
+        SharedState, Tag as MockTag, User as MockUser,
+    };
+    use persian_rug::{Accessor, Context, Proxy};
+    use serde_json::json;
+    use std::collections::{BTreeMap, BTreeSet, HashMap};
+    use std::str::FromStr;
+    use test_log::test;
+    use wiremock::matchers::{method, path, query_param, query_param_is_missing};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
 
     #[test]
     fn test_display() {
@@ -691,6 +2795,138 @@ mod tests {
         assert_eq!(Health::Canceled.to_string(), "Canceled");
     }
 
+    #[test]
+    fn test_query_config() {
+        let config: JobsQueryConfig = serde_yaml::from_str(
+            r#"
+states: [Running]
+health_not: [Canceled, Complete, Unknown]
+limit: 20
+id_after: 100
+submitted_after: 2022-04-10T16:30:00Z
+ended_before: 2022-04-11T09:00:00Z
+viewing_public_only: true
+displayed_device_types_only: true
+tags: [fast]
+tags_all: [usb, hdmi]
+stable_pagination: true
+ordering: submit_time
+descending: true
+raw_params:
+  actual_device__hostname: qemu-01
+"#,
+        )
+        .expect("failed to parse config");
+
+        let submitted = DateTime::parse_from_rfc3339("2022-04-10T16:30:00Z")
+            .unwrap()
+            .with_timezone(&Utc);
+        let expected = JobsQuery::new()
+            .state(State::Running)
+            .health(Health::Incomplete)
+            .limit(20)
+            .id_after(100)
+            .submitted_after(submitted)
+            .ended_before(
+                DateTime::parse_from_rfc3339("2022-04-11T09:00:00Z")
+                    .unwrap()
+                    .with_timezone(&Utc),
+            )
+            .viewing_public_only()
+            .displayed_device_types_only()
+            .tag("fast")
+            .tags_all(["usb", "hdmi"])
+            .stable_pagination()
+            .ordering(Ordering::SubmitTime, false)
+            .raw_param("actual_device__hostname", "qemu-01");
+
+        let mut url = url::Url::parse("http://example.com/jobs/").unwrap();
+        JobsQuery::new().apply(&config).append_to(&mut url);
+        let mut expected_url = url::Url::parse("http://example.com/jobs/").unwrap();
+        expected.append_to(&mut expected_url);
+        assert_eq!(url, expected_url);
+        assert!(url
+            .query_pairs()
+            .any(|(k, v)| k == "actual_device__hostname" && v == "qemu-01"));
+
+        assert_eq!(
+            serde_yaml::from_str::<JobsQueryConfig>("{}").expect("failed to parse config"),
+            JobsQueryConfig::default()
+        );
+        assert!(serde_yaml::from_str::<JobsQueryConfig>("state: [Running]").is_err());
+    }
+
+    #[test]
+    fn test_between() {
+        let start = DateTime::parse_from_rfc3339("2022-04-10T16:00:00Z")
+            .unwrap()
+            .with_timezone(&Utc);
+        let end = DateTime::parse_from_rfc3339("2022-04-10T17:00:00Z")
+            .unwrap()
+            .with_timezone(&Utc);
+
+        let mut url = url::Url::parse("http://example.com/jobs/").unwrap();
+        JobsQuery::new()
+            .submitted_after(end)
+            .submitted_between(start, end)
+            .ended_between(start, end)
+            .append_to(&mut url);
+        let pairs = url.query_pairs().into_owned().collect::<Vec<_>>();
+        let values = |key: &str| {
+            pairs
+                .iter()
+                .filter(|(k, _)| k == key)
+                .map(|(_, v)| v.as_str())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            values("submit_time__gt"),
+            vec!["2022-04-10T15:59:59.999999+00:00"]
+        );
+        assert_eq!(values("submit_time__lt"), vec!["2022-04-10T17:00:00+00:00"]);
+        assert_eq!(
+            values("end_time__gt"),
+            vec!["2022-04-10T15:59:59.999999+00:00"]
+        );
+        assert_eq!(values("end_time__lt"), vec!["2022-04-10T17:00:00+00:00"]);
+        assert!(values("start_time__gt").is_empty());
+    }
+
+    #[test]
+    fn test_scheduling_filters() {
+        let config: JobsQueryConfig = serde_yaml::from_str(
+            r#"
+priority_at_least: 10
+priority_at_most: 50
+health_check: false
+submitters: [alice, bob]
+actual_devices: [qemu-01]
+requested_device_types: [qemu]
+"#,
+        )
+        .expect("failed to parse config");
+
+        let mut url = url::Url::parse("http://example.com/jobs/").unwrap();
+        JobsQuery::new().apply(&config).append_to(&mut url);
+        let pairs = url.query_pairs().into_owned().collect::<Vec<_>>();
+        for (key, value) in [
+            ("priority__gte", "10"),
+            ("priority__lte", "50"),
+            ("health_check", "false"),
+            ("submitter__username__in", "alice,bob"),
+            ("actual_device__hostname", "qemu-01"),
+            ("requested_device_type__name", "qemu"),
+        ] {
+            assert!(
+                pairs.contains(&(key.to_string(), value.to_string())),
+                "missing {}={} in {}",
+                key,
+                value,
+                url
+            );
+        }
+    }
+
     #[test]
     fn test_from_str() {
         assert_eq!(Ok(State::Submitted), State::from_str("Submitted"));
@@ -747,6 +2983,14 @@ mod tests {
             for i in 0..job.viewing_groups.len() {
                 assert_eq!(job.viewing_groups[i], start.get(&jj.viewing_groups[i]).id);
             }
+            if jj.viewing_groups.is_empty() {
+                assert_eq!(job.visibility.is_public(), jj.is_public);
+            } else {
+                assert_eq!(
+                    job.visibility,
+                    Visibility::Group(job.viewing_groups.clone())
+                );
+            }
             assert_eq!(job.description, jj.description);
             assert_eq!(job.health_check, jj.health_check);
             assert_eq!(
@@ -811,6 +3055,7 @@ mod tests {
 
         let mut gen = Proxy::<lava_api_mock::Job<lava_api_mock::State>>::generator()
             .tags(SubsetsFromPersianRug::new())
+            .is_public(Repeat!(true, false))
             .health(Repeat!(
                 MockJobHealth::Complete,
                 MockJobHealth::Incomplete,
@@ -836,7 +3081,16 @@ mod tests {
             .take(50)
             .collect::<Vec<_>>();
 
-        let lava = Lava::new(&server.uri(), None).expect("failed to make lava server");
+        // Anonymous users only see public jobs, so query as a
+        // superuser, who sees them all
+        let _ = Proxy::<MockUser<_>>::builder()
+            .username("admin")
+            .is_superuser(true)
+            .token(Some("admin-token".to_string()))
+            .build(server.state_mut());
+
+        let lava = Lava::new(&server.uri(), Some("admin-token".to_string()))
+            .expect("failed to make lava server");
 
         let mut lj = lava.jobs().state(State::Running).query();
 
@@ -847,6 +3101,15 @@ mod tests {
         }
         assert_eq!(count, 8);
 
+        const RUNNING: JobsQuery = JobsQuery::new();
+        let mut lj = lava.jobs_with(RUNNING.state(State::Running)).query();
+        let mut count = 0;
+        while let Some(job) = lj.try_next().await.expect("failed to get job") {
+            assert_eq!(job.state, State::Running);
+            count += 1;
+        }
+        assert_eq!(count, 8);
+
         let mut lj = lava.jobs().state_not(State::Canceling).query();
         let mut count = 0;
         while let Some(job) = lj.try_next().await.expect("failed to get job") {
@@ -872,6 +3135,14 @@ mod tests {
         }
         assert_eq!(count, 38);
 
+        let mut lj = lava.jobs().viewing_public_only().query();
+        let mut count = 0;
+        while let Some(job) = lj.try_next().await.expect("failed to get job") {
+            assert_eq!(job.id % 2, 0);
+            count += 1;
+        }
+        assert_eq!(count, 25);
+
         let mut lj = lava.jobs().id_after(9i64).query();
         let mut count = 0;
         while let Some(job) = lj.try_next().await.expect("failed to get job") {
@@ -965,6 +3236,927 @@ mod tests {
         assert_eq!(count, 50);
     }
 
+    #[test(tokio::test)]
+    async fn test_visibility() {
+        let mut state = SharedState::new();
+        let (fred, m) = Proxy::<MockUser<_>>::builder()
+            .username("fred")
+            .token(Some("fred-token".to_string()))
+            .build(state.mutate());
+        let (_, m) = Proxy::<MockUser<_>>::builder()
+            .username("jane")
+            .token(Some("jane-token".to_string()))
+            .build(m);
+        let (_, m) = Proxy::<MockJob<_>>::builder()
+            .id(1)
+            .submitter(fred)
+            .build(m);
+        let _ = Proxy::<MockJob<_>>::builder()
+            .id(2)
+            .submitter(fred)
+            .is_public(false)
+            .viewing_groups(Vec::new())
+            .build(m);
+        let server = LavaMock::new(state, PaginationLimits::new()).await;
+
+        let ids = |token: Option<&str>| {
+            let lava = Lava::new(&server.uri(), token.map(str::to_string))
+                .expect("failed to make lava server");
+            async move {
+                let mut ids = lava
+                    .jobs()
+                    .query()
+                    .map_ok(|job| job.id)
+                    .try_collect::<Vec<_>>()
+                    .await
+                    .expect("failed to query jobs");
+                ids.sort();
+                ids
+            }
+        };
+
+        assert_eq!(ids(None).await, vec![1]);
+        assert_eq!(ids(Some("jane-token")).await, vec![1]);
+        assert_eq!(ids(Some("fred-token")).await, vec![1, 2]);
+
+        let jane = Lava::new(&server.uri(), Some("jane-token".to_string()))
+            .expect("failed to make lava server");
+        let err = jane
+            .cancel_job(1)
+            .await
+            .expect_err("cancelled another user's job");
+        assert!(matches!(err, CancellationError::PermissionDenied));
+        let err = jane
+            .cancel_job(2)
+            .await
+            .expect_err("cancelled an invisible job");
+        assert!(matches!(err, CancellationError::NotFound));
+    }
+
+    #[test(tokio::test)]
+    async fn test_job() {
+        let state = SharedState::new_populated(PopulationParams::new());
+        let server = LavaMock::new(state, PaginationLimits::new()).await;
+        let lava = Lava::new(&server.uri(), None).expect("failed to make lava server");
+
+        let jobs = lava
+            .jobs()
+            .query()
+            .try_collect::<Vec<_>>()
+            .await
+            .expect("failed to query jobs");
+        assert!(!jobs.is_empty());
+        for job in jobs.iter() {
+            let found = lava.job(job.id).await.expect("failed to get job");
+            assert_eq!(found.as_ref(), Some(job));
+        }
+        let missing = jobs.iter().map(|job| job.id).max().unwrap() + 1;
+        assert_eq!(lava.job(missing).await.expect("failed to get job"), None);
+
+        let ids = [jobs[0].id, jobs[jobs.len() - 1].id, missing];
+        let found = lava
+            .jobs()
+            .ids(&ids)
+            .query()
+            .map_ok(|job| job.id)
+            .try_collect::<Vec<_>>()
+            .await
+            .expect("failed to query jobs");
+        assert_eq!(found, &ids[..2]);
+
+        let mut state = SharedState::new();
+        let (fred, m) = Proxy::<MockUser<_>>::builder()
+            .username("fred")
+            .token(Some("fred-token".to_string()))
+            .build(state.mutate());
+        let _ = Proxy::<MockJob<_>>::builder()
+            .id(1)
+            .submitter(fred)
+            .is_public(false)
+            .viewing_groups(Vec::new())
+            .build(m);
+        let server = LavaMock::new(state, PaginationLimits::new()).await;
+
+        let anonymous = Lava::new(&server.uri(), None).expect("failed to make lava server");
+        assert_eq!(anonymous.job(1).await.expect("failed to get job"), None);
+        let fred = Lava::new(&server.uri(), Some("fred-token".to_string()))
+            .expect("failed to make lava server");
+        let job = fred
+            .job(1)
+            .await
+            .expect("failed to get job")
+            .expect("job not found");
+        assert_eq!(job.submitter, "fred");
+        assert_eq!(job.visibility, Visibility::Personal);
+    }
+
+    // A running job, with only the fields of a ReducedJob
+    fn reduced_job(id: i64) -> serde_json::Value {
+        json!({
+            "id": id,
+            "submitter": "alice",
+            "description": "a job",
+            "health_check": false,
+            "requested_device_type": "qemu",
+            "actual_device": "qemu-01",
+            "submit_time": "2022-03-01T10:00:00Z",
+            "start_time": "2022-03-01T10:05:00Z",
+            "end_time": null,
+            "state": "Running",
+            "health": "Unknown",
+            "priority": 50,
+        })
+    }
+
+    #[test(tokio::test)]
+    async fn test_tag_resolution() {
+        let job = |id: i64, tags: &[u32], failure_tags: &[u32]| {
+            let mut job = reduced_job(id);
+            let fields = job.as_object_mut().unwrap();
+            fields.insert("viewing_groups".to_string(), json!([]));
+            fields.insert("tags".to_string(), json!(tags));
+            fields.insert("failure_tags".to_string(), json!(failure_tags));
+            fields.insert("failure_comment".to_string(), json!(null));
+            for field in ["definition", "original_definition", "multinode_definition"] {
+                fields.insert(field.to_string(), json!(""));
+            }
+            job
+        };
+
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/api/v0.2/jobs/"))
+            .and(query_param_is_missing("offset"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "count": 5,
+                "next": format!("{}/api/v0.2/jobs/?offset=3", server.uri()),
+                "results": [job(1, &[1, 99], &[2]), job(2, &[1], &[]), job(3, &[2, 99], &[])],
+            })))
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/api/v0.2/jobs/"))
+            .and(query_param("offset", "3"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "count": 5,
+                "next": null,
+                "results": [job(4, &[3], &[]), job(5, &[], &[99])],
+            })))
+            .mount(&server)
+            .await;
+        // Each page has a tag which does not exist, so the cache is
+        // refreshed once for each page, rather than once per job.
+        Mock::given(method("GET"))
+            .and(path("/api/v0.2/tags/"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "count": 3,
+                "next": null,
+                "results": [
+                    {"id": 1, "name": "one", "description": null},
+                    {"id": 2, "name": "two", "description": null},
+                    {"id": 3, "name": "three", "description": null},
+                ],
+            })))
+            .expect(2)
+            .mount(&server)
+            .await;
+
+        let lava = Lava::new(&server.uri(), None).expect("failed to make lava server");
+        let jobs = lava
+            .jobs()
+            .query()
+            .try_collect::<Vec<_>>()
+            .await
+            .expect("failed to query jobs");
+
+        let names = |tags: &[Tag]| tags.iter().map(|t| t.name.clone()).collect::<Vec<_>>();
+        assert_eq!(jobs.len(), 5);
+        assert_eq!(names(&jobs[0].tags), vec!["one"]);
+        assert_eq!(names(&jobs[0].failure_tags), vec!["two"]);
+        assert_eq!(names(&jobs[2].tags), vec!["two"]);
+        assert_eq!(names(&jobs[3].tags), vec!["three"]);
+        assert!(jobs[4].failure_tags.is_empty());
+    }
+
+    #[test(tokio::test)]
+    async fn test_query_raw() {
+        let mut job = reduced_job(1);
+        let fields = job.as_object_mut().unwrap();
+        fields.insert("viewing_groups".to_string(), json!([]));
+        fields.insert("is_public".to_string(), json!(true));
+        fields.insert("tags".to_string(), json!([1, 99]));
+        fields.insert("failure_tags".to_string(), json!([2]));
+        fields.insert("failure_comment".to_string(), json!(null));
+        for field in ["definition", "original_definition", "multinode_definition"] {
+            fields.insert(field.to_string(), json!(""));
+        }
+
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/api/v0.2/jobs/"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "count": 1,
+                "next": null,
+                "results": [job],
+            })))
+            .mount(&server)
+            .await;
+        // Tags are never looked up
+        Mock::given(method("GET"))
+            .and(path("/api/v0.2/tags/"))
+            .respond_with(ResponseTemplate::new(500))
+            .expect(0)
+            .mount(&server)
+            .await;
+
+        let lava = Lava::new(&server.uri(), None).expect("failed to make lava server");
+        let jobs = lava.jobs().query_raw();
+        drop(lava);
+        let jobs = tokio::spawn(jobs.try_collect::<Vec<_>>())
+            .await
+            .unwrap()
+            .expect("failed to query jobs");
+        assert_eq!(jobs.len(), 1);
+        assert_eq!(jobs[0].tags, vec![1, 99]);
+        assert_eq!(jobs[0].failure_tags, vec![2]);
+        assert_eq!(jobs[0].visibility, Visibility::Public);
+
+        let tags = HashMap::from([(
+            1,
+            Tag {
+                id: 1,
+                name: "one".to_string(),
+                description: None,
+            },
+        )]);
+        let job = jobs[0].clone().resolve(&tags);
+        assert_eq!(job.tags.len(), 1);
+        assert_eq!(job.tags[0].name, "one");
+        assert!(job.failure_tags.is_empty());
+    }
+
+    #[test]
+    fn test_raw_round_trip() {
+        for (is_public, groups, visibility) in [
+            (json!(true), json!([]), Visibility::Public),
+            (json!(false), json!([]), Visibility::Personal),
+            (json!(true), json!([3]), Visibility::Group(vec![3])),
+            (json!(null), json!([]), Visibility::Unknown),
+        ] {
+            let mut job = reduced_job(1);
+            let fields = job.as_object_mut().unwrap();
+            fields.insert("viewing_groups".to_string(), groups);
+            fields.insert("is_public".to_string(), is_public);
+            fields.insert("tags".to_string(), json!([1, 2]));
+            fields.insert("failure_tags".to_string(), json!([]));
+            fields.insert("failure_comment".to_string(), json!(null));
+            for field in ["definition", "original_definition", "multinode_definition"] {
+                fields.insert(field.to_string(), json!(""));
+            }
+
+            let raw: RawJob = serde_json::from_value(job).expect("failed to parse job");
+            assert_eq!(raw.visibility, visibility);
+            let json = serde_json::to_value(&raw).expect("failed to serialize job");
+            assert!(json.get("visibility").is_none());
+            let again: RawJob = serde_json::from_value(json).expect("failed to reread job");
+            assert_eq!(again, raw);
+        }
+    }
+
+    #[test(tokio::test)]
+    async fn test_reduced() {
+        let state = SharedState::new_populated(PopulationParams::builder().jobs(20usize).build());
+        let server = LavaMock::new(state, PaginationLimits::builder().jobs(Some(7)).build()).await;
+
+        let lava = Lava::new(&server.uri(), None).expect("failed to make lava server");
+
+        // The mock ignores the requested fields, and sends complete jobs
+        let full = lava
+            .jobs()
+            .query()
+            .try_collect::<Vec<_>>()
+            .await
+            .expect("failed to query jobs");
+        let reduced = lava
+            .jobs()
+            .query_reduced()
+            .try_collect::<Vec<_>>()
+            .await
+            .expect("failed to query reduced jobs");
+        assert_eq!(full.len(), 20);
+        assert_eq!(reduced.len(), 20);
+        for (job, reduced) in full.iter().zip(reduced.iter()) {
+            assert_eq!(
+                reduced,
+                &ReducedJob {
+                    id: job.id,
+                    submitter: job.submitter.clone(),
+                    description: job.description.clone(),
+                    health_check: job.health_check,
+                    requested_device_type: job.requested_device_type.clone(),
+                    actual_device: job.actual_device.clone(),
+                    submit_time: job.submit_time,
+                    start_time: job.start_time,
+                    end_time: job.end_time,
+                    state: job.state,
+                    health: job.health,
+                    priority: job.priority,
+                }
+            );
+        }
+
+        // A server supporting field selection sends only those fields
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/api/v0.2/jobs/"))
+            .and(query_param("state", "Running"))
+            .and(query_param(
+                "fields",
+                "id,submitter,description,health_check,requested_device_type,\
+                 actual_device,submit_time,start_time,end_time,state,health,priority",
+            ))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "count": 1,
+                "next": null,
+                "results": [reduced_job(17)],
+            })))
+            .mount(&server)
+            .await;
+
+        let lava = Lava::new(&server.uri(), None).expect("failed to make lava server");
+        let reduced = lava
+            .jobs()
+            .state(State::Running)
+            .query_reduced()
+            .try_collect::<Vec<_>>()
+            .await
+            .expect("failed to query reduced jobs");
+        assert_eq!(reduced.len(), 1);
+        assert_eq!(reduced[0].id, 17);
+        assert_eq!(reduced[0].actual_device.as_deref(), Some("qemu-01"));
+        assert_eq!(reduced[0].state, State::Running);
+        assert!(reduced[0].end_time.is_none());
+    }
+
+    /// Shrink the result set between pages, checking that stable
+    /// pagination neither skips nor repeats any job, where offset
+    /// pagination skips one.
+    #[test(tokio::test)]
+    async fn test_stable_pagination() {
+        let mut state =
+            SharedState::new_populated(PopulationParams::builder().jobs(0usize).build());
+        state.add_jobs(8, |_, job| job.state = MockJobState::Submitted);
+        let server = LavaMock::new(
+            state.clone(),
+            PaginationLimits::builder().jobs(Some(3)).build(),
+        )
+        .await;
+
+        let lava = Lava::new(&server.uri(), None).expect("failed to make lava server");
+
+        for (stable, expected) in [
+            (false, vec![0, 1, 2, 4, 5, 6, 7]),
+            (true, vec![0, 1, 2, 3, 4, 5, 6, 7]),
+        ] {
+            {
+                let mut m = state.mutate();
+                for job in m.get_iter_mut::<MockJob<lava_api_mock::State>>() {
+                    job.state = MockJobState::Submitted;
+                }
+            }
+
+            let mut builder = lava.jobs().state(State::Submitted);
+            if stable {
+                builder = builder.stable_pagination();
+            }
+            let mut jobs = builder.query();
+            let mut ids = Vec::new();
+            for _ in 0..3 {
+                ids.push(
+                    jobs.try_next()
+                        .await
+                        .expect("failed to get job")
+                        .unwrap()
+                        .id,
+                );
+            }
+            assert_eq!(jobs.reported_items(), Some(8));
+
+            {
+                let mut m = state.mutate();
+                for job in m.get_iter_mut::<MockJob<lava_api_mock::State>>() {
+                    if job.id == 0 {
+                        job.state = MockJobState::Finished;
+                    }
+                }
+            }
+
+            while let Some(job) = jobs.try_next().await.expect("failed to get job") {
+                ids.push(job.id);
+            }
+            assert_eq!(ids, expected);
+            if stable {
+                // The count still includes the jobs on earlier pages
+                assert_eq!(jobs.reported_items(), Some(8));
+            }
+        }
+
+        // Descending stable queries continue below the last id
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/api/v0.2/jobs/"))
+            .and(query_param("id__lt", "8"))
+            .and(query_param_is_missing("offset"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "count": 0,
+                "next": null,
+                "results": [],
+            })))
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/api/v0.2/jobs/"))
+            .and(query_param("ordering", "-id"))
+            .and(query_param_is_missing("id__lt"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "count": 3,
+                "next": format!("{}/api/v0.2/jobs/?ordering=-id&offset=2", server.uri()),
+                "results": [reduced_job(9), reduced_job(8)],
+            })))
+            .mount(&server)
+            .await;
+
+        let lava = Lava::new(&server.uri(), None).expect("failed to make lava server");
+        let ids = lava
+            .jobs()
+            .ordering(Ordering::SubmitTime, false)
+            .stable_pagination()
+            .query_reduced()
+            .map_ok(|job| job.id)
+            .try_collect::<Vec<_>>()
+            .await
+            .expect("failed to query jobs");
+        assert_eq!(ids, vec![9, 8]);
+    }
+
+    #[test(tokio::test)]
+    async fn test_multi_key_ordering() {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/api/v0.2/jobs/"))
+            .and(query_param("ordering", "-priority,submit_time,id"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "count": 2,
+                "next": null,
+                "results": [reduced_job(3), reduced_job(1)],
+            })))
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/api/v0.2/jobs/"))
+            .and(query_param("ordering", "state"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "count": 1,
+                "next": null,
+                "results": [reduced_job(2)],
+            })))
+            .mount(&server)
+            .await;
+
+        let lava = Lava::new(&server.uri(), None).expect("failed to make lava server");
+        async fn query(builder: super::JobsBuilder<'_>) -> Vec<i64> {
+            builder
+                .query_reduced()
+                .map_ok(|job| job.id)
+                .try_collect::<Vec<_>>()
+                .await
+                .expect("failed to query jobs")
+        }
+
+        let ids = query(
+            lava.jobs()
+                .ordering(Ordering::Priority, false)
+                .then_by(Ordering::SubmitTime, true)
+                .then_by(Ordering::Id, true),
+        )
+        .await;
+        assert_eq!(ids, vec![3, 1]);
+
+        // A new ordering replaces the earlier keys
+        let ids = query(
+            lava.jobs()
+                .then_by(Ordering::Health, false)
+                .ordering(Ordering::State, true),
+        )
+        .await;
+        assert_eq!(ids, vec![2]);
+
+        assert_eq!(
+            Ordering::from_str("description").unwrap(),
+            Ordering::Description
+        );
+        assert_eq!(Ordering::Priority.to_string(), "priority");
+    }
+
+    #[test(tokio::test)]
+    async fn test_sharded() {
+        let state = SharedState::new_populated(PopulationParams::builder().jobs(20usize).build());
+        let server = LavaMock::new(state, PaginationLimits::builder().jobs(Some(4)).build()).await;
+
+        let lava = Lava::new(&server.uri(), None).expect("failed to make lava server");
+
+        let shards = lava.jobs().sharded(3).await.expect("failed to shard jobs");
+        assert_eq!(shards.len(), 3);
+        let mut ids = super::merge_shards(shards)
+            .map_ok(|job| job.id)
+            .try_collect::<Vec<_>>()
+            .await
+            .expect("failed to query shards");
+        ids.sort_unstable();
+        assert_eq!(ids, (0..20).collect::<Vec<_>>());
+
+        // Each shard keeps the filters of the query
+        let shards = lava
+            .jobs()
+            .id_after(14)
+            .sharded(10)
+            .await
+            .expect("failed to shard jobs");
+        assert_eq!(shards.len(), 5);
+        for (id, shard) in (15..20).zip(shards) {
+            let jobs = shard
+                .try_collect::<Vec<_>>()
+                .await
+                .expect("failed to query shard");
+            assert_eq!(jobs.len(), 1);
+            assert_eq!(jobs[0].id, id);
+        }
+
+        let shards = lava
+            .jobs()
+            .id_after(100)
+            .sharded(3)
+            .await
+            .expect("failed to shard jobs");
+        assert!(shards.is_empty());
+
+        // Each shard only holds the jobs matching a state filter
+        let mut state =
+            SharedState::new_populated(PopulationParams::builder().jobs(0usize).build());
+        state.add_jobs(20, |i, job| {
+            job.state = if i % 3 == 0 {
+                MockJobState::Finished
+            } else {
+                MockJobState::Submitted
+            }
+        });
+        let server = LavaMock::new(state, PaginationLimits::builder().jobs(Some(2)).build()).await;
+        let lava = Lava::new(&server.uri(), None).expect("failed to make lava server");
+
+        let shards = lava
+            .jobs()
+            .state(State::Finished)
+            .sharded(3)
+            .await
+            .expect("failed to shard jobs");
+        assert_eq!(shards.len(), 3);
+        let mut ids = Vec::new();
+        for shard in shards {
+            let jobs = shard
+                .try_collect::<Vec<_>>()
+                .await
+                .expect("failed to query shard");
+            assert!(jobs.iter().all(|job| job.state == State::Finished));
+            ids.extend(jobs.iter().map(|job| job.id));
+        }
+        ids.sort_unstable();
+        assert_eq!(ids, (0..20).step_by(3).collect::<Vec<_>>());
+    }
+
+    #[test(tokio::test)]
+    async fn test_deduplicated() {
+        let jobs = || {
+            let mut state =
+                SharedState::new_populated(PopulationParams::builder().jobs(0usize).build());
+            state.add_jobs(8, |_, _| {});
+            state
+        };
+        let limits = PaginationLimits::builder().jobs(Some(3)).build();
+
+        // Each new job pushes the last job of a page onto the next
+        let server = LavaMock::builder(jobs())
+            .limits(limits.clone())
+            .churn(Churn::Submit)
+            .build()
+            .await;
+        let lava = Lava::new(&server.uri(), None).expect("failed to make lava server");
+        let mut jobs_stream = lava
+            .jobs()
+            .ordering(Ordering::Id, false)
+            .query()
+            .deduplicated();
+        let mut ids = Vec::new();
+        while let Some(job) = jobs_stream.try_next().await.expect("failed to get job") {
+            ids.push(job.id);
+        }
+        assert_eq!(ids, vec![7, 6, 5, 4, 3, 2, 1, 0]);
+        assert_eq!(jobs_stream.duplicates(), 3);
+        assert_eq!(jobs_stream.suspected_omissions(), 0);
+
+        // Each removed job pulls the first job of a page onto the last
+        let server = LavaMock::builder(jobs())
+            .limits(limits)
+            .churn(Churn::Remove(0))
+            .build()
+            .await;
+        let lava = Lava::new(&server.uri(), None).expect("failed to make lava server");
+        let mut jobs_stream = lava
+            .jobs()
+            .ordering(Ordering::Id, true)
+            .query()
+            .deduplicated();
+        let mut ids = Vec::new();
+        while let Some(job) = jobs_stream.try_next().await.expect("failed to get job") {
+            ids.push(job.id);
+        }
+        assert_eq!(ids, vec![0, 1, 2, 4, 5, 6]);
+        assert_eq!(jobs_stream.duplicates(), 0);
+        assert_eq!(jobs_stream.suspected_omissions(), 2);
+    }
+
+    #[test(tokio::test)]
+    async fn test_window() {
+        let start = DateTime::parse_from_rfc3339("2022-03-17T17:00:00Z")
+            .unwrap()
+            .with_timezone(&Utc);
+        let mut state =
+            SharedState::new_populated(PopulationParams::builder().jobs(0usize).build());
+        // Jobs starting on each boundary, and a microsecond to either
+        // side of each
+        let offsets = [-1, 0, 1, 3_600_000_000 - 1, 3_600_000_000, 3_600_000_001];
+        state.add_jobs(offsets.len(), |i, job| {
+            job.start_time = Some(start + Duration::microseconds(offsets[i]))
+        });
+        let server = LavaMock::new(state, PaginationLimits::new()).await;
+
+        let lava = Lava::new(&server.uri(), None).expect("failed to make lava server");
+
+        let window = lava
+            .jobs()
+            .started_after(start + Duration::hours(2))
+            .window(start, start + Duration::hours(1))
+            .expect("failed to make window");
+        let pairs = window.url.query_pairs().collect::<BTreeMap<_, _>>();
+        assert_eq!(
+            pairs.get("start_time__gt").map(|v| v.as_ref()),
+            Some("2022-03-17T16:59:59.999999+00:00")
+        );
+        assert_eq!(
+            pairs.get("start_time__lt").map(|v| v.as_ref()),
+            Some("2022-03-17T18:00:00+00:00")
+        );
+
+        let ids = window
+            .jobs
+            .map_ok(|job| job.id)
+            .try_collect::<Vec<_>>()
+            .await
+            .expect("failed to query jobs");
+        assert_eq!(ids, vec![1, 2, 3]);
+
+        assert!(matches!(
+            lava.jobs().window(start, start),
+            Err(WindowError::Empty { .. })
+        ));
+    }
+
+    #[test(tokio::test)]
+    async fn test_displayed_device_types() {
+        let mut state =
+            SharedState::new_populated(PopulationParams::builder().jobs(0usize).build());
+        let (shown, m) = Proxy::<MockDeviceType<_>>::builder()
+            .name("shown")
+            .build(state.mutate());
+        let (hidden, _) = Proxy::<MockDeviceType<_>>::builder()
+            .name("hidden")
+            .display(false)
+            .build(m);
+        let device_types = [Some(shown), Some(hidden), None, Some(shown)];
+        state.add_jobs(device_types.len(), |i, job| {
+            job.requested_device_type = device_types[i]
+        });
+        let server = LavaMock::new(state, PaginationLimits::new()).await;
+
+        let lava = Lava::new(&server.uri(), None).expect("failed to make lava server");
+
+        let ids = lava
+            .jobs()
+            .displayed_device_types_only()
+            .query()
+            .map_ok(|job| job.id)
+            .try_collect::<Vec<_>>()
+            .await
+            .expect("failed to query jobs");
+        assert_eq!(ids, vec![0, 3]);
+
+        let all = lava
+            .jobs()
+            .query()
+            .try_collect::<Vec<_>>()
+            .await
+            .expect("failed to query jobs");
+        assert_eq!(all.len(), 4);
+    }
+
+    #[test(tokio::test)]
+    async fn test_latest_health_check() {
+        let mut state =
+            SharedState::new_populated(PopulationParams::builder().jobs(0usize).build());
+        let (qemu, m) = Proxy::<MockDeviceType<_>>::builder()
+            .name("qemu")
+            .build(state.mutate());
+        let (kevin, _) = Proxy::<MockDeviceType<_>>::builder().name("kevin").build(m);
+        let start = DateTime::parse_from_rfc3339("2022-04-10T16:00:00Z")
+            .unwrap()
+            .with_timezone(&Utc);
+        let jobs = [(qemu, true), (qemu, true), (qemu, false), (kevin, false)];
+        state.add_jobs(jobs.len(), |i, job| {
+            job.requested_device_type = Some(jobs[i].0);
+            job.health_check = jobs[i].1;
+            job.submit_time = Some(start + Duration::minutes(i as i64));
+        });
+        let server = LavaMock::new(state, PaginationLimits::new()).await;
+
+        let lava = Lava::new(&server.uri(), None).expect("failed to make lava server");
+
+        let job = lava
+            .latest_health_check("qemu")
+            .await
+            .expect("failed to query jobs")
+            .expect("no health check");
+        assert_eq!(job.id, 1);
+        assert!(job.health_check);
+
+        let job = lava
+            .latest_health_check("kevin")
+            .await
+            .expect("failed to query jobs");
+        assert!(job.is_none());
+    }
+
+    #[test(tokio::test)]
+    async fn test_tag_filters() {
+        let mut state =
+            SharedState::new_populated(PopulationParams::builder().jobs(0usize).build());
+        let (fast, m) = Proxy::<MockTag<_>>::builder()
+            .id(1001u32)
+            .name("fast")
+            .build(state.mutate());
+        let (usb, _) = Proxy::<MockTag<_>>::builder()
+            .id(1002u32)
+            .name("usb")
+            .build(m);
+        let tags = [vec![fast, usb], vec![fast], vec![usb], vec![]];
+        state.add_jobs(tags.len(), |i, job| job.tags = tags[i].clone());
+        let server = LavaMock::new(state, PaginationLimits::new()).await;
+
+        let lava = Lava::new(&server.uri(), None).expect("failed to make lava server");
+
+        let ids = |query: JobsQuery| {
+            lava.jobs_with(query)
+                .query()
+                .map_ok(|job| job.id)
+                .try_collect::<Vec<_>>()
+        };
+
+        let fast = ids(JobsQuery::new().tag("fast"))
+            .await
+            .expect("failed to query jobs");
+        assert_eq!(fast, vec![0, 1]);
+        let usb = ids(JobsQuery::new().tag(1002u32))
+            .await
+            .expect("failed to query jobs");
+        assert_eq!(usb, vec![0, 2]);
+        let any = ids(JobsQuery::new().tag("fast").tag("usb"))
+            .await
+            .expect("failed to query jobs");
+        assert_eq!(any, vec![0, 1, 2]);
+        let all = ids(JobsQuery::new().tags_all(["fast", "usb"]))
+            .await
+            .expect("failed to query jobs");
+        assert_eq!(all, vec![0]);
+        let all = ids(JobsQuery::new().tags_all([1001u32, 1002u32]))
+            .await
+            .expect("failed to query jobs");
+        assert_eq!(all, vec![0]);
+    }
+
+    #[test(tokio::test)]
+    async fn test_count() {
+        let mut state =
+            SharedState::new_populated(PopulationParams::builder().jobs(0usize).build());
+        state.add_jobs(5, |_, job| job.state = MockJobState::Running);
+        state.add_jobs(3, |_, job| job.state = MockJobState::Finished);
+        let limits = PaginationLimits::builder().jobs(Some(2)).build();
+        let server = LavaMock::new(state, limits).await;
+
+        let lava = Lava::new(&server.uri(), None).expect("failed to make lava server");
+
+        let count = |jobs: super::JobsBuilder<'_>| async move {
+            jobs.count().await.expect("failed to count jobs")
+        };
+        assert_eq!(count(lava.jobs()).await, 8);
+        assert_eq!(count(lava.jobs().state(State::Running)).await, 5);
+        assert_eq!(count(lava.jobs().state(State::Finished).limit(1)).await, 3);
+        assert_eq!(count(lava.jobs().id_after(100)).await, 0);
+        assert_eq!(count(lava.jobs().stable_pagination()).await, 8);
+    }
+
+    /// Only the jobs carrying the required tags count as yielded,
+    /// even though the others are read from the server too
+    #[test(tokio::test)]
+    async fn test_tag_filters_yielded() {
+        let mut state =
+            SharedState::new_populated(PopulationParams::builder().jobs(0usize).build());
+        let (fast, m) = Proxy::<MockTag<_>>::builder()
+            .id(1001u32)
+            .name("fast")
+            .build(state.mutate());
+        let (usb, _) = Proxy::<MockTag<_>>::builder()
+            .id(1002u32)
+            .name("usb")
+            .build(m);
+        let tags = [vec![fast, usb], vec![fast], vec![usb], vec![]];
+        state.add_jobs(tags.len() * 3, |i, job| {
+            job.tags = tags[i % tags.len()].clone()
+        });
+        let limits = PaginationLimits::builder().jobs(Some(5)).build();
+        let server = LavaMock::new(state, limits).await;
+
+        let lava = Lava::new(&server.uri(), None).expect("failed to make lava server");
+
+        for (query, expected) in [
+            (JobsQuery::new().tag("fast").tag("usb"), 9),
+            (JobsQuery::new().tags_all(["fast", "usb"]), 3),
+        ] {
+            let mut jobs = lava.jobs_with(query).query();
+            let mut collected = 0;
+            while jobs
+                .try_next()
+                .await
+                .expect("failed to query jobs")
+                .is_some()
+            {
+                collected += 1;
+            }
+            assert_eq!(collected, expected);
+            assert_eq!(jobs.yielded_items(), collected);
+        }
+    }
+
+    #[test(tokio::test)]
+    async fn test_cancel() {
+        let mut state = SharedState::new();
+        let _ = Proxy::<MockUser<_>>::builder()
+            .username("fred")
+            .token(Some("token".to_string()))
+            .build(state.mutate());
+        let server = LavaMock::new(state, PaginationLimits::new()).await;
+
+        let lava = Lava::new(&server.uri(), Some("token".to_string()))
+            .expect("failed to make lava server");
+        let id = lava
+            .submit_job("job_name: cancel-me\n")
+            .await
+            .expect("failed to submit")
+            .ids()[0];
+
+        let anonymous = Lava::new(&server.uri(), None).expect("failed to make lava server");
+        let err = anonymous
+            .cancel_job(id)
+            .await
+            .expect_err("cancelled without permission");
+        assert!(matches!(err, CancellationError::PermissionDenied));
+
+        lava.cancel_job(id).await.expect("failed to cancel");
+        let job = lava
+            .jobs()
+            .id(id)
+            .query()
+            .try_next()
+            .await
+            .expect("failed to query job")
+            .expect("cancelled job not found");
+        assert_eq!(job.state, State::Finished);
+        assert_eq!(job.health, Health::Canceled);
+
+        let err = lava
+            .cancel_job(id + 1)
+            .await
+            .expect_err("cancelled a missing job");
+        assert!(matches!(err, CancellationError::NotFound));
+    }
+
     #[test(tokio::test)]
     async fn test_junit() {
         let pop = PopulationParams::builder()
@@ -1027,4 +4219,43 @@ mod tests {
         }
         assert_eq!(seen.len(), 60);
     }
+
+    #[test(tokio::test)]
+    async fn test_junit_document() {
+        let pop = PopulationParams::builder()
+            .jobs(2usize)
+            .test_suites(2usize)
+            .test_cases(8usize)
+            .build();
+        let state = SharedState::new_populated(pop);
+        let server = LavaMock::new(state.clone(), PaginationLimits::new()).await;
+        let lava = Lava::new(&server.uri(), None).expect("failed to make lava server");
+
+        let start = state.access();
+        let mut total = 0;
+        for job in start.get_iter::<lava_api_mock::Job<lava_api_mock::State>>() {
+            let junit = lava
+                .job_results_junit(job.id)
+                .await
+                .expect("failed to obtain junit document");
+            let report = junit_parser::from_reader(std::io::Cursor::new(junit))
+                .expect("failed to parse mock junit output");
+            let cases = report.suites.iter().map(|s| s.cases.len()).sum::<usize>();
+
+            #[cfg(feature = "junit")]
+            {
+                let parsed = lava
+                    .job_results_junit_report(job.id)
+                    .await
+                    .expect("failed to obtain junit report");
+                assert_eq!(
+                    parsed.suites.iter().map(|s| s.cases.len()).sum::<usize>(),
+                    cases
+                );
+            }
+
+            total += cases;
+        }
+        assert_eq!(total, 8);
+    }
 }
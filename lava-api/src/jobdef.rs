@@ -0,0 +1,314 @@
+//! Inspect job definitions
+//!
+//! LAVA stores the definition of each job as the YAML document it was
+//! submitted with, which [`Job`](crate::job::Job) holds as a string.
+//! A [`JobDefinition`] gives typed access to the fields most
+//! consumers need, keeping everything else as [`serde_yaml::Value`]
+//! instances.
+//!
+//! Example:
+//! ```rust
+//! use lava_api::jobdef::JobDefinition;
+//! use std::time::Duration;
+//!
+//! let definition: JobDefinition = r#"
+//! job_name: boot test
+//! device_type: qemu
+//! timeouts:
+//!   job:
+//!     minutes: 10
+//! actions:
+//! - deploy:
+//!     to: tmpfs
+//! - boot:
+//!     method: qemu
+//!     timeout:
+//!       minutes: 2
+//! "#
+//! .parse()
+//! .expect("failed to parse definition");
+//!
+//! assert_eq!(definition.device_type.as_deref(), Some("qemu"));
+//! assert_eq!(definition.job_timeout(), Some(Duration::from_secs(600)));
+//! assert_eq!(definition.actions[1].kind, "boot");
+//! ```
+
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::convert::TryFrom;
+use std::str::FromStr;
+use std::time::Duration;
+
+/// A timeout from a job definition
+///
+/// LAVA timeouts are given in a single unit, for example
+/// `minutes: 5`, but any combination of units is accepted here, and
+/// they are added together.
+#[derive(Clone, Copy, Debug, Default, Deserialize, Serialize, PartialEq, Eq)]
+#[serde(default, deny_unknown_fields)]
+pub struct Timeout {
+    #[serde(skip_serializing_if = "is_zero")]
+    pub days: u64,
+    #[serde(skip_serializing_if = "is_zero")]
+    pub hours: u64,
+    #[serde(skip_serializing_if = "is_zero")]
+    pub minutes: u64,
+    #[serde(skip_serializing_if = "is_zero")]
+    pub seconds: u64,
+}
+
+fn is_zero(n: &u64) -> bool {
+    *n == 0
+}
+
+impl Timeout {
+    /// The length of this timeout.
+    ///
+    /// Timeouts too long to count in seconds are capped at
+    /// `u64::MAX` seconds.
+    pub fn duration(&self) -> Duration {
+        let hours = self.days.saturating_mul(24).saturating_add(self.hours);
+        let minutes = hours.saturating_mul(60).saturating_add(self.minutes);
+        Duration::from_secs(minutes.saturating_mul(60).saturating_add(self.seconds))
+    }
+}
+
+/// The `timeouts` section of a job definition
+#[derive(Clone, Debug, Default, Deserialize, Serialize, PartialEq, Eq)]
+#[serde(default)]
+pub struct Timeouts {
+    /// The timeout for the whole job
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub job: Option<Timeout>,
+    /// The default timeout for each action
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub action: Option<Timeout>,
+    /// The default timeout for each connection
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub connection: Option<Timeout>,
+    /// Timeouts for particular actions, by action name
+    #[serde(skip_serializing_if = "HashMap::is_empty")]
+    pub actions: HashMap<String, Timeout>,
+    /// Timeouts for the connections of particular actions, by action
+    /// name
+    #[serde(skip_serializing_if = "HashMap::is_empty")]
+    pub connections: HashMap<String, Timeout>,
+}
+
+/// The parameters of an action in a job definition
+///
+/// Only the parameters common to every kind of action are typed;
+/// the rest are kept in [`extra`](ActionParameters::extra).
+#[derive(Clone, Debug, Default, Deserialize, Serialize, PartialEq)]
+pub struct ActionParameters {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub namespace: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub timeout: Option<Timeout>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub failure_retry: Option<u32>,
+    #[serde(flatten)]
+    pub extra: HashMap<String, serde_yaml::Value>,
+}
+
+/// An action in a job definition, such as a `deploy`, `boot` or
+/// `test` action
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq)]
+#[serde(
+    try_from = "HashMap<String, ActionParameters>",
+    into = "HashMap<String, ActionParameters>"
+)]
+pub struct Action {
+    /// The kind of action, which is the key it is given under in the
+    /// definition
+    pub kind: String,
+    pub parameters: ActionParameters,
+}
+
+impl TryFrom<HashMap<String, ActionParameters>> for Action {
+    type Error = String;
+
+    fn try_from(map: HashMap<String, ActionParameters>) -> Result<Self, Self::Error> {
+        if map.len() != 1 {
+            return Err(format!(
+                "expected a single action, found {} keys",
+                map.len()
+            ));
+        }
+        let (kind, parameters) = map.into_iter().next().unwrap();
+        Ok(Action { kind, parameters })
+    }
+}
+
+impl From<Action> for HashMap<String, ActionParameters> {
+    fn from(action: Action) -> Self {
+        HashMap::from([(action.kind, action.parameters)])
+    }
+}
+
+/// A LAVA job definition
+///
+/// The fields of the definition which are not typed here are kept in
+/// [`extra`](JobDefinition::extra). Definitions can be parsed with
+/// [`str::parse`], or from a [`Job`](crate::job::Job) with
+/// [`Job::parsed_definition`](crate::job::Job::parsed_definition),
+/// and serialized again, for example to submit a modified copy.
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq)]
+pub struct JobDefinition {
+    pub job_name: String,
+    /// The requested device type, which multinode jobs give for each
+    /// role instead
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub device_type: Option<String>,
+    #[serde(default)]
+    pub timeouts: Timeouts,
+    #[serde(default)]
+    pub actions: Vec<Action>,
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub metadata: HashMap<String, serde_yaml::Value>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tags: Vec<String>,
+    #[serde(flatten)]
+    pub extra: HashMap<String, serde_yaml::Value>,
+}
+
+impl JobDefinition {
+    /// The timeout for the whole job, if one is given.
+    pub fn job_timeout(&self) -> Option<Duration> {
+        self.timeouts.job.as_ref().map(Timeout::duration)
+    }
+
+    /// The actions of the given kind, in the order they are run.
+    pub fn actions_of_kind<'a>(&'a self, kind: &'a str) -> impl Iterator<Item = &'a Action> {
+        self.actions.iter().filter(move |a| a.kind == kind)
+    }
+}
+
+impl FromStr for JobDefinition {
+    type Err = serde_yaml::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        serde_yaml::from_str(s)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{JobDefinition, Timeout};
+
+    use std::time::Duration;
+
+    #[test]
+    fn test_parse() {
+        let definition: JobDefinition = r#"
+job_name: kernel test
+device_type: rk3399-gru-kevin
+priority: medium
+visibility: public
+tags:
+- chromebook
+timeouts:
+  job:
+    hours: 1
+  action:
+    minutes: 5
+  actions:
+    lava-test-shell:
+      minutes: 20
+metadata:
+  kernel.version: "6.1"
+  build: 42
+actions:
+- deploy:
+    to: tftp
+    namespace: target
+    kernel:
+      url: https://example.com/Image
+- boot:
+    method: depthcharge
+    failure_retry: 3
+    timeout:
+      minutes: 3
+- test:
+    timeout:
+      minutes: 15
+    definitions:
+    - repository: https://example.com/tests.git
+      from: git
+      path: smoke.yaml
+      name: smoke
+"#
+        .parse()
+        .expect("failed to parse definition");
+
+        assert_eq!(definition.job_name, "kernel test");
+        assert_eq!(definition.device_type.as_deref(), Some("rk3399-gru-kevin"));
+        assert_eq!(definition.tags, vec!["chromebook".to_string()]);
+        assert_eq!(definition.job_timeout(), Some(Duration::from_secs(3600)));
+        assert_eq!(
+            definition.timeouts.action,
+            Some(Timeout {
+                minutes: 5,
+                ..Default::default()
+            })
+        );
+        assert_eq!(
+            definition.timeouts.actions["lava-test-shell"].duration(),
+            Duration::from_secs(1200)
+        );
+        assert!(definition.timeouts.connection.is_none());
+        assert_eq!(
+            definition.metadata["kernel.version"],
+            serde_yaml::Value::from("6.1")
+        );
+        assert_eq!(
+            definition.extra["priority"],
+            serde_yaml::Value::from("medium")
+        );
+        assert!(!definition.extra.contains_key("actions"));
+
+        let kinds: Vec<_> = definition.actions.iter().map(|a| a.kind.as_str()).collect();
+        assert_eq!(kinds, vec!["deploy", "boot", "test"]);
+        let deploy = &definition.actions[0].parameters;
+        assert_eq!(deploy.namespace.as_deref(), Some("target"));
+        assert_eq!(deploy.extra["to"], serde_yaml::Value::from("tftp"));
+        assert!(deploy.timeout.is_none());
+        let boot = &definition.actions[1].parameters;
+        assert_eq!(boot.failure_retry, Some(3));
+        assert_eq!(
+            boot.timeout.map(|t| t.duration()),
+            Some(Duration::from_secs(180))
+        );
+        assert_eq!(definition.actions_of_kind("test").count(), 1);
+
+        let yaml = serde_yaml::to_string(&definition).expect("failed to serialize definition");
+        assert!(!yaml.contains("null"));
+        assert_eq!(
+            yaml.parse::<JobDefinition>()
+                .expect("failed to reparse definition"),
+            definition
+        );
+
+        assert!("job_name: [".parse::<JobDefinition>().is_err());
+        assert!("job_name: bad\nactions:\n- deploy: {}\n  boot: {}\n"
+            .parse::<JobDefinition>()
+            .is_err());
+    }
+
+    #[test]
+    fn test_timeout() {
+        let timeout = Timeout {
+            days: 1,
+            hours: 2,
+            minutes: 3,
+            seconds: 4,
+        };
+        assert_eq!(timeout.duration(), Duration::from_secs(93784));
+
+        let timeout = Timeout {
+            days: u64::MAX / 2,
+            ..Default::default()
+        };
+        assert_eq!(timeout.duration(), Duration::from_secs(u64::MAX));
+    }
+}
@@ -5,22 +5,41 @@ use std::task::Poll;
 use std::time::Duration;
 
 use bytes::{Bytes, BytesMut};
-use chrono::NaiveDateTime;
+use chrono::{DateTime, FixedOffset, NaiveDateTime, TimeZone, Utc};
 use futures::future::BoxFuture;
 use futures::stream::BoxStream;
 use futures::{prelude::*, ready};
 use reqwest::{Response, StatusCode, Url};
-use serde::{Deserialize, Deserializer};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use serde_with::{DeserializeFromStr, SerializeDisplay};
 use thiserror::Error;
 
+use crate::error::{Classify, ErrorClass};
+use crate::job;
+use crate::paginator::PaginationError;
+use crate::test::PassFail;
+use crate::transport;
 use crate::Lava;
 
+/// The default interval between polls when following a log
+const DEFAULT_POLL_INTERVAL: Duration = Duration::from_secs(10);
+
+/// The target of records forwarded by [`JobLogEntry::emit_log`] and
+/// [`JobLogEntry::emit_trace`]
+pub const LOG_TARGET: &str = "lava_api::job_log";
+
 #[derive(Debug)]
 pub struct JobLogBuilder<'a> {
     lava: &'a Lava,
     id: i64,
     start: u64,
     end: u64,
+    timezone: FixedOffset,
+    follow: bool,
+    poll_interval: Duration,
+    timeout: Option<Duration>,
+    filter: JobLogFilter,
+    limits: JobLogLimits,
 }
 
 impl<'a> JobLogBuilder<'a> {
@@ -30,6 +49,12 @@ impl<'a> JobLogBuilder<'a> {
             id,
             start: 0,
             end: 0,
+            timezone: FixedOffset::east_opt(0).unwrap(),
+            follow: false,
+            poll_interval: DEFAULT_POLL_INTERVAL,
+            timeout: None,
+            filter: JobLogFilter::default(),
+            limits: JobLogLimits::default(),
         }
     }
 
@@ -43,29 +68,184 @@ impl<'a> JobLogBuilder<'a> {
         self
     }
 
+    /// Set the offset from UTC of the server's log timestamps.
+    ///
+    /// LAVA normally writes job log timestamps in UTC without an
+    /// explicit offset, which is the default here. For servers that
+    /// write local times instead, setting the offset here converts
+    /// the [`dt`](JobLogEntry::dt) of each entry returned by
+    /// [`log`](JobLogBuilder::log) to UTC, so that it can be compared
+    /// with job start and end times. Timestamps which carry their own
+    /// offset are always converted using that offset instead.
+    pub fn timezone(mut self, offset: FixedOffset) -> Self {
+        self.timezone = offset;
+        self
+    }
+
+    /// Set whether to keep following the log until the job finishes.
+    ///
+    /// When following, the [`JobLog`] returned by
+    /// [`log`](JobLogBuilder::log) does not end when it has read the
+    /// entries available so far. Instead it waits for the poll
+    /// interval, and reads any new entries, until the job reaches
+    /// [`State::Finished`](crate::job::State::Finished) and the last
+    /// of its log has been read. Logs which are not yet available,
+    /// for example because the job has not started, are treated as
+    /// empty rather than as [`JobLogError::NoData`].
+    ///
+    /// Following has no effect on [`raw`](JobLogBuilder::raw).
+    pub fn follow(mut self, follow: bool) -> Self {
+        self.follow = follow;
+        self
+    }
+
+    /// Set the interval between polls when following the log.
+    ///
+    /// The default is 10 seconds.
+    pub fn poll_interval(mut self, interval: Duration) -> Self {
+        self.poll_interval = interval;
+        self
+    }
+
+    /// Set the timeout for each request for the log, in place of the
+    /// one given to [`LavaBuilder::timeout`](crate::LavaBuilder::timeout).
+    ///
+    /// The timeout covers reading the whole of the response, so long
+    /// logs on slow connections may need more time than other
+    /// requests. Like the default timeout, this only applies to the
+    /// default transport.
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    /// Select only entries with one of the given levels.
+    ///
+    /// For example, passing `&[JobLogLevel::Error,
+    /// JobLogLevel::Results]` reads just the errors and results of a
+    /// job. Passing an empty slice selects entries of every level,
+    /// which is the default.
+    ///
+    /// LAVA cannot yet filter logs itself, so the whole log is still
+    /// downloaded, and entries are filtered as they are parsed.
+    /// Filtering has no effect on [`raw`](JobLogBuilder::raw), and
+    /// lines which cannot be parsed are still reported as errors.
+    pub fn levels(mut self, levels: &[JobLogLevel]) -> Self {
+        self.filter.levels = levels.to_vec();
+        self
+    }
+
+    /// Select only entries logged in the given namespace.
+    ///
+    /// Entries without a namespace, such as those logged before the
+    /// job's actions start, are not selected. As for
+    /// [`levels`](JobLogBuilder::levels), entries are filtered as
+    /// they are parsed.
+    pub fn namespace(mut self, namespace: &str) -> Self {
+        self.filter.namespace = Some(namespace.to_string());
+        self
+    }
+
+    /// Stop reading the log after `max_bytes` bytes.
+    ///
+    /// Job logs can run to hundreds of megabytes, so this bounds the
+    /// memory needed by consumers which keep the whole log. Only
+    /// whole lines are read within the limit. If the log has more
+    /// than this, the stream ends with
+    /// [`JobLogError::Truncated`] after the last line within the
+    /// limit; a log which fits exactly ends normally.
+    ///
+    /// The limit covers the whole of the log read, including when
+    /// following, and lines dropped by [`levels`](JobLogBuilder::levels)
+    /// or [`namespace`](JobLogBuilder::namespace). For
+    /// [`raw`](JobLogBuilder::raw) reads, the last line may be
+    /// incomplete if a single line spans the limit.
+    pub fn max_bytes(mut self, max_bytes: u64) -> Self {
+        self.limits.max_bytes = Some(max_bytes);
+        self
+    }
+
+    /// Stop reading the log after `max_entries` entries.
+    ///
+    /// As with [`max_bytes`](JobLogBuilder::max_bytes), a log with
+    /// more entries than this ends with [`JobLogError::Truncated`],
+    /// and entries dropped by filtering count towards the limit.
+    pub fn max_entries(mut self, max_entries: u64) -> Self {
+        self.limits.max_entries = Some(max_entries);
+        self
+    }
+
     pub fn raw(self) -> JobLogRaw<'a> {
-        JobLogRaw::new(self.lava, self.id, self.start, self.end)
+        JobLogRaw::new(
+            self.lava,
+            self.id,
+            self.start,
+            self.end,
+            self.timeout,
+            self.limits,
+        )
     }
 
     pub fn log(self) -> JobLog<'a> {
-        JobLog::new(self.lava, self.id, self.start, self.end)
+        JobLog::new(self)
+    }
+}
+
+// The entries selected from a log; LAVA has no way to select entries
+// on the server, so this is applied to each entry as it is parsed.
+#[derive(Debug, Clone, Default)]
+struct JobLogFilter {
+    levels: Vec<JobLogLevel>,
+    namespace: Option<String>,
+}
+
+// The most of a log to read
+#[derive(Debug, Clone, Copy, Default)]
+struct JobLogLimits {
+    max_bytes: Option<u64>,
+    max_entries: Option<u64>,
+}
+
+impl JobLogFilter {
+    fn matches(&self, entry: &JobLogEntry) -> bool {
+        (self.levels.is_empty() || self.levels.contains(&entry.lvl))
+            && (self.namespace.is_none() || entry.ns == self.namespace)
     }
 }
 
 #[derive(Debug, Error)]
 pub enum JobLogError {
-    #[error("Request failed: {0}")]
+    #[error("Job log request failed")]
     RequestError(#[from] reqwest::Error),
-    #[error("Parse error: {0} - {1}")]
-    ParseError(String, serde_yaml::Error),
+    #[error("Failed to parse job log line: {0}")]
+    ParseError(String, #[source] serde_yaml::Error),
     #[error("No data available")]
     NoData,
+    #[error("Failed to check job state")]
+    JobStateError(#[from] PaginationError),
+    #[error("Job not found")]
+    JobNotFound,
+    #[error("Job log truncated at its size limit")]
+    Truncated,
+}
+
+impl Classify for JobLogError {
+    fn class(&self) -> ErrorClass {
+        match self {
+            JobLogError::RequestError(e) => e.class(),
+            JobLogError::ParseError(..) => ErrorClass::Decode,
+            JobLogError::NoData | JobLogError::JobNotFound => ErrorClass::NotFound,
+            JobLogError::JobStateError(e) => e.class(),
+            JobLogError::Truncated => ErrorClass::Other,
+        }
+    }
 }
 
 enum LogRequest {
     Initial,
     Request(BoxFuture<'static, reqwest::Result<Response>>),
     Stream(BoxStream<'static, reqwest::Result<Bytes>>),
+    Truncated,
     Done,
 }
 
@@ -75,6 +255,7 @@ impl fmt::Debug for LogRequest {
             LogRequest::Initial => "Initial",
             LogRequest::Request(_) => "Request",
             LogRequest::Stream(_) => "Stream",
+            LogRequest::Truncated => "Truncated",
             LogRequest::Done => "Done",
         };
         f.write_str(fmt)
@@ -87,17 +268,36 @@ pub struct JobLogRaw<'a> {
     id: i64,
     start: u64,
     end: u64,
+    timeout: Option<Duration>,
+    limits: JobLogLimits,
+    bytes: u64,
+    entries: u64,
     state: LogRequest,
+    #[cfg(feature = "tracing")]
+    span: tracing::Span,
 }
 
 impl<'a> JobLogRaw<'a> {
-    fn new(lava: &'a Lava, id: i64, start: u64, end: u64) -> Self {
+    fn new(
+        lava: &'a Lava,
+        id: i64,
+        start: u64,
+        end: u64,
+        timeout: Option<Duration>,
+        limits: JobLogLimits,
+    ) -> Self {
         Self {
             lava,
             id,
             start,
             end,
+            timeout,
+            limits,
+            bytes: 0,
+            entries: 0,
             state: LogRequest::Initial,
+            #[cfg(feature = "tracing")]
+            span: tracing::debug_span!("job_log_read", job = id, start, end),
         }
     }
 
@@ -122,6 +322,38 @@ impl<'a> JobLogRaw<'a> {
         }
         url
     }
+
+    // Cut a chunk of the log short at the limits, returning what is
+    // left of it and whether anything was cut.
+    fn limit(&mut self, mut chunk: Bytes) -> (Bytes, bool) {
+        let mut end = chunk.len();
+        if let Some(max) = self.limits.max_bytes {
+            let remaining = usize::try_from(max.saturating_sub(self.bytes)).unwrap_or(usize::MAX);
+            if remaining < end {
+                end = chunk[..remaining]
+                    .iter()
+                    .rposition(|c| *c == b'\n')
+                    .map_or(0, |eol| eol + 1);
+            }
+        }
+        if let Some(max) = self.limits.max_entries {
+            let remaining = usize::try_from(max.saturating_sub(self.entries)).unwrap_or(usize::MAX);
+            let lines = chunk[..end]
+                .iter()
+                .enumerate()
+                .filter(|(_, c)| **c == b'\n')
+                .map(|(eol, _)| eol + 1);
+            if let Some(eol) = std::iter::once(0).chain(lines).nth(remaining) {
+                end = eol;
+            }
+        }
+
+        let truncated = end < chunk.len();
+        chunk.truncate(end);
+        self.bytes += chunk.len() as u64;
+        self.entries += chunk.iter().filter(|c| **c == b'\n').count() as u64;
+        (chunk, truncated)
+    }
 }
 
 impl Stream for JobLogRaw<'_> {
@@ -132,11 +364,16 @@ impl Stream for JobLogRaw<'_> {
         cx: &mut std::task::Context<'_>,
     ) -> std::task::Poll<Option<Self::Item>> {
         let me = self.get_mut();
+        #[cfg(feature = "tracing")]
+        let _span = me.span.clone().entered();
         loop {
             match me.state {
                 LogRequest::Initial => {
-                    let u = me.url();
-                    let r = me.lava.client.get(u).send();
+                    let mut request = transport::get(me.url());
+                    *request.timeout_mut() = me.timeout;
+                    let transport = me.lava.transport.clone();
+                    let retry = me.lava.retry.clone();
+                    let r = async move { retry.send(&*transport, request).await };
                     me.state = LogRequest::Request(r.boxed());
                 }
                 LogRequest::Request(ref mut r) => match ready!(r.as_mut().poll(cx)) {
@@ -156,13 +393,23 @@ impl Stream for JobLogRaw<'_> {
                 LogRequest::Stream(ref mut stream) => match ready!(stream.as_mut().poll_next(cx)) {
                     Some(Err(e)) => return Poll::Ready(Some(Err(e.into()))),
                     Some(Ok(b)) => {
-                        return Poll::Ready(Some(Ok(b)));
+                        let (b, truncated) = me.limit(b);
+                        if truncated {
+                            me.state = LogRequest::Truncated;
+                        }
+                        if !b.is_empty() {
+                            return Poll::Ready(Some(Ok(b)));
+                        }
                     }
                     None => {
                         me.state = LogRequest::Done;
                         return Poll::Ready(None);
                     }
                 },
+                LogRequest::Truncated => {
+                    me.state = LogRequest::Done;
+                    return Poll::Ready(Some(Err(JobLogError::Truncated)));
+                }
                 LogRequest::Done => return Poll::Ready(None),
             }
         }
@@ -183,20 +430,45 @@ where
     Ok(Some(Duration::from_secs_f64(duration)))
 }
 
-#[derive(Debug, Clone, Deserialize)]
+// Durations are written back as strings of seconds, as the server
+// gives them.
+fn serialize_duration<S>(duration: &Option<Duration>, s: S) -> Result<S::Ok, S::Error>
+where
+    S: Serializer,
+{
+    match duration {
+        Some(duration) => s.serialize_str(&duration.as_secs_f64().to_string()),
+        None => s.serialize_none(),
+    }
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
 pub struct JobResult {
     pub case: String,
     pub definition: String,
     pub namespace: Option<String>,
     pub level: Option<String>,
-    pub result: String,
-    #[serde(default, deserialize_with = "deserialize_duration")]
+    pub result: PassFail,
+    #[serde(
+        default,
+        deserialize_with = "deserialize_duration",
+        serialize_with = "serialize_duration",
+        skip_serializing_if = "Option::is_none"
+    )]
     pub duration: Option<Duration>,
     #[serde(default)]
     pub extra: HashMap<String, serde_yaml::Value>,
 }
 
-#[derive(Debug, Clone, Deserialize)]
+impl JobResult {
+    /// Whether this is the result of one of the dispatcher's own
+    /// actions, rather than of a test case.
+    pub fn is_action(&self) -> bool {
+        self.definition == "lava"
+    }
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
 #[serde(untagged)]
 pub enum JobLogMsg {
     Msg(String),
@@ -204,8 +476,22 @@ pub enum JobLogMsg {
     Result(JobResult),
 }
 
-#[derive(Debug, Clone, Deserialize)]
-#[serde(rename_all = "lowercase")]
+impl fmt::Display for JobLogMsg {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            JobLogMsg::Msg(msg) => f.write_str(msg),
+            JobLogMsg::Msgs(msgs) => f.write_str(&msgs.join("\n")),
+            JobLogMsg::Result(r) => write!(f, "{}.{}: {}", r.definition, r.case, r.result),
+        }
+    }
+}
+
+/// The level of a job log entry
+///
+/// LAVA has added levels in the past, so levels this crate does not
+/// know are kept as [`Other`](JobLogLevel::Other), rather than
+/// failing to parse the entry.
+#[derive(Debug, Clone, PartialEq, Eq, DeserializeFromStr, SerializeDisplay)]
 pub enum JobLogLevel {
     Debug,
     Info,
@@ -216,9 +502,156 @@ pub enum JobLogLevel {
     Input,
     Feedback,
     Exception,
+    /// A level not known to this crate, as given by the server
+    Other(String),
+}
+
+impl std::str::FromStr for JobLogLevel {
+    type Err = std::convert::Infallible;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(match s {
+            "debug" => JobLogLevel::Debug,
+            "info" => JobLogLevel::Info,
+            "warning" => JobLogLevel::Warning,
+            "error" => JobLogLevel::Error,
+            "results" => JobLogLevel::Results,
+            "target" => JobLogLevel::Target,
+            "input" => JobLogLevel::Input,
+            "feedback" => JobLogLevel::Feedback,
+            "exception" => JobLogLevel::Exception,
+            other => JobLogLevel::Other(other.to_string()),
+        })
+    }
+}
+
+impl fmt::Display for JobLogLevel {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            JobLogLevel::Debug => "debug",
+            JobLogLevel::Info => "info",
+            JobLogLevel::Warning => "warning",
+            JobLogLevel::Error => "error",
+            JobLogLevel::Results => "results",
+            JobLogLevel::Target => "target",
+            JobLogLevel::Input => "input",
+            JobLogLevel::Feedback => "feedback",
+            JobLogLevel::Exception => "exception",
+            JobLogLevel::Other(level) => level,
+        })
+    }
+}
+
+/// Map the level of a job log entry onto a [`log`] level.
+///
+/// Output from the device ([`Target`](JobLogLevel::Target)) and from
+/// other namespaces ([`Feedback`](JobLogLevel::Feedback)) is treated
+/// as informational, while the dispatcher's
+/// [`Input`](JobLogLevel::Input) to the device is debugging output.
+/// Unknown levels are also treated as informational.
+impl From<&JobLogLevel> for log::Level {
+    fn from(level: &JobLogLevel) -> Self {
+        match level {
+            JobLogLevel::Debug | JobLogLevel::Input => log::Level::Debug,
+            JobLogLevel::Info
+            | JobLogLevel::Results
+            | JobLogLevel::Target
+            | JobLogLevel::Feedback
+            | JobLogLevel::Other(_) => log::Level::Info,
+            JobLogLevel::Warning => log::Level::Warn,
+            JobLogLevel::Error | JobLogLevel::Exception => log::Level::Error,
+        }
+    }
+}
+
+impl From<JobLogLevel> for log::Level {
+    fn from(level: JobLogLevel) -> Self {
+        log::Level::from(&level)
+    }
+}
+
+/// Map the level of a job log entry onto a `tracing` level, in the
+/// same way as for [`log::Level`].
+#[cfg(feature = "tracing")]
+impl From<&JobLogLevel> for tracing::Level {
+    fn from(level: &JobLogLevel) -> Self {
+        match log::Level::from(level) {
+            log::Level::Error => tracing::Level::ERROR,
+            log::Level::Warn => tracing::Level::WARN,
+            log::Level::Info => tracing::Level::INFO,
+            log::Level::Debug => tracing::Level::DEBUG,
+            log::Level::Trace => tracing::Level::TRACE,
+        }
+    }
+}
+
+#[cfg(feature = "tracing")]
+impl From<JobLogLevel> for tracing::Level {
+    fn from(level: JobLogLevel) -> Self {
+        tracing::Level::from(&level)
+    }
+}
+
+// A log timestamp as written by the server; these are normally naive,
+// but an explicit offset is honoured if present.
+#[derive(Debug, Clone, Copy, DeserializeFromStr)]
+enum LogTime {
+    Naive(NaiveDateTime),
+    Offset(DateTime<FixedOffset>),
+}
+
+impl std::str::FromStr for LogTime {
+    type Err = chrono::ParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        DateTime::parse_from_rfc3339(s)
+            .map(LogTime::Offset)
+            .or_else(|_| s.parse().map(LogTime::Naive))
+    }
+}
+
+impl LogTime {
+    fn to_utc(self, timezone: &FixedOffset) -> NaiveDateTime {
+        match self {
+            LogTime::Naive(dt) => timezone.from_local_datetime(&dt).unwrap().naive_utc(),
+            LogTime::Offset(dt) => dt.naive_utc(),
+        }
+    }
 }
 
 #[derive(Debug, Clone, Deserialize)]
+struct LavaJobLogEntry {
+    dt: LogTime,
+    lvl: JobLogLevel,
+    ns: Option<String>,
+    msg: JobLogMsg,
+}
+
+impl LavaJobLogEntry {
+    fn into_entry(self, timezone: &FixedOffset) -> JobLogEntry {
+        JobLogEntry {
+            dt: self.dt.to_utc(timezone),
+            lvl: self.lvl,
+            ns: self.ns,
+            msg: self.msg,
+        }
+    }
+}
+
+impl From<LavaJobLogEntry> for JobLogEntry {
+    fn from(entry: LavaJobLogEntry) -> Self {
+        entry.into_entry(&FixedOffset::east_opt(0).unwrap())
+    }
+}
+
+/// A single entry in a job log
+///
+/// The timestamp [`dt`](JobLogEntry::dt) is in UTC: LAVA writes log
+/// timestamps in UTC, and when deserializing directly, timestamps
+/// with an explicit offset are converted. Servers writing local
+/// times can be accommodated with [`JobLogBuilder::timezone`].
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(from = "LavaJobLogEntry")]
 pub struct JobLogEntry {
     pub dt: NaiveDateTime,
     pub lvl: JobLogLevel,
@@ -226,20 +659,713 @@ pub struct JobLogEntry {
     pub msg: JobLogMsg,
 }
 
+impl JobLogEntry {
+    /// The time of this entry, as a UTC [`DateTime`] suitable for
+    /// comparison with the times reported for a
+    /// [`Job`](crate::job::Job).
+    pub fn time(&self) -> DateTime<Utc> {
+        Utc.from_utc_datetime(&self.dt)
+    }
+
+    /// The message of this entry, if it is a single line of text.
+    pub fn as_message(&self) -> Option<&str> {
+        match &self.msg {
+            JobLogMsg::Msg(msg) => Some(msg),
+            _ => None,
+        }
+    }
+
+    /// The result recorded by this entry, if it records one.
+    pub fn as_result(&self) -> Option<&JobResult> {
+        match &self.msg {
+            JobLogMsg::Result(r) => Some(r),
+            _ => None,
+        }
+    }
+
+    /// Whether this entry records the result of a test case.
+    ///
+    /// Results are reported both for test cases in the job's test
+    /// definitions and for the dispatcher's own actions, which use
+    /// the definition `lava`; only the former are test results.
+    pub fn is_test_result(&self) -> bool {
+        self.as_result().map(|r| !r.is_action()) == Some(true)
+    }
+
+    /// Whether this entry is at least as severe as `level`, using
+    /// the mapping of job log levels onto [`log::Level`].
+    ///
+    /// For example, `entry.is_at_least(log::Level::Warn)` is true
+    /// for warnings, errors and exceptions.
+    pub fn is_at_least(&self, level: log::Level) -> bool {
+        log::Level::from(&self.lvl) <= level
+    }
+
+    /// Forward this entry to the [`log`] crate, as a record for the
+    /// job with the given id.
+    ///
+    /// The record has the target [`LOG_TARGET`], and a level mapped
+    /// from the entry's level. The job id and namespace are included
+    /// in the message, since [`log`] records carry no other fields.
+    pub fn emit_log(&self, job: i64) {
+        let level = log::Level::from(&self.lvl);
+        match &self.ns {
+            Some(ns) => log::log!(target: LOG_TARGET, level, "job {} [{}]: {}", job, ns, self.msg),
+            None => log::log!(target: LOG_TARGET, level, "job {}: {}", job, self.msg),
+        }
+    }
+
+    /// Forward this entry to `tracing`, as an event for the job with
+    /// the given id.
+    ///
+    /// The event has the target [`LOG_TARGET`], a level mapped from
+    /// the entry's level, and fields for the job id, the namespace
+    /// and the time the entry was logged.
+    #[cfg(feature = "tracing")]
+    pub fn emit_trace(&self, job: i64) {
+        macro_rules! emit {
+            ($level:expr) => {
+                tracing::event!(
+                    target: LOG_TARGET,
+                    $level,
+                    job,
+                    namespace = self.ns.as_deref(),
+                    time = %self.time(),
+                    "{}",
+                    self.msg
+                )
+            };
+        }
+        match tracing::Level::from(&self.lvl) {
+            tracing::Level::ERROR => emit!(tracing::Level::ERROR),
+            tracing::Level::WARN => emit!(tracing::Level::WARN),
+            tracing::Level::INFO => emit!(tracing::Level::INFO),
+            tracing::Level::DEBUG => emit!(tracing::Level::DEBUG),
+            _ => emit!(tracing::Level::TRACE),
+        }
+    }
+}
+
+/// An action performed by the LAVA dispatcher while running a job
+///
+/// Actions form a tree, identified by their dotted
+/// [`level`](JobAction::level): action `1.2` is a child of action
+/// `1`. They are reconstructed from the `start:` and `end:` markers
+/// in the job log by [`actions`].
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct JobAction {
+    pub level: String,
+    pub name: String,
+    pub namespace: Option<String>,
+    pub started: NaiveDateTime,
+    /// When the action ended, if the log records its end
+    pub ended: Option<NaiveDateTime>,
+    pub timeout: Option<Duration>,
+    /// The duration of the action as reported by the dispatcher
+    pub duration: Option<Duration>,
+    /// The result reported for this action
+    pub result: Option<PassFail>,
+    pub children: Vec<JobAction>,
+}
+
+impl JobAction {
+    /// Whether the dispatcher reported a failure for this action.
+    pub fn is_failed(&self) -> bool {
+        self.result == Some(PassFail::Fail)
+    }
+
+    /// Find the action with the given level in this subtree.
+    pub fn find(&self, level: &str) -> Option<&JobAction> {
+        if self.level == level {
+            return Some(self);
+        }
+        self.children.iter().find_map(|c| c.find(level))
+    }
+}
+
+// Parse durations of the form HH:MM:SS as used in action markers
+fn parse_marker_duration(s: &str) -> Option<Duration> {
+    let mut parts = s.split(':');
+    let hours: u64 = parts.next()?.parse().ok()?;
+    let minutes: u64 = parts.next()?.parse().ok()?;
+    let seconds: f64 = parts.next()?.parse().ok()?;
+    if parts.next().is_some() {
+        return None;
+    }
+    let whole = hours
+        .checked_mul(3600)?
+        .checked_add(minutes.checked_mul(60)?)?;
+    Duration::from_secs(whole).checked_add(Duration::try_from_secs_f64(seconds).ok()?)
+}
+
+struct ActionMarker<'a> {
+    start: bool,
+    level: &'a str,
+    name: &'a str,
+    duration: Option<Duration>,
+    namespace: Option<&'a str>,
+}
+
+// Parse markers of the form:
+//   start: 1.2 action-name (timeout 00:10:00) [namespace]
+//   end: 1.2 action-name (duration 00:00:01) [namespace]
+fn parse_marker(msg: &str) -> Option<ActionMarker<'_>> {
+    let (start, rest) = if let Some(rest) = msg.strip_prefix("start: ") {
+        (true, rest)
+    } else {
+        (false, msg.strip_prefix("end: ")?)
+    };
+    let mut words = rest.split_whitespace();
+    let level = words.next()?;
+    if !level.chars().all(|c| c.is_ascii_digit() || c == '.') {
+        return None;
+    }
+    let name = words.next()?;
+
+    let mut duration = None;
+    let mut namespace = None;
+    while let Some(word) = words.next() {
+        if word == "(timeout" || word == "(duration" {
+            duration = words
+                .next()
+                .and_then(|d| d.strip_suffix(')'))
+                .and_then(parse_marker_duration);
+        } else if let Some(ns) = word.strip_prefix('[').and_then(|w| w.strip_suffix(']')) {
+            namespace = Some(ns);
+        }
+    }
+
+    Some(ActionMarker {
+        start,
+        level,
+        name,
+        duration,
+        namespace,
+    })
+}
+
+// A node of a tree of actions built from the markers in a log
+trait ActionNode: Sized {
+    fn level(&self) -> &str;
+    fn children_mut(&mut self) -> &mut Vec<Self>;
+}
+
+impl ActionNode for JobAction {
+    fn level(&self) -> &str {
+        &self.level
+    }
+
+    fn children_mut(&mut self) -> &mut Vec<Self> {
+        &mut self.children
+    }
+}
+
+// The actions of a log being read: those still open, from the
+// outermost inwards, and the outermost of those which have closed.
+// Closing an action moves it into the children of its parent.
+#[derive(Debug, Clone, Serialize)]
+struct ActionStack<T> {
+    #[serde(rename = "sections")]
+    closed: Vec<T>,
+    open: Vec<T>,
+}
+
+impl<T> Default for ActionStack<T> {
+    fn default() -> Self {
+        Self {
+            closed: Vec::new(),
+            open: Vec::new(),
+        }
+    }
+}
+
+impl<T: ActionNode> ActionStack<T> {
+    // Open a new action, first closing those which do not contain it
+    fn start(&mut self, action: T) {
+        let prefix = format!("{}.", action.level());
+        while let Some(top) = self.open.last() {
+            if prefix.starts_with(&format!("{}.", top.level())) {
+                break;
+            }
+            self.close_top();
+        }
+        self.open.push(action);
+    }
+
+    fn is_open(&self, level: &str) -> bool {
+        self.open.iter().any(|a| a.level() == level)
+    }
+
+    // Close the open action with the given level, and any within it,
+    // passing it to `f` just before it closes. Does nothing if no such
+    // action is open.
+    fn end<F: FnOnce(&mut T)>(&mut self, level: &str, f: F) {
+        if !self.is_open(level) {
+            return;
+        }
+        while self.open.last().map(|a| a.level() != level) == Some(true) {
+            self.close_top();
+        }
+        if let Some(top) = self.open.last_mut() {
+            f(top);
+        }
+        self.close_top();
+    }
+
+    fn close(&mut self) {
+        while !self.open.is_empty() {
+            self.close_top();
+        }
+    }
+
+    fn close_top(&mut self) {
+        if let Some(action) = self.open.pop() {
+            match self.open.last_mut() {
+                Some(parent) => parent.children_mut().push(action),
+                None => self.closed.push(action),
+            }
+        }
+    }
+}
+
+fn apply_results(action: &mut JobAction, results: &HashMap<String, PassFail>) {
+    if let Some(result) = results.get(&action.level) {
+        action.result = Some(*result);
+    }
+    for child in action.children.iter_mut() {
+        apply_results(child, results);
+    }
+}
+
+/// Reconstruct the tree of [`JobAction`] instances from job log
+/// entries.
+///
+/// The entries should be given in the order they were logged. An
+/// action whose end is missing from the log (because the job is still
+/// running, or was cut short) has no [`ended`](JobAction::ended)
+/// time. Results logged for an action's level are recorded in its
+/// [`result`](JobAction::result).
+pub fn actions<'a, I>(entries: I) -> Vec<JobAction>
+where
+    I: IntoIterator<Item = &'a JobLogEntry>,
+{
+    let mut stack = ActionStack::default();
+    let mut results = HashMap::new();
+
+    for entry in entries {
+        let msg = match &entry.msg {
+            JobLogMsg::Msg(msg) => msg,
+            JobLogMsg::Result(r) => {
+                if let Some(level) = &r.level {
+                    results.insert(level.clone(), r.result);
+                }
+                continue;
+            }
+            JobLogMsg::Msgs(_) => continue,
+        };
+        let marker = match parse_marker(msg) {
+            Some(marker) => marker,
+            None => continue,
+        };
+
+        if marker.start {
+            stack.start(JobAction {
+                level: marker.level.to_string(),
+                name: marker.name.to_string(),
+                namespace: marker
+                    .namespace
+                    .map(str::to_string)
+                    .or_else(|| entry.ns.clone()),
+                started: entry.dt,
+                ended: None,
+                timeout: marker.duration,
+                duration: None,
+                result: None,
+                children: Vec::new(),
+            });
+        } else {
+            stack.end(marker.level, |action| {
+                action.ended = Some(entry.dt);
+                action.duration = marker.duration;
+            });
+        }
+    }
+
+    stack.close();
+    for root in stack.closed.iter_mut() {
+        apply_results(root, &results);
+    }
+    stack.closed
+}
+
+/// A section of a job log, covering one action of the dispatcher
+///
+/// Like [`JobAction`], sections form a tree by their dotted
+/// [`level`](JobLogSection::level), but each also holds the entries
+/// logged while its action ran, other than those logged within its
+/// [`children`](JobLogSection::children). The start and end markers
+/// of the action are the first and last of its entries.
+#[derive(Debug, Clone, Serialize)]
+pub struct JobLogSection {
+    pub level: String,
+    pub name: String,
+    pub namespace: Option<String>,
+    pub started: NaiveDateTime,
+    /// When the action ended, if the log records its end
+    pub ended: Option<NaiveDateTime>,
+    pub timeout: Option<Duration>,
+    /// The duration of the action as reported by the dispatcher
+    pub duration: Option<Duration>,
+    /// The result reported for this action
+    pub result: Option<PassFail>,
+    pub entries: Vec<JobLogEntry>,
+    pub children: Vec<JobLogSection>,
+}
+
+impl JobLogSection {
+    /// How long the action took: the duration reported by the
+    /// dispatcher, or else the time between its start and end
+    /// markers, if it has ended.
+    pub fn elapsed(&self) -> Option<Duration> {
+        self.duration
+            .or_else(|| (self.ended? - self.started).to_std().ok())
+    }
+
+    /// Whether the dispatcher reported a failure for this action.
+    pub fn is_failed(&self) -> bool {
+        self.result == Some(PassFail::Fail)
+    }
+
+    /// Find the section with the given level in this subtree.
+    pub fn find(&self, level: &str) -> Option<&JobLogSection> {
+        if self.level == level {
+            return Some(self);
+        }
+        self.children.iter().find_map(|c| c.find(level))
+    }
+
+    // The most recent section in this subtree for the result of an
+    // action with the given level and namespace
+    fn find_result_mut(
+        &mut self,
+        level: &str,
+        namespace: Option<&str>,
+    ) -> Option<&mut JobLogSection> {
+        if self.level == level
+            && (namespace.is_none()
+                || self.namespace.is_none()
+                || self.namespace.as_deref() == namespace)
+        {
+            return Some(self);
+        }
+        self.children
+            .iter_mut()
+            .rev()
+            .find_map(|c| c.find_result_mut(level, namespace))
+    }
+}
+
+impl ActionNode for JobLogSection {
+    fn level(&self) -> &str {
+        &self.level
+    }
+
+    fn children_mut(&mut self) -> &mut Vec<Self> {
+        &mut self.children
+    }
+}
+
+/// A job log grouped into [`JobLogSection`]s as it is read
+///
+/// Entries are added one at a time with [`push`](JobLogTree::push),
+/// usually as they arrive from a [`JobLog`], so that a viewer can
+/// show the sections completed so far, and those still open, while
+/// the job runs. Results reported for an action are recorded in its
+/// section, matching both the level and, where both give one, the
+/// namespace.
+///
+/// Example:
+/// ```rust
+/// use lava_api::joblog::{JobLogEntry, JobLogTree};
+///
+/// let entries: Vec<JobLogEntry> = serde_yaml::from_str(
+///     r#"
+/// - {"dt": "2022-04-11T10:00:00.000000", "lvl": "info", "msg": "start: 1 deploy [common]"}
+/// - {"dt": "2022-04-11T10:00:01.000000", "lvl": "debug", "msg": "downloading"}
+/// - {"dt": "2022-04-11T10:00:02.000000", "lvl": "info", "msg": "end: 1 deploy (duration 00:00:02) [common]"}
+/// "#,
+/// )
+/// .expect("failed to parse log");
+///
+/// let mut tree = JobLogTree::new();
+/// tree.extend(entries);
+/// let deploy = tree.find("1").expect("no deploy section");
+/// assert_eq!(deploy.entries.len(), 3);
+/// ```
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct JobLogTree {
+    entries: Vec<JobLogEntry>,
+    #[serde(flatten)]
+    sections: ActionStack<JobLogSection>,
+}
+
+impl JobLogTree {
+    /// Create an empty [`JobLogTree`].
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Read the whole of `log` into a new [`JobLogTree`], closing any
+    /// sections left open at the end.
+    pub async fn from_log<S>(log: S) -> Result<Self, JobLogError>
+    where
+        S: Stream<Item = Result<JobLogEntry, JobLogError>>,
+    {
+        let mut tree = log
+            .try_fold(Self::new(), |mut tree, entry| async move {
+                tree.push(entry);
+                Ok(tree)
+            })
+            .await?;
+        tree.close();
+        Ok(tree)
+    }
+
+    /// Add the next entry of the log.
+    pub fn push(&mut self, entry: JobLogEntry) {
+        let marker = entry.as_message().and_then(parse_marker).map(|m| {
+            (
+                m.start,
+                m.level.to_string(),
+                m.name.to_string(),
+                m.duration,
+                m.namespace.map(str::to_string),
+            )
+        });
+
+        match marker {
+            Some((true, level, name, timeout, namespace)) => {
+                self.sections.start(JobLogSection {
+                    level,
+                    name,
+                    namespace: namespace.or_else(|| entry.ns.clone()),
+                    started: entry.dt,
+                    ended: None,
+                    timeout,
+                    duration: None,
+                    result: None,
+                    entries: vec![entry],
+                    children: Vec::new(),
+                });
+            }
+            Some((false, level, _, duration, _)) if self.sections.is_open(&level) => {
+                self.sections.end(&level, |section| {
+                    section.ended = Some(entry.dt);
+                    section.duration = duration;
+                    section.entries.push(entry);
+                });
+            }
+            _ => {
+                if let Some(r) = entry.as_result() {
+                    if let Some(level) = &r.level {
+                        let namespace = r.namespace.as_deref().or(entry.ns.as_deref());
+                        if let Some(section) = self.find_result_mut(level, namespace) {
+                            section.result = Some(r.result);
+                        }
+                    }
+                }
+                match self.sections.open.last_mut() {
+                    Some(top) => top.entries.push(entry),
+                    None => self.entries.push(entry),
+                }
+            }
+        }
+    }
+
+    /// Close the sections still open, as when the log has ended
+    /// without their end markers.
+    ///
+    /// Closed sections have no [`ended`](JobLogSection::ended) time.
+    pub fn close(&mut self) {
+        self.sections.close();
+    }
+
+    /// The entries logged outside any section.
+    pub fn entries(&self) -> &[JobLogEntry] {
+        &self.entries
+    }
+
+    /// The outermost sections which have been closed, in the order
+    /// they started.
+    pub fn sections(&self) -> &[JobLogSection] {
+        &self.sections.closed
+    }
+
+    /// The sections still open, from the outermost inwards.
+    ///
+    /// The children of each are those which have been closed; the
+    /// open section within it is the next in the list.
+    pub fn open_sections(&self) -> &[JobLogSection] {
+        &self.sections.open
+    }
+
+    /// Find the section with the given level, whether closed or
+    /// open.
+    pub fn find(&self, level: &str) -> Option<&JobLogSection> {
+        self.sections
+            .closed
+            .iter()
+            .chain(self.sections.open.iter())
+            .find_map(|s| s.find(level))
+    }
+
+    fn find_result_mut(
+        &mut self,
+        level: &str,
+        namespace: Option<&str>,
+    ) -> Option<&mut JobLogSection> {
+        self.sections
+            .open
+            .iter_mut()
+            .rev()
+            .chain(self.sections.closed.iter_mut().rev())
+            .find_map(|s| s.find_result_mut(level, namespace))
+    }
+}
+
+impl Extend<JobLogEntry> for JobLogTree {
+    fn extend<T: IntoIterator<Item = JobLogEntry>>(&mut self, iter: T) {
+        for entry in iter {
+            self.push(entry);
+        }
+    }
+}
+
+enum FollowState<'a> {
+    Checking(BoxFuture<'a, Result<Option<job::State>, PaginationError>>),
+    Reading { finished: bool },
+    Waiting(Pin<Box<tokio::time::Sleep>>),
+    Done,
+}
+
+impl fmt::Debug for FollowState<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FollowState::Checking(_) => f.write_str("Checking"),
+            FollowState::Reading { finished } => f
+                .debug_struct("Reading")
+                .field("finished", finished)
+                .finish(),
+            FollowState::Waiting(_) => f.write_str("Waiting"),
+            FollowState::Done => f.write_str("Done"),
+        }
+    }
+}
+
+// The progress of a JobLog which is following a job's log.
+//
+// Each round checks the job's state before reading the log from the
+// current offset, so that once the job is seen to be finished, the
+// read which follows is known to be complete.
+#[derive(Debug)]
+struct Follow<'a> {
+    lava: &'a Lava,
+    id: i64,
+    start: u64,
+    offset: u64,
+    end: u64,
+    interval: Duration,
+    timeout: Option<Duration>,
+    limits: JobLogLimits,
+    bytes: u64,
+    state: FollowState<'a>,
+}
+
+impl<'a> Follow<'a> {
+    fn new(builder: &JobLogBuilder<'a>) -> Self {
+        let mut follow = Self {
+            lava: builder.lava,
+            id: builder.id,
+            start: builder.start,
+            offset: builder.start,
+            end: builder.end,
+            interval: builder.poll_interval,
+            timeout: builder.timeout,
+            limits: builder.limits,
+            bytes: 0,
+            state: FollowState::Done,
+        };
+        follow.state = FollowState::Checking(follow.check());
+        follow
+    }
+
+    fn check(&self) -> BoxFuture<'a, Result<Option<job::State>, PaginationError>> {
+        let lava = self.lava;
+        let id = self.id;
+        async move {
+            let mut jobs = lava.jobs().id(id).query();
+            Ok(jobs.try_next().await?.map(|job| job.state))
+        }
+        .boxed()
+    }
+
+    // Whether the requested range of the log has been read
+    fn at_end(&self) -> bool {
+        self.end != 0 && self.offset >= self.end
+    }
+
+    // The limits for the next read, less what has been read so far
+    fn remaining(&self) -> JobLogLimits {
+        JobLogLimits {
+            max_bytes: self.limits.max_bytes.map(|m| m.saturating_sub(self.bytes)),
+            max_entries: self
+                .limits
+                .max_entries
+                .map(|m| m.saturating_sub(self.offset - self.start)),
+        }
+    }
+}
+
 #[derive(Debug)]
 pub struct JobLog<'a> {
     buf: Vec<Bytes>,
     from_buf: bool,
     raw: JobLogRaw<'a>,
+    timezone: FixedOffset,
+    follow: Option<Follow<'a>>,
+    filter: JobLogFilter,
+    #[cfg(feature = "tracing")]
+    span: tracing::Span,
 }
 
 impl<'a> JobLog<'a> {
-    fn new(lava: &'a Lava, id: i64, start: u64, end: u64) -> Self {
-        let raw = JobLogRaw::new(lava, id, start, end);
+    fn new(builder: JobLogBuilder<'a>) -> Self {
+        let follow = builder.follow.then(|| Follow::new(&builder));
+        let new_raw = || {
+            JobLogRaw::new(
+                builder.lava,
+                builder.id,
+                builder.start,
+                builder.end,
+                builder.timeout,
+                builder.limits,
+            )
+        };
+        #[cfg(feature = "tracing")]
+        let span = tracing::debug_span!("job_log", job = builder.id, follow = follow.is_some());
+        #[cfg(feature = "tracing")]
+        let raw = span.in_scope(new_raw);
+        #[cfg(not(feature = "tracing"))]
+        let raw = new_raw();
         Self {
             buf: Vec::new(),
             from_buf: false,
             raw,
+            timezone: builder.timezone,
+            follow,
+            filter: builder.filter,
+            #[cfg(feature = "tracing")]
+            span,
         }
     }
 }
@@ -252,7 +1378,48 @@ impl<'a> Stream for JobLog<'a> {
         cx: &mut std::task::Context<'_>,
     ) -> Poll<Option<Self::Item>> {
         let me = self.get_mut();
+        #[cfg(feature = "tracing")]
+        let _span = me.span.clone().entered();
         loop {
+            if let Some(follow) = me.follow.as_mut() {
+                match follow.state {
+                    FollowState::Checking(ref mut f) => {
+                        let state = match ready!(f.as_mut().poll(cx)) {
+                            Ok(Some(state)) => state,
+                            Ok(None) => {
+                                follow.state = FollowState::Done;
+                                return Poll::Ready(Some(Err(JobLogError::JobNotFound)));
+                            }
+                            Err(e) => {
+                                follow.state = FollowState::Done;
+                                return Poll::Ready(Some(Err(e.into())));
+                            }
+                        };
+                        // Partial lines are read again from the start
+                        me.buf.clear();
+                        me.from_buf = false;
+                        me.raw = JobLogRaw::new(
+                            follow.lava,
+                            follow.id,
+                            follow.offset,
+                            follow.end,
+                            follow.timeout,
+                            follow.remaining(),
+                        );
+                        follow.state = FollowState::Reading {
+                            finished: state == job::State::Finished,
+                        };
+                    }
+                    FollowState::Waiting(ref mut sleep) => {
+                        ready!(sleep.as_mut().poll(cx));
+                        follow.state = FollowState::Checking(follow.check());
+                        continue;
+                    }
+                    FollowState::Reading { .. } => (),
+                    FollowState::Done => return Poll::Ready(None),
+                }
+            }
+
             if me.from_buf {
                 let last = me.buf.last().unwrap();
                 if let Some(eol) = last.iter().position(|e| e == &b'\n') {
@@ -280,25 +1447,626 @@ impl<'a> Stream for JobLog<'a> {
                         }
                         buf.into()
                     };
+                    if let Some(follow) = me.follow.as_mut() {
+                        follow.offset += 1;
+                        follow.bytes += line.len() as u64;
+                    }
                     let l = line.slice(1..);
-                    let entry = serde_yaml::from_slice(l.as_ref()).map_err(|e| {
-                        let s = String::from_utf8_lossy(l.as_ref());
-                        JobLogError::ParseError(s.into_owned(), e)
-                    });
-                    return Poll::Ready(Some(entry));
+                    let entry = serde_yaml::from_slice(l.as_ref())
+                        .map(|e: LavaJobLogEntry| e.into_entry(&me.timezone))
+                        .map_err(|e| {
+                            let s = String::from_utf8_lossy(l.as_ref());
+                            JobLogError::ParseError(s.into_owned(), e)
+                        });
+                    match entry {
+                        Ok(entry) if !me.filter.matches(&entry) => continue,
+                        entry => return Poll::Ready(Some(entry)),
+                    }
                 } else {
                     me.from_buf = false;
                 }
             } else {
                 match ready!(Pin::new(&mut me.raw).poll_next(cx)) {
+                    // When following, a missing log is one which has
+                    // not been written yet
+                    Some(Err(JobLogError::NoData)) if me.follow.is_some() => (),
+                    Some(Err(JobLogError::Truncated)) => {
+                        if let Some(follow) = me.follow.as_mut() {
+                            follow.state = FollowState::Done;
+                        }
+                        return Poll::Ready(Some(Err(JobLogError::Truncated)));
+                    }
                     Some(Err(e)) => return Poll::Ready(Some(Err(e))),
                     Some(Ok(b)) => {
                         me.from_buf = true;
                         me.buf.push(b);
                     }
-                    None => return Poll::Ready(None),
+                    None => match me.follow.as_mut() {
+                        Some(follow) => {
+                            follow.state = match follow.state {
+                                FollowState::Reading { finished: false } if !follow.at_end() => {
+                                    FollowState::Waiting(Box::pin(tokio::time::sleep(
+                                        follow.interval,
+                                    )))
+                                }
+                                _ => FollowState::Done,
+                            };
+                        }
+                        None => return Poll::Ready(None),
+                    },
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_actions() {
+        let log = r#"
+- {"dt": "2022-04-11T10:00:00.000000", "lvl": "info", "msg": "start: 0 validate"}
+- {"dt": "2022-04-11T10:00:01.000000", "lvl": "info", "msg": "Start time: 2022-04-11 10:00:01"}
+- {"dt": "2022-04-11T10:00:02.000000", "lvl": "info", "msg": "start: 1 tftp-deploy (timeout 00:10:00) [common]"}
+- {"dt": "2022-04-11T10:00:03.000000", "lvl": "info", "msg": "start: 1.1 download-retry (timeout 00:10:00) [common]"}
+- {"dt": "2022-04-11T10:00:05.000000", "lvl": "info", "msg": "end: 1.1 download-retry (duration 00:00:02) [common]"}
+- {"dt": "2022-04-11T10:00:05.000000", "lvl": "results", "msg": {"case": "download-retry", "definition": "lava", "duration": "2.00", "level": "1.1", "namespace": "common", "result": "pass"}}
+- {"dt": "2022-04-11T10:00:06.000000", "lvl": "info", "msg": "end: 1 tftp-deploy (duration 00:00:04) [common]"}
+- {"dt": "2022-04-11T10:00:07.000000", "lvl": "info", "msg": "start: 2 uboot-action (timeout 00:05:00) [common]"}
+- {"dt": "2022-04-11T10:00:08.000000", "lvl": "info", "msg": "start: 2.1 bootloader-interrupt (timeout 00:00:30) [common]"}
+- {"dt": "2022-04-11T10:00:38.000000", "lvl": "error", "msg": "bootloader-interrupt timed out after 30 seconds"}
+- {"dt": "2022-04-11T10:00:38.000000", "lvl": "results", "msg": {"case": "bootloader-interrupt", "definition": "lava", "level": "2.1", "namespace": "common", "result": "fail"}}
+- {"dt": "2022-04-11T10:00:39.000000", "lvl": "info", "msg": "end: 2 uboot-action (duration 00:00:32) [common]"}
+"#;
+        let entries: Vec<JobLogEntry> = serde_yaml::from_str(log).expect("failed to parse log");
+        let actions = actions(&entries);
+
+        assert_eq!(actions.len(), 3);
+        assert_eq!(actions[0].level, "0");
+        assert_eq!(actions[0].name, "validate");
+        assert_eq!(actions[0].ended, None);
+        assert!(actions[0].children.is_empty());
+
+        let deploy = &actions[1];
+        assert_eq!(deploy.name, "tftp-deploy");
+        assert_eq!(deploy.namespace.as_deref(), Some("common"));
+        assert_eq!(deploy.timeout, Some(Duration::from_secs(600)));
+        assert_eq!(deploy.duration, Some(Duration::from_secs(4)));
+        assert_eq!(deploy.children.len(), 1);
+        let download = deploy.find("1.1").expect("missing download action");
+        assert_eq!(download.name, "download-retry");
+        assert_eq!(download.duration, Some(Duration::from_secs(2)));
+        assert_eq!(download.result, Some(PassFail::Pass));
+        assert!(!download.is_failed());
+
+        let boot = &actions[2];
+        assert_eq!(boot.name, "uboot-action");
+        assert_eq!(boot.duration, Some(Duration::from_secs(32)));
+        assert!(boot.ended.is_some());
+        let interrupt = boot.find("2.1").expect("missing interrupt action");
+        assert_eq!(interrupt.timeout, Some(Duration::from_secs(30)));
+        assert_eq!(interrupt.ended, None);
+        assert!(interrupt.is_failed());
+    }
+
+    #[test]
+    fn test_marker_duration() {
+        assert_eq!(
+            parse_marker_duration("01:02:03.5"),
+            Some(Duration::from_secs_f64(3723.5))
+        );
+        assert_eq!(parse_marker_duration("00:00:-1"), None);
+        assert_eq!(parse_marker_duration("00:00:inf"), None);
+        assert_eq!(parse_marker_duration("00:00:01:00"), None);
+        assert_eq!(
+            parse_marker_duration(&format!("{}:00:00", u64::MAX / 1000)),
+            None
+        );
+        assert_eq!(parse_marker_duration(&format!("00:00:{}", f64::MAX)), None);
+    }
+
+    #[test]
+    fn test_tree() {
+        let log = r#"
+- {"dt": "2022-04-11T09:59:59.000000", "lvl": "info", "msg": "lava-dispatcher, installed at version: 2022.04"}
+- {"dt": "2022-04-11T10:00:00.000000", "lvl": "info", "msg": "start: 1 tftp-deploy (timeout 00:10:00) [common]"}
+- {"dt": "2022-04-11T10:00:01.000000", "lvl": "info", "msg": "start: 1.1 download-retry (timeout 00:10:00) [common]"}
+- {"dt": "2022-04-11T10:00:02.000000", "lvl": "debug", "msg": "downloading kernel"}
+- {"dt": "2022-04-11T10:00:03.000000", "lvl": "info", "msg": "end: 1.1 download-retry (duration 00:00:02) [common]"}
+- {"dt": "2022-04-11T10:00:03.000000", "lvl": "results", "msg": {"case": "download-retry", "definition": "lava", "duration": "2.00", "level": "1.1", "namespace": "common", "result": "pass"}}
+- {"dt": "2022-04-11T10:00:04.000000", "lvl": "debug", "msg": "deploy done"}
+- {"dt": "2022-04-11T10:00:05.000000", "lvl": "info", "msg": "end: 1 tftp-deploy [common]"}
+- {"dt": "2022-04-11T10:00:06.000000", "lvl": "info", "msg": "start: 2 uboot-action (timeout 00:05:00) [common]"}
+- {"dt": "2022-04-11T10:00:07.000000", "lvl": "info", "msg": "start: 2.1 bootloader-interrupt (timeout 00:00:30) [common]"}
+- {"dt": "2022-04-11T10:00:37.000000", "lvl": "error", "msg": "bootloader-interrupt timed out after 30 seconds"}
+- {"dt": "2022-04-11T10:00:37.000000", "lvl": "results", "msg": {"case": "bootloader-interrupt", "definition": "lava", "level": "2.1", "namespace": "common", "result": "fail"}}
+"#;
+        let entries: Vec<JobLogEntry> = serde_yaml::from_str(log).expect("failed to parse log");
+
+        let mut tree = JobLogTree::new();
+        tree.extend(entries.iter().take(4).cloned());
+        assert!(tree.sections().is_empty());
+        assert_eq!(tree.open_sections().len(), 2);
+        assert_eq!(tree.open_sections()[1].entries.len(), 2);
+
+        tree.extend(entries.into_iter().skip(4));
+        assert_eq!(tree.entries().len(), 1);
+        assert_eq!(tree.sections().len(), 1);
+
+        let deploy = &tree.sections()[0];
+        assert_eq!(deploy.name, "tftp-deploy");
+        assert_eq!(deploy.namespace.as_deref(), Some("common"));
+        assert_eq!(deploy.duration, None);
+        assert_eq!(deploy.elapsed(), Some(Duration::from_secs(5)));
+        // The markers, the results of the download, which are logged
+        // after it ends, and the last debug message
+        assert_eq!(deploy.entries.len(), 4);
+        let download = deploy.find("1.1").expect("missing download section");
+        assert_eq!(download.entries.len(), 3);
+        assert_eq!(download.elapsed(), Some(Duration::from_secs(2)));
+        assert_eq!(download.result, Some(PassFail::Pass));
+
+        let interrupt = tree.find("2.1").expect("missing interrupt section");
+        assert!(interrupt.is_failed());
+        assert_eq!(interrupt.elapsed(), None);
+
+        tree.close();
+        assert!(tree.open_sections().is_empty());
+        assert_eq!(tree.sections().len(), 2);
+        let boot = &tree.sections()[1];
+        assert_eq!(boot.ended, None);
+        assert_eq!(boot.children.len(), 1);
+        assert!(boot.find("2.1").unwrap().is_failed());
+    }
+
+    #[test]
+    fn test_timezone() {
+        let log = r#"
+- {"dt": "2022-04-11T10:00:00.500000", "lvl": "info", "msg": "naive"}
+- {"dt": "2022-04-11T12:00:00+02:00", "lvl": "info", "msg": "offset"}
+"#;
+        let expected = Utc.with_ymd_and_hms(2022, 4, 11, 10, 0, 0).unwrap();
+
+        let entries: Vec<JobLogEntry> = serde_yaml::from_str(log).expect("failed to parse log");
+        assert_eq!(
+            entries[0].time(),
+            expected + chrono::Duration::milliseconds(500)
+        );
+        assert_eq!(entries[1].time(), expected);
+
+        let plus_one = FixedOffset::east_opt(3600).unwrap();
+        let entries: Vec<LavaJobLogEntry> = serde_yaml::from_str(log).expect("failed to parse log");
+        let entries: Vec<JobLogEntry> = entries
+            .into_iter()
+            .map(|e| e.into_entry(&plus_one))
+            .collect();
+        assert_eq!(
+            entries[0].time(),
+            expected - chrono::Duration::minutes(60) + chrono::Duration::milliseconds(500)
+        );
+        assert_eq!(entries[1].time(), expected);
+    }
+
+    #[test]
+    fn test_levels() {
+        let log = r#"
+- {"dt": "2022-04-11T10:00:00.000000", "lvl": "target", "msg": "U-Boot 2022.01"}
+- {"dt": "2022-04-11T10:00:01.000000", "lvl": "input", "msg": "boot\n"}
+- {"dt": "2022-04-11T10:00:02.000000", "lvl": "exception", "msg": "Job timed out"}
+- {"dt": "2022-04-11T10:00:03.000000", "lvl": "results", "msg": {"case": "boot", "definition": "lava", "result": "pass"}}
+"#;
+        let entries: Vec<JobLogEntry> = serde_yaml::from_str(log).expect("failed to parse log");
+        let levels = entries
+            .iter()
+            .map(|e| log::Level::from(&e.lvl))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            levels,
+            vec![
+                log::Level::Info,
+                log::Level::Debug,
+                log::Level::Error,
+                log::Level::Info
+            ]
+        );
+        assert_eq!(entries[0].msg.to_string(), "U-Boot 2022.01");
+        assert_eq!(entries[3].msg.to_string(), "lava.boot: pass");
+
+        for entry in entries.iter() {
+            entry.emit_log(5);
+        }
+
+        #[cfg(feature = "tracing")]
+        {
+            let subscriber = TraceLevels::default();
+            let traced = subscriber.0.clone();
+            tracing::subscriber::with_default(subscriber, || {
+                for entry in entries.iter() {
+                    entry.emit_trace(5);
                 }
+            });
+            assert_eq!(
+                *traced.lock().unwrap(),
+                vec![
+                    tracing::Level::INFO,
+                    tracing::Level::DEBUG,
+                    tracing::Level::ERROR,
+                    tracing::Level::INFO
+                ]
+            );
+        }
+    }
+
+    // A subscriber recording the levels of the events forwarded from
+    // job logs
+    #[cfg(feature = "tracing")]
+    #[derive(Default)]
+    struct TraceLevels(std::sync::Arc<std::sync::Mutex<Vec<tracing::Level>>>);
+
+    #[cfg(feature = "tracing")]
+    impl tracing::Subscriber for TraceLevels {
+        fn enabled(&self, _: &tracing::Metadata<'_>) -> bool {
+            true
+        }
+
+        fn new_span(&self, _: &tracing::span::Attributes<'_>) -> tracing::span::Id {
+            tracing::span::Id::from_u64(1)
+        }
+
+        fn record(&self, _: &tracing::span::Id, _: &tracing::span::Record<'_>) {}
+
+        fn record_follows_from(&self, _: &tracing::span::Id, _: &tracing::span::Id) {}
+
+        fn event(&self, event: &tracing::Event<'_>) {
+            if event.metadata().target() == LOG_TARGET {
+                self.0.lock().unwrap().push(*event.metadata().level());
             }
         }
+
+        fn enter(&self, _: &tracing::span::Id) {}
+
+        fn exit(&self, _: &tracing::span::Id) {}
+    }
+
+    #[test]
+    fn test_unknown_levels() {
+        let log = r#"
+- {"dt": "2022-04-11T10:00:00.000000", "lvl": "info", "msg": "start: 0 validate"}
+- {"dt": "2022-04-11T10:00:01.000000", "lvl": "telemetry", "msg": "power: 4.2W"}
+- {"dt": "2022-04-11T10:00:02.000000", "lvl": "Critical", "msg": "power lost"}
+"#;
+        let entries: Vec<JobLogEntry> = serde_yaml::from_str(log).expect("failed to parse log");
+        let levels = entries.iter().map(|e| e.lvl.clone()).collect::<Vec<_>>();
+        assert_eq!(
+            levels,
+            vec![
+                JobLogLevel::Info,
+                JobLogLevel::Other("telemetry".to_string()),
+                JobLogLevel::Other("Critical".to_string()),
+            ]
+        );
+        assert_eq!(log::Level::from(&entries[1].lvl), log::Level::Info);
+        assert!(!entries[2].is_at_least(log::Level::Warn));
+        assert_eq!(entries[1].msg.to_string(), "power: 4.2W");
+
+        for entry in entries.iter() {
+            entry.emit_log(5);
+        }
+
+        #[cfg(feature = "tracing")]
+        {
+            let subscriber = TraceLevels::default();
+            let traced = subscriber.0.clone();
+            tracing::subscriber::with_default(subscriber, || {
+                for entry in entries.iter() {
+                    entry.emit_trace(5);
+                }
+            });
+            assert_eq!(*traced.lock().unwrap(), vec![tracing::Level::INFO; 3]);
+        }
+    }
+
+    #[test]
+    fn test_results() {
+        let log = r#"
+- {"dt": "2022-04-11T10:00:00.000000", "lvl": "info", "msg": "start: 1 lava-test-retry"}
+- {"dt": "2022-04-11T10:00:01.000000", "lvl": "results", "msg": {"case": "ping", "definition": "0_smoke", "result": "fail", "duration": "0.50"}}
+- {"dt": "2022-04-11T10:00:02.000000", "lvl": "warning", "msg": "network unreachable"}
+- {"dt": "2022-04-11T10:00:03.000000", "lvl": "results", "msg": {"case": "lava-test-retry", "definition": "lava", "level": "1", "result": "pass"}}
+"#;
+        let entries: Vec<JobLogEntry> = serde_yaml::from_str(log).expect("failed to parse log");
+
+        assert_eq!(entries[0].as_message(), Some("start: 1 lava-test-retry"));
+        assert!(entries[0].as_result().is_none());
+
+        let ping = entries[1].as_result().expect("missing result");
+        assert_eq!(ping.case, "ping");
+        assert_eq!(ping.result, PassFail::Fail);
+        assert_eq!(ping.duration, Some(Duration::from_millis(500)));
+        assert!(entries[1].as_message().is_none());
+
+        let tests = entries
+            .iter()
+            .filter(|e| e.is_test_result())
+            .map(|e| e.as_result().unwrap().case.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(tests, vec!["ping"]);
+        assert!(entries[3].as_result().unwrap().is_action());
+
+        let warnings = entries
+            .iter()
+            .filter(|e| e.is_at_least(log::Level::Warn))
+            .filter_map(|e| e.as_message())
+            .collect::<Vec<_>>();
+        assert_eq!(warnings, vec!["network unreachable"]);
+
+        assert!(serde_yaml::from_str::<JobLogEntry>(
+            r#"{"dt": "2022-04-11T10:00:00.000000", "lvl": "results", "msg": {"case": "a", "definition": "b", "result": "womble"}}"#
+        )
+        .is_err());
+
+        let json = serde_json::to_string(&entries).expect("failed to serialize log");
+        let again: Vec<JobLogEntry> = serde_json::from_str(&json).expect("failed to reread log");
+        assert_eq!(format!("{:?}", again), format!("{:?}", entries));
+    }
+
+    fn job_json(state: &str) -> serde_json::Value {
+        serde_json::json!({
+            "count": 1,
+            "next": null,
+            "results": [{
+                "id": 5,
+                "submitter": "user",
+                "viewing_groups": [],
+                "description": "followed job",
+                "health_check": false,
+                "requested_device_type": "device-type",
+                "tags": [],
+                "actual_device": "device",
+                "submit_time": "2022-04-11T09:59:00Z",
+                "start_time": null,
+                "end_time": null,
+                "state": state,
+                "health": "Unknown",
+                "priority": 50,
+                "definition": "",
+                "original_definition": "",
+                "multinode_definition": "",
+                "failure_tags": [],
+                "failure_comment": null,
+            }],
+        })
+    }
+
+    #[test_log::test(tokio::test)]
+    async fn test_follow() {
+        use wiremock::matchers::{method, path, query_param};
+        use wiremock::{Mock, MockServer, ResponseTemplate};
+
+        let server = MockServer::start().await;
+        for state in ["Submitted", "Running"] {
+            Mock::given(method("GET"))
+                .and(path("/api/v0.2/jobs/"))
+                .respond_with(ResponseTemplate::new(200).set_body_json(job_json(state)))
+                .up_to_n_times(1)
+                .mount(&server)
+                .await;
+        }
+        Mock::given(method("GET"))
+            .and(path("/api/v0.2/jobs/"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(job_json("Finished")))
+            .mount(&server)
+            .await;
+
+        Mock::given(method("GET"))
+            .and(path("/api/v0.2/jobs/5/logs/"))
+            .and(query_param("start", "2"))
+            .respond_with(ResponseTemplate::new(200).set_body_string(
+                "- {\"dt\": \"2022-04-11T10:00:02.000000\", \"lvl\": \"info\", \"msg\": \"third\"}\n",
+            ))
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/api/v0.2/jobs/5/logs/"))
+            .respond_with(ResponseTemplate::new(404))
+            .up_to_n_times(1)
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/api/v0.2/jobs/5/logs/"))
+            .respond_with(ResponseTemplate::new(200).set_body_string(concat!(
+                "- {\"dt\": \"2022-04-11T10:00:00.000000\", \"lvl\": \"info\", \"msg\": \"first\"}\n",
+                "- {\"dt\": \"2022-04-11T10:00:01.000000\", \"lvl\": \"info\", \"msg\": \"second\"}\n",
+            )))
+            .mount(&server)
+            .await;
+
+        let lava = Lava::new(&server.uri(), None).expect("failed to make lava server");
+
+        // Without following, the missing log is reported
+        let err = lava
+            .log(6)
+            .log()
+            .try_collect::<Vec<_>>()
+            .await
+            .expect_err("read missing log");
+        assert!(matches!(err, JobLogError::NoData));
+
+        let entries = lava
+            .log(5)
+            .follow(true)
+            .poll_interval(Duration::from_millis(10))
+            .log()
+            .try_collect::<Vec<_>>()
+            .await
+            .expect("failed to follow log");
+        let messages = entries
+            .iter()
+            .map(|e| match &e.msg {
+                JobLogMsg::Msg(msg) => msg.as_str(),
+                _ => panic!("unexpected message {:?}", e.msg),
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(messages, vec!["first", "second", "third"]);
+    }
+
+    #[test_log::test(tokio::test)]
+    async fn test_filter() {
+        use wiremock::matchers::{method, path};
+        use wiremock::{Mock, MockServer, ResponseTemplate};
+
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/api/v0.2/jobs/5/logs/"))
+            .respond_with(ResponseTemplate::new(200).set_body_string(concat!(
+                "- {\"dt\": \"2022-04-11T10:00:00.000000\", \"lvl\": \"info\", \"msg\": \"starting\"}\n",
+                "- {\"dt\": \"2022-04-11T10:00:01.000000\", \"lvl\": \"debug\", \"ns\": \"common\", \"msg\": \"deploying\"}\n",
+                "- {\"dt\": \"2022-04-11T10:00:02.000000\", \"lvl\": \"error\", \"ns\": \"common\", \"msg\": \"deploy failed\"}\n",
+                "- {\"dt\": \"2022-04-11T10:00:03.000000\", \"lvl\": \"error\", \"ns\": \"test\", \"msg\": \"test failed\"}\n",
+                "- {\"dt\": \"2022-04-11T10:00:04.000000\", \"lvl\": \"results\", \"ns\": \"test\", \"msg\": {\"case\": \"boot\", \"definition\": \"0_smoke\", \"result\": \"fail\"}}\n",
+            )))
+            .mount(&server)
+            .await;
+
+        async fn read(builder: JobLogBuilder<'_>) -> Vec<String> {
+            builder
+                .log()
+                .map_ok(|e| e.dt.format("%S").to_string())
+                .try_collect()
+                .await
+                .expect("failed to read log")
+        }
+
+        let lava = Lava::new(&server.uri(), None).expect("failed to make lava server");
+
+        assert_eq!(read(lava.log(5)).await.len(), 5);
+        assert_eq!(
+            read(
+                lava.log(5)
+                    .levels(&[JobLogLevel::Error, JobLogLevel::Results])
+            )
+            .await,
+            vec!["02", "03", "04"]
+        );
+        assert_eq!(
+            read(lava.log(5).namespace("common")).await,
+            vec!["01", "02"]
+        );
+        assert_eq!(
+            read(lava.log(5).levels(&[JobLogLevel::Error]).namespace("test")).await,
+            vec!["03"]
+        );
+        assert_eq!(read(lava.log(5).levels(&[])).await.len(), 5);
+    }
+
+    #[test_log::test(tokio::test)]
+    async fn test_limits() {
+        use wiremock::matchers::{method, path};
+        use wiremock::{Mock, MockServer, ResponseTemplate};
+
+        let lines = (0..5)
+            .map(|i| {
+                format!(
+                    "- {{\"dt\": \"2022-04-11T10:00:0{}.000000\", \"lvl\": \"info\", \"msg\": \"line {}\"}}\n",
+                    i, i
+                )
+            })
+            .collect::<Vec<_>>();
+        let line_len = lines[0].len() as u64;
+
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/api/v0.2/jobs/5/logs/"))
+            .respond_with(ResponseTemplate::new(200).set_body_string(lines.concat()))
+            .mount(&server)
+            .await;
+
+        let lava = Lava::new(&server.uri(), None).expect("failed to make lava server");
+        async fn read(log: JobLog<'_>) -> (Vec<String>, Option<JobLogError>) {
+            let items = log.collect::<Vec<_>>().await;
+            let mut messages = Vec::new();
+            let mut error = None;
+            for item in items {
+                match item {
+                    Ok(entry) => messages.push(entry.as_message().unwrap().to_string()),
+                    Err(e) => error = Some(e),
+                }
+            }
+            (messages, error)
+        }
+
+        let (messages, error) = read(lava.log(5).max_entries(2).log()).await;
+        assert_eq!(messages, vec!["line 0", "line 1"]);
+        assert!(matches!(error, Some(JobLogError::Truncated)));
+
+        // Only whole lines are read within the limit
+        let (messages, error) = read(lava.log(5).max_bytes(line_len * 3 - 1).log()).await;
+        assert_eq!(messages, vec!["line 0", "line 1"]);
+        assert!(matches!(error, Some(JobLogError::Truncated)));
+
+        // A log which fits exactly is not truncated
+        let (messages, error) =
+            read(lava.log(5).max_entries(5).max_bytes(line_len * 5).log()).await;
+        assert_eq!(messages.len(), 5);
+        assert!(error.is_none());
+
+        let (messages, error) = read(lava.log(5).max_entries(0).log()).await;
+        assert!(messages.is_empty());
+        assert!(matches!(error, Some(JobLogError::Truncated)));
+
+        let raw = lava.log(5).max_bytes(line_len * 3).raw();
+        let items = raw.collect::<Vec<_>>().await;
+        let (last, chunks) = items.split_last().unwrap();
+        assert!(matches!(last, Err(JobLogError::Truncated)));
+        let body = chunks
+            .iter()
+            .map(|c| String::from_utf8(c.as_ref().unwrap().to_vec()).unwrap())
+            .collect::<String>();
+        assert_eq!(body, lines[..3].concat());
+    }
+
+    #[test_log::test(tokio::test)]
+    async fn test_timeout() {
+        use wiremock::matchers::{method, path};
+        use wiremock::{Mock, MockServer, ResponseTemplate};
+
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/api/v0.2/jobs/5/logs/"))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .set_body_string(
+                        "- {\"dt\": \"2022-04-11T10:00:00.000000\", \"lvl\": \"info\", \"msg\": \"slow\"}\n",
+                    )
+                    .set_delay(Duration::from_millis(500)),
+            )
+            .mount(&server)
+            .await;
+
+        let lava = Lava::builder(&server.uri())
+            .timeout(Duration::from_millis(100))
+            .slow_request_warning(Duration::from_millis(50))
+            .build()
+            .expect("failed to make lava server");
+
+        let err = lava
+            .log(5)
+            .log()
+            .try_collect::<Vec<_>>()
+            .await
+            .expect_err("log request did not time out");
+        assert!(matches!(err, JobLogError::RequestError(e) if e.is_timeout()));
+
+        let entries = lava
+            .log(5)
+            .timeout(Duration::from_secs(10))
+            .log()
+            .try_collect::<Vec<_>>()
+            .await
+            .expect("failed to read log");
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].as_message(), Some("slow"));
     }
 }
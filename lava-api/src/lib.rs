@@ -10,8 +10,52 @@
 //! - jobs
 //! - test results
 //! - devices
+//! - device types and their aliases
 //! - workers
 //! - tags (which apply to both jobs and devices)
+//! - job results in JUnit format
+//! - the user the token belongs to
+//!
+//! With the `arrow` feature enabled, jobs and test cases can also be
+//! exported as Arrow record batches or Parquet files, using the
+//! `arrow` module. With the `tracing` feature enabled, job log
+//! entries can be forwarded as `tracing` events, as well as to the
+//! [`log`] crate, and each request is made in a `tracing` span, nested
+//! in a span for the stream or job log that made it, wherever that
+//! is polled from. With the `mock` feature enabled, the
+//! `lava-api-mock` crate is re-exported, and the `mock` module
+//! converts the objects held by a mock server into those of this
+//! crate, for use in the tests of crates built on this one. With
+//! the `junit` feature enabled, job results can be retrieved as
+//! parsed JUnit reports.
+//!
+//! The token used to authenticate can be given directly, or found in
+//! the environment or a `.netrc` file, or fetched on demand, using
+//! the `auth` module.
+//!
+//! Job definitions can be inspected without writing YAML parsing
+//! code using the `jobdef` module.
+//!
+//! To follow changes to the devices on a server, the `cache` module
+//! provides a periodically refreshed device table with change
+//! notifications. To follow a single job until it finishes, the
+//! `watch` module polls it and reports each change to its state.
+//!
+//! Times reported by the server are parsed leniently, to cope with
+//! deployments which leave out fractional seconds or offsets; the
+//! `datetime` module makes the same parsing available to other
+//! models.
+//!
+//! The `stats` module counts the jobs queued and running on each
+//! device type with a few filtered queries, rather than by reading
+//! every job.
+//!
+//! To aggregate data from several servers, the `multi` module merges
+//! the streams read from each of them into one.
+//!
+//! Since servers from different LAVA releases differ in the fields
+//! and endpoints they support, the `system` module reports the
+//! version of a server and probes it for optional behaviour.
 //!
 //! Pagination is handled transparently, but you will likely want to
 //! use [`TryStreamExt`] to iterate over returned streams of objects,
@@ -43,44 +87,115 @@
 //! }
 //! # });
 //! ```
+//!
+//! # Cancellation
+//!
+//! No background tasks are spawned by this crate: every request is
+//! driven by the stream or future that issued it. Dropping a stream
+//! part way through, or dropping a future that is awaiting one (for
+//! example when it loses a [`tokio::select!`] or a timeout expires),
+//! aborts any request that is still in flight. Most streams borrow
+//! the [`Lava`] they were created from, so it cannot be dropped
+//! before them; streams that do not, such as
+//! [`workers`](Lava::workers), hold their own handle to the
+//! underlying connection pool and remain usable after the [`Lava`]
+//! is dropped.
+#[cfg(feature = "arrow")]
+pub mod arrow;
+pub mod auth;
+pub mod cache;
+pub mod datetime;
 pub mod device;
+pub mod devicetype;
+pub mod error;
+pub mod instrument;
 pub mod job;
+pub mod jobdef;
 pub mod joblog;
+#[cfg(any(test, feature = "mock"))]
+pub mod mock;
+pub mod multi;
 pub mod paginator;
+pub mod progress;
 mod queryset;
+pub mod queue;
+pub mod ratelimit;
+pub mod retry;
+pub mod snapshot;
+pub mod stats;
+pub mod submission;
+pub mod sync;
+pub mod system;
 pub mod tag;
 pub mod test;
+pub mod transport;
+pub mod user;
+pub mod watch;
 pub mod worker;
 
+use auth::{TokenError, TokenSource};
 use bytes::Bytes;
 use futures::stream::{Stream, TryStreamExt};
+use instrument::{Instrumentation, Instrumented};
 use joblog::JobLogBuilder;
+#[cfg(feature = "mock")]
+pub use lava_api_mock;
 use log::debug;
 use reqwest::{header, redirect::Policy, Client};
+pub use reqwest::{Certificate, Proxy};
 use std::collections::HashMap;
-use std::convert::TryInto;
+use std::sync::Arc;
+use std::time::Duration;
 use tokio::sync::RwLock;
 use url::Url;
 
-use device::Devices;
-use job::JobsBuilder;
-use paginator::{PaginationError, Paginator};
+use device::{Devices, DevicesBuilder, Health, TagCombinationCount};
+use devicetype::{Alias, DeviceType, DeviceTypesBuilder};
+use error::{Classify, ErrorClass};
+use job::{Job, JobsBuilder, JobsQuery};
+use paginator::{PageCache, PageCacheStats, PaginationError, Paginator};
+use queue::QueueEstimate;
+use ratelimit::{RateLimit, RateLimited};
+use retry::RetryPolicy;
+use snapshot::{EntityKind, Snapshot};
+use stats::{DeviceAvailability, QueueDepth};
+use submission::SubmittedJobs;
 use tag::Tag;
-use test::TestCase;
+use test::{ResultsSummary, TestCase, TestCasesBuilder, TestSuite, TestSuiteSummary};
 use thiserror::Error;
-use worker::Worker;
+use transport::{
+    HttpTransport, RedirectPolicy, Redirecting, SlowRequestWarning, TokenAuth, Transport,
+};
+use user::Profile;
+use watch::JobWatch;
+use worker::{Worker, WorkerUtilization, WorkersBuilder};
 
 /// Errors in construction of a [`Lava`] instance
 #[derive(Error, Debug)]
 pub enum LavaError {
-    #[error("Could not parse url")]
+    #[error("Could not parse server url")]
     ParseUrlError(#[from] url::ParseError),
-    #[error("Invalid token format")]
+    #[error("Invalid authentication token format")]
     InvalidToken(#[from] header::InvalidHeaderValue),
+    #[error("Failed to find authentication token")]
+    TokenSource(#[from] TokenError),
     #[error("Failed to build reqwest client")]
     ReqwestError(#[from] reqwest::Error),
 }
 
+impl Classify for LavaError {
+    fn class(&self) -> ErrorClass {
+        match self {
+            LavaError::InvalidToken(_)
+            | LavaError::TokenSource(TokenError::NotFound(_) | TokenError::NoHome) => {
+                ErrorClass::Auth
+            }
+            LavaError::ReqwestError(e) => e.class(),
+            _ => ErrorClass::Other,
+        }
+    }
+}
+
 /// A local proxy for a LAVA server
 ///
 /// This provides convenient access to some of the data
@@ -88,37 +203,429 @@ pub enum LavaError {
 /// workers.
 #[derive(Debug)]
 pub struct Lava {
-    client: Client,
+    transport: Arc<dyn Transport>,
     base: Url,
     tags: RwLock<HashMap<u32, Tag>>,
+    device_types: RwLock<HashMap<String, DeviceType>>,
+    retry: Arc<RetryPolicy>,
+    page_cache: Option<Arc<PageCache>>,
+    prefetch: Option<usize>,
+}
+
+/// The timeout for each request made by a [`Lava`] instance, unless
+/// another is given with [`LavaBuilder::timeout`]
+pub const DEFAULT_TIMEOUT: Duration = Duration::from_secs(300);
+
+/// Construct a [`Lava`] instance with non-default settings.
+///
+/// Example:
+/// ```rust
+/// use lava_api::{ratelimit::RateLimit, retry::RetryPolicy, Lava};
+/// use std::time::Duration;
+///
+/// let lava = Lava::builder("https://lava.example.com/")
+///     .token("secret")
+///     .retry_policy(RetryPolicy::new().max_attempts(5))
+///     .rate_limit(RateLimit::new().requests_per_second(10.0).max_in_flight(4))
+///     .timeout(Duration::from_secs(60))
+///     .slow_request_warning(Duration::from_secs(10))
+///     .user_agent("my-scheduler/1.0")
+///     .build()
+///     .expect("failed to make lava");
+/// ```
+#[derive(Debug)]
+pub struct LavaBuilder {
+    url: String,
+    token: Option<TokenSource>,
+    retry: RetryPolicy,
+    timeout: Option<Duration>,
+    connect_timeout: Option<Duration>,
+    slow_request_threshold: Option<Duration>,
+    rate_limit: RateLimit,
+    page_cache: Option<usize>,
+    prefetch: Option<usize>,
+    instrumentation: Option<Arc<dyn Instrumentation>>,
+    proxies: Vec<Proxy>,
+    certificates: Vec<Certificate>,
+    user_agent: Option<String>,
+    compression: bool,
+    redirects: RedirectPolicy,
+    transport: Option<Arc<dyn Transport>>,
+}
+
+impl LavaBuilder {
+    /// Create a new [`LavaBuilder`] for the server at `url`.
+    ///
+    /// By default, no token is used, requests are not retried, and
+    /// each request times out after [`DEFAULT_TIMEOUT`].
+    pub fn new(url: &str) -> Self {
+        Self {
+            url: url.to_string(),
+            token: None,
+            retry: RetryPolicy::none(),
+            timeout: Some(DEFAULT_TIMEOUT),
+            connect_timeout: None,
+            slow_request_threshold: None,
+            rate_limit: RateLimit::new(),
+            page_cache: None,
+            prefetch: None,
+            instrumentation: None,
+            proxies: Vec::new(),
+            certificates: Vec::new(),
+            user_agent: None,
+            compression: false,
+            redirects: RedirectPolicy::default(),
+            transport: None,
+        }
+    }
+
+    /// Set the LAVA security token used to validate access.
+    pub fn token<T: Into<String>>(mut self, token: T) -> Self {
+        self.token = Some(TokenSource::Token(token.into()));
+        self
+    }
+
+    /// Set where the LAVA security token used to validate access
+    /// comes from.
+    ///
+    /// This replaces any token given to [`token`](LavaBuilder::token).
+    /// See [`TokenSource`] for when the token is read.
+    pub fn token_source(mut self, source: TokenSource) -> Self {
+        self.token = Some(source);
+        self
+    }
+
+    /// Set the [`RetryPolicy`] for requests to the server.
+    pub fn retry_policy(mut self, retry: RetryPolicy) -> Self {
+        self.retry = retry;
+        self
+    }
+
+    /// Set the timeout for each request, from when it is sent until
+    /// the response body has been read.
+    ///
+    /// The default is [`DEFAULT_TIMEOUT`]. Note that the streams
+    /// returned by [`Lava`] make a separate request for each page.
+    /// Job logs, which can take longer to read, can be given their
+    /// own timeout with [`JobLogBuilder::timeout`].
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    /// Let requests run for as long as they take, instead of timing
+    /// out.
+    pub fn no_timeout(mut self) -> Self {
+        self.timeout = None;
+        self
+    }
+
+    /// Set the timeout for connecting to the server.
+    pub fn connect_timeout(mut self, timeout: Duration) -> Self {
+        self.connect_timeout = Some(timeout);
+        self
+    }
+
+    /// Log a warning for each request which takes longer than
+    /// `threshold` to be answered.
+    ///
+    /// The warning gives the method and path of the request and how
+    /// long it took, and is logged at [`log::Level::Warn`] whether or
+    /// not the request succeeded, so that a server which is becoming
+    /// slow can be noticed before requests start to time out. The
+    /// time is measured until the response headers arrive, not
+    /// including reading the body, and each retry is timed
+    /// separately. By default no warnings are logged.
+    pub fn slow_request_warning(mut self, threshold: Duration) -> Self {
+        self.slow_request_threshold = Some(threshold);
+        self
+    }
+
+    /// Set the [`RateLimit`] for requests to the server.
+    ///
+    /// By default requests are not limited.
+    pub fn rate_limit(mut self, limit: RateLimit) -> Self {
+        self.rate_limit = limit;
+        self
+    }
+
+    /// Keep up to `max_pages` pages of paginated queries in a
+    /// [`PageCache`], so that pages the server reports as unchanged
+    /// are not downloaded again.
+    ///
+    /// By default no pages are cached. The statistics of the cache
+    /// are available from [`Lava::page_cache_stats`].
+    pub fn page_cache(mut self, max_pages: usize) -> Self {
+        self.page_cache = Some(max_pages);
+        self
+    }
+
+    /// Request the next page of paginated queries once no more than
+    /// `max_buffered` items of the current page remain unread.
+    ///
+    /// This overlaps fetching the next page with processing the
+    /// current one, at the cost of holding up to one extra page in
+    /// memory. By default each page is only requested once the
+    /// previous one has been read completely.
+    pub fn prefetch(mut self, max_buffered: usize) -> Self {
+        self.prefetch = Some(max_buffered);
+        self
+    }
+
+    /// Report every request to the server to `instrumentation`.
+    ///
+    /// Each attempt at a request is timed from when it is passed on
+    /// to be sent, after any [`rate_limit`](LavaBuilder::rate_limit)
+    /// delay, until its response headers arrive. By default requests
+    /// are not reported.
+    pub fn instrumentation<I: Instrumentation + 'static>(
+        mut self,
+        instrumentation: Arc<I>,
+    ) -> Self {
+        self.instrumentation = Some(instrumentation);
+        self
+    }
+
+    /// Add a [`Proxy`] through which to connect to the server.
+    ///
+    /// This can be called more than once; proxies are tried in the
+    /// order they were added. By default, the system proxy settings
+    /// are used.
+    pub fn proxy(mut self, proxy: Proxy) -> Self {
+        self.proxies.push(proxy);
+        self
+    }
+
+    /// Add a root [`Certificate`] to trust when connecting to the
+    /// server, in addition to the system's trusted roots.
+    pub fn add_root_certificate(mut self, certificate: Certificate) -> Self {
+        self.certificates.push(certificate);
+        self
+    }
+
+    /// Set the `User-Agent` header sent with each request.
+    pub fn user_agent<T: Into<String>>(mut self, user_agent: T) -> Self {
+        self.user_agent = Some(user_agent.into());
+        self
+    }
+
+    /// Set whether to ask the server for gzip compressed responses.
+    ///
+    /// Compressed responses are decompressed as they are read, so
+    /// this is invisible to callers, but can greatly reduce the time
+    /// taken to download job logs and large queries from servers
+    /// which support it. By default responses are not compressed.
+    pub fn compression(mut self, compression: bool) -> Self {
+        self.compression = compression;
+        self
+    }
+
+    /// Set which redirects from the server to follow.
+    ///
+    /// By default, redirects within the same server are followed,
+    /// such as those adding a trailing slash, but not those to other
+    /// servers. At most [`MAX_REDIRECTS`](transport::MAX_REDIRECTS)
+    /// are followed for each request.
+    pub fn redirects(mut self, policy: RedirectPolicy) -> Self {
+        self.redirects = policy;
+        self
+    }
+
+    /// Send requests with an existing [`Client`], instead of one
+    /// made from the settings of this builder.
+    ///
+    /// This lets an application share a connection pool, and its own
+    /// client settings, between several servers. The crate only adds
+    /// the token to each request. As with
+    /// [`transport`](LavaBuilder::transport), the timeout, proxy,
+    /// certificate, user agent and compression settings are ignored.
+    /// The client should be built with
+    /// [`Policy::none`](reqwest::redirect::Policy::none), so that the
+    /// [`redirects`](LavaBuilder::redirects) setting decides which
+    /// redirects are followed.
+    ///
+    /// Clients from `reqwest_middleware` are not accepted: their
+    /// errors cannot be turned into the [`reqwest::Error`] a
+    /// [`Transport`] returns. Applications wanting to add their own
+    /// middleware, such as for telemetry, can instead wrap a
+    /// [`HttpTransport`] in a [`Transport`] of their own.
+    pub fn client(self, client: Client) -> Self {
+        self.transport(HttpTransport::new(client))
+    }
+
+    /// Send requests with the given [`Transport`], instead of over
+    /// HTTP.
+    ///
+    /// The timeout, proxy, certificate, user agent and compression
+    /// settings only apply to the default transport, and are ignored
+    /// when one is given here. The token is added to each request
+    /// before it is passed to the transport.
+    pub fn transport<T: Transport + 'static>(mut self, transport: T) -> Self {
+        self.transport = Some(Arc::new(transport));
+        self
+    }
+
+    /// Create the [`Lava`] instance.
+    ///
+    /// The token is only ever sent to the server at the url given to
+    /// [`new`](LavaBuilder::new), whatever the other settings, so that
+    /// following a redirect cannot send it to another server.
+    pub fn build(self) -> Result<Lava, LavaError> {
+        let host: Url = self.url.parse()?;
+        let base = host.join("api/v0.2/")?;
+        let tags = RwLock::new(HashMap::new());
+        let device_types = RwLock::new(HashMap::new());
+
+        let token = match self.token {
+            Some(source) => Some(source.resolve(&host)?),
+            None => None,
+        };
+
+        let transport = match self.transport {
+            Some(transport) => transport,
+            None => Arc::new(HttpTransport::new(Self::http_client(
+                self.timeout,
+                self.connect_timeout,
+                self.proxies,
+                self.certificates,
+                self.user_agent,
+                self.compression,
+            )?)),
+        };
+        let transport = match token {
+            Some(token) => Arc::new(TokenAuth::new(transport, token, host.origin())?),
+            None => transport,
+        };
+        let transport = match self.redirects {
+            RedirectPolicy::None => transport,
+            policy => Arc::new(Redirecting::new(transport, policy)),
+        };
+        let transport = match self.instrumentation {
+            Some(instrumentation) => Arc::new(Instrumented::new(transport, instrumentation)),
+            None => transport,
+        };
+        let transport = match self.slow_request_threshold {
+            Some(threshold) => Arc::new(SlowRequestWarning::new(transport, threshold)),
+            None => transport,
+        };
+        #[cfg(feature = "tracing")]
+        let transport: Arc<dyn Transport> = Arc::new(transport::Traced::new(transport));
+        // Requests are only timed once they are within the limits
+        let transport: Arc<dyn Transport> = if self.rate_limit.is_limited() {
+            Arc::new(RateLimited::new(transport, &self.rate_limit))
+        } else {
+            transport
+        };
+
+        Ok(Lava {
+            transport,
+            base,
+            tags,
+            device_types,
+            retry: Arc::new(self.retry),
+            page_cache: self.page_cache.map(|n| Arc::new(PageCache::new(n))),
+            prefetch: self.prefetch,
+        })
+    }
+
+    fn http_client(
+        timeout: Option<Duration>,
+        connect_timeout: Option<Duration>,
+        proxies: Vec<Proxy>,
+        certificates: Vec<Certificate>,
+        user_agent: Option<String>,
+        compression: bool,
+    ) -> Result<Client, LavaError> {
+        // Redirects are followed by the transport, which keeps the token
+        // from other servers
+        let mut client = Client::builder().redirect(Policy::none()).gzip(compression);
+        if let Some(timeout) = timeout {
+            client = client.timeout(timeout);
+        }
+        if let Some(timeout) = connect_timeout {
+            client = client.connect_timeout(timeout);
+        }
+        for proxy in proxies {
+            client = client.proxy(proxy);
+        }
+        for certificate in certificates {
+            client = client.add_root_certificate(certificate);
+        }
+        if let Some(user_agent) = user_agent {
+            client = client.user_agent(user_agent);
+        }
+        Ok(client.build()?)
+    }
 }
 
 impl Lava {
     /// Create a new Lava proxy
     ///
     /// Here `url` is the address of the server, and `token` is an
-    /// optional LAVA security token used to validate access.
+    /// optional LAVA security token used to validate access. Use
+    /// [`builder`](Lava::builder) for more control over the
+    /// connection.
     pub fn new(url: &str, token: Option<String>) -> Result<Lava, LavaError> {
-        let host: Url = url.parse()?;
-        let base = host.join("api/v0.2/")?;
-        let tags = RwLock::new(HashMap::new());
-        let mut headers = header::HeaderMap::new();
+        let mut builder = Self::builder(url);
+        if let Some(token) = token {
+            builder = builder.token(token);
+        }
+        builder.build()
+    }
 
-        if let Some(t) = token {
-            headers.insert(
-                reqwest::header::AUTHORIZATION,
-                format!("Token {}", t).try_into()?,
-            );
+    /// Create a new Lava proxy sending requests with an existing
+    /// [`Client`].
+    ///
+    /// See [`LavaBuilder::client`] for how the client is used.
+    ///
+    /// Example:
+    /// ```rust
+    /// use lava_api::Lava;
+    /// use reqwest::redirect::Policy;
+    ///
+    /// let client = reqwest::Client::builder()
+    ///     .redirect(Policy::none())
+    ///     .build()
+    ///     .expect("failed to make client");
+    /// let first = Lava::with_client("https://lava.example.com/", None, client.clone())
+    ///     .expect("failed to make lava");
+    /// let second = Lava::with_client("https://lava.example.org/", None, client)
+    ///     .expect("failed to make lava");
+    /// ```
+    pub fn with_client(
+        url: &str,
+        token: Option<String>,
+        client: Client,
+    ) -> Result<Lava, LavaError> {
+        let mut builder = Self::builder(url).client(client);
+        if let Some(token) = token {
+            builder = builder.token(token);
         }
+        builder.build()
+    }
 
-        // Force redirect policy none as that will drop sensitive headers; in
-        // particular tokens
-        let client = Client::builder()
-            .redirect(Policy::none())
-            .default_headers(headers)
-            .build()?;
+    /// Obtain a [`LavaBuilder`] for the server at `url`.
+    pub fn builder(url: &str) -> LavaBuilder {
+        LavaBuilder::new(url)
+    }
+
+    fn paginator<T>(&self, url: Url) -> Paginator<T>
+    where
+        T: serde::de::DeserializeOwned + 'static,
+    {
+        Paginator::with_transport(
+            self.transport.clone(),
+            url,
+            self.retry.clone(),
+            self.page_cache.clone(),
+        )
+        .prefetch(self.prefetch)
+    }
 
-        Ok(Lava { client, base, tags })
+    /// The statistics of the [`PageCache`], if one was requested
+    /// with [`LavaBuilder::page_cache`].
+    pub fn page_cache_stats(&self) -> Option<PageCacheStats> {
+        self.page_cache.as_ref().map(|cache| cache.stats())
     }
 
     /// Refresh the tag cache
@@ -135,7 +642,7 @@ impl Lava {
         debug!("Refreshing tags cache");
         let mut tags = self.tags.write().await;
         let url = self.base.join("tags/")?;
-        let mut new_tags: Paginator<Tag> = Paginator::new(self.client.clone(), url);
+        let mut new_tags: Paginator<Tag> = self.paginator(url);
         while let Some(t) = new_tags.try_next().await? {
             tags.insert(t.id, t);
         }
@@ -146,16 +653,26 @@ impl Lava {
     /// Retrieve the [`Tag`] for the given tag id.
     pub async fn tag(&self, tag: u32) -> Option<Tag> {
         debug!("Checking for tag id: {}", tag);
+        self.tags_by_id([tag]).await.remove(&tag)
+    }
+
+    // Look up the tags with the given ids, refreshing the cache at
+    // most once, and only if some of them are missing. Ids which no
+    // tag on the server has are left out of the result.
+    async fn tags_by_id<I: IntoIterator<Item = u32>>(&self, ids: I) -> HashMap<u32, Tag> {
+        let ids = ids.into_iter().collect::<Vec<_>>();
         {
             let tags = self.tags.read().await;
-            if let Some(t) = tags.get(&tag) {
-                return Some(t.clone());
+            if ids.iter().all(|id| tags.contains_key(id)) {
+                return ids.iter().map(|id| (*id, tags[id].clone())).collect();
             }
         }
         let _ = self.refresh_tags().await;
 
         let tags = self.tags.read().await;
-        tags.get(&tag).cloned()
+        ids.iter()
+            .filter_map(|id| tags.get(id).map(|t| (*id, t.clone())))
+            .collect()
     }
 
     /// Retrieve all the tags from the server
@@ -168,16 +685,122 @@ impl Lava {
         Ok(tags.values().cloned().collect())
     }
 
+    /// Retrieve the tags whose name or description contains the
+    /// given text, ignoring case.
+    ///
+    /// See [`find_tags`](tag::find_tags) for details.
+    pub async fn find_tags(&self, text: &str) -> Result<Vec<Tag>, PaginationError> {
+        tag::find_tags(self, text).await
+    }
+
+    /// Create a new tag on the server.
+    ///
+    /// See [`create_tag`](tag::create_tag) for details.
+    pub async fn create_tag(
+        &self,
+        name: &str,
+        description: Option<&str>,
+    ) -> Result<Tag, tag::TagError> {
+        tag::create_tag(self, name, description).await
+    }
+
+    /// Delete a tag from the server.
+    ///
+    /// See [`delete_tag`](tag::delete_tag) for details.
+    pub async fn delete_tag(&self, id: u32) -> Result<(), tag::TagError> {
+        tag::delete_tag(self, id).await
+    }
+
     /// Obtain a [`Stream`](futures::stream::Stream) of all the
     /// [`Device`](device::Device) instances on the server.
     pub fn devices(&self) -> Devices {
         Devices::new(self)
     }
 
+    /// Count the devices carrying all of the tags in each of the
+    /// given combinations.
+    ///
+    /// See [`device_counts_by_tags`](device::device_counts_by_tags)
+    /// for details.
+    pub async fn device_counts_by_tags(
+        &self,
+        combinations: &[&[&str]],
+    ) -> Result<Vec<TagCombinationCount>, PaginationError> {
+        device::device_counts_by_tags(self, combinations).await
+    }
+
+    /// Obtain a customisable query object for
+    /// [`Device`](device::Device) instances on the server.
+    ///
+    /// The returned [`DevicesBuilder`] can be used first to select the
+    /// subset of devices that will be returned, and then after that
+    /// is complete to obtain a stream of matching devices.
+    pub fn devices_builder(&self) -> DevicesBuilder {
+        DevicesBuilder::new(self)
+    }
+
+    /// Set the health of a device on the server, for example to take
+    /// it into or out of maintenance.
+    ///
+    /// See [`set_device_health`](device::set_device_health) for
+    /// details.
+    pub async fn set_device_health(
+        &self,
+        hostname: &str,
+        health: Health,
+        reason: Option<&str>,
+    ) -> Result<(), device::DeviceHealthError> {
+        device::set_device_health(self, hostname, health, reason).await
+    }
+
+    /// Add a tag to a device, creating the tag if needed.
+    ///
+    /// See [`add_device_tag`](device::add_device_tag) for details.
+    pub async fn add_device_tag(
+        &self,
+        hostname: &str,
+        tag: &str,
+    ) -> Result<(), device::DeviceTagError> {
+        device::add_device_tag(self, hostname, tag).await
+    }
+
+    /// Remove a tag from a device.
+    ///
+    /// See [`remove_device_tag`](device::remove_device_tag) for
+    /// details.
+    pub async fn remove_device_tag(
+        &self,
+        hostname: &str,
+        tag: &str,
+    ) -> Result<(), device::DeviceTagError> {
+        device::remove_device_tag(self, hostname, tag).await
+    }
+
     pub fn log(&self, id: i64) -> JobLogBuilder {
         JobLogBuilder::new(self, id)
     }
 
+    /// Obtain a stream of the changes to the job with the given id,
+    /// which ends when the job finishes.
+    ///
+    /// See [`JobWatch`] for details.
+    pub fn watch_job(&self, id: i64) -> JobWatch {
+        JobWatch::new(self, id)
+    }
+
+    /// Wait for the job with the given id to finish, and return its
+    /// final record.
+    ///
+    /// See [`wait_for_job`](watch::wait_for_job) for details.
+    pub async fn wait_for_job(
+        &self,
+        id: i64,
+        timeout: Duration,
+        poll_interval: Duration,
+    ) -> Result<Job, watch::WaitError> {
+        watch::wait_for_job(self, id, timeout, poll_interval).await
+    }
+
     /// Obtain a customisable query object for [`Job`](job::Job)
     /// instances on the server.
     ///
@@ -189,14 +812,112 @@ impl Lava {
         JobsBuilder::new(self)
     }
 
-    pub async fn submit_job(&self, definition: &str) -> Result<Vec<i64>, job::SubmissionError> {
-        job::submit_job(self, definition).await
+    /// Obtain a query object for [`Job`](job::Job) instances on the
+    /// server, starting from a [`JobsQuery`] constructed in advance.
+    pub fn jobs_with(&self, query: JobsQuery) -> JobsBuilder {
+        JobsBuilder::with_query(self, query)
+    }
+
+    /// Retrieve the job with the given id.
+    ///
+    /// This returns `None` if there is no such job, or if it is not
+    /// visible to the user this instance's token belongs to. To look
+    /// up many jobs at once, use [`JobsBuilder::ids`] instead.
+    pub async fn job(&self, id: i64) -> Result<Option<Job>, job::JobError> {
+        job::job(self, id).await
+    }
+
+    /// Retrieve the most recent health check job for the named
+    /// device type.
+    ///
+    /// See [`latest_health_check`](job::latest_health_check) for
+    /// details.
+    pub async fn latest_health_check(
+        &self,
+        device_type: &str,
+    ) -> Result<Option<Job>, PaginationError> {
+        job::latest_health_check(self, device_type).await
+    }
+
+    /// Submit a job definition to the server.
+    ///
+    /// On success, this returns the ids of the jobs created, which
+    /// will be a group of jobs for a multinode definition.
+    pub async fn submit_job(
+        &self,
+        definition: &str,
+    ) -> Result<SubmittedJobs, submission::SubmissionError> {
+        submission::submit_job(self, definition).await
+    }
+
+    /// Resubmit the job with the given id.
+    ///
+    /// See [`submission::resubmit_job`] for details.
+    pub async fn resubmit_job(
+        &self,
+        id: i64,
+    ) -> Result<SubmittedJobs, submission::SubmissionError> {
+        submission::resubmit_job(self, id).await
     }
 
+    /// Cancel the job with the given id.
+    ///
+    /// This requires a token with permission to cancel the job;
+    /// otherwise [`PermissionDenied`](job::CancellationError::PermissionDenied)
+    /// is returned.
     pub async fn cancel_job(&self, id: i64) -> Result<(), job::CancellationError> {
         job::cancel_job(self, id).await
     }
 
+    /// Retrieve the [`Profile`] of the user this instance's token
+    /// belongs to, or `None` if no token was given.
+    ///
+    /// This can be used to check that the expected account is in use
+    /// before submitting or cancelling jobs.
+    pub async fn whoami(&self) -> Result<Option<Profile>, user::WhoamiError> {
+        user::whoami(self).await
+    }
+
+    /// Obtain a query for the jobs submitted by the user this
+    /// instance's token belongs to.
+    ///
+    /// See [`my_jobs`](user::my_jobs) for details.
+    pub async fn my_jobs(&self) -> Result<JobsBuilder, user::MyJobsError> {
+        user::my_jobs(self).await
+    }
+
+    /// Obtain the results of the job with the given id as a JUnit
+    /// XML document.
+    ///
+    /// The document is returned as a [`Stream`] of [`Bytes`], so
+    /// that large result sets do not have to be held in memory.
+    ///
+    /// Example:
+    /// ```rust
+    /// use futures::stream::TryStreamExt;
+    /// # use lava_api_mock::{LavaMock, PaginationLimits, PopulationParams, SharedState};
+    /// use lava_api::Lava;
+    /// #
+    /// # tokio_test::block_on( async {
+    /// # let limits = PaginationLimits::new();
+    /// # let population = PopulationParams::new();
+    /// # let mock = LavaMock::new(SharedState::new_populated(population), limits).await;
+    /// # let service_uri = mock.uri();
+    /// # let lava_token = None;
+    ///
+    /// let lava = Lava::new(&service_uri, lava_token).expect("failed to make lava");
+    ///
+    /// let junit: Vec<u8> = lava
+    ///     .job_results_as_junit(0)
+    ///     .await
+    ///     .expect("failed to request junit")
+    ///     .map_ok(|b| b.to_vec())
+    ///     .try_concat()
+    ///     .await
+    ///     .expect("failed to read junit");
+    /// println!("{}", String::from_utf8_lossy(&junit));
+    /// # });
+    /// ```
     pub async fn job_results_as_junit(
         &self,
         id: i64,
@@ -204,6 +925,50 @@ impl Lava {
         job::job_results_as_junit(self, id).await
     }
 
+    /// Obtain the results of the job with the given id as a complete
+    /// JUnit XML document.
+    ///
+    /// See [`job_results_junit`](job::job_results_junit) for details.
+    pub async fn job_results_junit(&self, id: i64) -> Result<Bytes, job::ResultsError> {
+        job::job_results_junit(self, id).await
+    }
+
+    /// Obtain the results of the job with the given id as a parsed
+    /// JUnit report.
+    ///
+    /// This requires the `junit` feature.
+    ///
+    /// Example:
+    /// ```rust
+    /// # use lava_api_mock::{LavaMock, PaginationLimits, PopulationParams, SharedState};
+    /// use lava_api::Lava;
+    /// #
+    /// # tokio_test::block_on( async {
+    /// # let limits = PaginationLimits::new();
+    /// # let population = PopulationParams::new();
+    /// # let mock = LavaMock::new(SharedState::new_populated(population), limits).await;
+    /// # let service_uri = mock.uri();
+    /// # let lava_token = None;
+    ///
+    /// let lava = Lava::new(&service_uri, lava_token).expect("failed to make lava");
+    ///
+    /// let report = lava
+    ///     .job_results_junit_report(0)
+    ///     .await
+    ///     .expect("failed to obtain junit report");
+    /// for suite in report.suites {
+    ///     println!("{}: {} failures", suite.name, suite.failures);
+    /// }
+    /// # });
+    /// ```
+    #[cfg(feature = "junit")]
+    pub async fn job_results_junit_report(
+        &self,
+        id: i64,
+    ) -> Result<junit_parser::TestSuites, job::JunitReportError> {
+        job::job_results_junit_report(self, id).await
+    }
+
     /// Obtain a [`Stream`](futures::stream::Stream) of all the
     /// [`Worker`] instances on the server.
     pub fn workers(&self) -> Paginator<Worker> {
@@ -211,7 +976,239 @@ impl Lava {
             .base
             .join("workers/")
             .expect("Failed to append to base url");
-        Paginator::new(self.client.clone(), url)
+        self.paginator(url)
+    }
+
+    /// Obtain a customisable query object for [`Worker`] instances
+    /// on the server.
+    ///
+    /// The returned [`WorkersBuilder`] can be used first to select
+    /// the subset of workers that will be returned, and then after
+    /// that is complete to obtain a stream of matching workers.
+    pub fn workers_builder(&self) -> WorkersBuilder {
+        WorkersBuilder::new(self)
+    }
+
+    /// Set the health of a worker on the server, for example to
+    /// drain it before maintenance of its host.
+    ///
+    /// See [`set_worker_health`](worker::set_worker_health) for
+    /// details.
+    pub async fn set_worker_health(
+        &self,
+        hostname: &str,
+        health: worker::Health,
+    ) -> Result<(), worker::WorkerUpdateError> {
+        worker::set_worker_health(self, hostname, health).await
+    }
+
+    /// Set the maximum number of jobs a worker on the server may run
+    /// at once.
+    ///
+    /// See [`set_worker_job_limit`](worker::set_worker_job_limit) for
+    /// details.
+    pub async fn set_worker_job_limit(
+        &self,
+        hostname: &str,
+        job_limit: u32,
+    ) -> Result<(), worker::WorkerUpdateError> {
+        worker::set_worker_job_limit(self, hostname, job_limit).await
+    }
+
+    /// Retrieve the version of LAVA the server is running, if it
+    /// reports one.
+    ///
+    /// See [`server_version`](system::server_version) for details.
+    pub async fn server_version(
+        &self,
+    ) -> Result<Option<system::ServerVersion>, system::SystemError> {
+        system::server_version(self).await
+    }
+
+    /// Find the optional behaviour supported by the server.
+    ///
+    /// See [`capabilities`](system::capabilities) for details.
+    pub async fn capabilities(&self) -> Result<system::Capabilities, system::SystemError> {
+        system::capabilities(self).await
+    }
+
+    /// Refresh the device type cache
+    ///
+    /// Device types are cached in the same way as tags, so that the
+    /// device type names given by jobs and devices can be resolved
+    /// cheaply. The cache has to be periodically refreshed to account
+    /// for changes.
+    ///
+    /// Note that device types are automatically refreshed by calling
+    /// [`device_type`](Self::device_type) with a name that is not in
+    /// the cache, but not by calling
+    /// [`device_types`](Self::device_types).
+    pub async fn refresh_device_types(&self) -> Result<(), PaginationError> {
+        debug!("Refreshing device types cache");
+        let mut device_types = self.device_types.write().await;
+        let mut new_device_types = self.device_types();
+        let mut refreshed = HashMap::new();
+        while let Some(dt) = new_device_types.try_next().await? {
+            refreshed.insert(dt.name.clone(), dt);
+        }
+        *device_types = refreshed;
+
+        Ok(())
+    }
+
+    /// Retrieve the [`DeviceType`] with the given name.
+    ///
+    /// This can be used to resolve the
+    /// [`requested_device_type`](job::Job::requested_device_type) of
+    /// a job, or the [`device_type`](device::Device::device_type) of
+    /// a device.
+    pub async fn device_type(&self, name: &str) -> Option<DeviceType> {
+        debug!("Checking for device type: {}", name);
+        {
+            let device_types = self.device_types.read().await;
+            if let Some(dt) = device_types.get(name) {
+                return Some(dt.clone());
+            }
+        }
+        let _ = self.refresh_device_types().await;
+
+        let device_types = self.device_types.read().await;
+        device_types.get(name).cloned()
+    }
+
+    /// Retrieve the [`DeviceType`] with the given name, or with the
+    /// given name as one of its aliases.
+    ///
+    /// Device type names take precedence over aliases. If an alias
+    /// is shared by more than one device type, any of them may be
+    /// returned. This uses the same cache as
+    /// [`device_type`](Self::device_type), and refreshes it in the
+    /// same way.
+    pub async fn resolve_device_type(&self, name: &str) -> Option<DeviceType> {
+        debug!("Resolving device type or alias: {}", name);
+        if let Some(dt) = self.cached_device_type(name).await {
+            return Some(dt);
+        }
+        let _ = self.refresh_device_types().await;
+
+        self.cached_device_type(name).await
+    }
+
+    async fn cached_device_type(&self, name: &str) -> Option<DeviceType> {
+        let device_types = self.device_types.read().await;
+        device_types
+            .get(name)
+            .or_else(|| {
+                device_types
+                    .values()
+                    .find(|dt| dt.aliases.iter().any(|a| a == name))
+            })
+            .cloned()
+    }
+
+    /// Obtain a [`Stream`](futures::stream::Stream) of all the
+    /// [`Alias`] instances on the server.
+    pub fn aliases(&self) -> Paginator<Alias> {
+        let url = self
+            .base
+            .join("aliases/")
+            .expect("Failed to append to base url");
+        self.paginator(url)
+    }
+
+    /// Obtain a [`Stream`](futures::stream::Stream) of all the
+    /// [`DeviceType`] instances on the server.
+    pub fn device_types(&self) -> Paginator<DeviceType> {
+        let url = self
+            .base
+            .join("devicetypes/")
+            .expect("Failed to append to base url");
+        self.paginator(url)
+    }
+
+    /// Obtain a customisable query object for [`DeviceType`]
+    /// instances on the server.
+    ///
+    /// The returned [`DeviceTypesBuilder`] can be used first to select
+    /// the subset of device types that will be returned, and then
+    /// after that is complete to obtain a stream of matching device
+    /// types.
+    pub fn device_types_builder(&self) -> DeviceTypesBuilder {
+        DeviceTypesBuilder::new(self)
+    }
+
+    /// Read all the objects of the given kinds from the server
+    /// concurrently.
+    ///
+    /// See [`snapshot`](snapshot::snapshot) for details.
+    pub async fn snapshot(&self, kinds: &[EntityKind]) -> Result<Snapshot, PaginationError> {
+        snapshot::snapshot(self, kinds).await
+    }
+
+    /// Report how many jobs each [`Worker`] is running relative to
+    /// its job limit.
+    ///
+    /// See [`worker_utilization`](worker::worker_utilization) for
+    /// details.
+    pub async fn worker_utilization(&self) -> Result<Vec<WorkerUtilization>, PaginationError> {
+        worker::worker_utilization(self).await
+    }
+
+    /// Estimate how many queued jobs are ahead of a job submitted
+    /// now for the given device type and priority.
+    ///
+    /// See [`queue_estimate`](queue::queue_estimate) for details.
+    pub async fn queue_estimate(
+        &self,
+        device_type: &str,
+        priority: i64,
+    ) -> Result<QueueEstimate, PaginationError> {
+        queue::queue_estimate(self, device_type, priority).await
+    }
+
+    /// Count the submitted, scheduled and running jobs for each
+    /// device type on the server.
+    ///
+    /// See [`queue_depth`](stats::queue_depth) for details.
+    pub async fn queue_depth(&self) -> Result<Vec<QueueDepth>, PaginationError> {
+        stats::queue_depth(self).await
+    }
+
+    /// Report the availability of each device, and the outcome of
+    /// its health checks within the last `window`.
+    ///
+    /// See [`device_availability`](stats::device_availability) for
+    /// details.
+    pub async fn device_availability(
+        &self,
+        window: Duration,
+    ) -> Result<Vec<DeviceAvailability>, PaginationError> {
+        stats::device_availability(self, window).await
+    }
+
+    /// Obtain a [`Stream`](futures::stream::Stream) of all the
+    /// [`TestSuite`] instances for a given job id.
+    pub fn test_suites(&self, job_id: i64) -> Paginator<TestSuite> {
+        let url = self
+            .base
+            .join("jobs/")
+            .and_then(|x| x.join(&format!("{}/", job_id)))
+            .and_then(|x| x.join("suites/"))
+            .expect("Failed to build test suite url");
+        self.paginator(url)
+    }
+
+    /// Obtain a [`Stream`](futures::stream::Stream) of the
+    /// [`TestSuite`] instances for a given job id, each of which can
+    /// summarize its test cases when asked.
+    ///
+    /// See [`suite_summaries`](test::suite_summaries) for details.
+    pub fn suite_summaries(
+        &self,
+        job_id: i64,
+    ) -> impl Stream<Item = Result<TestSuiteSummary, PaginationError>> + Send + Unpin + 'static
+    {
+        test::suite_summaries(self, job_id)
     }
 
     /// Obtain a [`Stream`](futures::stream::Stream) of all the
@@ -223,6 +1220,398 @@ impl Lava {
             .and_then(|x| x.join(&format!("{}/", job_id)))
             .and_then(|x| x.join("tests/"))
             .expect("Failed to build test case url");
-        Paginator::new(self.client.clone(), url)
+        self.paginator(url)
+    }
+
+    /// Obtain a customisable query object for the [`TestCase`]
+    /// instances of a given job id.
+    ///
+    /// The returned [`TestCasesBuilder`] can be used first to select
+    /// the subset of test cases that will be returned, and then after
+    /// that is complete to obtain a stream of matching test cases.
+    pub fn test_cases_builder(&self, job_id: i64) -> TestCasesBuilder {
+        TestCasesBuilder::new(self, job_id)
+    }
+
+    /// Summarize the test results of the job with the given id.
+    ///
+    /// All of the [`TestCase`] instances of the job are retrieved,
+    /// and the number with each result, the total duration and the
+    /// failing cases are collected into a [`ResultsSummary`].
+    ///
+    /// Example:
+    /// ```rust
+    /// # use lava_api_mock::{LavaMock, PaginationLimits, PopulationParams, SharedState};
+    /// use lava_api::{Lava, test::PassFail};
+    /// #
+    /// # tokio_test::block_on( async {
+    /// # let limits = PaginationLimits::new();
+    /// # let population = PopulationParams::new();
+    /// # let mock = LavaMock::new(SharedState::new_populated(population), limits).await;
+    /// # let service_uri = mock.uri();
+    /// # let lava_token = None;
+    ///
+    /// let lava = Lava::new(&service_uri, lava_token).expect("failed to make lava");
+    ///
+    /// let summary = lava
+    ///     .job_results_summary(0)
+    ///     .await
+    ///     .expect("failed to summarize results");
+    /// println!(
+    ///     "{} of {} passed in {:?}",
+    ///     summary.count(PassFail::Pass),
+    ///     summary.total(),
+    ///     summary.duration
+    /// );
+    /// for failure in summary.failures {
+    ///     println!("{} failed: {:?}", failure.name, failure.error_type);
+    /// }
+    /// # });
+    /// ```
+    pub async fn job_results_summary(
+        &self,
+        job_id: i64,
+    ) -> Result<ResultsSummary, PaginationError> {
+        test::job_results_summary(self, job_id).await
+    }
+
+    /// Obtain a [`Stream`] of the jobs selected by a [`JobsBuilder`],
+    /// each with all of its [`TestCase`] instances.
+    ///
+    /// The test cases of up to
+    /// [`JOBS_WITH_TESTS_CONCURRENCY`](test::JOBS_WITH_TESTS_CONCURRENCY)
+    /// jobs are read at once; see
+    /// [`jobs_with_tests`](test::jobs_with_tests) to choose another
+    /// limit.
+    ///
+    /// Example:
+    /// ```rust
+    /// use futures::stream::TryStreamExt;
+    /// # use lava_api_mock::{LavaMock, PaginationLimits, PopulationParams, SharedState};
+    /// use lava_api::{Lava, job::State};
+    /// #
+    /// # tokio_test::block_on( async {
+    /// # let limits = PaginationLimits::new();
+    /// # let population = PopulationParams::new();
+    /// # let mock = LavaMock::new(SharedState::new_populated(population), limits).await;
+    /// # let service_uri = mock.uri();
+    /// # let lava_token = None;
+    ///
+    /// let lava = Lava::new(&service_uri, lava_token).expect("failed to make lava");
+    ///
+    /// let mut jobs = lava.jobs_with_tests(lava.jobs().state(State::Finished));
+    /// while let Some((job, cases)) = jobs
+    ///     .try_next()
+    ///     .await
+    ///     .expect("failed to read jobs")
+    /// {
+    ///     println!("Job {} has {} test cases", job.id, cases.len());
+    /// }
+    /// # });
+    /// ```
+    pub fn jobs_with_tests<'a>(
+        &'a self,
+        jobs: JobsBuilder<'a>,
+    ) -> impl Stream<Item = Result<(Job, Vec<TestCase>), PaginationError>> + Send + Unpin + 'a {
+        test::jobs_with_tests(self, jobs, test::JOBS_WITH_TESTS_CONCURRENCY)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Lava, LavaError};
+    use crate::error::{Classify, ErrorClass};
+
+    use futures::TryStreamExt;
+    use serde_json::json;
+    use std::time::Duration;
+    use test_log::test;
+    use wiremock::matchers::{header, method, path};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    #[test(tokio::test)]
+    async fn test_builder() {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/api/v0.2/workers/"))
+            .and(header("user-agent", "test-agent/1.0"))
+            .and(header("authorization", "Token secret"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "count": 0,
+                "next": null,
+                "results": [],
+            })))
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/api/v0.2/devices/"))
+            .respond_with(ResponseTemplate::new(200).set_delay(Duration::from_secs(5)))
+            .mount(&server)
+            .await;
+
+        let lava = Lava::builder(&server.uri())
+            .token("secret")
+            .user_agent("test-agent/1.0")
+            .timeout(Duration::from_millis(100))
+            .connect_timeout(Duration::from_secs(1))
+            .build()
+            .expect("failed to make lava server");
+
+        let workers = lava
+            .workers()
+            .try_collect::<Vec<_>>()
+            .await
+            .expect("failed to query workers");
+        assert!(workers.is_empty());
+
+        lava.devices()
+            .try_collect::<Vec<_>>()
+            .await
+            .expect_err("request did not time out");
+    }
+
+    #[test]
+    fn test_error_class() {
+        let err = Lava::new("not a url", None).expect_err("parsed a bad url");
+        assert!(matches!(err, LavaError::ParseUrlError(_)));
+        assert_eq!(err.class(), ErrorClass::Other);
+
+        let err = Lava::new("http://localhost", Some("bad\ntoken".to_string()))
+            .expect_err("accepted a bad token");
+        assert!(matches!(err, LavaError::InvalidToken(_)));
+        assert_eq!(err.class(), ErrorClass::Auth);
+    }
+
+    #[test(tokio::test)]
+    async fn test_compression() {
+        use flate2::write::GzEncoder;
+        use std::io::Write;
+
+        fn gzip(body: &[u8]) -> ResponseTemplate {
+            let mut encoder = GzEncoder::new(Vec::new(), flate2::Compression::default());
+            encoder.write_all(body).unwrap();
+            ResponseTemplate::new(200)
+                .insert_header("content-encoding", "gzip")
+                .set_body_raw(encoder.finish().unwrap(), "application/octet-stream")
+        }
+
+        let server = MockServer::start().await;
+        let page = json!({
+            "count": 1,
+            "next": null,
+            "results": [{"id": 1, "name": "hdmi", "description": null}],
+        });
+        Mock::given(method("GET"))
+            .and(path("/api/v0.2/tags/"))
+            .and(header("accept-encoding", "gzip"))
+            .respond_with(gzip(page.to_string().as_bytes()))
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/api/v0.2/jobs/5/logs/"))
+            .and(header("accept-encoding", "gzip"))
+            .respond_with(gzip(
+                b"- {\"dt\": \"2022-04-11T10:00:00.000000\", \"lvl\": \"info\", \"msg\": \"packed\"}\n",
+            ))
+            .mount(&server)
+            .await;
+
+        let lava = Lava::builder(&server.uri())
+            .compression(true)
+            .build()
+            .expect("failed to make lava server");
+
+        let tags = lava.tags().await.expect("failed to query tags");
+        assert_eq!(tags.len(), 1);
+        assert_eq!(tags[0].name, "hdmi");
+
+        let entries = lava
+            .log(5)
+            .log()
+            .try_collect::<Vec<_>>()
+            .await
+            .expect("failed to read log");
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].as_message(), Some("packed"));
+
+        // Without compression, nothing matches the mocks
+        let lava = Lava::new(&server.uri(), None).expect("failed to make lava server");
+        lava.tags()
+            .await
+            .expect_err("uncompressed request was answered");
+    }
+
+    #[test(tokio::test)]
+    async fn test_with_client() {
+        use reqwest::header::{HeaderMap, HeaderValue};
+        use reqwest::redirect::Policy;
+
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/api/v0.2/tags/"))
+            .and(header("authorization", "Token secret"))
+            .and(header("x-tenant", "lab"))
+            .and(header("user-agent", "shared/1.0"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "count": 0,
+                "next": null,
+                "results": [],
+            })))
+            .mount(&server)
+            .await;
+
+        let mut headers = HeaderMap::new();
+        headers.insert("x-tenant", HeaderValue::from_static("lab"));
+        let client = reqwest::Client::builder()
+            .default_headers(headers)
+            .user_agent("shared/1.0")
+            .redirect(Policy::none())
+            .build()
+            .expect("failed to make client");
+
+        let lava = Lava::with_client(&server.uri(), Some("secret".to_string()), client.clone())
+            .expect("failed to make lava server");
+        let tags = lava.tags().await.expect("failed to query tags");
+        assert!(tags.is_empty());
+
+        // The client's own settings take precedence over the builder's
+        let lava = Lava::builder(&server.uri())
+            .token("secret")
+            .user_agent("ignored/1.0")
+            .client(client)
+            .build()
+            .expect("failed to make lava server");
+        lava.tags().await.expect("failed to query tags");
+    }
+
+    #[test(tokio::test)]
+    async fn test_redirects() {
+        use super::system::SystemError;
+        use super::transport::RedirectPolicy;
+        use reqwest::StatusCode;
+
+        let server = MockServer::start().await;
+        let other = MockServer::start().await;
+        let version = ResponseTemplate::new(200).set_body_json(json!({"version": "2023.01"}));
+        Mock::given(method("GET"))
+            .and(path("/same/api/v0.2/system/version/"))
+            .respond_with(
+                ResponseTemplate::new(301)
+                    .insert_header("location", "/moved/api/v0.2/system/version/"),
+            )
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/moved/api/v0.2/system/version/"))
+            .and(header("authorization", "Token secret"))
+            .respond_with(version.clone())
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/away/api/v0.2/system/version/"))
+            .respond_with(ResponseTemplate::new(307).insert_header(
+                "location",
+                format!("{}/api/v0.2/system/version/", other.uri()).as_str(),
+            ))
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/api/v0.2/system/version/"))
+            .respond_with(version)
+            .mount(&other)
+            .await;
+
+        let lava = |prefix: &str, policy: Option<RedirectPolicy>| {
+            let builder = Lava::builder(&format!("{}/{}/", server.uri(), prefix)).token("secret");
+            match policy {
+                Some(policy) => builder.redirects(policy),
+                None => builder,
+            }
+            .build()
+            .expect("failed to make lava server")
+        };
+
+        // Same origin redirects are followed by default, with the token
+        let found = lava("same", None)
+            .server_version()
+            .await
+            .expect("failed to follow redirect")
+            .expect("no version after redirect");
+        assert_eq!(found.version, "2023.01");
+
+        assert!(matches!(
+            lava("same", Some(RedirectPolicy::None))
+                .server_version()
+                .await,
+            Err(SystemError::UnexpectedReply(StatusCode::MOVED_PERMANENTLY))
+        ));
+        assert!(matches!(
+            lava("away", None).server_version().await,
+            Err(SystemError::UnexpectedReply(StatusCode::TEMPORARY_REDIRECT))
+        ));
+        assert!(other.received_requests().await.unwrap().is_empty());
+
+        // Redirects elsewhere can be followed, but without the token
+        let found = lava("away", Some(RedirectPolicy::Any))
+            .server_version()
+            .await
+            .expect("failed to follow redirect")
+            .expect("no version after redirect");
+        assert_eq!(found.version, "2023.01");
+        let received = other.received_requests().await.unwrap();
+        assert_eq!(received.len(), 1);
+        assert!(received[0]
+            .headers
+            .keys()
+            .all(|name| name.as_str() != "authorization"));
+
+        // Pages are redirected by the same policy
+        Mock::given(method("GET"))
+            .and(path("/same/api/v0.2/workers/"))
+            .respond_with(
+                ResponseTemplate::new(301).insert_header("location", "/moved/api/v0.2/workers/"),
+            )
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/moved/api/v0.2/workers/"))
+            .and(header("authorization", "Token secret"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "count": 0,
+                "next": null,
+                "results": [],
+            })))
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/away/api/v0.2/workers/"))
+            .respond_with(ResponseTemplate::new(302).insert_header(
+                "location",
+                format!("{}/api/v0.2/workers/", other.uri()).as_str(),
+            ))
+            .mount(&server)
+            .await;
+
+        let workers: Vec<_> = lava("same", None)
+            .workers()
+            .try_collect()
+            .await
+            .expect("failed to follow redirect");
+        assert!(workers.is_empty());
+
+        let error = lava("same", Some(RedirectPolicy::None))
+            .workers()
+            .try_collect::<Vec<_>>()
+            .await
+            .expect_err("followed redirect");
+        assert_eq!(error.status(), Some(StatusCode::MOVED_PERMANENTLY));
+
+        let error = lava("away", None)
+            .workers()
+            .try_collect::<Vec<_>>()
+            .await
+            .expect_err("followed redirect");
+        assert_eq!(error.status(), Some(StatusCode::FOUND));
+        assert_eq!(other.received_requests().await.unwrap().len(), 1);
     }
 }
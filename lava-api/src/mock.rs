@@ -0,0 +1,194 @@
+//! Convert objects held by a mock server
+//!
+//! The `lava-api-mock` crate provides a mock LAVA server, whose
+//! state is a set of objects describing the jobs, devices and so on
+//! that it serves. This module converts those objects into the
+//! corresponding objects of this crate, so that tests can compare
+//! what was read from the server with what it holds.
+//!
+//! With the `mock` feature enabled, this module is available to
+//! other crates, and the mock crate itself is re-exported as
+//! [`lava_api_mock`](crate::lava_api_mock), so that the integration
+//! tests of crates built on this one can use a mock server matching
+//! this version of the client.
+
+use lava_api_mock::{
+    Device as MockDevice, DeviceHealth as MockDeviceHealth, DeviceState as MockDeviceState,
+    DeviceType as MockDeviceType, Group as MockGroup, Job as MockJob, JobHealth as MockJobHealth,
+    JobState as MockJobState, Tag as MockTag, User as MockUser, Worker as MockWorker,
+};
+use persian_rug::{Accessor, Context};
+
+use crate::device::{self, Device};
+use crate::job::{self, Job, Visibility};
+use crate::tag::Tag;
+
+impl From<MockDeviceHealth> for device::Health {
+    fn from(health: MockDeviceHealth) -> Self {
+        use device::Health::*;
+        match health {
+            MockDeviceHealth::Unknown => Unknown,
+            MockDeviceHealth::Maintenance => Maintenance,
+            MockDeviceHealth::Good => Good,
+            MockDeviceHealth::Bad => Bad,
+            MockDeviceHealth::Looping => Looping,
+            MockDeviceHealth::Retired => Retired,
+        }
+    }
+}
+
+impl From<MockDeviceState> for device::State {
+    fn from(state: MockDeviceState) -> Self {
+        use device::State::*;
+        match state {
+            MockDeviceState::Idle => Idle,
+            MockDeviceState::Reserved => Reserved,
+            MockDeviceState::Running => Running,
+        }
+    }
+}
+
+impl From<MockJobState> for job::State {
+    fn from(state: MockJobState) -> Self {
+        use job::State::*;
+        match state {
+            MockJobState::Submitted => Submitted,
+            MockJobState::Scheduling => Scheduling,
+            MockJobState::Scheduled => Scheduled,
+            MockJobState::Running => Running,
+            MockJobState::Canceling => Canceling,
+            MockJobState::Finished => Finished,
+        }
+    }
+}
+
+impl From<MockJobHealth> for job::Health {
+    fn from(health: MockJobHealth) -> Self {
+        use job::Health::*;
+        match health {
+            MockJobHealth::Unknown => Unknown,
+            MockJobHealth::Complete => Complete,
+            MockJobHealth::Incomplete => Incomplete,
+            MockJobHealth::Canceled => Canceled,
+        }
+    }
+}
+
+impl Tag {
+    /// Convert a tag held by a mock server.
+    pub fn from_mock<'b, B, C>(tag: &MockTag<C>, _context: B) -> Tag
+    where
+        B: 'b + Accessor<Context = C>,
+        C: Context + 'static,
+    {
+        Self {
+            id: tag.id,
+            name: tag.name.clone(),
+            description: tag.description.clone(),
+        }
+    }
+}
+
+impl Device {
+    /// Convert a device held by a mock server, looking up its
+    /// worker, device type, tags, owner, group and health report job
+    /// in `context`.
+    #[persian_rug::constraints(
+        context = C,
+        access(
+            MockTag<C>,
+            MockDeviceType<C>,
+            MockWorker<C>,
+            MockUser<C>,
+            MockGroup<C>,
+            MockJob<C>
+        )
+    )]
+    pub fn from_mock<'b, B, C>(dev: &MockDevice<C>, context: B) -> Device
+    where
+        B: 'b + Accessor<Context = C>,
+        C: Context + 'static,
+    {
+        Self {
+            hostname: dev.hostname.clone(),
+            worker_host: context.get(&dev.worker_host).hostname.clone(),
+            device_type: context.get(&dev.device_type).name.clone(),
+            description: dev.description.clone(),
+            device_version: dev.device_version.clone(),
+            physical_owner: dev.physical_owner.map(|u| context.get(&u).id),
+            physical_group: dev.physical_group.map(|g| context.get(&g).id),
+            state: dev.state.clone().into(),
+            health: dev.health.clone().into(),
+            tags: dev
+                .tags
+                .iter()
+                .map(|t| Tag::from_mock(context.get(t), context.clone()))
+                .collect::<Vec<_>>(),
+            is_synced: dev.is_synced,
+            last_health_report_job: dev.last_health_report_job.map(|j| context.get(&j).id),
+        }
+    }
+}
+
+impl Job {
+    /// Convert a job held by a mock server, looking up the objects
+    /// it refers to in `context`.
+    ///
+    /// The job must have a submission time.
+    #[persian_rug::constraints(
+        context = C,
+        access(
+            MockUser<C>,
+            MockGroup<C>,
+            MockTag<C>,
+            MockDevice<C>,
+            MockDeviceType<C>
+        )
+    )]
+    pub fn from_mock<'b, B, C>(job: &MockJob<C>, context: B) -> Job
+    where
+        B: 'b + Accessor<Context = C>,
+        C: Context + 'static,
+    {
+        let viewing_groups = job
+            .viewing_groups
+            .iter()
+            .map(|g| context.get(g).id)
+            .collect::<Vec<_>>();
+        Self {
+            id: job.id,
+            submitter: context.get(&job.submitter).username.clone(),
+            visibility: Visibility::new(Some(job.is_public), &viewing_groups),
+            viewing_groups,
+            description: job.description.clone(),
+            health_check: job.health_check,
+            requested_device_type: job
+                .requested_device_type
+                .map(|d| context.get(&d).name.to_string()),
+            tags: job
+                .tags
+                .iter()
+                .map(|t| Tag::from_mock(context.get(t), context.clone()))
+                .collect::<Vec<_>>(),
+            actual_device: job
+                .actual_device
+                .as_ref()
+                .map(|d| context.get(d).hostname.to_string()),
+            submit_time: job.submit_time.unwrap(),
+            start_time: job.start_time,
+            end_time: job.end_time,
+            state: job.state.into(),
+            health: job.health.into(),
+            priority: job.priority,
+            definition: job.definition.clone(),
+            original_definition: job.original_definition.clone(),
+            multinode_definition: job.multinode_definition.clone(),
+            failure_tags: job
+                .failure_tags
+                .iter()
+                .map(|t| Tag::from_mock(context.get(t), context.clone()))
+                .collect::<Vec<_>>(),
+            failure_comment: job.failure_comment.clone(),
+        }
+    }
+}
@@ -0,0 +1,244 @@
+//! Query several servers at once
+//!
+//! A [`MultiLava`] holds a [`Lava`] for each of several servers, each
+//! with a name, and merges the streams read from all of them into
+//! one. Every item is tagged with the name of the server it came
+//! from, and a server which fails only ends its own part of the
+//! merged stream, so that one unreachable server does not hide the
+//! data from the others.
+//!
+//! Example:
+//! ```rust,no_run
+//! use futures::stream::StreamExt;
+//! use lava_api::multi::MultiLava;
+//! use lava_api::Lava;
+//!
+//! # tokio_test::block_on( async {
+//! let servers = MultiLava::new()
+//!     .server("lab-a", Lava::new("https://lava-a.example.com/", None).unwrap())
+//!     .server("lab-b", Lava::new("https://lava-b.example.com/", None).unwrap());
+//!
+//! let mut devices = servers.devices();
+//! while let Some(device) = devices.next().await {
+//!     match device {
+//!         Ok(device) => println!("{}: {}", device.server, device.item.hostname),
+//!         Err(e) => println!("{} failed: {}", e.server, e.error),
+//!     }
+//! }
+//! # });
+//! ```
+
+use futures::stream::{self, Stream, StreamExt, TryStream, TryStreamExt};
+use serde::Serialize;
+use thiserror::Error;
+
+use crate::device::Device;
+use crate::error::{Classify, ErrorClass};
+use crate::job::Job;
+use crate::paginator::PaginationError;
+use crate::worker::Worker;
+use crate::Lava;
+
+/// An item read from one of the servers of a [`MultiLava`]
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct FromServer<T> {
+    /// The name of the server the item came from
+    pub server: String,
+    pub item: T,
+}
+
+/// A failure reading from one of the servers of a [`MultiLava`]
+#[derive(Error, Debug)]
+#[error("Failed to read from server {server}")]
+pub struct ServerError {
+    /// The name of the server which failed
+    pub server: String,
+    #[source]
+    pub error: PaginationError,
+}
+
+impl Classify for ServerError {
+    fn class(&self) -> ErrorClass {
+        self.error.class()
+    }
+}
+
+/// A collection of named [`Lava`] instances, read from together
+#[derive(Debug, Default)]
+pub struct MultiLava {
+    servers: Vec<(String, Lava)>,
+}
+
+impl MultiLava {
+    /// Create a new [`MultiLava`], with no servers.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a server, with the name its items are tagged with.
+    pub fn server<N: Into<String>>(mut self, name: N, lava: Lava) -> Self {
+        self.servers.push((name.into(), lava));
+        self
+    }
+
+    /// The servers, with their names, in the order they were added.
+    pub fn servers(&self) -> impl Iterator<Item = (&str, &Lava)> {
+        self.servers
+            .iter()
+            .map(|(name, lava)| (name.as_str(), lava))
+    }
+
+    /// Obtain the stream made by `query` for each server, merged
+    /// into one.
+    ///
+    /// Items are returned as soon as any server provides them, so
+    /// the items from different servers are interleaved. When the
+    /// stream for a server returns an error, the error is passed on
+    /// as a [`ServerError`] and nothing more is read from that
+    /// server, but the other servers are still read to the end.
+    ///
+    /// Example:
+    /// ```rust,no_run
+    /// use futures::stream::StreamExt;
+    /// use lava_api::job::State;
+    /// use lava_api::multi::MultiLava;
+    ///
+    /// # tokio_test::block_on( async {
+    /// # let servers = MultiLava::new();
+    /// let running = servers
+    ///     .query(|lava| lava.jobs().state(State::Running).query())
+    ///     .filter_map(|job| async { job.ok() })
+    ///     .count()
+    ///     .await;
+    /// println!("{} jobs running across all servers", running);
+    /// # });
+    /// ```
+    pub fn query<'a, F, S, T>(
+        &'a self,
+        query: F,
+    ) -> impl Stream<Item = Result<FromServer<T>, ServerError>> + Send + 'a
+    where
+        F: Fn(&'a Lava) -> S,
+        S: TryStream<Ok = T, Error = PaginationError> + Send + 'a,
+        T: Send + 'a,
+    {
+        stream::select_all(self.servers.iter().map(|(name, lava)| {
+            // The stream for a server is dropped after its first
+            // error, rather than polled again to retry.
+            let inner = Box::pin(query(lava).into_stream());
+            stream::unfold(Some(inner), move |inner| {
+                let server = name.clone();
+                async move {
+                    let mut inner = inner?;
+                    match inner.next().await? {
+                        Ok(item) => Some((Ok(FromServer { server, item }), Some(inner))),
+                        Err(error) => Some((Err(ServerError { server, error }), None)),
+                    }
+                }
+            })
+            .boxed()
+        }))
+    }
+
+    /// Obtain a stream of all the [`Job`] instances on every server.
+    pub fn jobs(&self) -> impl Stream<Item = Result<FromServer<Job>, ServerError>> + Send + '_ {
+        self.query(|lava| lava.jobs().query())
+    }
+
+    /// Obtain a stream of all the [`Device`] instances on every
+    /// server.
+    pub fn devices(
+        &self,
+    ) -> impl Stream<Item = Result<FromServer<Device>, ServerError>> + Send + '_ {
+        self.query(|lava| lava.devices())
+    }
+
+    /// Obtain a stream of all the [`Worker`] instances on every
+    /// server.
+    pub fn workers(
+        &self,
+    ) -> impl Stream<Item = Result<FromServer<Worker>, ServerError>> + Send + '_ {
+        self.query(|lava| lava.workers())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::MultiLava;
+    use crate::Lava;
+
+    use futures::StreamExt;
+    use serde_json::json;
+    use test_log::test;
+    use wiremock::matchers::{method, path};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    fn worker(hostname: &str) -> serde_json::Value {
+        json!({
+            "hostname": hostname,
+            "state": "Online",
+            "health": "Active",
+            "job_limit": 0,
+        })
+    }
+
+    #[test(tokio::test)]
+    async fn test_workers() {
+        let good = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/api/v0.2/workers/"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "count": 2,
+                "next": null,
+                "results": [worker("a"), worker("b")],
+            })))
+            .mount(&good)
+            .await;
+        let bad = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/api/v0.2/workers/"))
+            .respond_with(ResponseTemplate::new(503))
+            .expect(1)
+            .mount(&bad)
+            .await;
+
+        let servers = MultiLava::new()
+            .server(
+                "good",
+                Lava::new(&good.uri(), None).expect("failed to make lava server"),
+            )
+            .server(
+                "bad",
+                Lava::new(&bad.uri(), None).expect("failed to make lava server"),
+            );
+        assert_eq!(
+            servers.servers().map(|(name, _)| name).collect::<Vec<_>>(),
+            vec!["good", "bad"]
+        );
+
+        let mut workers = Vec::new();
+        let mut errors = Vec::new();
+        let mut merged = servers.workers();
+        while let Some(item) = merged.next().await {
+            match item {
+                Ok(worker) => workers.push((worker.server, worker.item.hostname)),
+                Err(e) => errors.push((e.server, e.error.status())),
+            }
+        }
+        workers.sort();
+        assert_eq!(
+            workers,
+            vec![
+                ("good".to_string(), "a".to_string()),
+                ("good".to_string(), "b".to_string())
+            ]
+        );
+        assert_eq!(
+            errors,
+            vec![(
+                "bad".to_string(),
+                Some(reqwest::StatusCode::SERVICE_UNAVAILABLE)
+            )]
+        );
+    }
+}
@@ -1,48 +1,469 @@
+use bytes::Bytes;
 use futures::future::BoxFuture;
 use futures::stream::Stream;
 use futures::FutureExt;
 use log::debug;
-use reqwest::Client;
-use serde::{de::DeserializeOwned, Deserialize};
-use std::collections::VecDeque;
+use reqwest::header::{HeaderValue, ETAG, IF_MODIFIED_SINCE, IF_NONE_MATCH, LAST_MODIFIED};
+use reqwest::{Client, StatusCode};
+use serde::{de::DeserializeOwned, Deserialize, Deserializer};
+use std::collections::{HashMap, VecDeque};
+use std::fmt;
 use std::pin::Pin;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
 use std::task::{Context, Poll};
 use thiserror::Error;
 use url::Url;
 
+use crate::error::{Classify, ErrorClass};
+use crate::retry::RetryPolicy;
+use crate::transport::{self, HttpTransport, Transport};
+
+/// What went wrong reading a page, as reported by a
+/// [`PaginationError`]
 #[derive(Debug, Error)]
 #[non_exhaustive]
-pub enum PaginationError {
-    #[error("http request failed: {0}")]
+pub enum PaginationErrorKind {
+    #[error("HTTP request for paginated data failed")]
     ReqWest(#[from] reqwest::Error),
-    #[error("HTTP redirect without location")]
-    RedirectMissing,
-    #[error("HTTP redirect not valid utf-8")]
-    RedirectInvalidUTF8,
-    #[error("Too many redirects")]
-    TooManyRedirects,
-    #[error("Failed to parse next uri: {0}")]
+    /// A redirect which the
+    /// [`RedirectPolicy`](crate::transport::RedirectPolicy) did not
+    /// allow following, or one too many.
+    #[error("HTTP redirect not followed ({0})")]
+    Redirected(StatusCode),
+    #[error("Failed to parse url of next page")]
     ParseNextError(#[from] url::ParseError),
+    #[error("Failed to parse page")]
+    InvalidPage(#[from] serde_json::Error),
+    #[error(transparent)]
+    InvalidRecord(#[from] RecordError),
+}
+
+/// A failure to read a page of a paginated query
+///
+/// As well as what went wrong, this records the url of the page
+/// which could not be read, when it is known, and whether trying
+/// again might succeed. A stream which returns an error can be
+/// polled again to retry the same page, or, for jobs, restarted from
+/// that page with [`Jobs::resume_from`](crate::job::Jobs::resume_from).
+/// The exception is an [`InvalidRecord`](PaginationErrorKind::InvalidRecord)
+/// error, after which the stream skips that record and continues
+/// with the next, rather than reading the page again.
+#[derive(Debug)]
+pub struct PaginationError {
+    kind: PaginationErrorKind,
+    url: Option<Box<Url>>,
+}
+
+impl Classify for PaginationError {
+    fn class(&self) -> ErrorClass {
+        match &self.kind {
+            PaginationErrorKind::ReqWest(e) => e.class(),
+            PaginationErrorKind::InvalidPage(_) | PaginationErrorKind::InvalidRecord(_) => {
+                ErrorClass::Decode
+            }
+            _ => ErrorClass::Other,
+        }
+    }
+}
+
+impl PaginationError {
+    pub(crate) fn new<K: Into<PaginationErrorKind>>(kind: K, url: Url) -> Self {
+        Self {
+            kind: kind.into(),
+            url: Some(Box::new(url)),
+        }
+    }
+
+    /// What went wrong.
+    pub fn kind(&self) -> &PaginationErrorKind {
+        &self.kind
+    }
+
+    /// The url of the page which could not be read, if known.
+    pub fn url(&self) -> Option<&Url> {
+        self.url.as_deref()
+    }
+
+    /// The offset into the result set of the page which could not be
+    /// read, if it was requested by offset.
+    ///
+    /// The first page of a query, and pages requested by key rather
+    /// than by offset, have no offset.
+    pub fn offset(&self) -> Option<u64> {
+        self.url
+            .as_ref()?
+            .query_pairs()
+            .find(|(k, _)| k == "offset")
+            .and_then(|(_, offset)| offset.parse().ok())
+    }
+
+    /// The status of the server's reply, if it sent an error reply
+    /// or a redirect which was not followed.
+    pub fn status(&self) -> Option<StatusCode> {
+        match &self.kind {
+            PaginationErrorKind::ReqWest(e) => e.status(),
+            PaginationErrorKind::Redirected(status) => Some(*status),
+            _ => None,
+        }
+    }
+
+    /// Whether the failure might be transient, so that reading the
+    /// page again could succeed.
+    ///
+    /// This is the case when the [`class`](Classify::class) of the
+    /// error is [transient](ErrorClass::is_transient): timeouts,
+    /// connection failures, failures reading the body of a reply,
+    /// and replies which report the server as overloaded or failing
+    /// (429 and 5xx). Other error replies, and pages which could not
+    /// be parsed, are not retryable.
+    pub fn is_retryable(&self) -> bool {
+        self.class().is_transient()
+    }
+}
+
+impl fmt::Display for PaginationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.url {
+            Some(url) => write!(f, "{} (reading {})", self.kind, url),
+            None => self.kind.fmt(f),
+        }
+    }
+}
+
+impl std::error::Error for PaginationError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        std::error::Error::source(&self.kind)
+    }
+}
+
+impl From<PaginationErrorKind> for PaginationError {
+    fn from(kind: PaginationErrorKind) -> Self {
+        let url = match &kind {
+            PaginationErrorKind::ReqWest(e) => e.url().cloned().map(Box::new),
+            _ => None,
+        };
+        Self { kind, url }
+    }
+}
+
+impl From<reqwest::Error> for PaginationError {
+    fn from(e: reqwest::Error) -> Self {
+        PaginationErrorKind::from(e).into()
+    }
+}
+
+impl From<url::ParseError> for PaginationError {
+    fn from(e: url::ParseError) -> Self {
+        PaginationErrorKind::from(e).into()
+    }
+}
+
+impl From<serde_json::Error> for PaginationError {
+    fn from(e: serde_json::Error) -> Self {
+        PaginationErrorKind::from(e).into()
+    }
+}
+
+/// A record of a paginated query which could not be parsed
+///
+/// This keeps the record as the server sent it, so that records which
+/// do not match what this crate expects, for example because the
+/// server is newer, can be logged or handled by hand. Records are
+/// only reported this way by a [`LenientPaginator`]; a [`Paginator`]
+/// reports them as a [`PaginationError`].
+#[derive(Debug, Error)]
+#[error("Failed to parse record")]
+pub struct RecordError {
+    #[source]
+    error: serde_json::Error,
+    value: serde_json::Value,
+}
+
+impl RecordError {
+    /// Why the record could not be parsed.
+    pub fn error(&self) -> &serde_json::Error {
+        &self.error
+    }
+
+    /// The record as the server sent it.
+    pub fn value(&self) -> &serde_json::Value {
+        &self.value
+    }
+
+    /// Take the record as the server sent it.
+    pub fn into_value(self) -> serde_json::Value {
+        self.value
+    }
+}
+
+// A record of a page, which is parsed on its own so that a record
+// which cannot be parsed does not prevent reading the rest of the
+// page.
+#[derive(Debug)]
+struct Record<T>(Result<T, RecordError>);
+
+impl<'de, T: DeserializeOwned> Deserialize<'de> for Record<T> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let value = serde_json::Value::deserialize(deserializer)?;
+        let record = T::deserialize(&value);
+        Ok(Record(record.map_err(|error| RecordError { error, value })))
+    }
+}
+
+/// A cache of the pages returned by paginated queries
+///
+/// When a [`Lava`](crate::Lava) is given a cache with
+/// [`LavaBuilder::page_cache`](crate::LavaBuilder::page_cache), each
+/// page the server marks with an `ETag` or `Last-Modified` header is
+/// kept, and later requests for the same page ask the server to send
+/// it only if it has changed. Unchanged pages are then read from the
+/// cache instead of being downloaded again, which helps clients
+/// which poll the same queries repeatedly. Pages are still parsed
+/// each time they are read.
+///
+/// Whether a server sends these headers depends on how it is
+/// deployed; when it does not, the cache has no effect beyond
+/// counting misses. The least recently used pages are discarded once
+/// the cache is full.
+#[derive(Debug)]
+pub struct PageCache {
+    max_pages: usize,
+    pages: Mutex<CachedPages>,
+    hits: AtomicU64,
+    misses: AtomicU64,
+}
+
+#[derive(Debug, Default)]
+struct CachedPages {
+    pages: HashMap<Url, CachedPage>,
+    // Incremented on each use, to find the least recently used page
+    clock: u64,
+}
+
+#[derive(Clone, Debug)]
+struct CachedPage {
+    etag: Option<HeaderValue>,
+    last_modified: Option<HeaderValue>,
+    body: Bytes,
+    used: u64,
+}
+
+/// How well a [`PageCache`] is working
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct PageCacheStats {
+    /// The number of pages read from the cache
+    pub hits: u64,
+    /// The number of pages downloaded from the server
+    pub misses: u64,
+    /// The number of pages currently cached
+    pub pages: usize,
+}
+
+impl PageCacheStats {
+    /// The fraction of pages read from the cache, or `None` if no
+    /// pages have been read.
+    pub fn hit_rate(&self) -> Option<f64> {
+        let total = self.hits + self.misses;
+        (total > 0).then(|| self.hits as f64 / total as f64)
+    }
+}
+
+impl PageCache {
+    /// Create a new [`PageCache`] holding at most `max_pages` pages.
+    pub fn new(max_pages: usize) -> Self {
+        Self {
+            max_pages,
+            pages: Mutex::new(CachedPages::default()),
+            hits: AtomicU64::new(0),
+            misses: AtomicU64::new(0),
+        }
+    }
+
+    /// The hit rate and size of the cache so far.
+    pub fn stats(&self) -> PageCacheStats {
+        PageCacheStats {
+            hits: self.hits.load(Ordering::Relaxed),
+            misses: self.misses.load(Ordering::Relaxed),
+            pages: self.pages.lock().unwrap().pages.len(),
+        }
+    }
+
+    /// Discard every cached page, keeping the statistics.
+    pub fn clear(&self) {
+        self.pages.lock().unwrap().pages.clear();
+    }
+
+    fn get(&self, url: &Url) -> Option<CachedPage> {
+        let mut cached = self.pages.lock().unwrap();
+        cached.clock += 1;
+        let clock = cached.clock;
+        cached.pages.get_mut(url).map(|page| {
+            page.used = clock;
+            page.clone()
+        })
+    }
+
+    fn insert(
+        &self,
+        url: Url,
+        etag: Option<HeaderValue>,
+        last_modified: Option<HeaderValue>,
+        body: Bytes,
+    ) {
+        if self.max_pages == 0 {
+            return;
+        }
+        let mut cached = self.pages.lock().unwrap();
+        cached.clock += 1;
+        let used = cached.clock;
+        if cached.pages.len() >= self.max_pages && !cached.pages.contains_key(&url) {
+            let oldest = cached
+                .pages
+                .iter()
+                .min_by_key(|(_, page)| page.used)
+                .map(|(url, _)| url.clone());
+            if let Some(oldest) = oldest {
+                cached.pages.remove(&oldest);
+            }
+        }
+        cached.pages.insert(
+            url,
+            CachedPage {
+                etag,
+                last_modified,
+                body,
+                used,
+            },
+        );
+    }
+
+    fn remove(&self, url: &Url) {
+        self.pages.lock().unwrap().pages.remove(url);
+    }
+}
+
+/// Progress through a paginated query, for example to drive a
+/// progress bar.
+///
+/// This is implemented by every stream in this crate which reads
+/// its items a page at a time.
+pub trait PaginationProgress {
+    /// The server's latest report of how many items are in the
+    /// result set, or `None` if no page has been received yet.
+    ///
+    /// This only counts the items matching the query, and is subject
+    /// to change as the stream is read, owing to pagination.
+    fn reported_items(&self) -> Option<u32>;
+
+    /// The number of items the stream has yielded so far.
+    fn yielded_items(&self) -> u32;
+
+    /// The number of pages received from the server so far.
+    fn fetched_pages(&self) -> u32;
 }
 
 #[derive(Deserialize, Debug)]
+#[serde(bound(deserialize = "T: DeserializeOwned"))]
 struct PaginatedReply<T> {
+    count: u32,
+    next: Option<String>,
+    results: VecDeque<Record<T>>,
+}
+
+// A page whose records are parsed directly, without first being kept
+// as the server sent them.
+#[derive(Deserialize)]
+#[serde(bound(deserialize = "T: DeserializeOwned"))]
+struct DirectReply<T> {
     count: u32,
     next: Option<String>,
     results: VecDeque<T>,
 }
 
+impl<T: DeserializeOwned> PaginatedReply<T> {
+    // Parse a page from `body`. Keeping each record as a
+    // `serde_json::Value` makes parsing much slower, so that is only
+    // done for pages with a record which cannot otherwise be parsed,
+    // to find out which.
+    fn parse(body: &[u8]) -> Result<Self, serde_json::Error> {
+        match serde_json::from_slice::<DirectReply<T>>(body) {
+            Ok(page) => Ok(PaginatedReply {
+                count: page.count,
+                next: page.next,
+                results: page.results.into_iter().map(|r| Record(Ok(r))).collect(),
+            }),
+            Err(_) => serde_json::from_slice(body),
+        }
+    }
+}
+
 enum State<T> {
     Data(PaginatedReply<T>),
-    Next(BoxFuture<'static, Result<PaginatedReply<T>, PaginationError>>),
+    Next(PageFuture<T>),
     Failed,
 }
 
+// Continuation by key rather than by offset: each page after the
+// first is requested by setting `param` to the key of the last item
+// received, instead of following the server's link to the next page.
+struct Keyset<T> {
+    param: &'static str,
+    key: fn(&T) -> String,
+    last: Option<String>,
+    // The number of items yielded before the current page, which the
+    // server's count no longer includes.
+    skipped: u32,
+}
+
+impl<T> Keyset<T> {
+    // The url of the page after `current`, whose last item has the
+    // key `last`.
+    fn url_after(&self, current: &Url, last: &str) -> Url {
+        let pairs = current
+            .query_pairs()
+            .filter(|(k, _)| k != "offset" && k != self.param)
+            .map(|(k, v)| (k.into_owned(), v.into_owned()))
+            .collect::<Vec<_>>();
+        let mut url = current.clone();
+        url.query_pairs_mut()
+            .clear()
+            .extend_pairs(pairs)
+            .append_pair(self.param, last);
+        url
+    }
+}
+
+type PageFuture<T> = BoxFuture<'static, Result<PaginatedReply<T>, PaginationError>>;
+
+// A request for a page made before it was needed.
+enum Prefetch<T> {
+    Pending(PageFuture<T>),
+    Ready(Result<PaginatedReply<T>, PaginationError>),
+}
+
 pub struct Paginator<T> {
-    client: Client,
+    transport: Arc<dyn Transport>,
+    retry: Arc<RetryPolicy>,
+    cache: Option<Arc<PageCache>>,
     current: Url,
     next: State<T>,
     count: Option<u32>,
+    yielded: u32,
+    pages: u32,
+    // The number of items yielded before the current page
+    page_start: u32,
+    keyset: Option<Keyset<T>>,
+    // The number of unread items of the current page at which the
+    // next page is requested, if pages are prefetched
+    prefetch: Option<usize>,
+    // The request for the page after the current one, made before
+    // the current page has been read, with its url
+    prefetched: Option<(Url, Prefetch<T>)>,
+    // The span in which the stream is read, so that the requests it
+    // makes are grouped together wherever it is polled from
+    #[cfg(feature = "tracing")]
+    span: tracing::Span,
 }
 
 impl<T> Paginator<T>
@@ -50,75 +471,272 @@ where
     T: DeserializeOwned + 'static,
 {
     pub fn new(client: Client, url: Url) -> Self {
-        let next = State::Next(Self::get(client.clone(), url.clone()).boxed());
+        Self::with_transport(
+            Arc::new(HttpTransport::new(client)),
+            url,
+            Arc::new(RetryPolicy::none()),
+            None,
+        )
+    }
+
+    pub(crate) fn with_transport(
+        transport: Arc<dyn Transport>,
+        url: Url,
+        retry: Arc<RetryPolicy>,
+        cache: Option<Arc<PageCache>>,
+    ) -> Self {
+        let next = State::Next(
+            Self::get(transport.clone(), retry.clone(), cache.clone(), url.clone()).boxed(),
+        );
+        #[cfg(feature = "tracing")]
+        let span = tracing::debug_span!("paginate", url = %url);
 
         Paginator {
-            client,
+            transport,
+            retry,
+            cache,
             current: url,
             next,
             count: None,
+            yielded: 0,
+            pages: 0,
+            page_start: 0,
+            keyset: None,
+            prefetch: None,
+            prefetched: None,
+            #[cfg(feature = "tracing")]
+            span,
         }
     }
 
-    async fn get(client: Client, uri: Url) -> Result<PaginatedReply<T>, PaginationError>
+    /// Continue the query by key, rather than by offset.
+    ///
+    /// The query must be ordered by the key given by `key`, with
+    /// `param` the filter selecting the items after a given key in
+    /// that order (for example `id__gt` for ascending ids). Pages
+    /// after the first are then requested by filtering on the last
+    /// item received, so that items added or removed while paging do
+    /// not shift the page boundaries.
+    pub(crate) fn keyset(mut self, param: &'static str, key: fn(&T) -> String) -> Self {
+        self.keyset = Some(Keyset {
+            param,
+            key,
+            last: None,
+            skipped: 0,
+        });
+        self
+    }
+
+    /// Request the next page before the current one has been read.
+    ///
+    /// The next page is requested once no more than `max_buffered`
+    /// items of the current page remain unread, so that it can arrive
+    /// while those are being processed, rather than only being
+    /// requested once they have all been read. At most `max_buffered`
+    /// items and one page are then held at once. The request makes
+    /// progress only while the stream is polled. `None` disables
+    /// prefetching.
+    pub(crate) fn prefetch(mut self, max_buffered: Option<usize>) -> Self {
+        self.prefetch = max_buffered;
+        self
+    }
+
+    async fn get(
+        transport: Arc<dyn Transport>,
+        retry: Arc<RetryPolicy>,
+        cache: Option<Arc<PageCache>>,
+        uri: Url,
+    ) -> Result<PaginatedReply<T>, PaginationError>
     where
         T: DeserializeOwned,
     {
-        let mut redirects: u8 = 0;
-        let mut u = uri.clone();
-        let response = loop {
-            let response = client.get(u.clone()).send().await?;
+        Self::fetch(transport, retry, cache, uri.clone())
+            .await
+            .map_err(|kind| PaginationError::new(kind, uri))
+    }
 
-            if !response.status().is_redirection() {
-                break response;
+    async fn fetch(
+        transport: Arc<dyn Transport>,
+        retry: Arc<RetryPolicy>,
+        cache: Option<Arc<PageCache>>,
+        uri: Url,
+    ) -> Result<PaginatedReply<T>, PaginationErrorKind>
+    where
+        T: DeserializeOwned,
+    {
+        // Redirects are followed by the transport, as its policy
+        // allows, so any which reach here were not to be followed.
+        let mut request = transport::get(uri.clone());
+        let cached = cache.as_ref().and_then(|c| c.get(&uri));
+        if let Some(page) = &cached {
+            let headers = request.headers_mut();
+            if let Some(etag) = &page.etag {
+                headers.insert(IF_NONE_MATCH, etag.clone());
             }
-
-            if redirects > 9 {
-                return Err(PaginationError::TooManyRedirects);
+            if let Some(last_modified) = &page.last_modified {
+                headers.insert(IF_MODIFIED_SINCE, last_modified.clone());
             }
+        }
+        let response = retry.send(&*transport, request).await?;
 
-            redirects += 1;
-            if let Some(location) = response.headers().get("location") {
-                let redirect = std::str::from_utf8(location.as_bytes())
-                    .or(Err(PaginationError::RedirectInvalidUTF8))?;
-
-                debug!("Redirecting from {:?} to {:?}", u, location);
-                u = u.join(redirect)?;
-                // Prevent https to http downgrade as we might have a token in
-                // the request
-                if uri.scheme() == "https" && u.scheme() == "http" {
-                    u.set_scheme("https").unwrap();
+        if response.status() == StatusCode::NOT_MODIFIED {
+            if let Some(page) = cached {
+                debug!("Using cached page for {:?}", uri);
+                if let Some(cache) = &cache {
+                    cache.hits.fetch_add(1, Ordering::Relaxed);
                 }
-            } else {
-                return Err(PaginationError::RedirectMissing);
+                return Ok(PaginatedReply::parse(&page.body)?);
             }
+        }
+
+        if response.status().is_redirection() {
+            return Err(PaginationErrorKind::Redirected(response.status()));
+        }
+
+        let response = response.error_for_status()?;
+        let cache = match cache {
+            Some(cache) => cache,
+            None => return Ok(PaginatedReply::parse(&response.bytes().await?)?),
         };
 
-        response
-            .error_for_status()?
-            .json()
-            .await
-            .map_err(|e| e.into())
+        cache.misses.fetch_add(1, Ordering::Relaxed);
+        let etag = response.headers().get(ETAG).cloned();
+        let last_modified = response.headers().get(LAST_MODIFIED).cloned();
+        if etag.is_none() && last_modified.is_none() {
+            // Drop any stale copy, since it can no longer be validated
+            cache.remove(&uri);
+            return Ok(PaginatedReply::parse(&response.bytes().await?)?);
+        }
+        let body = response.bytes().await?;
+        let page = PaginatedReply::parse(&body)?;
+        cache.insert(uri, etag, last_modified, body);
+        Ok(page)
     }
 
-    fn next_data(&mut self) -> Result<Option<T>, PaginationError> {
+    // The url of the page after the current one, once all of its
+    // items have been read, or `None` if it is the last page.
+    fn next_url(&self) -> Option<Result<Url, url::ParseError>> {
+        let d = match &self.next {
+            State::Data(d) => d,
+            _ => return None,
+        };
+        let n = d.next.as_ref()?;
+        let keyset_url = self.keyset.as_ref().and_then(|keyset| {
+            // Records which could not be parsed have no key, so the
+            // next page follows the last one which could
+            let last = match d.results.iter().rev().find_map(|r| r.0.as_ref().ok()) {
+                Some(item) => (keyset.key)(item),
+                None => keyset.last.clone()?,
+            };
+            Some(keyset.url_after(&self.current, &last))
+        });
+        Some(keyset_url.map_or_else(|| n.parse(), Ok))
+    }
+
+    // Request the next page, if prefetching is enabled and few enough
+    // items of the current page remain.
+    fn start_prefetch(&mut self) {
+        let max_buffered = match self.prefetch {
+            Some(max_buffered) if self.prefetched.is_none() => max_buffered,
+            _ => return,
+        };
+        match &self.next {
+            State::Data(d) if d.results.len() <= max_buffered => {}
+            _ => return,
+        }
+        if let Some(Ok(u)) = self.next_url() {
+            let fetch = Self::get(
+                self.transport.clone(),
+                self.retry.clone(),
+                self.cache.clone(),
+                u.clone(),
+            )
+            .boxed();
+            self.prefetched = Some((u, Prefetch::Pending(fetch)));
+        }
+    }
+
+    // Start the request for the next page if it is due, and make
+    // progress on it. Its page is only taken once the current page
+    // has been read.
+    fn drive_prefetch(&mut self, cx: &mut Context) {
+        self.start_prefetch();
+        if let Some((_, prefetch)) = &mut self.prefetched {
+            if let Prefetch::Pending(fetch) = prefetch {
+                if let Poll::Ready(page) = fetch.poll_unpin(cx) {
+                    *prefetch = Prefetch::Ready(page);
+                }
+            }
+        }
+    }
+
+    // Make `page` the current page, or on failure, arrange for it to
+    // be requested again when the stream is next polled.
+    fn page_arrived(
+        &mut self,
+        page: Result<PaginatedReply<T>, PaginationError>,
+    ) -> Result<(), PaginationError> {
+        match page {
+            Ok(page) => {
+                self.pages += 1;
+                self.page_start = self.yielded;
+                self.next = State::Data(page);
+                Ok(())
+            }
+            Err(e) => {
+                self.next = State::Next(
+                    Self::get(
+                        self.transport.clone(),
+                        self.retry.clone(),
+                        self.cache.clone(),
+                        self.current.clone(),
+                    )
+                    .boxed(),
+                );
+                Err(e)
+            }
+        }
+    }
+
+    fn next_data(&mut self) -> Result<Option<Result<T, RecordError>>, PaginationError> {
         if let State::Data(d) = &mut self.next {
-            self.count = Some(d.count);
-            if let Some(data) = d.results.pop_front() {
+            let skipped = self.keyset.as_ref().map(|k| k.skipped).unwrap_or_default();
+            self.count = Some(d.count + skipped);
+            if let Some(Record(data)) = d.results.pop_front() {
+                if let (Some(keyset), Ok(data)) = (&mut self.keyset, &data) {
+                    keyset.last = Some((keyset.key)(data));
+                }
                 return Ok(Some(data));
             }
+        }
 
-            if let Some(n) = &d.next {
-                let u: Result<Url, _> = n.parse();
-                match u {
-                    Ok(u) => {
-                        self.next = State::Next(Self::get(self.client.clone(), u.clone()).boxed());
-                        self.current = u;
-                    }
-                    Err(e) => {
-                        self.next = State::Failed;
-                        return Err(e.into());
-                    }
+        if let Some(u) = self.next_url() {
+            if let Some(keyset) = &mut self.keyset {
+                keyset.skipped = self.yielded;
+            }
+            match u {
+                Ok(u) => {
+                    let fetch = match self.prefetched.take() {
+                        Some((url, Prefetch::Pending(fetch))) if url == u => fetch,
+                        Some((url, Prefetch::Ready(page))) if url == u => {
+                            self.current = u;
+                            self.page_arrived(page)?;
+                            return self.next_data();
+                        }
+                        _ => Self::get(
+                            self.transport.clone(),
+                            self.retry.clone(),
+                            self.cache.clone(),
+                            u.clone(),
+                        )
+                        .boxed(),
+                    };
+                    self.next = State::Next(fetch);
+                    self.current = u;
+                }
+                Err(e) => {
+                    self.next = State::Failed;
+                    return Err(PaginationError::new(e, self.current.clone()));
                 }
             }
         }
@@ -128,33 +746,150 @@ where
     pub fn reported_items(&self) -> Option<u32> {
         self.count
     }
+
+    /// Report records which cannot be parsed individually, rather than
+    /// as errors of the query.
+    ///
+    /// The returned stream yields each record as a `Result<T,
+    /// RecordError>`, with the record as the server sent it attached
+    /// to each which could not be parsed, so that those can be logged
+    /// and skipped. Only failures to read a page are reported as a
+    /// [`PaginationError`].
+    ///
+    /// Example:
+    /// ```rust
+    /// use futures::stream::TryStreamExt;
+    /// # use lava_api_mock::{LavaMock, PaginationLimits, PopulationParams, SharedState};
+    /// use lava_api::Lava;
+    /// #
+    /// # tokio_test::block_on( async {
+    /// # let limits = PaginationLimits::new();
+    /// # let population = PopulationParams::new();
+    /// # let mock = LavaMock::new(SharedState::new_populated(population), limits).await;
+    /// # let service_uri = mock.uri();
+    /// # let lava_token = None;
+    ///
+    /// let lava = Lava::new(&service_uri, lava_token).expect("failed to create Lava object");
+    ///
+    /// let mut workers = lava.workers().lenient();
+    /// while let Some(worker) = workers.try_next().await.expect("failed to read workers") {
+    ///     match worker {
+    ///         Ok(worker) => println!("Worker {}", worker.hostname),
+    ///         Err(e) => println!("Skipping unreadable worker {}", e.value()),
+    ///     }
+    /// }
+    /// # });
+    /// ```
+    pub fn lenient(self) -> LenientPaginator<T> {
+        LenientPaginator { paginator: self }
+    }
+
+    /// A new paginator for the same query, starting after the first
+    /// `read` items this one has yielded.
+    ///
+    /// The new paginator starts by reading the current page again, from
+    /// the first item not read; when this paginator has failed to read
+    /// a page, that is the page which failed. The progress counts carry
+    /// over to the new paginator.
+    pub(crate) fn resume(&self, read: u32) -> Self {
+        let taken = match self.next {
+            State::Data(_) => read.saturating_sub(self.page_start),
+            _ => 0,
+        };
+        let mut url = self.current.clone();
+        if taken > 0 {
+            let offset = url
+                .query_pairs()
+                .find(|(k, _)| k == "offset")
+                .and_then(|(_, v)| v.parse::<u32>().ok())
+                .unwrap_or_default();
+            let pairs = url
+                .query_pairs()
+                .filter(|(k, _)| k != "offset")
+                .map(|(k, v)| (k.into_owned(), v.into_owned()))
+                .collect::<Vec<_>>();
+            url.query_pairs_mut()
+                .clear()
+                .extend_pairs(pairs)
+                .append_pair("offset", &(offset + taken).to_string());
+        }
+
+        let mut paginator = Self::with_transport(
+            self.transport.clone(),
+            url,
+            self.retry.clone(),
+            self.cache.clone(),
+        );
+        // Restarting within the current page leaves the keyset where
+        // it was: the url still filters from the previous page.
+        paginator.keyset = self.keyset.as_ref().map(|k| Keyset {
+            param: k.param,
+            key: k.key,
+            last: k.last.clone(),
+            skipped: k.skipped,
+        });
+        paginator.prefetch = self.prefetch;
+        paginator.count = self.count;
+        paginator.yielded = read;
+        paginator.page_start = read;
+        paginator.pages = self.pages;
+        paginator
+    }
+
+    /// The items of the current page which have not yet been
+    /// yielded.
+    pub(crate) fn buffered_items(&self) -> impl Iterator<Item = &T> {
+        match &self.next {
+            State::Data(d) => Some(d.results.iter().filter_map(|r| r.0.as_ref().ok())),
+            _ => None,
+        }
+        .into_iter()
+        .flatten()
+    }
 }
 
-impl<T> Stream for Paginator<T>
+impl<T> PaginationProgress for Paginator<T> {
+    fn reported_items(&self) -> Option<u32> {
+        self.count
+    }
+
+    fn yielded_items(&self) -> u32 {
+        self.yielded
+    }
+
+    fn fetched_pages(&self) -> u32 {
+        self.pages
+    }
+}
+
+impl<T> Paginator<T>
 where
     T: DeserializeOwned + Unpin + 'static,
 {
-    type Item = Result<T, PaginationError>;
-
-    fn poll_next(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Option<Self::Item>> {
-        let me = self.get_mut();
-        if let Some(data) = me.next_data()? {
+    fn poll_record(
+        &mut self,
+        cx: &mut Context,
+    ) -> Poll<Option<Result<Result<T, RecordError>, PaginationError>>> {
+        #[cfg(feature = "tracing")]
+        let _span = self.span.clone().entered();
+        self.drive_prefetch(cx);
+        if let Some(data) = self.next_data()? {
+            self.yielded += 1;
             return Poll::Ready(Some(Ok(data)));
         }
 
-        if let State::Next(n) = &mut me.next {
+        if let State::Next(n) = &mut self.next {
             match n.as_mut().poll(cx) {
                 Poll::Ready(r) => {
-                    match r {
-                        Ok(r) => me.next = State::Data(r),
-                        Err(e) => {
-                            me.next = State::Next(
-                                Self::get(me.client.clone(), me.current.clone()).boxed(),
-                            );
-                            return Poll::Ready(Some(Err(e)));
-                        }
+                    if let Err(e) = self.page_arrived(r) {
+                        return Poll::Ready(Some(Err(e)));
+                    }
+                    self.drive_prefetch(cx);
+                    let data = self.next_data().transpose();
+                    if let Some(Ok(_)) = data {
+                        self.yielded += 1;
                     }
-                    Poll::Ready(me.next_data().transpose())
+                    Poll::Ready(data)
                 }
                 _ => Poll::Pending,
             }
@@ -163,3 +898,519 @@ where
         }
     }
 }
+
+impl<T> Stream for Paginator<T>
+where
+    T: DeserializeOwned + Unpin + 'static,
+{
+    type Item = Result<T, PaginationError>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Option<Self::Item>> {
+        let me = self.get_mut();
+        me.poll_record(cx).map(|r| {
+            r.map(|r| r.and_then(|r| r.map_err(|e| PaginationError::new(e, me.current.clone()))))
+        })
+    }
+}
+
+/// A [`Paginator`] reporting records which cannot be parsed
+/// individually
+///
+/// This is created by [`Paginator::lenient`]; see there for details.
+pub struct LenientPaginator<T> {
+    paginator: Paginator<T>,
+}
+
+impl<T> PaginationProgress for LenientPaginator<T> {
+    fn reported_items(&self) -> Option<u32> {
+        self.paginator.count
+    }
+
+    fn yielded_items(&self) -> u32 {
+        self.paginator.yielded
+    }
+
+    fn fetched_pages(&self) -> u32 {
+        self.paginator.pages
+    }
+}
+
+impl<T> Stream for LenientPaginator<T>
+where
+    T: DeserializeOwned + Unpin + 'static,
+{
+    type Item = Result<Result<T, RecordError>, PaginationError>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Option<Self::Item>> {
+        self.get_mut().paginator.poll_record(cx)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{PageCache, PaginationErrorKind, PaginationProgress};
+    use crate::error::{Classify, ErrorClass};
+    use crate::transport::{HttpTransport, Transport};
+    use crate::Lava;
+
+    use bytes::Bytes;
+    use reqwest::header::HeaderValue;
+    use reqwest::redirect::Policy;
+    use reqwest::{Request, Response};
+    use url::Url;
+
+    use futures::future::BoxFuture;
+    use futures::{poll, StreamExt, TryStreamExt};
+    use serde_json::json;
+    use std::sync::atomic::{AtomicUsize, Ordering as AtomicOrdering};
+    use std::sync::Arc;
+    use std::task::Poll;
+    use std::time::Duration;
+    use test_log::test;
+    use wiremock::matchers::{header, method, path, query_param};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    // A transport sending requests over HTTP, counting those sent
+    // and those whose replies are still awaited.
+    #[derive(Debug)]
+    struct Counting {
+        inner: HttpTransport,
+        sent: Arc<AtomicUsize>,
+        in_flight: Arc<AtomicUsize>,
+    }
+
+    // Marks a request as no longer in flight when dropped, whether it
+    // was answered or abandoned.
+    struct InFlight(Arc<AtomicUsize>);
+
+    impl Drop for InFlight {
+        fn drop(&mut self) {
+            self.0.fetch_sub(1, AtomicOrdering::SeqCst);
+        }
+    }
+
+    impl Transport for Counting {
+        fn execute(&self, request: Request) -> BoxFuture<'static, reqwest::Result<Response>> {
+            self.sent.fetch_add(1, AtomicOrdering::SeqCst);
+            self.in_flight.fetch_add(1, AtomicOrdering::SeqCst);
+            let in_flight = InFlight(self.in_flight.clone());
+            let response = self.inner.execute(request);
+            Box::pin(async move {
+                let _in_flight = in_flight;
+                response.await
+            })
+        }
+    }
+
+    /// Drop streams and the [`Lava`] while slow requests for workers
+    /// and jobs are still in flight, checking that each request is
+    /// abandoned along with the stream which made it.
+    #[test(tokio::test)]
+    async fn test_drop_in_flight() {
+        let server = MockServer::start().await;
+        for endpoint in ["workers", "jobs"] {
+            Mock::given(method("GET"))
+                .and(path(format!("/api/v0.2/{}/", endpoint)))
+                .respond_with(
+                    ResponseTemplate::new(200)
+                        .set_body_json(json!({"count": 0, "next": null, "results": []}))
+                        .set_delay(Duration::from_secs(60)),
+                )
+                .mount(&server)
+                .await;
+        }
+
+        let sent = Arc::new(AtomicUsize::new(0));
+        let in_flight = Arc::new(AtomicUsize::new(0));
+        let client = reqwest::Client::builder()
+            .redirect(Policy::none())
+            .build()
+            .expect("failed to make client");
+        let lava = Lava::builder(&server.uri())
+            .transport(Counting {
+                inner: HttpTransport::new(client),
+                sent: sent.clone(),
+                in_flight: in_flight.clone(),
+            })
+            .build()
+            .expect("failed to make lava server");
+
+        let mut workers = lava.workers();
+        assert!(matches!(poll!(workers.next()), Poll::Pending));
+
+        let mut jobs = lava.jobs().query();
+        assert!(matches!(poll!(jobs.next()), Poll::Pending));
+        assert_eq!(sent.load(AtomicOrdering::SeqCst), 2);
+        assert_eq!(in_flight.load(AtomicOrdering::SeqCst), 2);
+        drop(jobs);
+        assert_eq!(in_flight.load(AtomicOrdering::SeqCst), 1);
+
+        // The paginator does not borrow the Lava, and its request
+        // carries on without it
+        drop(lava);
+        assert!(matches!(poll!(workers.next()), Poll::Pending));
+        assert_eq!(in_flight.load(AtomicOrdering::SeqCst), 1);
+        drop(workers);
+        assert_eq!(in_flight.load(AtomicOrdering::SeqCst), 0);
+        assert_eq!(sent.load(AtomicOrdering::SeqCst), 2);
+    }
+
+    fn worker(hostname: &str) -> serde_json::Value {
+        json!({
+            "hostname": hostname,
+            "state": "Online",
+            "health": "Active",
+            "job_limit": 0,
+        })
+    }
+
+    #[test(tokio::test)]
+    async fn test_progress() {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/api/v0.2/workers/"))
+            .and(query_param("offset", "2"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "count": 3,
+                "next": null,
+                "results": [worker("c")],
+            })))
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/api/v0.2/workers/"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "count": 3,
+                "next": format!("{}/api/v0.2/workers/?offset=2", server.uri()),
+                "results": [worker("a"), worker("b")],
+            })))
+            .mount(&server)
+            .await;
+
+        let lava = Lava::new(&server.uri(), None).expect("failed to make lava server");
+
+        let mut workers = lava.workers();
+        assert_eq!(PaginationProgress::reported_items(&workers), None);
+        assert_eq!(workers.fetched_pages(), 0);
+
+        workers.try_next().await.expect("failed to get worker");
+        assert_eq!(PaginationProgress::reported_items(&workers), Some(3));
+        assert_eq!(workers.yielded_items(), 1);
+        assert_eq!(workers.fetched_pages(), 1);
+
+        workers.try_next().await.expect("failed to get worker");
+        workers.try_next().await.expect("failed to get worker");
+        assert_eq!(workers.yielded_items(), 3);
+        assert_eq!(workers.fetched_pages(), 2);
+
+        assert!(workers
+            .try_next()
+            .await
+            .expect("failed to end workers")
+            .is_none());
+        assert_eq!(workers.yielded_items(), 3);
+    }
+
+    #[test(tokio::test)]
+    async fn test_error_and_resume() {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/api/v0.2/workers/"))
+            .and(query_param("offset", "2"))
+            .respond_with(ResponseTemplate::new(503))
+            .up_to_n_times(1)
+            .with_priority(1)
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/api/v0.2/workers/"))
+            .and(query_param("offset", "2"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "count": 3,
+                "next": null,
+                "results": [worker("c")],
+            })))
+            .with_priority(2)
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/api/v0.2/workers/"))
+            .and(query_param("offset", "1"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "count": 3,
+                "next": format!("{}/api/v0.2/workers/?offset=2", server.uri()),
+                "results": [worker("b")],
+            })))
+            .with_priority(2)
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/api/v0.2/workers/"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "count": 3,
+                "next": format!("{}/api/v0.2/workers/?offset=2", server.uri()),
+                "results": [worker("a"), worker("b")],
+            })))
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/api/v0.2/devicetypes/"))
+            .respond_with(ResponseTemplate::new(404))
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/api/v0.2/devices/"))
+            .respond_with(ResponseTemplate::new(401))
+            .mount(&server)
+            .await;
+
+        let lava = Lava::new(&server.uri(), None).expect("failed to make lava server");
+
+        // Restart part way through the first page
+        let mut workers = lava.workers();
+        let a = workers.try_next().await.expect("failed to get worker");
+        assert_eq!(a.map(|w| w.hostname), Some("a".to_string()));
+        let mut workers = workers.resume(workers.yielded_items());
+        assert_eq!(workers.yielded_items(), 1);
+        let b = workers.try_next().await.expect("failed to get worker");
+        assert_eq!(b.map(|w| w.hostname), Some("b".to_string()));
+
+        let err = workers.try_next().await.expect_err("read unavailable page");
+        assert_eq!(err.status(), Some(reqwest::StatusCode::SERVICE_UNAVAILABLE));
+        assert_eq!(err.offset(), Some(2));
+        assert_eq!(err.url().map(|u| u.path()), Some("/api/v0.2/workers/"));
+        assert!(err.is_retryable());
+        assert_eq!(err.class(), ErrorClass::Server);
+
+        // Restart from the page which failed
+        let workers = workers
+            .resume(workers.yielded_items())
+            .map_ok(|w| w.hostname)
+            .try_collect::<Vec<_>>()
+            .await
+            .expect("failed to get workers");
+        assert_eq!(workers, vec!["c"]);
+
+        let err = lava
+            .device_types()
+            .try_next()
+            .await
+            .expect_err("read missing page");
+        assert_eq!(err.status(), Some(reqwest::StatusCode::NOT_FOUND));
+        assert_eq!(err.offset(), None);
+        assert!(!err.is_retryable());
+        assert_eq!(err.class(), ErrorClass::NotFound);
+
+        let err = lava
+            .devices()
+            .try_next()
+            .await
+            .expect_err("read page without authorization");
+        assert_eq!(err.class(), ErrorClass::Auth);
+    }
+
+    #[test(tokio::test)]
+    async fn test_page_cache() {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/api/v0.2/workers/"))
+            .and(header("If-None-Match", "\"v1\""))
+            .respond_with(ResponseTemplate::new(304))
+            .with_priority(1)
+            .expect(2)
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/api/v0.2/workers/"))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .insert_header("ETag", "\"v1\"")
+                    .set_body_json(json!({
+                        "count": 2,
+                        "next": null,
+                        "results": [worker("a"), worker("b")],
+                    })),
+            )
+            .expect(1)
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/api/v0.2/devicetypes/"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "count": 0,
+                "next": null,
+                "results": [],
+            })))
+            .mount(&server)
+            .await;
+
+        let lava = Lava::builder(&server.uri())
+            .page_cache(4)
+            .build()
+            .expect("failed to make lava server");
+        assert_eq!(lava.page_cache_stats().and_then(|s| s.hit_rate()), None);
+
+        for _ in 0..3 {
+            let workers = lava
+                .workers()
+                .map_ok(|w| w.hostname)
+                .try_collect::<Vec<_>>()
+                .await
+                .expect("failed to get workers");
+            assert_eq!(workers, vec!["a", "b"]);
+        }
+
+        // Pages without validators are not kept
+        lava.device_types()
+            .try_collect::<Vec<_>>()
+            .await
+            .expect("failed to get device types");
+
+        let stats = lava.page_cache_stats().expect("no page cache");
+        assert_eq!(stats.hits, 2);
+        assert_eq!(stats.misses, 2);
+        assert_eq!(stats.pages, 1);
+        assert_eq!(stats.hit_rate(), Some(0.5));
+
+        let uncached = Lava::new(&server.uri(), None).expect("failed to make lava server");
+        assert!(uncached.page_cache_stats().is_none());
+    }
+
+    #[test(tokio::test)]
+    async fn test_prefetch() {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/api/v0.2/workers/"))
+            .and(query_param("offset", "3"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "count": 4,
+                "next": null,
+                "results": [worker("d")],
+            })))
+            .expect(1)
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/api/v0.2/workers/"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "count": 4,
+                "next": format!("{}/api/v0.2/workers/?offset=3", server.uri()),
+                "results": [worker("a"), worker("b"), worker("c")],
+            })))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let lava = Lava::builder(&server.uri())
+            .prefetch(1)
+            .build()
+            .expect("failed to make lava server");
+
+        let requested = || async {
+            server
+                .received_requests()
+                .await
+                .unwrap()
+                .iter()
+                .filter(|r| r.url.query() == Some("offset=3"))
+                .count()
+        };
+
+        let mut workers = lava.workers();
+        workers.try_next().await.expect("failed to get worker");
+        workers.try_next().await.expect("failed to get worker");
+        assert_eq!(requested().await, 0);
+
+        // Reading the last item of the page starts the request for the next
+        let c = workers.try_next().await.expect("failed to get worker");
+        assert_eq!(c.map(|w| w.hostname), Some("c".to_string()));
+        for _ in 0..100 {
+            if requested().await > 0 {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        assert_eq!(requested().await, 1);
+        assert_eq!(workers.fetched_pages(), 1);
+
+        let rest = workers
+            .map_ok(|w| w.hostname)
+            .try_collect::<Vec<_>>()
+            .await
+            .expect("failed to get workers");
+        assert_eq!(rest, vec!["d"]);
+    }
+
+    #[test(tokio::test)]
+    async fn test_lenient() {
+        let server = MockServer::start().await;
+        let mut broken = worker("b");
+        broken["state"] = json!("Exploded");
+        Mock::given(method("GET"))
+            .and(path("/api/v0.2/workers/"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "count": 3,
+                "next": null,
+                "results": [worker("a"), broken, worker("c")],
+            })))
+            .mount(&server)
+            .await;
+
+        let lava = Lava::new(&server.uri(), None).expect("failed to make lava server");
+
+        let workers = lava
+            .workers()
+            .lenient()
+            .try_collect::<Vec<_>>()
+            .await
+            .expect("failed to get workers");
+        assert_eq!(workers.len(), 3);
+        assert_eq!(workers[0].as_ref().unwrap().hostname, "a");
+        let err = workers[1].as_ref().expect_err("parsed broken worker");
+        assert_eq!(err.value()["hostname"], "b");
+        assert_eq!(workers[2].as_ref().unwrap().hostname, "c");
+
+        // Without leniency the record is an error, after which the
+        // rest of the page can still be read
+        let mut workers = lava.workers();
+        workers.try_next().await.expect("failed to get worker");
+        let err = workers.try_next().await.expect_err("parsed broken worker");
+        assert!(matches!(err.kind(), PaginationErrorKind::InvalidRecord(_)));
+        assert!(!err.is_retryable());
+        assert_eq!(err.class(), ErrorClass::Decode);
+        let c = workers.try_next().await.expect("failed to get worker");
+        assert_eq!(c.map(|w| w.hostname), Some("c".to_string()));
+        assert_eq!(workers.yielded_items(), 3);
+    }
+
+    #[test]
+    fn test_page_cache_eviction() {
+        let cache = PageCache::new(2);
+        let url = |n: u32| Url::parse(&format!("https://lava.example.com/?offset={}", n)).unwrap();
+        let etag = Some(HeaderValue::from_static("\"x\""));
+
+        cache.insert(url(0), etag.clone(), None, Bytes::from_static(b"0"));
+        cache.insert(url(1), etag.clone(), None, Bytes::from_static(b"1"));
+        assert!(cache.get(&url(0)).is_some());
+        cache.insert(url(2), etag.clone(), None, Bytes::from_static(b"2"));
+
+        // The page least recently used is discarded
+        assert!(cache.get(&url(1)).is_none());
+        assert_eq!(
+            cache.get(&url(0)).map(|p| p.body),
+            Some(Bytes::from_static(b"0"))
+        );
+        assert_eq!(
+            cache.get(&url(2)).map(|p| p.body),
+            Some(Bytes::from_static(b"2"))
+        );
+        assert_eq!(cache.stats().pages, 2);
+
+        cache.clear();
+        assert_eq!(cache.stats().pages, 0);
+
+        let disabled = PageCache::new(0);
+        disabled.insert(url(0), etag, None, Bytes::from_static(b"0"));
+        assert_eq!(disabled.stats().pages, 0);
+    }
+}
@@ -0,0 +1,242 @@
+//! Track progress through streams, to resume after errors
+//!
+//! The streams in this crate make many requests to the server over
+//! their lifetime, any of which can fail. When that happens, the
+//! stream cannot continue from where it left off, and callers must
+//! create a new one. A [`Tracked`] stream records how far it got, so
+//! that the new stream can skip the items already seen: for example
+//! with [`JobsBuilder::id_after`](crate::job::JobsBuilder::id_after)
+//! on the last job id seen, or with
+//! [`JobLogBuilder::start`](crate::joblog::JobLogBuilder::start) on
+//! the number of log entries read.
+//!
+//! Example:
+//! ```rust
+//! use futures::stream::TryStreamExt;
+//! # use lava_api_mock::{LavaMock, PaginationLimits, PopulationParams, SharedState};
+//! use lava_api::{Lava, progress::TrackExt};
+//! #
+//! # tokio_test::block_on( async {
+//! # let limits = PaginationLimits::new();
+//! # let population = PopulationParams::new();
+//! # let mock = LavaMock::new(SharedState::new_populated(population), limits).await;
+//! # let service_uri = mock.uri();
+//! # let lava_token = None;
+//!
+//! let lava = Lava::new(&service_uri, lava_token).expect("failed to make lava");
+//!
+//! let mut jobs = lava.jobs().query().track(|job| job.id);
+//! while let Some(job) = jobs.try_next().await.unwrap_or(None) {
+//!     println!("Got job {:?}", job);
+//! }
+//!
+//! if jobs.progress().failed() {
+//!     println!(
+//!         "Failed after {} jobs; resume after job {:?}",
+//!         jobs.progress().items(),
+//!         jobs.progress().last()
+//!     );
+//! }
+//! # });
+//! ```
+
+use futures::stream::{FusedStream, Stream, TryStream};
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+use crate::paginator::PaginationProgress;
+
+/// How far a [`Tracked`] stream has progressed.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Progress<K> {
+    items: u64,
+    last: Option<K>,
+    failed: bool,
+}
+
+impl<K> Progress<K> {
+    fn new() -> Self {
+        Self {
+            items: 0,
+            last: None,
+            failed: false,
+        }
+    }
+
+    /// The number of items successfully yielded so far.
+    pub fn items(&self) -> u64 {
+        self.items
+    }
+
+    /// The key of the last item successfully yielded, if any.
+    pub fn last(&self) -> Option<&K> {
+        self.last.as_ref()
+    }
+
+    /// Whether the stream has yielded an error.
+    ///
+    /// The error itself is passed on to the consumer of the stream.
+    pub fn failed(&self) -> bool {
+        self.failed
+    }
+}
+
+/// A stream adapter recording the [`Progress`] of the stream it wraps.
+///
+/// Each item is passed through unchanged. The key of each successful
+/// item, as computed by the function given at construction, is
+/// recorded, along with a count of the successful items. By default
+/// the stream ends after yielding the first error, since the streams
+/// in this crate cannot recover from them; use
+/// [`continue_on_error`](Tracked::continue_on_error) to change this.
+///
+/// These are usually created using [`TrackExt::track`].
+pub struct Tracked<S, F, K> {
+    inner: S,
+    key: F,
+    progress: Progress<K>,
+    stop_on_error: bool,
+    done: bool,
+}
+
+impl<S, F, K> Tracked<S, F, K>
+where
+    S: TryStream + Unpin,
+    F: FnMut(&S::Ok) -> K,
+{
+    /// Wrap `inner`, using `key` to identify each of its items.
+    pub fn new(inner: S, key: F) -> Self {
+        Self {
+            inner,
+            key,
+            progress: Progress::new(),
+            stop_on_error: true,
+            done: false,
+        }
+    }
+
+    /// Keep polling the wrapped stream after it yields an error.
+    pub fn continue_on_error(mut self) -> Self {
+        self.stop_on_error = false;
+        self
+    }
+
+    /// The progress made so far.
+    pub fn progress(&self) -> &Progress<K> {
+        &self.progress
+    }
+
+    /// Discard the wrapped stream, returning the progress made.
+    pub fn into_progress(self) -> Progress<K> {
+        self.progress
+    }
+}
+
+impl<S, F, K> Stream for Tracked<S, F, K>
+where
+    S: TryStream + Unpin,
+    F: FnMut(&S::Ok) -> K + Unpin,
+    K: Unpin,
+{
+    type Item = Result<S::Ok, S::Error>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Option<Self::Item>> {
+        let me = self.get_mut();
+        if me.done {
+            return Poll::Ready(None);
+        }
+
+        match Pin::new(&mut me.inner).try_poll_next(cx) {
+            Poll::Ready(Some(Ok(item))) => {
+                me.progress.items += 1;
+                me.progress.last = Some((me.key)(&item));
+                Poll::Ready(Some(Ok(item)))
+            }
+            Poll::Ready(Some(Err(e))) => {
+                me.progress.failed = true;
+                me.done = me.stop_on_error;
+                Poll::Ready(Some(Err(e)))
+            }
+            Poll::Ready(None) => {
+                me.done = true;
+                Poll::Ready(None)
+            }
+            Poll::Pending => Poll::Pending,
+        }
+    }
+}
+
+impl<S, F, K> FusedStream for Tracked<S, F, K>
+where
+    S: TryStream + Unpin,
+    F: FnMut(&S::Ok) -> K + Unpin,
+    K: Unpin,
+{
+    fn is_terminated(&self) -> bool {
+        self.done
+    }
+}
+
+impl<S, F, K> PaginationProgress for Tracked<S, F, K>
+where
+    S: PaginationProgress,
+{
+    fn reported_items(&self) -> Option<u32> {
+        self.inner.reported_items()
+    }
+
+    fn yielded_items(&self) -> u32 {
+        self.inner.yielded_items()
+    }
+
+    fn fetched_pages(&self) -> u32 {
+        self.inner.fetched_pages()
+    }
+}
+
+/// An extension trait adding [`track`](TrackExt::track) to streams.
+pub trait TrackExt: TryStream + Unpin + Sized {
+    /// Record the progress of this stream, identifying items by the
+    /// key computed by `key`. See [`Tracked`] for details.
+    fn track<F, K>(self, key: F) -> Tracked<Self, F, K>
+    where
+        F: FnMut(&Self::Ok) -> K,
+    {
+        Tracked::new(self, key)
+    }
+}
+
+impl<S: TryStream + Unpin> TrackExt for S {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use futures::stream::{self, StreamExt, TryStreamExt};
+
+    #[test]
+    fn test_track() {
+        let items = vec![Ok(1), Ok(2), Err("failed"), Ok(3)];
+
+        let mut tracked = stream::iter(items.clone()).track(|i| i * 10);
+        let seen: Vec<_> = tokio_test::block_on((&mut tracked).collect());
+        assert_eq!(seen, vec![Ok(1), Ok(2), Err("failed")]);
+        assert!(tracked.is_terminated());
+        let progress = tracked.into_progress();
+        assert_eq!(progress.items(), 2);
+        assert_eq!(progress.last(), Some(&20));
+        assert!(progress.failed());
+
+        let mut tracked = stream::iter(items).track(|i| i * 10).continue_on_error();
+        let seen: Vec<_> = tokio_test::block_on((&mut tracked).collect());
+        assert_eq!(seen.len(), 4);
+        assert_eq!(tracked.progress().items(), 3);
+        assert_eq!(tracked.progress().last(), Some(&30));
+        assert!(tracked.progress().failed());
+
+        let mut tracked = stream::iter(vec![Ok::<_, ()>("a")]).track(|s| s.len());
+        assert_eq!(tokio_test::block_on(tracked.try_next()), Ok(Some("a")));
+        assert_eq!(tokio_test::block_on(tracked.try_next()), Ok(None));
+        assert!(!tracked.progress().failed());
+    }
+}
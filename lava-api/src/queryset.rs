@@ -2,6 +2,8 @@ use std::collections::HashSet;
 use std::fmt::Display;
 use std::hash::Hash;
 
+use url::Url;
+
 /// Implement `QuerySetMember` to include a simple enum into a
 /// QuerySet. This trait is necessary so that the query set can have
 /// some way of knowing what the full set of values in the enum is.
@@ -26,14 +28,14 @@ pub struct QuerySet<Q: QuerySetMember> {
     values: Option<HashSet<Q>>,
     /// This is the remote name to query. It has to be stored here,
     /// because we'll need to mangle it in some cases.
-    field_name: String,
+    field_name: &'static str,
 }
 
 impl<Q: QuerySetMember> QuerySet<Q> {
     /// `field_name` should be the base Django field name,
     /// e.g. "state"; any required variations like "state__in" will be
     /// created from this automatically when `query()` is called.
-    pub fn new(field_name: String) -> Self {
+    pub const fn new(field_name: &'static str) -> Self {
         QuerySet {
             values: None,
             field_name,
@@ -83,7 +85,7 @@ impl<Q: QuerySetMember> QuerySet<Q> {
             match values.len() {
                 0 => Some((format!("{}__in", self.field_name), String::new())),
                 1 => Some((
-                    self.field_name.clone(),
+                    self.field_name.to_string(),
                     values.iter().next().unwrap().to_string(),
                 )),
                 _ if values.len() == Q::all().len() => None,
@@ -102,6 +104,68 @@ impl<Q: QuerySetMember> QuerySet<Q> {
     }
 }
 
+/// A `ValueSet` represents an allowed set of values for a field whose
+/// possible values cannot be listed, such as names and ids. Unlike a
+/// [`QuerySet`], it can only be narrowed by including values, and an
+/// empty set means that every value is acceptable, so that no terms
+/// are added to the filtering for the result set.
+///
+/// Values are kept in the order they were first included, so that
+/// the same calls always give the same query.
+#[derive(Debug, Clone)]
+pub struct ValueSet<T> {
+    values: Vec<T>,
+    /// This is the remote name to query, from which the name of a set
+    /// query is made.
+    field_name: &'static str,
+}
+
+impl<T: PartialEq + Display> ValueSet<T> {
+    /// `field_name` should be the base Django field name,
+    /// e.g. "actual_device__hostname".
+    pub const fn new(field_name: &'static str) -> Self {
+        ValueSet {
+            values: Vec::new(),
+            field_name,
+        }
+    }
+
+    /// Request that a value be included in the result set. Including
+    /// a value more than once has no further effect.
+    pub fn include(&mut self, value: T) -> &mut Self {
+        if !self.values.contains(&value) {
+            self.values.push(value);
+        }
+        self
+    }
+
+    /// Return a key-value pair suitable for inclusion in a URL query
+    /// string, which will match the values requested so far, or
+    /// `None` when no values have been included.
+    pub fn query(&self) -> Option<(String, String)> {
+        match self.values.len() {
+            0 => None,
+            1 => Some((self.field_name.to_string(), self.values[0].to_string())),
+            _ => Some((
+                format!("{}__in", self.field_name),
+                self.values
+                    .iter()
+                    .map(|v| v.to_string())
+                    .collect::<Vec<String>>()
+                    .join(","),
+            )),
+        }
+    }
+
+    /// Add the pair returned by [`query`](ValueSet::query), if any,
+    /// to the query of `url`.
+    pub fn append_to(&self, url: &mut Url) {
+        if let Some((key, value)) = self.query() {
+            url.query_pairs_mut().append_pair(&key, &value);
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -163,20 +227,18 @@ mod tests {
     #[test]
     fn test_query_set() {
         // The default value yields no query
-        let pair = QuerySet::<Test1>::new(String::from("test1")).query();
+        let pair = QuerySet::<Test1>::new("test1").query();
         assert!(pair.is_none());
 
         // An individual item gives a Django single value query
-        let pair = QuerySet::new(String::from("test2"))
-            .include(Test2::State4)
-            .query();
+        let pair = QuerySet::new("test2").include(Test2::State4).query();
         assert!(pair.is_some());
         let (field, value) = pair.unwrap();
         assert_eq!(field, "test2");
         assert_eq!(value, "State4");
 
         // A pair of items gives a set query
-        let pair = QuerySet::new(String::from("test1"))
+        let pair = QuerySet::new("test1")
             .include(Test1::State1)
             .include(Test1::State2)
             .query();
@@ -187,7 +249,7 @@ mod tests {
         assert!(value == "State1,State2" || value == "State2,State1");
 
         // Including all items explicitly takes us back to no query
-        let pair = QuerySet::new(String::from("test1"))
+        let pair = QuerySet::new("test1")
             .include(Test1::State1)
             .include(Test1::State2)
             .include(Test1::State3)
@@ -195,9 +257,7 @@ mod tests {
         assert!(pair.is_none());
 
         // Excluding one item gives us a set query
-        let pair = QuerySet::new(String::from("test1"))
-            .exclude(&Test1::State1)
-            .query();
+        let pair = QuerySet::new("test1").exclude(&Test1::State1).query();
 
         assert!(pair.is_some());
         let (field, value) = pair.unwrap();
@@ -205,7 +265,7 @@ mod tests {
         assert!(value == "State2,State3" || value == "State3,State2");
 
         // Excluding all but one item gives us a single value query
-        let pair = QuerySet::new(String::from("test2"))
+        let pair = QuerySet::new("test2")
             .exclude(&Test2::State1)
             .exclude(&Test2::State2)
             .exclude(&Test2::State4)
@@ -216,7 +276,7 @@ mod tests {
         assert_eq!(value, "State3");
 
         // Excluding all items gives us an empty set query
-        let pair = QuerySet::new(String::from("test1"))
+        let pair = QuerySet::new("test1")
             .exclude(&Test1::State1)
             .exclude(&Test1::State2)
             .exclude(&Test1::State3)
@@ -227,7 +287,7 @@ mod tests {
         assert_eq!(value, "");
 
         // Including and then excluding an item gives us the empty set
-        let pair = QuerySet::new(String::from("test1"))
+        let pair = QuerySet::new("test1")
             .include(Test1::State1)
             .exclude(&Test1::State1)
             .query();
@@ -237,10 +297,44 @@ mod tests {
         assert_eq!(value, "");
 
         // Excluding and then including an item gives us the complete set
-        let pair = QuerySet::new(String::from("test2"))
+        let pair = QuerySet::new("test2")
             .exclude(&Test2::State5)
             .include(Test2::State5)
             .query();
         assert!(pair.is_none());
     }
+
+    #[test]
+    fn test_value_set() {
+        // The default value yields no query
+        assert!(ValueSet::<i64>::new("id").query().is_none());
+
+        // An individual item gives a Django single value query
+        let pair = ValueSet::new("submitter__username")
+            .include("alice".to_string())
+            .query();
+        assert_eq!(
+            pair,
+            Some(("submitter__username".to_string(), "alice".to_string()))
+        );
+
+        // Several items give a set query, in the order included, and
+        // repeating an item changes nothing
+        let pair = ValueSet::new("id")
+            .include(3)
+            .include(1)
+            .include(3)
+            .include(2)
+            .query();
+        assert_eq!(pair, Some(("id__in".to_string(), "3,1,2".to_string())));
+
+        let mut url = Url::parse("http://lava.example.com/api/v0.2/jobs/").unwrap();
+        ValueSet::<u32>::new("tags__id").append_to(&mut url);
+        assert_eq!(url.query(), None);
+        ValueSet::new("tags__id")
+            .include(4)
+            .include(5)
+            .append_to(&mut url);
+        assert_eq!(url.query(), Some("tags__id__in=4%2C5"));
+    }
 }
@@ -0,0 +1,147 @@
+//! Estimate the queue ahead of a job
+
+use serde::Serialize;
+
+use crate::job::State;
+use crate::paginator::PaginationError;
+use crate::Lava;
+
+/// An estimate of the queue ahead of a job, made by
+/// [`queue_estimate`]
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct QueueEstimate {
+    /// The device type the job requests
+    pub device_type: String,
+    /// The priority of the job
+    pub priority: i64,
+    /// The number of queued jobs for the device type with a higher
+    /// priority
+    pub higher_priority: u32,
+    /// The number of queued jobs for the device type with the same
+    /// priority, all of which were submitted earlier
+    pub same_priority: u32,
+}
+
+impl QueueEstimate {
+    /// The number of queued jobs which would be scheduled first.
+    pub fn ahead(&self) -> u32 {
+        self.higher_priority + self.same_priority
+    }
+}
+
+/// Estimate how many queued jobs are ahead of a job submitted now
+/// for `device_type` with the given `priority`.
+///
+/// LAVA schedules the queued jobs for a device type in order of
+/// decreasing priority, and in order of submission for jobs of equal
+/// priority, so a new job waits for every queued job of a higher or
+/// equal priority. The estimate does not account for health checks,
+/// which are scheduled before any other job, for multinode jobs,
+/// which need several devices at once, or for tags restricting the
+/// devices a job can use. It is made from two queries, so it is only
+/// an approximation on a busy server.
+pub async fn queue_estimate(
+    lava: &Lava,
+    device_type: &str,
+    priority: i64,
+) -> Result<QueueEstimate, PaginationError> {
+    let queued = || {
+        lava.jobs()
+            .state(State::Submitted)
+            .requested_device_type(device_type)
+    };
+    let higher_priority = queued()
+        .priority_at_least(priority.saturating_add(1))
+        .count()
+        .await?;
+    let at_least = queued().priority_at_least(priority).count().await?;
+
+    Ok(QueueEstimate {
+        device_type: device_type.to_string(),
+        priority,
+        higher_priority,
+        same_priority: at_least.saturating_sub(higher_priority),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::QueueEstimate;
+    use crate::Lava;
+
+    use boulder::{BuildableWithPersianRug, BuilderWithPersianRug};
+    use lava_api_mock::{
+        DeviceType as MockDeviceType, JobState as MockJobState, LavaMock, PaginationLimits,
+        PopulationParams, SharedState, State,
+    };
+    use persian_rug::Proxy;
+    use test_log::test;
+
+    #[test(tokio::test)]
+    async fn test_queue_estimate() {
+        let mut state = SharedState::new_populated(
+            PopulationParams::builder()
+                .device_types(0usize)
+                .devices(0usize)
+                .jobs(0usize)
+                .build(),
+        );
+        let (a, m) = Proxy::<MockDeviceType<State>>::builder()
+            .name("type-a")
+            .build(state.mutate());
+        let (b, _) = Proxy::<MockDeviceType<State>>::builder()
+            .name("type-b")
+            .build(m);
+        let jobs = [
+            (a, 0, MockJobState::Submitted),
+            (a, 50, MockJobState::Submitted),
+            (a, 50, MockJobState::Submitted),
+            (a, 100, MockJobState::Submitted),
+            (a, 100, MockJobState::Running),
+            (a, 100, MockJobState::Finished),
+            (b, 100, MockJobState::Submitted),
+        ];
+        state.add_jobs(jobs.len(), |i, job| {
+            let (device_type, priority, job_state) = jobs[i];
+            job.requested_device_type = Some(device_type);
+            job.priority = priority;
+            job.state = job_state;
+        });
+        let server = LavaMock::new(state, PaginationLimits::new()).await;
+
+        let lava = Lava::new(&server.uri(), None).expect("failed to make lava server");
+
+        let estimate = lava
+            .queue_estimate("type-a", 50)
+            .await
+            .expect("failed to estimate queue");
+        assert_eq!(
+            estimate,
+            QueueEstimate {
+                device_type: "type-a".to_string(),
+                priority: 50,
+                higher_priority: 1,
+                same_priority: 2,
+            }
+        );
+        assert_eq!(estimate.ahead(), 3);
+
+        let estimate = lava
+            .queue_estimate("type-a", 0)
+            .await
+            .expect("failed to estimate queue");
+        assert_eq!((estimate.higher_priority, estimate.same_priority), (3, 1));
+
+        let estimate = lava
+            .queue_estimate("type-b", 50)
+            .await
+            .expect("failed to estimate queue");
+        assert_eq!((estimate.higher_priority, estimate.same_priority), (1, 0));
+
+        let estimate = lava
+            .queue_estimate("type-c", 50)
+            .await
+            .expect("failed to estimate queue");
+        assert_eq!(estimate.ahead(), 0);
+    }
+}
@@ -0,0 +1,258 @@
+//! Limit the rate of requests to the server
+
+use futures::future::BoxFuture;
+use reqwest::{Request, Response};
+use std::fmt::{self, Debug, Formatter};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::sync::Semaphore;
+use tokio::time::Instant;
+
+use crate::transport::Transport;
+
+// The longest time between the starts of two requests; lower rates
+// are raised to this, so that the time of the next request can
+// always be represented.
+const MAX_INTERVAL: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// How many requests may be made to the server, and how quickly.
+///
+/// The limits are shared by every request made through a
+/// [`Lava`](crate::Lava), including each page of every paginated
+/// stream, each job log request and each retry, so that a client
+/// reading many streams at once still stays within them. Requests
+/// over either limit wait until they can be sent, rather than
+/// failing.
+///
+/// A request is counted as in flight from when it is sent until the
+/// response headers arrive; reading the body of a response is not
+/// limited. Use
+/// [`LavaBuilder::rate_limit`](crate::LavaBuilder::rate_limit) to
+/// set the limits for a [`Lava`](crate::Lava).
+///
+/// Example:
+/// ```rust
+/// use lava_api::ratelimit::RateLimit;
+///
+/// let limit = RateLimit::new().requests_per_second(5.0).max_in_flight(2);
+/// ```
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct RateLimit {
+    requests_per_second: Option<f64>,
+    max_in_flight: Option<usize>,
+}
+
+impl RateLimit {
+    /// Create a new [`RateLimit`]
+    ///
+    /// By default requests are not limited at all.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set the maximum number of requests started each second.
+    ///
+    /// Requests are spaced evenly, rather than sent in bursts.
+    /// Values which are not positive are ignored, and rates below one
+    /// request a day are treated as one request a day.
+    pub fn requests_per_second(mut self, rate: f64) -> Self {
+        self.requests_per_second = (rate > 0.0 && rate.is_finite()).then_some(rate);
+        self
+    }
+
+    /// Set the maximum number of requests waiting on the server at
+    /// once.
+    ///
+    /// Values less than 1 are treated as 1.
+    pub fn max_in_flight(mut self, requests: usize) -> Self {
+        self.max_in_flight = Some(requests.max(1));
+        self
+    }
+
+    // The minimum time between the starts of two requests.
+    fn interval(&self) -> Option<Duration> {
+        self.requests_per_second.map(|rate| {
+            Duration::try_from_secs_f64(1.0 / rate).map_or(MAX_INTERVAL, |i| i.min(MAX_INTERVAL))
+        })
+    }
+
+    pub(crate) fn is_limited(&self) -> bool {
+        self.requests_per_second.is_some() || self.max_in_flight.is_some()
+    }
+}
+
+/// A [`Transport`] holding back requests until they are within a
+/// [`RateLimit`], before passing them on.
+pub(crate) struct RateLimited {
+    inner: Arc<dyn Transport>,
+    interval: Option<Duration>,
+    // The earliest time at which the next request may start
+    next: Arc<Mutex<Instant>>,
+    in_flight: Option<Arc<Semaphore>>,
+}
+
+impl RateLimited {
+    pub(crate) fn new(inner: Arc<dyn Transport>, limit: &RateLimit) -> Self {
+        Self {
+            inner,
+            interval: limit.interval(),
+            next: Arc::new(Mutex::new(Instant::now())),
+            in_flight: limit.max_in_flight.map(|n| Arc::new(Semaphore::new(n))),
+        }
+    }
+}
+
+impl Debug for RateLimited {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.debug_struct("RateLimited")
+            .field("inner", &self.inner)
+            .field("interval", &self.interval)
+            .field(
+                "max_in_flight",
+                &self.in_flight.as_ref().map(|s| s.available_permits()),
+            )
+            .finish_non_exhaustive()
+    }
+}
+
+impl Transport for RateLimited {
+    fn execute(&self, request: Request) -> BoxFuture<'static, reqwest::Result<Response>> {
+        let inner = self.inner.clone();
+        let interval = self.interval;
+        let next = self.next.clone();
+        let in_flight = self.in_flight.clone();
+        Box::pin(async move {
+            // The semaphore is never closed, so acquiring cannot fail
+            let _permit = match in_flight {
+                Some(semaphore) => Some(semaphore.acquire_owned().await.unwrap()),
+                None => None,
+            };
+            if let Some(interval) = interval {
+                // Reserve the next slot, so that concurrent requests
+                // each wait for their own.
+                let start = {
+                    let mut next = next.lock().unwrap();
+                    let start = (*next).max(Instant::now());
+                    *next = start + interval;
+                    start
+                };
+                tokio::time::sleep_until(start).await;
+            }
+            inner.execute(request).await
+        })
+    }
+
+    fn retrying(&self, request: &Request, attempt: u32) {
+        self.inner.retrying(request, attempt);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::RateLimit;
+    use crate::transport::Transport;
+    use crate::Lava;
+
+    use futures::future::{join_all, BoxFuture};
+    use reqwest::{Request, Response};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+    use std::time::{Duration, Instant};
+    use test_log::test;
+
+    // A transport answering every request with an empty page after a
+    // short delay, and recording the most requests it was ever
+    // answering at once.
+    #[derive(Debug, Default)]
+    struct Slow {
+        current: Arc<AtomicUsize>,
+        most: Arc<AtomicUsize>,
+        total: Arc<AtomicUsize>,
+    }
+
+    impl Transport for Slow {
+        fn execute(&self, _request: Request) -> BoxFuture<'static, reqwest::Result<Response>> {
+            let current = self.current.clone();
+            let most = self.most.clone();
+            self.total.fetch_add(1, Ordering::SeqCst);
+            Box::pin(async move {
+                let now = current.fetch_add(1, Ordering::SeqCst) + 1;
+                most.fetch_max(now, Ordering::SeqCst);
+                tokio::time::sleep(Duration::from_millis(50)).await;
+                current.fetch_sub(1, Ordering::SeqCst);
+                let body = r#"{"count": 0, "next": null, "results": []}"#;
+                let response = http::Response::builder().status(200).body(body).unwrap();
+                Ok(response.into())
+            })
+        }
+    }
+
+    async fn read_workers(lava: &Lava, streams: usize) {
+        join_all((0..streams).map(|_| async {
+            futures::TryStreamExt::try_collect::<Vec<_>>(lava.workers())
+                .await
+                .expect("failed to get workers")
+        }))
+        .await;
+    }
+
+    #[test(tokio::test)]
+    async fn test_in_flight() {
+        let transport = Slow::default();
+        let most = transport.most.clone();
+        let lava = Lava::builder("https://lava.example.com/")
+            .rate_limit(RateLimit::new().max_in_flight(2))
+            .transport(transport)
+            .build()
+            .expect("failed to make lava");
+
+        read_workers(&lava, 6).await;
+        assert_eq!(most.load(Ordering::SeqCst), 2);
+
+        let transport = Slow::default();
+        let most = transport.most.clone();
+        let lava = Lava::builder("https://lava.example.com/")
+            .transport(transport)
+            .build()
+            .expect("failed to make lava");
+
+        read_workers(&lava, 6).await;
+        assert_eq!(most.load(Ordering::SeqCst), 6);
+    }
+
+    #[test(tokio::test)]
+    async fn test_rate() {
+        let transport = Slow::default();
+        let total = transport.total.clone();
+        let lava = Lava::builder("https://lava.example.com/")
+            .rate_limit(RateLimit::new().requests_per_second(20.0))
+            .transport(transport)
+            .build()
+            .expect("failed to make lava");
+
+        // Five requests 50ms apart, the last of which takes 50ms
+        let start = Instant::now();
+        read_workers(&lava, 5).await;
+        assert!(start.elapsed() >= Duration::from_millis(250));
+        assert_eq!(total.load(Ordering::SeqCst), 5);
+    }
+
+    #[test]
+    fn test_limits() {
+        assert!(!RateLimit::new().is_limited());
+        assert!(!RateLimit::new().requests_per_second(0.0).is_limited());
+        assert!(!RateLimit::new().requests_per_second(f64::NAN).is_limited());
+        assert!(RateLimit::new().requests_per_second(0.5).is_limited());
+        assert_eq!(
+            RateLimit::new().requests_per_second(4.0).interval(),
+            Some(Duration::from_millis(250))
+        );
+        for rate in [1e-9, f64::MIN_POSITIVE] {
+            assert_eq!(
+                RateLimit::new().requests_per_second(rate).interval(),
+                Some(Duration::from_secs(24 * 60 * 60))
+            );
+        }
+        assert_eq!(RateLimit::new().max_in_flight(0).max_in_flight, Some(1));
+    }
+}
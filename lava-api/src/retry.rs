@@ -0,0 +1,259 @@
+//! Retry requests which fail transiently
+
+use reqwest::{Request, Response, StatusCode};
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hasher};
+use std::time::Duration;
+
+use crate::transport::Transport;
+
+/// When and how often to retry requests to the server.
+///
+/// Requests are retried when they time out, when the connection to
+/// the server fails, or when the server replies with one of a set of
+/// status codes (by default 429, 502, 503 and 504). Between attempts
+/// the client waits for a delay which starts at the initial backoff
+/// and doubles after each attempt, up to the maximum backoff. With
+/// jitter enabled, each delay is instead chosen at random between
+/// half and all of that value, so that many clients do not retry in
+/// step.
+///
+/// Retries apply to each page of a paginated query, and to the
+/// initial request of a job log, so that a long query survives a
+/// brief outage part way through. Use
+/// [`LavaBuilder::retry_policy`](crate::LavaBuilder::retry_policy)
+/// to set the policy for a [`Lava`](crate::Lava).
+///
+/// Example:
+/// ```rust
+/// use lava_api::retry::RetryPolicy;
+/// use std::time::Duration;
+///
+/// let policy = RetryPolicy::new()
+///     .max_attempts(5)
+///     .backoff(Duration::from_millis(200), Duration::from_secs(10));
+/// ```
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RetryPolicy {
+    max_attempts: u32,
+    initial_backoff: Duration,
+    max_backoff: Duration,
+    jitter: bool,
+    statuses: Vec<StatusCode>,
+}
+
+impl RetryPolicy {
+    /// Create a new [`RetryPolicy`]
+    ///
+    /// The default policy makes up to 4 attempts, with a backoff
+    /// starting at 500ms and limited to 30s, with jitter.
+    pub fn new() -> Self {
+        Self {
+            max_attempts: 4,
+            initial_backoff: Duration::from_millis(500),
+            max_backoff: Duration::from_secs(30),
+            jitter: true,
+            statuses: vec![
+                StatusCode::TOO_MANY_REQUESTS,
+                StatusCode::BAD_GATEWAY,
+                StatusCode::SERVICE_UNAVAILABLE,
+                StatusCode::GATEWAY_TIMEOUT,
+            ],
+        }
+    }
+
+    /// Create a [`RetryPolicy`] which never retries.
+    ///
+    /// This is the policy used by [`Lava::new`](crate::Lava::new).
+    pub fn none() -> Self {
+        Self::new().max_attempts(1)
+    }
+
+    /// Set the maximum number of attempts made for each request,
+    /// including the first.
+    ///
+    /// Values less than 1 are treated as 1.
+    pub fn max_attempts(mut self, attempts: u32) -> Self {
+        self.max_attempts = attempts.max(1);
+        self
+    }
+
+    /// Set the delay before the first retry, and the maximum delay
+    /// between any two attempts.
+    pub fn backoff(mut self, initial: Duration, max: Duration) -> Self {
+        self.initial_backoff = initial;
+        self.max_backoff = max;
+        self
+    }
+
+    /// Set whether delays are randomised.
+    pub fn jitter(mut self, jitter: bool) -> Self {
+        self.jitter = jitter;
+        self
+    }
+
+    /// Set the status codes of the replies which are retried.
+    pub fn retry_on<I: IntoIterator<Item = StatusCode>>(mut self, statuses: I) -> Self {
+        self.statuses = statuses.into_iter().collect();
+        self
+    }
+
+    // The delay after the given (1-based) failed attempt.
+    fn delay(&self, attempt: u32) -> Duration {
+        let delay = self
+            .initial_backoff
+            .checked_mul(1u32.checked_shl(attempt - 1).unwrap_or(u32::MAX))
+            .unwrap_or(self.max_backoff)
+            .min(self.max_backoff);
+        if self.jitter {
+            delay.mul_f64(0.5 + random_fraction() / 2.0)
+        } else {
+            delay
+        }
+    }
+
+    fn retry_error(&self, error: &reqwest::Error) -> bool {
+        error.is_timeout() || error.is_connect()
+    }
+
+    fn retry_status(&self, status: StatusCode) -> bool {
+        self.statuses.contains(&status)
+    }
+
+    /// Send `request` using `transport`, sending a copy of it again
+    /// for each retry.
+    ///
+    /// Once the attempts are used up, the last reply or error is
+    /// returned. Requests whose bodies cannot be copied are only
+    /// sent once.
+    pub(crate) async fn send(
+        &self,
+        transport: &dyn Transport,
+        mut request: Request,
+    ) -> reqwest::Result<Response> {
+        let mut attempt = 1;
+        loop {
+            let next = if attempt < self.max_attempts {
+                request.try_clone()
+            } else {
+                None
+            };
+            let result = transport.execute(request).await;
+            let next = match next {
+                Some(next) => next,
+                None => return result,
+            };
+            match &result {
+                Ok(response) if self.retry_status(response.status()) => {}
+                Err(error) if self.retry_error(error) => {}
+                _ => return result,
+            }
+            let delay = self.delay(attempt);
+            log::debug!(
+                "Retrying request (attempt {} of {}) in {:?}",
+                attempt + 1,
+                self.max_attempts,
+                delay
+            );
+            tokio::time::sleep(delay).await;
+            attempt += 1;
+            transport.retrying(&next, attempt);
+            request = next;
+        }
+    }
+}
+
+/// A random number between 0 and 1, for spreading out delays.
+pub(crate) fn random_fraction() -> f64 {
+    // Any source of randomness will do here; the hasher keys are
+    // random for each RandomState.
+    let random = RandomState::new().build_hasher().finish();
+    random as f64 / u64::MAX as f64
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::RetryPolicy;
+    use crate::Lava;
+
+    use futures::TryStreamExt;
+    use serde_json::json;
+    use std::time::Duration;
+    use test_log::test;
+    use wiremock::matchers::{method, path};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    #[test]
+    fn test_delay() {
+        let policy = RetryPolicy::new()
+            .backoff(Duration::from_millis(100), Duration::from_millis(500))
+            .jitter(false);
+        assert_eq!(policy.delay(1), Duration::from_millis(100));
+        assert_eq!(policy.delay(2), Duration::from_millis(200));
+        assert_eq!(policy.delay(3), Duration::from_millis(400));
+        assert_eq!(policy.delay(4), Duration::from_millis(500));
+        assert_eq!(policy.delay(40), Duration::from_millis(500));
+
+        let policy = policy.jitter(true);
+        for attempt in 1..5 {
+            let delay = policy.delay(attempt);
+            let max = policy.clone().jitter(false).delay(attempt);
+            assert!(delay >= max / 2 && delay <= max);
+        }
+
+        assert_eq!(RetryPolicy::none().max_attempts, 1);
+        assert_eq!(RetryPolicy::new().max_attempts(0).max_attempts, 1);
+    }
+
+    #[test(tokio::test)]
+    async fn test_retry() {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/api/v0.2/workers/"))
+            .respond_with(ResponseTemplate::new(503))
+            .up_to_n_times(2)
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/api/v0.2/workers/"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "count": 1,
+                "next": null,
+                "results": [{
+                    "hostname": "worker",
+                    "state": "Online",
+                    "health": "Active",
+                    "job_limit": 0,
+                }],
+            })))
+            .mount(&server)
+            .await;
+
+        // Without retries, the first failure is returned
+        let lava = Lava::new(&server.uri(), None).expect("failed to make lava server");
+        lava.workers()
+            .try_collect::<Vec<_>>()
+            .await
+            .expect_err("succeeded without retrying");
+
+        let policy = RetryPolicy::new()
+            .max_attempts(2)
+            .backoff(Duration::from_millis(1), Duration::from_millis(1));
+        let lava = Lava::builder(&server.uri())
+            .retry_policy(policy)
+            .build()
+            .expect("failed to make lava server");
+        let workers = lava
+            .workers()
+            .try_collect::<Vec<_>>()
+            .await
+            .expect("failed to retry");
+        assert_eq!(workers.len(), 1);
+    }
+}
@@ -0,0 +1,157 @@
+//! Retrieve several kinds of object from the server at once
+
+use chrono::{DateTime, Utc};
+use futures::future::BoxFuture;
+use futures::stream::{self, StreamExt, TryStreamExt};
+use futures::{FutureExt, TryFutureExt};
+use serde::Serialize;
+
+use crate::device::Device;
+use crate::devicetype::DeviceType;
+use crate::paginator::PaginationError;
+use crate::tag::Tag;
+use crate::worker::Worker;
+use crate::Lava;
+
+/// The maximum number of endpoints read concurrently by [`snapshot`]
+const SNAPSHOT_CONCURRENCY: usize = 2;
+
+/// The kinds of object that can be included in a [`Snapshot`]
+#[derive(Copy, Clone, Debug, Hash, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+pub enum EntityKind {
+    Devices,
+    DeviceTypes,
+    Tags,
+    Workers,
+}
+
+/// A collection of objects read from the server
+///
+/// Each field is `None` unless the corresponding [`EntityKind`] was
+/// requested.
+#[derive(Clone, Debug, Serialize)]
+pub struct Snapshot {
+    /// The time at which reading the snapshot began
+    pub taken: DateTime<Utc>,
+    pub devices: Option<Vec<Device>>,
+    pub device_types: Option<Vec<DeviceType>>,
+    pub tags: Option<Vec<Tag>>,
+    pub workers: Option<Vec<Worker>>,
+}
+
+enum Part {
+    Devices(Vec<Device>),
+    DeviceTypes(Vec<DeviceType>),
+    Tags(Vec<Tag>),
+    Workers(Vec<Worker>),
+}
+
+fn fetch(lava: &Lava, kind: EntityKind) -> BoxFuture<'_, Result<Part, PaginationError>> {
+    match kind {
+        EntityKind::Devices => lava.devices().try_collect().map_ok(Part::Devices).boxed(),
+        EntityKind::DeviceTypes => lava
+            .device_types()
+            .try_collect()
+            .map_ok(Part::DeviceTypes)
+            .boxed(),
+        EntityKind::Tags => lava.tags().map_ok(Part::Tags).boxed(),
+        EntityKind::Workers => lava.workers().try_collect().map_ok(Part::Workers).boxed(),
+    }
+}
+
+/// Read all the objects of the given kinds from the server.
+///
+/// The endpoints are read concurrently, with at most two in flight
+/// at once. If reading any endpoint fails, the first error is
+/// returned and the remaining reads are abandoned.
+///
+/// Note that the data is not read atomically: the
+/// [`taken`](Snapshot::taken) time is recorded before any requests
+/// are made, and objects may change while the snapshot is being
+/// read.
+pub async fn snapshot(lava: &Lava, kinds: &[EntityKind]) -> Result<Snapshot, PaginationError> {
+    let mut snapshot = Snapshot {
+        taken: Utc::now(),
+        devices: None,
+        device_types: None,
+        tags: None,
+        workers: None,
+    };
+
+    let mut kinds = kinds.to_vec();
+    kinds.sort();
+    kinds.dedup();
+
+    let mut parts = stream::iter(kinds)
+        .map(|kind| fetch(lava, kind))
+        .buffer_unordered(SNAPSHOT_CONCURRENCY);
+
+    while let Some(part) = parts.try_next().await? {
+        match part {
+            Part::Devices(d) => snapshot.devices = Some(d),
+            Part::DeviceTypes(d) => snapshot.device_types = Some(d),
+            Part::Tags(t) => snapshot.tags = Some(t),
+            Part::Workers(w) => snapshot.workers = Some(w),
+        }
+    }
+
+    Ok(snapshot)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::EntityKind;
+    use crate::Lava;
+
+    use boulder::{Buildable, Builder};
+    use lava_api_mock::{LavaMock, PaginationLimits, PopulationParams, SharedState};
+    use test_log::test;
+
+    #[test(tokio::test)]
+    async fn test_snapshot() {
+        let state = SharedState::new_populated(
+            PopulationParams::builder()
+                .devices(12usize)
+                .device_types(4usize)
+                .tags(7usize)
+                .workers(3usize)
+                .build(),
+        );
+        let server = LavaMock::new(
+            state,
+            PaginationLimits::builder()
+                .devices(Some(5))
+                .device_types(Some(3))
+                .tags(Some(2))
+                .workers(Some(2))
+                .build(),
+        )
+        .await;
+
+        let lava = Lava::new(&server.uri(), None).expect("failed to make lava server");
+
+        let snapshot = lava
+            .snapshot(&[
+                EntityKind::Workers,
+                EntityKind::Devices,
+                EntityKind::Tags,
+                EntityKind::DeviceTypes,
+                EntityKind::Workers,
+            ])
+            .await
+            .expect("failed to take snapshot");
+        assert_eq!(snapshot.devices.map(|d| d.len()), Some(12));
+        assert_eq!(snapshot.device_types.map(|d| d.len()), Some(4));
+        assert_eq!(snapshot.tags.map(|t| t.len()), Some(7));
+        assert_eq!(snapshot.workers.map(|w| w.len()), Some(3));
+
+        let snapshot = lava
+            .snapshot(&[EntityKind::Tags])
+            .await
+            .expect("failed to take snapshot");
+        assert!(snapshot.devices.is_none());
+        assert!(snapshot.device_types.is_none());
+        assert_eq!(snapshot.tags.map(|t| t.len()), Some(7));
+        assert!(snapshot.workers.is_none());
+    }
+}
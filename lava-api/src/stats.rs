@@ -0,0 +1,443 @@
+//! Summarise the activity on a server
+
+use std::collections::HashMap;
+use std::time::Duration;
+
+use chrono::Utc;
+use futures::future;
+use futures::stream::{self, StreamExt, TryStreamExt};
+use serde::Serialize;
+
+use crate::device::{self, Device};
+use crate::job::{self, ReducedJob, State};
+use crate::paginator::PaginationError;
+use crate::worker::{self, Worker};
+use crate::Lava;
+
+/// The number of device types whose jobs are counted at once by
+/// [`queue_depth`]
+pub const QUEUE_DEPTH_CONCURRENCY: usize = 4;
+
+/// The jobs waiting for or running on a device type, as counted by
+/// [`queue_depth`]
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct QueueDepth {
+    /// The device type the jobs request
+    pub device_type: String,
+    /// The number of jobs waiting to be scheduled
+    pub submitted: u32,
+    /// The number of jobs scheduled on a device, but not yet started
+    pub scheduled: u32,
+    /// The number of jobs running
+    pub running: u32,
+}
+
+impl QueueDepth {
+    /// The number of jobs which have not yet finished.
+    pub fn total(&self) -> u32 {
+        self.submitted + self.scheduled + self.running
+    }
+}
+
+// Count the jobs for `device_type` in any of the given states.
+async fn count_jobs(
+    lava: &Lava,
+    device_type: &str,
+    states: &[State],
+) -> Result<u32, PaginationError> {
+    states
+        .iter()
+        .fold(
+            lava.jobs().requested_device_type(device_type),
+            |jobs, state| jobs.state(*state),
+        )
+        .count()
+        .await
+}
+
+async fn device_type_depth(
+    lava: &Lava,
+    device_type: String,
+) -> Result<QueueDepth, PaginationError> {
+    let mut depth = QueueDepth {
+        device_type,
+        submitted: 0,
+        scheduled: 0,
+        running: 0,
+    };
+
+    let total = count_jobs(
+        lava,
+        &depth.device_type,
+        &[State::Submitted, State::Scheduled, State::Running],
+    )
+    .await?;
+    if total > 0 {
+        depth.submitted = count_jobs(lava, &depth.device_type, &[State::Submitted]).await?;
+        depth.scheduled = count_jobs(lava, &depth.device_type, &[State::Scheduled]).await?;
+        depth.running = total.saturating_sub(depth.submitted + depth.scheduled);
+    }
+    Ok(depth)
+}
+
+/// Count the unfinished jobs for each device type on the server.
+///
+/// Rather than reading every job, this asks the server how many jobs
+/// match a filter: one query for the device types, one for the
+/// unfinished jobs of each device type, and two more for each device
+/// type which has any. Jobs which do not request a device type, such
+/// as some multinode sub-jobs, are not counted. The counts are made
+/// from separate queries, so on a busy server they are only
+/// approximately consistent with one another.
+///
+/// The result has an entry for every device type, in the order the
+/// server lists them.
+pub async fn queue_depth(lava: &Lava) -> Result<Vec<QueueDepth>, PaginationError> {
+    let device_types = lava
+        .device_types()
+        .map_ok(|dt| dt.name)
+        .try_collect::<Vec<_>>()
+        .await?;
+
+    stream::iter(device_types)
+        .map(|name| device_type_depth(lava, name))
+        .buffered(QUEUE_DEPTH_CONCURRENCY)
+        .try_collect()
+        .await
+}
+
+/// Whether a device can run jobs, and how its recent health checks
+/// went, as reported by [`device_availability`]
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct DeviceAvailability {
+    pub hostname: String,
+    pub device_type: String,
+    pub worker_host: String,
+    pub health: device::Health,
+    pub state: device::State,
+    /// The state of the device's worker, or `None` if the worker is
+    /// not visible
+    pub worker_state: Option<worker::State>,
+    /// The health of the device's worker, or `None` if the worker is
+    /// not visible
+    pub worker_health: Option<worker::Health>,
+    /// The number of health checks which completed in the window
+    pub health_checks_complete: u32,
+    /// The number of health checks which failed in the window
+    pub health_checks_incomplete: u32,
+    /// The number of health checks which were canceled in the window
+    pub health_checks_canceled: u32,
+    /// The id of the last health check to finish in the window
+    pub last_health_check: Option<i64>,
+    /// The health of the last health check to finish in the window
+    pub last_health_check_health: Option<job::Health>,
+}
+
+impl DeviceAvailability {
+    /// Whether the device can currently be given jobs: it is in good
+    /// health, and its worker is online and active.
+    pub fn is_available(&self) -> bool {
+        self.health == device::Health::Good
+            && self.worker_state == Some(worker::State::Online)
+            && self.worker_health == Some(worker::Health::Active)
+    }
+
+    /// The fraction of health checks in the window which completed,
+    /// not counting those which were canceled.
+    ///
+    /// This is `None` when there were no such health checks.
+    pub fn health_check_pass_rate(&self) -> Option<f64> {
+        let total = self.health_checks_complete + self.health_checks_incomplete;
+        if total > 0 {
+            Some(f64::from(self.health_checks_complete) / f64::from(total))
+        } else {
+            None
+        }
+    }
+}
+
+impl DeviceAvailability {
+    fn new(device: Device, worker: Option<&Worker>) -> Self {
+        DeviceAvailability {
+            worker_state: worker.map(|w| w.state),
+            worker_health: worker.map(|w| w.health),
+            health_checks_complete: 0,
+            health_checks_incomplete: 0,
+            health_checks_canceled: 0,
+            last_health_check: None,
+            last_health_check_health: None,
+            hostname: device.hostname,
+            device_type: device.device_type,
+            worker_host: device.worker_host,
+            health: device.health,
+            state: device.state,
+        }
+    }
+
+    // Count a health check which ran on this device; health checks
+    // must be counted in the order they ended.
+    fn count(&mut self, job: &ReducedJob) {
+        match job.health {
+            job::Health::Complete => self.health_checks_complete += 1,
+            job::Health::Incomplete => self.health_checks_incomplete += 1,
+            job::Health::Canceled => self.health_checks_canceled += 1,
+            job::Health::Unknown => return,
+        }
+        self.last_health_check = Some(job.id);
+        self.last_health_check_health = Some(job.health);
+    }
+}
+
+/// Report the availability of each device on the server, including
+/// the outcome of the health checks which finished on it within the
+/// last `window`.
+///
+/// This makes three queries, run concurrently: one for the devices,
+/// one for the workers, and one for the health checks which finished
+/// within the window on any device. As with the other reports, the
+/// queries are separate, so on a busy server the result is only
+/// approximately consistent.
+///
+/// The result has an entry for every device which is not retired, in
+/// the order the server lists them.
+pub async fn device_availability(
+    lava: &Lava,
+    window: Duration,
+) -> Result<Vec<DeviceAvailability>, PaginationError> {
+    // A window reaching back before any representable time covers
+    // every health check
+    let since = chrono::Duration::from_std(window)
+        .ok()
+        .and_then(|window| Utc::now().checked_sub_signed(window));
+    let mut health_checks = lava
+        .jobs()
+        .health_check(true)
+        .state(job::State::Finished)
+        .ordering(job::Ordering::EndTime, true);
+    if let Some(since) = since {
+        health_checks = health_checks.ended_after(since);
+    }
+    let (devices, workers, health_checks) = future::try_join3(
+        lava.devices_builder()
+            .health_not(device::Health::Retired)
+            .query()
+            .try_collect::<Vec<_>>(),
+        lava.workers().try_collect::<Vec<_>>(),
+        health_checks.query_reduced().try_collect::<Vec<_>>(),
+    )
+    .await?;
+    let workers = workers
+        .into_iter()
+        .map(|w| (w.hostname.clone(), w))
+        .collect::<HashMap<_, _>>();
+
+    let mut availability = devices
+        .into_iter()
+        .map(|device| {
+            let worker = workers.get(&device.worker_host);
+            DeviceAvailability::new(device, worker)
+        })
+        .collect::<Vec<_>>();
+    let by_hostname = availability
+        .iter()
+        .enumerate()
+        .map(|(i, a)| (a.hostname.clone(), i))
+        .collect::<HashMap<_, _>>();
+    for job in health_checks {
+        if let Some(&i) = job.actual_device.as_ref().and_then(|d| by_hostname.get(d)) {
+            availability[i].count(&job);
+        }
+    }
+    Ok(availability)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::QueueDepth;
+    use crate::{device, job, worker, Lava};
+
+    use boulder::{BuildableWithPersianRug, BuilderWithPersianRug};
+    use chrono::Utc;
+    use lava_api_mock::{
+        Device as MockDevice, DeviceHealth as MockDeviceHealth, DeviceType as MockDeviceType,
+        JobHealth as MockJobHealth, JobState as MockJobState, LavaMock, PaginationLimits,
+        PopulationParams, SharedState, State, Worker as MockWorker, WorkerState as MockWorkerState,
+    };
+    use persian_rug::Proxy;
+    use std::time::Duration;
+    use test_log::test;
+
+    #[test(tokio::test)]
+    async fn test_queue_depth() {
+        let mut state = SharedState::new_populated(
+            PopulationParams::builder()
+                .device_types(0usize)
+                .devices(0usize)
+                .jobs(0usize)
+                .build(),
+        );
+        let (a, m) = Proxy::<MockDeviceType<State>>::builder()
+            .name("type-a")
+            .build(state.mutate());
+        let (b, m) = Proxy::<MockDeviceType<State>>::builder()
+            .name("type-b")
+            .build(m);
+        let _ = Proxy::<MockDeviceType<State>>::builder()
+            .name("type-c")
+            .build(m);
+        let jobs = [
+            (Some(a), MockJobState::Submitted),
+            (Some(a), MockJobState::Submitted),
+            (Some(a), MockJobState::Scheduled),
+            (Some(a), MockJobState::Running),
+            (Some(a), MockJobState::Finished),
+            (Some(b), MockJobState::Running),
+            (Some(b), MockJobState::Canceling),
+            (None, MockJobState::Submitted),
+        ];
+        state.add_jobs(jobs.len(), |i, job| {
+            let (device_type, job_state) = jobs[i];
+            job.requested_device_type = device_type;
+            job.state = job_state;
+        });
+        let server = LavaMock::new(state, PaginationLimits::new()).await;
+
+        let lava = Lava::new(&server.uri(), None).expect("failed to make lava server");
+
+        let mut depth = lava.queue_depth().await.expect("failed to count jobs");
+        depth.sort_by(|a, b| a.device_type.cmp(&b.device_type));
+        assert_eq!(
+            depth,
+            vec![
+                QueueDepth {
+                    device_type: "type-a".to_string(),
+                    submitted: 2,
+                    scheduled: 1,
+                    running: 1,
+                },
+                QueueDepth {
+                    device_type: "type-b".to_string(),
+                    submitted: 0,
+                    scheduled: 0,
+                    running: 1,
+                },
+                QueueDepth {
+                    device_type: "type-c".to_string(),
+                    submitted: 0,
+                    scheduled: 0,
+                    running: 0,
+                },
+            ]
+        );
+        assert_eq!(depth[0].total(), 4);
+    }
+
+    #[test(tokio::test)]
+    async fn test_device_availability() {
+        let mut state = SharedState::new_populated(
+            PopulationParams::builder()
+                .device_types(0usize)
+                .devices(0usize)
+                .jobs(0usize)
+                .workers(0usize)
+                .build(),
+        );
+        let (online, m) = Proxy::<MockWorker<State>>::builder()
+            .hostname("worker-online")
+            .build(state.mutate());
+        let (offline, m) = Proxy::<MockWorker<State>>::builder()
+            .hostname("worker-offline")
+            .state(MockWorkerState::Offline)
+            .build(m);
+        let (dt, m) = Proxy::<MockDeviceType<State>>::builder()
+            .name("type-a")
+            .build(m);
+        let (good, m) = Proxy::<MockDevice<State>>::builder()
+            .hostname("device-good")
+            .device_type(dt)
+            .worker_host(online)
+            .health(MockDeviceHealth::Good)
+            .build(m);
+        let (bad, m) = Proxy::<MockDevice<State>>::builder()
+            .hostname("device-bad")
+            .device_type(dt)
+            .worker_host(online)
+            .health(MockDeviceHealth::Bad)
+            .build(m);
+        let (_, m) = Proxy::<MockDevice<State>>::builder()
+            .hostname("device-orphan")
+            .device_type(dt)
+            .worker_host(offline)
+            .health(MockDeviceHealth::Good)
+            .build(m);
+        let _ = Proxy::<MockDevice<State>>::builder()
+            .hostname("device-retired")
+            .device_type(dt)
+            .worker_host(online)
+            .health(MockDeviceHealth::Retired)
+            .build(m);
+
+        let now = Utc::now();
+        let jobs = [
+            (good, true, MockJobHealth::Incomplete, 3),
+            (good, true, MockJobHealth::Complete, 2),
+            (good, true, MockJobHealth::Complete, 1),
+            (good, false, MockJobHealth::Incomplete, 1),
+            // Too long ago to count
+            (good, true, MockJobHealth::Incomplete, 48),
+            (bad, true, MockJobHealth::Canceled, 2),
+            (bad, true, MockJobHealth::Incomplete, 1),
+        ];
+        state.add_jobs(jobs.len(), |i, job| {
+            let (device, health_check, health, hours_ago) = jobs[i];
+            let end = now - chrono::Duration::hours(hours_ago);
+            job.health_check = health_check;
+            job.actual_device = Some(device);
+            job.state = MockJobState::Finished;
+            job.health = health;
+            job.start_time = Some(end - chrono::Duration::minutes(5));
+            job.end_time = Some(end);
+        });
+        let server = LavaMock::new(state, PaginationLimits::new()).await;
+
+        let lava = Lava::new(&server.uri(), None).expect("failed to make lava server");
+
+        let mut availability = lava
+            .device_availability(Duration::from_secs(24 * 60 * 60))
+            .await
+            .expect("failed to report availability");
+        availability.sort_by(|a, b| a.hostname.cmp(&b.hostname));
+        let hostnames = availability
+            .iter()
+            .map(|a| a.hostname.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            hostnames,
+            vec!["device-bad", "device-good", "device-orphan"]
+        );
+
+        let bad = &availability[0];
+        assert!(!bad.is_available());
+        assert_eq!(bad.health, device::Health::Bad);
+        assert_eq!(bad.health_checks_canceled, 1);
+        assert_eq!(bad.health_checks_incomplete, 1);
+        assert_eq!(bad.health_check_pass_rate(), Some(0.0));
+        assert_eq!(bad.last_health_check, Some(6));
+        assert_eq!(bad.last_health_check_health, Some(job::Health::Incomplete));
+
+        let good = &availability[1];
+        assert!(good.is_available());
+        assert_eq!(good.worker_host, "worker-online");
+        assert_eq!(good.health_checks_complete, 2);
+        assert_eq!(good.health_checks_incomplete, 1);
+        assert_eq!(good.health_check_pass_rate(), Some(2.0 / 3.0));
+        assert_eq!(good.last_health_check, Some(2));
+        assert_eq!(good.last_health_check_health, Some(job::Health::Complete));
+
+        let orphan = &availability[2];
+        assert!(!orphan.is_available());
+        assert_eq!(orphan.worker_state, Some(worker::State::Offline));
+        assert_eq!(orphan.health_check_pass_rate(), None);
+        assert_eq!(orphan.last_health_check, None);
+    }
+}
@@ -0,0 +1,291 @@
+//! Submit jobs
+
+use reqwest::StatusCode;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::error::{Classify, ErrorClass};
+use crate::transport;
+use crate::Lava;
+
+#[derive(Error, Debug)]
+pub enum SubmissionError {
+    #[error("Job submission request failed")]
+    Request(#[from] reqwest::Error),
+    #[error("Invalid job: {0}")]
+    InvalidJob(String),
+    #[error("Not permitted to submit job")]
+    PermissionDenied,
+    #[error("Job to resubmit not found")]
+    NotFound,
+    #[error("Unexpected reply to job submission: {0}")]
+    UnexpectedReply(reqwest::StatusCode),
+    #[error("Job submission reply contained no job ids")]
+    NoJobs,
+}
+
+impl Classify for SubmissionError {
+    fn class(&self) -> ErrorClass {
+        match self {
+            SubmissionError::Request(e) => e.class(),
+            SubmissionError::InvalidJob(_) => ErrorClass::Invalid,
+            SubmissionError::PermissionDenied => ErrorClass::Auth,
+            SubmissionError::NotFound => ErrorClass::NotFound,
+            SubmissionError::UnexpectedReply(s) => s.class(),
+            SubmissionError::NoJobs => ErrorClass::Decode,
+        }
+    }
+}
+
+/// The jobs created by a successful submission.
+///
+/// A single job definition creates one job, unless it uses the
+/// multinode protocol, in which case one job is created for each
+/// node of the group.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub enum SubmittedJobs {
+    /// A single job with the given id.
+    Single(i64),
+    /// The ids of each of the jobs in a multinode group, in the
+    /// order reported by the server.
+    MultiNode(Vec<i64>),
+}
+
+impl SubmittedJobs {
+    fn new(mut ids: Vec<i64>) -> Result<Self, SubmissionError> {
+        match ids.len() {
+            0 => Err(SubmissionError::NoJobs),
+            1 => Ok(SubmittedJobs::Single(ids.remove(0))),
+            _ => Ok(SubmittedJobs::MultiNode(ids)),
+        }
+    }
+
+    /// The ids of all the jobs created.
+    pub fn ids(&self) -> &[i64] {
+        match self {
+            SubmittedJobs::Single(id) => std::slice::from_ref(id),
+            SubmittedJobs::MultiNode(ids) => ids,
+        }
+    }
+
+    /// Whether the submission created a multinode group.
+    pub fn is_multinode(&self) -> bool {
+        matches!(self, SubmittedJobs::MultiNode(_))
+    }
+}
+
+#[derive(Debug, Serialize)]
+struct Submission<'a> {
+    definition: &'a str,
+}
+
+#[derive(Debug, Deserialize)]
+struct SubmissionReply {
+    message: String,
+    #[serde(default)]
+    job_ids: Vec<i64>,
+}
+
+async fn submission_reply(res: reqwest::Response) -> Result<SubmittedJobs, SubmissionError> {
+    match res.status() {
+        StatusCode::CREATED => {
+            let reply: SubmissionReply = res.json().await?;
+            SubmittedJobs::new(reply.job_ids)
+        }
+        StatusCode::BAD_REQUEST => {
+            let reply: SubmissionReply = res.json().await?;
+            Err(SubmissionError::InvalidJob(reply.message))
+        }
+        StatusCode::UNAUTHORIZED | StatusCode::FORBIDDEN => Err(SubmissionError::PermissionDenied),
+        StatusCode::NOT_FOUND => Err(SubmissionError::NotFound),
+        s => Err(SubmissionError::UnexpectedReply(s)),
+    }
+}
+
+pub async fn submit_job(lava: &Lava, definition: &str) -> Result<SubmittedJobs, SubmissionError> {
+    let url = lava
+        .base
+        .join("jobs/")
+        .expect("Failed to append to base url");
+    let sub = Submission { definition };
+
+    let post = lava
+        .transport
+        .execute(transport::post_json(url, &sub))
+        .await?;
+    submission_reply(post).await
+}
+
+/// Submit the definition of an existing job again, returning the ids
+/// of the new jobs.
+///
+/// Resubmitting one job of a multinode group resubmits the whole
+/// group.
+pub async fn resubmit_job(lava: &Lava, id: i64) -> Result<SubmittedJobs, SubmissionError> {
+    let mut url = lava.base.clone();
+    url.path_segments_mut()
+        .unwrap()
+        .pop_if_empty()
+        .push("jobs")
+        .push(&id.to_string())
+        .push("resubmit")
+        .push("");
+
+    let res = lava.transport.execute(transport::get(url)).await?;
+    submission_reply(res).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{SubmissionError, SubmittedJobs};
+    use crate::Lava;
+
+    use boulder::{Buildable, BuildableWithPersianRug, Builder, BuilderWithPersianRug};
+    use futures::TryStreamExt;
+    use lava_api_mock::{
+        DeviceType, LavaMock, PaginationLimits, PopulationParams, SharedState, User,
+    };
+    use persian_rug::Proxy;
+    use test_log::test;
+
+    fn add_user(state: &mut SharedState, token: &str) {
+        let _ = Proxy::<User<_>>::builder()
+            .username("fred")
+            .token(Some(token.to_string()))
+            .build(state.mutate());
+    }
+
+    fn add_qemu(state: &mut SharedState) {
+        let _ = Proxy::<DeviceType<_>>::builder()
+            .name("qemu")
+            .build(state.mutate());
+    }
+
+    const SINGLE: &str = r#"
+job_name: single
+device_type: qemu
+visibility: public
+actions: []
+"#;
+
+    const MULTINODE: &str = r#"
+job_name: multinode
+visibility: public
+protocols:
+  lava-multinode:
+    roles:
+      server:
+        device_type: qemu
+        count: 1
+      client:
+        device_type: qemu
+        count: 2
+actions: []
+"#;
+
+    #[test(tokio::test)]
+    async fn test_submit() {
+        let mut state =
+            SharedState::new_populated(PopulationParams::builder().jobs(5usize).build());
+        add_user(&mut state, "token");
+        add_qemu(&mut state);
+        let server = LavaMock::new(state, PaginationLimits::new()).await;
+
+        let lava = Lava::new(&server.uri(), Some("token".to_string()))
+            .expect("failed to make lava server");
+
+        let submitted = lava.submit_job(SINGLE).await.expect("failed to submit");
+        assert!(!submitted.is_multinode());
+        let id = submitted.ids()[0];
+        let job = lava
+            .jobs()
+            .id(id)
+            .query()
+            .try_next()
+            .await
+            .expect("failed to query job")
+            .expect("submitted job not found");
+        assert_eq!(job.definition, SINGLE);
+        assert_eq!(job.state, crate::job::State::Submitted);
+
+        let submitted = lava.submit_job(MULTINODE).await.expect("failed to submit");
+        assert!(submitted.is_multinode());
+        assert_eq!(submitted.ids().len(), 3);
+        assert!(!submitted.ids().contains(&id));
+
+        let err = lava
+            .submit_job("- not a job")
+            .await
+            .expect_err("submitted an invalid job");
+        assert!(matches!(err, SubmissionError::InvalidJob(_)));
+
+        let anonymous = Lava::new(&server.uri(), None).expect("failed to make lava server");
+        let err = anonymous
+            .submit_job(SINGLE)
+            .await
+            .expect_err("submitted without a token");
+        assert!(matches!(err, SubmissionError::PermissionDenied));
+    }
+
+    #[test(tokio::test)]
+    async fn test_resubmit() {
+        let mut state = SharedState::new();
+        add_user(&mut state, "token");
+        add_qemu(&mut state);
+        let server = LavaMock::new(state, PaginationLimits::new()).await;
+
+        let lava = Lava::new(&server.uri(), Some("token".to_string()))
+            .expect("failed to make lava server");
+
+        let original = lava.submit_job(MULTINODE).await.expect("failed to submit");
+        let resubmitted = lava
+            .resubmit_job(original.ids()[1])
+            .await
+            .expect("failed to resubmit");
+        assert!(resubmitted.is_multinode());
+        assert_eq!(resubmitted.ids().len(), 3);
+        for id in resubmitted.ids() {
+            assert!(!original.ids().contains(id));
+        }
+
+        let err = lava
+            .resubmit_job(1000)
+            .await
+            .expect_err("resubmitted a missing job");
+        assert!(matches!(err, SubmissionError::NotFound));
+
+        let anonymous = Lava::new(&server.uri(), None).expect("failed to make lava server");
+        let err = anonymous
+            .resubmit_job(original.ids()[0])
+            .await
+            .expect_err("resubmitted without permission");
+        assert!(matches!(err, SubmissionError::PermissionDenied));
+    }
+
+    #[test]
+    fn test_submitted_jobs() {
+        assert!(matches!(
+            SubmittedJobs::new(Vec::new()),
+            Err(SubmissionError::NoJobs)
+        ));
+        let single = SubmittedJobs::new(vec![4]).unwrap();
+        assert_eq!(single, SubmittedJobs::Single(4));
+        assert_eq!(single.ids(), &[4]);
+        let multi = SubmittedJobs::new(vec![5, 6]).unwrap();
+        assert_eq!(multi.ids(), &[5, 6]);
+        assert!(multi.is_multinode());
+    }
+
+    #[test(tokio::test)]
+    async fn test_error_source() {
+        // Nothing should be listening on port 1
+        let lava = Lava::new("http://127.0.0.1:1/", None).expect("failed to make lava");
+
+        let err = lava
+            .submit_job("")
+            .await
+            .expect_err("submission succeeded with no server");
+        assert_eq!(err.to_string(), "Job submission request failed");
+        assert!(std::error::Error::source(&err).is_some());
+    }
+}
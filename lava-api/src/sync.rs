@@ -0,0 +1,223 @@
+//! Read the jobs submitted since an earlier run
+//!
+//! A [`JobCursor`] records the newest job seen so far, and produces a
+//! [`JobsBuilder`] for the jobs after it, so that a periodic export
+//! need not read the whole history of the server each time. The
+//! cursor can be serialized, to be kept between runs.
+//!
+//! Example:
+//! ```rust
+//! use futures::stream::TryStreamExt;
+//! # use lava_api_mock::{LavaMock, PaginationLimits, PopulationParams, SharedState};
+//! use lava_api::sync::JobCursor;
+//! use lava_api::Lava;
+//! #
+//! # tokio_test::block_on( async {
+//! # let limits = PaginationLimits::new();
+//! # let population = PopulationParams::new();
+//! # let mock = LavaMock::new(SharedState::new_populated(population), limits).await;
+//! # let service_uri = mock.uri();
+//! let lava = Lava::new(&service_uri, None).expect("failed to make lava");
+//!
+//! let mut cursor = JobCursor::by_id();
+//! let mut jobs = cursor.jobs(&lava).query();
+//! while let Some(job) = jobs.try_next().await.expect("failed to query jobs") {
+//!     // Store the job somewhere, then move past it
+//!     cursor.observe(&job);
+//! }
+//! let saved = serde_json::to_string(&cursor).expect("failed to save cursor");
+//!
+//! // On the next run, only newer jobs are read
+//! let cursor: JobCursor = serde_json::from_str(&saved).expect("failed to load cursor");
+//! let jobs: Vec<_> = cursor
+//!     .jobs(&lava)
+//!     .query()
+//!     .try_collect()
+//!     .await
+//!     .expect("failed to query jobs");
+//! assert!(jobs.is_empty());
+//! # });
+//! ```
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::job::{Job, JobsBuilder, Ordering};
+use crate::Lava;
+
+/// Which field of a job a [`JobCursor`] follows
+#[derive(Copy, Clone, Debug, Hash, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CursorKey {
+    /// The job id, which the server assigns in increasing order
+    Id,
+    /// The time the job was submitted
+    SubmitTime,
+}
+
+/// The newest job read so far from a server
+///
+/// The cursor keeps both the highest id and the latest submit time
+/// it has [`observe`](JobCursor::observe)d, but only the one given
+/// by its [`key`](JobCursor::key) selects the jobs to read next.
+///
+/// Following ids is the more reliable choice: ids are never reused,
+/// and the jobs are read with
+/// [`stable_pagination`](JobsBuilder::stable_pagination), so none is
+/// missed while jobs are being submitted. Submit times are compared
+/// strictly, so a job submitted at exactly the time of the newest
+/// job seen is missed, and queries by submit time use offset
+/// pagination.
+///
+/// Either way, only newly submitted jobs are read. Changes to jobs
+/// which have already been read, such as their completion, are not.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct JobCursor {
+    key: CursorKey,
+    last_id: Option<i64>,
+    last_submit_time: Option<DateTime<Utc>>,
+}
+
+impl JobCursor {
+    /// Create a new [`JobCursor`] following `key`, which has seen no
+    /// jobs, so that the first query reads every job.
+    pub fn new(key: CursorKey) -> Self {
+        Self {
+            key,
+            last_id: None,
+            last_submit_time: None,
+        }
+    }
+
+    /// Create a new [`JobCursor`] following job ids.
+    pub fn by_id() -> Self {
+        Self::new(CursorKey::Id)
+    }
+
+    /// Create a new [`JobCursor`] following submit times.
+    pub fn by_submit_time() -> Self {
+        Self::new(CursorKey::SubmitTime)
+    }
+
+    /// The field of a job this cursor follows.
+    pub fn key(&self) -> CursorKey {
+        self.key
+    }
+
+    /// The highest job id seen so far.
+    pub fn last_id(&self) -> Option<i64> {
+        self.last_id
+    }
+
+    /// The latest submit time seen so far.
+    pub fn last_submit_time(&self) -> Option<DateTime<Utc>> {
+        self.last_submit_time
+    }
+
+    /// Record that `job` has been read, moving the cursor past it
+    /// if it is newer than those seen before.
+    ///
+    /// Calling this only once a job has been processed means that a
+    /// run which fails part way through reads the unprocessed jobs
+    /// again next time.
+    pub fn observe(&mut self, job: &Job) {
+        self.last_id = self.last_id.max(Some(job.id));
+        self.last_submit_time = self.last_submit_time.max(Some(job.submit_time));
+    }
+
+    /// Select the jobs after the newest seen so far, oldest first.
+    ///
+    /// The returned builder can be narrowed further before making
+    /// the query, though the ordering should not be changed.
+    pub fn jobs<'a>(&self, lava: &'a Lava) -> JobsBuilder<'a> {
+        let builder = lava.jobs();
+        match self.key {
+            CursorKey::Id => {
+                let builder = builder.ordering(Ordering::Id, true).stable_pagination();
+                match self.last_id {
+                    Some(id) => builder.id_after(id),
+                    None => builder,
+                }
+            }
+            CursorKey::SubmitTime => {
+                let builder = builder.ordering(Ordering::SubmitTime, true);
+                match self.last_submit_time {
+                    Some(when) => builder.submitted_after(when),
+                    None => builder,
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use futures::TryStreamExt;
+    use serde_json::json;
+    use test_log::test;
+    use wiremock::matchers::{method, path, query_param, query_param_is_missing};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    #[test(tokio::test)]
+    async fn test_cursor() {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/api/v0.2/jobs/"))
+            .and(query_param("id__gt", "41"))
+            .and(query_param("ordering", "id"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "count": 0,
+                "next": null,
+                "results": [],
+            })))
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/api/v0.2/jobs/"))
+            .and(query_param("submit_time__gt", "2022-04-11T10:00:00+00:00"))
+            .and(query_param("ordering", "submit_time"))
+            .and(query_param_is_missing("id__gt"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "count": 0,
+                "next": null,
+                "results": [],
+            })))
+            .mount(&server)
+            .await;
+
+        let saved = json!({
+            "key": "id",
+            "last_id": 41,
+            "last_submit_time": "2022-04-11T10:00:00Z",
+        });
+        let mut cursor: JobCursor = serde_json::from_value(saved).expect("failed to load cursor");
+        assert_eq!(cursor.key(), CursorKey::Id);
+        assert_eq!(cursor.last_id(), Some(41));
+
+        let lava = Lava::new(&server.uri(), None).expect("failed to make lava server");
+        let jobs = cursor
+            .jobs(&lava)
+            .query()
+            .try_collect::<Vec<_>>()
+            .await
+            .expect("failed to query jobs by id");
+        assert!(jobs.is_empty());
+
+        cursor.key = CursorKey::SubmitTime;
+        let jobs = cursor
+            .jobs(&lava)
+            .query()
+            .try_collect::<Vec<_>>()
+            .await
+            .expect("failed to query jobs by submit time");
+        assert!(jobs.is_empty());
+
+        let empty = JobCursor::by_submit_time();
+        assert_eq!(empty.last_id(), None);
+        let roundtrip: JobCursor =
+            serde_json::from_str(&serde_json::to_string(&cursor).unwrap()).unwrap();
+        assert_eq!(roundtrip, cursor);
+    }
+}
@@ -0,0 +1,271 @@
+//! Discover the version and capabilities of the server
+//!
+//! Servers from different LAVA releases do not all report the same
+//! fields, or support the same endpoints. The [`ServerVersion`]
+//! reported by a server gives its release, and [`Capabilities`]
+//! checks directly for the optional behaviour this crate can make
+//! use of, so that clients can adapt to the server they are talking
+//! to rather than failing part way through.
+
+use reqwest::StatusCode;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use thiserror::Error;
+
+use crate::error::{Classify, ErrorClass};
+use crate::transport;
+use crate::Lava;
+
+#[derive(Error, Debug)]
+pub enum SystemError {
+    #[error("System request failed")]
+    Request(#[from] reqwest::Error),
+    #[error("Unexpected reply to system request: {0}")]
+    UnexpectedReply(reqwest::StatusCode),
+}
+
+impl Classify for SystemError {
+    fn class(&self) -> ErrorClass {
+        match self {
+            SystemError::Request(e) => e.class(),
+            SystemError::UnexpectedReply(s) => s.class(),
+        }
+    }
+}
+
+/// The version of LAVA a server is running
+///
+/// LAVA versions begin with the year and month of their release,
+/// such as `2023.10`, possibly followed by a suffix for packaging or
+/// development builds.
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq, Eq)]
+pub struct ServerVersion {
+    /// The version exactly as reported by the server
+    pub version: String,
+}
+
+impl ServerVersion {
+    /// The year and month of the release, if the version begins with
+    /// them.
+    ///
+    /// Example:
+    /// ```rust
+    /// use lava_api::system::ServerVersion;
+    ///
+    /// let version = ServerVersion { version: "2023.10.post1".to_string() };
+    /// assert_eq!(version.release(), Some((2023, 10)));
+    /// ```
+    pub fn release(&self) -> Option<(u16, u8)> {
+        let mut parts = self.version.split(|c: char| !c.is_ascii_digit());
+        let year = parts.next()?.parse().ok()?;
+        let month = parts.next()?.parse().ok()?;
+        Some((year, month))
+    }
+
+    /// Whether this is the release of the given year and month, or a
+    /// later one.
+    ///
+    /// Versions which do not begin with a release are not counted as
+    /// being at least any release.
+    pub fn at_least(&self, year: u16, month: u8) -> bool {
+        self.release()
+            .map(|release| release >= (year, month))
+            .unwrap_or(false)
+    }
+}
+
+impl fmt::Display for ServerVersion {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.version)
+    }
+}
+
+/// Retrieve the [`ServerVersion`] of the server, or `None` if it
+/// does not report one.
+pub async fn server_version(lava: &Lava) -> Result<Option<ServerVersion>, SystemError> {
+    let url = lava
+        .base
+        .join("system/version/")
+        .expect("Failed to append to base url");
+
+    let res = lava
+        .retry
+        .send(&*lava.transport, transport::get(url))
+        .await?;
+
+    match res.status() {
+        StatusCode::OK => Ok(Some(res.json().await?)),
+        StatusCode::NOT_FOUND => Ok(None),
+        s => Err(SystemError::UnexpectedReply(s)),
+    }
+}
+
+/// The optional behaviour supported by a server
+///
+/// Behaviour which can only be checked against existing data, such
+/// as the fields reported for jobs, is `None` when there was no data
+/// visible to check against.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct Capabilities {
+    /// The version the server reports, if any
+    pub version: Option<ServerVersion>,
+    /// Whether the server honours the `fields` parameter, and so
+    /// sends less data for
+    /// [`JobsBuilder::query_reduced`](crate::job::JobsBuilder::query_reduced)
+    pub field_selection: Option<bool>,
+    /// Whether the server reports the visibility of jobs; when it
+    /// does not,
+    /// [`Job::visibility`](crate::job::Job#structfield.visibility) is always
+    /// [`Visibility::Unknown`](crate::job::Visibility::Unknown)
+    pub job_visibility: Option<bool>,
+}
+
+#[derive(Deserialize)]
+struct JobsPage {
+    results: Vec<serde_json::Map<String, serde_json::Value>>,
+}
+
+/// Find the [`Capabilities`] of the server.
+///
+/// This asks the server for its version, and for a single job,
+/// selecting only some of its fields, to see which are returned.
+pub async fn capabilities(lava: &Lava) -> Result<Capabilities, SystemError> {
+    let version = server_version(lava).await?;
+
+    let mut url = lava
+        .base
+        .join("jobs/")
+        .expect("Failed to append to base url");
+    url.query_pairs_mut()
+        .append_pair("limit", "1")
+        .append_pair("fields", "id,is_public");
+
+    let res = lava
+        .retry
+        .send(&*lava.transport, transport::get(url))
+        .await?;
+    let job = match res.status() {
+        StatusCode::OK => res.json::<JobsPage>().await?.results.into_iter().next(),
+        s => return Err(SystemError::UnexpectedReply(s)),
+    };
+
+    Ok(Capabilities {
+        version,
+        field_selection: job.as_ref().map(|job| !job.contains_key("description")),
+        job_visibility: job.as_ref().map(|job| job.contains_key("is_public")),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Capabilities, ServerVersion};
+    use crate::Lava;
+
+    use boulder::{Buildable, Builder};
+    use lava_api_mock::{Endpoint, LavaMock, PopulationParams, SharedState};
+    use serde_json::json;
+    use test_log::test;
+    use wiremock::matchers::{method, path};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    #[test]
+    fn test_release() {
+        let version = |v: &str| ServerVersion {
+            version: v.to_string(),
+        };
+        assert_eq!(version("2020.12").release(), Some((2020, 12)));
+        assert_eq!(version("2024.01+bookworm").release(), Some((2024, 1)));
+        assert_eq!(version("2023.10.post1").release(), Some((2023, 10)));
+        assert_eq!(version("git").release(), None);
+        assert_eq!(version("2023").release(), None);
+
+        assert!(version("2023.10").at_least(2023, 10));
+        assert!(version("2024.01").at_least(2023, 10));
+        assert!(!version("2023.06").at_least(2023, 10));
+        assert!(!version("unknown").at_least(2000, 1));
+    }
+
+    #[test(tokio::test)]
+    async fn test_mock() {
+        let state = SharedState::new_populated(PopulationParams::builder().jobs(1usize).build());
+        let server = LavaMock::builder(state.clone())
+            .version("2023.10")
+            .build()
+            .await;
+        let lava = Lava::new(&server.uri(), None).expect("failed to make lava server");
+
+        let version = lava
+            .server_version()
+            .await
+            .expect("failed to get version")
+            .expect("no version reported");
+        assert_eq!(version.to_string(), "2023.10");
+
+        // The mock ignores the fields parameter for jobs
+        assert_eq!(
+            lava.capabilities()
+                .await
+                .expect("failed to get capabilities"),
+            Capabilities {
+                version: Some(version),
+                field_selection: Some(false),
+                job_visibility: Some(true),
+            }
+        );
+
+        let server = LavaMock::builder(SharedState::new())
+            .disable(Endpoint::Version)
+            .build()
+            .await;
+        let lava = Lava::new(&server.uri(), None).expect("failed to make lava server");
+        assert_eq!(
+            lava.server_version().await.expect("failed to get version"),
+            None
+        );
+        assert_eq!(
+            lava.capabilities()
+                .await
+                .expect("failed to get capabilities"),
+            Capabilities {
+                version: None,
+                field_selection: None,
+                job_visibility: None,
+            }
+        );
+    }
+
+    #[test(tokio::test)]
+    async fn test_field_selection() {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/api/v0.2/system/version/"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "version": "2020.12",
+            })))
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/api/v0.2/jobs/"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "count": 1,
+                "next": null,
+                "previous": null,
+                "results": [{"id": 7}],
+            })))
+            .mount(&server)
+            .await;
+
+        let lava = Lava::new(&server.uri(), None).expect("failed to make lava server");
+        let capabilities = lava
+            .capabilities()
+            .await
+            .expect("failed to get capabilities");
+        assert!(capabilities
+            .version
+            .as_ref()
+            .map(|v| !v.at_least(2021, 1))
+            .unwrap());
+        assert_eq!(capabilities.field_selection, Some(true));
+        assert_eq!(capabilities.job_visibility, Some(false));
+    }
+}
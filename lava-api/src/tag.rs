@@ -1,9 +1,20 @@
-//! Retrieve tags
+//! Retrieve and manage tags
 
-use serde::Deserialize;
+use futures::TryStreamExt;
+use reqwest::StatusCode;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use thiserror::Error;
+use url::Url;
+
+use crate::error::{Classify, ErrorClass};
+use crate::paginator::{PaginationError, Paginator};
+use crate::queryset::ValueSet;
+use crate::transport;
+use crate::Lava;
 
 /// Metadata for a tag on the LAVA server
-#[derive(Clone, Deserialize, Debug, PartialEq, Eq)]
+#[derive(Clone, Deserialize, Serialize, Debug, PartialEq, Eq)]
 pub struct Tag {
     /// The unique id of the tag
     pub id: u32,
@@ -13,31 +24,305 @@ pub struct Tag {
     pub description: Option<String>,
 }
 
+/// A tag to filter on, given either by name or by id
+///
+/// This converts from a name, an id, or a [`Tag`], so that filters
+/// such as [`DevicesBuilder::tag`](crate::device::DevicesBuilder::tag)
+/// accept any of them.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum TagRef {
+    /// The tag with this id
+    Id(u32),
+    /// The tag with this name
+    Name(String),
+}
+
+impl From<u32> for TagRef {
+    fn from(id: u32) -> Self {
+        TagRef::Id(id)
+    }
+}
+
+impl From<&str> for TagRef {
+    fn from(name: &str) -> Self {
+        TagRef::Name(name.to_string())
+    }
+}
+
+impl From<String> for TagRef {
+    fn from(name: String) -> Self {
+        TagRef::Name(name)
+    }
+}
+
+impl From<&String> for TagRef {
+    fn from(name: &String) -> Self {
+        TagRef::Name(name.clone())
+    }
+}
+
+impl From<&Tag> for TagRef {
+    fn from(tag: &Tag) -> Self {
+        TagRef::Id(tag.id)
+    }
+}
+
+// The tags a query requires, as the traversals of the `tags`
+// relation of the objects queried.
+#[derive(Clone, Debug)]
+pub(crate) struct TagFilter {
+    // Objects carrying any one of these are returned
+    any: Vec<TagRef>,
+    // Objects must carry every one of these to be returned
+    all: Vec<TagRef>,
+}
+
+impl TagFilter {
+    pub(crate) const fn new() -> Self {
+        Self {
+            any: Vec::new(),
+            all: Vec::new(),
+        }
+    }
+
+    pub(crate) fn any(&mut self, tag: TagRef) {
+        self.any.push(tag);
+    }
+
+    pub(crate) fn all(&mut self, tag: TagRef) {
+        self.all.push(tag);
+    }
+
+    pub(crate) fn append_to(&self, url: &mut Url) {
+        let mut ids = ValueSet::new("tags__id");
+        let mut names = ValueSet::new("tags__name");
+        for tag in self.any.iter() {
+            match tag {
+                TagRef::Id(id) => {
+                    ids.include(*id);
+                }
+                TagRef::Name(name) => {
+                    names.include(name.clone());
+                }
+            }
+        }
+        ids.append_to(url);
+        names.append_to(url);
+
+        // The server only reads the last of a repeated parameter, so
+        // it is given at most one of the tags required together, to
+        // narrow the query; `carried_by` checks the rest.
+        if let Some(tag) = self.all.first() {
+            let (key, value) = match tag {
+                TagRef::Id(id) => ("tags__id", id.to_string()),
+                TagRef::Name(name) => ("tags__name", name.clone()),
+            };
+            if !url.query_pairs().any(|(k, _)| k == key) {
+                url.query_pairs_mut().append_pair(key, &value);
+            }
+        }
+    }
+
+    // Whether an object with `tags` carries every tag required
+    // together.
+    pub(crate) fn carried_by(&self, tags: &[Tag]) -> bool {
+        self.all.iter().all(|required| {
+            tags.iter().any(|tag| match required {
+                TagRef::Id(id) => tag.id == *id,
+                TagRef::Name(name) => &tag.name == name,
+            })
+        })
+    }
+}
+
+#[derive(Error, Debug)]
+pub enum TagError {
+    #[error("Tag request failed")]
+    Request(#[from] reqwest::Error),
+    #[error("Failed to make url for tag request")]
+    ParseUrlError(#[from] url::ParseError),
+    #[error("Invalid tag: {0}")]
+    InvalidTag(String),
+    #[error("Not permitted to change tags")]
+    PermissionDenied,
+    #[error("Tag not found")]
+    NotFound,
+    #[error("Unexpected reply to tag request: {0}")]
+    UnexpectedReply(reqwest::StatusCode),
+}
+
+impl Classify for TagError {
+    fn class(&self) -> ErrorClass {
+        match self {
+            TagError::Request(e) => e.class(),
+            TagError::ParseUrlError(_) => ErrorClass::Other,
+            TagError::InvalidTag(_) => ErrorClass::Invalid,
+            TagError::PermissionDenied => ErrorClass::Auth,
+            TagError::NotFound => ErrorClass::NotFound,
+            TagError::UnexpectedReply(s) => s.class(),
+        }
+    }
+}
+
+/// Retrieve the tags whose name or description contains `text`,
+/// ignoring case.
+///
+/// The filtering is done by the server, but since it can only match
+/// one field at a time, this makes one query for names and another
+/// for descriptions. The tags are returned in order of id, and added
+/// to the tag cache of `lava`.
+pub async fn find_tags(lava: &Lava, text: &str) -> Result<Vec<Tag>, PaginationError> {
+    let mut found = BTreeMap::new();
+    for field in ["name__icontains", "description__icontains"] {
+        let mut url = lava.base.join("tags/")?;
+        url.query_pairs_mut().append_pair(field, text);
+        let mut tags: Paginator<Tag> = lava.paginator(url);
+        while let Some(tag) = tags.try_next().await? {
+            found.insert(tag.id, tag);
+        }
+    }
+
+    let mut cache = lava.tags.write().await;
+    for tag in found.values() {
+        cache.insert(tag.id, tag.clone());
+    }
+    Ok(found.into_values().collect())
+}
+
+#[derive(Serialize)]
+struct NewTag<'a> {
+    name: &'a str,
+    description: Option<&'a str>,
+}
+
+// Render the field errors in a 400 reply, such as
+// `{"name": ["tag with this name already exists."]}`.
+fn invalid_tag(reply: &serde_json::Value) -> TagError {
+    let messages = match reply.as_object() {
+        Some(fields) => fields
+            .iter()
+            .flat_map(|(field, errors)| {
+                let errors = match errors.as_array() {
+                    Some(errors) => errors.clone(),
+                    None => vec![errors.clone()],
+                };
+                errors.into_iter().map(move |e| match e {
+                    serde_json::Value::String(e) => format!("{}: {}", field, e),
+                    e => format!("{}: {}", field, e),
+                })
+            })
+            .collect::<Vec<_>>()
+            .join("; "),
+        None => reply.to_string(),
+    };
+    TagError::InvalidTag(messages)
+}
+
+/// Create a tag with the given name and description, returning the
+/// new [`Tag`].
+///
+/// Creating tags requires a token for a user with permission to add
+/// them, usually an administrator. The new tag is added to the tag
+/// cache of `lava`.
+pub async fn create_tag(
+    lava: &Lava,
+    name: &str,
+    description: Option<&str>,
+) -> Result<Tag, TagError> {
+    let url = lava.base.join("tags/")?;
+    let tag = NewTag { name, description };
+
+    let res = lava
+        .transport
+        .execute(transport::post_json(url, &tag))
+        .await?;
+
+    match res.status() {
+        StatusCode::CREATED => {
+            let tag: Tag = res.json().await?;
+            lava.tags.write().await.insert(tag.id, tag.clone());
+            Ok(tag)
+        }
+        StatusCode::BAD_REQUEST => Err(invalid_tag(&res.json().await?)),
+        StatusCode::UNAUTHORIZED | StatusCode::FORBIDDEN => Err(TagError::PermissionDenied),
+        s => Err(TagError::UnexpectedReply(s)),
+    }
+}
+
+/// Delete the tag with the given id.
+///
+/// As for [`create_tag`], this requires a token for a user with
+/// permission to delete tags. The tag is removed from the tag cache
+/// of `lava`.
+pub async fn delete_tag(lava: &Lava, id: u32) -> Result<(), TagError> {
+    let mut url = lava.base.clone();
+    url.path_segments_mut()
+        .unwrap()
+        .pop_if_empty()
+        .push("tags")
+        .push(&id.to_string())
+        .push("");
+
+    let res = lava.transport.execute(transport::delete(url)).await?;
+
+    match res.status() {
+        StatusCode::OK | StatusCode::NO_CONTENT => {
+            lava.tags.write().await.remove(&id);
+            Ok(())
+        }
+        StatusCode::UNAUTHORIZED | StatusCode::FORBIDDEN => Err(TagError::PermissionDenied),
+        StatusCode::NOT_FOUND => Err(TagError::NotFound),
+        s => Err(TagError::UnexpectedReply(s)),
+    }
+}
+
 #[cfg(test)]
 mod tests {
-    use super::Tag;
+    use super::{Tag, TagError};
     use crate::Lava;
 
-    use boulder::{Buildable, Builder};
+    use boulder::{Buildable, BuildableWithPersianRug, Builder, BuilderWithPersianRug};
     use lava_api_mock::{
-        LavaMock, PaginationLimits, PopulationParams, SharedState, State, Tag as MockTag,
+        LavaMock, PaginationLimits, PopulationParams, SharedState, State, Tag as MockTag, User,
     };
-    use persian_rug::{Accessor, Context};
+    use persian_rug::{Accessor, Proxy};
     use std::collections::BTreeMap;
     use test_log::test;
 
-    impl Tag {
-        pub fn from_mock<'b, B, C>(tag: &MockTag<C>, _context: B) -> Tag
-        where
-            B: 'b + Accessor<Context = C>,
-            C: Context + 'static,
-        {
-            Self {
-                id: tag.id,
-                name: tag.name.clone(),
-                description: tag.description.clone(),
-            }
-        }
+    /// Only one of the tags required together is sent to the server,
+    /// and the rest are checked on the objects it returns
+    #[test]
+    fn test_tag_filter() {
+        use super::{TagFilter, TagRef};
+        use url::Url;
+
+        let tag = |id: u32, name: &str| Tag {
+            id,
+            name: name.to_string(),
+            description: None,
+        };
+
+        let mut filter = TagFilter::new();
+        filter.all(TagRef::from("fast"));
+        filter.all(TagRef::from("usb"));
+        filter.all(TagRef::from(3u32));
+        let mut url = Url::parse("https://lava.example.com/api/v0.2/devices/").unwrap();
+        filter.append_to(&mut url);
+        assert_eq!(url.query(), Some("tags__name=fast"));
+
+        assert!(filter.carried_by(&[tag(1, "fast"), tag(2, "usb"), tag(3, "hdmi")]));
+        assert!(!filter.carried_by(&[tag(1, "fast"), tag(3, "hdmi")]));
+        assert!(!filter.carried_by(&[tag(1, "fast"), tag(2, "usb")]));
+        assert!(TagFilter::new().carried_by(&[]));
+
+        // A parameter already used for tags of which any will do is
+        // left to those
+        let mut filter = TagFilter::new();
+        filter.any(TagRef::from("hdmi"));
+        filter.all(TagRef::from("fast"));
+        let mut url = Url::parse("https://lava.example.com/api/v0.2/devices/").unwrap();
+        filter.append_to(&mut url);
+        assert_eq!(url.query(), Some("tags__name=hdmi"));
     }
 
     /// Stream 49 tags with a page limit of 5 from the server
@@ -73,4 +358,104 @@ mod tests {
         }
         assert_eq!(seen.len(), 49);
     }
+
+    #[test(tokio::test)]
+    async fn test_find_tags() {
+        let mut state = SharedState::new();
+        {
+            let m = state.mutate();
+            let (_, m) = Proxy::<MockTag<State>>::builder()
+                .id(1u32)
+                .name("hdmi")
+                .description(Some("Has an HDMI capture device".to_string()))
+                .build(m);
+            let (_, m) = Proxy::<MockTag<State>>::builder()
+                .id(2u32)
+                .name("usb-otg")
+                .description(Some("USB on-the-go port".to_string()))
+                .build(m);
+            let _ = Proxy::<MockTag<State>>::builder()
+                .id(3u32)
+                .name("hdmi-cec")
+                .description(None::<String>)
+                .build(m);
+        }
+        let server = LavaMock::new(state, PaginationLimits::new()).await;
+
+        let lava = Lava::new(&server.uri(), None).expect("failed to make lava server");
+
+        let names = |tags: Vec<Tag>| tags.into_iter().map(|t| t.name).collect::<Vec<_>>();
+        assert_eq!(
+            names(lava.find_tags("HDMI").await.expect("failed to find tags")),
+            vec!["hdmi", "hdmi-cec"]
+        );
+        assert_eq!(
+            names(lava.find_tags("port").await.expect("failed to find tags")),
+            vec!["usb-otg"]
+        );
+        assert!(lava
+            .find_tags("serial")
+            .await
+            .expect("failed to find tags")
+            .is_empty());
+    }
+
+    #[test(tokio::test)]
+    async fn test_create_delete() {
+        let mut state = SharedState::new();
+        {
+            let m = state.mutate();
+            let (_, m) = Proxy::<User<State>>::builder()
+                .username("admin")
+                .is_superuser(true)
+                .token(Some("admin-token".to_string()))
+                .build(m);
+            let (_, m) = Proxy::<User<State>>::builder()
+                .username("fred")
+                .token(Some("fred-token".to_string()))
+                .build(m);
+            let _ = Proxy::<MockTag<State>>::builder()
+                .id(1u32)
+                .name("hdmi")
+                .build(m);
+        }
+        let server = LavaMock::new(state, PaginationLimits::new()).await;
+
+        let fred = Lava::new(&server.uri(), Some("fred-token".to_string()))
+            .expect("failed to make lava server");
+        let err = fred
+            .create_tag("usb-otg", None)
+            .await
+            .expect_err("created tag without permission");
+        assert!(matches!(err, TagError::PermissionDenied));
+
+        let admin = Lava::new(&server.uri(), Some("admin-token".to_string()))
+            .expect("failed to make lava server");
+        let err = admin
+            .create_tag("hdmi", None)
+            .await
+            .expect_err("created duplicate tag");
+        assert!(matches!(err, TagError::InvalidTag(_)));
+
+        let tag = admin
+            .create_tag("usb-otg", Some("USB on-the-go port"))
+            .await
+            .expect("failed to create tag");
+        assert_eq!(tag.name, "usb-otg");
+        assert_eq!(tag.description.as_deref(), Some("USB on-the-go port"));
+        assert_eq!(admin.tag(tag.id).await, Some(tag.clone()));
+
+        let err = fred
+            .delete_tag(tag.id)
+            .await
+            .expect_err("deleted tag without permission");
+        assert!(matches!(err, TagError::PermissionDenied));
+
+        admin.delete_tag(1).await.expect("failed to delete tag");
+        let err = admin.delete_tag(1).await.expect_err("deleted tag twice");
+        assert!(matches!(err, TagError::NotFound));
+
+        let tags = fred.tags().await.expect("failed to get tags");
+        assert_eq!(tags, vec![tag]);
+    }
 }
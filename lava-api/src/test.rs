@@ -1,15 +1,39 @@
 //! Retrieve test data
 
 use chrono::{DateTime, Utc};
+use futures::stream::{Stream, TryStream, TryStreamExt};
 use serde::de::Visitor;
-use serde::{Deserialize, Deserializer};
-use serde_with::DeserializeFromStr;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use serde_with::{DeserializeFromStr, SerializeDisplay};
+use std::collections::HashMap;
 use std::fmt;
-use strum::{Display, EnumString};
+use std::time::Duration;
+use strum::{Display, EnumIter, EnumString, IntoEnumIterator};
+use thiserror::Error;
+use tokio::sync::OnceCell;
+use url::Url;
+
+use crate::datetime;
+use crate::job::{Job, JobError, JobsBuilder};
+use crate::paginator::{PaginationError, Paginator};
+use crate::queryset::{QuerySet, QuerySetMember};
+use crate::Lava;
 
 /// The result of running a [`TestCase`], as stored by LAVA
 // From lava/lava_results_app/models.py in TestCase::RESULT_CHOICES
-#[derive(Copy, DeserializeFromStr, Clone, Debug, Display, EnumString, PartialEq, Eq)]
+#[derive(
+    Copy,
+    DeserializeFromStr,
+    SerializeDisplay,
+    Clone,
+    Debug,
+    Display,
+    EnumIter,
+    EnumString,
+    Hash,
+    PartialEq,
+    Eq,
+)]
 #[strum(serialize_all = "snake_case")]
 pub enum PassFail {
     Pass,
@@ -18,9 +42,18 @@ pub enum PassFail {
     Unknown,
 }
 
+impl QuerySetMember for PassFail {
+    type Iter = PassFailIter;
+    fn all() -> Self::Iter {
+        Self::iter()
+    }
+}
+
 /// The type of an error that occurred running a test
 // From lava/lava_common/exceptions.py as the error_type fields of the classes
-#[derive(Copy, DeserializeFromStr, Clone, Debug, Display, EnumString, PartialEq, Eq)]
+#[derive(
+    Copy, DeserializeFromStr, SerializeDisplay, Clone, Debug, Display, EnumString, PartialEq, Eq,
+)]
 pub enum ErrorType {
     None,
     Infrastructure,
@@ -49,7 +82,7 @@ pub enum ErrorType {
 // - lava/lava_scheduler_app/views.py internal_v1_jobs_logs
 // And then from there to
 // - lava/lava_results_app/dbutils.py map_scanned_results
-#[derive(Clone, Debug, Deserialize)]
+#[derive(Clone, Debug, Deserialize, Serialize)]
 pub struct Metadata {
     // These three fields are present or the results would have been
     // rejected earlier by map_scanned_results.
@@ -71,10 +104,260 @@ pub struct Metadata {
     pub error_type: Option<ErrorType>,
 }
 
+/// Which timeout expired to cause a failure
+#[derive(Clone, Debug, Hash, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+pub enum TimeoutKind {
+    /// The overall job timeout
+    Job,
+    /// A timeout waiting on a connection to the device
+    Connection,
+    /// The timeout of the named action
+    Action(String),
+    /// A timeout whose message could not be interpreted
+    Unknown,
+}
+
+/// A timeout reported in the [`Metadata`] of a failed [`TestCase`]
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct Timeout {
+    pub kind: TimeoutKind,
+    /// The length of the timeout, if given in the message
+    pub seconds: Option<u64>,
+}
+
+impl Timeout {
+    // Messages are of the form "<name> timed out after <n> seconds",
+    // where the name is "job" for the job timeout, and connection
+    // timeouts mention the connection.
+    fn parse(msg: &str) -> Self {
+        let msg = msg.trim();
+        let lower = msg.to_lowercase();
+        let seconds = lower
+            .split_once("timed out after ")
+            .and_then(|(_, rest)| rest.split_whitespace().next())
+            .and_then(|n| n.parse::<f64>().ok())
+            .map(|n| n as u64);
+
+        let kind = if lower.contains("connection") {
+            TimeoutKind::Connection
+        } else if let Some((name, _)) = msg.split_once(" timed out") {
+            match name.trim() {
+                "" => TimeoutKind::Unknown,
+                n if n.eq_ignore_ascii_case("job") => TimeoutKind::Job,
+                n => TimeoutKind::Action(n.to_string()),
+            }
+        } else {
+            TimeoutKind::Unknown
+        };
+
+        Timeout { kind, seconds }
+    }
+}
+
+impl Metadata {
+    /// The timeout that caused this failure, if it was a timeout.
+    ///
+    /// This is only `Some` when the [`error_type`](Metadata::error_type)
+    /// is a timeout; the kind of timeout is determined from the
+    /// [`error_msg`](Metadata::error_msg).
+    pub fn timeout(&self) -> Option<Timeout> {
+        match self.error_type {
+            Some(ErrorType::LavaTimeout) | Some(ErrorType::MultinodeTimeout) => Some(
+                Timeout::parse(self.error_msg.as_deref().unwrap_or_default()),
+            ),
+            _ => None,
+        }
+    }
+}
+
+/// Count the timeouts of each kind in a stream of [`TestCase`]
+/// instances.
+///
+/// This can be used with [`test_cases`](crate::Lava::test_cases) to
+/// find out what is timing out in a job, or over a series of jobs
+/// when the streams are chained together.
+pub async fn count_timeouts<S>(cases: S) -> Result<HashMap<TimeoutKind, usize>, S::Error>
+where
+    S: TryStream<Ok = TestCase>,
+{
+    cases
+        .try_fold(HashMap::new(), |mut counts, case| async move {
+            if let Some(timeout) = case.metadata.as_ref().and_then(Metadata::timeout) {
+                *counts.entry(timeout.kind).or_default() += 1;
+            }
+            Ok(counts)
+        })
+        .await
+}
+
+/// A failed [`TestCase`] in a [`ResultsSummary`]
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct FailedCase {
+    pub id: i64,
+    pub name: String,
+    /// The id of the suite the test case belongs to
+    pub suite: i64,
+    pub error_type: Option<ErrorType>,
+    pub error_msg: Option<String>,
+}
+
+/// The outcome of a set of [`TestCase`] instances
+///
+/// This is returned by [`summarize`] and
+/// [`job_results_summary`](crate::Lava::job_results_summary).
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize)]
+pub struct ResultsSummary {
+    /// The number of test cases with each result
+    pub counts: HashMap<PassFail, usize>,
+    /// The sum of the durations recorded in the test case metadata
+    pub duration: Duration,
+    /// The number of test cases which recorded a duration
+    pub timed: usize,
+    /// The test cases which failed, in the order they were seen
+    pub failures: Vec<FailedCase>,
+}
+
+impl ResultsSummary {
+    /// The number of test cases with the given result.
+    pub fn count(&self, result: PassFail) -> usize {
+        self.counts.get(&result).copied().unwrap_or_default()
+    }
+
+    /// The total number of test cases.
+    pub fn total(&self) -> usize {
+        self.counts.values().sum()
+    }
+
+    fn add(&mut self, case: TestCase) {
+        *self.counts.entry(case.result).or_default() += 1;
+
+        let duration = case
+            .metadata
+            .as_ref()
+            .and_then(|m| m.duration.as_deref())
+            .and_then(|d| d.trim().parse::<f64>().ok())
+            .and_then(|d| Duration::try_from_secs_f64(d).ok());
+        if let Some(duration) = duration {
+            self.duration += duration;
+            self.timed += 1;
+        }
+
+        if case.result == PassFail::Fail {
+            let (error_type, error_msg) = case
+                .metadata
+                .map(|m| (m.error_type, m.error_msg))
+                .unwrap_or_default();
+            self.failures.push(FailedCase {
+                id: case.id,
+                name: case.name,
+                suite: case.suite,
+                error_type,
+                error_msg,
+            });
+        }
+    }
+}
+
+/// Summarize a stream of [`TestCase`] instances.
+///
+/// As for [`count_timeouts`], streams from several jobs can be
+/// chained together to summarize them all at once.
+pub async fn summarize<S>(cases: S) -> Result<ResultsSummary, S::Error>
+where
+    S: TryStream<Ok = TestCase>,
+{
+    cases
+        .try_fold(ResultsSummary::default(), |mut summary, case| async move {
+            summary.add(case);
+            Ok(summary)
+        })
+        .await
+}
+
+/// Summarize the test results of the job with the given id.
+pub async fn job_results_summary(
+    lava: &Lava,
+    job_id: i64,
+) -> Result<ResultsSummary, PaginationError> {
+    summarize(lava.test_cases(job_id)).await
+}
+
+/// The number of jobs whose test cases are read at once by
+/// [`jobs_with_tests`](crate::Lava::jobs_with_tests)
+pub const JOBS_WITH_TESTS_CONCURRENCY: usize = 4;
+
+/// Retrieve the jobs selected by a [`JobsBuilder`] together with
+/// their [`TestCase`] instances.
+///
+/// The test cases of at most `concurrency` jobs are read at once,
+/// while further jobs are only requested as earlier ones are
+/// consumed. Jobs are yielded in the order of the query, each with
+/// all of its test cases. A `concurrency` of zero is treated as one.
+pub fn jobs_with_tests<'a>(
+    lava: &'a Lava,
+    jobs: JobsBuilder<'a>,
+    concurrency: usize,
+) -> impl Stream<Item = Result<(Job, Vec<TestCase>), PaginationError>> + Send + Unpin + 'a {
+    jobs.query()
+        .map_ok(move |job| async move {
+            let cases = lava.test_cases(job.id).try_collect().await?;
+            Ok((job, cases))
+        })
+        .try_buffered(concurrency.max(1))
+}
+
+/// A [`TestSuite`] of a job, with a summary of its test cases
+///
+/// These are returned by [`suite_summaries`]. The test cases of the
+/// suite are only read when [`summary`](TestSuiteSummary::summary)
+/// is first called, so suites which are not of interest cost nothing
+/// more than the listing of suites.
+#[derive(Clone, Debug)]
+pub struct TestSuiteSummary {
+    pub suite: TestSuite,
+    summary: OnceCell<ResultsSummary>,
+}
+
+impl TestSuiteSummary {
+    fn new(suite: TestSuite) -> Self {
+        Self {
+            suite,
+            summary: OnceCell::new(),
+        }
+    }
+
+    /// Retrieve the [`ResultsSummary`] of the test cases in this
+    /// suite.
+    ///
+    /// The test cases are requested from the server the first time
+    /// this is called, and the same summary is returned by later
+    /// calls, on this instance or any clone of it made afterwards.
+    pub async fn summary(&self, lava: &Lava) -> Result<&ResultsSummary, PaginationError> {
+        self.summary
+            .get_or_try_init(|| {
+                summarize(
+                    TestCasesBuilder::new(lava, self.suite.job)
+                        .suite(self.suite.id)
+                        .query(),
+                )
+            })
+            .await
+    }
+}
+
+/// Retrieve the [`TestSuite`] instances of the job with the given
+/// id, each ready to summarize its test cases.
+pub fn suite_summaries(
+    lava: &Lava,
+    job_id: i64,
+) -> impl Stream<Item = Result<TestSuiteSummary, PaginationError>> + Send + Unpin + 'static {
+    lava.test_suites(job_id).map_ok(TestSuiteSummary::new)
+}
+
 /// The data available for a test case for a [`Job`](crate::job::Job)
 /// from the LAVA API
 // From lava/lava_results_app/models.py in TestCase
-#[derive(Clone, Debug, Deserialize)]
+#[derive(Clone, Debug, Deserialize, Serialize)]
 pub struct TestCase {
     pub id: i64,
     pub name: String,
@@ -82,15 +365,290 @@ pub struct TestCase {
     pub unit: String,
     pub result: PassFail,
     pub measurement: Option<String>,
-    #[serde(deserialize_with = "nested_yaml")]
+    #[serde(deserialize_with = "nested_yaml", serialize_with = "to_nested_yaml")]
     pub metadata: Option<Metadata>,
     pub suite: i64,
     pub start_log_line: Option<u32>,
     pub end_log_line: Option<u32>,
     pub test_set: Option<i64>,
+    #[serde(deserialize_with = "datetime::deserialize")]
     pub logged: DateTime<Utc>,
     // from v02 api
     pub resource_uri: String,
+    #[serde(skip)]
+    parents: Parents,
+}
+
+// The parents of a test case, once they have been looked up.
+#[derive(Clone, Debug, Default)]
+struct Parents {
+    suite: OnceCell<TestSuite>,
+    job: OnceCell<Job>,
+}
+
+/// The data available for a test suite of a [`Job`] from the LAVA
+/// API
+// From lava/lava_results_app/models.py in TestSuite
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq, Eq)]
+pub struct TestSuite {
+    pub id: i64,
+    /// The id of the job the suite belongs to
+    pub job: i64,
+    pub name: String,
+    // from v02 api
+    pub resource_uri: Option<String>,
+}
+
+#[derive(Error, Debug)]
+pub enum ParentError {
+    #[error("Test case resource uri does not name a job: {0}")]
+    NoJob(String),
+    #[error("Test suite request failed")]
+    Suite(#[from] PaginationError),
+    #[error("Job request failed")]
+    Job(#[from] JobError),
+    #[error("Parent of test case not found")]
+    NotFound,
+}
+
+impl TestCase {
+    /// The id of the job this test case belongs to.
+    ///
+    /// Test cases do not record their job directly, so this is taken
+    /// from the [`resource_uri`](TestCase::resource_uri), which is of
+    /// the form `.../jobs/<job>/suites/<suite>/tests/<id>/`.
+    pub fn job_id(&self) -> Option<i64> {
+        let mut segments = self.resource_uri.split('/');
+        segments.find(|s| *s == "jobs")?;
+        segments.next()?.parse().ok()
+    }
+
+    /// Retrieve the [`TestSuite`] this test case belongs to.
+    ///
+    /// The suite is requested from the server the first time this is
+    /// called, and the same suite is returned by later calls, on this
+    /// instance or any clone of it made afterwards.
+    pub async fn suite(&self, lava: &Lava) -> Result<&TestSuite, ParentError> {
+        self.parents
+            .suite
+            .get_or_try_init(|| async {
+                let job = self
+                    .job_id()
+                    .ok_or_else(|| ParentError::NoJob(self.resource_uri.clone()))?;
+                let mut url = lava
+                    .base
+                    .join(&format!("jobs/{}/suites/", job))
+                    .expect("Failed to build test suite url");
+                url.query_pairs_mut()
+                    .append_pair("id", &self.suite.to_string());
+                let mut suites: Paginator<TestSuite> = lava.paginator(url);
+                suites.try_next().await?.ok_or(ParentError::NotFound)
+            })
+            .await
+    }
+
+    /// Retrieve the [`Job`] this test case belongs to.
+    ///
+    /// As for [`suite`](TestCase::suite), the job is only requested
+    /// from the server the first time this is called.
+    pub async fn job(&self, lava: &Lava) -> Result<&Job, ParentError> {
+        self.parents
+            .job
+            .get_or_try_init(|| async {
+                let job = self
+                    .job_id()
+                    .ok_or_else(|| ParentError::NoJob(self.resource_uri.clone()))?;
+                lava.job(job).await?.ok_or(ParentError::NotFound)
+            })
+            .await
+    }
+}
+
+/// Select the [`TestCase`] instances of a job to retrieve.
+///
+/// The filtering is performed by the server, so that only the
+/// matching test cases are transferred. Test cases are returned in
+/// order of id.
+///
+/// Example:
+/// ```rust
+/// use futures::stream::TryStreamExt;
+/// # use lava_api_mock::{LavaMock, PaginationLimits, PopulationParams, SharedState};
+/// use lava_api::{Lava, test::PassFail};
+/// #
+/// # tokio_test::block_on( async {
+/// # let limits = PaginationLimits::new();
+/// # let population = PopulationParams::new();
+/// # let mock = LavaMock::new(SharedState::new_populated(population), limits).await;
+/// # let service_uri = mock.uri();
+/// # let lava_token = None;
+///
+/// let lava = Lava::new(&service_uri, lava_token).expect("failed to make lava");
+///
+/// let mut failures = lava
+///     .test_cases_builder(0)
+///     .result(PassFail::Fail)
+///     .query();
+/// while let Some(case) = failures
+///     .try_next()
+///     .await
+///     .expect("failed to read test cases")
+/// {
+///     println!("{} failed", case.name);
+/// }
+/// # });
+/// ```
+pub struct TestCasesBuilder<'a> {
+    lava: &'a Lava,
+    job_id: i64,
+    results: QuerySet<PassFail>,
+    name_contains: Option<String>,
+    suite: Option<i64>,
+    logged_after: Option<DateTime<Utc>>,
+    logged_before: Option<DateTime<Utc>>,
+    measurement_above: Option<f64>,
+    measurement_below: Option<f64>,
+    limit: Option<u32>,
+}
+
+impl<'a> TestCasesBuilder<'a> {
+    /// Create a new [`TestCasesBuilder`] for the job with the given
+    /// id.
+    ///
+    /// The default query returns every test case of the job, with
+    /// default result pagination.
+    pub fn new(lava: &'a Lava, job_id: i64) -> Self {
+        Self {
+            lava,
+            job_id,
+            results: QuerySet::new("result"),
+            name_contains: None,
+            suite: None,
+            logged_after: None,
+            logged_before: None,
+            measurement_above: None,
+            measurement_below: None,
+            limit: None,
+        }
+    }
+
+    /// Return test cases with this result.
+    pub fn result(mut self, result: PassFail) -> Self {
+        self.results.include(result);
+        self
+    }
+
+    /// Exclude test cases with this result.
+    pub fn result_not(mut self, result: PassFail) -> Self {
+        self.results.exclude(&result);
+        self
+    }
+
+    /// Return only test cases whose name contains `name`.
+    pub fn name_contains<T: Into<String>>(mut self, name: T) -> Self {
+        self.name_contains = Some(name.into());
+        self
+    }
+
+    /// Return only test cases from the suite with the given id.
+    pub fn suite(mut self, suite: i64) -> Self {
+        self.suite = Some(suite);
+        self
+    }
+
+    /// Return only test cases logged strictly after the given
+    /// instant.
+    pub fn logged_after(mut self, when: DateTime<Utc>) -> Self {
+        self.logged_after = Some(when);
+        self
+    }
+
+    /// Return only test cases logged strictly before the given
+    /// instant.
+    pub fn logged_before(mut self, when: DateTime<Utc>) -> Self {
+        self.logged_before = Some(when);
+        self
+    }
+
+    /// Return only test cases with a measurement strictly greater
+    /// than `value`.
+    ///
+    /// Test cases without a measurement are never returned when
+    /// this is set.
+    pub fn measurement_above(mut self, value: f64) -> Self {
+        self.measurement_above = Some(value);
+        self
+    }
+
+    /// Return only test cases with a measurement strictly less than
+    /// `value`.
+    ///
+    /// Test cases without a measurement are never returned when
+    /// this is set.
+    pub fn measurement_below(mut self, value: f64) -> Self {
+        self.measurement_below = Some(value);
+        self
+    }
+
+    /// Set the number of test cases retrieved at a time while the
+    /// query is running.
+    ///
+    /// This is a page size, and has the same caveats as
+    /// [`JobsBuilder::limit`](crate::job::JobsBuilder::limit).
+    pub fn limit(mut self, limit: u32) -> Self {
+        self.limit = Some(limit);
+        self
+    }
+
+    /// Begin querying for test cases, returning a [`Stream`] of
+    /// [`TestCase`] instances.
+    ///
+    /// [`Stream`]: futures::stream::Stream
+    pub fn query(self) -> Paginator<TestCase> {
+        self.lava.paginator(self.url())
+    }
+
+    fn url(&self) -> Url {
+        let mut url = self
+            .lava
+            .base
+            .join("jobs/")
+            .and_then(|x| x.join(&format!("{}/", self.job_id)))
+            .and_then(|x| x.join("tests/"))
+            .expect("Failed to build test case url");
+        url.query_pairs_mut().append_pair("ordering", "id");
+        if let Some(pair) = self.results.query() {
+            url.query_pairs_mut().append_pair(&pair.0, &pair.1);
+        }
+        if let Some(name) = &self.name_contains {
+            url.query_pairs_mut().append_pair("name__contains", name);
+        }
+        if let Some(suite) = self.suite {
+            url.query_pairs_mut()
+                .append_pair("suite__id", &suite.to_string());
+        }
+        if let Some(when) = self.logged_after {
+            url.query_pairs_mut()
+                .append_pair("logged__gt", &when.to_rfc3339());
+        }
+        if let Some(when) = self.logged_before {
+            url.query_pairs_mut()
+                .append_pair("logged__lt", &when.to_rfc3339());
+        }
+        if let Some(value) = self.measurement_above {
+            url.query_pairs_mut()
+                .append_pair("measurement__gt", &value.to_string());
+        }
+        if let Some(value) = self.measurement_below {
+            url.query_pairs_mut()
+                .append_pair("measurement__lt", &value.to_string());
+        }
+        if let Some(limit) = self.limit {
+            url.query_pairs_mut()
+                .append_pair("limit", &limit.to_string());
+        }
+        url
+    }
 }
 
 fn nested_yaml<'de, D, T>(deser: D) -> Result<T, D::Error>
@@ -138,15 +696,29 @@ where
     deser.deserialize_str(StrVisitor::default())
 }
 
+// The server gives the metadata as a YAML document in a string, and
+// it is written back the same way so that it can be read again.
+fn to_nested_yaml<S, T>(value: &T, ser: S) -> Result<S::Ok, S::Error>
+where
+    S: Serializer,
+    T: Serialize,
+{
+    let yaml = serde_yaml::to_string(value).map_err(serde::ser::Error::custom)?;
+    ser.serialize_str(&yaml)
+}
+
 #[cfg(test)]
 mod tests {
-    use super::{ErrorType, Metadata, PassFail, TestCase};
+    use super::{
+        summarize, ErrorType, Metadata, ParentError, PassFail, TestCase, TestCasesBuilder,
+        TimeoutKind,
+    };
 
     use crate::Lava;
     use boulder::{Buildable, Builder};
     use futures::TryStreamExt;
     use lava_api_mock::{Job, LavaMock, PaginationLimits, PopulationParams, SharedState, State};
-    use persian_rug::Accessor;
+    use persian_rug::{Accessor, Context};
     use std::collections::BTreeMap;
     use test_log::test;
 
@@ -193,6 +765,61 @@ result: fail
         assert_eq!(meta.error_type, Some(ErrorType::Infrastructure));
     }
 
+    #[test]
+    fn test_timeout() {
+        let meta = |error_type, error_msg: &str| Metadata {
+            definition: "lava".to_string(),
+            case: "job".to_string(),
+            result: PassFail::Fail,
+            namespace: None,
+            level: None,
+            duration: None,
+            extra: None,
+            error_msg: Some(error_msg.to_string()),
+            error_type: Some(error_type),
+        };
+
+        let t = meta(
+            ErrorType::LavaTimeout,
+            "bootloader-interrupt timed out after 30 seconds",
+        )
+        .timeout()
+        .expect("no timeout found");
+        assert_eq!(
+            t.kind,
+            TimeoutKind::Action("bootloader-interrupt".to_string())
+        );
+        assert_eq!(t.seconds, Some(30));
+
+        let t = meta(ErrorType::LavaTimeout, "job timed out after 3600 seconds")
+            .timeout()
+            .expect("no timeout found");
+        assert_eq!(t.kind, TimeoutKind::Job);
+        assert_eq!(t.seconds, Some(3600));
+
+        let t = meta(
+            ErrorType::MultinodeTimeout,
+            "lava-test-shell connection timed out after 600 seconds",
+        )
+        .timeout()
+        .expect("no timeout found");
+        assert_eq!(t.kind, TimeoutKind::Connection);
+        assert_eq!(t.seconds, Some(600));
+
+        let t = meta(ErrorType::LavaTimeout, "something went wrong")
+            .timeout()
+            .expect("no timeout found");
+        assert_eq!(t.kind, TimeoutKind::Unknown);
+        assert_eq!(t.seconds, None);
+
+        assert!(meta(
+            ErrorType::Infrastructure,
+            "auto-login timed out after 5 seconds"
+        )
+        .timeout()
+        .is_none());
+    }
+
     #[test]
     fn test_test_case() {
         let json = r#"
@@ -239,6 +866,189 @@ result: fail
         );
         assert_eq!(tc.suite, 10892144i64);
         assert_eq!(tc.test_set, None);
+
+        // The metadata is written back as nested YAML, so the test
+        // case can be read again
+        let json = serde_json::to_string(&tc).expect("failed to serialize testcase");
+        let again: TestCase = serde_json::from_str(&json).expect("failed to reread testcase");
+        assert_eq!(format!("{:?}", again), format!("{:?}", tc));
+    }
+
+    #[test(tokio::test)]
+    async fn test_summarize() {
+        let case = |id: i64, result: &str, metadata: &str| -> Result<TestCase, ()> {
+            Ok(serde_json::from_value(serde_json::json!({
+                "id": id,
+                "result": result,
+                "resource_uri": format!("http://lava.invalid/api/v0.2/jobs/1/suites/2/tests/{}/", id),
+                "unit": "",
+                "name": format!("case-{}", id),
+                "measurement": null,
+                "metadata": metadata,
+                "start_log_line": null,
+                "end_log_line": null,
+                "logged": "2022-02-28T19:29:01.998922Z",
+                "suite": 2,
+                "test_set": null
+            }))
+            .expect("failed to deserialize testcase"))
+        };
+
+        let cases = vec![
+            case(1, "pass", "case: a\ndefinition: lava\nduration: '0.25'\nresult: pass\n"),
+            case(2, "pass", "case: b\ndefinition: lava\nduration: '1.50'\nresult: pass\n"),
+            case(3, "skip", "case: c\ndefinition: lava\nresult: skip\n"),
+            case(
+                4,
+                "fail",
+                "case: job\ndefinition: lava\nerror_msg: job timed out after 60 seconds\nerror_type: LAVATimeout\nresult: fail\n",
+            ),
+            case(5, "fail", "case: e\ndefinition: lava\nresult: fail\n"),
+            case(6, "unknown", "case: f\ndefinition: lava\nduration: 'n/a'\nresult: unknown\n"),
+        ];
+
+        let summary = summarize(futures::stream::iter(cases))
+            .await
+            .expect("failed to summarize");
+        assert_eq!(summary.total(), 6);
+        assert_eq!(summary.count(PassFail::Pass), 2);
+        assert_eq!(summary.count(PassFail::Fail), 2);
+        assert_eq!(summary.count(PassFail::Skip), 1);
+        assert_eq!(summary.count(PassFail::Unknown), 1);
+        assert_eq!(summary.duration, std::time::Duration::from_millis(1750));
+        assert_eq!(summary.timed, 2);
+
+        assert_eq!(summary.failures.len(), 2);
+        assert_eq!(summary.failures[0].id, 4);
+        assert_eq!(summary.failures[0].name, "case-4");
+        assert_eq!(summary.failures[0].suite, 2);
+        assert_eq!(summary.failures[0].error_type, Some(ErrorType::LavaTimeout));
+        assert_eq!(
+            summary.failures[0].error_msg.as_deref(),
+            Some("job timed out after 60 seconds")
+        );
+        assert_eq!(summary.failures[1].id, 5);
+        assert_eq!(summary.failures[1].error_type, None);
+
+        let empty = summarize(futures::stream::iter(Vec::<Result<TestCase, ()>>::new()))
+            .await
+            .expect("failed to summarize");
+        assert_eq!(empty.total(), 0);
+        assert_eq!(empty.count(PassFail::Pass), 0);
+    }
+
+    #[test(tokio::test)]
+    async fn test_job_results_summary() {
+        let pop = PopulationParams::builder()
+            .jobs(2usize)
+            .test_suites(3usize)
+            .test_cases(30usize)
+            .build();
+        let state = SharedState::new_populated(pop);
+        let server = LavaMock::new(
+            state.clone(),
+            PaginationLimits::builder().test_cases(Some(7)).build(),
+        )
+        .await;
+        let lava = Lava::new(&server.uri(), None).expect("failed to make lava server");
+
+        let start = state.access();
+        for job in start.get_iter::<Job<State>>() {
+            let cases = start
+                .get_iter::<lava_api_mock::TestCase<State>>()
+                .filter(|t| start.get(&start.get(&t.suite).job).id == job.id)
+                .collect::<Vec<_>>();
+
+            let summary = lava
+                .job_results_summary(job.id)
+                .await
+                .expect("failed to summarize job results");
+            assert_eq!(summary.total(), cases.len());
+            for result in [
+                PassFail::Pass,
+                PassFail::Fail,
+                PassFail::Skip,
+                PassFail::Unknown,
+            ] {
+                assert_eq!(
+                    summary.count(result),
+                    cases
+                        .iter()
+                        .filter(|t| t.result.to_string() == result.to_string())
+                        .count()
+                );
+            }
+            let mut failed = cases
+                .iter()
+                .filter(|t| t.result.to_string() == "fail")
+                .map(|t| t.id)
+                .collect::<Vec<_>>();
+            failed.sort();
+            let mut seen = summary.failures.iter().map(|f| f.id).collect::<Vec<_>>();
+            seen.sort();
+            assert_eq!(seen, failed);
+        }
+    }
+
+    #[test(tokio::test)]
+    async fn test_jobs_with_tests() {
+        let pop = PopulationParams::builder()
+            .jobs(5usize)
+            .test_suites(10usize)
+            .test_cases(40usize)
+            .build();
+        let state = SharedState::new_populated(pop);
+        let server = LavaMock::new(
+            state.clone(),
+            PaginationLimits::builder()
+                .jobs(Some(2))
+                .test_cases(Some(3))
+                .build(),
+        )
+        .await;
+        let lava = Lava::new(&server.uri(), None).expect("failed to make lava server");
+
+        let start = state.access();
+        let mut expected = BTreeMap::new();
+        for t in start.get_iter::<lava_api_mock::TestCase<State>>() {
+            expected
+                .entry(start.get(&start.get(&t.suite).job).id)
+                .or_insert_with(Vec::new)
+                .push(t.id);
+        }
+
+        for concurrency in [0, 1, 3] {
+            let ids = lava
+                .jobs()
+                .query()
+                .map_ok(|job| job.id)
+                .try_collect::<Vec<_>>()
+                .await
+                .expect("failed to get jobs");
+            let joined = super::jobs_with_tests(&lava, lava.jobs(), concurrency)
+                .try_collect::<Vec<_>>()
+                .await
+                .expect("failed to get jobs with tests");
+
+            assert_eq!(
+                joined.iter().map(|(job, _)| job.id).collect::<Vec<_>>(),
+                ids
+            );
+            for (job, cases) in joined {
+                let mut seen = cases.iter().map(|t| t.id).collect::<Vec<_>>();
+                seen.sort();
+                let mut want = expected.get(&job.id).cloned().unwrap_or_default();
+                want.sort();
+                assert_eq!(seen, want);
+            }
+        }
+
+        let total = lava
+            .jobs_with_tests(lava.jobs())
+            .try_fold(0, |n, (_, cases)| async move { Ok(n + cases.len()) })
+            .await
+            .expect("failed to get jobs with tests");
+        assert_eq!(total, 40);
     }
 
     /// Stream 20 tests each from 3 jobs with a page limit of 6 from
@@ -296,4 +1106,226 @@ result: fail
         }
         assert_eq!(seen.len(), 60);
     }
+
+    #[test(tokio::test)]
+    async fn test_builder() {
+        let pop = PopulationParams::builder()
+            .jobs(2usize)
+            .test_suites(3usize)
+            .test_cases(30usize)
+            .build();
+        let state = SharedState::new_populated(pop);
+        let server = LavaMock::new(
+            state.clone(),
+            PaginationLimits::builder().test_cases(Some(7)).build(),
+        )
+        .await;
+        let lava = Lava::new(&server.uri(), None).expect("failed to make lava server");
+
+        let start = state.access();
+        let job = start.get_iter::<Job<State>>().next().unwrap();
+        let cases = start
+            .get_iter::<lava_api_mock::TestCase<State>>()
+            .filter(|t| start.get(&start.get(&t.suite).job).id == job.id)
+            .collect::<Vec<_>>();
+        let logged = cases[cases.len() / 2].logged;
+
+        let expected = |f: &dyn Fn(&lava_api_mock::TestCase<State>) -> bool| {
+            cases
+                .iter()
+                .filter(|t| f(t))
+                .map(|t| t.id)
+                .collect::<Vec<_>>()
+        };
+        let ids = |builder: TestCasesBuilder| async move {
+            builder
+                .query()
+                .map_ok(|t| t.id)
+                .try_collect::<Vec<_>>()
+                .await
+                .expect("failed to query test cases")
+        };
+
+        let failures = ids(lava.test_cases_builder(job.id).result(PassFail::Fail)).await;
+        assert_eq!(
+            failures,
+            expected(&|t| t.result == lava_api_mock::PassFail::Fail)
+        );
+
+        let not_passed = ids(lava.test_cases_builder(job.id).result_not(PassFail::Pass)).await;
+        assert_eq!(
+            not_passed,
+            expected(&|t| t.result != lava_api_mock::PassFail::Pass)
+        );
+
+        let named = ids(lava.test_cases_builder(job.id).name_contains("1")).await;
+        assert_eq!(named, expected(&|t| t.name.contains('1')));
+
+        let suite = start.get(&cases[0].suite).id;
+        let in_suite = ids(lava.test_cases_builder(job.id).suite(suite)).await;
+        assert_eq!(in_suite, expected(&|t| start.get(&t.suite).id == suite));
+
+        let after = ids(lava.test_cases_builder(job.id).logged_after(logged)).await;
+        assert_eq!(after, expected(&|t| t.logged > logged));
+        let before = ids(lava.test_cases_builder(job.id).logged_before(logged)).await;
+        assert_eq!(before, expected(&|t| t.logged < logged));
+
+        let url = lava
+            .test_cases_builder(job.id)
+            .measurement_above(0.5)
+            .measurement_below(2.0)
+            .url();
+        let pairs = url.query_pairs().into_owned().collect::<BTreeMap<_, _>>();
+        assert_eq!(
+            pairs.get("measurement__gt").map(String::as_str),
+            Some("0.5")
+        );
+        assert_eq!(pairs.get("measurement__lt").map(String::as_str), Some("2"));
+    }
+
+    #[test(tokio::test)]
+    async fn test_suite_summaries() {
+        let pop = PopulationParams::builder()
+            .jobs(2usize)
+            .test_suites(5usize)
+            .test_cases(30usize)
+            .build();
+        let state = SharedState::new_populated(pop);
+        let server = LavaMock::new(
+            state.clone(),
+            PaginationLimits::builder().test_cases(Some(4)).build(),
+        )
+        .await;
+        let lava = Lava::new(&server.uri(), None).expect("failed to make lava server");
+
+        let start = state.access();
+        for job in start.get_iter::<Job<State>>() {
+            let suites = lava
+                .suite_summaries(job.id)
+                .try_collect::<Vec<_>>()
+                .await
+                .expect("failed to get suites");
+
+            let mut expected_suites = start
+                .get_iter::<lava_api_mock::TestSuite<State>>()
+                .filter(|s| start.get(&s.job).id == job.id)
+                .map(|s| s.id)
+                .collect::<Vec<_>>();
+            expected_suites.sort();
+            let mut ids = suites.iter().map(|s| s.suite.id).collect::<Vec<_>>();
+            ids.sort();
+            assert_eq!(ids, expected_suites);
+            assert!(suites.iter().all(|s| s.suite.job == job.id));
+
+            for suite in suites.iter() {
+                let mut expected = BTreeMap::new();
+                for case in start.get_iter::<lava_api_mock::TestCase<State>>() {
+                    if start.get(&case.suite).id == suite.suite.id {
+                        *expected.entry(case.result.to_string()).or_insert(0) += 1;
+                    }
+                }
+
+                let summary = suite.summary(&lava).await.expect("failed to summarize");
+                assert_eq!(summary.total(), expected.values().sum::<usize>());
+                for result in [
+                    PassFail::Pass,
+                    PassFail::Fail,
+                    PassFail::Skip,
+                    PassFail::Unknown,
+                ] {
+                    assert_eq!(
+                        summary.count(result),
+                        expected
+                            .get(&result.to_string())
+                            .copied()
+                            .unwrap_or_default()
+                    );
+                }
+
+                // The summary is only computed once
+                assert!(std::ptr::eq(summary, suite.summary(&lava).await.unwrap()));
+            }
+        }
+    }
+
+    #[test(tokio::test)]
+    async fn test_parents() {
+        let pop = PopulationParams::builder()
+            .jobs(3usize)
+            .test_suites(6usize)
+            .test_cases(20usize)
+            .build();
+        let mut state = SharedState::new_populated(pop);
+        {
+            // Give each test case a resource uri in the form a real
+            // server uses.
+            let start = state.access();
+            let uris = start
+                .get_iter::<lava_api_mock::TestCase<State>>()
+                .map(|t| {
+                    let suite = start.get(&t.suite);
+                    format!(
+                        "/api/v0.2/jobs/{}/suites/{}/tests/{}/",
+                        start.get(&suite.job).id,
+                        suite.id,
+                        t.id
+                    )
+                })
+                .collect::<Vec<_>>();
+            let mut m = state.mutate();
+            for (t, uri) in m.get_iter_mut::<lava_api_mock::TestCase<State>>().zip(uris) {
+                t.resource_uri = uri;
+            }
+        }
+        let server = LavaMock::new(state.clone(), PaginationLimits::new()).await;
+        let lava = Lava::new(&server.uri(), None).expect("failed to make lava server");
+
+        let start = state.access();
+        for job in start.get_iter::<Job<State>>() {
+            let cases = lava
+                .test_cases(job.id)
+                .try_collect::<Vec<_>>()
+                .await
+                .expect("failed to get tests");
+            for case in cases {
+                assert_eq!(case.job_id(), Some(job.id));
+                let suite = case.suite(&lava).await.expect("failed to get suite");
+                assert_eq!(suite.id, case.suite);
+                assert_eq!(suite.job, job.id);
+                let found = case.job(&lava).await.expect("failed to get job");
+                assert_eq!(found.id, job.id);
+
+                // Later lookups are cached, and clones keep what was cached
+                let copy = case.clone();
+                assert!(std::ptr::eq(
+                    case.suite(&lava).await.unwrap(),
+                    case.suite(&lava).await.unwrap()
+                ));
+                assert_eq!(copy.job(&lava).await.unwrap().id, job.id);
+            }
+        }
+
+        let mut case: TestCase = serde_json::from_value(serde_json::json!({
+            "id": 1,
+            "name": "orphan",
+            "unit": "",
+            "result": "pass",
+            "measurement": null,
+            "metadata": "definition: lava\ncase: orphan\nresult: pass\n",
+            "suite": 1000,
+            "start_log_line": null,
+            "end_log_line": null,
+            "test_set": null,
+            "logged": "2022-04-10T16:30:00Z",
+            "resource_uri": "example-resource-uri-1",
+        }))
+        .expect("failed to parse test case");
+        assert_eq!(case.job_id(), None);
+        assert!(matches!(case.job(&lava).await, Err(ParentError::NoJob(_))));
+        case.resource_uri = "/api/v0.2/jobs/0/suites/1000/tests/1/".to_string();
+        assert!(matches!(
+            case.suite(&lava).await,
+            Err(ParentError::NotFound)
+        ));
+    }
 }
@@ -0,0 +1,781 @@
+//! Send requests to the server
+//!
+//! Every request made by a [`Lava`](crate::Lava) instance is passed
+//! to a [`Transport`], which is responsible for delivering it to the
+//! server and returning the response. By default this is an
+//! [`HttpTransport`], which uses a [`reqwest::Client`] configured by
+//! [`LavaBuilder`](crate::LavaBuilder). Other implementations can be
+//! supplied with [`LavaBuilder::transport`](crate::LavaBuilder::transport),
+//! for example to add another authentication scheme, or to reach the
+//! server by some other route. A [`CannedTransport`] replies to
+//! requests with fixed responses, for tests which do not need a
+//! whole mock server.
+//!
+//! Example:
+//! ```rust
+//! use futures::future::{self, BoxFuture};
+//! use futures::stream::TryStreamExt;
+//! use lava_api::transport::Transport;
+//! use lava_api::Lava;
+//!
+//! #[derive(Debug)]
+//! struct NoWorkers;
+//!
+//! impl Transport for NoWorkers {
+//!     fn execute(
+//!         &self,
+//!         _request: reqwest::Request,
+//!     ) -> BoxFuture<'static, reqwest::Result<reqwest::Response>> {
+//!         let body = r#"{"count": 0, "next": null, "results": []}"#;
+//!         let response = http::Response::builder().status(200).body(body).unwrap();
+//!         Box::pin(future::ok(response.into()))
+//!     }
+//! }
+//!
+//! # tokio_test::block_on( async {
+//! let lava = Lava::builder("https://lava.example.com/")
+//!     .transport(NoWorkers)
+//!     .build()
+//!     .expect("failed to make lava");
+//! let workers: Vec<_> = lava.workers().try_collect().await.expect("failed to get workers");
+//! assert!(workers.is_empty());
+//! # });
+//! ```
+
+use futures::future::BoxFuture;
+use reqwest::header::{
+    HeaderValue, InvalidHeaderValue, AUTHORIZATION, CONTENT_LENGTH, CONTENT_TYPE, COOKIE, LOCATION,
+    PROXY_AUTHORIZATION,
+};
+use reqwest::{Client, Method, Request, Response, StatusCode};
+use serde::Serialize;
+use std::fmt::{self, Debug, Formatter};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use url::{Origin, Url};
+
+use crate::auth::{Token, TokenProvider};
+
+/// A means of sending requests to a LAVA server
+///
+/// Implementations are given complete requests, including any
+/// authentication headers, and must return the server's response
+/// unchanged: in particular, redirects must not be followed, since
+/// that could send the token to another server.
+pub trait Transport: Debug + Send + Sync {
+    /// Send `request`, returning the response.
+    fn execute(&self, request: Request) -> BoxFuture<'static, reqwest::Result<Response>>;
+
+    /// Note that `request` is about to be sent again by a retry, as
+    /// the given (1-based) attempt.
+    ///
+    /// Transports wrapping another should pass this on. By default
+    /// it does nothing.
+    fn retrying(&self, _request: &Request, _attempt: u32) {}
+}
+
+/// A [`Transport`] making HTTP requests with a [`reqwest::Client`]
+///
+/// This is the transport used unless another is given to
+/// [`LavaBuilder::transport`](crate::LavaBuilder::transport).
+#[derive(Clone, Debug)]
+pub struct HttpTransport {
+    client: Client,
+}
+
+impl HttpTransport {
+    /// Create a new [`HttpTransport`] sending requests with `client`.
+    ///
+    /// The client should be configured not to follow redirects.
+    pub fn new(client: Client) -> Self {
+        Self { client }
+    }
+}
+
+impl Transport for HttpTransport {
+    fn execute(&self, request: Request) -> BoxFuture<'static, reqwest::Result<Response>> {
+        Box::pin(self.client.execute(request))
+    }
+}
+
+#[derive(Clone, Debug)]
+struct CannedReply {
+    method: Method,
+    path: String,
+    status: StatusCode,
+    body: String,
+}
+
+/// A [`Transport`] replying to requests with canned responses
+///
+/// Each reply is given for a method and a url path, and is sent as
+/// JSON for every request matching both; the query of a request is
+/// not considered, and when several replies match, the last one
+/// given is used. Requests with no matching reply receive a 404
+/// response. The method and url of each request are recorded, and
+/// clones share the record, so a test can keep a clone to check the
+/// requests made through a [`Lava`](crate::Lava).
+///
+/// Example:
+/// ```rust
+/// use futures::stream::TryStreamExt;
+/// use lava_api::transport::CannedTransport;
+/// use lava_api::Lava;
+/// use reqwest::{Method, StatusCode};
+///
+/// # tokio_test::block_on( async {
+/// let transport = CannedTransport::new().reply(
+///     Method::GET,
+///     "/api/v0.2/workers/",
+///     StatusCode::OK,
+///     r#"{"count": 0, "next": null, "results": []}"#,
+/// );
+/// let lava = Lava::builder("https://lava.example.com/")
+///     .transport(transport.clone())
+///     .build()
+///     .expect("failed to make lava");
+///
+/// let workers: Vec<_> = lava.workers().try_collect().await.expect("failed to get workers");
+/// assert!(workers.is_empty());
+/// assert_eq!(transport.requests().len(), 1);
+/// # });
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct CannedTransport {
+    replies: Vec<CannedReply>,
+    requests: Arc<Mutex<Vec<(Method, Url)>>>,
+}
+
+impl CannedTransport {
+    /// Create a new [`CannedTransport`], with no replies.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Reply to `method` requests for `path` with the given status
+    /// and JSON body.
+    pub fn reply<P: Into<String>, B: Into<String>>(
+        mut self,
+        method: Method,
+        path: P,
+        status: StatusCode,
+        body: B,
+    ) -> Self {
+        self.replies.push(CannedReply {
+            method,
+            path: path.into(),
+            status,
+            body: body.into(),
+        });
+        self
+    }
+
+    /// The method and url of each request received so far, in the
+    /// order they arrived.
+    pub fn requests(&self) -> Vec<(Method, Url)> {
+        self.requests.lock().unwrap().clone()
+    }
+}
+
+impl Transport for CannedTransport {
+    fn execute(&self, request: Request) -> BoxFuture<'static, reqwest::Result<Response>> {
+        self.requests
+            .lock()
+            .unwrap()
+            .push((request.method().clone(), request.url().clone()));
+        let (status, body) = match self
+            .replies
+            .iter()
+            .rev()
+            .find(|r| r.method == request.method() && r.path == request.url().path())
+        {
+            Some(reply) => (reply.status, reply.body.clone()),
+            None => (
+                StatusCode::NOT_FOUND,
+                r#"{"detail": "Not found."}"#.to_string(),
+            ),
+        };
+        let response = http::Response::builder()
+            .status(status)
+            .header(CONTENT_TYPE, "application/json")
+            .body(body)
+            .expect("Failed to build canned response");
+        Box::pin(futures::future::ok(response.into()))
+    }
+}
+
+/// A [`Transport`] adding a LAVA token to each request before
+/// passing it on.
+pub(crate) struct TokenAuth {
+    inner: Arc<dyn Transport>,
+    token: AuthToken,
+    // The only origin the token is sent to
+    origin: Origin,
+}
+
+enum AuthToken {
+    Fixed(HeaderValue),
+    Provider(Arc<dyn TokenProvider>),
+}
+
+// The authorization header for `token`
+fn auth_header(token: &str) -> Result<HeaderValue, InvalidHeaderValue> {
+    let mut header = HeaderValue::try_from(format!("Token {}", token))?;
+    header.set_sensitive(true);
+    Ok(header)
+}
+
+impl TokenAuth {
+    pub(crate) fn new(
+        inner: Arc<dyn Transport>,
+        token: Token,
+        origin: Origin,
+    ) -> Result<Self, InvalidHeaderValue> {
+        let token = match token {
+            Token::Fixed(token) => AuthToken::Fixed(auth_header(&token)?),
+            Token::Provider(provider) => AuthToken::Provider(provider),
+        };
+        Ok(Self {
+            inner,
+            token,
+            origin,
+        })
+    }
+}
+
+impl Debug for TokenAuth {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        // Keep the token itself out of any logs
+        f.debug_struct("TokenAuth")
+            .field("inner", &self.inner)
+            .finish_non_exhaustive()
+    }
+}
+
+impl Transport for TokenAuth {
+    fn execute(&self, mut request: Request) -> BoxFuture<'static, reqwest::Result<Response>> {
+        // Requests which have been redirected elsewhere go without
+        if request.url().origin() != self.origin {
+            return self.inner.execute(request);
+        }
+        let provider = match &self.token {
+            AuthToken::Fixed(token) => {
+                request.headers_mut().insert(AUTHORIZATION, token.clone());
+                return self.inner.execute(request);
+            }
+            AuthToken::Provider(provider) => provider.clone(),
+        };
+        let inner = self.inner.clone();
+        Box::pin(async move {
+            match provider.token().await.map(|token| auth_header(&token)) {
+                Some(Ok(token)) => {
+                    request.headers_mut().insert(AUTHORIZATION, token);
+                }
+                Some(Err(_)) => log::warn!("Ignoring invalid token from token provider"),
+                None => (),
+            }
+            inner.execute(request).await
+        })
+    }
+
+    fn retrying(&self, request: &Request, attempt: u32) {
+        self.inner.retrying(request, attempt);
+    }
+}
+
+/// Which redirects to follow
+///
+/// This is set with
+/// [`LavaBuilder::redirects`](crate::LavaBuilder::redirects). Whatever
+/// the policy, the token is only sent to the server a
+/// [`Lava`](crate::Lava) instance was made for, so a redirect to
+/// another server never reveals it.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum RedirectPolicy {
+    /// Follow no redirects
+    None,
+    /// Follow redirects to the same scheme, host and port, such as
+    /// those adding a trailing slash to a path
+    #[default]
+    SameOrigin,
+    /// Follow every redirect, such as to the canonical host of a
+    /// server, sending the token only to the original server
+    Any,
+}
+
+/// The most redirects followed for a single request
+pub const MAX_REDIRECTS: usize = 10;
+
+/// A [`Transport`] following redirects according to a
+/// [`RedirectPolicy`].
+#[derive(Debug)]
+pub(crate) struct Redirecting {
+    inner: Arc<dyn Transport>,
+    policy: RedirectPolicy,
+}
+
+impl Redirecting {
+    pub(crate) fn new(inner: Arc<dyn Transport>, policy: RedirectPolicy) -> Self {
+        Self { inner, policy }
+    }
+}
+
+// The request to send in place of `request` after it received
+// `response`, if that is a redirect the policy allows following.
+fn redirected(
+    mut request: Request,
+    response: &Response,
+    policy: RedirectPolicy,
+    origin: &Origin,
+) -> Option<Request> {
+    let status = response.status();
+    let keep_method = match status {
+        StatusCode::MOVED_PERMANENTLY | StatusCode::FOUND | StatusCode::SEE_OTHER => false,
+        StatusCode::TEMPORARY_REDIRECT | StatusCode::PERMANENT_REDIRECT => true,
+        _ => return None,
+    };
+    let location = response.headers().get(LOCATION)?.to_str().ok()?;
+    let url = request.url().join(location).ok()?;
+    let same_origin = url.origin() == *origin;
+    match policy {
+        RedirectPolicy::None => return None,
+        RedirectPolicy::SameOrigin if !same_origin => return None,
+        _ => (),
+    }
+
+    log::debug!("Redirecting from {} to {}", request.url(), url);
+    let headers = request.headers_mut();
+    if !same_origin {
+        for header in [AUTHORIZATION, COOKIE, PROXY_AUTHORIZATION] {
+            headers.remove(header);
+        }
+    }
+    // As browsers do, other requests become GET requests without a
+    // body, except where the redirect requires the method to be kept.
+    if !keep_method && request.method() != Method::GET && request.method() != Method::HEAD {
+        for header in [CONTENT_TYPE, CONTENT_LENGTH] {
+            request.headers_mut().remove(header);
+        }
+        *request.method_mut() = Method::GET;
+        *request.body_mut() = None;
+    }
+    *request.url_mut() = url;
+    Some(request)
+}
+
+impl Transport for Redirecting {
+    fn execute(&self, request: Request) -> BoxFuture<'static, reqwest::Result<Response>> {
+        if self.policy == RedirectPolicy::None {
+            return self.inner.execute(request);
+        }
+        let inner = self.inner.clone();
+        let policy = self.policy;
+        Box::pin(async move {
+            let origin = request.url().origin();
+            let mut request = request;
+            let mut redirects = 0;
+            loop {
+                // Requests with streamed bodies cannot be sent again,
+                // so their redirects are returned as they are.
+                let copy = request.try_clone();
+                let response = inner.execute(request).await?;
+                if redirects == MAX_REDIRECTS {
+                    return Ok(response);
+                }
+                match copy.and_then(|copy| redirected(copy, &response, policy, &origin)) {
+                    Some(next) => {
+                        request = next;
+                        redirects += 1;
+                    }
+                    None => return Ok(response),
+                }
+            }
+        })
+    }
+
+    fn retrying(&self, request: &Request, attempt: u32) {
+        self.inner.retrying(request, attempt);
+    }
+}
+
+/// A [`Transport`] logging a warning for each request which takes
+/// longer than a threshold to be answered, before passing it on.
+#[derive(Debug)]
+pub(crate) struct SlowRequestWarning {
+    inner: Arc<dyn Transport>,
+    threshold: Duration,
+}
+
+impl SlowRequestWarning {
+    pub(crate) fn new(inner: Arc<dyn Transport>, threshold: Duration) -> Self {
+        Self { inner, threshold }
+    }
+}
+
+impl Transport for SlowRequestWarning {
+    fn execute(&self, request: Request) -> BoxFuture<'static, reqwest::Result<Response>> {
+        // Only the path is logged, as the query can be very long
+        let method = request.method().clone();
+        let path = request.url().path().to_string();
+        let threshold = self.threshold;
+        let start = Instant::now();
+        let response = self.inner.execute(request);
+        Box::pin(async move {
+            let response = response.await;
+            let elapsed = start.elapsed();
+            if elapsed > threshold {
+                log::warn!("Slow request: {} {} took {:?}", method, path, elapsed);
+            }
+            response
+        })
+    }
+
+    fn retrying(&self, request: &Request, attempt: u32) {
+        self.inner.retrying(request, attempt);
+    }
+}
+
+/// A [`Transport`] running each request in a `tracing` span, before
+/// passing it on.
+///
+/// The span records the method and url of the request, the job it
+/// concerns if there is one, and the status of the reply.
+#[cfg(feature = "tracing")]
+#[derive(Debug)]
+pub(crate) struct Traced {
+    inner: Arc<dyn Transport>,
+}
+
+#[cfg(feature = "tracing")]
+impl Traced {
+    pub(crate) fn new(inner: Arc<dyn Transport>) -> Self {
+        Self { inner }
+    }
+}
+
+// The id of the job a request concerns, for urls of the form
+// `.../jobs/<id>/...`.
+#[cfg(feature = "tracing")]
+fn job_id(url: &Url) -> Option<i64> {
+    let mut segments = url.path_segments()?;
+    segments.find(|s| *s == "jobs")?;
+    segments.next()?.parse().ok()
+}
+
+#[cfg(feature = "tracing")]
+impl Transport for Traced {
+    fn execute(&self, request: Request) -> BoxFuture<'static, reqwest::Result<Response>> {
+        use tracing::Instrument;
+
+        let span = tracing::debug_span!(
+            "request",
+            method = %request.method(),
+            url = %request.url(),
+            job = job_id(request.url()),
+            status = tracing::field::Empty,
+        );
+        let response = span.in_scope(|| self.inner.execute(request));
+        let current = span.clone();
+        Box::pin(
+            async move {
+                let response = response.await;
+                if let Ok(response) = &response {
+                    current.record("status", response.status().as_u16());
+                }
+                response
+            }
+            .instrument(span),
+        )
+    }
+
+    fn retrying(&self, request: &Request, attempt: u32) {
+        self.inner.retrying(request, attempt);
+    }
+}
+
+/// Create a GET request for `url`.
+pub(crate) fn get(url: Url) -> Request {
+    Request::new(Method::GET, url)
+}
+
+/// Create a DELETE request for `url`.
+pub(crate) fn delete(url: Url) -> Request {
+    Request::new(Method::DELETE, url)
+}
+
+/// Create a POST request for `url`, with `body` as its JSON content.
+pub(crate) fn post_json<T: Serialize>(url: Url, body: &T) -> Request {
+    json_request(Method::POST, url, body)
+}
+
+/// Create a PATCH request for `url`, with `body` as its JSON content.
+pub(crate) fn patch_json<T: Serialize>(url: Url, body: &T) -> Request {
+    json_request(Method::PATCH, url, body)
+}
+
+fn json_request<T: Serialize>(method: Method, url: Url, body: &T) -> Request {
+    let mut request = Request::new(method, url);
+    request
+        .headers_mut()
+        .insert(CONTENT_TYPE, HeaderValue::from_static("application/json"));
+    *request.body_mut() = Some(
+        serde_json::to_vec(body)
+            .expect("Failed to serialize request body")
+            .into(),
+    );
+    request
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{CannedTransport, Transport};
+    use crate::job::CancellationError;
+    use crate::Lava;
+
+    use futures::future::{self, BoxFuture};
+    use reqwest::{Method, Request, Response, StatusCode};
+    use std::sync::{Arc, Mutex};
+    use test_log::test;
+
+    // The path and authorization header of a request
+    type Seen = (String, Option<String>);
+
+    // A transport refusing every request, and recording the path
+    // and authorization header of each.
+    #[derive(Debug, Default)]
+    struct Refuse {
+        requests: Arc<Mutex<Vec<Seen>>>,
+    }
+
+    impl Transport for Refuse {
+        fn execute(&self, request: Request) -> BoxFuture<'static, reqwest::Result<Response>> {
+            let auth = request
+                .headers()
+                .get(reqwest::header::AUTHORIZATION)
+                .map(|v| v.to_str().unwrap().to_string());
+            self.requests
+                .lock()
+                .unwrap()
+                .push((request.url().path().to_string(), auth));
+            let response = http::Response::builder().status(403).body("").unwrap();
+            Box::pin(future::ok(response.into()))
+        }
+    }
+
+    #[test(tokio::test)]
+    async fn test_canned() {
+        let transport = CannedTransport::new()
+            .reply(
+                Method::GET,
+                "/api/v0.2/jobs/3/cancel/",
+                StatusCode::OK,
+                "{}",
+            )
+            .reply(
+                Method::GET,
+                "/api/v0.2/jobs/4/cancel/",
+                StatusCode::OK,
+                "{}",
+            )
+            .reply(
+                Method::GET,
+                "/api/v0.2/jobs/4/cancel/",
+                StatusCode::FORBIDDEN,
+                "{}",
+            );
+        let lava = Lava::builder("https://lava.example.com/")
+            .transport(transport.clone())
+            .build()
+            .expect("failed to make lava");
+
+        lava.cancel_job(3).await.expect("failed to cancel job");
+        // The last matching reply is used
+        let err = lava.cancel_job(4).await.expect_err("cancelled job");
+        assert!(matches!(err, CancellationError::PermissionDenied));
+        let err = lava.cancel_job(5).await.expect_err("cancelled missing job");
+        assert!(matches!(err, CancellationError::NotFound));
+
+        let paths = transport
+            .requests()
+            .into_iter()
+            .map(|(method, url)| (method, url.path().to_string()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            paths,
+            vec![
+                (Method::GET, "/api/v0.2/jobs/3/cancel/".to_string()),
+                (Method::GET, "/api/v0.2/jobs/4/cancel/".to_string()),
+                (Method::GET, "/api/v0.2/jobs/5/cancel/".to_string()),
+            ]
+        );
+    }
+
+    #[test(tokio::test)]
+    async fn test_transport() {
+        let transport = Refuse::default();
+        let requests = transport.requests.clone();
+        let lava = Lava::builder("https://lava.example.com/")
+            .token("secret")
+            .transport(transport)
+            .build()
+            .expect("failed to make lava");
+
+        assert!(!format!("{:?}", lava).contains("secret"));
+        let err = lava.cancel_job(7).await.expect_err("cancelled job");
+        assert!(matches!(err, CancellationError::PermissionDenied));
+
+        let anonymous = Lava::builder("https://lava.example.com/")
+            .transport(Refuse {
+                requests: requests.clone(),
+            })
+            .build()
+            .expect("failed to make lava");
+        anonymous.cancel_job(8).await.expect_err("cancelled job");
+
+        assert_eq!(
+            *requests.lock().unwrap(),
+            vec![
+                (
+                    "/api/v0.2/jobs/7/cancel/".to_string(),
+                    Some("Token secret".to_string())
+                ),
+                ("/api/v0.2/jobs/8/cancel/".to_string(), None),
+            ]
+        );
+    }
+
+    // A subscriber recording the name of each span, with the name of
+    // its parent and its fields.
+    #[cfg(feature = "tracing")]
+    #[derive(Default)]
+    struct Spans {
+        spans: Mutex<Vec<SpanRecord>>,
+        stack: Mutex<Vec<u64>>,
+    }
+
+    #[cfg(feature = "tracing")]
+    struct SpanRecord {
+        name: String,
+        parent: Option<String>,
+        fields: Vec<(String, String)>,
+    }
+
+    #[cfg(feature = "tracing")]
+    struct Fields<'a>(&'a mut Vec<(String, String)>);
+
+    #[cfg(feature = "tracing")]
+    impl tracing::field::Visit for Fields<'_> {
+        fn record_debug(&mut self, field: &tracing::field::Field, value: &dyn std::fmt::Debug) {
+            self.0
+                .push((field.name().to_string(), format!("{:?}", value)));
+        }
+    }
+
+    #[cfg(feature = "tracing")]
+    impl tracing::Subscriber for Spans {
+        fn enabled(&self, _metadata: &tracing::Metadata<'_>) -> bool {
+            true
+        }
+
+        fn new_span(&self, attrs: &tracing::span::Attributes<'_>) -> tracing::Id {
+            let mut spans = self.spans.lock().unwrap();
+            let parent = match attrs.parent() {
+                Some(parent) => Some(parent.into_u64()),
+                None if attrs.is_contextual() => self.stack.lock().unwrap().last().copied(),
+                None => None,
+            };
+            let parent = parent.map(|p| spans[p as usize - 1].name.clone());
+            let mut fields = Vec::new();
+            attrs.record(&mut Fields(&mut fields));
+            spans.push(SpanRecord {
+                name: attrs.metadata().name().to_string(),
+                parent,
+                fields,
+            });
+            tracing::Id::from_u64(spans.len() as u64)
+        }
+
+        fn record(&self, span: &tracing::Id, values: &tracing::span::Record<'_>) {
+            let mut spans = self.spans.lock().unwrap();
+            values.record(&mut Fields(&mut spans[span.into_u64() as usize - 1].fields));
+        }
+
+        fn record_follows_from(&self, _span: &tracing::Id, _follows: &tracing::Id) {}
+
+        fn event(&self, _event: &tracing::Event<'_>) {}
+
+        fn enter(&self, span: &tracing::Id) {
+            self.stack.lock().unwrap().push(span.into_u64());
+        }
+
+        fn exit(&self, _span: &tracing::Id) {
+            self.stack.lock().unwrap().pop();
+        }
+    }
+
+    #[cfg(feature = "tracing")]
+    #[test(tokio::test)]
+    async fn test_spans() {
+        use futures::TryStreamExt;
+
+        #[derive(Debug)]
+        struct Empty;
+
+        impl Transport for Empty {
+            fn execute(&self, _request: Request) -> BoxFuture<'static, reqwest::Result<Response>> {
+                let body = r#"{"count": 0, "next": null, "results": []}"#;
+                let response = http::Response::builder().status(200).body(body).unwrap();
+                Box::pin(future::ok(response.into()))
+            }
+        }
+
+        let subscriber = Arc::new(Spans::default());
+        let _guard = tracing::subscriber::set_default(subscriber.clone());
+
+        let lava = Lava::builder("https://lava.example.com/")
+            .transport(Empty)
+            .build()
+            .expect("failed to make lava");
+        let workers = lava.workers();
+        // The stream is read outside the span it was created in
+        tracing::debug_span!("caller").in_scope(|| drop(lava.workers()));
+        workers
+            .try_collect::<Vec<_>>()
+            .await
+            .expect("failed to get workers");
+        lava.cancel_job(12).await.expect("failed to cancel job");
+
+        let spans = subscriber.spans.lock().unwrap();
+        let names = spans
+            .iter()
+            .map(|span| (span.name.as_str(), span.parent.as_deref()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            names,
+            vec![
+                ("paginate", None),
+                ("caller", None),
+                ("paginate", Some("caller")),
+                ("request", Some("paginate")),
+                ("request", None),
+            ]
+        );
+
+        let field = |span: usize, name: &str| {
+            spans[span]
+                .fields
+                .iter()
+                .rev()
+                .find(|(n, _)| n == name)
+                .map(|(_, v)| v.clone())
+        };
+        assert_eq!(
+            field(3, "url").as_deref(),
+            Some("https://lava.example.com/api/v0.2/workers/")
+        );
+        assert_eq!(field(3, "status").as_deref(), Some("200"));
+        assert_eq!(field(3, "job").as_deref(), None);
+        assert_eq!(field(4, "method").as_deref(), Some("GET"));
+        assert_eq!(field(4, "job").as_deref(), Some("12"));
+    }
+}
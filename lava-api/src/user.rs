@@ -0,0 +1,229 @@
+//! Retrieve the user on whose behalf requests are made
+
+use reqwest::StatusCode;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::error::{Classify, ErrorClass};
+use crate::job::JobsBuilder;
+use crate::transport;
+use crate::Lava;
+
+#[derive(Error, Debug)]
+pub enum WhoamiError {
+    #[error("User request failed")]
+    Request(#[from] reqwest::Error),
+    #[error("Token not accepted by server")]
+    InvalidToken,
+    #[error("Unexpected reply to user request: {0}")]
+    UnexpectedReply(reqwest::StatusCode),
+}
+
+impl Classify for WhoamiError {
+    fn class(&self) -> ErrorClass {
+        match self {
+            WhoamiError::Request(e) => e.class(),
+            WhoamiError::InvalidToken => ErrorClass::Auth,
+            WhoamiError::UnexpectedReply(s) => s.class(),
+        }
+    }
+}
+
+#[derive(Error, Debug)]
+pub enum MyJobsError {
+    #[error("Failed to identify user")]
+    Whoami(#[from] WhoamiError),
+    #[error("No user to find the jobs of, as no token was given")]
+    Anonymous,
+}
+
+impl Classify for MyJobsError {
+    fn class(&self) -> ErrorClass {
+        match self {
+            MyJobsError::Whoami(e) => e.class(),
+            MyJobsError::Anonymous => ErrorClass::Auth,
+        }
+    }
+}
+
+/// The user authenticated by a [`Lava`] instance's token.
+///
+/// Some servers report only the name of the user, in which case the
+/// other fields are left empty.
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq, Eq)]
+pub struct Profile {
+    pub username: String,
+    #[serde(default)]
+    pub email: Option<String>,
+    #[serde(default)]
+    pub is_superuser: bool,
+    /// The names of the groups the user belongs to.
+    #[serde(default)]
+    pub groups: Vec<String>,
+    /// The permissions held by the user, as Django permission names
+    /// such as `lava_scheduler_app.cancel_resubmit_testjob`.
+    #[serde(default)]
+    pub permissions: Vec<String>,
+}
+
+impl Profile {
+    /// Whether the user holds the given permission, either directly
+    /// or by being a superuser.
+    pub fn has_permission(&self, permission: &str) -> bool {
+        self.is_superuser || self.permissions.iter().any(|p| p == permission)
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+enum WhoamiReply {
+    Anonymous(()),
+    Username(String),
+    Profile(Profile),
+}
+
+impl From<WhoamiReply> for Option<Profile> {
+    fn from(reply: WhoamiReply) -> Self {
+        match reply {
+            WhoamiReply::Anonymous(_) => None,
+            WhoamiReply::Username(username) if username.is_empty() => None,
+            WhoamiReply::Username(username) => Some(Profile {
+                username,
+                email: None,
+                is_superuser: false,
+                groups: Vec::new(),
+                permissions: Vec::new(),
+            }),
+            WhoamiReply::Profile(profile) => Some(profile),
+        }
+    }
+}
+
+/// Retrieve the [`Profile`] of the user whose token the given
+/// [`Lava`] uses, or `None` if it makes anonymous requests.
+pub async fn whoami(lava: &Lava) -> Result<Option<Profile>, WhoamiError> {
+    let url = lava
+        .base
+        .join("system/whoami/")
+        .expect("Failed to append to base url");
+
+    let res = lava.transport.execute(transport::get(url)).await?;
+
+    match res.status() {
+        StatusCode::OK => Ok(res.json::<WhoamiReply>().await?.into()),
+        StatusCode::UNAUTHORIZED | StatusCode::FORBIDDEN => Err(WhoamiError::InvalidToken),
+        s => Err(WhoamiError::UnexpectedReply(s)),
+    }
+}
+
+/// Obtain a query for the jobs submitted by the user whose token the
+/// given [`Lava`] uses.
+///
+/// This asks the server who the user is with [`whoami`], and then
+/// restricts a [`JobsBuilder`] to the jobs they submitted, to which
+/// further filters can be added before querying. Anonymous requests
+/// have no user, and give [`MyJobsError::Anonymous`].
+pub async fn my_jobs(lava: &Lava) -> Result<JobsBuilder<'_>, MyJobsError> {
+    let profile = whoami(lava).await?.ok_or(MyJobsError::Anonymous)?;
+    Ok(lava.jobs().submitter(profile.username))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{MyJobsError, Profile, WhoamiError, WhoamiReply};
+    use crate::Lava;
+
+    use boulder::{BuildableWithPersianRug, BuilderWithPersianRug};
+    use futures::TryStreamExt;
+    use lava_api_mock::{Group, LavaMock, PaginationLimits, SharedState, User};
+    use persian_rug::Proxy;
+    use test_log::test;
+
+    #[test(tokio::test)]
+    async fn test_whoami() {
+        let mut state = SharedState::new();
+        let (group, m) = Proxy::<Group<_>>::builder()
+            .name("lab")
+            .build(state.mutate());
+        let _ = Proxy::<User<_>>::builder()
+            .username("fred")
+            .email(Some("fred@example.com".to_string()))
+            .group(Some(group))
+            .token(Some("fred-token".to_string()))
+            .build(m);
+        let server = LavaMock::new(state, PaginationLimits::new()).await;
+
+        let lava = Lava::new(&server.uri(), Some("fred-token".to_string()))
+            .expect("failed to make lava server");
+        let profile = lava
+            .whoami()
+            .await
+            .expect("failed to get user")
+            .expect("no user for token");
+        assert_eq!(profile.username, "fred");
+        assert_eq!(profile.email.as_deref(), Some("fred@example.com"));
+        assert_eq!(profile.groups, vec!["lab".to_string()]);
+        assert!(!profile.is_superuser);
+
+        let anonymous = Lava::new(&server.uri(), None).expect("failed to make lava server");
+        assert_eq!(anonymous.whoami().await.expect("failed to get user"), None);
+
+        let invalid = Lava::new(&server.uri(), Some("bad-token".to_string()))
+            .expect("failed to make lava server");
+        let err = invalid.whoami().await.expect_err("accepted a bad token");
+        assert!(matches!(err, WhoamiError::InvalidToken));
+    }
+
+    #[test(tokio::test)]
+    async fn test_my_jobs() {
+        let mut state = SharedState::new();
+        let (fred, m) = Proxy::<User<_>>::builder()
+            .username("fred")
+            .token(Some("fred-token".to_string()))
+            .build(state.mutate());
+        let (jim, _) = Proxy::<User<_>>::builder().username("jim").build(m);
+        let submitters = [fred, jim, fred];
+        state.add_jobs(submitters.len(), |i, job| job.submitter = submitters[i]);
+        let server = LavaMock::new(state, PaginationLimits::new()).await;
+
+        let lava = Lava::new(&server.uri(), Some("fred-token".to_string()))
+            .expect("failed to make lava server");
+        let jobs = lava
+            .my_jobs()
+            .await
+            .expect("failed to identify user")
+            .query()
+            .try_collect::<Vec<_>>()
+            .await
+            .expect("failed to query jobs");
+        assert_eq!(
+            jobs.iter().map(|job| job.id).collect::<Vec<_>>(),
+            vec![0, 2]
+        );
+        assert!(jobs.iter().all(|job| job.submitter == "fred"));
+
+        let anonymous = Lava::new(&server.uri(), None).expect("failed to make lava server");
+        let err = anonymous.my_jobs().await.expect_err("found jobs of nobody");
+        assert!(matches!(err, MyJobsError::Anonymous));
+    }
+
+    #[test]
+    fn test_reply() {
+        let reply: WhoamiReply = serde_json::from_str("\"fred\"").unwrap();
+        let profile: Option<Profile> = reply.into();
+        assert_eq!(profile.map(|p| p.username), Some("fred".to_string()));
+
+        for anonymous in ["null", "\"\""] {
+            let reply: WhoamiReply = serde_json::from_str(anonymous).unwrap();
+            assert_eq!(Option::<Profile>::from(reply), None);
+        }
+
+        let reply: WhoamiReply = serde_json::from_str(
+            r#"{"username": "admin", "is_superuser": true, "permissions": []}"#,
+        )
+        .unwrap();
+        let profile = Option::<Profile>::from(reply).unwrap();
+        assert!(profile.has_permission("lava_scheduler_app.cancel_resubmit_testjob"));
+        assert!(profile.groups.is_empty());
+    }
+}
@@ -0,0 +1,468 @@
+//! Follow the progress of a job
+//!
+//! A [`JobWatch`], obtained from [`Lava::watch_job`], polls the
+//! server for a job until it finishes, and returns a [`JobEvent`] for
+//! each change it sees along the way. This saves consumers which
+//! need to wait for a job, or report on its progress, from writing
+//! their own polling loop. To just wait for a job to finish, use
+//! [`wait_for_job`] instead.
+//!
+//! Example:
+//! ```rust,no_run
+//! use futures::stream::TryStreamExt;
+//! use lava_api::watch::JobEvent;
+//! use lava_api::Lava;
+//! use std::time::Duration;
+//!
+//! # tokio_test::block_on( async {
+//! let lava = Lava::new("https://lava.example.com/", None).unwrap();
+//! let mut events = lava.watch_job(1234).interval(Duration::from_secs(30));
+//! while let Some(event) = events.try_next().await.expect("failed to poll job") {
+//!     match event {
+//!         JobEvent::StateChanged { to, .. } => println!("Job is now {}", to),
+//!         JobEvent::HealthChanged { to, .. } => println!("Job health is now {}", to),
+//!         JobEvent::LogAvailable => println!("Job log can now be read"),
+//!     }
+//! }
+//! # });
+//! ```
+
+use futures::stream::{self, BoxStream, Stream, StreamExt};
+use serde::Serialize;
+use std::collections::VecDeque;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use std::time::Duration;
+use thiserror::Error;
+
+use crate::error::{Classify, ErrorClass};
+use crate::job::{self, Health, Job, JobError, State};
+use crate::retry::random_fraction;
+use crate::Lava;
+
+/// The default interval between polls of a watched job
+const DEFAULT_INTERVAL: Duration = Duration::from_secs(10);
+
+/// The default maximum random delay added to each interval
+const DEFAULT_JITTER: Duration = Duration::from_secs(2);
+
+/// A change to a watched job
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub enum JobEvent {
+    /// The state of the job changed
+    ///
+    /// The first poll of the job reports its state with no `from`.
+    StateChanged { from: Option<State>, to: State },
+    /// The health of the job changed
+    ///
+    /// The first poll of the job reports its health with no `from`.
+    HealthChanged { from: Option<Health>, to: Health },
+    /// The job has started, so its log can be read
+    ///
+    /// This is returned at most once, and not at all for jobs which
+    /// finish without starting, such as those canceled while queued.
+    LogAvailable,
+}
+
+#[derive(Error, Debug)]
+pub enum WatchError {
+    #[error("Failed to poll job")]
+    Poll(#[from] JobError),
+    #[error("Job {0} not found")]
+    NotFound(i64),
+}
+
+impl Classify for WatchError {
+    fn class(&self) -> ErrorClass {
+        match self {
+            WatchError::Poll(e) => e.class(),
+            WatchError::NotFound(_) => ErrorClass::NotFound,
+        }
+    }
+}
+
+#[derive(Error, Debug)]
+pub enum WaitError {
+    #[error("Failed to poll job")]
+    Poll(#[from] JobError),
+    #[error("Job {0} not found")]
+    NotFound(i64),
+    #[error("Timed out waiting for job {0} to finish")]
+    Timeout(i64),
+}
+
+impl Classify for WaitError {
+    fn class(&self) -> ErrorClass {
+        match self {
+            WaitError::Poll(e) => e.class(),
+            WaitError::NotFound(_) => ErrorClass::NotFound,
+            WaitError::Timeout(_) => ErrorClass::Other,
+        }
+    }
+}
+
+/// Wait for the job with the given id to finish, and return its final
+/// record.
+///
+/// The job is polled every `poll_interval` until it reaches
+/// [`State::Finished`], which includes jobs which were canceled; the
+/// [`health`](Job::health) of the returned job says how it ended. If
+/// it has not finished within `timeout`, [`WaitError::Timeout`] is
+/// returned. A failed poll is returned as an error straight away,
+/// after any retries made by the [`Lava`]'s retry policy.
+///
+/// The returned future holds no state other than the poll in
+/// progress, so it can be dropped at any point, for example when it
+/// loses a [`tokio::select!`], and created again later.
+pub async fn wait_for_job(
+    lava: &Lava,
+    id: i64,
+    timeout: Duration,
+    poll_interval: Duration,
+) -> Result<Job, WaitError> {
+    let wait = async {
+        loop {
+            match job::job(lava, id).await? {
+                Some(job) if job.state == State::Finished => return Ok(job),
+                Some(_) => tokio::time::sleep(poll_interval).await,
+                None => return Err(WaitError::NotFound(id)),
+            }
+        }
+    };
+    tokio::time::timeout(timeout, wait)
+        .await
+        .unwrap_or(Err(WaitError::Timeout(id)))
+}
+
+/// A stream of the [`JobEvent`]s of a job
+///
+/// The stream ends once the job reaches [`State::Finished`], after
+/// returning the events for the poll which saw that. A poll which
+/// fails is returned as a [`WatchError::Poll`], after which the job
+/// is polled again at the next interval, so callers can choose
+/// whether to give up. If the job does not exist, or is not visible
+/// to the user, [`WatchError::NotFound`] is returned and the stream
+/// ends.
+///
+/// The interval and jitter must be set before the stream is first
+/// polled. Dropping the stream stops the polling, including any
+/// request in flight.
+pub struct JobWatch<'a> {
+    lava: &'a Lava,
+    id: i64,
+    interval: Duration,
+    jitter: Duration,
+    inner: Option<BoxStream<'a, Result<JobEvent, WatchError>>>,
+}
+
+impl<'a> JobWatch<'a> {
+    pub fn new(lava: &'a Lava, id: i64) -> Self {
+        Self {
+            lava,
+            id,
+            interval: DEFAULT_INTERVAL,
+            jitter: DEFAULT_JITTER,
+            inner: None,
+        }
+    }
+
+    /// Set the interval between polls of the job.
+    ///
+    /// The default is 10 seconds.
+    pub fn interval(mut self, interval: Duration) -> Self {
+        self.interval = interval;
+        self
+    }
+
+    /// Set the maximum random delay added to each interval.
+    ///
+    /// This spreads out the polls of many watchers started together,
+    /// so that they do not all reach the server at once. The default
+    /// is 2 seconds; a zero jitter polls at exactly the interval.
+    pub fn jitter(mut self, jitter: Duration) -> Self {
+        self.jitter = jitter;
+        self
+    }
+}
+
+impl<'a> Stream for JobWatch<'a> {
+    type Item = Result<JobEvent, WatchError>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = &mut *self;
+        let inner = match &mut this.inner {
+            Some(inner) => inner,
+            inner => inner.insert(
+                Watching::new(this.lava, this.id, this.interval, this.jitter)
+                    .stream()
+                    .boxed(),
+            ),
+        };
+        inner.poll_next_unpin(cx)
+    }
+}
+
+/// The progress of a [`JobWatch`] through the polls of its job
+struct Watching<'a> {
+    lava: &'a Lava,
+    id: i64,
+    interval: Duration,
+    jitter: Duration,
+    last: Option<(State, Health)>,
+    started: bool,
+    polled: bool,
+    done: bool,
+    pending: VecDeque<JobEvent>,
+}
+
+impl<'a> Watching<'a> {
+    fn new(lava: &'a Lava, id: i64, interval: Duration, jitter: Duration) -> Self {
+        Self {
+            lava,
+            id,
+            interval,
+            jitter,
+            last: None,
+            started: false,
+            polled: false,
+            done: false,
+            pending: VecDeque::new(),
+        }
+    }
+
+    fn delay(&self) -> Duration {
+        self.interval + self.jitter.mul_f64(random_fraction())
+    }
+
+    fn update(&mut self, job: &Job) {
+        let (from_state, from_health) = match self.last {
+            Some((state, health)) => (Some(state), Some(health)),
+            None => (None, None),
+        };
+        if from_state != Some(job.state) {
+            self.pending.push_back(JobEvent::StateChanged {
+                from: from_state,
+                to: job.state,
+            });
+        }
+        if from_health != Some(job.health) {
+            self.pending.push_back(JobEvent::HealthChanged {
+                from: from_health,
+                to: job.health,
+            });
+        }
+        if !self.started && job.start_time.is_some() {
+            self.started = true;
+            self.pending.push_back(JobEvent::LogAvailable);
+        }
+        self.last = Some((job.state, job.health));
+        self.done = job.state == State::Finished;
+    }
+
+    fn stream(self) -> impl Stream<Item = Result<JobEvent, WatchError>> + Send + 'a {
+        stream::unfold(self, |mut w| async move {
+            loop {
+                if let Some(event) = w.pending.pop_front() {
+                    return Some((Ok(event), w));
+                }
+                if w.done {
+                    return None;
+                }
+                if w.polled {
+                    tokio::time::sleep(w.delay()).await;
+                }
+                w.polled = true;
+                match job::job(w.lava, w.id).await {
+                    Ok(Some(job)) => w.update(&job),
+                    Ok(None) => {
+                        w.done = true;
+                        return Some((Err(WatchError::NotFound(w.id)), w));
+                    }
+                    Err(e) => return Some((Err(e.into()), w)),
+                }
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{JobEvent, WaitError, WatchError};
+    use crate::job::{Health, State};
+    use crate::Lava;
+
+    use futures::StreamExt;
+    use serde_json::json;
+    use std::time::Duration;
+    use test_log::test;
+    use wiremock::matchers::{method, path};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    fn job_json(state: &str, health: &str, started: bool) -> serde_json::Value {
+        json!({
+            "id": 5,
+            "submitter": "user",
+            "viewing_groups": [],
+            "description": "watched job",
+            "health_check": false,
+            "requested_device_type": "device-type",
+            "tags": [],
+            "actual_device": "device",
+            "submit_time": "2022-04-11T09:59:00Z",
+            "start_time": if started { json!("2022-04-11T10:00:00Z") } else { json!(null) },
+            "end_time": null,
+            "state": state,
+            "health": health,
+            "priority": 50,
+            "definition": "",
+            "original_definition": "",
+            "multinode_definition": "",
+            "failure_tags": [],
+            "failure_comment": null,
+        })
+    }
+
+    #[test(tokio::test)]
+    async fn test_watch() {
+        let server = MockServer::start().await;
+        for (status, body) in [
+            (200, job_json("Submitted", "Unknown", false)),
+            (200, job_json("Submitted", "Unknown", false)),
+            (503, json!({})),
+            (200, job_json("Running", "Unknown", true)),
+        ] {
+            Mock::given(method("GET"))
+                .and(path("/api/v0.2/jobs/5/"))
+                .respond_with(ResponseTemplate::new(status).set_body_json(body))
+                .up_to_n_times(1)
+                .mount(&server)
+                .await;
+        }
+        Mock::given(method("GET"))
+            .and(path("/api/v0.2/jobs/5/"))
+            .respond_with(
+                ResponseTemplate::new(200).set_body_json(job_json("Finished", "Complete", true)),
+            )
+            .mount(&server)
+            .await;
+
+        let lava = Lava::new(&server.uri(), None).expect("failed to make lava server");
+        let events = lava
+            .watch_job(5)
+            .interval(Duration::from_millis(10))
+            .jitter(Duration::ZERO)
+            .collect::<Vec<_>>()
+            .await;
+
+        assert_eq!(events.len(), 7);
+        assert!(matches!(
+            events[2],
+            Err(WatchError::Poll(crate::job::JobError::UnexpectedReply(
+                reqwest::StatusCode::SERVICE_UNAVAILABLE
+            )))
+        ));
+        let events = events
+            .into_iter()
+            .filter_map(Result::ok)
+            .collect::<Vec<_>>();
+        assert_eq!(
+            events,
+            vec![
+                JobEvent::StateChanged {
+                    from: None,
+                    to: State::Submitted
+                },
+                JobEvent::HealthChanged {
+                    from: None,
+                    to: Health::Unknown
+                },
+                JobEvent::StateChanged {
+                    from: Some(State::Submitted),
+                    to: State::Running
+                },
+                JobEvent::LogAvailable,
+                JobEvent::StateChanged {
+                    from: Some(State::Running),
+                    to: State::Finished
+                },
+                JobEvent::HealthChanged {
+                    from: Some(Health::Unknown),
+                    to: Health::Complete
+                },
+            ]
+        );
+
+        let server = MockServer::start().await;
+        let lava = Lava::new(&server.uri(), None).expect("failed to make lava server");
+        let events = lava.watch_job(6).collect::<Vec<_>>().await;
+        assert_eq!(events.len(), 1);
+        assert!(matches!(events[0], Err(WatchError::NotFound(6))));
+    }
+
+    #[test(tokio::test)]
+    async fn test_wait_for_job() {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/api/v0.2/jobs/5/"))
+            .respond_with(
+                ResponseTemplate::new(200).set_body_json(job_json("Running", "Unknown", true)),
+            )
+            .up_to_n_times(2)
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/api/v0.2/jobs/5/"))
+            .respond_with(
+                ResponseTemplate::new(200).set_body_json(job_json("Finished", "Canceled", true)),
+            )
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/api/v0.2/jobs/7/"))
+            .respond_with(
+                ResponseTemplate::new(200).set_body_json(job_json("Running", "Unknown", true)),
+            )
+            .mount(&server)
+            .await;
+
+        let lava = Lava::new(&server.uri(), None).expect("failed to make lava server");
+        let job = lava
+            .wait_for_job(5, Duration::from_secs(10), Duration::from_millis(10))
+            .await
+            .expect("failed to wait for job");
+        assert_eq!(job.state, State::Finished);
+        assert_eq!(job.health, Health::Canceled);
+
+        let err = lava
+            .wait_for_job(7, Duration::from_millis(50), Duration::from_millis(10))
+            .await
+            .expect_err("job finished unexpectedly");
+        assert!(matches!(err, WaitError::Timeout(7)));
+
+        let err = lava
+            .wait_for_job(6, Duration::from_secs(10), Duration::from_millis(10))
+            .await
+            .expect_err("found missing job");
+        assert!(matches!(err, WaitError::NotFound(6)));
+
+        // Losing a select leaves nothing running
+        tokio::select! {
+            _ = lava.wait_for_job(7, Duration::from_secs(10), Duration::from_millis(10)) => {
+                panic!("job finished unexpectedly")
+            }
+            _ = tokio::time::sleep(Duration::from_millis(30)) => (),
+        }
+        let polls = || async {
+            server
+                .received_requests()
+                .await
+                .expect("requests not recorded")
+                .iter()
+                .filter(|r| r.url.path() == "/api/v0.2/jobs/7/")
+                .count()
+        };
+        let before = polls().await;
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        assert_eq!(polls().await, before);
+    }
+}
@@ -1,11 +1,27 @@
-//! Retrieve workers
+//! Retrieve and manage workers
 
-use serde::Deserialize;
-use serde_with::DeserializeFromStr;
+use chrono::{DateTime, Utc};
+use futures::TryStreamExt;
+use reqwest::StatusCode;
+use serde::{Deserialize, Serialize};
+use serde_with::{DeserializeFromStr, SerializeDisplay};
+use std::collections::HashMap;
+use std::time::Duration;
 use strum::{Display, EnumString};
+use thiserror::Error;
+use url::Url;
+
+use crate::datetime;
+use crate::error::{Classify, ErrorClass};
+use crate::job;
+use crate::paginator::{PaginationError, Paginator};
+use crate::transport;
+use crate::Lava;
 
 /// The current usage of a worker
-#[derive(Copy, Clone, Debug, DeserializeFromStr, Display, EnumString, PartialEq, Eq)]
+#[derive(
+    Copy, Clone, Debug, DeserializeFromStr, Display, EnumString, PartialEq, Eq, SerializeDisplay,
+)]
 pub enum Health {
     Active,
     Maintenance,
@@ -13,27 +29,638 @@ pub enum Health {
 }
 
 /// The online status of a worker
-#[derive(Copy, Clone, Debug, DeserializeFromStr, Display, EnumString, PartialEq, Eq)]
+#[derive(
+    Copy, Clone, Debug, DeserializeFromStr, Display, EnumString, PartialEq, Eq, SerializeDisplay,
+)]
 pub enum State {
     Online,
     Offline,
 }
 
+/// The possible orderings in which workers can be returned
+///
+/// These are usually combined with a [`bool`] in use, indicating
+/// whether the order is to be ascending or descending.
+#[derive(
+    Copy, Clone, Debug, Default, Hash, PartialEq, Eq, Display, EnumString, DeserializeFromStr,
+)]
+#[strum(serialize_all = "snake_case")]
+pub enum Ordering {
+    #[default]
+    Hostname,
+    Description,
+    LastPing,
+    State,
+    Health,
+}
+
 /// A subset of the available data for a worker from LAVA
-#[derive(Clone, Deserialize, Debug)]
+#[derive(Clone, Deserialize, Serialize, Debug)]
 pub struct Worker {
     pub hostname: String,
     pub state: State,
     pub health: Health,
+    /// The maximum number of jobs the worker may run at once, where
+    /// zero means there is no limit.
+    pub job_limit: i64,
+    /// When the worker last contacted the server, if it ever has
+    #[serde(default, deserialize_with = "datetime::deserialize_option")]
+    pub last_ping: Option<DateTime<Utc>>,
+    /// The version of LAVA the worker runs, as it last reported
+    #[serde(default)]
+    pub version: Option<String>,
+    /// The version of the server the worker's administrators were
+    /// last notified of, when it differed from the worker's
+    #[serde(default)]
+    pub master_version_notified: Option<String>,
+}
+
+impl Worker {
+    /// Whether the worker has not contacted the server in the last
+    /// `threshold`, or never has.
+    ///
+    /// A worker which is online pings the server every few seconds,
+    /// so one which has stopped can be detected before the server
+    /// marks it offline.
+    pub fn is_stale(&self, threshold: Duration) -> bool {
+        self.is_stale_at(threshold, Utc::now())
+    }
+
+    fn is_stale_at(&self, threshold: Duration, now: DateTime<Utc>) -> bool {
+        match (self.last_ping, chrono::Duration::from_std(threshold)) {
+            (Some(last_ping), Ok(threshold)) => now - last_ping > threshold,
+            (Some(_), Err(_)) => false,
+            (None, _) => true,
+        }
+    }
+}
+
+/// Select the workers to return from a query.
+///
+/// This is obtained from [`Lava::workers_builder`], and its
+/// [`query`](WorkersBuilder::query) method returns a stream of the
+/// matching workers.
+///
+/// The server only filters workers by a single health and a single
+/// state, so calling [`health`](WorkersBuilder::health) or
+/// [`state`](WorkersBuilder::state) again replaces the earlier value.
+///
+/// Example:
+/// ```rust
+/// use futures::stream::TryStreamExt;
+/// # use lava_api_mock::{LavaMock, PaginationLimits, PopulationParams, SharedState};
+/// use lava_api::Lava;
+/// use lava_api::worker::{Health, Ordering};
+/// #
+/// # tokio_test::block_on( async {
+/// # let limits = PaginationLimits::new();
+/// # let population = PopulationParams::new();
+/// # let mock = LavaMock::new(SharedState::new_populated(population), limits).await;
+/// # let service_uri = mock.uri();
+/// # let lava_token = None;
+///
+/// let lava = Lava::new(&service_uri, lava_token).expect("failed to make lava");
+///
+/// let mut workers = lava
+///     .workers_builder()
+///     .health(Health::Active)
+///     .ordering(Ordering::LastPing, false)
+///     .query();
+///
+/// while let Some(worker) = workers.try_next().await.expect("failed to get worker") {
+///     println!("Worker {} is {}", worker.hostname, worker.state);
+/// }
+/// # });
+/// ```
+pub struct WorkersBuilder<'a> {
+    lava: &'a Lava,
+    health: Option<Health>,
+    state: Option<State>,
+    hostname_contains: Option<String>,
+    last_ping_after: Option<DateTime<Utc>>,
+    last_ping_before: Option<DateTime<Utc>>,
+    limit: Option<u32>,
+    ordering: Ordering,
+    ascending: bool,
+    raw_params: Vec<(String, String)>,
+}
+
+impl<'a> WorkersBuilder<'a> {
+    /// Create a new [`WorkersBuilder`]
+    ///
+    /// The default query is:
+    /// - order by [`Ordering::Hostname`]
+    /// - no filtering
+    /// - default result pagination
+    pub fn new(lava: &'a Lava) -> Self {
+        Self {
+            lava,
+            health: None,
+            state: None,
+            hostname_contains: None,
+            last_ping_after: None,
+            last_ping_before: None,
+            limit: None,
+            ordering: Ordering::Hostname,
+            ascending: true,
+            raw_params: Vec::new(),
+        }
+    }
+
+    /// Return only workers with this health.
+    ///
+    /// Example:
+    /// ```rust
+    /// use futures::stream::TryStreamExt;
+    /// # use lava_api_mock::WorkerHealth;
+    /// use lava_api::{Lava, worker::Health};
+    /// #
+    /// # tokio_test::block_on( async {
+    /// # let mock = lava_api_mock::doctest::workers(|state| {
+    /// #     state.add_workers(1, |_, worker| worker.health = WorkerHealth::Maintenance);
+    /// #     state.add_workers(2, |_, worker| worker.health = WorkerHealth::Active);
+    /// # })
+    /// # .await;
+    /// # let service_uri = mock.uri();
+    /// let lava = Lava::new(&service_uri, None).expect("failed to make lava");
+    ///
+    /// let workers: Vec<_> = lava
+    ///     .workers_builder()
+    ///     .health(Health::Maintenance)
+    ///     .query()
+    ///     .try_collect()
+    ///     .await
+    ///     .expect("failed to query workers");
+    /// assert_eq!(workers.len(), 1);
+    /// assert_eq!(workers[0].health, Health::Maintenance);
+    /// # });
+    /// ```
+    pub fn health(mut self, health: Health) -> Self {
+        self.health = Some(health);
+        self
+    }
+
+    /// Return only workers in this state.
+    ///
+    /// Example:
+    /// ```rust
+    /// use futures::stream::TryStreamExt;
+    /// # use lava_api_mock::WorkerState;
+    /// use lava_api::{Lava, worker::State};
+    /// #
+    /// # tokio_test::block_on( async {
+    /// # let mock = lava_api_mock::doctest::workers(|state| {
+    /// #     state.add_workers(1, |_, worker| worker.state = WorkerState::Offline);
+    /// #     state.add_workers(2, |_, worker| worker.state = WorkerState::Online);
+    /// # })
+    /// # .await;
+    /// # let service_uri = mock.uri();
+    /// let lava = Lava::new(&service_uri, None).expect("failed to make lava");
+    ///
+    /// let workers: Vec<_> = lava
+    ///     .workers_builder()
+    ///     .state(State::Offline)
+    ///     .query()
+    ///     .try_collect()
+    ///     .await
+    ///     .expect("failed to query workers");
+    /// assert_eq!(workers.len(), 1);
+    /// assert_eq!(workers[0].state, State::Offline);
+    /// # });
+    /// ```
+    pub fn state(mut self, state: State) -> Self {
+        self.state = Some(state);
+        self
+    }
+
+    /// Return only workers whose hostname contains `text`.
+    ///
+    /// Example:
+    /// ```rust
+    /// use futures::stream::TryStreamExt;
+    /// use lava_api::Lava;
+    /// #
+    /// # tokio_test::block_on( async {
+    /// # let mock = lava_api_mock::doctest::workers(|state| {
+    /// #     state.add_workers(2, |i, worker| worker.hostname = format!("lab-a-{}", i));
+    /// #     state.add_workers(1, |_, worker| worker.hostname = "other".to_string());
+    /// # })
+    /// # .await;
+    /// # let service_uri = mock.uri();
+    /// let lava = Lava::new(&service_uri, None).expect("failed to make lava");
+    ///
+    /// let workers: Vec<_> = lava
+    ///     .workers_builder()
+    ///     .hostname_contains("lab")
+    ///     .query()
+    ///     .try_collect()
+    ///     .await
+    ///     .expect("failed to query workers");
+    /// let hostnames: Vec<_> = workers.iter().map(|worker| worker.hostname.as_str()).collect();
+    /// assert_eq!(hostnames, vec!["lab-a-0", "lab-a-1"]);
+    /// # });
+    /// ```
+    pub fn hostname_contains<T: Into<String>>(mut self, text: T) -> Self {
+        self.hostname_contains = Some(text.into());
+        self
+    }
+
+    /// Return only workers which last pinged the server after the
+    /// given time.
+    ///
+    /// Example:
+    /// ```rust
+    /// use futures::stream::TryStreamExt;
+    /// use chrono::{DateTime, Duration, Utc};
+    /// use lava_api::Lava;
+    /// #
+    /// # tokio_test::block_on( async {
+    /// # let mock = lava_api_mock::doctest::workers(|state| {
+    /// #     let start: DateTime<Utc> = "2022-03-17T17:00:00Z".parse().unwrap();
+    /// #     state.add_workers(4, |i, worker| {
+    /// #         worker.last_ping = Some(start + Duration::hours(i as i64));
+    /// #     });
+    /// # })
+    /// # .await;
+    /// # let service_uri = mock.uri();
+    /// let lava = Lava::new(&service_uri, None).expect("failed to make lava");
+    ///
+    /// let cutoff: DateTime<Utc> = "2022-03-17T18:30:00Z".parse().unwrap();
+    /// let workers: Vec<_> = lava
+    ///     .workers_builder()
+    ///     .last_ping_after(cutoff)
+    ///     .query()
+    ///     .try_collect()
+    ///     .await
+    ///     .expect("failed to query workers");
+    /// assert_eq!(workers.len(), 2);
+    /// # });
+    /// ```
+    pub fn last_ping_after(mut self, when: DateTime<Utc>) -> Self {
+        self.last_ping_after = Some(when);
+        self
+    }
+
+    /// Return only workers which last pinged the server before the
+    /// given time.
+    ///
+    /// Workers which have never pinged the server are not returned.
+    ///
+    /// Example:
+    /// ```rust
+    /// use futures::stream::TryStreamExt;
+    /// use chrono::{DateTime, Duration, Utc};
+    /// use lava_api::Lava;
+    /// #
+    /// # tokio_test::block_on( async {
+    /// # let mock = lava_api_mock::doctest::workers(|state| {
+    /// #     let start: DateTime<Utc> = "2022-03-17T17:00:00Z".parse().unwrap();
+    /// #     state.add_workers(4, |i, worker| {
+    /// #         worker.last_ping = Some(start + Duration::hours(i as i64));
+    /// #     });
+    /// # })
+    /// # .await;
+    /// # let service_uri = mock.uri();
+    /// let lava = Lava::new(&service_uri, None).expect("failed to make lava");
+    ///
+    /// let cutoff: DateTime<Utc> = "2022-03-17T18:30:00Z".parse().unwrap();
+    /// let workers: Vec<_> = lava
+    ///     .workers_builder()
+    ///     .last_ping_before(cutoff)
+    ///     .query()
+    ///     .try_collect()
+    ///     .await
+    ///     .expect("failed to query workers");
+    /// assert_eq!(workers.len(), 2);
+    /// # });
+    /// ```
+    pub fn last_ping_before(mut self, when: DateTime<Utc>) -> Self {
+        self.last_ping_before = Some(when);
+        self
+    }
+
+    /// Return only workers which have not pinged the server for at
+    /// least `age`, as measured by the local clock.
+    ///
+    /// This is [`last_ping_before`](Self::last_ping_before) the
+    /// current time less `age`, so that stale workers can be found
+    /// without fetching every worker. As there, workers which have
+    /// never pinged the server are not returned.
+    ///
+    /// Example:
+    /// ```rust
+    /// use futures::stream::TryStreamExt;
+    /// use chrono::{Duration, Utc};
+    /// use lava_api::Lava;
+    /// #
+    /// # tokio_test::block_on( async {
+    /// # let mock = lava_api_mock::doctest::workers(|state| {
+    /// #     let now = Utc::now();
+    /// #     state.add_workers(4, |i, worker| {
+    /// #         worker.last_ping = Some(now - Duration::minutes(10 * i as i64));
+    /// #     });
+    /// # })
+    /// # .await;
+    /// # let service_uri = mock.uri();
+    /// let lava = Lava::new(&service_uri, None).expect("failed to make lava");
+    ///
+    /// let stale: Vec<_> = lava
+    ///     .workers_builder()
+    ///     .not_pinged_for(Duration::minutes(15))
+    ///     .query()
+    ///     .try_collect()
+    ///     .await
+    ///     .expect("failed to query workers");
+    /// assert_eq!(stale.len(), 2);
+    /// # });
+    /// ```
+    pub fn not_pinged_for(self, age: chrono::Duration) -> Self {
+        self.last_ping_before(Utc::now() - age)
+    }
+
+    /// Set the number of workers retrieved at a time while the query
+    /// is running.
+    ///
+    /// This is a page size, and has the same caveats as
+    /// [`JobsBuilder::limit`](crate::job::JobsBuilder::limit).
+    pub fn limit(mut self, limit: u32) -> Self {
+        self.limit = Some(limit);
+        self
+    }
+
+    /// Add a query parameter which is passed to the server unchanged.
+    ///
+    /// This gives access to filters the server supports which are
+    /// not otherwise available here, for example
+    /// `description__contains`, with the same caveats as for
+    /// [`JobsBuilder::raw_param`](crate::job::JobsBuilder::raw_param).
+    ///
+    /// Example:
+    /// ```rust
+    /// use futures::stream::TryStreamExt;
+    /// use lava_api::Lava;
+    /// #
+    /// # tokio_test::block_on( async {
+    /// # let mock = lava_api_mock::doctest::workers(|state| {
+    /// #     state.add_workers(1, |_, worker| worker.description = Some("Rack A".to_string()));
+    /// #     state.add_workers(2, |_, worker| worker.description = Some("Rack B".to_string()));
+    /// # })
+    /// # .await;
+    /// # let service_uri = mock.uri();
+    /// let lava = Lava::new(&service_uri, None).expect("failed to make lava");
+    ///
+    /// let workers: Vec<_> = lava
+    ///     .workers_builder()
+    ///     .raw_param("description__contains", "Rack A")
+    ///     .query()
+    ///     .try_collect()
+    ///     .await
+    ///     .expect("failed to query workers");
+    /// assert_eq!(workers.len(), 1);
+    /// # });
+    /// ```
+    pub fn raw_param<K: Into<String>, V: Into<String>>(mut self, key: K, value: V) -> Self {
+        self.raw_params.push((key.into(), value.into()));
+        self
+    }
+
+    /// Order returned workers by the given key.
+    pub fn ordering(mut self, ordering: Ordering, ascending: bool) -> Self {
+        self.ordering = ordering;
+        self.ascending = ascending;
+        self
+    }
+
+    /// Begin querying for workers, returning a [`Paginator`] of the
+    /// matching [`Worker`] instances.
+    pub fn query(self) -> Paginator<Worker> {
+        self.lava.paginator(self.url())
+    }
+
+    fn url(&self) -> Url {
+        let mut url = self
+            .lava
+            .base
+            .join("workers/")
+            .expect("Failed to append to base url");
+        url.query_pairs_mut().append_pair(
+            "ordering",
+            &format!(
+                "{}{}",
+                match self.ascending {
+                    true => "",
+                    false => "-",
+                },
+                self.ordering
+            ),
+        );
+        if let Some(health) = self.health {
+            url.query_pairs_mut()
+                .append_pair("health", &health.to_string());
+        }
+        if let Some(state) = self.state {
+            url.query_pairs_mut()
+                .append_pair("state", &state.to_string());
+        }
+        if let Some(text) = &self.hostname_contains {
+            url.query_pairs_mut()
+                .append_pair("hostname__contains", text);
+        }
+        if let Some(when) = self.last_ping_after {
+            url.query_pairs_mut()
+                .append_pair("last_ping__gt", &when.to_rfc3339());
+        }
+        if let Some(when) = self.last_ping_before {
+            url.query_pairs_mut()
+                .append_pair("last_ping__lt", &when.to_rfc3339());
+        }
+        if let Some(limit) = self.limit {
+            url.query_pairs_mut()
+                .append_pair("limit", &limit.to_string());
+        }
+        for (key, value) in self.raw_params.iter() {
+            url.query_pairs_mut().append_pair(key, value);
+        }
+        url
+    }
+}
+
+/// The number of jobs running on a [`Worker`] compared to its limit
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct WorkerUtilization {
+    pub hostname: String,
+    /// The number of running jobs on devices attached to the worker
+    pub running: u32,
+    /// The job limit of the worker, where zero means there is no limit
+    pub job_limit: i64,
+}
+
+impl WorkerUtilization {
+    /// The ratio of running jobs to the job limit
+    ///
+    /// This is `None` when the worker has no job limit.
+    pub fn ratio(&self) -> Option<f64> {
+        if self.job_limit > 0 {
+            Some(f64::from(self.running) / self.job_limit as f64)
+        } else {
+            None
+        }
+    }
+
+    /// Whether the worker is running more jobs than its limit allows
+    pub fn is_overloaded(&self) -> bool {
+        self.job_limit > 0 && i64::from(self.running) > self.job_limit
+    }
+}
+
+/// Compute the [`WorkerUtilization`] of every worker on the server.
+///
+/// Running jobs are attributed to workers through the worker host of
+/// the device they are running on. Since this requires reading
+/// workers, devices and jobs in sequence, the result is only an
+/// approximation on a busy server.
+pub async fn worker_utilization(lava: &Lava) -> Result<Vec<WorkerUtilization>, PaginationError> {
+    let workers: Vec<Worker> = lava.workers().try_collect().await?;
+
+    let mut device_workers = HashMap::new();
+    let mut devices = lava.devices();
+    while let Some(device) = devices.try_next().await? {
+        device_workers.insert(device.hostname, device.worker_host);
+    }
+
+    let mut running: HashMap<String, u32> = HashMap::new();
+    let mut jobs = lava.jobs().state(job::State::Running).query();
+    while let Some(job) = jobs.try_next().await? {
+        if let Some(worker) = job
+            .actual_device
+            .as_ref()
+            .and_then(|d| device_workers.get(d))
+        {
+            *running.entry(worker.clone()).or_default() += 1;
+        }
+    }
+
+    Ok(workers
+        .into_iter()
+        .map(|w| WorkerUtilization {
+            running: running.get(&w.hostname).copied().unwrap_or_default(),
+            hostname: w.hostname,
+            job_limit: w.job_limit,
+        })
+        .collect())
+}
+
+#[derive(Error, Debug)]
+pub enum WorkerUpdateError {
+    #[error("Worker update request failed")]
+    Request(#[from] reqwest::Error),
+    #[error("Invalid worker update: {0}")]
+    InvalidUpdate(String),
+    #[error("Not permitted to change workers")]
+    PermissionDenied,
+    #[error("Worker not found")]
+    NotFound,
+    #[error("Unexpected reply to worker update: {0}")]
+    UnexpectedReply(reqwest::StatusCode),
+}
+
+impl Classify for WorkerUpdateError {
+    fn class(&self) -> ErrorClass {
+        match self {
+            WorkerUpdateError::Request(e) => e.class(),
+            WorkerUpdateError::InvalidUpdate(_) => ErrorClass::Invalid,
+            WorkerUpdateError::PermissionDenied => ErrorClass::Auth,
+            WorkerUpdateError::NotFound => ErrorClass::NotFound,
+            WorkerUpdateError::UnexpectedReply(s) => s.class(),
+        }
+    }
+}
+
+#[derive(Default, Serialize)]
+struct WorkerUpdate {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    health: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    job_limit: Option<u32>,
+}
+
+async fn update_worker(
+    lava: &Lava,
+    hostname: &str,
+    update: &WorkerUpdate,
+) -> Result<(), WorkerUpdateError> {
+    let mut url = lava.base.clone();
+    url.path_segments_mut()
+        .unwrap()
+        .pop_if_empty()
+        .push("workers")
+        .push(hostname)
+        .push("");
+
+    let res = lava
+        .transport
+        .execute(transport::patch_json(url, update))
+        .await?;
+
+    match res.status() {
+        StatusCode::OK | StatusCode::NO_CONTENT => Ok(()),
+        StatusCode::BAD_REQUEST => Err(WorkerUpdateError::InvalidUpdate(res.text().await?)),
+        StatusCode::UNAUTHORIZED | StatusCode::FORBIDDEN => {
+            Err(WorkerUpdateError::PermissionDenied)
+        }
+        StatusCode::NOT_FOUND => Err(WorkerUpdateError::NotFound),
+        s => Err(WorkerUpdateError::UnexpectedReply(s)),
+    }
+}
+
+/// Set the health of the worker with the given hostname.
+///
+/// Changing workers requires a token for a user with permission to
+/// change them, usually an administrator. Putting a worker into
+/// [`Health::Maintenance`] stops the server scheduling new jobs on
+/// its devices, while letting running jobs finish, so that the
+/// worker can be drained before its host is taken down.
+pub async fn set_worker_health(
+    lava: &Lava,
+    hostname: &str,
+    health: Health,
+) -> Result<(), WorkerUpdateError> {
+    let update = WorkerUpdate {
+        health: Some(health.to_string()),
+        ..Default::default()
+    };
+    update_worker(lava, hostname, &update).await
+}
+
+/// Set the maximum number of jobs the worker with the given hostname
+/// may run at once, where zero means there is no limit.
+///
+/// As for [`set_worker_health`], this requires a token for a user
+/// with permission to change workers.
+pub async fn set_worker_job_limit(
+    lava: &Lava,
+    hostname: &str,
+    job_limit: u32,
+) -> Result<(), WorkerUpdateError> {
+    let update = WorkerUpdate {
+        job_limit: Some(job_limit),
+        ..Default::default()
+    };
+    update_worker(lava, hostname, &update).await
 }
 
 #[cfg(test)]
 mod tests {
+    use super::{Health, Ordering, State as WState, WorkerUpdateError};
     use crate::Lava;
-    use boulder::{Buildable, Builder};
+    use boulder::{Buildable, BuildableWithPersianRug, Builder, BuilderWithPersianRug};
+    use chrono::{Duration, TimeZone, Utc};
     use futures::TryStreamExt;
-    use lava_api_mock::{LavaMock, PaginationLimits, PopulationParams, SharedState, State, Worker};
-    use persian_rug::Accessor;
+    use lava_api_mock::{
+        Job, JobState, LavaMock, PaginationLimits, PopulationParams, SharedState, State, User,
+        Worker, WorkerHealth, WorkerState,
+    };
+    use persian_rug::{Accessor, Context, Proxy};
     use std::collections::BTreeMap;
     use test_log::test;
 
@@ -66,9 +693,241 @@ mod tests {
             assert_eq!(worker.hostname, wk.hostname);
             assert_eq!(worker.state.to_string(), wk.state.to_string());
             assert_eq!(worker.health.to_string(), wk.health.to_string());
+            assert_eq!(worker.job_limit, wk.job_limit);
+            assert_eq!(worker.last_ping, wk.last_ping);
+            assert_eq!(worker.version, wk.version);
+            assert_eq!(worker.master_version_notified, wk.master_version_notified);
 
             seen.insert(worker.hostname.clone(), worker.clone());
         }
         assert_eq!(seen.len(), 51);
     }
+
+    #[test]
+    fn test_is_stale() {
+        let now = Utc.with_ymd_and_hms(2022, 4, 10, 16, 0, 0).unwrap();
+        let mut worker = super::Worker {
+            hostname: "worker".to_string(),
+            state: WState::Online,
+            health: Health::Active,
+            job_limit: 0,
+            last_ping: Some(now - Duration::seconds(30)),
+            version: None,
+            master_version_notified: None,
+        };
+        let minute = std::time::Duration::from_secs(60);
+        assert!(!worker.is_stale_at(minute, now));
+        assert!(worker.is_stale_at(std::time::Duration::from_secs(10), now));
+        // Thresholds too long to represent are never exceeded
+        assert!(!worker.is_stale_at(std::time::Duration::MAX, now));
+        worker.last_ping = None;
+        assert!(worker.is_stale_at(minute, now));
+    }
+
+    /// Mark a third of 30 jobs as running, and check that they are
+    /// attributed to the workers of their devices
+    #[test(tokio::test)]
+    async fn test_utilization() {
+        let mut state = SharedState::new_populated(
+            PopulationParams::builder()
+                .workers(3usize)
+                .devices(10usize)
+                .jobs(30usize)
+                .build(),
+        );
+        {
+            let mut m = state.mutate();
+            for j in m.get_iter_mut::<Job<State>>() {
+                if j.id % 3 == 0 {
+                    j.state = JobState::Running;
+                }
+            }
+        }
+        let server = LavaMock::new(state.clone(), Default::default()).await;
+
+        let mut expected = BTreeMap::new();
+        let start = state.access();
+        for j in start.get_iter::<Job<State>>() {
+            if j.state == JobState::Running {
+                if let Some(d) = j.actual_device.as_ref() {
+                    let worker = start.get(&start.get(d).worker_host);
+                    *expected.entry(worker.hostname.clone()).or_insert(0u32) += 1;
+                }
+            }
+        }
+
+        let lava = Lava::new(&server.uri(), None).expect("failed to make lava server");
+
+        let utilization = lava
+            .worker_utilization()
+            .await
+            .expect("failed to get utilization");
+        assert_eq!(utilization.len(), 3);
+        for u in utilization {
+            let running = expected.get(&u.hostname).copied().unwrap_or_default();
+            assert_eq!(u.running, running);
+            assert_eq!(u.job_limit, 100);
+            assert_eq!(u.ratio(), Some(f64::from(running) / 100.0));
+            assert!(!u.is_overloaded());
+        }
+    }
+
+    #[test(tokio::test)]
+    async fn test_builder() {
+        let mut state =
+            SharedState::new_populated(PopulationParams::builder().workers(6usize).build());
+        let base = Utc.with_ymd_and_hms(2022, 3, 17, 17, 0, 0).unwrap();
+        {
+            let mut m = state.mutate();
+            for (i, w) in m.get_iter_mut::<Worker<State>>().enumerate() {
+                w.hostname = format!("{}-worker-{}", if i < 2 { "lab" } else { "farm" }, i);
+                w.health = if i % 3 == 0 {
+                    WorkerHealth::Maintenance
+                } else {
+                    WorkerHealth::Active
+                };
+                w.state = if i % 2 == 0 {
+                    WorkerState::Online
+                } else {
+                    WorkerState::Offline
+                };
+                w.last_ping = Some(base + Duration::hours(i as i64));
+            }
+        }
+        let server = LavaMock::new(
+            state.clone(),
+            PaginationLimits::builder().workers(Some(2)).build(),
+        )
+        .await;
+
+        let lava = Lava::new(&server.uri(), None).expect("failed to make lava server");
+
+        let hostnames = |workers: Vec<super::Worker>| {
+            workers.into_iter().map(|w| w.hostname).collect::<Vec<_>>()
+        };
+
+        let workers = lava
+            .workers_builder()
+            .health(Health::Maintenance)
+            .query()
+            .try_collect::<Vec<_>>()
+            .await
+            .expect("failed to get workers");
+        assert_eq!(hostnames(workers), vec!["farm-worker-3", "lab-worker-0"]);
+
+        let workers = lava
+            .workers_builder()
+            .health(Health::Active)
+            .state(WState::Offline)
+            .query()
+            .try_collect::<Vec<_>>()
+            .await
+            .expect("failed to get workers");
+        assert_eq!(hostnames(workers), vec!["farm-worker-5", "lab-worker-1"]);
+
+        let workers = lava
+            .workers_builder()
+            .hostname_contains("lab")
+            .ordering(Ordering::Hostname, false)
+            .query()
+            .try_collect::<Vec<_>>()
+            .await
+            .expect("failed to get workers");
+        assert_eq!(hostnames(workers), vec!["lab-worker-1", "lab-worker-0"]);
+
+        let workers = lava
+            .workers_builder()
+            .last_ping_after(base + Duration::minutes(30))
+            .last_ping_before(base + Duration::hours(4))
+            .ordering(Ordering::LastPing, false)
+            .limit(1)
+            .query()
+            .try_collect::<Vec<_>>()
+            .await
+            .expect("failed to get workers");
+        assert_eq!(
+            hostnames(workers),
+            vec!["farm-worker-3", "farm-worker-2", "lab-worker-1"]
+        );
+    }
+
+    /// Drain a worker and change its job limit, checking that only
+    /// superusers may do so, and that the mock records each change
+    #[test(tokio::test)]
+    async fn test_update() {
+        let mut state = SharedState::new_populated(
+            PopulationParams::builder()
+                .workers(2usize)
+                .devices(0usize)
+                .build(),
+        );
+        {
+            let m = state.mutate();
+            let (_, m) = Proxy::<User<State>>::builder()
+                .username("admin")
+                .is_superuser(true)
+                .token(Some("admin-token".to_string()))
+                .build(m);
+            let _ = Proxy::<User<State>>::builder()
+                .username("fred")
+                .token(Some("fred-token".to_string()))
+                .build(m);
+        }
+        let hostname = state
+            .access()
+            .get_iter::<Worker<State>>()
+            .next()
+            .unwrap()
+            .hostname
+            .clone();
+        let server = LavaMock::new(state.clone(), PaginationLimits::new()).await;
+        let worker = || {
+            let state = state.access();
+            let worker = state
+                .get_iter::<Worker<State>>()
+                .find(|w| w.hostname == hostname)
+                .unwrap();
+            (worker.health.clone(), worker.job_limit)
+        };
+
+        let fred = Lava::new(&server.uri(), Some("fred-token".to_string()))
+            .expect("failed to make lava server");
+        let err = fred
+            .set_worker_health(&hostname, Health::Maintenance)
+            .await
+            .expect_err("changed worker health without permission");
+        assert!(matches!(err, WorkerUpdateError::PermissionDenied));
+        assert_eq!(worker(), (WorkerHealth::Active, 100));
+
+        let admin = Lava::new(&server.uri(), Some("admin-token".to_string()))
+            .expect("failed to make lava server");
+        admin
+            .set_worker_health(&hostname, Health::Maintenance)
+            .await
+            .expect("failed to set worker health");
+        assert_eq!(worker(), (WorkerHealth::Maintenance, 100));
+
+        admin
+            .set_worker_job_limit(&hostname, 2)
+            .await
+            .expect("failed to set worker job limit");
+        assert_eq!(worker(), (WorkerHealth::Maintenance, 2));
+
+        let workers = admin
+            .workers_builder()
+            .health(Health::Maintenance)
+            .query()
+            .try_collect::<Vec<_>>()
+            .await
+            .expect("failed to get workers");
+        assert_eq!(workers.len(), 1);
+        assert_eq!(workers[0].hostname, hostname);
+        assert_eq!(workers[0].job_limit, 2);
+
+        let err = admin
+            .set_worker_job_limit("no-such-worker", 1)
+            .await
+            .expect_err("changed a missing worker");
+        assert!(matches!(err, WorkerUpdateError::NotFound));
+    }
 }